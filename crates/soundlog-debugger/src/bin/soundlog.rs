@@ -4,12 +4,8 @@
 //! left AST pane and a right hex viewer; here we initialize the placeholder
 //! state and call into the module each frame.
 
-use anyhow::Context;
 use clap::{Parser, Subcommand};
-use flate2::read::GzDecoder;
-use std::fs;
-use std::io::{Cursor, Read};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 // Use the library crate's modules and types. The library crate (this package)
@@ -18,42 +14,105 @@ use soundlog_debugger::cui;
 use soundlog_debugger::gui;
 use soundlog_debugger::logger::Logger;
 
+use soundlog::vgm::align::AlignOptions;
+use soundlog::vgm::lint::LintOptions;
+use soundlog::vgm::stream::{WriteRateLimit, WriteRateLimitMode};
+
 /// Simple CLI: optional subcommand `test`, otherwise optional file path to display
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Execute parse and build round-trip tests. Also output header details
     Test {
-        /// Path to binary file to test (use '-' for stdin)
-        #[arg(value_name = "FILE")]
-        file: PathBuf,
+        /// Paths to binary files to test (use '-' for stdin); several files run in batch mode
+        #[arg(value_name = "FILE", required = true)]
+        files: Vec<PathBuf>,
 
         /// Dry-run: do not print standard one-line outputs; only emit errors/panics
         #[arg(long)]
         dry_run: bool,
+
+        /// Compare parsed commands and header values instead of bytes, and
+        /// report benign encoding differences separately from data loss
+        #[arg(long)]
+        semantic: bool,
+
+        /// Also check that rebuilding each file is reproducible byte for byte
+        #[arg(long)]
+        deterministic: bool,
+
+        /// Write a machine-readable report of all tested files to this path (use '-' for stdout)
+        #[arg(long, value_name = "PATH")]
+        report: Option<PathBuf>,
+
+        /// Report format: junit or json
+        #[arg(long, value_name = "FORMAT", default_value = "junit")]
+        report_format: cui::report::ReportFormat,
+
+        /// Gzip-compress the report
+        #[arg(long)]
+        gzip: bool,
+
+        /// Run the tests again every time one of the files changes
+        #[arg(long)]
+        watch: bool,
+    },
+    /// Show header details and the guessed sound driver
+    Info {
+        /// Input VGM file path (use '-' for stdin)
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+
+        /// Print the details again every time the file changes
+        #[arg(long)]
+        watch: bool,
     },
     /// Re-dump VGM file with DAC streams expanded to chip writes
     Redump {
-        /// Input VGM file path
+        /// Input VGM file path (use '-' for stdin), or a directory to process every .vgm/.vgz file in it
         #[arg(value_name = "INPUT")]
         input: PathBuf,
 
-        /// Output VGM file path (use '-' for stdout)
+        /// Output VGM file path (use '-' for stdout), or the output directory when INPUT is a directory
         #[arg(value_name = "OUTPUT")]
         output: PathBuf,
 
         /// Print diagnostic output after redump (re-parse output and show diagnostics)
         #[arg(long)]
         diag: bool,
+
+        /// Minimum gap in samples between generated DAC writes to one chip (for real hardware players)
+        #[arg(long, value_name = "SAMPLES")]
+        min_write_gap: Option<usize>,
+
+        /// What to do with DAC writes that come too soon: coalesce (delay, keep latest value) or drop
+        #[arg(long, value_name = "MODE", default_value = "coalesce", value_parser = ["coalesce", "drop"])]
+        rate_limit_mode: String,
+
+        /// Bus busy time after each write to a chip, e.g. ym2612=10000 (nanoseconds; repeatable)
+        #[arg(long, value_name = "CHIP=NS", value_parser = parse_write_delay)]
+        write_delay: Vec<(String, u32)>,
+
+        /// Gzip-compress the output (also done for OUTPUT paths ending in .vgz or .gz)
+        #[arg(long)]
+        gzip: bool,
+
+        /// State file of a directory run, used to resume it (default: OUTPUT/.soundlog-batch)
+        #[arg(long, value_name = "PATH")]
+        state: Option<PathBuf>,
     },
     /// Parse and display VGM file commands with offsets and lengths
     Parse {
-        /// VGM file path to parse
+        /// VGM file path to parse (use '-' for stdin)
         #[arg(value_name = "FILE")]
         file: PathBuf,
+
+        /// Parse the file again every time it changes
+        #[arg(long)]
+        watch: bool,
     },
     /// Play VGM file and display register writes with events
     Play {
-        /// VGM file path to play
+        /// VGM file path to play (use '-' for stdin)
         #[arg(value_name = "FILE")]
         file: PathBuf,
 
@@ -74,6 +133,244 @@ enum Commands {
         #[arg(long)]
         loop_base: Option<i8>,
     },
+    /// Export per-frame register deltas as text or JSON for tracker tooling
+    Frames {
+        /// Input VGM file path (use '-' for stdin)
+        #[arg(value_name = "INPUT")]
+        input: PathBuf,
+
+        /// Output file path (use '-' for stdout)
+        #[arg(value_name = "OUTPUT", default_value = "-")]
+        output: PathBuf,
+
+        /// Frame length in samples (735 = 1/60s, 882 = 1/50s at 44.1kHz)
+        #[arg(long, default_value_t = 735)]
+        samples_per_frame: u32,
+
+        /// Emit JSON instead of plain text
+        #[arg(long)]
+        json: bool,
+
+        /// Gzip-compress the output
+        #[arg(long)]
+        gzip: bool,
+    },
+    /// Convert VGM file to XGM (v1) for the SGDK Mega Drive sound driver
+    Xgm {
+        /// Input VGM file path (use '-' for stdin)
+        #[arg(value_name = "INPUT")]
+        input: PathBuf,
+
+        /// Output XGM file path (use '-' for stdout)
+        #[arg(value_name = "OUTPUT")]
+        output: PathBuf,
+
+        /// Write a PAL (50Hz) file regardless of the VGM header rate
+        #[arg(long)]
+        pal: bool,
+
+        /// Gzip-compress the output
+        #[arg(long)]
+        gzip: bool,
+    },
+    /// Compress PCM data blocks to shrink VGM files
+    Optimize {
+        /// Input VGM file path (use '-' for stdin), or a directory to process every .vgm/.vgz file in it
+        #[arg(value_name = "INPUT")]
+        input: PathBuf,
+
+        /// Output VGM file path (use '-' for stdout), or the output directory when INPUT is a directory
+        #[arg(value_name = "OUTPUT")]
+        output: PathBuf,
+
+        /// Compressed value width in bits (1-7)
+        #[arg(long, default_value_t = 4)]
+        bits: u8,
+
+        /// Drop ROM data that is never read by the chips
+        #[arg(long)]
+        prune_rom: bool,
+
+        /// Gzip-compress the output (also done for OUTPUT paths ending in .vgz or .gz)
+        #[arg(long)]
+        gzip: bool,
+
+        /// State file of a directory run, used to resume it (default: OUTPUT/.soundlog-batch)
+        #[arg(long, value_name = "PATH")]
+        state: Option<PathBuf>,
+    },
+    /// Recompute broken header offsets (EOF, GD3, data, loop, extra header)
+    Repair {
+        /// Input VGM file path (use '-' for stdin)
+        #[arg(value_name = "INPUT")]
+        input: PathBuf,
+
+        /// Output VGM file path (use '-' for stdout)
+        #[arg(value_name = "OUTPUT")]
+        output: PathBuf,
+
+        /// Gzip-compress the output (also done for OUTPUT paths ending in .vgz or .gz)
+        #[arg(long)]
+        gzip: bool,
+    },
+    /// Report chips that are never written and FM channels that are never audible
+    Lint {
+        /// Input VGM file path (use '-' for stdin)
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+        /// Also report chips written without a header clock or dual-chip bit
+        #[arg(long)]
+        strict: bool,
+        /// Report chips written more than this many times without a wait
+        #[arg(long, value_name = "WRITES")]
+        max_burst: Option<usize>,
+        /// Report chips written more than this many times within 1 ms
+        #[arg(long, value_name = "WRITES")]
+        max_writes_per_ms: Option<usize>,
+    },
+    /// Print per-register write counts of every chip as a heatmap grid
+    Heatmap {
+        /// Input VGM file path (use '-' for stdin)
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+        /// Also print write totals per window of this many samples
+        #[arg(long, value_name = "SAMPLES")]
+        window: Option<u64>,
+    },
+    /// Show or edit the GD3 tags without re-encoding the command stream
+    Gd3 {
+        /// Input VGM file path (use '-' for stdin)
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+        /// Set a field, e.g. `track_name_en=Title` or `title=Title` (an empty value clears it)
+        #[arg(long, value_name = "FIELD=VALUE")]
+        set: Vec<String>,
+        /// Write the edited VGM here (use '-' for stdout)
+        #[arg(long, short, value_name = "OUTPUT")]
+        output: Option<PathBuf>,
+        /// Write the edited tags back to FILE, in place when they fit
+        #[arg(long, conflicts_with = "gzip")]
+        in_place: bool,
+        /// Gzip-compress the output (also done for OUTPUT paths ending in .vgz or .gz)
+        #[arg(long)]
+        gzip: bool,
+    },
+    /// Report hanging notes, patch and DAC stream differences when the song loops
+    LoopCheck {
+        /// Input VGM file path (use '-' for stdin)
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+        /// Write a copy with the differences fixed at the loop point (use '-' for stdout)
+        #[arg(long, value_name = "OUTPUT")]
+        patch: Option<PathBuf>,
+        /// Gzip-compress the patched copy (also done for paths ending in .vgz or .gz)
+        #[arg(long)]
+        gzip: bool,
+    },
+    /// Render the PCM data written by a single DAC stream into a WAV file
+    BounceStream {
+        /// Input VGM file path (use '-' for stdin)
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+
+        /// DAC stream id to isolate
+        #[arg(long, default_value_t = 0)]
+        stream: u8,
+
+        /// Output WAV file path (use '-' for stdout)
+        #[arg(long, value_name = "WAV")]
+        wav: PathBuf,
+
+        /// Gzip-compress the output
+        #[arg(long)]
+        gzip: bool,
+    },
+    /// Align two renditions of the same song by their key-ons and report the timing drift
+    Align {
+        /// Reference VGM file path (use '-' for stdin)
+        #[arg(value_name = "LEFT")]
+        left: PathBuf,
+
+        /// VGM file path to align to LEFT (use '-' for stdin)
+        #[arg(value_name = "RIGHT")]
+        right: PathBuf,
+
+        /// Largest start offset searched, in samples
+        #[arg(long, value_name = "SAMPLES", default_value_t = 88_200)]
+        max_offset: u64,
+
+        /// Largest distance between matched key-ons, in samples
+        #[arg(long, value_name = "SAMPLES", default_value_t = 441)]
+        tolerance: u64,
+
+        /// Report the drift per window of this many samples
+        #[arg(long, value_name = "SAMPLES", default_value_t = 220_500)]
+        window: u64,
+    },
+    /// Print the decoded registers of the chips at a time or command index
+    State {
+        /// Input VGM file path (use '-' for stdin)
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+
+        /// Time to take the state at, e.g. 1:23 or 83.5 (seconds)
+        #[arg(long, value_name = "TIME", value_parser = cui::state::parse_time, conflicts_with = "command", required_unless_present = "command")]
+        at: Option<f64>,
+
+        /// Take the state right after this command index instead
+        #[arg(long, value_name = "INDEX")]
+        command: Option<usize>,
+
+        /// Only print this chip, e.g. ym2612 (repeatable)
+        #[arg(long, value_name = "CHIP")]
+        chip: Vec<String>,
+
+        /// Emit JSON instead of plain text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print per-channel note counts, pitch range, voice usage and active time
+    Channels {
+        /// Input VGM file path (use '-' for stdin)
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+    },
+    /// Write a shareable summary: tags, chips, duration, channel activity chart, voices, data blocks
+    Report {
+        /// Input VGM file path (use '-' for stdin)
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+
+        /// Output path (use '-' for stdout)
+        #[arg(value_name = "OUTPUT", default_value = "-")]
+        output: PathBuf,
+
+        /// Write a standalone HTML page instead of Markdown
+        #[arg(long)]
+        html: bool,
+
+        /// Gzip-compress the output
+        #[arg(long)]
+        gzip: bool,
+    },
+    /// Write a VGM file as an editable command script, or build one from a script
+    Script {
+        /// Input VGM file path, a script with --import (use '-' for stdin)
+        #[arg(value_name = "INPUT")]
+        input: PathBuf,
+
+        /// Output path (use '-' for stdout)
+        #[arg(value_name = "OUTPUT", default_value = "-")]
+        output: PathBuf,
+
+        /// Build a VGM file from the script INPUT
+        #[arg(long)]
+        import: bool,
+
+        /// Gzip-compress the output (with --import, also done for OUTPUT paths ending in .vgz or .gz)
+        #[arg(long)]
+        gzip: bool,
+    },
 }
 
 #[derive(Parser, Debug)]
@@ -88,36 +385,71 @@ struct Args {
     #[command(subcommand)]
     command: Option<Commands>,
 
-    /// Path to binary file to display (supports .vgz (gzipped) and raw files)
+    /// Path to binary file to display (supports .vgz (gzipped) and raw files; use '-' for stdin)
     file: Option<PathBuf>,
+
+    /// Only print errors to stderr, not summaries or warnings
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Print debug diagnostics to stderr; repeat (-vv) for trace output.
+    /// `info` also prints the parse time and the memory held by the document
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
 }
 
-/// Helper: read bytes from a path, automatically handling `.vgz`/`.gz` or gzip header.
-///
-/// This centralizes the logic used both by the `test` subcommand and by the GUI
-/// loader so the detection/decompression implementation isn't duplicated.
-fn load_bytes_from_path(path: &PathBuf) -> anyhow::Result<Vec<u8>> {
-    // Read file contents
-    let data =
-        fs::read(path).with_context(|| format!("failed to read file: {}", path.display()))?;
-
-    // Detect gzip by extension or by header (0x1f 0x8b)
-    let is_gzip = path
-        .extension()
-        .and_then(|s| s.to_str())
-        .map(|s| s.eq_ignore_ascii_case("vgz") || s.eq_ignore_ascii_case("gz"))
-        .unwrap_or(false)
-        || (data.len() >= 2 && data[0] == 0x1f && data[1] == 0x8b);
-
-    if is_gzip {
-        let mut decoder = GzDecoder::new(Cursor::new(data));
-        let mut out = Vec::new();
-        decoder
-            .read_to_end(&mut out)
-            .context("gzip decompression failed")?;
-        Ok(out)
-    } else {
-        Ok(data)
+/// Parse a `CHIP=NS` write-delay argument.
+fn parse_write_delay(arg: &str) -> Result<(String, u32), String> {
+    let (chip, ns) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected CHIP=NS, got '{}'", arg))?;
+    let ns = ns
+        .parse::<u32>()
+        .map_err(|e| format!("invalid nanoseconds '{}': {}", ns, e))?;
+    Ok((chip.to_string(), ns))
+}
+
+/// Log `err` as the failure of `what` and return its exit code (see
+/// `cui::exit`).
+fn fail(what: &str, err: &anyhow::Error) -> i32 {
+    log::error!("{}: {:#}", what, err);
+    cui::exit::code_for_error(err)
+}
+
+/// Log an error of `cui::vgm::read_input`, which names the input itself, and
+/// return its exit code.
+fn fail_read(err: &anyhow::Error) -> i32 {
+    log::error!("{:#}", err);
+    cui::exit::code_for_error(err)
+}
+
+/// Run `process` for every VGM file in the `input` directory (see
+/// `cui::batch::run_batch`) and exit with the code of the run.
+fn run_batch(
+    input: &Path,
+    output: &Path,
+    state: Option<&Path>,
+    job: &str,
+    gzip: bool,
+    process: impl FnMut(&Path, &Path, Vec<u8>) -> anyhow::Result<()>,
+) -> ! {
+    match cui::batch::run_batch(input, output, state, job, gzip, process) {
+        Ok(code) => std::process::exit(code),
+        Err(e) => std::process::exit(fail("batch failed", &e)),
+    }
+}
+
+/// Exit with the code returned by `run`, or with `watch` run it again every
+/// time one of `files` changes and keep going until interrupted.
+fn run_or_watch(watch: bool, files: &[PathBuf], mut run: impl FnMut() -> i32) -> ! {
+    if !watch {
+        std::process::exit(run());
+    }
+    match cui::watch::watch_files(files, || {
+        run();
+    }) {
+        Ok(()) => std::process::exit(cui::exit::SUCCESS),
+        Err(e) => std::process::exit(fail("watch failed", &e)),
     }
 }
 
@@ -127,82 +459,149 @@ fn load_bytes_from_path(path: &PathBuf) -> anyhow::Result<Vec<u8>> {
 fn main() {
     // Parse CLI args early so we can load the initial bytes before creating the UI.
     let args = Args::parse();
-    // Create a default logger; some subcommands will override this based on their dry_run flags.
+    soundlog_debugger::logger::init_diagnostics(soundlog_debugger::logger::verbosity_level(
+        args.quiet,
+        args.verbose,
+    ));
+    // Logger for the normal output of `parse` and `play`; `play` overrides it
+    // based on its dry_run flag.
     let mut logger = Arc::new(Logger::new_stdout(false));
 
     // Handle subcommands
     match args.command {
-        Some(Commands::Test { file, dry_run }) => {
-            // Configure logger according to dry_run so main's messages respect it.
-            logger = Arc::new(Logger::new_stdout(dry_run));
-            // Pass `dry_run` through directly so that `--dry-run` results in no normal/stdout output
-            match load_bytes_from_path(&file) {
-                Ok(bytes) => {
-                    match cui::vgm::test_roundtrip(&file, bytes, dry_run) {
-                        Ok(_) => std::process::exit(0),
+        Some(Commands::Test {
+            files,
+            dry_run,
+            semantic,
+            deterministic,
+            report,
+            report_format,
+            gzip,
+            watch,
+        }) => {
+            // `--dry-run` results in no normal/stdout output; a report written
+            // to stdout implies it so the report is not mixed with diagnostics.
+            let dry_run = dry_run || report.as_deref() == Some(Path::new("-"));
+            run_or_watch(watch, &files, || {
+                let mut cases = Vec::with_capacity(files.len());
+                let mut exit_code = cui::exit::SUCCESS;
+                for file in &files {
+                    let started = std::time::Instant::now();
+                    let outcome = match cui::vgm::read_input(file) {
+                        Ok(bytes) => match cui::vgm::test_roundtrip(
+                            file,
+                            bytes,
+                            dry_run,
+                            semantic,
+                            deterministic,
+                        ) {
+                            Ok(outcome) => {
+                                exit_code = exit_code.max(cui::exit::code_for_outcome(&outcome));
+                                outcome
+                            }
+                            Err(e) => {
+                                exit_code = exit_code.max(fail("test_roundtrip failed", &e));
+                                cui::report::TestOutcome::Error(e.to_string())
+                            }
+                        },
                         Err(e) => {
-                            // Qualify macro with crate name so the exported macro is resolved.
-                            soundlog_debugger::log_error!(&*logger, "test_roundtrip failed: {}", e);
-                            std::process::exit(1);
+                            exit_code = exit_code.max(fail_read(&e));
+                            cui::report::TestOutcome::Error(format!("{:#}", e))
                         }
-                    }
+                    };
+                    cases.push(cui::report::TestCase {
+                        file: file.to_string_lossy().into_owned(),
+                        outcome,
+                        duration: started.elapsed(),
+                    });
                 }
-                Err(e) => {
-                    soundlog_debugger::log_error!(&*logger, "failed to read input for test: {}", e);
-                    std::process::exit(1);
+                if let Some(report) = &report
+                    && let Err(e) = cui::report::write_report(report, report_format, &cases, gzip)
+                {
+                    exit_code = exit_code.max(fail("failed to write report", &e));
                 }
-            }
+                exit_code
+            });
         }
         Some(Commands::Redump {
             input,
             output,
             diag,
+            min_write_gap,
+            rate_limit_mode,
+            write_delay,
+            gzip,
+            state,
         }) => {
+            let rate_limit = min_write_gap.map(|min_gap_samples| WriteRateLimit {
+                min_gap_samples,
+                mode: if rate_limit_mode == "drop" {
+                    WriteRateLimitMode::Drop
+                } else {
+                    WriteRateLimitMode::Coalesce
+                },
+            });
+            if input.is_dir() {
+                let job = format!(
+                    "redump diag={} min_write_gap={:?} rate_limit_mode={} write_delay={:?} gzip={}",
+                    diag, min_write_gap, rate_limit_mode, write_delay, gzip
+                );
+                run_batch(
+                    &input,
+                    &output,
+                    state.as_deref(),
+                    &job,
+                    gzip,
+                    |input, output, bytes| {
+                        cui::vgm::redump_vgm(
+                            input,
+                            output,
+                            bytes,
+                            diag,
+                            rate_limit,
+                            &write_delay,
+                            gzip,
+                        )
+                    },
+                );
+            }
             // Load input bytes
-            match load_bytes_from_path(&input) {
+            match cui::vgm::read_input(&input) {
                 Ok(bytes) => {
                     // Call redump_vgm (preserves original loop and fadeout information from the file)
-                    match cui::vgm::redump_vgm(&input, &output, bytes, diag) {
+                    match cui::vgm::redump_vgm(
+                        &input,
+                        &output,
+                        bytes,
+                        diag,
+                        rate_limit,
+                        &write_delay,
+                        gzip,
+                    ) {
                         Ok(_) => {
                             // redump succeeded; diagnostics (if diag) are produced inside `redump_vgm`.
-                            std::process::exit(0);
-                        }
-                        Err(e) => {
-                            soundlog_debugger::log_error!(&*logger, "redump failed: {}", e);
-                            std::process::exit(1);
+                            std::process::exit(cui::exit::SUCCESS);
                         }
+                        Err(e) => std::process::exit(fail("redump failed", &e)),
                     }
                 }
-                Err(e) => {
-                    soundlog_debugger::log_error!(
-                        &*logger,
-                        "failed to read input for redump: {}",
-                        e
-                    );
-                    std::process::exit(1);
-                }
+                Err(e) => std::process::exit(fail_read(&e)),
             }
         }
-        Some(Commands::Parse { file }) => {
-            // Load file
-            match load_bytes_from_path(&file) {
-                Ok(bytes) => {
-                    // Call parse_vgm (pass logger Arc so the parse path can use centralized logging)
-                    match cui::vgm::parse_vgm(&file, bytes, logger.clone()) {
-                        Ok(_) => {
-                            std::process::exit(0);
-                        }
-                        Err(e) => {
-                            soundlog_debugger::log_error!(&*logger, "parse failed: {}", e);
-                            std::process::exit(1);
+        Some(Commands::Parse { file, watch }) => {
+            run_or_watch(watch, std::slice::from_ref(&file), || {
+                // Load file
+                match cui::vgm::read_input(&file) {
+                    Ok(bytes) => {
+                        // Call parse_vgm (pass logger Arc so the parse path can use centralized logging)
+                        match cui::vgm::parse_vgm(&file, bytes, logger.clone()) {
+                            Ok(_) => cui::exit::SUCCESS,
+                            Err(e) => fail("parse failed", &e),
                         }
                     }
+                    Err(e) => fail_read(&e),
                 }
-                Err(e) => {
-                    soundlog_debugger::log_error!(&*logger, "failed to read file: {}", e);
-                    std::process::exit(1);
-                }
-            }
+            });
         }
         Some(Commands::Play {
             file,
@@ -213,7 +612,7 @@ fn main() {
         }) => {
             // Configure logger according to dry_run so main-level messages respect it.
             logger = Arc::new(Logger::new_stdout(dry_run));
-            match load_bytes_from_path(&file) {
+            match cui::vgm::read_input(&file) {
                 Ok(bytes) => {
                     // Default loop_count to Some(1) when unspecified
                     let loop_count = loop_count.or(Some(1));
@@ -227,29 +626,258 @@ fn main() {
                         loop_base,
                     ) {
                         Ok(_) => {
-                            std::process::exit(0);
-                        }
-                        Err(e) => {
-                            soundlog_debugger::log_error!(&*logger, "play failed: {}", e);
-                            std::process::exit(1);
+                            std::process::exit(cui::exit::SUCCESS);
                         }
+                        Err(e) => std::process::exit(fail("play failed", &e)),
                     }
                 }
-                Err(e) => {
-                    soundlog_debugger::log_error!(&*logger, "failed to read file: {}", e);
-                    std::process::exit(1);
+                Err(e) => std::process::exit(fail_read(&e)),
+            }
+        }
+        Some(Commands::Frames {
+            input,
+            output,
+            samples_per_frame,
+            json,
+            gzip,
+        }) => match cui::vgm::read_input(&input) {
+            Ok(bytes) => {
+                match cui::frames::export_frames(
+                    &input,
+                    &output,
+                    bytes,
+                    samples_per_frame,
+                    json,
+                    gzip,
+                ) {
+                    Ok(_) => std::process::exit(cui::exit::SUCCESS),
+                    Err(e) => std::process::exit(fail("frame export failed", &e)),
                 }
             }
+            Err(e) => std::process::exit(fail_read(&e)),
+        },
+        Some(Commands::Xgm {
+            input,
+            output,
+            pal,
+            gzip,
+        }) => match cui::vgm::read_input(&input) {
+            Ok(bytes) => match cui::xgm::export_xgm(&input, &output, bytes, pal, gzip) {
+                Ok(_) => std::process::exit(cui::exit::SUCCESS),
+                Err(e) => std::process::exit(fail("xgm export failed", &e)),
+            },
+            Err(e) => std::process::exit(fail_read(&e)),
+        },
+        Some(Commands::Optimize {
+            input,
+            output,
+            bits,
+            prune_rom,
+            gzip,
+            state,
+        }) => {
+            if input.is_dir() {
+                let job = format!(
+                    "optimize bits={} prune_rom={} gzip={}",
+                    bits, prune_rom, gzip
+                );
+                run_batch(
+                    &input,
+                    &output,
+                    state.as_deref(),
+                    &job,
+                    gzip,
+                    |input, output, bytes| {
+                        cui::optimize::optimize_vgm(input, output, bytes, bits, prune_rom, gzip)
+                    },
+                );
+            }
+            match cui::vgm::read_input(&input) {
+                Ok(bytes) => {
+                    match cui::optimize::optimize_vgm(&input, &output, bytes, bits, prune_rom, gzip)
+                    {
+                        Ok(_) => std::process::exit(cui::exit::SUCCESS),
+                        Err(e) => std::process::exit(fail("optimize failed", &e)),
+                    }
+                }
+                Err(e) => std::process::exit(fail_read(&e)),
+            }
         }
+        Some(Commands::Repair {
+            input,
+            output,
+            gzip,
+        }) => match cui::vgm::read_input(&input) {
+            Ok(bytes) => match cui::repair::repair_vgm(&input, &output, bytes, gzip) {
+                Ok(_) => std::process::exit(cui::exit::SUCCESS),
+                Err(e) => std::process::exit(fail("repair failed", &e)),
+            },
+            Err(e) => std::process::exit(fail_read(&e)),
+        },
+        Some(Commands::Lint {
+            file,
+            strict,
+            max_burst,
+            max_writes_per_ms,
+        }) => match cui::vgm::read_input(&file) {
+            Ok(bytes) => match cui::lint::lint_vgm(
+                &file,
+                bytes,
+                &LintOptions {
+                    strict,
+                    max_burst,
+                    max_writes_per_ms,
+                },
+            ) {
+                Ok(0) => std::process::exit(cui::exit::SUCCESS),
+                Ok(_) => std::process::exit(cui::exit::VALIDATION_FAILED),
+                Err(e) => std::process::exit(fail("lint failed", &e)),
+            },
+            Err(e) => std::process::exit(fail_read(&e)),
+        },
+        Some(Commands::Info { file, watch }) => run_or_watch(
+            watch,
+            std::slice::from_ref(&file),
+            || match cui::vgm::read_input(&file) {
+                Ok(bytes) => match cui::info::info_vgm(&file, bytes, args.verbose > 0) {
+                    Ok(_) => cui::exit::SUCCESS,
+                    Err(e) => fail("info failed", &e),
+                },
+                Err(e) => fail_read(&e),
+            },
+        ),
+        Some(Commands::Heatmap { file, window }) => match cui::vgm::read_input(&file) {
+            Ok(bytes) => match cui::heatmap::heatmap_vgm(&file, bytes, window) {
+                Ok(_) => std::process::exit(cui::exit::SUCCESS),
+                Err(e) => std::process::exit(fail("heatmap failed", &e)),
+            },
+            Err(e) => std::process::exit(fail_read(&e)),
+        },
+        Some(Commands::Gd3 {
+            file,
+            set,
+            output,
+            in_place,
+            gzip,
+        }) => match cui::vgm::read_input(&file) {
+            Ok(bytes) => {
+                match cui::gd3::gd3_vgm(&file, bytes, &set, output.as_deref(), in_place, gzip) {
+                    Ok(_) => std::process::exit(cui::exit::SUCCESS),
+                    Err(e) => std::process::exit(fail("gd3 failed", &e)),
+                }
+            }
+            Err(e) => std::process::exit(fail_read(&e)),
+        },
+        Some(Commands::LoopCheck { file, patch, gzip }) => match cui::vgm::read_input(&file) {
+            Ok(bytes) => {
+                match cui::loop_check::loop_check_vgm(&file, bytes, patch.as_deref(), gzip) {
+                    Ok(0) => std::process::exit(cui::exit::SUCCESS),
+                    Ok(_) => std::process::exit(cui::exit::VALIDATION_FAILED),
+                    Err(e) => std::process::exit(fail("loop check failed", &e)),
+                }
+            }
+            Err(e) => std::process::exit(fail_read(&e)),
+        },
+        Some(Commands::State {
+            file,
+            at,
+            command,
+            chip,
+            json,
+        }) => {
+            let position = match command {
+                Some(command) => cui::state::StatePosition::Command(command),
+                None => cui::state::StatePosition::Seconds(at.unwrap_or_default()),
+            };
+            match cui::vgm::read_input(&file) {
+                Ok(bytes) => match cui::state::state_vgm(&file, bytes, position, &chip, json) {
+                    Ok(_) => std::process::exit(cui::exit::SUCCESS),
+                    Err(e) => std::process::exit(fail("state failed", &e)),
+                },
+                Err(e) => std::process::exit(fail_read(&e)),
+            }
+        }
+        Some(Commands::BounceStream {
+            file,
+            stream,
+            wav,
+            gzip,
+        }) => match cui::vgm::read_input(&file) {
+            Ok(bytes) => match cui::bounce::bounce_stream(&file, &wav, bytes, stream, gzip) {
+                Ok(_) => std::process::exit(cui::exit::SUCCESS),
+                Err(e) => std::process::exit(fail("bounce-stream failed", &e)),
+            },
+            Err(e) => std::process::exit(fail_read(&e)),
+        },
+        Some(Commands::Align {
+            left,
+            right,
+            max_offset,
+            tolerance,
+            window,
+        }) => {
+            if left.as_os_str() == "-" && right.as_os_str() == "-" {
+                log::error!("LEFT and RIGHT cannot both be stdin");
+                std::process::exit(cui::exit::USAGE);
+            }
+            let options = AlignOptions::new()
+                .with_max_offset(max_offset)
+                .with_tolerance(tolerance)
+                .with_window(window);
+            match (cui::vgm::read_input(&left), cui::vgm::read_input(&right)) {
+                (Ok(left_bytes), Ok(right_bytes)) => {
+                    match cui::align::align_vgm(&left, left_bytes, &right, right_bytes, &options) {
+                        Ok(_) => std::process::exit(cui::exit::SUCCESS),
+                        Err(e) => std::process::exit(fail("align failed", &e)),
+                    }
+                }
+                (Err(e), _) | (_, Err(e)) => {
+                    std::process::exit(fail_read(&e));
+                }
+            }
+        }
+        Some(Commands::Channels { file }) => match cui::vgm::read_input(&file) {
+            Ok(bytes) => match cui::channels::channels_vgm(&file, bytes) {
+                Ok(_) => std::process::exit(cui::exit::SUCCESS),
+                Err(e) => std::process::exit(fail("channels failed", &e)),
+            },
+            Err(e) => std::process::exit(fail_read(&e)),
+        },
+        Some(Commands::Report {
+            file,
+            output,
+            html,
+            gzip,
+        }) => match cui::vgm::read_input(&file) {
+            Ok(bytes) => match cui::score::report_vgm(&file, &output, bytes, html, gzip) {
+                Ok(_) => std::process::exit(cui::exit::SUCCESS),
+                Err(e) => std::process::exit(fail("report failed", &e)),
+            },
+            Err(e) => std::process::exit(fail_read(&e)),
+        },
+        Some(Commands::Script {
+            input,
+            output,
+            import,
+            gzip,
+        }) => match cui::vgm::read_input(&input) {
+            Ok(bytes) => match cui::script::script_vgm(&input, &output, bytes, import, gzip) {
+                Ok(_) => std::process::exit(cui::exit::SUCCESS),
+                Err(e) => std::process::exit(fail("script failed", &e)),
+            },
+            Err(e) => std::process::exit(fail_read(&e)),
+        },
         None => {}
     }
 
     // Try to load bytes from the provided file, otherwise keep empty vector.
     let mut initial_bytes: Vec<u8> = Vec::new();
     if let Some(path) = args.file {
-        match load_bytes_from_path(&path) {
+        match cui::vgm::read_input(&path) {
             Ok(data) => initial_bytes = data,
-            Err(e) => soundlog_debugger::log_error!(&logger, "failed to read file: {}", e),
+            Err(e) => {
+                fail_read(&e);
+            }
         }
     }
 
@@ -1,4 +1,23 @@
+pub mod align;
+pub mod batch;
+pub mod bounce;
+pub mod channels;
+pub mod exit;
+pub mod frames;
+pub mod gd3;
+pub mod heatmap;
+pub mod info;
+pub mod lint;
+pub mod loop_check;
+pub mod optimize;
 pub mod play;
 pub mod redump;
+pub mod repair;
+pub mod report;
+pub mod score;
+pub mod script;
+pub mod state;
 pub mod test;
 pub mod vgm;
+pub mod watch;
+pub mod xgm;
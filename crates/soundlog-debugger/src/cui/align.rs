@@ -0,0 +1,63 @@
+// chipstream/crates/soundlog-debugger/src/cui/align.rs
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use soundlog::VgmDocument;
+use soundlog::vgm::align::{AlignOptions, align_documents};
+
+// Align two renditions of the same song by their key-on events and print the
+// start offset, the number of matched key-ons and the drift per window, one
+// line per window. Times are printed in samples and milliseconds (44.1 kHz).
+pub fn align_vgm(
+    left_path: &Path,
+    left_data: Vec<u8>,
+    right_path: &Path,
+    right_data: Vec<u8>,
+    options: &AlignOptions,
+) -> Result<()> {
+    let left: VgmDocument = (&left_data[..])
+        .try_into()
+        .with_context(|| format!("failed to parse input VGM: {}", left_path.display()))?;
+    let right: VgmDocument = (&right_data[..])
+        .try_into()
+        .with_context(|| format!("failed to parse input VGM: {}", right_path.display()))?;
+
+    let alignment = align_documents(&left, &right, options);
+    let ms = |samples: i64| samples as f64 * 1000.0 / 44_100.0;
+    println!(
+        "offset: {} samples ({:.2} ms)",
+        alignment.offset,
+        ms(alignment.offset)
+    );
+    println!(
+        "matched: {} key-ons ({:.1}%), unmatched: {} left, {} right",
+        alignment.pairs.len(),
+        alignment.match_ratio() * 100.0,
+        alignment.unmatched_left,
+        alignment.unmatched_right
+    );
+    if alignment.pairs.is_empty() {
+        return Ok(());
+    }
+    println!("drift per {} samples:", alignment.window);
+    for window in &alignment.windows {
+        println!(
+            "  {:>10}: {:>5} pairs, min {:>6} max {:>6} mean {:>6} ({:+.2} ms)",
+            window.start,
+            window.matched,
+            window.min_drift,
+            window.max_drift,
+            window.mean_drift,
+            ms(window.mean_drift)
+        );
+    }
+    let final_drift = alignment.final_drift().unwrap_or(0);
+    println!(
+        "final drift: {} samples ({:+.2} ms), max {} samples",
+        final_drift,
+        ms(final_drift),
+        alignment.max_drift()
+    );
+    Ok(())
+}
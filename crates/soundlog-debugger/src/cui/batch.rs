@@ -0,0 +1,298 @@
+// chipstream/crates/soundlog-debugger/src/cui/batch.rs
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow, bail};
+
+use crate::cui::exit;
+use crate::cui::vgm::{has_gzip_extension, input_bytes};
+
+// Name of the state file kept in the output directory.
+pub const STATE_FILE_NAME: &str = ".soundlog-batch";
+
+// First line of a state file. The command and its options follow it, so a
+// state written by another command or with other options is not reused.
+const STATE_MAGIC: &str = "# soundlog batch v1";
+
+// A finished file: the hashes of the input and the output it produced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Entry {
+    input_hash: u64,
+    output_hash: u64,
+}
+
+// Progress of a directory-wide run, appended to after every finished file so
+// an interrupted run can resume.
+//
+// The file holds the header line, then one `<input hash> <output hash>
+// <relative path>` line per finished file, hashes as 16 hex digits.
+struct BatchState {
+    entries: HashMap<String, Entry>,
+    writer: BufWriter<File>,
+}
+
+impl BatchState {
+    // Open the state at `path` for `job` (command and options). Entries of a
+    // state written for another job are dropped and the file is started over.
+    fn open(path: &Path, job: &str) -> Result<Self> {
+        let header = format!("{} {}", STATE_MAGIC, job);
+        let mut entries = HashMap::new();
+        let mut reuse = false;
+        match fs::read_to_string(path) {
+            Ok(text) => {
+                let mut lines = text.lines();
+                if lines.next() == Some(header.as_str()) {
+                    reuse = true;
+                    // A line cut off by an interruption does not parse and
+                    // its file is processed again.
+                    entries.extend(lines.filter_map(parse_entry));
+                } else {
+                    log::info!(
+                        "{}: written for another command or options, starting over",
+                        path.display()
+                    );
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("failed to read state file: {}", path.display()));
+            }
+        }
+
+        let file = if reuse {
+            OpenOptions::new().append(true).open(path)
+        } else {
+            File::create(path)
+        }
+        .with_context(|| format!("failed to open state file: {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        if !reuse {
+            writeln!(writer, "{}", header)
+                .and_then(|_| writer.flush())
+                .with_context(|| format!("failed to write state file: {}", path.display()))?;
+        }
+        Ok(Self { entries, writer })
+    }
+
+    // Record `relative` as finished; flushed so the entry survives an
+    // interruption right after.
+    fn record(&mut self, relative: &str, entry: Entry) -> Result<()> {
+        writeln!(
+            self.writer,
+            "{:016x} {:016x} {}",
+            entry.input_hash, entry.output_hash, relative
+        )
+        .and_then(|_| self.writer.flush())
+        .context("failed to write state file")?;
+        self.entries.insert(relative.to_string(), entry);
+        Ok(())
+    }
+}
+
+// Parse one `<input hash> <output hash> <relative path>` line.
+fn parse_entry(line: &str) -> Option<(String, Entry)> {
+    let mut fields = line.splitn(3, ' ');
+    let input_hash = u64::from_str_radix(fields.next()?, 16).ok()?;
+    let output_hash = u64::from_str_radix(fields.next()?, 16).ok()?;
+    let relative = fields.next().filter(|r| !r.is_empty())?;
+    Some((
+        relative.to_string(),
+        Entry {
+            input_hash,
+            output_hash,
+        },
+    ))
+}
+
+// 64-bit FNV-1a. Stable across builds and platforms, unlike `DefaultHasher`,
+// so state files stay valid after an update.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+// Append the `.vgm` and `.vgz` files below `dir` to `out`.
+fn collect_inputs(dir: &Path, out: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = fs::read_dir(dir)
+        .with_context(|| format!("failed to read directory: {}", dir.display()))?;
+    for entry in entries {
+        let path = entry
+            .with_context(|| format!("failed to read directory: {}", dir.display()))?
+            .path();
+        if path.is_dir() {
+            collect_inputs(&path, out)?;
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("vgm") || ext.eq_ignore_ascii_case("vgz"))
+        {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+// Run `process` for every `.vgm`/`.vgz` file below `input_dir`, writing to
+// the same relative path below `output_dir`, and return the exit code of the
+// run.
+//
+// The output is gzipped when `gzip` is set or the input is a `.vgz` file (see
+// `write_vgm`), and its extension is changed to `.vgz` to match. Inputs that
+// would be written to the same output (`a.vgm` and `a.vgz` with `gzip`) are
+// not processed and fail instead.
+//
+// `job` names the command and its options. Finished files are recorded in
+// the state file (`state_path`, or `STATE_FILE_NAME` in `output_dir`); a file
+// is skipped when its input still has the recorded hash and its output still
+// exists with the recorded hash. A file that fails is logged and the run goes
+// on; the exit code is then the highest code of the failures.
+pub fn run_batch(
+    input_dir: &Path,
+    output_dir: &Path,
+    state_path: Option<&Path>,
+    job: &str,
+    gzip: bool,
+    mut process: impl FnMut(&Path, &Path, Vec<u8>) -> Result<()>,
+) -> Result<i32> {
+    if output_dir.as_os_str() == "-" || output_dir.is_file() {
+        bail!("OUTPUT must be a directory when INPUT is a directory");
+    }
+    fs::create_dir_all(output_dir)
+        .with_context(|| format!("failed to create directory: {}", output_dir.display()))?;
+    let input_dir = &input_dir
+        .canonicalize()
+        .with_context(|| format!("failed to read directory: {}", input_dir.display()))?;
+    let output_root = output_dir
+        .canonicalize()
+        .with_context(|| format!("failed to read directory: {}", output_dir.display()))?;
+    let state_path = state_path
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| output_dir.join(STATE_FILE_NAME));
+    let mut state = BatchState::open(&state_path, job)?;
+
+    let mut inputs = Vec::new();
+    collect_inputs(input_dir, &mut inputs)?;
+    // An output directory inside the input directory holds earlier outputs,
+    // not inputs.
+    inputs.retain(|input| !input.starts_with(&output_root));
+    // Visit the files in the same order on every run.
+    inputs.sort();
+
+    let jobs: Vec<(&PathBuf, &Path, PathBuf)> = inputs
+        .iter()
+        .filter_map(|input| {
+            let relative = input.strip_prefix(input_dir).ok()?;
+            let mut output = output_dir.join(relative);
+            if gzip || has_gzip_extension(input) {
+                output.set_extension("vgz");
+            }
+            Some((input, relative, output))
+        })
+        .collect();
+    let mut sources: HashMap<&Path, Vec<&Path>> = HashMap::new();
+    for (input, _, output) in &jobs {
+        sources.entry(output).or_default().push(input);
+    }
+
+    let (mut processed, mut skipped, mut failed) = (0usize, 0usize, 0usize);
+    let mut exit_code = exit::SUCCESS;
+    for (input, relative, output) in &jobs {
+        let key = relative.to_string_lossy().replace('\\', "/");
+
+        let result = (|| -> Result<bool> {
+            if let Some(others) = sources.get(output.as_path())
+                && let Some(other) = others.iter().find(|other| **other != input.as_path())
+            {
+                return Err(anyhow!(
+                    "{}: output {} is also written for {}",
+                    input.display(),
+                    output.display(),
+                    other.display()
+                ));
+            }
+            let raw = fs::read(input)
+                .with_context(|| format!("failed to read file: {}", input.display()))?;
+            let input_hash = fnv1a(&raw);
+            if let Some(entry) = state.entries.get(&key)
+                && entry.input_hash == input_hash
+                && fs::read(output).is_ok_and(|bytes| fnv1a(&bytes) == entry.output_hash)
+            {
+                return Ok(false);
+            }
+
+            if let Some(parent) = output.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("failed to create directory: {}", parent.display()))?;
+            }
+            process(input, output, input_bytes(input, raw)?)?;
+            let written = fs::read(output)
+                .with_context(|| format!("failed to read output: {}", output.display()))?;
+            state.record(
+                &key,
+                Entry {
+                    input_hash,
+                    output_hash: fnv1a(&written),
+                },
+            )?;
+            Ok(true)
+        })();
+
+        match result {
+            Ok(true) => processed += 1,
+            Ok(false) => {
+                log::debug!("{}: up to date, skipped", input.display());
+                skipped += 1;
+            }
+            Err(e) => {
+                log::error!("{:#}", e);
+                exit_code = exit_code.max(exit::code_for_error(&e));
+                failed += 1;
+            }
+        }
+    }
+
+    log::info!(
+        "batch: {} file(s) processed, {} skipped as up to date, {} failed (state: {})",
+        processed,
+        skipped,
+        failed,
+        state_path.display()
+    );
+    Ok(exit_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inputs_sharing_an_output_fail() {
+        let dir = std::env::temp_dir().join(format!("soundlog-batch-{}", std::process::id()));
+        let (input_dir, output_dir) = (dir.join("in"), dir.join("out"));
+        fs::create_dir_all(&input_dir).unwrap();
+        for name in ["a.vgm", "a.vgz", "b.vgm"] {
+            fs::write(input_dir.join(name), b"Vgm ").unwrap();
+        }
+
+        let mut written = Vec::new();
+        let code = run_batch(
+            &input_dir,
+            &output_dir,
+            None,
+            "test",
+            true,
+            |_, output, _| {
+                written.push(output.file_name().unwrap().to_owned());
+                fs::write(output, b"").map_err(Into::into)
+            },
+        )
+        .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(code, exit::FAILURE);
+        assert_eq!(written, vec!["b.vgz"]);
+    }
+}
@@ -0,0 +1,394 @@
+// chipstream/crates/soundlog-debugger/src/cui/bounce.rs
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+
+use soundlog::VgmDocument;
+use soundlog::chip::Chip;
+use soundlog::vgm::command::{RegisterWrite, VgmCommand, WaitSamples};
+use soundlog::vgm::stream::{StreamResult, VgmStream};
+
+use crate::cui::vgm::write_output;
+
+// Sample rate of the rendered WAV: the VGM sample clock.
+const SAMPLE_RATE: u32 = 44100;
+
+// Render the writes generated by a single DAC stream into a WAV file.
+//
+// Every command that does not belong to `stream_id` (chip writes, other
+// streams, 0x8n data-bank writes) is removed from the document while the
+// data blocks and waits are kept, so expanding the result through
+// `VgmStream` yields exactly the writes generated by that stream. The writes
+// are rendered as 16-bit mono at 44100 Hz, see `render_stream`. The WAV goes
+// to stdout when `wav_path` is "-" (the summary then to stderr),
+// gzip-compressed with `gzip`.
+pub fn bounce_stream(
+    input_path: &Path,
+    wav_path: &Path,
+    data: Vec<u8>,
+    stream_id: u8,
+    gzip: bool,
+) -> Result<()> {
+    let doc: VgmDocument = (&data[..])
+        .try_into()
+        .with_context(|| format!("failed to parse input VGM: {}", input_path.display()))?;
+
+    let rendered = render_stream(&doc, stream_id)?;
+    let pcm: Vec<u8> = rendered
+        .samples
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .collect();
+    let wav = wav_bytes(&pcm, SAMPLE_RATE, 16);
+    write_output(wav_path, &wav, gzip, "WAV")?;
+
+    let summary = format!(
+        "\"{}\": stream {}: {} writes, {} samples at {} Hz (16-bit), starting at sample {}",
+        input_path.display(),
+        stream_id,
+        rendered.writes,
+        rendered.samples.len(),
+        SAMPLE_RATE,
+        rendered.start_sample
+    );
+    if wav_path == Path::new("-") {
+        log::info!("{}", summary);
+    } else {
+        println!("{}", summary);
+    }
+    Ok(())
+}
+
+// Audio rendered from one DAC stream.
+struct Rendered {
+    // Signed 16-bit samples at `SAMPLE_RATE`.
+    samples: Vec<i16>,
+    // Sample position of the first write in the document.
+    start_sample: u64,
+    // Number of writes the stream generated.
+    writes: usize,
+}
+
+// Expand stream `stream_id` of `doc` and render its writes.
+//
+// The audio starts at the stream's first write and runs to the end of the
+// document, so the silence between two starts of the stream is kept. Each
+// write holds its value for one period of the stream frequency in effect
+// at that sample (every `SetStreamFrequency` is followed) or until the next
+// write, whichever comes first; the rest is silence. Values are scaled by
+// the target chip's sample format: OKIM6258 bytes are decoded as two 4-bit
+// OKI ADPCM nibbles (low nibble first) with one decoder per chip instance,
+// PWM values are 12-bit unsigned, and other chips are 8-bit unsigned, or
+// 16-bit unsigned when a value does not fit in a byte.
+fn render_stream(doc: &VgmDocument, stream_id: u8) -> Result<Rendered> {
+    let mut isolated = doc.clone();
+    isolated.commands.clear();
+    // The loop offset points into the original command list.
+    isolated.header.loop_offset = 0;
+    isolated.header.loop_samples = 0;
+    // (sample position, frequency) of every `SetStreamFrequency` of the stream.
+    let mut frequencies: Vec<(u64, u32)> = Vec::new();
+    let mut position = 0u64;
+    for cmd in &doc.commands {
+        let keep = match cmd {
+            VgmCommand::DataBlock(_)
+            | VgmCommand::EndOfData(_)
+            | VgmCommand::WaitSamples(_)
+            | VgmCommand::Wait735Samples(_)
+            | VgmCommand::Wait882Samples(_)
+            | VgmCommand::WaitNSample(_) => true,
+            VgmCommand::YM2612Port0Address2AWriteAndWaitN(_) => {
+                // keep the timing of the data-bank write, drop the write itself
+                let wait = cmd.wait_samples();
+                if wait > 0 {
+                    isolated.commands.push(WaitSamples(wait as u16).into());
+                }
+                false
+            }
+            VgmCommand::SetupStreamControl(s) => s.stream_id == stream_id,
+            VgmCommand::SetStreamData(s) => s.stream_id == stream_id,
+            VgmCommand::SetStreamFrequency(s) => {
+                if s.stream_id == stream_id {
+                    frequencies.push((position, s.frequency));
+                }
+                s.stream_id == stream_id
+            }
+            VgmCommand::StartStream(s) => s.stream_id == stream_id,
+            VgmCommand::StartStreamFastCall(s) => s.stream_id == stream_id,
+            VgmCommand::StopStream(s) => s.stream_id == stream_id || s.stream_id == 0xFF,
+            _ => false,
+        };
+        position += u64::from(cmd.wait_samples());
+        if keep {
+            isolated.commands.push(cmd.clone());
+        }
+    }
+    if frequencies.is_empty() {
+        bail!("stream {} has no frequency set", stream_id);
+    }
+
+    let mut stream = VgmStream::from_document(isolated);
+    stream.set_loop_count(Some(1));
+    let mut writes: Vec<(u64, RegisterWrite)> = Vec::new();
+    let mut end_sample = 0;
+    for result in stream.timed() {
+        match result {
+            Ok((sample, StreamResult::Command(cmd))) => {
+                if let Some(write) = cmd.register_write() {
+                    writes.push((sample, write));
+                }
+                end_sample = sample;
+            }
+            Ok((sample, StreamResult::NeedsMoreData | StreamResult::EndOfStream)) => {
+                end_sample = end_sample.max(sample);
+                break;
+            }
+            Err(e) => bail!("stream processing error: {}", e),
+        }
+    }
+    let Some(&(start_sample, _)) = writes.first() else {
+        bail!("stream {} generated no writes", stream_id);
+    };
+    let last_sample = writes.last().map_or(start_sample, |(sample, _)| *sample);
+    let end_sample = end_sample.max(last_sample + 1);
+
+    let wide = writes.iter().any(|(_, w)| w.value > 0xFF);
+    let mut decoders: HashMap<u8, OkiAdpcm> = HashMap::new();
+    let mut samples = vec![0i16; (end_sample - start_sample) as usize];
+    for (i, (sample, write)) in writes.iter().enumerate() {
+        let next = writes.get(i + 1).map_or(end_sample, |(next, _)| *next);
+        let from = (sample - start_sample) as usize;
+        let len = (next - sample).min(period(&frequencies, *sample)) as usize;
+        let half = len / 2;
+        let (first, second) = match write.chip {
+            Chip::Okim6258 => {
+                let decoder = decoders.entry(write.instance as u8).or_default();
+                let first = decoder.decode(write.value as u8 & 0x0F);
+                (first, decoder.decode(write.value as u8 >> 4))
+            }
+            Chip::Pwm => {
+                let value = (((write.value & 0xFFF) as i32 - 0x800) << 4) as i16;
+                (value, value)
+            }
+            _ if wide => {
+                let value = (write.value as u16 ^ 0x8000) as i16;
+                (value, value)
+            }
+            _ => {
+                let value = (((write.value & 0xFF) as i32 - 0x80) << 8) as i16;
+                (value, value)
+            }
+        };
+        samples[from..from + half].fill(first);
+        samples[from + half..from + len].fill(second);
+    }
+
+    Ok(Rendered {
+        samples,
+        start_sample,
+        writes: writes.len(),
+    })
+}
+
+// Length in output samples of one write period at `sample`: the frequency
+// set by the latest `SetStreamFrequency` at or before `sample` (the first one
+// before any is in effect). A zero frequency holds until the next write.
+fn period(frequencies: &[(u64, u32)], sample: u64) -> u64 {
+    let frequency = frequencies
+        .iter()
+        .take_while(|(at, _)| *at <= sample)
+        .last()
+        .or(frequencies.first())
+        .map_or(0, |(_, frequency)| *frequency);
+    if frequency == 0 {
+        u64::MAX
+    } else {
+        u64::from(SAMPLE_RATE).div_ceil(u64::from(frequency)).max(1)
+    }
+}
+
+// OKI 4-bit ADPCM decoder (OKIM6258/MSM5205) with a 12-bit output.
+#[derive(Debug, Default)]
+struct OkiAdpcm {
+    signal: i32,
+    step: usize,
+}
+
+impl OkiAdpcm {
+    const STEPS: [i32; 49] = [
+        16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66, 73, 80, 88, 97, 107, 118,
+        130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449, 494, 544, 598, 658,
+        724, 796, 876, 963, 1060, 1166, 1282, 1411, 1552,
+    ];
+    const INDEX_SHIFT: [isize; 8] = [-1, -1, -1, -1, 2, 4, 6, 8];
+
+    // Decode one nibble and return the signal scaled to 16 bits.
+    fn decode(&mut self, nibble: u8) -> i16 {
+        let step = Self::STEPS[self.step];
+        let mut diff = step / 8;
+        if nibble & 1 != 0 {
+            diff += step / 4;
+        }
+        if nibble & 2 != 0 {
+            diff += step / 2;
+        }
+        if nibble & 4 != 0 {
+            diff += step;
+        }
+        if nibble & 8 != 0 {
+            diff = -diff;
+        }
+        self.signal = (self.signal + diff).clamp(-2048, 2047);
+        self.step = self
+            .step
+            .saturating_add_signed(Self::INDEX_SHIFT[usize::from(nibble & 7)])
+            .min(Self::STEPS.len() - 1);
+        (self.signal << 4) as i16
+    }
+}
+
+// Wrap mono PCM data in a RIFF/WAVE container.
+fn wav_bytes(pcm: &[u8], sample_rate: u32, bits: u16) -> Vec<u8> {
+    let block_align = bits / 8;
+    let mut out = Vec::with_capacity(44 + pcm.len());
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + pcm.len() as u32).to_le_bytes());
+    out.extend_from_slice(b"WAVEfmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    out.extend_from_slice(&1u16.to_le_bytes()); // mono
+    out.extend_from_slice(&sample_rate.to_le_bytes());
+    out.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    out.extend_from_slice(&block_align.to_le_bytes());
+    out.extend_from_slice(&bits.to_le_bytes());
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&(pcm.len() as u32).to_le_bytes());
+    out.extend_from_slice(pcm);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use soundlog::VgmBuilder;
+    use soundlog::vgm::command::{
+        DacStreamChipType, Instance, LengthMode, SetStreamData, SetStreamFrequency,
+        SetupStreamControl, StartStream,
+    };
+    use soundlog::vgm::header::ChipId;
+
+    // A document that streams `data` (bank `data_type`) to `chip_id` at
+    // `frequency`, started at sample 0.
+    fn streamed(chip_id: ChipId, data_type: u8, data: Vec<u8>, frequency: u32) -> VgmBuilder {
+        let len = data.len() as u32;
+        let mut builder = VgmBuilder::new();
+        builder.add_data_block_for_bank(data_type, data);
+        builder.add_vgm_command(SetupStreamControl {
+            stream_id: 0,
+            chip_type: DacStreamChipType::new(chip_id, Instance::Primary),
+            write_port: 0,
+            write_command: 0x2A,
+        });
+        builder.add_vgm_command(SetStreamData {
+            stream_id: 0,
+            data_bank_id: data_type,
+            step_size: 1,
+            step_base: 0,
+        });
+        builder.add_vgm_command(SetStreamFrequency {
+            stream_id: 0,
+            frequency,
+        });
+        builder.add_vgm_command(start(len));
+        builder
+    }
+
+    fn start(data_length: u32) -> StartStream {
+        StartStream {
+            stream_id: 0,
+            data_start_offset: 0,
+            length_mode: LengthMode::CommandCount {
+                reverse: false,
+                looped: false,
+            },
+            data_length,
+        }
+    }
+
+    #[test]
+    fn test_bounce_keeps_gap_between_starts() {
+        let mut builder = streamed(ChipId::Ym2612, 0x00, vec![0xFF; 4], 44100);
+        builder.add_vgm_command(WaitSamples(10));
+        builder.add_vgm_command(start(4));
+        builder.add_vgm_command(WaitSamples(10));
+        let rendered = render_stream(&builder.finalize(), 0).unwrap();
+
+        assert_eq!(rendered.writes, 8);
+        assert_eq!(rendered.samples.len(), 20);
+        assert!(rendered.samples[..4].iter().all(|s| *s == 0x7F00));
+        assert!(rendered.samples[4..10].iter().all(|s| *s == 0));
+        assert!(rendered.samples[10..14].iter().all(|s| *s == 0x7F00));
+    }
+
+    #[test]
+    fn test_bounce_follows_frequency_changes() {
+        let mut builder = streamed(ChipId::Ym2612, 0x00, vec![0xFF; 2], 44100);
+        builder.add_vgm_command(WaitSamples(10));
+        builder.add_vgm_command(SetStreamFrequency {
+            stream_id: 0,
+            frequency: 22050,
+        });
+        builder.add_vgm_command(start(2));
+        builder.add_vgm_command(WaitSamples(10));
+        let rendered = render_stream(&builder.finalize(), 0).unwrap();
+
+        // Two writes at 44100 Hz, then two at 22050 Hz that last two
+        // samples each.
+        assert_eq!(rendered.writes, 4);
+        assert!(rendered.samples[..2].iter().all(|s| *s == 0x7F00));
+        assert!(rendered.samples[2..10].iter().all(|s| *s == 0));
+        assert!(rendered.samples[10..14].iter().all(|s| *s == 0x7F00));
+        assert!(rendered.samples[14..].iter().all(|s| *s == 0));
+    }
+
+    #[test]
+    fn test_bounce_scales_pwm_as_12_bit() {
+        let mut builder = streamed(ChipId::Pwm, 0x03, vec![0xFF, 0x00], 44100);
+        builder.add_vgm_command(WaitSamples(4));
+        let rendered = render_stream(&builder.finalize(), 0).unwrap();
+
+        // write_command 0x2A supplies the high nibble: 0xAFF and 0xA00.
+        assert_eq!(rendered.samples[0], ((0xAFF - 0x800) << 4) as i16);
+        assert_eq!(rendered.samples[1], ((0xA00 - 0x800) << 4) as i16);
+    }
+
+    #[test]
+    fn test_bounce_decodes_okim6258_adpcm() {
+        let mut builder = streamed(ChipId::Okim6258, 0x04, vec![0x77, 0x77], 22050);
+        builder.add_vgm_command(WaitSamples(4));
+        let rendered = render_stream(&builder.finalize(), 0).unwrap();
+
+        let mut decoder = OkiAdpcm::default();
+        let expected: Vec<i16> = (0..4).map(|_| decoder.decode(7)).collect();
+        assert_eq!(rendered.samples, expected);
+        // The signal rises nibble by nibble instead of repeating 0x77.
+        assert!(rendered.samples.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_bounce_ignores_original_loop_point() {
+        let mut builder = streamed(ChipId::Ym2612, 0x00, vec![0xFF; 2], 44100);
+        builder.add_vgm_command(WaitSamples(5));
+        // setup, data, frequency, start, wait: the loop starts at the
+        // second wait.
+        builder.set_loop_offset(5);
+        builder.add_vgm_command(WaitSamples(5));
+        let doc = builder.finalize();
+        assert!(doc.header.loop_offset != 0);
+
+        let rendered = render_stream(&doc, 0).unwrap();
+        assert_eq!(rendered.samples.len(), 10);
+    }
+}
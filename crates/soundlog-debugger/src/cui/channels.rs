@@ -0,0 +1,70 @@
+// chipstream/crates/soundlog-debugger/src/cui/channels.rs
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use soundlog::VgmDocument;
+use soundlog::vgm::usage::channel_usage;
+
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+// Nearest equal-tempered note name of a frequency, e.g. `A4` for 440 Hz.
+fn note_name(hz: f32) -> String {
+    let midi = (69.0 + 12.0 * (hz / 440.0).log2()).round() as i32;
+    format!(
+        "{}{}",
+        NOTE_NAMES[midi.rem_euclid(12) as usize],
+        midi.div_euclid(12) - 1
+    )
+}
+
+// Print one line per channel keyed on at least once: the number of notes,
+// the pitch range, the share of the song during which the channel sounds
+// and the voices (FM patches, PSG duty and noise modes) it keys on, as
+// `#index x count`. Voice numbers are shared by all channels of a chip.
+pub fn channels_vgm(input_path: &Path, data: Vec<u8>) -> Result<()> {
+    let doc: VgmDocument = (&data[..])
+        .try_into()
+        .with_context(|| format!("failed to parse input VGM: {}", input_path.display()))?;
+
+    let report = channel_usage(&doc);
+    if report.channels.is_empty() {
+        println!("No channel with key state tracking is keyed on.");
+        return Ok(());
+    }
+    for usage in &report.channels {
+        let pitch = match usage.pitch_range {
+            Some((low, high)) => format!(
+                "{:.1}-{:.1} Hz ({}-{})",
+                low,
+                high,
+                note_name(low),
+                note_name(high)
+            ),
+            None => "pitch unknown".to_string(),
+        };
+        let voices: Vec<String> = usage
+            .voices
+            .iter()
+            .map(|(voice, count)| format!("#{} x{}", voice, count))
+            .collect();
+        println!(
+            "{:?} ({:?}) ch {}: {} notes, {}, active {:.1}%{}",
+            usage.chip,
+            usage.instance,
+            usage.channel,
+            usage.notes,
+            pitch,
+            usage.active_percent(report.samples),
+            if voices.is_empty() {
+                String::new()
+            } else {
+                format!(", voices {}", voices.join(" "))
+            }
+        );
+    }
+    println!("{} voices", report.voices.len());
+    Ok(())
+}
@@ -0,0 +1,46 @@
+//! Process exit codes shared by every subcommand.
+//!
+//! | code | meaning |
+//! |------|---------|
+//! | 0 | success |
+//! | 1 | the check ran and found problems (lint findings, loop issues, round-trip mismatches) |
+//! | 2 | invalid command line |
+//! | 3 | the input is not a valid VGM file |
+//! | 4 | a file or stream could not be read or written |
+//! | 5 | any other failure |
+
+use soundlog::ParseError;
+
+use crate::cui::report::TestOutcome;
+
+pub const SUCCESS: i32 = 0;
+pub const VALIDATION_FAILED: i32 = 1;
+/// Also used by clap for argument errors.
+pub const USAGE: i32 = 2;
+pub const PARSE_ERROR: i32 = 3;
+pub const IO_ERROR: i32 = 4;
+pub const FAILURE: i32 = 5;
+
+/// Exit code for `err`, picked from the outermost parse or I/O error in its
+/// cause chain.
+pub fn code_for_error(err: &anyhow::Error) -> i32 {
+    for cause in err.chain() {
+        if cause.is::<ParseError>() {
+            return PARSE_ERROR;
+        }
+        if cause.is::<std::io::Error>() {
+            return IO_ERROR;
+        }
+    }
+    FAILURE
+}
+
+/// Exit code for one `test` result. A file that does not parse, before or
+/// after the round trip, counts as a parse error.
+pub fn code_for_outcome(outcome: &TestOutcome) -> i32 {
+    match outcome {
+        TestOutcome::Pass => SUCCESS,
+        TestOutcome::Failure(_) => VALIDATION_FAILED,
+        TestOutcome::Error(_) => PARSE_ERROR,
+    }
+}
@@ -0,0 +1,37 @@
+// chipstream/crates/soundlog-debugger/src/cui/frames.rs
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use soundlog::VgmDocument;
+use soundlog::vgm::export::frames::FrameExport;
+
+use crate::cui::vgm::write_output;
+
+// Export a per-frame register-delta listing of a VGM file.
+//
+// The document is grouped into ticks of `samples_per_frame` samples and only
+// register writes that change a value are listed. The listing is written as
+// text (or JSON when `json` is set) to `output_path`, or to stdout when the
+// path is "-", gzip-compressed with `gzip`.
+pub fn export_frames(
+    input_path: &Path,
+    output_path: &Path,
+    data: Vec<u8>,
+    samples_per_frame: u32,
+    json: bool,
+    gzip: bool,
+) -> Result<()> {
+    let doc: VgmDocument = (&data[..])
+        .try_into()
+        .with_context(|| format!("failed to parse input VGM: {}", input_path.display()))?;
+
+    let export = FrameExport::from_document(&doc, samples_per_frame);
+    let rendered = if json {
+        export.to_json()
+    } else {
+        export.to_text()
+    };
+
+    write_output(output_path, rendered.as_bytes(), gzip, "frame export")
+}
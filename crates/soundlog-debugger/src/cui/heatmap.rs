@@ -0,0 +1,56 @@
+// chipstream/crates/soundlog-debugger/src/cui/heatmap.rs
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use soundlog::VgmDocument;
+use soundlog::vgm::heatmap::{HeatmapOptions, register_heatmap};
+
+// Print the register write counts of every chip instance as 16-column grids,
+// one per written port (`.` for registers that are never written). With
+// `window`, a line of per-window write totals follows each chip so that busy
+// and idle parts of the song stand out.
+pub fn heatmap_vgm(input_path: &Path, data: Vec<u8>, window: Option<u64>) -> Result<()> {
+    let doc: VgmDocument = (&data[..])
+        .try_into()
+        .with_context(|| format!("failed to parse input VGM: {}", input_path.display()))?;
+
+    let options = HeatmapOptions { window };
+    let heatmap = register_heatmap(&doc, &options);
+    for chip in &heatmap.chips {
+        println!(
+            "{:?} ({:?}): {} writes",
+            chip.chip,
+            chip.instance,
+            chip.total()
+        );
+        for port in chip.ports() {
+            println!("  port {}", port);
+            print!("       ");
+            for column in 0..16 {
+                print!(" {:>6X}", column);
+            }
+            println!();
+            for (row, counts) in chip.grid(port).iter().enumerate() {
+                print!("  {:04X}:", row * 16);
+                for &count in counts {
+                    if count == 0 {
+                        print!(" {:>6}", ".");
+                    } else {
+                        print!(" {:>6}", count);
+                    }
+                }
+                println!();
+            }
+        }
+        if let Some(window) = heatmap.window {
+            let totals: Vec<String> = chip
+                .windows
+                .iter()
+                .map(|counts| counts.values().sum::<u64>().to_string())
+                .collect();
+            println!("  per {} samples: {}", window, totals.join(" "));
+        }
+    }
+    Ok(())
+}
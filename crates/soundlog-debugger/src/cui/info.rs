@@ -0,0 +1,95 @@
+// chipstream/crates/soundlog-debugger/src/cui/info.rs
+use std::path::Path;
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use comfy_table::{Cell, ContentArrangement, Table, presets::NOTHING};
+
+use soundlog::VgmDocument;
+use soundlog::vgm::driver::detect_driver;
+use soundlog::vgm::frame_rate::infer_frame_rate;
+
+use crate::cui::vgm::summarize_doc;
+
+// Print the header summary of a VGM file followed by the guessed sound
+// driver and frame rate. All driver candidates are listed best first with their score and the
+// patterns that matched, since the guess is only a heuristic.
+//
+// With `verbose`, the parse time and the memory held by the parsed document
+// are listed too, to judge how a file scales before loading it elsewhere.
+pub fn info_vgm(input_path: &Path, data: Vec<u8>, verbose: bool) -> Result<()> {
+    let started = Instant::now();
+    let doc: VgmDocument = (&data[..])
+        .try_into()
+        .with_context(|| format!("failed to parse input VGM: {}", input_path.display()))?;
+    let parse_time = started.elapsed();
+
+    let guesses = detect_driver(&doc);
+    let driver = if guesses.is_empty() {
+        "(unknown)".to_string()
+    } else {
+        guesses
+            .iter()
+            .map(|guess| {
+                format!(
+                    "{} ({}%): {}",
+                    guess.driver,
+                    guess.score,
+                    guess.evidence.join(", ")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    let frame_rate = match infer_frame_rate(&doc) {
+        Some(guess) => {
+            let common = guess
+                .most_common()
+                .iter()
+                .take(3)
+                .map(|(gap, count)| format!("{}x{}", gap, count))
+                .collect::<Vec<_>>()
+                .join(" ");
+            format!(
+                "{} (60 Hz {}%, 50 Hz {}% of {} gaps)\ngaps: {}",
+                guess.rate, guess.ntsc_share, guess.pal_share, guess.gaps, common
+            )
+        }
+        None => "(unknown)".to_string(),
+    };
+
+    let mut table = Table::new();
+    table.load_preset(NOTHING);
+    table.set_content_arrangement(ContentArrangement::Dynamic);
+    let mut rows = summarize_doc(&doc);
+    rows.push(("driver".to_string(), driver));
+    rows.push(("frame_rate".to_string(), frame_rate));
+    if verbose {
+        let footprint = doc.memory_footprint();
+        rows.push((
+            "parse time".to_string(),
+            format!("{:.3} ms", parse_time.as_secs_f64() * 1000.0),
+        ));
+        rows.push((
+            "memory".to_string(),
+            format!(
+                "{} bytes ({:.1}x file size)\ncommands: {} bytes\ndata: {} bytes\nmetadata: {} bytes",
+                footprint.total(),
+                footprint.total() as f64 / data.len().max(1) as f64,
+                footprint.commands,
+                footprint.data,
+                footprint.metadata
+            ),
+        ));
+    }
+    for (field, value) in rows {
+        for (i, line) in value.split('\n').enumerate() {
+            let field = if i == 0 { field.as_str() } else { "" };
+            table.add_row(vec![Cell::new(field), Cell::new(line)]);
+        }
+    }
+    println!("\"{}\"", input_path.display());
+    println!("{}", table);
+    Ok(())
+}
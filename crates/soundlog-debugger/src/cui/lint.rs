@@ -0,0 +1,25 @@
+// chipstream/crates/soundlog-debugger/src/cui/lint.rs
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use soundlog::VgmDocument;
+use soundlog::vgm::lint::{LintOptions, lint_with_options};
+
+// Print the lint findings of a VGM file, one per line, prefixed with the file
+// name. `options` enables the strict and write burst checks.
+// Returns the number of findings so the caller can pick the exit code.
+pub fn lint_vgm(input_path: &Path, data: Vec<u8>, options: &LintOptions) -> Result<usize> {
+    let doc: VgmDocument = (&data[..])
+        .try_into()
+        .with_context(|| format!("failed to parse input VGM: {}", input_path.display()))?;
+
+    let report = lint_with_options(&doc, options);
+    for issue in &report.issues {
+        println!("\"{}\": {}", input_path.display(), issue);
+    }
+    if report.is_clean() {
+        log::info!("\"{}\": lint: no issues", input_path.display());
+    }
+    Ok(report.issues.len())
+}
@@ -0,0 +1,62 @@
+// chipstream/crates/soundlog-debugger/src/cui/loop_check.rs
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use soundlog::VgmDocument;
+use soundlog::vgm::loop_check::check_loop;
+use soundlog::vgm::transform::patch_loop_state;
+
+use crate::cui::vgm::write_vgm;
+
+// Print the chip state differences between the first pass over the loop point
+// and the jump back from the end, one per line, prefixed with the file name.
+//
+// With `patch_path`, writes reconciling the differences are inserted at the
+// loop point and the result is written there (`-` for stdout, gzip-compressed
+// with `gzip` or a `.vgz`/`.gz` path); only the issues that could not be fixed
+// are printed then, to stderr when the VGM goes to stdout. Returns the number
+// of printed findings so the caller can pick the exit code.
+pub fn loop_check_vgm(
+    input_path: &Path,
+    data: Vec<u8>,
+    patch_path: Option<&Path>,
+    gzip: bool,
+) -> Result<usize> {
+    let doc: VgmDocument = (&data[..])
+        .try_into()
+        .with_context(|| format!("failed to parse input VGM: {}", input_path.display()))?;
+
+    let report = check_loop(&doc);
+    if report.loop_start.is_none() {
+        log::info!("\"{}\": loop-check: no loop", input_path.display());
+        return Ok(0);
+    }
+    let issues = match patch_path {
+        Some(patch_path) => {
+            let (patched, patch) = patch_loop_state(&doc);
+            let bytes: Vec<u8> = (&patched).into();
+            write_vgm(patch_path, &bytes, gzip)?;
+            log::info!(
+                "\"{}\": loop-check: {} issue(s) fixed with {} command(s) at the loop point",
+                input_path.display(),
+                patch.resolved.len(),
+                patch.inserted_commands
+            );
+            patch.unresolved
+        }
+        None => report.issues,
+    };
+    let to_stderr = patch_path == Some(Path::new("-"));
+    for issue in &issues {
+        if to_stderr {
+            log::warn!("\"{}\": {}", input_path.display(), issue);
+        } else {
+            println!("\"{}\": {}", input_path.display(), issue);
+        }
+    }
+    if issues.is_empty() {
+        log::info!("\"{}\": loop-check: no issues", input_path.display());
+    }
+    Ok(issues.len())
+}
@@ -0,0 +1,84 @@
+// chipstream/crates/soundlog-debugger/src/cui/optimize.rs
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+
+use soundlog::VgmDocument;
+use soundlog::vgm::transform::{
+    CompressOptions, PruneRomOptions, compress_data_blocks, dedupe_data_blocks, prune_rom_blocks,
+};
+
+// Remove duplicate PCM data blocks of a VGM file, then rewrite the remaining
+// uncompressed blocks as compressed blocks.
+//
+// `bits` is the compressed value width. With `prune_rom`, unread parts of ROM
+// data blocks are dropped first. The result is re-parsed and written
+// gzipped with `gzip` or when `output_path` ends in `.vgz` or `.gz`. A one-line summary is printed to stderr so it
+// does not mix with the VGM bytes when writing to stdout.
+pub fn optimize_vgm(
+    input_path: &Path,
+    output_path: &Path,
+    data: Vec<u8>,
+    bits: u8,
+    prune_rom: bool,
+    gzip: bool,
+) -> Result<()> {
+    if !(1..=7).contains(&bits) {
+        bail!("--bits must be between 1 and 7, got {}", bits);
+    }
+    let doc: VgmDocument = (&data[..])
+        .try_into()
+        .with_context(|| format!("failed to parse input VGM: {}", input_path.display()))?;
+
+    let doc = if prune_rom {
+        let (pruned, report) = prune_rom_blocks(&doc, &PruneRomOptions::default());
+        log::info!(
+            "\"{}\": optimize: {} ROM block(s) pruned, {} -> {} bytes",
+            input_path.display(),
+            report.pruned_blocks,
+            report.bytes_before,
+            report.bytes_after
+        );
+        if !report.skipped.is_empty() {
+            log::warn!(
+                "\"{}\": optimize: ROM usage unknown, left as is: {:?}",
+                input_path.display(),
+                report.skipped
+            );
+        }
+        pruned
+    } else {
+        doc
+    };
+    let (deduped, dedupe) = dedupe_data_blocks(&doc);
+    log::info!(
+        "\"{}\": optimize: {} duplicate block(s) removed ({} bytes), {} kept for stream references",
+        input_path.display(),
+        dedupe.removed_blocks,
+        dedupe.bytes_saved,
+        dedupe.kept_for_references
+    );
+    let (optimized, report) = compress_data_blocks(&deduped, &CompressOptions::new(bits));
+    let bytes: Vec<u8> = (&optimized).into();
+    let _: VgmDocument = (&bytes[..])
+        .try_into()
+        .with_context(|| "optimized VGM failed to re-parse")?;
+    crate::cui::vgm::write_vgm(output_path, &bytes, gzip)?;
+
+    match report.encoding {
+        Some(encoding) => log::info!(
+            "\"{}\": optimize: {} block(s) compressed with {:?}, {} -> {} bytes",
+            input_path.display(),
+            report.compressed_blocks,
+            encoding,
+            report.bytes_before,
+            report.bytes_after
+        ),
+        None => log::info!(
+            "\"{}\": optimize: data blocks left uncompressed ({} bytes)",
+            input_path.display(),
+            report.bytes_before
+        ),
+    }
+    Ok(())
+}
@@ -75,6 +75,26 @@ pub fn play_vgm(
                     }
                 }
                 StateEvent::KeyOff { channel } => write!(f, "KeyOff(ch={})", channel),
+                StateEvent::SampleStart {
+                    channel,
+                    offset,
+                    length,
+                } => write!(
+                    f,
+                    "SampleStart(ch={}, offset=0x{:06X}, length={})",
+                    channel, offset, length
+                ),
+                StateEvent::RhythmHit {
+                    channel,
+                    instrument,
+                    attenuation,
+                } => write!(
+                    f,
+                    "RhythmHit(ch={}, {}, att={})",
+                    channel,
+                    instrument.name(),
+                    attenuation
+                ),
                 StateEvent::ToneChange { channel, tone } => {
                     if let Some(freq) = tone.freq_hz {
                         write!(
@@ -1,24 +1,58 @@
 // chipstream/crates/soundlog-debugger/src/cui/redump.rs
-use std::fs;
 use std::path::Path;
 
 use anyhow::{Context, Result};
 
 use soundlog::VgmBuilder;
 use soundlog::VgmDocument;
-use soundlog::vgm::stream::{StreamResult, VgmStream};
+use soundlog::vgm::stream::{StreamResult, VgmStream, WriteRateLimit};
 
 // Redump VGM file with DAC streams expanded to chip writes.
 //
 // This function parses the input VGM, processes it through VgmStream (which expands
 // DAC Stream Control commands into actual chip writes), and writes the result to
-// a new VGM file. This is useful for verifying that stream expansion works correctly.
-pub fn redump_vgm(input_path: &Path, output_path: &Path, data: Vec<u8>, diag: bool) -> Result<()> {
+// a new VGM file, gzipped with `gzip` or when `output_path` ends in `.vgz` or `.gz`. This is
+// useful for verifying that stream expansion works correctly.
+//
+// When `rate_limit` is given, generated DAC writes to each chip are spaced at
+// least `min_gap_samples` apart so the output is safe for real hardware players.
+// `write_delays` lists (chip name, busy nanoseconds) pairs; DAC writes are held
+// back while the named chip is still busy with a previous write.
+pub fn redump_vgm(
+    input_path: &Path,
+    output_path: &Path,
+    data: Vec<u8>,
+    diag: bool,
+    rate_limit: Option<WriteRateLimit>,
+    write_delays: &[(String, u32)],
+    gzip: bool,
+) -> Result<()> {
     // Parse original VGM document
     let doc_orig: VgmDocument = (&data[..])
         .try_into()
         .with_context(|| format!("failed to parse input VGM: {}", input_path.display()))?;
 
+    // Resolve chip names against the chips used by the file
+    let mut chip_delays = Vec::with_capacity(write_delays.len());
+    for (name, ns) in write_delays {
+        let chip = doc_orig
+            .header
+            .chip_instances()
+            .into_iter()
+            .map(|(_, chip, _)| chip)
+            .find(|chip| format!("{:?}", chip).eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                anyhow::anyhow!("--write-delay: chip '{}' is not used by this file", name)
+            })?;
+        chip_delays.push((chip, *ns));
+    }
+    let configure = |stream: &mut VgmStream| {
+        stream.set_write_rate_limit(rate_limit);
+        for (chip, ns) in &chip_delays {
+            stream.set_chip_write_delay(chip.clone(), Some(*ns));
+        }
+    };
+
     // Calculate the original loop command index from the header's loop_offset
     // Always compute the original loop index so we preserve the original loop
     // structure in the redumped output.
@@ -49,6 +83,8 @@ pub fn redump_vgm(input_path: &Path, output_path: &Path, data: Vec<u8>, diag: bo
         // Expand the intro commands through VgmStream
         let intro_doc = intro_builder.finalize();
         let mut intro_stream = VgmStream::from_document(intro_doc);
+        // Same scheduling as the full expansion so the loop index lines up
+        configure(&mut intro_stream);
         // Don't set loop_count - we want to process all intro commands exactly once
         // (The intro_doc doesn't have a loop point set, so it will process all commands)
 
@@ -80,6 +116,7 @@ pub fn redump_vgm(input_path: &Path, output_path: &Path, data: Vec<u8>, diag: bo
 
     // Redump after a single playback
     stream.set_loop_count(Some(1));
+    configure(&mut stream);
 
     // Collect all commands from stream
     let mut commands = Vec::new();
@@ -103,6 +140,42 @@ pub fn redump_vgm(input_path: &Path, output_path: &Path, data: Vec<u8>, diag: bo
         }
     }
 
+    // Report DAC streams that fought over the same register; the writes were
+    // interleaved (VgmStream's default overlap policy).
+    for c in stream.stream_collisions() {
+        log::warn!(
+            "sample {}: DAC stream {} started while stream {} writes to {:?}#{} port {} reg 0x{:02X}",
+            c.sample,
+            c.stream_id,
+            c.active_stream_id,
+            c.chip_id,
+            usize::from(c.instance),
+            c.write_port,
+            c.write_command
+        );
+    }
+
+    // Data blocks read after the first wait grew their bank mid-playback.
+    for block in stream.late_data_blocks() {
+        log::warn!(
+            "sample {}: data block extends bank 0x{:02X} from {} to {} bytes",
+            block.sample,
+            block.bank,
+            block.previous_len,
+            block.len
+        );
+    }
+
+    if rate_limit.is_some() || !chip_delays.is_empty() {
+        let report = stream.write_rate_limit_report();
+        log::info!(
+            "write scheduling: {} write(s) delayed, {} coalesced, {} dropped",
+            report.delayed,
+            report.coalesced,
+            report.dropped
+        );
+    }
+
     // Ensure the redumped command stream terminates with EndOfData
     commands.push(soundlog::vgm::command::VgmCommand::EndOfData(
         soundlog::vgm::command::EndOfData,
@@ -172,17 +245,7 @@ pub fn redump_vgm(input_path: &Path, output_path: &Path, data: Vec<u8>, diag: bo
     let rebuilt_bytes: Vec<u8> = (&doc_rebuilt).into();
 
     // Write to output file or stdout if output_path is "-" (convention)
-    if output_path == std::path::Path::new("-") {
-        // Write to stdout
-        use std::io::Write;
-        let mut stdout = std::io::stdout();
-        stdout
-            .write_all(&rebuilt_bytes)
-            .with_context(|| "failed to write output VGM to stdout")?;
-    } else {
-        fs::write(output_path, &rebuilt_bytes)
-            .with_context(|| format!("failed to write output VGM: {}", output_path.display()))?;
-    }
+    crate::cui::vgm::write_vgm(output_path, &rebuilt_bytes, gzip)?;
 
     // Re-parse serialized bytes into a VgmDocument
     let doc_reparsed_res: Result<VgmDocument, _> = (&rebuilt_bytes[..]).try_into();
@@ -193,7 +256,7 @@ pub fn redump_vgm(input_path: &Path, output_path: &Path, data: Vec<u8>, diag: bo
             }
         }
         Err(e) => {
-            eprintln!(
+            log::error!(
                 "\"{}\": roundtrip: serialization produced bytes (len={}), but re-parse failed: {} — run with --diag to see serialized bytes and diagnostics",
                 output_path.display(),
                 rebuilt_bytes.len(),
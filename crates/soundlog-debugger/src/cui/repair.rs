@@ -0,0 +1,45 @@
+// chipstream/crates/soundlog-debugger/src/cui/repair.rs
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use soundlog::VgmDocument;
+use soundlog::vgm::repair::{RepairedField, repair_offsets};
+
+use crate::cui::vgm::write_vgm;
+
+// Recompute the header offsets of a VGM file from its content and write the
+// result to `output_path` (`-` for stdout), gzip-compressed with `gzip` or a
+// `.vgz`/`.gz` path.
+//
+// Every changed field is printed to stderr. The repaired file is re-parsed
+// before it is written, so a file that is broken beyond its offsets is
+// reported as an error instead.
+pub fn repair_vgm(input_path: &Path, output_path: &Path, data: Vec<u8>, gzip: bool) -> Result<()> {
+    let (bytes, repairs) = repair_offsets(&data)
+        .with_context(|| format!("failed to read VGM header: {}", input_path.display()))?;
+    let _: VgmDocument = (&bytes[..])
+        .try_into()
+        .with_context(|| "repaired VGM failed to parse")?;
+
+    write_vgm(output_path, &bytes, gzip)?;
+
+    for repair in &repairs {
+        let field = match repair.field {
+            RepairedField::Header(field) => format!("{:?}", field),
+            RepairedField::ChipClockOffset => "ExtraHeader.ChipClockOffset".to_string(),
+            RepairedField::ChipVolumeOffset => "ExtraHeader.ChipVolumeOffset".to_string(),
+        };
+        log::info!(
+            "\"{}\": repair: {} 0x{:08X} -> 0x{:08X}",
+            input_path.display(),
+            field,
+            repair.stored,
+            repair.repaired
+        );
+    }
+    if repairs.is_empty() {
+        log::info!("\"{}\": repair: offsets are valid", input_path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,199 @@
+// chipstream/crates/soundlog-debugger/src/cui/report.rs
+use std::fmt::Write as _;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::cui::vgm::write_output;
+
+// Machine-readable report format for `test` runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Junit,
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "junit" => Ok(ReportFormat::Junit),
+            "json" => Ok(ReportFormat::Json),
+            other => Err(format!(
+                "unknown report format '{}' (expected junit or json)",
+                other
+            )),
+        }
+    }
+}
+
+// Result of a roundtrip test on one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestOutcome {
+    // Roundtrip succeeded.
+    Pass,
+    // Roundtrip completed but the result differs from the original.
+    Failure(String),
+    // The file could not be read, parsed or re-parsed.
+    Error(String),
+}
+
+// One file of a `test` run.
+#[derive(Debug, Clone)]
+pub struct TestCase {
+    pub file: String,
+    pub outcome: TestOutcome,
+    pub duration: Duration,
+}
+
+// Count (passed, failures, errors) of a run.
+fn summary(cases: &[TestCase]) -> (usize, usize, usize) {
+    cases
+        .iter()
+        .fold((0, 0, 0), |(p, f, e), case| match case.outcome {
+            TestOutcome::Pass => (p + 1, f, e),
+            TestOutcome::Failure(_) => (p, f + 1, e),
+            TestOutcome::Error(_) => (p, f, e + 1),
+        })
+}
+
+fn escape_xml(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c if (c as u32) < 0x20 && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn escape_json(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// Render a JUnit XML report with one test case per file.
+pub fn to_junit(cases: &[TestCase]) -> String {
+    let (_, failures, errors) = summary(cases);
+    let total: f64 = cases.iter().map(|c| c.duration.as_secs_f64()).sum();
+    let mut out = String::new();
+    let _ = writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>");
+    let _ = writeln!(
+        out,
+        "<testsuites tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{:.3}\">",
+        cases.len(),
+        failures,
+        errors,
+        total
+    );
+    let _ = writeln!(
+        out,
+        "  <testsuite name=\"soundlog.roundtrip\" tests=\"{}\" failures=\"{}\" errors=\"{}\" time=\"{:.3}\">",
+        cases.len(),
+        failures,
+        errors,
+        total
+    );
+    for case in cases {
+        let _ = write!(
+            out,
+            "    <testcase classname=\"soundlog.roundtrip\" name=\"{}\" time=\"{:.3}\"",
+            escape_xml(&case.file),
+            case.duration.as_secs_f64()
+        );
+        match &case.outcome {
+            TestOutcome::Pass => {
+                let _ = writeln!(out, "/>");
+            }
+            TestOutcome::Failure(message) => {
+                let _ = writeln!(out, ">");
+                let _ = writeln!(out, "      <failure message=\"{}\"/>", escape_xml(message));
+                let _ = writeln!(out, "    </testcase>");
+            }
+            TestOutcome::Error(message) => {
+                let _ = writeln!(out, ">");
+                let _ = writeln!(out, "      <error message=\"{}\"/>", escape_xml(message));
+                let _ = writeln!(out, "    </testcase>");
+            }
+        }
+    }
+    let _ = writeln!(out, "  </testsuite>");
+    let _ = writeln!(out, "</testsuites>");
+    out
+}
+
+// Render a JSON report:
+// `{"tests":N,"passed":P,"failures":F,"errors":E,"cases":[{"file":..,"status":..,"message":..,"time":..}]}`
+// where `status` is one of "pass", "failure" or "error".
+pub fn to_json(cases: &[TestCase]) -> String {
+    let (passed, failures, errors) = summary(cases);
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "{{\"tests\":{},\"passed\":{},\"failures\":{},\"errors\":{},\"cases\":[",
+        cases.len(),
+        passed,
+        failures,
+        errors
+    );
+    for (i, case) in cases.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let (status, message) = match &case.outcome {
+            TestOutcome::Pass => ("pass", None),
+            TestOutcome::Failure(m) => ("failure", Some(m)),
+            TestOutcome::Error(m) => ("error", Some(m)),
+        };
+        let message = message
+            .map(|m| format!("\"{}\"", escape_json(m)))
+            .unwrap_or_else(|| "null".to_string());
+        let _ = write!(
+            out,
+            "{{\"file\":\"{}\",\"status\":\"{}\",\"message\":{},\"time\":{:.3}}}",
+            escape_json(&case.file),
+            status,
+            message,
+            case.duration.as_secs_f64()
+        );
+    }
+    out.push_str("]}\n");
+    out
+}
+
+// Write a report of `cases` in `format` to `path`, or to stdout when the path
+// is "-", gzip-compressed with `gzip`.
+pub fn write_report(
+    path: &Path,
+    format: ReportFormat,
+    cases: &[TestCase],
+    gzip: bool,
+) -> Result<()> {
+    let rendered = match format {
+        ReportFormat::Junit => to_junit(cases),
+        ReportFormat::Json => to_json(cases),
+    };
+    write_output(path, rendered.as_bytes(), gzip, "report")
+}
@@ -0,0 +1,426 @@
+// chipstream/crates/soundlog-debugger/src/cui/score.rs
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use soundlog::VgmDocument;
+use soundlog::chip::Chip;
+use soundlog::meta::Gd3Language;
+use soundlog::vgm::analysis::{ChannelTimeline, Note, run_analyses};
+use soundlog::vgm::command::{Instance, VgmCommand};
+use soundlog::vgm::detail::parse_data_block;
+use soundlog::vgm::usage::{ChannelUsageAnalysis, ChannelUsageReport};
+
+use crate::cui::vgm::{format_data_block_type, write_output};
+
+const SAMPLE_RATE: f64 = 44_100.0;
+
+// Size of the channel activity chart, in pixels.
+const CHART_WIDTH: f64 = 800.0;
+const CHART_LABEL_WIDTH: f64 = 160.0;
+const CHART_ROW_HEIGHT: f64 = 14.0;
+const CHART_AXIS_HEIGHT: f64 = 16.0;
+
+// One part of the report, rendered as Markdown or HTML.
+enum Section {
+    Table {
+        title: &'static str,
+        header: Vec<&'static str>,
+        rows: Vec<Vec<String>>,
+    },
+    Chart {
+        title: &'static str,
+        svg: String,
+    },
+}
+
+// Write a shareable summary of a VGM file: GD3 tags, chips, duration, a
+// channel activity chart (SVG), the voices keyed on and the data blocks.
+// The channel usage and the notes come from one `run_analyses` pass.
+//
+// The report is Markdown with the chart inlined as raw HTML, or a standalone
+// HTML page with `html`. It is written to `output_path`, or to stdout when
+// the path is "-", gzip-compressed with `gzip`.
+pub fn report_vgm(
+    input_path: &Path,
+    output_path: &Path,
+    data: Vec<u8>,
+    html: bool,
+    gzip: bool,
+) -> Result<()> {
+    let doc: VgmDocument = (&data[..])
+        .try_into()
+        .with_context(|| format!("failed to parse input VGM: {}", input_path.display()))?;
+
+    let mut usage = ChannelUsageAnalysis::new();
+    let mut timeline = ChannelTimeline::new();
+    run_analyses(&doc, &mut [&mut usage, &mut timeline]);
+    let usage = usage.into_report();
+    let notes = timeline.into_notes();
+
+    let title = doc
+        .gd3
+        .as_ref()
+        .and_then(|gd3| gd3.title(Gd3Language::English))
+        .unwrap_or_else(|| {
+            input_path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| input_path.display().to_string())
+        });
+    let sections = vec![
+        tags_section(&doc),
+        chips_section(&doc, &usage),
+        Section::Chart {
+            title: "Channel activity",
+            svg: activity_chart(&usage, &notes),
+        },
+        channels_section(&usage),
+        voices_section(&usage),
+        data_blocks_section(&doc),
+    ];
+    let rendered = if html {
+        render_html(&title, &sections)
+    } else {
+        render_markdown(&title, &sections)
+    };
+
+    write_output(output_path, rendered.as_bytes(), gzip, "report")
+}
+
+// `samples` at 44.1 kHz as `m:ss.mmm`.
+fn format_time(samples: u64) -> String {
+    let millis = (samples as f64 * 1000.0 / SAMPLE_RATE).round() as u64;
+    format!(
+        "{}:{:02}.{:03}",
+        millis / 60_000,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+fn tags_section(doc: &VgmDocument) -> Section {
+    let header = &doc.header;
+    let mut rows = Vec::new();
+    if let Some(gd3) = &doc.gd3 {
+        let language = Gd3Language::English;
+        let fields = [
+            ("Title", gd3.title(language)),
+            ("Game", gd3.game(language)),
+            ("System", gd3.system(language)),
+            ("Author", gd3.author(language)),
+            ("Release date", gd3.release_date.clone()),
+            ("Ripped by", gd3.creator.clone()),
+            ("Notes", gd3.notes.clone()),
+        ];
+        for (field, value) in fields {
+            if let Some(value) = value.filter(|value| !value.is_empty()) {
+                rows.push(vec![field.to_string(), value]);
+            }
+        }
+    }
+    rows.push(vec![
+        "Duration".to_string(),
+        format_time(doc.sample_length()),
+    ]);
+    if let (Some(start), Some(length)) = (doc.loop_start_sample(), doc.loop_sample_length()) {
+        rows.push(vec![
+            "Loop".to_string(),
+            format!("{} from {}", format_time(length), format_time(start)),
+        ]);
+    }
+    rows.push(vec![
+        "VGM version".to_string(),
+        format!("{:X}.{:02X}", header.version >> 8, header.version & 0xFF),
+    ]);
+    Section::Table {
+        title: "Song",
+        header: vec!["Field", "Value"],
+        rows,
+    }
+}
+
+fn chips_section(doc: &VgmDocument, usage: &ChannelUsageReport) -> Section {
+    let rows = doc
+        .header
+        .chip_instances()
+        .into_iter()
+        .map(|(instance, chip, clock)| {
+            let channels = usage
+                .channels
+                .iter()
+                .filter(|c| c.chip == chip && c.instance == instance)
+                .count();
+            vec![
+                format!("{:?}", chip),
+                format!("{:?}", instance),
+                format!("{:.0} Hz", clock),
+                channels.to_string(),
+            ]
+        })
+        .collect();
+    Section::Table {
+        title: "Chips",
+        header: vec!["Chip", "Instance", "Clock", "Channels used"],
+        rows,
+    }
+}
+
+fn channels_section(usage: &ChannelUsageReport) -> Section {
+    let rows = usage
+        .channels
+        .iter()
+        .map(|channel| {
+            let pitch = match channel.pitch_range {
+                Some((low, high)) => format!("{:.1}-{:.1} Hz", low, high),
+                None => "-".to_string(),
+            };
+            let voices: Vec<String> = channel
+                .voices
+                .keys()
+                .map(|voice| format!("#{}", voice))
+                .collect();
+            vec![
+                channel_label(&channel.chip, channel.instance, channel.channel),
+                channel.notes.to_string(),
+                pitch,
+                format!("{:.1}%", channel.active_percent(usage.samples)),
+                voices.join(" "),
+            ]
+        })
+        .collect();
+    Section::Table {
+        title: "Channels",
+        header: vec!["Channel", "Notes", "Pitch range", "Active", "Voices"],
+        rows,
+    }
+}
+
+fn voices_section(usage: &ChannelUsageReport) -> Section {
+    let rows = usage
+        .voices
+        .iter()
+        .enumerate()
+        .map(|(index, voice)| {
+            let key_ons: usize = usage
+                .channels
+                .iter()
+                .filter_map(|channel| channel.voices.get(&index))
+                .sum();
+            let registers: Vec<String> = voice
+                .registers
+                .iter()
+                .map(|value| format!("{:02X}", value))
+                .collect();
+            vec![
+                format!("#{}", index),
+                format!("{:?} ({:?})", voice.chip, voice.instance),
+                key_ons.to_string(),
+                registers.join(" "),
+            ]
+        })
+        .collect();
+    Section::Table {
+        title: "Instruments",
+        header: vec!["Voice", "Chip", "Key-ons", "Registers"],
+        rows,
+    }
+}
+
+fn data_blocks_section(doc: &VgmDocument) -> Section {
+    // (blocks, bytes) per block type, in type order
+    let mut blocks: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+    for command in doc.iter() {
+        if let VgmCommand::DataBlock(block) = command {
+            let size = block.data.len();
+            let name = match parse_data_block(*block.clone()) {
+                Ok(data_type) => format_data_block_type(&data_type),
+                Err((block, _)) => format!("Unknown(0x{:02X})", block.data_type),
+            };
+            let entry = blocks.entry(name).or_default();
+            entry.0 += 1;
+            entry.1 += size;
+        }
+    }
+    let rows = blocks
+        .into_iter()
+        .map(|(name, (count, bytes))| vec![name, count.to_string(), bytes.to_string()])
+        .collect();
+    Section::Table {
+        title: "Data blocks",
+        header: vec!["Type", "Blocks", "Bytes"],
+        rows,
+    }
+}
+
+fn channel_label(chip: &Chip, instance: Instance, channel: u8) -> String {
+    format!("{:?} ({:?}) ch {}", chip, instance, channel)
+}
+
+// One row per channel of `usage` with a bar for every note and a tick every
+// 10% of the song.
+fn activity_chart(usage: &ChannelUsageReport, notes: &[Note]) -> String {
+    let length = usage.samples.max(1) as f64;
+    let plot_width = CHART_WIDTH - CHART_LABEL_WIDTH;
+    let height = CHART_AXIS_HEIGHT + CHART_ROW_HEIGHT * usage.channels.len() as f64;
+    let x = |time: u64| CHART_LABEL_WIDTH + plot_width * time as f64 / length;
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="monospace" font-size="10">"#,
+        w = CHART_WIDTH,
+        h = height
+    );
+    for tick in 0..=10 {
+        let time = usage.samples * tick / 10;
+        let anchor = if tick == 10 { "end" } else { "middle" };
+        let _ = writeln!(
+            svg,
+            r##"<line x1="{x:.1}" y1="{top}" x2="{x:.1}" y2="{h}" stroke="#ddd"/><text x="{x:.1}" y="10" text-anchor="{anchor}">{label:.1}s</text>"##,
+            x = x(time),
+            top = CHART_AXIS_HEIGHT - 4.0,
+            h = height,
+            label = time as f64 / SAMPLE_RATE,
+        );
+    }
+    for (row, channel) in usage.channels.iter().enumerate() {
+        let y = CHART_AXIS_HEIGHT + CHART_ROW_HEIGHT * row as f64;
+        let _ = writeln!(
+            svg,
+            r#"<text x="0" y="{:.1}">{}</text>"#,
+            y + CHART_ROW_HEIGHT - 4.0,
+            escape_html(&channel_label(
+                &channel.chip,
+                channel.instance,
+                channel.channel
+            ))
+        );
+        let channel_notes = notes.iter().filter(|note| {
+            note.chip == channel.chip
+                && note.instance == channel.instance
+                && note.channel == channel.channel
+        });
+        for note in channel_notes {
+            let start = x(note.start);
+            let end = x(note.end.unwrap_or(usage.samples));
+            let _ = writeln!(
+                svg,
+                r##"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="#4a7ebb"/>"##,
+                start,
+                y + 2.0,
+                (end - start).max(1.0),
+                CHART_ROW_HEIGHT - 4.0
+            );
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// Table cells are single lines: `|` is escaped and line breaks become `<br>`.
+fn escape_markdown_cell(text: &str) -> String {
+    escape_html(text)
+        .replace('|', "\\|")
+        .replace("\r\n", "<br>")
+        .replace('\n', "<br>")
+}
+
+fn render_markdown(title: &str, sections: &[Section]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# {}", escape_html(title));
+    for section in sections {
+        match section {
+            Section::Table {
+                title,
+                header,
+                rows,
+            } => {
+                let _ = writeln!(out, "\n## {}\n", title);
+                if rows.is_empty() {
+                    out.push_str("(none)\n");
+                    continue;
+                }
+                let _ = writeln!(out, "| {} |", header.join(" | "));
+                let _ = writeln!(out, "|{}", "---|".repeat(header.len()));
+                for row in rows {
+                    let cells: Vec<String> =
+                        row.iter().map(|cell| escape_markdown_cell(cell)).collect();
+                    let _ = writeln!(out, "| {} |", cells.join(" | "));
+                }
+            }
+            Section::Chart { title, svg } => {
+                let _ = writeln!(out, "\n## {}\n", title);
+                out.push_str(svg);
+            }
+        }
+    }
+    out
+}
+
+fn render_html(title: &str, sections: &[Section]) -> String {
+    let title = escape_html(title);
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>",
+        title
+    );
+    out.push_str(
+        "<style>\nbody { font-family: sans-serif; margin: 2em; }\n\
+         table { border-collapse: collapse; margin-bottom: 1em; }\n\
+         th, td { border: 1px solid #ccc; padding: 2px 8px; text-align: left; }\n\
+         </style>\n</head>\n<body>\n",
+    );
+    let _ = writeln!(out, "<h1>{}</h1>", title);
+    for section in sections {
+        match section {
+            Section::Table {
+                title,
+                header,
+                rows,
+            } => {
+                let _ = writeln!(out, "<h2>{}</h2>", title);
+                if rows.is_empty() {
+                    out.push_str("<p>(none)</p>\n");
+                    continue;
+                }
+                out.push_str("<table>\n<tr>");
+                for cell in header {
+                    let _ = write!(out, "<th>{}</th>", cell);
+                }
+                out.push_str("</tr>\n");
+                for row in rows {
+                    out.push_str("<tr>");
+                    for cell in row {
+                        let _ = write!(out, "<td>{}</td>", escape_html(cell));
+                    }
+                    out.push_str("</tr>\n");
+                }
+                out.push_str("</table>\n");
+            }
+            Section::Chart { title, svg } => {
+                let _ = writeln!(out, "<h2>{}</h2>", title);
+                out.push_str(svg);
+            }
+        }
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
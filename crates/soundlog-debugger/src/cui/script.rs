@@ -0,0 +1,34 @@
+// chipstream/crates/soundlog-debugger/src/cui/script.rs
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use soundlog::VgmDocument;
+use soundlog::vgm::script::{from_script, to_script};
+
+use crate::cui::vgm::{write_output, write_vgm};
+
+// Write a VGM file as a command script (see `soundlog::vgm::script`), or with
+// `import` build a VGM file from a script. The output goes to `output_path`,
+// or to stdout when the path is "-", gzip-compressed with `gzip` (and for an
+// imported VGM also with a `.vgz`/`.gz` path).
+pub fn script_vgm(
+    input_path: &Path,
+    output_path: &Path,
+    data: Vec<u8>,
+    import: bool,
+    gzip: bool,
+) -> Result<()> {
+    if import {
+        let text = std::str::from_utf8(&data)
+            .with_context(|| format!("script is not UTF-8: {}", input_path.display()))?;
+        let doc = from_script(text)
+            .with_context(|| format!("failed to read script: {}", input_path.display()))?;
+        write_vgm(output_path, &Vec::<u8>::from(&doc), gzip)
+    } else {
+        let doc: VgmDocument = (&data[..])
+            .try_into()
+            .with_context(|| format!("failed to parse input VGM: {}", input_path.display()))?;
+        write_output(output_path, to_script(&doc).as_bytes(), gzip, "script")
+    }
+}
@@ -0,0 +1,118 @@
+// chipstream/crates/soundlog-debugger/src/cui/state.rs
+use std::fmt::Write;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+
+use soundlog::VgmDocument;
+use soundlog::vgm::snapshot::{command_at_sample, snapshot_at_command};
+
+/// Point of the song to take the chip state at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatePosition {
+    /// Time in seconds; writes made at that sample are included.
+    Seconds(f64),
+    /// Command index; the state right after that command ran.
+    Command(usize),
+}
+
+// Parse a `[[h:]m:]s[.fff]` time such as `1:23` or `83.5` into seconds.
+pub fn parse_time(arg: &str) -> Result<f64, String> {
+    let mut seconds = 0.0;
+    for part in arg.split(':') {
+        let value: f64 = part
+            .parse()
+            .map_err(|_| format!("invalid time '{}', expected [[h:]m:]s[.fff]", arg))?;
+        if value < 0.0 {
+            return Err(format!("invalid time '{}': negative", arg));
+        }
+        seconds = seconds * 60.0 + value;
+    }
+    Ok(seconds)
+}
+
+// Print the decoded register map of the chips at `position`, replaying the
+// chip writes up to there. `chips` limits the output to chips with these
+// names (case-insensitive, e.g. `ym2612`); every written chip is printed when
+// it is empty. With `json` the maps are printed as one JSON object.
+pub fn state_vgm(
+    input_path: &Path,
+    data: Vec<u8>,
+    position: StatePosition,
+    chips: &[String],
+    json: bool,
+) -> Result<()> {
+    let doc: VgmDocument = (&data[..])
+        .try_into()
+        .with_context(|| format!("failed to parse input VGM: {}", input_path.display()))?;
+
+    let sample_rate = doc.header.effective_sample_rate() as f64;
+    let index = match position {
+        StatePosition::Seconds(seconds) => {
+            command_at_sample(&doc, (seconds * sample_rate).round() as u64)
+        }
+        StatePosition::Command(command) => {
+            if command >= doc.commands.len() {
+                bail!(
+                    "command {} is out of range ({} commands)",
+                    command,
+                    doc.commands.len()
+                );
+            }
+            command + 1
+        }
+    };
+    let sample: u64 = doc.commands[..index]
+        .iter()
+        .map(|command| command.wait_samples() as u64)
+        .sum();
+
+    let snapshots: Vec<_> = snapshot_at_command(&doc, index)
+        .into_iter()
+        .filter(|snapshot| {
+            chips.is_empty()
+                || chips
+                    .iter()
+                    .any(|name| format!("{:?}", snapshot.chip).eq_ignore_ascii_case(name))
+        })
+        .collect();
+
+    if json {
+        let mut out = String::new();
+        let _ = write!(
+            out,
+            "{{\"sample\":{},\"commands\":{},\"chips\":[",
+            sample, index
+        );
+        for (i, snapshot) in snapshots.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(
+                out,
+                "{{\"instance\":{},\"map\":{}}}",
+                usize::from(snapshot.instance),
+                snapshot.register_map().to_json()
+            );
+        }
+        out.push_str("]}");
+        println!("{}", out);
+        return Ok(());
+    }
+
+    println!(
+        "State at sample {} ({:.3}s), after {} commands",
+        sample,
+        sample as f64 / sample_rate,
+        index
+    );
+    if snapshots.is_empty() {
+        println!("No chip was written yet.");
+    }
+    for snapshot in &snapshots {
+        println!();
+        println!("{:?}:", snapshot.instance);
+        print!("{:#}", snapshot.register_map());
+    }
+    Ok(())
+}
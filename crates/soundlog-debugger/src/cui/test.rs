@@ -5,6 +5,10 @@ use std::path::Path;
 use anyhow::Result;
 
 use soundlog::VgmDocument;
+use soundlog::vgm::diff::diff_documents;
+use soundlog::vgm::verify::verify_deterministic;
+
+use crate::cui::report::TestOutcome;
 
 /// Test command: parse, serialize, re-parse roundtrip test and compare binary bytes.
 /// Prints detailed diagnostics including a compact field-by-field comparison.
@@ -12,7 +16,23 @@ use soundlog::VgmDocument;
 /// The comparison is semantic: a roundtrip is considered successful if either the
 /// serialized bytes match exactly, or the parsed documents match except for
 /// placement-only differences (GD3/data offset).
-pub fn test_roundtrip(path: &Path, data: Vec<u8>, dry_run: bool) -> Result<()> {
+///
+/// With `semantic` set the byte comparison is skipped and the documents are
+/// compared with `soundlog::vgm::diff::diff_documents`; benign encoding
+/// differences are reported separately from real data loss.
+///
+/// With `deterministic` set a passing file is also checked with
+/// `soundlog::vgm::verify::verify_deterministic`: serializing it must give
+/// the same bytes every time, and those bytes must rebuild to themselves.
+///
+/// The returned `TestOutcome` is used for machine-readable reports.
+pub fn test_roundtrip(
+    path: &Path,
+    data: Vec<u8>,
+    dry_run: bool,
+    semantic: bool,
+    deterministic: bool,
+) -> Result<TestOutcome> {
     // Prepare quoted full-path string for one-line outputs. Try to canonicalize to get absolute path,
     // but fall back to the provided path if canonicalize fails.
     let file_str = match path.canonicalize() {
@@ -25,8 +45,8 @@ pub fn test_roundtrip(path: &Path, data: Vec<u8>, dry_run: bool) -> Result<()> {
     let doc_orig = match doc_orig_res {
         Ok(d) => d,
         Err(e) => {
-            eprintln!("\"{}\": parse error: {}", file_str, e);
-            return Ok(());
+            log::error!("\"{}\": parse error: {}", file_str, e);
+            return Ok(TestOutcome::Error(format!("parse error: {}", e)));
         }
     };
 
@@ -34,7 +54,10 @@ pub fn test_roundtrip(path: &Path, data: Vec<u8>, dry_run: bool) -> Result<()> {
     let rebuilt: Vec<u8> = (&doc_orig).into();
     let doc_reparsed_res: Result<VgmDocument, _> = (&rebuilt[..]).try_into();
 
-    match doc_reparsed_res {
+    let outcome = match doc_reparsed_res {
+        Ok(doc_reparsed) if semantic => {
+            report_semantic(&file_str, &doc_orig, &doc_reparsed, dry_run)
+        }
         Ok(doc_reparsed) => {
             // Use the diagnostic helpers from the parent `vgm` module.
             let semantic_match =
@@ -50,31 +73,127 @@ pub fn test_roundtrip(path: &Path, data: Vec<u8>, dry_run: bool) -> Result<()> {
                         );
                     }
                 }
+                TestOutcome::Pass
             } else {
-                // One-line error with filename as requested. Exit code remains zero.
+                // One-line error with filename as requested; `test` exits with
+                // `cui::exit::VALIDATION_FAILED` for it.
                 // Inform user how to see detailed diagnostics: re-run without --dry-run.
-                println!(
-                    "\"{}\": roundtrip: MISMATCH (original {} bytes, serialized {} bytes) — re-run without --dry-run to see detailed diagnostics",
-                    file_str,
-                    data.len(),
-                    rebuilt.len()
+                result_line(
+                    dry_run,
+                    format!(
+                        "\"{}\": roundtrip: MISMATCH (original {} bytes, serialized {} bytes) — re-run without --dry-run to see detailed diagnostics",
+                        file_str,
+                        data.len(),
+                        rebuilt.len()
+                    ),
                 );
                 if !dry_run {
                     crate::cui::vgm::print_diag_compact(&doc_orig, &doc_reparsed, &data, &rebuilt);
                 }
+                TestOutcome::Failure(format!(
+                    "roundtrip mismatch (original {} bytes, serialized {} bytes)",
+                    data.len(),
+                    rebuilt.len()
+                ))
             }
         }
         Err(e) => {
             // One-line error with filename; re-parse failed after serialization.
             // Advise re-running without --dry-run to see serialized bytes/diagnostics.
-            eprintln!(
+            log::error!(
                 "\"{}\": roundtrip: serialization produced bytes (len={}), but re-parse failed: {} — re-run without --dry-run to see serialized bytes and diagnostics",
                 file_str,
                 rebuilt.len(),
                 e
             );
+            TestOutcome::Error(format!("re-parse failed: {}", e))
         }
+    };
+
+    if deterministic && outcome == TestOutcome::Pass {
+        return Ok(check_deterministic(&file_str, &doc_orig, dry_run));
     }
+    Ok(outcome)
+}
 
-    Ok(())
+/// Print the one-line result of a failed check: to stdout, or to stderr with
+/// `dry_run`, which keeps stdout free (e.g. for a report written to `-`).
+fn result_line(dry_run: bool, line: String) {
+    if dry_run {
+        log::error!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+/// Check that rebuilding the parsed document is reproducible byte for byte.
+fn check_deterministic(file_str: &str, doc: &VgmDocument, dry_run: bool) -> TestOutcome {
+    match verify_deterministic(doc) {
+        Ok(bytes) => {
+            if !dry_run {
+                println!(
+                    " deterministic: rebuild is reproducible ({} bytes)",
+                    bytes.len()
+                );
+            }
+            TestOutcome::Pass
+        }
+        Err(e) => {
+            result_line(
+                dry_run,
+                format!("\"{}\": deterministic: FAILED: {}", file_str, e),
+            );
+            TestOutcome::Failure(format!("not deterministic: {}", e))
+        }
+    }
+}
+
+/// Maximum number of losses listed for a semantic mismatch.
+const MAX_LISTED_LOSSES: usize = 10;
+
+/// Print the result of a semantic (structural) comparison.
+fn report_semantic(
+    file_str: &str,
+    left: &VgmDocument,
+    right: &VgmDocument,
+    dry_run: bool,
+) -> TestOutcome {
+    let diff = diff_documents(left, right);
+    let benign = diff.benign().count();
+
+    if !diff.has_loss() {
+        if !dry_run {
+            println!(
+                " roundtrip: semantically equivalent ({} benign difference(s))",
+                benign
+            );
+            for d in diff.benign() {
+                println!("  benign: {}", d.message);
+            }
+        }
+        return TestOutcome::Pass;
+    }
+
+    let losses: Vec<_> = diff.losses().collect();
+    result_line(
+        dry_run,
+        format!(
+            "\"{}\": roundtrip: MISMATCH ({} loss(es), {} benign difference(s)) — re-run without --dry-run to see detailed diagnostics",
+            file_str,
+            losses.len(),
+            benign
+        ),
+    );
+    if !dry_run {
+        for d in losses.iter().take(MAX_LISTED_LOSSES) {
+            println!("  loss: {}", d.message);
+        }
+        if losses.len() > MAX_LISTED_LOSSES {
+            println!("  ... {} more", losses.len() - MAX_LISTED_LOSSES);
+        }
+        for d in diff.benign() {
+            println!("  benign: {}", d.message);
+        }
+    }
+    TestOutcome::Failure(format!("{} loss(es): {}", losses.len(), losses[0].message))
 }
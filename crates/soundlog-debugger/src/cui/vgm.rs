@@ -1,8 +1,13 @@
 use std::convert::TryInto;
+use std::fs;
+use std::io::{Read, Write};
 use std::path::Path;
 use std::sync::Arc;
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
 
 use soundlog::VgmDocument;
 use soundlog::vgm::detail::{DataBlockType, parse_data_block};
@@ -27,7 +32,7 @@ fn pad_to_width(s: &str, width: usize) -> String {
 /// Produce a stable set of key/value summary fields for a `VgmDocument`.
 /// This is used by the test command to compare documents field-by-field
 /// rather than requiring byte-for-byte equality.
-fn summarize_doc(doc: &VgmDocument) -> Vec<(String, String)> {
+pub(crate) fn summarize_doc(doc: &VgmDocument) -> Vec<(String, String)> {
     let header = &doc.header;
 
     // chips
@@ -85,22 +90,32 @@ fn summarize_doc(doc: &VgmDocument) -> Vec<(String, String)> {
     let c140_chip_type = format!("{:?}", header.c140_chip_type);
     let total_samples = format!("{}", header.total_samples);
 
-    // waits total
-    let total_wait_samples: u64 = doc
-        .commands
-        .iter()
-        .map(|c| match c {
-            soundlog::vgm::command::VgmCommand::WaitSamples(ws) => ws.0 as u64,
-            soundlog::vgm::command::VgmCommand::WaitNSample(n) => n.0 as u64 + 1,
-            soundlog::vgm::command::VgmCommand::Wait735Samples(_) => 735u64,
-            soundlog::vgm::command::VgmCommand::Wait882Samples(_) => 882u64,
-            soundlog::vgm::command::VgmCommand::YM2612Port0Address2AWriteAndWaitN(n) => n.0 as u64,
-            _ => 0u64,
-        })
-        .sum();
-    const BASE_SR: f32 = 44100.0f32;
-    let wait_seconds = (total_wait_samples as f32) / BASE_SR;
-    let waits_total = format!("{} ({:.3} s @ 44100Hz)", total_wait_samples, wait_seconds);
+    // waits total and encoding mix
+    let wait_stats = doc.wait_stats();
+    let total_wait_samples = wait_stats.total_samples();
+    let sample_rate = header.effective_sample_rate();
+    let wait_seconds = (total_wait_samples as f32) / sample_rate as f32;
+    let waits_total = format!(
+        "{} ({:.3} s @ {}Hz)",
+        total_wait_samples, wait_seconds, sample_rate
+    );
+
+    let wait_encoding = [
+        ("0x61", wait_stats.wait_samples),
+        ("0x62", wait_stats.wait_735),
+        ("0x63", wait_stats.wait_882),
+        ("0x7n", wait_stats.wait_n),
+        ("0x8n", wait_stats.ym2612_write_and_wait),
+    ]
+    .iter()
+    .filter(|(_, count)| count.commands > 0)
+    .map(|(opcode, count)| format!("{}={} ({} smp)", opcode, count.commands, count.samples))
+    .collect::<Vec<_>>();
+    let wait_encoding = if wait_encoding.is_empty() {
+        "(none)".to_string()
+    } else {
+        wait_encoding.join(" ")
+    };
 
     // data blocks
     let (db_count, db_total_bytes) =
@@ -170,21 +185,11 @@ fn summarize_doc(doc: &VgmDocument) -> Vec<(String, String)> {
     }
 
     // commands info: count and rough distribution
-    let mut cmd_counts: std::collections::HashMap<&'static str, usize> =
-        std::collections::HashMap::new();
-    for c in &doc.commands {
-        let key: &'static str = match c {
-            soundlog::vgm::command::VgmCommand::WaitSamples(_) => "wait",
-            soundlog::vgm::command::VgmCommand::DataBlock(_) => "data_block",
-            _ => "other",
-        };
-        *cmd_counts.entry(key).or_default() += 1;
-    }
     let commands_info = format!(
         "count={} waits={} data_blocks={}",
         doc.commands.len(),
-        *cmd_counts.get("wait").unwrap_or(&0usize),
-        *cmd_counts.get("data_block").unwrap_or(&0usize)
+        wait_stats.wait_commands(),
+        db_count
     );
 
     // Assemble rows in a stable order
@@ -203,6 +208,7 @@ fn summarize_doc(doc: &VgmDocument) -> Vec<(String, String)> {
         ("volume_modifier".into(), volume_modifier),
         ("total_samples".into(), total_samples),
         ("waits_total (calc)".into(), waits_total),
+        ("wait_encoding".into(), wait_encoding),
         ("data_blocks".into(), data_blocks),
         ("data_block_types".into(), data_block_types),
     ];
@@ -516,6 +522,105 @@ fn extra_headers_semantically_equal(
     }
 }
 
+/// `true` when `path` ends in `.vgz` or `.gz`.
+pub(crate) fn has_gzip_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|s| s.to_str())
+        .is_some_and(|s| s.eq_ignore_ascii_case("vgz") || s.eq_ignore_ascii_case("gz"))
+}
+
+/// Read the file at `input_path`, or stdin when the path is `-`, and
+/// decompress it like `input_bytes`.
+pub fn read_input(input_path: &Path) -> Result<Vec<u8>> {
+    let data = if input_path == Path::new("-") {
+        let mut data = Vec::new();
+        std::io::stdin()
+            .lock()
+            .read_to_end(&mut data)
+            .context("failed to read stdin")?;
+        data
+    } else {
+        fs::read(input_path)
+            .with_context(|| format!("failed to read file: {}", input_path.display()))?
+    };
+    log::debug!("read {} byte(s) from {}", data.len(), input_path.display());
+    input_bytes(input_path, data)
+}
+
+/// Write `bytes` to `output_path`, or to stdout when the path is `-`,
+/// gzip-compressed when `gzip` is set. `what` names the output in errors.
+pub fn write_output(output_path: &Path, bytes: &[u8], gzip: bool, what: &str) -> Result<()> {
+    let compressed;
+    let bytes = if gzip {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(bytes)
+            .with_context(|| format!("failed to compress {}", what))?;
+        compressed = encoder
+            .finish()
+            .with_context(|| format!("failed to compress {}", what))?;
+        &compressed[..]
+    } else {
+        bytes
+    };
+    log::debug!(
+        "writing {} {} byte(s) to {}",
+        what,
+        bytes.len(),
+        output_path.display()
+    );
+    if output_path == Path::new("-") {
+        std::io::stdout()
+            .write_all(bytes)
+            .with_context(|| format!("failed to write {} to stdout", what))
+    } else {
+        fs::write(output_path, bytes)
+            .with_context(|| format!("failed to write {}: {}", what, output_path.display()))
+    }
+}
+
+/// Write serialized VGM `bytes` like `write_output`, gzip-compressed when
+/// `gzip` is set or the path ends in `.vgz` or `.gz`.
+pub fn write_vgm(output_path: &Path, bytes: &[u8], gzip: bool) -> Result<()> {
+    write_output(
+        output_path,
+        bytes,
+        gzip || has_gzip_extension(output_path),
+        "VGM",
+    )
+}
+
+/// Contents of a VGM file read from `input_path`: gunzipped when the path ends
+/// in `.vgz` or `.gz` or `data` starts with the gzip magic (`1f 8b`), `data`
+/// as it is otherwise.
+pub fn input_bytes(input_path: &Path, data: Vec<u8>) -> Result<Vec<u8>> {
+    input_bytes_with_limit(input_path, data, u64::MAX)
+}
+
+/// `input_bytes` for untrusted input: fails when the gunzipped contents grow
+/// past `max_size` bytes instead of decompressing all of them.
+pub fn input_bytes_with_limit(input_path: &Path, data: Vec<u8>, max_size: u64) -> Result<Vec<u8>> {
+    let is_gzip = has_gzip_extension(input_path) || data.starts_with(&[0x1f, 0x8b]);
+    if is_gzip {
+        let mut out = Vec::new();
+        GzDecoder::new(&data[..])
+            .take(max_size.saturating_add(1))
+            .read_to_end(&mut out)
+            .with_context(|| format!("gzip decompression failed: {}", input_path.display()))?;
+        if out.len() as u64 > max_size {
+            bail!(
+                "gunzipped {} exceeds {} bytes",
+                input_path.display(),
+                max_size
+            );
+        }
+        log::debug!("gunzipped {} byte(s) to {}", data.len(), out.len());
+        Ok(out)
+    } else {
+        Ok(data)
+    }
+}
+
 pub use crate::cui::test::test_roundtrip;
 
 pub use crate::cui::redump::redump_vgm;
@@ -532,6 +637,9 @@ pub fn parse_vgm(file_path: &Path, data: Vec<u8>, logger: Arc<Logger>) -> Result
     // Get command offsets and lengths
     let offsets_and_lengths = doc.command_offsets_and_lengths();
 
+    // Labels from the annotation sidecar, if one exists next to the file
+    let annotations = load_annotations(file_path)?;
+
     // Print commands with offsets and lengths
     let _ = logger.info(format_args!(
         "{:<12} {:<8} {:<8} {:<8} {:}",
@@ -546,16 +654,7 @@ pub fn parse_vgm(file_path: &Path, data: Vec<u8>, logger: Arc<Logger>) -> Result
         .enumerate()
     {
         // Accumulate samples from Wait-family commands.
-        // WaitNSample stores raw n (0..=15); actual wait is n+1 samples.
-        // YM2612Port0Address2AWriteAndWaitN stores n directly (no +1).
-        let delta: u64 = match cmd {
-            soundlog::VgmCommand::WaitSamples(s) => s.0 as u64,
-            soundlog::VgmCommand::Wait735Samples(_) => 735,
-            soundlog::VgmCommand::Wait882Samples(_) => 882,
-            soundlog::VgmCommand::WaitNSample(s) => s.0 as u64 + 1,
-            soundlog::VgmCommand::YM2612Port0Address2AWriteAndWaitN(s) => s.0 as u64,
-            _ => 0,
-        };
+        let delta = cmd.wait_samples() as u64;
         let samples_at_issue = total_samples;
         total_samples += delta;
 
@@ -567,11 +666,34 @@ pub fn parse_vgm(file_path: &Path, data: Vec<u8>, logger: Arc<Logger>) -> Result
             length,
             CommandBrief(cmd)
         ));
+        if let Some(annotation) = annotations.get(index) {
+            let _ = logger.info(format_args!(
+                "{:<12} {:<8} ; [{}] {}",
+                "",
+                "",
+                annotation.label,
+                annotation.comment.replace('\n', " ")
+            ));
+        }
     }
 
     Ok(())
 }
 
+/// Read the annotation sidecar of `file_path`, or an empty set when there is none.
+fn load_annotations(file_path: &Path) -> Result<soundlog::vgm::annotation::Annotations> {
+    use soundlog::vgm::annotation::{Annotations, sidecar_path};
+
+    let path = sidecar_path(file_path);
+    if !path.is_file() {
+        return Ok(Annotations::new());
+    }
+    let text = std::fs::read_to_string(&path)
+        .with_context(|| format!("failed to read annotations: {}", path.display()))?;
+    Annotations::from_sidecar(&text)
+        .with_context(|| format!("failed to parse annotations: {}", path.display()))
+}
+
 /// Lightweight Display wrapper that formats a `VgmCommand` on-demand without allocating.
 ///
 /// Use `CommandBrief(&cmd)` in `format_args!` to delay formatting until the logger
@@ -916,6 +1038,27 @@ impl<'a> std::fmt::Display for DataBlockTypeDisplay<'a> {
 /// Backwards-compatible helper: keep existing `format_data_block_type` returning a `String`.
 /// Internally it uses the `DataBlockTypeDisplay` wrapper so callers that still need a String
 /// will get one, but new call sites can use the display wrapper to avoid allocation.
-fn format_data_block_type(data_type: &DataBlockType) -> String {
+pub(crate) fn format_data_block_type(data_type: &DataBlockType) -> String {
     DataBlockTypeDisplay(data_type).to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gunzipped_input_is_capped() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[0u8; 4096]).unwrap();
+        let gzipped = encoder.finish().unwrap();
+        let path = Path::new("bomb.vgz");
+
+        assert_eq!(
+            input_bytes_with_limit(path, gzipped.clone(), 4096)
+                .unwrap()
+                .len(),
+            4096
+        );
+        assert!(input_bytes_with_limit(path, gzipped, 4095).is_err());
+    }
+}
@@ -0,0 +1,54 @@
+// chipstream/crates/soundlog-debugger/src/cui/watch.rs
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use notify::{EventKind, RecursiveMode, Watcher};
+
+// Writers often save a file in several steps (truncate, write, rename); the
+// events of one save are collapsed into a single re-run.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+// Run `run` once, then again every time one of `files` changes, until the
+// process is interrupted. The parent directories are watched rather than the
+// files, so a file replaced by rename (as most editors and converters save)
+// keeps being followed.
+pub fn watch_files(files: &[PathBuf], mut run: impl FnMut()) -> Result<()> {
+    let mut targets = Vec::with_capacity(files.len());
+    for file in files {
+        if file.as_os_str() == "-" {
+            bail!("--watch needs a file path, not stdin");
+        }
+        targets.push(
+            file.canonicalize()
+                .with_context(|| format!("failed to watch file: {}", file.display()))?,
+        );
+    }
+    let dirs: BTreeSet<&Path> = targets.iter().filter_map(|t| t.parent()).collect();
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).context("failed to start file watcher")?;
+    for dir in dirs {
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .with_context(|| format!("failed to watch directory: {}", dir.display()))?;
+    }
+
+    run();
+    loop {
+        let event = match rx.recv() {
+            Ok(event) => event.context("file watcher failed")?,
+            Err(_) => return Ok(()),
+        };
+        if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+            || !event.paths.iter().any(|path| targets.contains(path))
+        {
+            continue;
+        }
+        while rx.recv_timeout(DEBOUNCE).is_ok() {}
+        log::info!("\n--- file changed, running again ---");
+        run();
+    }
+}
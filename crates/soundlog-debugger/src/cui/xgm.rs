@@ -0,0 +1,44 @@
+// chipstream/crates/soundlog-debugger/src/cui/xgm.rs
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use soundlog::VgmDocument;
+use soundlog::vgm::export::xgm::{XgmOptions, to_xgm};
+
+use crate::cui::vgm::write_output;
+
+// Convert a VGM file into an XGM v1 file for the SGDK sound driver.
+//
+// The frame rate follows the VGM header rate unless `pal` forces 50 Hz. A
+// one-line summary (frames, samples, dropped commands) is printed to stderr
+// so it does not mix with the XGM bytes when writing to stdout. The XGM bytes
+// are gzip-compressed with `gzip`.
+pub fn export_xgm(
+    input_path: &Path,
+    output_path: &Path,
+    data: Vec<u8>,
+    pal: bool,
+    gzip: bool,
+) -> Result<()> {
+    let doc: VgmDocument = (&data[..])
+        .try_into()
+        .with_context(|| format!("failed to parse input VGM: {}", input_path.display()))?;
+
+    let mut options = XgmOptions::for_document(&doc);
+    options.pal |= pal;
+    let (bytes, report) = to_xgm(&doc, &options)
+        .with_context(|| format!("failed to convert to XGM: {}", input_path.display()))?;
+
+    write_output(output_path, &bytes, gzip, "XGM")?;
+
+    log::info!(
+        "\"{}\": xgm: frames={} samples={} pcm_plays={} dropped={}",
+        input_path.display(),
+        report.frames,
+        report.samples,
+        report.pcm_plays,
+        report.dropped_commands
+    );
+    Ok(())
+}
@@ -1,7 +1,15 @@
 mod app;
+mod download;
 mod hex;
+mod minimap;
+mod register_index;
+mod settings;
 mod state;
 
 pub use app::run_gui;
+pub use download::{Download, is_http_url};
 pub use hex::HexViewer;
+pub use minimap::{Minimap, MinimapMarkers};
+pub use register_index::RegisterIndex;
+pub use settings::{Settings, Theme};
 pub use state::{UiState, show_ui};
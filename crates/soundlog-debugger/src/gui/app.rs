@@ -9,7 +9,7 @@ use std::cell::RefCell;
 use eframe::egui;
 use eframe::{CreationContext, Frame, NativeOptions};
 
-use super::UiState;
+use super::{Settings, UiState};
 use soundlog::VgmBuilder;
 use soundlog::meta::Gd3;
 use soundlog::vgm::command::WaitSamples;
@@ -19,11 +19,11 @@ use soundlog::vgm::command::WaitSamples;
 /// This used to live in `main.rs`. It configures the native window options and
 /// starts the `eframe` event loop with `ui::Debuger` as the application.
 pub fn run_gui(initial_bytes: Vec<u8>) {
-    // Configure native options: fix horizontal width to 1024 and allow vertical resizing.
+    // Configure native options: start at 1024x800; the width follows the
+    // bytes-per-row setting, so both directions are resizable.
     let native_options = NativeOptions {
         initial_window_size: Some(egui::vec2(1024.0, 800.0)),
-        min_window_size: Some(egui::vec2(1024.0, 200.0)),
-        max_window_size: Some(egui::vec2(1024.0, 5000.0)),
+        min_window_size: Some(egui::vec2(640.0, 200.0)),
         ..NativeOptions::default()
     };
 
@@ -35,7 +35,7 @@ pub fn run_gui(initial_bytes: Vec<u8>) {
             Box::new(Debuger::new_with_bytes(cc, initial_bytes.clone()))
         }),
     ) {
-        eprintln!("failed to launch native window: {:?}", err);
+        log::error!("failed to launch native window: {:?}", err);
     }
 }
 
@@ -54,11 +54,15 @@ impl Debuger {
         let current = ctx.pixels_per_point();
         ctx.set_pixels_per_point(current * 1.2);
 
+        // Restore the layout settings saved on the previous run.
+        let settings = Settings::load(cc.storage);
+        settings.apply_theme(ctx);
+
         // Initialize UI state: if we have initial bytes, populate AST from them;
         // otherwise construct an empty VGM using `VgmBuilder` and parse that so
         // the UI displays a real (empty) VGM document instead of purely
         // synthetic placeholders.
-        let state = if initial_bytes.is_empty() {
+        let mut state = if initial_bytes.is_empty() {
             // Build an empty VGM document and serialize to bytes.
             let mut builder = VgmBuilder::new();
             // Add a single small wait command so a command bucket appears in the AST.
@@ -77,14 +81,15 @@ impl Debuger {
             let bytes: Vec<u8> = (&doc).into();
 
             let mut s = UiState::new_empty();
-            s.populate_from_bytes(&bytes);
+            s.populate_from_bytes(bytes.into());
             s
         } else {
             let mut s = UiState::new_empty();
-            s.populate_from_bytes(&initial_bytes);
+            s.populate_from_bytes(initial_bytes.into());
             s
         };
 
+        state.settings = settings;
         Self {
             state: RefCell::new(state),
         }
@@ -99,4 +104,9 @@ impl eframe::App for Debuger {
         // via `super::show_ui`.
         super::show_ui(&mut self.state.borrow_mut(), ctx, frame);
     }
+
+    // Called by eframe on shutdown and periodically to persist settings.
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        self.state.borrow().settings.save(storage);
+    }
 }
@@ -0,0 +1,169 @@
+/*! Opening a VGM file from an http(s) URL.
+
+A `Download` fetches the URL on a background thread and reports the bytes
+received so far, so the toolbar can show a progress bar while the file is
+transferred. The finished body goes through the same gzip detection as files
+opened from disk (`cui::vgm::input_bytes`), so `.vgz` links open directly.
+The file is then parsed with `ParseLimits::untrusted()`.
+*/
+
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::cui::vgm::input_bytes_with_limit;
+
+/// Size of one read from the response body; progress is reported per read.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Largest body accepted, before and after gunzipping; the download fails
+/// once more is received or decompressed.
+const MAX_BODY_SIZE: u64 = 64 << 20;
+
+/// Time allowed for connecting, and for each read once connected.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Messages sent by the download worker.
+enum DownloadMessage {
+    Progress { received: u64, total: Option<u64> },
+    Done(Result<Vec<u8>, String>),
+}
+
+/// A running download of a VGM file.
+pub struct Download {
+    pub url: String,
+    /// Bytes of the body received so far.
+    pub received: u64,
+    /// Body size from `Content-Length`, if the server sent one.
+    pub total: Option<u64>,
+    rx: mpsc::Receiver<DownloadMessage>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl Download {
+    /// Start downloading `url` in the background. Fails when `url` is not an
+    /// http(s) URL.
+    pub fn start(url: &str) -> Result<Self, String> {
+        let url = url.trim();
+        if !is_http_url(url) {
+            return Err(format!("not an http(s) URL: {}", url));
+        }
+        let (tx, rx) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let worker_url = url.to_string();
+        let worker_cancel = Arc::clone(&cancel);
+        thread::spawn(move || {
+            let result = fetch(&worker_url, &worker_cancel, &tx);
+            let _ = tx.send(DownloadMessage::Done(result));
+        });
+        Ok(Self {
+            url: url.to_string(),
+            received: 0,
+            total: None,
+            rx,
+            cancel,
+        })
+    }
+
+    /// Apply the progress reported by the worker and return the result once
+    /// the download has finished: the decompressed file, or an error message.
+    pub fn poll(&mut self) -> Option<Result<Vec<u8>, String>> {
+        loop {
+            match self.rx.try_recv() {
+                Ok(DownloadMessage::Progress { received, total }) => {
+                    self.received = received;
+                    self.total = total;
+                }
+                Ok(DownloadMessage::Done(result)) => return Some(result),
+                Err(mpsc::TryRecvError::Empty) => return None,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    return Some(Err("download worker stopped".to_string()));
+                }
+            }
+        }
+    }
+
+    /// Fraction of the body received, when the size is known.
+    pub fn fraction(&self) -> Option<f32> {
+        self.total
+            .filter(|&total| total > 0)
+            .map(|total| (self.received as f64 / total as f64).min(1.0) as f32)
+    }
+
+    /// Ask the worker to stop; `poll` then returns an error.
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// `true` when `text` (ignoring surrounding whitespace) starts with
+/// `http://` or `https://`.
+pub fn is_http_url(text: &str) -> bool {
+    let text = text.trim();
+    ["http://", "https://"].iter().any(|scheme| {
+        text.len() > scheme.len()
+            && text
+                .get(..scheme.len())
+                .is_some_and(|prefix| prefix.eq_ignore_ascii_case(scheme))
+    })
+}
+
+// Download `url`, reporting progress to `tx`, and decompress the body.
+// Bodies larger than `MAX_BODY_SIZE` are rejected, by `Content-Length` up
+// front or once that much has been received, and so are gzipped bodies that
+// decompress to more than that.
+fn fetch(
+    url: &str,
+    cancel: &AtomicBool,
+    tx: &mpsc::Sender<DownloadMessage>,
+) -> Result<Vec<u8>, String> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(CONNECT_TIMEOUT)
+        .timeout_read(READ_TIMEOUT)
+        .build();
+    let response = agent.get(url).call().map_err(|e| e.to_string())?;
+    let total = response
+        .header("Content-Length")
+        .and_then(|len| len.parse::<u64>().ok());
+    if let Some(total) = total
+        && total > MAX_BODY_SIZE
+    {
+        return Err(too_large(total));
+    }
+    let mut reader = response.into_reader();
+    let mut data = Vec::with_capacity(total.unwrap_or(0) as usize);
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            return Err("download cancelled".to_string());
+        }
+        let n = reader.read(&mut chunk).map_err(|e| e.to_string())?;
+        if n == 0 {
+            break;
+        }
+        data.extend_from_slice(&chunk[..n]);
+        if data.len() as u64 > MAX_BODY_SIZE {
+            return Err(too_large(data.len() as u64));
+        }
+        let _ = tx.send(DownloadMessage::Progress {
+            received: data.len() as u64,
+            total,
+        });
+    }
+    // Judge `.vgz` by the path alone; query strings and fragments would hide
+    // the extension.
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    input_bytes_with_limit(Path::new(path), data, MAX_BODY_SIZE).map_err(|e| format!("{:#}", e))
+}
+
+fn too_large(size: u64) -> String {
+    format!(
+        "file too large: {} bytes (max {} bytes)",
+        size, MAX_BODY_SIZE
+    )
+}
@@ -3,15 +3,19 @@
 //! This module implements a small, self-contained hex viewer widget which
 //! renders its contents using `egui::Painter`. It supports:
 //!  - fixed bytes-per-line layout (configurable),
-//!  - painter-based drawing of offsets, hex bytes and ASCII column,
+//!  - painter-based drawing of offsets, hex bytes and an optional ASCII
+//!    column that mirrors the selection and accepts clicks,
 //!  - click-to-select a single byte (highlighted),
-//!  - range selection outline and reference markers (added).
+//!  - range selection outline and reference markers (added),
+//!  - shift-click to extend the selection to a byte range,
+//!  - a context menu to export the selected range (consumed by the caller).
 //!
 //! The widget is intentionally lightweight and does not (yet) implement:
 //!  - keyboard selection/drag selection,
 //!  - highly optimized rendering of extremely large buffers.
 #![allow(clippy::manual_div_ceil)]
 use eframe::egui;
+use std::sync::Arc;
 
 /// Stateful painter-based hex viewer.
 pub struct HexViewer {
@@ -19,6 +23,8 @@ pub struct HexViewer {
     bytes_per_line: usize,
     /// Font size used for drawing.
     font_size: f32,
+    /// Whether the ASCII column is drawn next to the hex bytes.
+    show_ascii: bool,
     /// Currently selected global byte index (if any).
     selected: Option<usize>,
     /// Optional selected byte range (inclusive start, inclusive end).
@@ -48,13 +54,18 @@ pub struct HexViewer {
     last_selection_rect: Option<egui::Rect>,
     /// Optional original bytes (file bytes) kept so the tooltip can always show
     /// the true Original bytes even when the viewer is displaying rebuilt bytes.
-    original_bytes: Option<Vec<u8>>,
+    original_bytes: Option<Arc<[u8]>>,
     /// Last clicked byte index (set when the user clicks a byte cell). Cleared
     /// when consumed via `take_last_clicked_byte()`.
     last_clicked_byte: Option<usize>,
     /// Optional rebuilt/serialized bytes produced by the background parser so
     /// the viewer can display both Original and Rebuilt data in tooltips.
-    rebuilt_bytes: Option<Vec<u8>>,
+    rebuilt_bytes: Option<Arc<[u8]>>,
+    /// Inclusive byte range drawn by the last `show()` (visible lines only).
+    visible_range: Option<(usize, usize)>,
+    /// Inclusive byte range the user asked to export from the context menu.
+    /// Cleared when consumed via `take_export_request()`.
+    export_request: Option<(usize, usize)>,
 }
 
 impl Default for HexViewer {
@@ -69,6 +80,7 @@ impl HexViewer {
         Self {
             bytes_per_line: 16,
             font_size: 12.0,
+            show_ascii: true,
             selected: None,
             selection_range: None,
             reference_markers: Vec::new(),
@@ -85,6 +97,8 @@ impl HexViewer {
             original_bytes: None,
             rebuilt_bytes: None,
             last_clicked_byte: None,
+            export_request: None,
+            visible_range: None,
         }
     }
 
@@ -92,13 +106,13 @@ impl HexViewer {
     /// This should be called by the UI layer with the true file bytes even when the
     /// viewer is asked to display rebuilt bytes.
     #[allow(dead_code)]
-    pub fn set_original_bytes(&mut self, bytes: Option<Vec<u8>>) {
+    pub fn set_original_bytes(&mut self, bytes: Option<Arc<[u8]>>) {
         self.original_bytes = bytes;
     }
 
     /// Set the rebuilt/serialized bytes so the viewer can reference them in tooltips.
     #[allow(dead_code)]
-    pub fn set_rebuilt_bytes(&mut self, bytes: Option<Vec<u8>>) {
+    pub fn set_rebuilt_bytes(&mut self, bytes: Option<Arc<[u8]>>) {
         self.rebuilt_bytes = bytes;
     }
 
@@ -108,6 +122,16 @@ impl HexViewer {
         self.last_clicked_byte.take()
     }
 
+    /// Inclusive byte range visible in the last `show()`, if any.
+    pub fn visible_range(&self) -> Option<(usize, usize)> {
+        self.visible_range
+    }
+
+    /// Consume and return the range the user asked to export, if any.
+    pub fn take_export_request(&mut self) -> Option<(usize, usize)> {
+        self.export_request.take()
+    }
+
     /// Set the number of bytes per line.
     #[allow(dead_code)]
     pub fn with_bytes_per_line(mut self, bpl: usize) -> Self {
@@ -124,6 +148,23 @@ impl HexViewer {
         self
     }
 
+    /// Change the number of bytes per line.
+    pub fn set_bytes_per_line(&mut self, bpl: usize) {
+        self.bytes_per_line = bpl.max(1);
+    }
+
+    /// Change the font size used for rendering.
+    pub fn set_font_size(&mut self, size: f32) {
+        if size > 0.0 {
+            self.font_size = size;
+        }
+    }
+
+    /// Show or hide the ASCII column.
+    pub fn set_show_ascii(&mut self, show: bool) {
+        self.show_ascii = show;
+    }
+
     /// Return the configured font size so other UI parts can match rendering.
     pub fn font_size(&self) -> f32 {
         self.font_size
@@ -144,6 +185,11 @@ impl HexViewer {
         }
     }
 
+    /// Returns the current inclusive selection range (if any).
+    pub fn selection_range(&self) -> Option<(usize, usize)> {
+        self.selection_range
+    }
+
     /// Clear any selection range.
     pub fn clear_selection_range(&mut self) {
         self.selection_range = None;
@@ -327,6 +373,7 @@ impl HexViewer {
     pub fn show(&mut self, ui: &mut egui::Ui, bytes: &[u8]) {
         // Clear last selection rect
         self.last_selection_rect = None;
+        self.visible_range = None;
 
         // Determine metrics from UI + configured font size.
         let font = egui::FontId::monospace(self.font_size);
@@ -348,7 +395,7 @@ impl HexViewer {
         let offset_chars = 9.0; // "00000000:" -> 9 chars (8 hex + colon)
         let offset_width = offset_chars * char_w + 8.0;
         let hex_cell_w = char_w * 3.0; // "FF " (two hex + space)
-        let _ascii_cell_w = char_w * 1.0;
+        let ascii_cell_w = char_w;
         let sep_gap = 12.0_f32;
 
         // Compute required total height and allocate an area.
@@ -409,6 +456,10 @@ impl HexViewer {
             let last_index = lines.saturating_sub(1);
             first_line = first_line.min(last_index);
             last_line = last_line.min(last_index);
+            self.visible_range = Some((
+                first_line * bpl,
+                ((last_line + 1) * bpl).min(bytes.len()) - 1,
+            ));
 
             // Draw only the visible lines
             for line_idx in first_line..=last_line {
@@ -462,23 +513,32 @@ impl HexViewer {
                     );
                 }
 
-                // Draw ASCII column
-                let mut ascii_text = String::with_capacity(chunk.len());
-                for b in chunk.iter() {
-                    let ch = if b.is_ascii_graphic() || *b == b' ' {
-                        *b as char
-                    } else {
-                        '.'
-                    };
-                    ascii_text.push(ch);
+                // Draw ASCII column one character per cell so selections line up
+                // with the characters.
+                if self.show_ascii {
+                    for (i, b) in chunk.iter().enumerate() {
+                        let x = ascii_base_x + (i as f32) * ascii_cell_w;
+                        if self.selected == Some(offset + i) {
+                            let cell_rect = egui::Rect::from_min_size(
+                                egui::pos2(x, line_top),
+                                egui::vec2(ascii_cell_w, row_height - 4.0),
+                            );
+                            painter.rect_filled(cell_rect, 2.0, ui.visuals().selection.bg_fill);
+                        }
+                        let ch = if b.is_ascii_graphic() || *b == b' ' {
+                            *b as char
+                        } else {
+                            '.'
+                        };
+                        painter.text(
+                            egui::pos2(x, line_top),
+                            egui::Align2::LEFT_TOP,
+                            ch,
+                            font.clone(),
+                            ui.visuals().text_color(),
+                        );
+                    }
                 }
-                painter.text(
-                    egui::pos2(ascii_base_x, line_top),
-                    egui::Align2::LEFT_TOP,
-                    ascii_text,
-                    font.clone(),
-                    ui.visuals().text_color(),
-                );
             }
         }
 
@@ -554,6 +614,14 @@ impl HexViewer {
                     // Fill; draw stroke only if selection outlines are enabled and this selection
                     // is not covered by a fill-only range.
                     painter.rect_filled(seg_rect, 0.0, fill_color);
+                    // Mirror the segment in the ASCII column (fill only).
+                    if self.show_ascii {
+                        let ascii_rect = egui::Rect::from_min_max(
+                            egui::pos2(ascii_base_x + line_start * ascii_cell_w, y0),
+                            egui::pos2(ascii_base_x + (line_end + 1.0) * ascii_cell_w, y1),
+                        );
+                        painter.rect_filled(ascii_rect, 0.0, fill_color);
+                    }
                     if self.selection_outline_enabled {
                         // If any configured fill-only range fully covers the selection, skip stroke.
                         let mut covered = false;
@@ -1036,16 +1104,35 @@ impl HexViewer {
         if resp.clicked()
             && let Some(pos) = ui.input(|i| i.pointer.hover_pos())
         {
-            // Compute coordinates relative to the hex grid start.
+            // Compute coordinates relative to the hex grid start, or to the ASCII
+            // column start when the click lands there.
             let rel_x = pos.x - (base_x + offset_width);
             let rel_y = pos.y - rect.min.y;
-            if rel_x >= 0.0 && rel_y >= 0.0 {
+            let hex_col = (rel_x >= 0.0).then(|| (rel_x / hex_cell_w).floor() as usize);
+            let col = match hex_col {
+                Some(col) if col < bpl => Some(col),
+                _ if self.show_ascii && pos.x >= ascii_base_x => {
+                    let col = ((pos.x - ascii_base_x) / ascii_cell_w).floor() as usize;
+                    (col < bpl).then_some(col)
+                }
+                _ => None,
+            };
+            if rel_y >= 0.0 {
                 let line_idx = (rel_y / row_height).floor() as usize;
-                let col_f = (rel_x / hex_cell_w).floor();
-                if col_f >= 0.0 {
-                    let col = col_f as usize;
+                if let Some(col) = col {
                     let global_idx = line_idx.saturating_mul(bpl).saturating_add(col);
-                    if global_idx < bytes.len() {
+                    let extend = ui.input(|i| i.modifiers.shift);
+                    if global_idx < bytes.len()
+                        && extend
+                        && let Some(anchor) = self.selected
+                    {
+                        // Shift-click: extend the selection from the anchor byte.
+                        // The AST focus is left alone so the range is kept.
+                        self.selection_range =
+                            Some((anchor.min(global_idx), anchor.max(global_idx)));
+                        self.reference_markers = vec![anchor.min(global_idx)];
+                        ui.ctx().request_repaint();
+                    } else if global_idx < bytes.len() {
                         // Update selection state so the viewer highlights the clicked byte.
                         self.selected = Some(global_idx);
                         self.selection_range = Some((global_idx, global_idx));
@@ -1063,5 +1150,22 @@ impl HexViewer {
                 }
             }
         }
+
+        // Right-click context menu: export the selected range. The request is
+        // only recorded here; the outer UI asks for a path and writes the file.
+        let selection = self.selection_range.filter(|&(_, e)| e < bytes.len());
+        resp.context_menu(|ui| {
+            let label = match selection {
+                Some((s, e)) => format!("Export selection ({} bytes)...", e - s + 1),
+                None => "Export selection...".to_string(),
+            };
+            if ui
+                .add_enabled(selection.is_some(), egui::Button::new(label))
+                .clicked()
+            {
+                self.export_request = selection;
+                ui.close_menu();
+            }
+        });
     }
 }
@@ -0,0 +1,87 @@
+//! Vertical minimap of the whole file for the hex viewer.
+//!
+//! The strip maps the file length onto its height and colors the regions of
+//! interest: data blocks, diff ranges, the loop point, the current selection
+//! and the part of the file visible in the hex viewer. Clicking or dragging
+//! on the strip returns the byte offset under the pointer so the caller can
+//! scroll the hex viewer there.
+use eframe::egui;
+
+/// File regions computed by the background parse.
+#[derive(Clone, Debug, Default)]
+pub struct MinimapMarkers {
+    /// File offset of the loop point, if the file loops.
+    pub loop_offset: Option<usize>,
+    /// Data block commands as (start, len).
+    pub data_blocks: Vec<(usize, usize)>,
+}
+
+/// Everything drawn on the minimap for one frame. Ranges are inclusive.
+pub struct Minimap<'a> {
+    pub len: usize,
+    pub markers: &'a MinimapMarkers,
+    pub diffs: &'a [(usize, usize)],
+    pub selection: Option<(usize, usize)>,
+    pub visible: Option<(usize, usize)>,
+}
+
+impl Minimap<'_> {
+    /// Draw the strip filling the available height of `ui`. Returns the byte
+    /// offset under the pointer while the strip is clicked or dragged.
+    pub fn show(&self, ui: &mut egui::Ui) -> Option<usize> {
+        let size = ui.available_size();
+        let (rect, resp) = ui.allocate_exact_size(size, egui::Sense::click_and_drag());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+        if self.len == 0 {
+            return None;
+        }
+
+        let height = rect.height();
+        let y_of = |offset: usize| rect.min.y + offset as f32 / self.len as f32 * height;
+        // Every region is at least one pixel high so single bytes stay visible.
+        let band = |start: usize, end: usize, x0: f32, x1: f32| {
+            let y0 = y_of(start);
+            let y1 = y_of(end.saturating_add(1)).max(y0 + 1.0);
+            egui::Rect::from_min_max(egui::pos2(x0, y0), egui::pos2(x1, y1))
+        };
+        let (left, right) = (rect.min.x, rect.max.x);
+        let mid = rect.center().x;
+
+        // Visible window of the hex viewer behind everything else.
+        if let Some((s, e)) = self.visible {
+            let color = ui.visuals().widgets.inactive.bg_fill;
+            painter.rect_filled(band(s, e, left, right), 0.0, color);
+        }
+        // Data blocks on the left half, diffs on the right half.
+        let data_color = egui::Color32::from_rgb(70, 130, 180);
+        for &(start, len) in &self.markers.data_blocks {
+            if len > 0 {
+                painter.rect_filled(band(start, start + len - 1, left, mid), 0.0, data_color);
+            }
+        }
+        let diff_color = egui::Color32::from_rgb(220, 60, 60);
+        for &(s, e) in self.diffs {
+            painter.rect_filled(band(s, e, mid, right), 0.0, diff_color);
+        }
+        // Selection across the full width.
+        if let Some((s, e)) = self.selection {
+            let color = ui.visuals().selection.bg_fill;
+            painter.rect_filled(band(s, e, left, right), 0.0, color);
+        }
+        // Loop point as a horizontal line.
+        if let Some(offset) = self.markers.loop_offset {
+            let y = y_of(offset);
+            let stroke = egui::Stroke::new(2.0, egui::Color32::from_rgb(80, 200, 120));
+            painter.line_segment([egui::pos2(left, y), egui::pos2(right, y)], stroke);
+        }
+
+        if resp.clicked() || resp.dragged() {
+            let pos = resp.interact_pointer_pos()?;
+            let fraction = ((pos.y - rect.min.y) / height).clamp(0.0, 1.0);
+            let offset = (fraction * self.len as f32) as usize;
+            return Some(offset.min(self.len - 1));
+        }
+        None
+    }
+}
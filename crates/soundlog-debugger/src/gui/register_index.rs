@@ -0,0 +1,62 @@
+//! Per-register index of chip writes.
+//!
+//! Built once by the background parse so the tree can jump from a chip write
+//! to the previous or next write to the same register (same chip, instance,
+//! port and register) without rescanning the command stream.
+use std::collections::HashMap;
+
+use soundlog::VgmDocument;
+use soundlog::chip::Chip;
+use soundlog::vgm::command::Instance;
+
+/// Chip, instance, port and register of a write.
+pub type RegisterKey = (Chip, Instance, u8, u32);
+
+/// Command indices of the writes to every register.
+#[derive(Clone, Debug, Default)]
+pub struct RegisterIndex {
+    /// Register written by each command, `None` for other commands.
+    keys: Vec<Option<RegisterKey>>,
+    /// Ascending command indices per register.
+    writes: HashMap<RegisterKey, Vec<usize>>,
+}
+
+impl RegisterIndex {
+    pub fn build(doc: &VgmDocument) -> Self {
+        let mut index = Self::default();
+        for (i, cmd) in doc.iter().enumerate() {
+            let key = cmd
+                .register_write()
+                .map(|w| (w.chip, w.instance, w.port, w.register));
+            if let Some(key) = &key {
+                index.writes.entry(key.clone()).or_default().push(i);
+            }
+            index.keys.push(key);
+        }
+        index
+    }
+
+    /// Register written by command `command`, if it is a chip write.
+    pub fn key(&self, command: usize) -> Option<&RegisterKey> {
+        self.keys.get(command)?.as_ref()
+    }
+
+    /// The next (`forward`) or previous write to the register written by
+    /// command `command`.
+    pub fn step(&self, command: usize, forward: bool) -> Option<usize> {
+        let writes = self.writes.get(self.key(command)?)?;
+        let pos = writes.binary_search(&command).ok()?;
+        if forward {
+            writes.get(pos + 1).copied()
+        } else {
+            writes.get(pos.checked_sub(1)?).copied()
+        }
+    }
+
+    /// Number of writes to the register written by command `command`.
+    pub fn count(&self, command: usize) -> usize {
+        self.key(command)
+            .and_then(|key| self.writes.get(key))
+            .map_or(0, Vec::len)
+    }
+}
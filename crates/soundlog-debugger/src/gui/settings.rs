@@ -0,0 +1,190 @@
+//! Persisted GUI settings.
+//!
+//! Layout and font choices are stored through `eframe::Storage` (one string
+//! per key) so they survive restarts. Missing or malformed values fall back
+//! to the defaults, and every value is clamped to a usable range on load.
+use eframe::egui;
+
+/// Color theme of the GUI.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Theme {
+    Dark,
+    Light,
+}
+
+impl Theme {
+    fn as_str(self) -> &'static str {
+        match self {
+            Theme::Dark => "dark",
+            Theme::Light => "light",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "dark" => Some(Theme::Dark),
+            "light" => Some(Theme::Light),
+            _ => None,
+        }
+    }
+}
+
+/// User-adjustable layout settings.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    /// Font size of the hex viewer and the AST labels.
+    pub font_size: f32,
+    /// Bytes shown per hex viewer row, one of `BYTES_PER_LINE_CHOICES`.
+    pub bytes_per_line: usize,
+    /// Whether the hex viewer shows the ASCII column.
+    pub show_ascii: bool,
+    /// Width of the left AST panel in points.
+    pub ast_panel_width: f32,
+    pub theme: Theme,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            font_size: 12.0,
+            bytes_per_line: 16,
+            show_ascii: true,
+            ast_panel_width: 240.0,
+            theme: Theme::Dark,
+        }
+    }
+}
+
+const KEY_FONT_SIZE: &str = "soundlog.font_size";
+const KEY_BYTES_PER_LINE: &str = "soundlog.bytes_per_line";
+const KEY_SHOW_ASCII: &str = "soundlog.show_ascii";
+const KEY_AST_PANEL_WIDTH: &str = "soundlog.ast_panel_width";
+const KEY_THEME: &str = "soundlog.theme";
+
+impl Settings {
+    pub const FONT_SIZE_RANGE: std::ops::RangeInclusive<f32> = 8.0..=24.0;
+    pub const BYTES_PER_LINE_CHOICES: [usize; 3] = [8, 16, 32];
+    pub const AST_PANEL_WIDTH_RANGE: std::ops::RangeInclusive<f32> = 160.0..=640.0;
+
+    /// Load settings from `storage`, using defaults for missing values.
+    pub fn load(storage: Option<&dyn eframe::Storage>) -> Self {
+        let mut settings = Self::default();
+        let Some(storage) = storage else {
+            return settings;
+        };
+        if let Some(v) = storage
+            .get_string(KEY_FONT_SIZE)
+            .and_then(|s| s.parse().ok())
+        {
+            settings.font_size = v;
+        }
+        if let Some(v) = storage
+            .get_string(KEY_BYTES_PER_LINE)
+            .and_then(|s| s.parse().ok())
+        {
+            settings.bytes_per_line = v;
+        }
+        if let Some(v) = storage
+            .get_string(KEY_SHOW_ASCII)
+            .and_then(|s| s.parse().ok())
+        {
+            settings.show_ascii = v;
+        }
+        if let Some(v) = storage
+            .get_string(KEY_AST_PANEL_WIDTH)
+            .and_then(|s| s.parse().ok())
+        {
+            settings.ast_panel_width = v;
+        }
+        if let Some(v) = storage.get_string(KEY_THEME).and_then(|s| Theme::parse(&s)) {
+            settings.theme = v;
+        }
+        settings.clamp();
+        settings
+    }
+
+    /// Write the settings to `storage`.
+    pub fn save(&self, storage: &mut dyn eframe::Storage) {
+        storage.set_string(KEY_FONT_SIZE, self.font_size.to_string());
+        storage.set_string(KEY_BYTES_PER_LINE, self.bytes_per_line.to_string());
+        storage.set_string(KEY_SHOW_ASCII, self.show_ascii.to_string());
+        storage.set_string(KEY_AST_PANEL_WIDTH, self.ast_panel_width.to_string());
+        storage.set_string(KEY_THEME, self.theme.as_str().to_string());
+    }
+
+    /// Apply the theme to the egui context.
+    pub fn apply_theme(&self, ctx: &egui::Context) {
+        ctx.set_visuals(match self.theme {
+            Theme::Dark => egui::Visuals::dark(),
+            Theme::Light => egui::Visuals::light(),
+        });
+    }
+
+    fn clamp(&mut self) {
+        let clamp_f32 = |v: f32, r: &std::ops::RangeInclusive<f32>| {
+            if v.is_finite() {
+                v.clamp(*r.start(), *r.end())
+            } else {
+                *r.start()
+            }
+        };
+        self.font_size = clamp_f32(self.font_size, &Self::FONT_SIZE_RANGE);
+        self.ast_panel_width = clamp_f32(self.ast_panel_width, &Self::AST_PANEL_WIDTH_RANGE);
+        if !Self::BYTES_PER_LINE_CHOICES.contains(&self.bytes_per_line) {
+            self.bytes_per_line = Self::default().bytes_per_line;
+        }
+    }
+}
+
+/// Draw the settings window while `open` is set.
+pub fn show_settings_window(ctx: &egui::Context, open: &mut bool, settings: &mut Settings) {
+    let theme = settings.theme;
+    egui::Window::new("Settings")
+        .collapsible(false)
+        .resizable(false)
+        .open(open)
+        .show(ctx, |ui| {
+            egui::Grid::new("settings_grid")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Font size");
+                    ui.add(egui::Slider::new(
+                        &mut settings.font_size,
+                        Settings::FONT_SIZE_RANGE,
+                    ));
+                    ui.end_row();
+
+                    ui.label("Bytes per row");
+                    ui.horizontal(|ui| {
+                        for bpl in Settings::BYTES_PER_LINE_CHOICES {
+                            ui.radio_value(&mut settings.bytes_per_line, bpl, bpl.to_string());
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("ASCII column");
+                    ui.checkbox(&mut settings.show_ascii, "Show");
+                    ui.end_row();
+
+                    ui.label("Tree panel width");
+                    ui.add(egui::Slider::new(
+                        &mut settings.ast_panel_width,
+                        Settings::AST_PANEL_WIDTH_RANGE,
+                    ));
+                    ui.end_row();
+
+                    ui.label("Theme");
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut settings.theme, Theme::Dark, "Dark");
+                        ui.radio_value(&mut settings.theme, Theme::Light, "Light");
+                    });
+                    ui.end_row();
+                });
+            if ui.button("Reset to defaults").clicked() {
+                *settings = Settings::default();
+            }
+        });
+    if settings.theme != theme {
+        settings.apply_theme(ctx);
+    }
+}
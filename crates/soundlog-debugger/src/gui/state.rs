@@ -10,26 +10,37 @@ Strategy:
   allocating child widgets/strings for each command.
 - When the user expands `Commands` (or presses "Show more"), the UI requests
   a chunk of command nodes to be generated in a background thread. The
-  background worker reparses (from bytes) and formats only the requested
-  range into `AstNode`s, then sends them back to the UI which appends them
+  background worker formats only the requested range of the shared parsed
+  document into `AstNode`s, then sends them back to the UI which appends them
   into an in-memory chunk for incremental display.
 
 This avoids doing large string allocation and widget construction on the UI
 thread all at once and keeps the UI responsive for very large VGM files.
 */
 
-use crate::gui::HexViewer;
+use crate::gui::settings::show_settings_window;
+use crate::gui::{
+    Download, HexViewer, Minimap, MinimapMarkers, RegisterIndex, Settings, is_http_url,
+};
 use eframe::egui;
 
-use soundlog::VgmDocument;
-use soundlog::vgm::VgmHeaderField;
 use soundlog::vgm::command::VgmCommand;
 use soundlog::vgm::detail::{DataBlockType, parse_data_block};
+use soundlog::vgm::heatmap::{HeatmapOptions, register_heatmap};
+use soundlog::vgm::opcode::DATA_BLOCK_HEADER_LEN;
+use soundlog::vgm::parser::{CancelToken, ParseHooks, ParseLimits};
+use soundlog::vgm::snapshot::snapshot_at_command;
+use soundlog::vgm::{MemoryFootprint, VgmHeaderField};
+use soundlog::{ParseError, SharedVgmDocument, VgmDocument};
 
 use std::collections::HashMap;
+use std::sync::Arc;
 use std::sync::mpsc;
 use std::thread;
 
+/// Number of commands per lazily loaded bucket under the `Commands` node.
+const COMMAND_BUCKET_SIZE: usize = 1000;
+
 /// Simple AST node representation for the UI.
 /// `lazy_count` is Some(n) when this node is a placeholder for many children
 /// (e.g. the `Commands` node) and children are fetched lazily.
@@ -47,6 +58,11 @@ pub struct AstNode {
     /// file bytes. When present, clicking the node will highlight this range
     /// in the hex viewer.
     pub byte_range: Option<(usize, usize)>,
+    /// Optional byte range (start, len) of the payload inside `byte_range`,
+    /// e.g. the data of a data block without its 7-byte command header.
+    pub payload_range: Option<(usize, usize)>,
+    /// Index in the document command list, for command nodes.
+    pub command_index: Option<usize>,
 }
 
 impl AstNode {
@@ -58,6 +74,8 @@ impl AstNode {
             lazy_count: None,
             lazy_start: None,
             byte_range: None,
+            payload_range: None,
+            command_index: None,
         }
     }
 
@@ -84,6 +102,76 @@ impl AstNode {
         self.byte_range = Some((start, len));
         self
     }
+
+    /// Mark this node as the command at `index` in the document.
+    pub fn with_command_index(mut self, index: usize) -> Self {
+        self.command_index = Some(index);
+        self
+    }
+
+    /// Attach a payload byte range (start offset, length) to this node.
+    pub fn with_payload_range(mut self, start: usize, len: usize) -> Self {
+        self.payload_range = Some((start, len));
+        self
+    }
+}
+
+/// State of the "export bytes" window opened from the hex viewer or AST
+/// context menus.
+pub struct ExportDialog {
+    /// First byte to export.
+    pub start: usize,
+    /// Last byte to export (inclusive).
+    pub end: usize,
+    /// Destination path as typed by the user.
+    pub path: String,
+    /// Error of the last save attempt, shown in the window.
+    pub error: Option<String>,
+}
+
+impl ExportDialog {
+    /// Open the window for the inclusive range `start..=end` with a default
+    /// file name derived from the range.
+    pub fn new(start: usize, end: usize) -> Self {
+        Self {
+            start,
+            end,
+            path: format!("export_{:08X}-{:08X}.bin", start, end),
+            error: None,
+        }
+    }
+}
+
+/// State of the "Open URL" window.
+#[derive(Default)]
+pub struct OpenUrlDialog {
+    /// URL as typed or pasted by the user.
+    pub url: String,
+    /// Error of the last download, shown in the window.
+    pub error: Option<String>,
+}
+
+/// State of the register inspector window opened from the AST context menu.
+pub struct RegisterInspector {
+    /// Command the chip state was taken after.
+    pub command: usize,
+    /// Decoded register map of the chip written by the command.
+    pub text: String,
+}
+
+impl RegisterInspector {
+    /// Replay `doc` through command `command` and decode the registers of
+    /// the chip it writes. None when the command is not a chip write.
+    pub fn new(doc: &VgmDocument, command: usize) -> Option<Self> {
+        let write = doc.commands.get(command)?.register_write()?;
+        let snapshot = snapshot_at_command(doc, command + 1)
+            .into_iter()
+            .find(|s| s.chip == write.chip && s.instance == write.instance)?;
+        Some(Self {
+            command,
+            text: format!("{:#}", snapshot.register_map()),
+        })
+    }
 }
 
 /// Messages sent from background workers to the UI.
@@ -103,14 +191,40 @@ pub enum AstBuildMessage {
     /// Each tuple is (start_inclusive, end_inclusive).
     /// The `Diff` variant now carries the rebuilt bytes as well so the UI can
     /// display both original and rebuilt data when needed.
-    Diff(Vec<(usize, usize)>, Vec<u8>),
+    Diff(Vec<(usize, usize)>, Arc<[u8]>),
+    /// The parsed document, shared with the workers that format command ranges.
+    Document(SharedVgmDocument),
+    /// Loop point and data block regions for the minimap.
+    Markers(MinimapMarkers),
+    /// Per-register write index for "next/previous write" navigation.
+    RegisterIndex(RegisterIndex),
+    /// Parse time and memory of the parsed document for the status bar.
+    Stats(ParseStats),
+    /// Bytes consumed so far by the initial parse, out of `total`.
+    Progress {
+        consumed: usize,
+        total: usize,
+    },
+    /// The initial parse was cancelled by the user.
+    Cancelled,
     Error(String),
 }
 
+/// Load statistics shown in the status bar.
+#[derive(Clone, Copy, Debug)]
+pub struct ParseStats {
+    pub parse_time: std::time::Duration,
+    pub commands: usize,
+    pub footprint: MemoryFootprint,
+}
+
 /// UI state holding AST, raw bytes and supporting maps for lazy-loading.
 pub struct UiState {
     pub ast_root: Vec<AstNode>,
-    pub bytes: Vec<u8>,
+    /// File bytes, shared with the background workers and the hex viewer.
+    pub bytes: Arc<[u8]>,
+    /// The parsed document once the initial parse is done.
+    pub document: Option<SharedVgmDocument>,
     pub selected_ast: Option<Vec<usize>>,
     /// The last observed selected AST label rect (widget coords). Used to
     /// scroll the left pane so keyboard-driven selection is visible.
@@ -124,7 +238,7 @@ pub struct UiState {
     pub hex_viewer: HexViewer,
     /// If a background parse produced rebuilt/serialized bytes (used to detect diffs),
     /// keep them here so UI components can access both original (`bytes`) and rebuilt bytes.
-    pub rebuilt_bytes: Option<Vec<u8>>,
+    pub rebuilt_bytes: Option<Arc<[u8]>>,
 
     /// Channel receiver to accept background build messages (full or partial).
     pub ast_build_rx: Option<mpsc::Receiver<AstBuildMessage>>,
@@ -133,6 +247,13 @@ pub struct UiState {
 
     /// Whether an initial parse is in progress.
     pub ast_building: bool,
+    /// Progress of the initial parse as (consumed bytes, total bytes).
+    pub ast_progress: Option<(usize, usize)>,
+    /// Flag polled by the initial parse worker; setting it stops the worker.
+    pub ast_cancel: Option<CancelToken>,
+    /// Limits of the initial parse; `ParseLimits::untrusted()` for files
+    /// opened from a URL.
+    pub parse_limits: ParseLimits,
 
     /// For lazy nodes (keyed by path string like "0" or "1.2"), store the already
     /// loaded child nodes in display order (appended as partial chunks arrive).
@@ -151,6 +272,27 @@ pub struct UiState {
 
     /// Temporary set of enqueued requests to prevent duplicate deferred loads.
     pub enqueued_requests: HashMap<String, bool>,
+
+    /// Open "export bytes" window, if any.
+    pub export_dialog: Option<ExportDialog>,
+    /// Open register inspector window, if any.
+    pub register_inspector: Option<RegisterInspector>,
+    /// Open "Open URL" window, if any.
+    pub open_url: Option<OpenUrlDialog>,
+    /// Running download of a file opened from a URL.
+    pub download: Option<Download>,
+
+    /// Persisted layout settings (saved by the app through eframe storage).
+    pub settings: Settings,
+    /// Whether the settings window is open.
+    pub show_settings: bool,
+
+    /// Regions drawn on the hex viewer minimap.
+    pub minimap_markers: MinimapMarkers,
+    /// Writes per register, used to jump between writes to one register.
+    pub register_index: RegisterIndex,
+    /// Statistics of the last completed parse.
+    pub parse_stats: Option<ParseStats>,
 }
 
 impl UiState {
@@ -164,11 +306,12 @@ impl UiState {
             AstNode::new("Commands", "No commands loaded").with_lazy(0),
         ];
 
-        let bytes = (0u8..=255u8).collect::<Vec<u8>>();
+        let bytes = (0u8..=255u8).collect::<Arc<[u8]>>();
 
         Self {
             ast_root,
             bytes,
+            document: None,
             selected_ast: None,
             last_selected_ast_rect: None,
             pending_focus: None,
@@ -178,18 +321,31 @@ impl UiState {
             ast_build_rx: None,
             ast_build_tx: None,
             ast_building: false,
+            ast_progress: None,
+            ast_cancel: None,
+            parse_limits: ParseLimits::unlimited(),
             loaded_lazy_nodes: HashMap::new(),
             pending_requests: HashMap::new(),
             lazy_chunk_size: 200,
             deferred_loads: Vec::new(),
             enqueued_requests: HashMap::new(),
+            export_dialog: None,
+            register_inspector: None,
+            open_url: None,
+            download: None,
+            settings: Settings::default(),
+            show_settings: false,
+            minimap_markers: MinimapMarkers::default(),
+            register_index: RegisterIndex::default(),
+            parse_stats: None,
         }
     }
 
     pub fn new_empty() -> Self {
         Self {
             ast_root: Vec::new(),
-            bytes: Vec::new(),
+            bytes: Arc::from([]),
+            document: None,
             selected_ast: None,
             last_selected_ast_rect: None,
             pending_focus: None,
@@ -199,11 +355,23 @@ impl UiState {
             ast_build_rx: None,
             ast_build_tx: None,
             ast_building: false,
+            ast_progress: None,
+            ast_cancel: None,
+            parse_limits: ParseLimits::unlimited(),
             loaded_lazy_nodes: HashMap::new(),
             pending_requests: HashMap::new(),
             lazy_chunk_size: 200,
             deferred_loads: Vec::new(),
             enqueued_requests: HashMap::new(),
+            export_dialog: None,
+            register_inspector: None,
+            open_url: None,
+            download: None,
+            settings: Settings::default(),
+            show_settings: false,
+            minimap_markers: MinimapMarkers::default(),
+            register_index: RegisterIndex::default(),
+            parse_stats: None,
         }
     }
 
@@ -332,7 +500,11 @@ impl UiState {
         if doc.header.spcm_interface != 0 {
             header_children.push(AstNode::new(
                 "SPCM interface",
-                format!("{}", doc.header.spcm_interface),
+                format!(
+                    "0x{:08x} {:?}",
+                    doc.header.spcm_interface,
+                    doc.header.sega_pcm_interface()
+                ),
             ));
         }
         if doc.header.rf5c68_clock != 0 {
@@ -746,119 +918,120 @@ impl UiState {
     /// Build a GD3 top-level node (with child fields and byte ranges) if present.
     /// Returns Some(AstNode) when GD3 metadata exists and at least one child field
     /// is non-empty; otherwise returns None.
+    /// Build the "Register Heatmap" node: one child per chip instance with
+    /// the write counts of each written port in rows of 16 registers.
+    fn build_heatmap_node(doc: &VgmDocument) -> AstNode {
+        let heatmap = register_heatmap(doc, &HeatmapOptions::new());
+        let chips = heatmap
+            .chips
+            .iter()
+            .map(|chip| {
+                let mut rows = Vec::new();
+                for port in chip.ports() {
+                    for (row, counts) in chip.grid(port).iter().enumerate() {
+                        if counts.iter().all(|&count| count == 0) {
+                            continue;
+                        }
+                        let detail = counts
+                            .iter()
+                            .map(|&count| match count {
+                                0 => ".".to_string(),
+                                count => count.to_string(),
+                            })
+                            .collect::<Vec<_>>()
+                            .join(" ");
+                        rows.push(AstNode::new(
+                            format!("port {} 0x{:02X}", port, row * 16),
+                            detail,
+                        ));
+                    }
+                }
+                AstNode::new(
+                    format!("{:?} ({:?})", chip.chip, chip.instance),
+                    format!("{} writes", chip.total()),
+                )
+                .with_children(rows)
+            })
+            .collect();
+        AstNode::new(
+            "Register Heatmap",
+            format!("{} chip instance(s)", heatmap.chips.len()),
+        )
+        .with_children(chips)
+    }
+
     fn build_gd3_node(doc: &VgmDocument) -> Option<AstNode> {
         if doc.header.gd3_offset != 0 {
             let gd3_start = doc.header.gd3_offset.wrapping_add(0x14) as usize;
-            // Fields start after the 12-byte Gd3 header (ident+version+len).
-            let mut field_off = gd3_start + 12_usize;
             let gd3_ref = doc.gd3.as_ref()?;
-            let mut gd3_children: Vec<AstNode> = Vec::new();
-
-            // Helper to push a field node and advance the running offset.
-            // For the Notes field we strip newlines so the AST/right-pane detail
-            // shows a single-line note (per request).
-            let push_field =
-                |children: &mut Vec<AstNode>, title: &str, v: &Option<String>, off: &mut usize| {
-                    if let Some(s) = v {
-                        // UTF-16LE code units -> two bytes each
-                        let len_bytes = s.encode_utf16().count() * 2;
-                        // For Notes, remove newline characters; otherwise keep the original string.
-                        let detail = if title == "Notes" {
-                            s.replace('\n', " ")
-                        } else {
-                            s.clone()
-                        };
-                        children.push(AstNode::new(title, detail).with_byte_range(*off, len_bytes));
-                        // advance past string bytes + 2-byte UTF-16 nul terminator
-                        *off = off.saturating_add(len_bytes + 2);
-                    } else {
-                        // empty field: only the 2-byte terminator is present
-                        *off = off.saturating_add(2);
-                    }
-                };
-
-            push_field(
-                &mut gd3_children,
+            const TITLES: [&str; 11] = [
                 "Track name (EN)",
-                &gd3_ref.track_name_en,
-                &mut field_off,
-            );
-            push_field(
-                &mut gd3_children,
                 "Track name (JP)",
-                &gd3_ref.track_name_origin,
-                &mut field_off,
-            );
-            push_field(
-                &mut gd3_children,
                 "Game name (EN)",
-                &gd3_ref.game_name_en,
-                &mut field_off,
-            );
-            push_field(
-                &mut gd3_children,
                 "Game name (JP)",
-                &gd3_ref.game_name_origin,
-                &mut field_off,
-            );
-            push_field(
-                &mut gd3_children,
                 "System name (EN)",
-                &gd3_ref.system_name_en,
-                &mut field_off,
-            );
-            push_field(
-                &mut gd3_children,
                 "System name (JP)",
-                &gd3_ref.system_name_origin,
-                &mut field_off,
-            );
-            push_field(
-                &mut gd3_children,
                 "Author (EN)",
-                &gd3_ref.author_name_en,
-                &mut field_off,
-            );
-            push_field(
-                &mut gd3_children,
                 "Author (JP)",
-                &gd3_ref.author_name_origin,
-                &mut field_off,
-            );
-            push_field(
-                &mut gd3_children,
                 "Release date",
-                &gd3_ref.release_date,
-                &mut field_off,
-            );
-            push_field(
-                &mut gd3_children,
                 "Creator",
-                &gd3_ref.creator,
-                &mut field_off,
-            );
-            push_field(&mut gd3_children, "Notes", &gd3_ref.notes, &mut field_off);
+                "Notes",
+            ];
+            let mut gd3_children: Vec<AstNode> = Vec::new();
+            for ((title, value), (offset, len)) in TITLES
+                .into_iter()
+                .zip(gd3_ref.fields())
+                .zip(gd3_ref.field_ranges())
+            {
+                let Some(s) = value else {
+                    continue;
+                };
+                // For Notes, remove newline characters so the AST/right-pane
+                // detail shows a single-line note.
+                let detail = if title == "Notes" {
+                    s.replace('\n', " ")
+                } else {
+                    s.clone()
+                };
+                gd3_children.push(
+                    AstNode::new(title, detail)
+                        .with_byte_range(gd3_start.saturating_add(offset), len),
+                );
+            }
 
             if !gd3_children.is_empty() {
                 // Attach a GD3 top-level node and also record the full GD3 chunk range
                 // so selecting the GD3 node highlights the entire metadata chunk.
                 let mut gd3_node = AstNode::new("GD3", "Metadata").with_children(gd3_children);
-                let gd3_len = doc.gd3.as_ref().map(|g| g.to_bytes().len()).unwrap_or(0);
-                if gd3_len > 0 {
-                    let gd3_start = doc.header.gd3_offset.wrapping_add(0x14) as usize;
-                    gd3_node.byte_range = Some((gd3_start, gd3_len));
-                }
+                gd3_node.byte_range = Some((gd3_start, gd3_ref.byte_len()));
                 return Some(gd3_node);
             }
         }
         None
     }
 
+    /// Replace the displayed file with `bytes`: stop a running parse, drop
+    /// everything derived from the previous file and parse the new one within
+    /// `limits`. The settings are kept.
+    pub fn load_bytes(&mut self, bytes: Vec<u8>, limits: ParseLimits) {
+        if let Some(cancel) = self.ast_cancel.take() {
+            cancel.cancel();
+        }
+        *self = Self {
+            settings: std::mem::take(&mut self.settings),
+            show_settings: self.show_settings,
+            parse_limits: limits,
+            ..Self::new_empty()
+        };
+        self.populate_from_bytes(bytes.into());
+    }
+
     /// Kick off initial parse in background. This will produce a lightweight
     /// AST where the `Commands` node has `lazy_count = Some(total)`.
-    pub fn populate_from_bytes(&mut self, bytes: &[u8]) {
+    pub fn populate_from_bytes(&mut self, bytes: Arc<[u8]>) {
         // store raw bytes
-        self.bytes = bytes.to_vec();
+        self.bytes = bytes;
+        self.document = None;
 
         // If a background parse is already running, do nothing.
         if self.ast_building {
@@ -870,14 +1043,35 @@ impl UiState {
         self.ast_build_rx = Some(rx);
         self.ast_build_tx = Some(tx.clone());
         self.ast_building = true;
+        self.ast_progress = Some((0, self.bytes.len()));
+        let cancel = CancelToken::new();
+        self.ast_cancel = Some(cancel.clone());
 
-        // Clone bytes to move into worker.
-        let data = self.bytes.clone();
+        // Share bytes with the worker.
+        let data = Arc::clone(&self.bytes);
+        let limits = self.parse_limits;
 
         // Spawn background thread to parse the document and produce the lightweight AST.
         thread::spawn(move || {
-            match VgmDocument::try_from(data.as_slice()) {
+            let started = std::time::Instant::now();
+            let hooks = ParseHooks::new()
+                .on_progress(|consumed, total| {
+                    let _ = tx.send(AstBuildMessage::Progress { consumed, total });
+                })
+                .with_cancel(cancel.clone())
+                .with_limits(limits);
+            let parsed = VgmDocument::parse_with_hooks(&data, hooks);
+            let parse_time = started.elapsed();
+            match parsed {
                 Ok(doc) => {
+                    let doc = SharedVgmDocument::from(doc);
+                    let _ = tx.send(AstBuildMessage::Document(doc.clone()));
+                    let _ = tx.send(AstBuildMessage::Stats(ParseStats {
+                        parse_time,
+                        commands: doc.commands.len(),
+                        footprint: doc.memory_footprint(),
+                    }));
+
                     // Build header node (extracted helper).
                     let mut nodes: Vec<AstNode> = Vec::new();
                     let header_node = Self::build_header_node(&doc);
@@ -886,7 +1080,7 @@ impl UiState {
                     // Commands node: create bucketed children (e.g. [0..1000], [1000..2000], ...)
                     // Each bucket is a lazy node that can be expanded to load its commands.
                     let total_cmds = doc.commands.len();
-                    let bucket_size = 1000usize;
+                    let bucket_size = COMMAND_BUCKET_SIZE;
                     let mut buckets: Vec<AstNode> = Vec::new();
                     let mut start_idx = 0usize;
                     while start_idx < total_cmds {
@@ -911,14 +1105,31 @@ impl UiState {
                     if let Some(gd3_node) = Self::build_gd3_node(&doc) {
                         nodes.push(gd3_node);
                     }
+                    nodes.push(Self::build_heatmap_node(&doc));
 
                     let _ = tx.send(AstBuildMessage::Full(nodes));
 
+                    // Loop point and data block regions for the minimap.
+                    let loop_offset = (doc.header.loop_offset != 0)
+                        .then(|| doc.header.loop_offset as usize + 0x1C);
+                    let data_blocks = doc
+                        .iter()
+                        .zip(doc.sourcemap().iter())
+                        .filter(|(cmd, _)| matches!(cmd, VgmCommand::DataBlock(_)))
+                        .map(|(_, &range)| range)
+                        .collect();
+                    let _ = tx.send(AstBuildMessage::Markers(MinimapMarkers {
+                        loop_offset,
+                        data_blocks,
+                    }));
+                    let _ = tx.send(AstBuildMessage::RegisterIndex(RegisterIndex::build(&doc)));
+                    if cancel.is_cancelled() {
+                        return;
+                    }
+
                     // Compute differences between the original bytes (`data`) and the
                     // serialized/rebuilt bytes produced by the document serializer.
-                    // `VgmDocument` implements `From<&VgmDocument> for Vec<u8>` so use
-                    // `Vec::from(&doc)` rather than the private `to_bytes()` method.
-                    let rebuilt_bytes = Vec::from(&doc);
+                    let rebuilt_bytes = doc.serialized();
                     let max_len = std::cmp::max(data.len(), rebuilt_bytes.len());
                     let mut diffs: Vec<(usize, usize)> = Vec::new();
                     let mut in_diff = false;
@@ -951,6 +1162,9 @@ impl UiState {
                     // in tooltips or other diagnostics views.
                     let _ = tx.send(AstBuildMessage::Diff(diffs, rebuilt_bytes));
                 }
+                Err(ParseError::Cancelled { .. }) => {
+                    let _ = tx.send(AstBuildMessage::Cancelled);
+                }
                 Err(e) => {
                     let _ = tx.send(AstBuildMessage::Error(format!("{:?}", e)));
                 }
@@ -963,8 +1177,8 @@ impl UiState {
     ///   the node is a bucket; otherwise absolute).
     /// - `count` is how many commands to format.
     ///
-    /// This spawns a background worker which reparses the VGM bytes and produces
-    /// formatted `AstNode`s for the specified range. Results are sent via the
+    /// This spawns a background worker which formats the specified range of
+    /// the shared parsed document into `AstNode`s. Results are sent via the
     /// shared sender stored in `ast_build_tx`. Note: the `start` in the
     /// `AstBuildMessage::Partial` is the *relative* offset within the bucket so
     /// the UI can insert the returned chunk at the correct position; the
@@ -988,11 +1202,11 @@ impl UiState {
             return;
         }
 
-        // Ensure we have bytes to parse and a sender to send results.
-        if self.bytes.is_empty() {
-            self.push_event("request skipped: no bytes".to_string());
+        // Ensure we have a parsed document and a sender to send results.
+        let Some(doc) = self.document.clone() else {
+            self.push_event("request skipped: no document".to_string());
             return;
-        }
+        };
         let tx_opt = self.ast_build_tx.clone();
         if tx_opt.is_none() {
             self.push_event("request skipped: no tx".to_string());
@@ -1007,9 +1221,6 @@ impl UiState {
             path_key, start, count
         ));
 
-        // Clone bytes to move into thread.
-        let data = self.bytes.clone();
-
         // Determine base absolute start for this path (if the node corresponds to a bucket).
         // If the node at `path` has a `lazy_start`, treat the provided `start` as
         // relative to that bucket; otherwise `start` is absolute.
@@ -1031,67 +1242,70 @@ impl UiState {
         let absolute_start = base_abs.saturating_add(relative_start);
 
         thread::spawn(move || {
-            // Re-parse document in background and produce requested range using absolute indices.
-            match VgmDocument::try_from(data.as_slice()) {
-                Ok(doc) => {
-                    let total = doc.commands.len();
-                    if absolute_start >= total {
-                        // Nothing to do; send empty chunk. Use relative_start so the UI knows insertion pos.
-                        let _ = tx.send(AstBuildMessage::Partial {
-                            path,
-                            start: relative_start,
-                            nodes: Vec::new(),
-                        });
-                        return;
-                    }
-                    let end = std::cmp::min(absolute_start + count, total);
-
-                    let mut nodes: Vec<AstNode> = Vec::with_capacity(end - absolute_start);
-                    // Compute absolute offsets/lengths for commands once and attach them
-                    // to the returned AstNodes so the UI can highlight the exact bytes.
-                    let abs_ranges = doc.sourcemap();
-                    for (abs_i, cmd) in doc.iter().enumerate().take(end).skip(absolute_start) {
-                        // Prefer showing a parsed DataBlock summary both in the
-                        // title and detail when available so the left-pane doesn't
-                        // show the raw `DataBlock(...)` debug blob.
-                        let (title, detail) = match cmd {
-                            VgmCommand::DataBlock(db) => match parse_data_block(*db.clone()) {
-                                Ok(dbt) => {
-                                    let inner_dbg = match dbt {
-                                        DataBlockType::UncompressedStream(s) => format!("{:?}", s),
-                                        DataBlockType::CompressedStream(c) => format!("{:?}", c),
-                                        DataBlockType::DecompressionTable(t) => format!("{:?}", t),
-                                        DataBlockType::RomRamDump(r) => format!("{:?}", r),
-                                        DataBlockType::RamWrite16(rw) => format!("{:?}", rw),
-                                        DataBlockType::RamWrite32(rw) => format!("{:?}", rw),
-                                    };
-                                    (format!("{}: {}", abs_i, inner_dbg), inner_dbg.clone())
-                                }
-                                Err((_, err)) => (
-                                    format!("{}: DataBlock(parse error)", abs_i),
-                                    format!("<DataBlock parse error: {:?}>", err),
-                                ),
-                            },
-                            _ => (format!("{}: {:?}", abs_i, cmd), format!("{:?}", cmd)),
-                        };
-
-                        if let Some((off, len)) = abs_ranges.get(abs_i).copied() {
-                            nodes.push(AstNode::new(title, detail).with_byte_range(off, len));
-                        } else {
-                            nodes.push(AstNode::new(title, detail));
+            // Format the requested range of the shared document using absolute indices.
+            let total = doc.commands.len();
+            if absolute_start >= total {
+                // Nothing to do; send empty chunk. Use relative_start so the UI knows insertion pos.
+                let _ = tx.send(AstBuildMessage::Partial {
+                    path,
+                    start: relative_start,
+                    nodes: Vec::new(),
+                });
+                return;
+            }
+            let end = std::cmp::min(absolute_start + count, total);
+
+            let mut nodes: Vec<AstNode> = Vec::with_capacity(end - absolute_start);
+            // Compute absolute offsets/lengths for commands once and attach them
+            // to the returned AstNodes so the UI can highlight the exact bytes.
+            let abs_ranges = doc.sourcemap();
+            for (abs_i, cmd) in doc.iter().enumerate().take(end).skip(absolute_start) {
+                // Prefer showing a parsed DataBlock summary both in the
+                // title and detail when available so the left-pane doesn't
+                // show the raw `DataBlock(...)` debug blob.
+                let (title, detail) = match cmd {
+                    VgmCommand::DataBlock(db) => match parse_data_block(*db.clone()) {
+                        Ok(dbt) => {
+                            let inner_dbg = match dbt {
+                                DataBlockType::UncompressedStream(s) => format!("{:?}", s),
+                                DataBlockType::CompressedStream(c) => format!("{:?}", c),
+                                DataBlockType::DecompressionTable(t) => format!("{:?}", t),
+                                DataBlockType::RomRamDump(r) => format!("{:?}", r),
+                                DataBlockType::RamWrite16(rw) => format!("{:?}", rw),
+                                DataBlockType::RamWrite32(rw) => format!("{:?}", rw),
+                            };
+                            (format!("{}: {}", abs_i, inner_dbg), inner_dbg.clone())
                         }
-                    }
+                        Err((_, err)) => (
+                            format!("{}: DataBlock(parse error)", abs_i),
+                            format!("<DataBlock parse error: {:?}>", err),
+                        ),
+                    },
+                    _ => (format!("{}: {:?}", abs_i, cmd), format!("{:?}", cmd)),
+                };
 
-                    let _ = tx.send(AstBuildMessage::Partial {
-                        path,
-                        start: relative_start,
-                        nodes,
-                    });
-                }
-                Err(e) => {
-                    let _ = tx.send(AstBuildMessage::Error(format!("{:?}", e)));
+                if let Some((off, len)) = abs_ranges.get(abs_i).copied() {
+                    let mut node = AstNode::new(title, detail)
+                        .with_byte_range(off, len)
+                        .with_command_index(abs_i);
+                    // 0x67 0x66 tt ss ss ss ss, then the payload.
+                    if matches!(cmd, VgmCommand::DataBlock(_)) && len > DATA_BLOCK_HEADER_LEN {
+                        node = node.with_payload_range(
+                            off + DATA_BLOCK_HEADER_LEN,
+                            len - DATA_BLOCK_HEADER_LEN,
+                        );
+                    }
+                    nodes.push(node);
+                } else {
+                    nodes.push(AstNode::new(title, detail));
                 }
             }
+
+            let _ = tx.send(AstBuildMessage::Partial {
+                path,
+                start: relative_start,
+                nodes,
+            });
         });
     }
 }
@@ -1104,6 +1318,118 @@ fn path_key_for(path: &[usize]) -> String {
         .join(".")
 }
 
+/// Resolve the node at `path`, descending into loaded bucket children for
+/// lazy nodes.
+fn ast_node_at(state: &UiState, path: &[usize]) -> Option<AstNode> {
+    let mut node = state.ast_root.get(*path.first()?)?.clone();
+    for depth in 1..path.len() {
+        node = if node.lazy_start.is_some() {
+            state
+                .loaded_lazy_nodes
+                .get(&path_key_for(&path[..depth]))?
+                .get(path[depth])?
+                .clone()
+        } else {
+            node.children.get(path[depth])?.clone()
+        };
+    }
+    Some(node)
+}
+
+/// Tree path of the command at `command`: `[commands, bucket, index]`.
+fn command_path(state: &UiState, command: usize) -> Option<Vec<usize>> {
+    let commands = state.ast_root.iter().position(|n| n.title == "Commands")?;
+    Some(vec![
+        commands,
+        command / COMMAND_BUCKET_SIZE,
+        command % COMMAND_BUCKET_SIZE,
+    ])
+}
+
+/// Leaf nodes are drawn as focusable labels; only those are keyboard targets
+/// below the top level.
+fn is_leaf(node: &AstNode) -> bool {
+    node.children.is_empty() && node.lazy_count.is_none()
+}
+
+/// The keyboard target after (or before) the node at `path` (depth >= 2).
+///
+/// Inside a command bucket this steps through the commands and crosses into
+/// the neighbouring bucket at its ends; the target may not be loaded yet, in
+/// which case drawing the (forced open) bucket requests it. Elsewhere it
+/// moves to the next leaf sibling.
+fn step_ast_path(state: &UiState, path: &[usize], forward: bool) -> Option<Vec<usize>> {
+    let (&idx, parent) = path.split_last()?;
+    let parent_node = ast_node_at(state, parent)?;
+    if let Some(total) = parent_node.lazy_count
+        && parent_node.lazy_start.is_some()
+    {
+        let (&bucket, grand) = parent.split_last()?;
+        let target = if forward {
+            if idx + 1 < total {
+                [parent, &[idx + 1]].concat()
+            } else {
+                let buckets = ast_node_at(state, grand)?.children;
+                buckets.get(bucket + 1)?;
+                [grand, &[bucket + 1, 0]].concat()
+            }
+        } else if idx > 0 {
+            [parent, &[idx - 1]].concat()
+        } else {
+            let previous = ast_node_at(state, grand)?
+                .children
+                .get(bucket.checked_sub(1)?)?
+                .lazy_count?;
+            [grand, &[bucket - 1, previous.checked_sub(1)?]].concat()
+        };
+        return Some(target);
+    }
+    let siblings = &parent_node.children;
+    let next = if forward {
+        (idx + 1..siblings.len()).find(|&i| is_leaf(&siblings[i]))
+    } else {
+        (0..idx).rev().find(|&i| is_leaf(&siblings[i]))
+    }?;
+    Some([parent, &[next]].concat())
+}
+
+/// The first keyboard target inside the top-level node `idx`: the first
+/// command when its children are buckets, otherwise the first leaf child.
+fn first_child_path(state: &UiState, idx: usize) -> Option<Vec<usize>> {
+    let node = state.ast_root.get(idx)?;
+    let first = node.children.first()?;
+    if first.lazy_start.is_some() {
+        return Some(vec![idx, 0, 0]);
+    }
+    let child = node.children.iter().position(is_leaf)?;
+    Some(vec![idx, child])
+}
+
+/// The node keyboard navigation starts from: a nested target still waiting
+/// for focus, else the selection. Top-level targets are selected right away,
+/// so a top-level `pending_focus` may be stale and is ignored.
+fn keyboard_position(state: &UiState) -> Option<&Vec<usize>> {
+    state
+        .pending_focus
+        .as_ref()
+        .filter(|p| p.len() >= 2)
+        .or(state.selected_ast.as_ref())
+}
+
+/// Whether the keyboard position is a top-level node or unset.
+fn current_is_top_level(state: &UiState) -> bool {
+    keyboard_position(state).is_none_or(|p| p.len() <= 1)
+}
+
+/// Whether the collapsing header at `path` must be open so a pending
+/// keyboard target below it can be drawn and focused.
+fn opens_for_pending_focus(state: &UiState, path: &[usize]) -> bool {
+    state
+        .pending_focus
+        .as_ref()
+        .is_some_and(|p| p.len() > path.len() && p.starts_with(path))
+}
+
 /// Parse an address/offset from an AstNode detail string.
 ///
 /// Supports "0x..." hexadecimal tokens (first occurrence) and the first
@@ -1157,6 +1483,7 @@ fn draw_ast_node(ui: &mut egui::Ui, node: &AstNode, path: Vec<usize>, state: &mu
                 egui::RichText::new(&display_title).size(state.hex_viewer.font_size()),
             )
             .default_open(total <= 100)
+            .open(opens_for_pending_focus(state, &path).then_some(true))
             .show(ui, |ui| {
                 ui.add_space(4.0);
 
@@ -1291,6 +1618,47 @@ fn draw_ast_node(ui: &mut egui::Ui, node: &AstNode, path: Vec<usize>, state: &mu
                 // Close the context menu after handling the click so it doesn't remain open.
                 ui.close_menu();
             }
+            // Export the bytes of the node (and the payload of data blocks) to a file.
+            if let Some((start, len)) = node.byte_range
+                && len > 0
+                && ui.button("Export bytes...").clicked()
+            {
+                state.export_dialog = Some(ExportDialog::new(start, start + len - 1));
+                ui.close_menu();
+            }
+            if let Some((start, len)) = node.payload_range
+                && len > 0
+                && ui.button("Export payload...").clicked()
+            {
+                state.export_dialog = Some(ExportDialog::new(start, start + len - 1));
+                ui.close_menu();
+            }
+            // Trace one register through the song: jump to the neighbouring writes.
+            if let Some(command) = node.command_index
+                && state.register_index.key(command).is_some()
+            {
+                ui.separator();
+                ui.label(format!(
+                    "{} writes to this register",
+                    state.register_index.count(command)
+                ));
+                for (label, forward) in [("Previous write", false), ("Next write", true)] {
+                    let target = state.register_index.step(command, forward);
+                    if ui
+                        .add_enabled(target.is_some(), egui::Button::new(label))
+                        .clicked()
+                    {
+                        state.pending_focus = target.and_then(|t| command_path(state, t));
+                        ui.close_menu();
+                    }
+                }
+                if let Some(doc) = state.document.as_ref()
+                    && ui.button("Inspect chip registers").clicked()
+                {
+                    state.register_inspector = RegisterInspector::new(doc, command);
+                    ui.close_menu();
+                }
+            }
         });
 
         // If a keyboard-driven navigation requested that this path be focused/visible,
@@ -1478,6 +1846,7 @@ fn draw_ast_node(ui: &mut egui::Ui, node: &AstNode, path: Vec<usize>, state: &mu
     } else {
         CollapsingHeader::new(egui::RichText::new(&node.title).size(state.hex_viewer.font_size()))
             .default_open(false)
+            .open(opens_for_pending_focus(state, &path).then_some(true))
             .show(ui, |ui| {
                 ui.add_space(4.0);
                 for (i, child) in node.children.iter().enumerate() {
@@ -1493,8 +1862,7 @@ fn draw_ast_node(ui: &mut egui::Ui, node: &AstNode, path: Vec<usize>, state: &mu
 pub fn show_ui(state: &mut UiState, ctx: &egui::Context, _frame: &mut eframe::Frame) {
     // If we have bytes but no AST yet, start initial populate.
     if state.ast_root.is_empty() && !state.bytes.is_empty() {
-        let bytes_clone = state.bytes.clone();
-        state.populate_from_bytes(&bytes_clone);
+        state.populate_from_bytes(Arc::clone(&state.bytes));
     }
 
     // Poll any background messages (drain all available messages).
@@ -1538,6 +1906,7 @@ pub fn show_ui(state: &mut UiState, ctx: &egui::Context, _frame: &mut eframe::Fr
                     state.loaded_lazy_nodes.clear();
                     state.pending_requests.clear();
                     state.ast_building = false;
+                    state.ast_progress = None;
                     state.push_event("received: full ast".to_string());
                 }
                 AstBuildMessage::Partial { path, start, nodes } => {
@@ -1566,6 +1935,7 @@ pub fn show_ui(state: &mut UiState, ctx: &egui::Context, _frame: &mut eframe::Fr
                     } else if start < entry.len() {
                         // Overwrite existing range if overlapping (best-effort).
                         let mut idx = start;
+                        #[allow(clippy::explicit_counter_loop)]
                         for n in nodes.into_iter() {
                             if idx < entry.len() {
                                 entry[idx] = n;
@@ -1602,8 +1972,8 @@ pub fn show_ui(state: &mut UiState, ctx: &egui::Context, _frame: &mut eframe::Fr
                     // Update hex viewer overlay ranges so mismatches are shown as red outlines.
                     state.hex_viewer.set_diff_ranges(diffs);
                     // Provide the rebuilt bytes to the HexViewer so its diff tooltip can
-                    // show both Original and Rebuilt values. Clone here because we will
-                    // also store the rebuilt bytes in the UiState.
+                    // show both Original and Rebuilt values; the buffer is shared with
+                    // the UiState.
                     state
                         .hex_viewer
                         .set_rebuilt_bytes(Some(rebuilt_bytes.clone()));
@@ -1614,9 +1984,39 @@ pub fn show_ui(state: &mut UiState, ctx: &egui::Context, _frame: &mut eframe::Fr
                     ctx.request_repaint();
                     state.push_event("received: diff ranges".to_string());
                 }
+                AstBuildMessage::Document(doc) => {
+                    state.document = Some(doc);
+                }
+                AstBuildMessage::Markers(markers) => {
+                    state.minimap_markers = markers;
+                }
+                AstBuildMessage::RegisterIndex(index) => {
+                    state.register_index = index;
+                }
+                AstBuildMessage::Stats(stats) => {
+                    state.parse_stats = Some(stats);
+                }
+                AstBuildMessage::Progress { consumed, total } => {
+                    if state.ast_building {
+                        state.ast_progress = Some((consumed, total));
+                    }
+                }
+                AstBuildMessage::Cancelled => {
+                    state.ast_root = vec![AstNode::new(
+                        "Parse Cancelled",
+                        "Parsing was cancelled before the document was complete",
+                    )];
+                    state.ast_building = false;
+                    state.ast_progress = None;
+                    state.ast_cancel = None;
+                    state.pending_requests.clear();
+                    state.loaded_lazy_nodes.clear();
+                    state.push_event("received: parse cancelled".to_string());
+                }
                 AstBuildMessage::Error(e) => {
                     state.ast_root = vec![AstNode::new("Parse Error", e)];
                     state.ast_building = false;
+                    state.ast_progress = None;
                     state.pending_requests.clear();
                     state.loaded_lazy_nodes.clear();
                     state.push_event("received: parse error".to_string());
@@ -1625,15 +2025,82 @@ pub fn show_ui(state: &mut UiState, ctx: &egui::Context, _frame: &mut eframe::Fr
         }
     }
 
+    // Finish a download: open the file, or show the error in the URL window.
+    if let Some(download) = state.download.as_mut() {
+        match download.poll() {
+            // Files from a URL are untrusted input.
+            Some(Ok(bytes)) => state.load_bytes(bytes, ParseLimits::untrusted()),
+            Some(Err(err)) => {
+                let url = download.url.clone();
+                state.download = None;
+                state.open_url = Some(OpenUrlDialog {
+                    url,
+                    error: Some(err),
+                });
+            }
+            None => ctx.request_repaint_after(std::time::Duration::from_millis(50)),
+        }
+    }
+
+    // Pasting an http(s) URL outside a text field downloads and opens it.
+    if !ctx.wants_keyboard_input() {
+        let pasted = ctx.input(|i| {
+            i.events.iter().find_map(|event| match event {
+                egui::Event::Paste(text) if is_http_url(text) => Some(text.clone()),
+                _ => None,
+            })
+        });
+        if let Some(url) = pasted {
+            start_download(state, &url);
+        }
+    }
+
+    // Settings window; the hex viewer follows the settings on every frame.
+    let mut show_settings = state.show_settings;
+    show_settings_window(ctx, &mut show_settings, &mut state.settings);
+    state.show_settings = show_settings;
+    state.hex_viewer.set_font_size(state.settings.font_size);
+    state
+        .hex_viewer
+        .set_bytes_per_line(state.settings.bytes_per_line);
+    state.hex_viewer.set_show_ascii(state.settings.show_ascii);
+
+    // Bottom status bar: file size, parse time and memory of the parsed document.
+    egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            ui.label(format!("{} bytes", state.bytes.len()));
+            if let Some(stats) = state.parse_stats {
+                let total = stats.footprint.total();
+                ui.separator();
+                ui.label(format!("{} commands", stats.commands));
+                ui.separator();
+                ui.label(format!(
+                    "parsed in {:.1} ms",
+                    stats.parse_time.as_secs_f64() * 1000.0
+                ));
+                ui.separator();
+                ui.label(format!(
+                    "memory {:.1} MiB ({:.1}x file size)",
+                    total as f64 / (1024.0 * 1024.0),
+                    total as f64 / state.bytes.len().max(1) as f64
+                ))
+                .on_hover_text(format!(
+                    "commands: {} bytes\ndata: {} bytes\nmetadata: {} bytes",
+                    stats.footprint.commands, stats.footprint.data, stats.footprint.metadata
+                ));
+            }
+        });
+    });
+
     // Left sidebar AST
+    let panel_width = state.settings.ast_panel_width;
     egui::SidePanel::left("ast_panel")
         .resizable(false)
-        // Reduce default left panel width so the hex viewer on the right is more visible.
-        .default_width(240.0)
+        .default_width(panel_width)
         // Keep the left panel width fixed so clicking inside doesn't cause the separator
         // to jump when internal content briefly changes size.
-        .min_width(240.0)
-        .max_width(240.0)
+        .min_width(panel_width)
+        .max_width(panel_width)
         .show(ctx, |ui| {
             egui::ScrollArea::vertical()
                 .auto_shrink([false, false])
@@ -1674,13 +2141,42 @@ pub fn show_ui(state: &mut UiState, ctx: &egui::Context, _frame: &mut eframe::Fr
                         ctx.request_repaint();
                     }
 
-                    // Keyboard navigation for left-pane top-level selection (Up/Down only).
-                    if (input.key_pressed(egui::Key::ArrowUp)
-                        || input.key_pressed(egui::Key::ArrowDown))
-                        && total > 0
-                    {
+                    // Keyboard navigation below the top level: Up/Down step through the
+                    // leaf nodes (commands cross bucket boundaries, loading the next
+                    // bucket), Left returns to the top-level node, Right enters one.
+                    // The target label applies the selection when it receives focus.
+                    let up = input.key_pressed(egui::Key::ArrowUp);
+                    let down = input.key_pressed(egui::Key::ArrowDown);
+                    let current = keyboard_position(state).cloned();
+                    let mut top_level_target = None;
+                    match current {
+                        Some(path) if path.len() >= 2 => {
+                            if up || down {
+                                if let Some(target) = step_ast_path(state, &path, down) {
+                                    state.pending_focus = Some(target);
+                                    ctx.request_repaint();
+                                }
+                            } else if input.key_pressed(egui::Key::ArrowLeft) {
+                                top_level_target = Some(path[0]);
+                            }
+                        }
+                        Some(path) if input.key_pressed(egui::Key::ArrowRight) => {
+                            if let Some(target) = first_child_path(state, path[0]) {
+                                state.pending_focus = Some(target);
+                                ctx.request_repaint();
+                            }
+                        }
+                        _ => {}
+                    }
+
+                    // Keyboard navigation for left-pane top-level selection (Up/Down, or Left
+                    // from a nested node).
+                    let at_top_level = current_is_top_level(state);
+                    if ((up || down) && at_top_level || top_level_target.is_some()) && total > 0 {
                         let cur = state.selected_ast.as_ref().and_then(|p| p.first().copied());
-                        let new_idx = if input.key_pressed(egui::Key::ArrowUp) {
+                        let new_idx = if let Some(idx) = top_level_target {
+                            Some(idx)
+                        } else if up {
                             match cur {
                                 Some(0) => Some(0),
                                 Some(n) => Some(n.saturating_sub(1)),
@@ -1751,6 +2247,25 @@ pub fn show_ui(state: &mut UiState, ctx: &egui::Context, _frame: &mut eframe::Fr
             });
         });
 
+    // Far right: minimap of the whole file; clicking or dragging scrolls the hex viewer.
+    egui::SidePanel::right("minimap_panel")
+        .resizable(false)
+        .exact_width(24.0)
+        .show(ctx, |ui| {
+            let len = state.rebuilt_bytes.as_ref().unwrap_or(&state.bytes).len();
+            let minimap = Minimap {
+                len,
+                markers: &state.minimap_markers,
+                diffs: state.hex_viewer.diff_ranges(),
+                selection: state.hex_viewer.selection_range(),
+                visible: state.hex_viewer.visible_range(),
+            };
+            if let Some(offset) = minimap.show(ui) {
+                state.hex_viewer.set_pending_scroll_to(offset, offset);
+                ctx.request_repaint();
+            }
+        });
+
     // Right: hex viewer & toolbar
     egui::CentralPanel::default().show(ctx, |ui| {
         ui.vertical(|ui| {
@@ -1759,6 +2274,47 @@ pub fn show_ui(state: &mut UiState, ctx: &egui::Context, _frame: &mut eframe::Fr
                 if state.ast_building {
                     ui.add_space(12.0);
                     ui.colored_label(ui.visuals().selection.bg_fill, "Parsing...");
+                    // Percent of bytes consumed by the background parser.
+                    if let Some((consumed, total)) = state.ast_progress {
+                        let fraction = if total == 0 {
+                            0.0
+                        } else {
+                            consumed as f32 / total as f32
+                        };
+                        ui.add(
+                            egui::ProgressBar::new(fraction)
+                                .desired_width(160.0)
+                                .show_percentage(),
+                        );
+                    }
+                    if let Some(cancel) = state.ast_cancel.as_ref()
+                        && ui.button("Cancel").clicked()
+                    {
+                        cancel.cancel();
+                    }
+                    // Keep polling the worker so progress is shown without input events.
+                    ctx.request_repaint_after(std::time::Duration::from_millis(50));
+                }
+                if let Some(download) = state.download.as_ref() {
+                    ui.add_space(12.0);
+                    ui.colored_label(ui.visuals().selection.bg_fill, "Downloading...")
+                        .on_hover_text(&download.url);
+                    match download.fraction() {
+                        Some(fraction) => {
+                            ui.add(
+                                egui::ProgressBar::new(fraction)
+                                    .desired_width(160.0)
+                                    .show_percentage(),
+                            );
+                        }
+                        None => {
+                            ui.spinner();
+                            ui.label(format!("{} bytes", download.received));
+                        }
+                    }
+                    if ui.button("Cancel").clicked() {
+                        download.cancel();
+                    }
                 }
 
                 // Diff status indicator in the right-pane toolbar:
@@ -1874,6 +2430,18 @@ pub fn show_ui(state: &mut UiState, ctx: &egui::Context, _frame: &mut eframe::Fr
 
                         // Diff label
                         ui.label(diff_text);
+
+                        ui.add_space(gap_next_diff);
+                        if ui
+                            .add_enabled(state.download.is_none(), egui::Button::new("Open URL"))
+                            .on_hover_text("Download a .vgm/.vgz file; pasting a URL also opens it")
+                            .clicked()
+                        {
+                            state.open_url = Some(OpenUrlDialog::default());
+                        }
+                        if ui.button("Settings").clicked() {
+                            state.show_settings = !state.show_settings;
+                        }
                     });
                 }
             });
@@ -1964,6 +2532,14 @@ pub fn show_ui(state: &mut UiState, ctx: &egui::Context, _frame: &mut eframe::Fr
         }
     }
 
+    // Export requested from the hex viewer context menu, then the export window.
+    if let Some((start, end)) = state.hex_viewer.take_export_request() {
+        state.export_dialog = Some(ExportDialog::new(start, end));
+    }
+    show_export_dialog(state, ctx);
+    show_register_inspector(state, ctx);
+    show_open_url_dialog(state, ctx);
+
     // Drain deferred loads queued during drawing to avoid nested mutable borrows.
     if !state.deferred_loads.is_empty() {
         let mut to_process = Vec::new();
@@ -1976,3 +2552,139 @@ pub fn show_ui(state: &mut UiState, ctx: &egui::Context, _frame: &mut eframe::Fr
         }
     }
 }
+
+/// Draw the "export bytes" window and write the range when the user saves.
+///
+/// Bytes are taken from the buffer shown in the hex viewer (the rebuilt bytes
+/// when available), so the file matches what was selected.
+fn show_export_dialog(state: &mut UiState, ctx: &egui::Context) {
+    let Some(dialog) = state.export_dialog.as_mut() else {
+        return;
+    };
+    let bytes = state.rebuilt_bytes.as_ref().unwrap_or(&state.bytes);
+    let mut open = true;
+    let mut close = false;
+    egui::Window::new("Export bytes")
+        .collapsible(false)
+        .resizable(false)
+        .open(&mut open)
+        .show(ctx, |ui| {
+            ui.label(format!(
+                "Range: 0x{:08X}-0x{:08X} ({} bytes)",
+                dialog.start,
+                dialog.end,
+                dialog.end - dialog.start + 1
+            ));
+            ui.horizontal(|ui| {
+                ui.label("Path:");
+                ui.text_edit_singleline(&mut dialog.path);
+            });
+            if let Some(err) = &dialog.error {
+                ui.colored_label(ui.visuals().error_fg_color, err);
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    let result = match bytes.get(dialog.start..=dialog.end) {
+                        Some(range) => std::fs::write(dialog.path.trim(), range)
+                            .map_err(|e| format!("failed to write {}: {}", dialog.path, e)),
+                        None => Err("range is outside the file".to_string()),
+                    };
+                    match result {
+                        Ok(()) => close = true,
+                        Err(err) => dialog.error = Some(err),
+                    }
+                }
+                if ui.button("Cancel").clicked() {
+                    close = true;
+                }
+            });
+        });
+    if !open || close {
+        state.export_dialog = None;
+    }
+}
+
+/// Start downloading `url`, replacing the document once it has arrived. A
+/// download already running is left alone.
+fn start_download(state: &mut UiState, url: &str) {
+    if state.download.is_some() {
+        return;
+    }
+    match Download::start(url) {
+        Ok(download) => {
+            state.download = Some(download);
+            state.open_url = None;
+        }
+        Err(err) => {
+            state.open_url = Some(OpenUrlDialog {
+                url: url.trim().to_string(),
+                error: Some(err),
+            });
+        }
+    }
+}
+
+/// Draw the "Open URL" window: an http(s) URL of a .vgm/.vgz file to
+/// download and open.
+fn show_open_url_dialog(state: &mut UiState, ctx: &egui::Context) {
+    let Some(dialog) = state.open_url.as_mut() else {
+        return;
+    };
+    let mut open = true;
+    let mut close = false;
+    let mut submit = None;
+    egui::Window::new("Open URL")
+        .collapsible(false)
+        .resizable(false)
+        .open(&mut open)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("URL:");
+                let edit = ui.add(
+                    egui::TextEdit::singleline(&mut dialog.url)
+                        .hint_text("https://example.com/song.vgz")
+                        .desired_width(360.0),
+                );
+                if edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                    submit = Some(dialog.url.clone());
+                }
+            });
+            if let Some(err) = &dialog.error {
+                ui.colored_label(ui.visuals().error_fg_color, err);
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Open").clicked() {
+                    submit = Some(dialog.url.clone());
+                }
+                if ui.button("Cancel").clicked() {
+                    close = true;
+                }
+            });
+        });
+    if !open || close {
+        state.open_url = None;
+    } else if let Some(url) = submit {
+        start_download(state, &url);
+    }
+}
+
+/// Draw the register inspector window: the programmer's view of the chip
+/// written by the inspected command, right after that write.
+fn show_register_inspector(state: &mut UiState, ctx: &egui::Context) {
+    let Some(inspector) = state.register_inspector.as_ref() else {
+        return;
+    };
+    let mut open = true;
+    egui::Window::new(format!("Registers after command #{}", inspector.command))
+        .id(egui::Id::new("register_inspector"))
+        .open(&mut open)
+        .default_size(egui::vec2(560.0, 420.0))
+        .show(ctx, |ui| {
+            egui::ScrollArea::both().show(ui, |ui| {
+                ui.label(egui::RichText::new(&inspector.text).monospace());
+            });
+        });
+    if !open {
+        state.register_inspector = None;
+    }
+}
@@ -13,6 +13,10 @@
 //! The API favors passing `fmt::Arguments` (via `format_args!`) so that formatting work
 //! is deferred until `write_fmt` is actually invoked. When the logger is Noop, `log`
 //! returns immediately without calling `write_fmt`, avoiding allocation/format work.
+//!
+//! `Logger` carries the normal output of a command. Diagnostics (errors, warnings,
+//! summaries and debug traces) go through the `log` macros instead, which
+//! `init_diagnostics` routes to stderr at the level picked by `-q`/`-v`/`-vv`.
 
 #![allow(dead_code)]
 
@@ -243,6 +247,41 @@ macro_rules! log_debug {
     };
 }
 
+/// Diagnostics level for the `-q` flag and the number of `-v` flags: errors
+/// only, info (summaries and warnings) by default, debug for `-v` and trace
+/// for `-vv`.
+pub fn verbosity_level(quiet: bool, verbose: u8) -> log::LevelFilter {
+    match (quiet, verbose) {
+        (true, _) => log::LevelFilter::Error,
+        (false, 0) => log::LevelFilter::Info,
+        (false, 1) => log::LevelFilter::Debug,
+        (false, _) => log::LevelFilter::Trace,
+    }
+}
+
+/// Print the `log` macros to stderr up to `level`. `RUST_LOG` overrides the
+/// level, e.g. `RUST_LOG=soundlog_debugger::cui::redump=trace`.
+///
+/// Info records are printed as is so summaries read like plain messages; the
+/// other levels get a `error:`/`warning:`/`debug:`/`trace:` prefix.
+pub fn init_diagnostics(level: log::LevelFilter) {
+    let _ = env_logger::Builder::new()
+        .filter_level(level)
+        .parse_default_env()
+        .format(|buf, record| {
+            let prefix = match record.level() {
+                log::Level::Error => "error: ",
+                log::Level::Warn => "warning: ",
+                log::Level::Info => "",
+                log::Level::Debug => "debug: ",
+                log::Level::Trace => "trace: ",
+            };
+            writeln!(buf, "{}{}", prefix, record.args())
+        })
+        .target(env_logger::Target::Stderr)
+        .try_init();
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -340,4 +379,12 @@ mod tests {
         let res = logger.info(format_args!("hi"));
         assert!(res.is_err());
     }
+
+    #[test]
+    fn test_verbosity_level() {
+        assert_eq!(verbosity_level(true, 2), log::LevelFilter::Error);
+        assert_eq!(verbosity_level(false, 0), log::LevelFilter::Info);
+        assert_eq!(verbosity_level(false, 1), log::LevelFilter::Debug);
+        assert_eq!(verbosity_level(false, 3), log::LevelFilter::Trace);
+    }
 }
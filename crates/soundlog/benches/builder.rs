@@ -0,0 +1,71 @@
+// chipstream/crates/soundlog/benches/builder.rs
+//
+// Time building a document of one million commands with `add_vgm_command`,
+// with `add_vgm_commands` and with `add_vgm_commands` on a builder created
+// by `with_capacity`, then finalizing it. Uses only the standard library:
+//
+//     cargo bench -p soundlog --bench builder
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use soundlog::VgmBuilder;
+use soundlog::chip::Ym2612Spec;
+use soundlog::vgm::command::{Instance, VgmCommand, WaitSamples};
+
+const COMMANDS: usize = 1_000_000;
+const RUNS: usize = 5;
+
+// Alternating YM2612 writes and short waits, like a converted log.
+fn commands() -> impl Iterator<Item = VgmCommand> {
+    (0..COMMANDS).map(|i| {
+        if i % 2 == 0 {
+            VgmCommand::from((
+                Instance::Primary,
+                Ym2612Spec {
+                    port: 0,
+                    register: 0x2A,
+                    value: i as u8,
+                },
+            ))
+        } else {
+            WaitSamples(1).into()
+        }
+    })
+}
+
+// Fastest of `RUNS` runs.
+fn measure(name: &str, mut build: impl FnMut() -> VgmBuilder) {
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let document = build().finalize();
+        best = best.min(start.elapsed());
+        black_box(document);
+    }
+    println!(
+        "{:<32} {:>8.2} ms ({:.1} ns/command)",
+        name,
+        best.as_secs_f64() * 1000.0,
+        best.as_secs_f64() * 1e9 / COMMANDS as f64
+    );
+}
+
+fn main() {
+    measure("add_vgm_command", || {
+        let mut builder = VgmBuilder::new();
+        for command in commands() {
+            builder.add_vgm_command(command);
+        }
+        builder
+    });
+    measure("add_vgm_commands", || {
+        let mut builder = VgmBuilder::new();
+        builder.add_vgm_commands(commands());
+        builder
+    });
+    measure("with_capacity + add_vgm_commands", || {
+        let mut builder = VgmBuilder::with_capacity(COMMANDS + 1);
+        builder.add_vgm_commands(commands());
+        builder
+    });
+}
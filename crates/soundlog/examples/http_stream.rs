@@ -0,0 +1,366 @@
+// chipstream/crates/soundlog/examples/http_stream.rs
+//
+// End-to-end network streaming with `VgmStream::push_chunk`, using only the
+// standard library.
+//
+// Three parties talk HTTP/1.1 over loopback:
+//
+// - a source server that sends a VGM file with `Transfer-Encoding: chunked`,
+//   the way a radio-style (ICY) server trickles a stream,
+// - a relay server that fetches the VGM from the source, feeds the body to a
+//   `VgmStream` chunk by chunk as it arrives and streams the expanded commands
+//   (including writes generated by DAC streams) back as newline-delimited
+//   JSON, one object per command with its absolute sample position,
+// - a client that reads the JSON lines from the relay.
+//
+// Run with an optional VGM file (uncompressed); a small generated song is
+// used otherwise:
+//
+//     cargo run -p soundlog --example http_stream -- [file.vgm]
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+
+use soundlog::vgm::command::{VgmCommand, WaitSamples};
+use soundlog::vgm::header::VgmHeader;
+use soundlog::vgm::stream::{StreamResult, VgmStream};
+use soundlog::{VgmBuilder, VgmDocument};
+
+// Size of the chunks the source server sends, small enough that commands
+// and data blocks regularly straddle chunk boundaries.
+const SOURCE_CHUNK_SIZE: usize = 256;
+
+fn main() -> io::Result<()> {
+    let vgm = match std::env::args().nth(1) {
+        Some(path) => std::fs::read(path)?,
+        None => (&demo_document()).into(),
+    };
+
+    let source = spawn_server(move |stream| serve_vgm(stream, &vgm))?;
+    let relay = spawn_server(move |stream| serve_ndjson(stream, source))?;
+
+    for line in fetch_lines(relay)? {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+// Accept connections on an ephemeral loopback port, one thread each.
+fn spawn_server<F>(handler: F) -> io::Result<SocketAddr>
+where
+    F: Fn(TcpStream) -> io::Result<()> + Clone + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let handler = handler.clone();
+            thread::spawn(move || {
+                if let Err(e) = handler(stream) {
+                    eprintln!("connection error: {}", e);
+                }
+            });
+        }
+    });
+    Ok(addr)
+}
+
+// Source: the VGM file as a chunked HTTP body.
+fn serve_vgm(stream: TcpStream, vgm: &[u8]) -> io::Result<()> {
+    let mut writer = respond(stream, "audio/x-vgm")?;
+    for chunk in vgm.chunks(SOURCE_CHUNK_SIZE) {
+        write_chunk(&mut writer, chunk)?;
+    }
+    finish_chunks(&mut writer)
+}
+
+// Relay: fetch the VGM from `source` and stream the expanded commands.
+fn serve_ndjson(stream: TcpStream, source: SocketAddr) -> io::Result<()> {
+    let mut writer = respond(stream, "application/x-ndjson")?;
+    let mut body = get(source, "/song.vgm")?;
+
+    let mut parser = VgmStream::new();
+    parser.set_loop_count(Some(1));
+    let mut header = Vec::new();
+    let mut buf = [0u8; 1024];
+    'feed: loop {
+        let n = body.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        // The parser only takes command bytes: hold back the header.
+        let commands = match command_start(&header) {
+            Some(_) => &buf[..n],
+            None => {
+                header.extend_from_slice(&buf[..n]);
+                match command_start(&header) {
+                    Some(start) if header.len() >= start => &header[start..],
+                    _ => continue,
+                }
+            }
+        };
+        parser.push_chunk(commands).map_err(io::Error::other)?;
+
+        let mut lines = String::new();
+        for item in parser.timed() {
+            match item.map_err(io::Error::other)? {
+                (_, StreamResult::Command(VgmCommand::WaitSamples(_))) => {}
+                (sample, StreamResult::Command(command)) => {
+                    lines.push_str(&command_json(sample, &command));
+                    lines.push('\n');
+                }
+                (_, StreamResult::NeedsMoreData) => break,
+                (_, StreamResult::EndOfStream) => {
+                    write_chunk(&mut writer, lines.as_bytes())?;
+                    break 'feed;
+                }
+            }
+        }
+        write_chunk(&mut writer, lines.as_bytes())?;
+    }
+    finish_chunks(&mut writer)
+}
+
+// Client: the JSON lines served by the relay.
+fn fetch_lines(relay: SocketAddr) -> io::Result<Vec<String>> {
+    BufReader::new(get(relay, "/commands")?).lines().collect()
+}
+
+// Offset of the first command once enough of the header has arrived.
+fn command_start(header: &[u8]) -> Option<usize> {
+    let field = |offset: usize| {
+        let bytes = header.get(offset..offset + 4)?;
+        Some(u32::from_le_bytes(bytes.try_into().ok()?))
+    };
+    Some(VgmHeader::command_start(field(0x08)?, field(0x34)?))
+}
+
+fn command_json(sample: u64, command: &VgmCommand) -> String {
+    match command.register_write() {
+        Some(write) => format!(
+            r#"{{"sample":{},"chip":"{:?}","instance":{},"port":{},"register":{},"value":{}}}"#,
+            sample,
+            write.chip,
+            usize::from(write.instance),
+            write.port,
+            write.register,
+            write.value
+        ),
+        None => {
+            // The variant name of the command, e.g. `DataBlock`.
+            let debug = format!("{:?}", command);
+            let name = debug.split(['(', ' ', '{']).next().unwrap_or_default();
+            format!(r#"{{"sample":{},"command":"{}"}}"#, sample, name)
+        }
+    }
+}
+
+// HTTP plumbing.
+
+fn respond(stream: TcpStream, content_type: &str) -> io::Result<TcpStream> {
+    // Read and ignore the request head.
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n",
+        content_type
+    )?;
+    Ok(stream)
+}
+
+fn write_chunk(writer: &mut impl Write, data: &[u8]) -> io::Result<()> {
+    if data.is_empty() {
+        return Ok(());
+    }
+    write!(writer, "{:X}\r\n", data.len())?;
+    writer.write_all(data)?;
+    writer.write_all(b"\r\n")
+}
+
+fn finish_chunks(writer: &mut impl Write) -> io::Result<()> {
+    writer.write_all(b"0\r\n\r\n")?;
+    writer.flush()
+}
+
+// GET `path` and return the response body. Accepts `HTTP/1.x` and
+// SHOUTcast-style `ICY` status lines, chunked or plain bodies.
+fn get(addr: SocketAddr, path: &str) -> io::Result<Box<dyn Read + Send>> {
+    let mut stream = TcpStream::connect(addr)?;
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, addr
+    )?;
+    let mut reader = BufReader::new(stream);
+
+    let mut status = String::new();
+    reader.read_line(&mut status)?;
+    let ok = status.split_whitespace().nth(1) == Some("200");
+    if !(status.starts_with("HTTP/1.") || status.starts_with("ICY")) || !ok {
+        return Err(io::Error::other(format!(
+            "unexpected status: {}",
+            status.trim_end()
+        )));
+    }
+    let mut chunked = false;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            chunked |= name.eq_ignore_ascii_case("transfer-encoding")
+                && value.trim().eq_ignore_ascii_case("chunked");
+        }
+    }
+    Ok(if chunked {
+        Box::new(ChunkedReader {
+            inner: reader,
+            remaining: 0,
+            done: false,
+        })
+    } else {
+        Box::new(reader)
+    })
+}
+
+// Decoder of a `Transfer-Encoding: chunked` body.
+struct ChunkedReader<R> {
+    inner: R,
+    remaining: usize,
+    done: bool,
+}
+
+impl<R: BufRead> Read for ChunkedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 && !self.done {
+            let mut line = String::new();
+            self.inner.read_line(&mut line)?;
+            let size = line.trim_end().split(';').next().unwrap_or_default();
+            self.remaining = usize::from_str_radix(size, 16)
+                .map_err(|_| io::Error::other(format!("bad chunk size: {:?}", line)))?;
+            if self.remaining == 0 {
+                // Last chunk: skip the (empty) trailer.
+                self.done = true;
+                self.inner.read_line(&mut line)?;
+            }
+        }
+        if self.done {
+            return Ok(0);
+        }
+        let len = buf.len().min(self.remaining);
+        let n = self.inner.read(&mut buf[..len])?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.remaining -= n;
+        if self.remaining == 0 {
+            let mut crlf = [0u8; 2];
+            self.inner.read_exact(&mut crlf)?;
+        }
+        Ok(n)
+    }
+}
+
+// A short YM2612 song: an FM key-on followed by a DAC stream.
+fn demo_document() -> VgmDocument {
+    use soundlog::chip::{Chip, Ym2612Spec};
+    use soundlog::vgm::command::{
+        DacStreamChipType, Instance, LengthMode, SetStreamData, SetStreamFrequency,
+        SetupStreamControl, StartStream,
+    };
+    use soundlog::vgm::detail::{StreamChipType, UncompressedStream};
+    use soundlog::vgm::header::ChipId;
+
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+    builder.attach_data_block(UncompressedStream {
+        chip_type: StreamChipType::Ym2612Pcm,
+        data: (0..600).map(|i| (i % 256) as u8).collect(),
+    });
+    builder.add_chip_write(
+        Instance::Primary,
+        Ym2612Spec {
+            port: 0,
+            register: 0x28,
+            value: 0xF0,
+        },
+    );
+    builder.add_vgm_command(WaitSamples(100));
+    builder.add_vgm_command(SetupStreamControl {
+        stream_id: 0,
+        chip_type: DacStreamChipType {
+            chip_id: ChipId::Ym2612,
+            instance: Instance::Primary,
+        },
+        write_port: 0,
+        write_command: 0x2A,
+    });
+    builder.add_vgm_command(SetStreamData {
+        stream_id: 0,
+        data_bank_id: 0x00,
+        step_size: 1,
+        step_base: 0,
+    });
+    builder.add_vgm_command(SetStreamFrequency {
+        stream_id: 0,
+        frequency: 22_050,
+    });
+    builder.add_vgm_command(StartStream {
+        stream_id: 0,
+        data_start_offset: 0,
+        length_mode: LengthMode::PlayUntilEnd {
+            reverse: false,
+            looped: false,
+        },
+        data_length: 0,
+    });
+    builder.add_vgm_command(WaitSamples(1200));
+    builder.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relays_expanded_commands_over_http() {
+        let vgm: Vec<u8> = (&demo_document()).into();
+        let source = spawn_server(move |stream| serve_vgm(stream, &vgm)).unwrap();
+        let relay = spawn_server(move |stream| serve_ndjson(stream, source)).unwrap();
+
+        let lines = fetch_lines(relay).unwrap();
+
+        assert_eq!(
+            lines[0],
+            r#"{"sample":0,"chip":"Ym2612","instance":0,"port":0,"register":40,"value":240}"#
+        );
+        // 600 DAC bytes at half the sample rate, starting at sample 100
+        assert_eq!(lines.len(), 601);
+        assert_eq!(
+            lines[2],
+            r#"{"sample":102,"chip":"Ym2612","instance":0,"port":0,"register":42,"value":1}"#
+        );
+        assert!(lines[600].starts_with(r#"{"sample":1298,"#));
+    }
+
+    #[test]
+    fn decodes_chunked_bodies() {
+        let body = b"4\r\nabcd\r\n2;ext=1\r\nef\r\n0\r\n\r\n";
+        let mut reader = ChunkedReader {
+            inner: &body[..],
+            remaining: 0,
+            done: false,
+        };
+        let mut decoded = String::new();
+        reader.read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, "abcdef");
+    }
+}
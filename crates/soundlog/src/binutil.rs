@@ -63,6 +63,64 @@ pub enum ParseError {
         limit: usize,
         attempted_size: usize,
     },
+
+    /// Parsing was cancelled through the `CancelToken` passed to
+    /// `VgmDocument::parse_with_hooks`.
+    ///
+    /// `offset` is the number of bytes consumed when parsing stopped.
+    Cancelled { offset: usize },
+
+    /// Offset arithmetic would overflow or underflow.
+    ///
+    /// - `offset` is the position the computation started from.
+    /// - `delta` is the value added to or subtracted from it.
+    /// - `context` names the offset being computed (for example
+    ///   `"gd3_offset"`).
+    OffsetOverflow {
+        offset: usize,
+        delta: usize,
+        context: Option<String>,
+    },
+
+    /// The input exceeded a limit of `vgm::parser::ParseLimits`.
+    ///
+    /// - `limit` is the limit that was exceeded.
+    /// - `value` is the size or count found in the input.
+    /// - `max` is the configured maximum.
+    LimitExceeded {
+        limit: ResourceLimit,
+        value: usize,
+        max: usize,
+    },
+}
+
+/// A limit of `vgm::parser::ParseLimits`, reported by
+/// `ParseError::LimitExceeded`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResourceLimit {
+    /// Length of the input in bytes.
+    FileSize,
+    /// Payload size of one data block.
+    DataBlockSize,
+    /// Payload size of all data blocks together.
+    TotalDataBlockSize,
+    /// Number of commands.
+    CommandCount,
+    /// Uncompressed size declared by a compressed data block.
+    DecompressedSize,
+}
+
+impl fmt::Display for ResourceLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ResourceLimit::FileSize => "file size",
+            ResourceLimit::DataBlockSize => "data block size",
+            ResourceLimit::TotalDataBlockSize => "total data block size",
+            ResourceLimit::CommandCount => "command count",
+            ResourceLimit::DecompressedSize => "decompressed size",
+        };
+        f.write_str(name)
+    }
 }
 
 impl fmt::Display for ParseError {
@@ -110,19 +168,122 @@ impl fmt::Display for ParseError {
                 "data block size limit exceeded: current {} bytes, limit {} bytes, attempted to add {} bytes",
                 current_size, limit, attempted_size
             ),
+            ParseError::Cancelled { offset } => {
+                write!(f, "parsing cancelled at offset 0x{:X}", offset)
+            }
+            ParseError::OffsetOverflow {
+                offset,
+                delta,
+                context,
+            } => {
+                if let Some(ctx) = context {
+                    write!(
+                        f,
+                        "offset overflow at {}: 0x{:X} with delta 0x{:X}",
+                        ctx, offset, delta
+                    )
+                } else {
+                    write!(
+                        f,
+                        "offset overflow: 0x{:X} with delta 0x{:X}",
+                        offset, delta
+                    )
+                }
+            }
+            ParseError::LimitExceeded { limit, value, max } => {
+                write!(f, "{} limit exceeded: {} (max {})", limit, value, max)
+            }
         }
     }
 }
 
 impl std::error::Error for ParseError {}
 
+/// Absolute byte position within a serialized file.
+///
+/// VGM headers store most offsets relative to the position of the field that
+/// holds them (`gd3_offset` is relative to `0x14`, `extra_header_offset` to
+/// `0xBC`, and so on). `FileOffset` converts between the two forms with
+/// checked arithmetic, so a stored value close to `u32::MAX` is reported
+/// instead of wrapping around to a small position inside the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct FileOffset(usize);
+
+impl FileOffset {
+    /// Wrap an absolute position.
+    pub const fn new(offset: usize) -> Self {
+        FileOffset(offset)
+    }
+
+    /// The absolute position.
+    pub const fn get(self) -> usize {
+        self.0
+    }
+
+    /// Resolve `value` stored in the field at `field`, i.e. `field + value`.
+    ///
+    /// Returns `None` when the position does not fit in `usize`.
+    pub fn from_relative(field: usize, value: u32) -> Option<Self> {
+        field.checked_add(value as usize).map(FileOffset)
+    }
+
+    /// The value to store in the field at `field` to point at this position,
+    /// i.e. `self - field`.
+    ///
+    /// Returns `None` when the position is before the field or the distance
+    /// does not fit in `u32`.
+    pub fn to_relative(self, field: usize) -> Option<u32> {
+        u32::try_from(self.0.checked_sub(field)?).ok()
+    }
+
+    /// The position `len` bytes further, `None` on overflow.
+    pub fn checked_add(self, len: usize) -> Option<Self> {
+        self.0.checked_add(len).map(FileOffset)
+    }
+
+    /// Distance from `base` to this position, `None` when `base` is after it.
+    pub fn offset_from(self, base: FileOffset) -> Option<usize> {
+        self.0.checked_sub(base.0)
+    }
+}
+
+impl From<usize> for FileOffset {
+    fn from(offset: usize) -> Self {
+        FileOffset(offset)
+    }
+}
+
+impl From<FileOffset> for usize {
+    fn from(offset: FileOffset) -> Self {
+        offset.0
+    }
+}
+
+impl fmt::Display for FileOffset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:X}", self.0)
+    }
+}
+
+/// Resolve the relative offset `value` stored in the field at `field`.
+///
+/// Returns `Err(ParseError::OffsetOverflow)` naming `context` when the
+/// position does not fit in `usize`.
+pub fn resolve_relative(field: usize, value: u32, context: &str) -> Result<FileOffset, ParseError> {
+    FileOffset::from_relative(field, value).ok_or_else(|| ParseError::OffsetOverflow {
+        offset: field,
+        delta: value as usize,
+        context: Some(context.into()),
+    })
+}
+
 /// Read a 32-bit little-endian unsigned integer from `bytes` at `off`.
 ///
 /// Returns `Ok(u32)` when the four bytes starting at `off` are available and
 /// were successfully interpreted as a little-endian `u32`. Returns
 /// `Err(ParseError::OffsetOutOfRange)` when the buffer is too short.
 pub fn read_u32_le_at(bytes: &[u8], off: usize) -> Result<u32, ParseError> {
-    if bytes.len() < off + 4 {
+    if bytes.len() < off.saturating_add(4) {
         return Err(ParseError::OffsetOutOfRange {
             offset: off,
             needed: 4,
@@ -141,7 +302,7 @@ pub fn read_u32_le_at(bytes: &[u8], off: usize) -> Result<u32, ParseError> {
 /// were successfully interpreted as a little-endian `u16`. Returns
 /// `Err(ParseError::OffsetOutOfRange)` when the buffer is too short.
 pub fn read_u16_le_at(bytes: &[u8], off: usize) -> Result<u16, ParseError> {
-    if bytes.len() < off + 2 {
+    if bytes.len() < off.saturating_add(2) {
         return Err(ParseError::OffsetOutOfRange {
             offset: off,
             needed: 2,
@@ -176,7 +337,7 @@ pub fn read_u8_at(bytes: &[u8], off: usize) -> Result<u8, ParseError> {
 /// range is within bounds. Returns `Err(ParseError::OffsetOutOfRange)` when the
 /// requested range exceeds the available buffer.
 pub fn read_slice(bytes: &[u8], off: usize, len: usize) -> Result<&[u8], ParseError> {
-    if bytes.len() < off + len {
+    if bytes.len() < off.saturating_add(len) {
         return Err(ParseError::OffsetOutOfRange {
             offset: off,
             needed: len,
@@ -194,7 +355,7 @@ pub fn read_slice(bytes: &[u8], off: usize, len: usize) -> Result<&[u8], ParseEr
 /// `off+1` and `off+2` in big-endian order; if they are not available the
 /// function returns `Err(ParseError::OffsetOutOfRange)`.
 pub fn read_u24_be_at(bytes: &[u8], off: usize) -> Result<u32, ParseError> {
-    if bytes.len() < off + 3 {
+    if bytes.len() < off.saturating_add(3) {
         return Err(ParseError::OffsetOutOfRange {
             offset: off,
             needed: 3,
@@ -276,6 +437,32 @@ mod tests {
             "header too short: VGM header"
         );
         assert_eq!(format!("{}", ParseError::Other("boom".into())), "boom");
+        assert_eq!(
+            format!(
+                "{}",
+                ParseError::OffsetOverflow {
+                    offset: 0x14,
+                    delta: 0xFFFF_FFF0,
+                    context: Some("gd3_offset".into())
+                }
+            ),
+            "offset overflow at gd3_offset: 0x14 with delta 0xFFFFFFF0"
+        );
+        assert_eq!(
+            format!("{}", ParseError::Cancelled { offset: 0x40 }),
+            "parsing cancelled at offset 0x40"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                ParseError::LimitExceeded {
+                    limit: ResourceLimit::CommandCount,
+                    value: 11,
+                    max: 10
+                }
+            ),
+            "command count limit exceeded: 11 (max 10)"
+        );
         assert_eq!(
             format!(
                 "{}",
@@ -386,6 +573,23 @@ mod tests {
         assert_eq!(read_i32_le_at(&buf3, 0).unwrap(), 2_147_483_647);
     }
 
+    #[test]
+    fn file_offset_checked_math() {
+        let gd3 = FileOffset::from_relative(0x14, 0x100).unwrap();
+        assert_eq!(gd3.get(), 0x114);
+        assert_eq!(gd3.to_relative(0x14), Some(0x100));
+        assert_eq!(FileOffset::new(0x10).to_relative(0x14), None);
+        assert_eq!(FileOffset::new(usize::MAX).checked_add(1), None);
+        assert_eq!(gd3.offset_from(FileOffset::new(0x100)), Some(0x14));
+        assert_eq!(FileOffset::new(0x100).offset_from(gd3), None);
+        assert!(resolve_relative(usize::MAX, 1, "test").is_err());
+
+        // Reads past the end of the address space fail instead of panicking.
+        let buf = [0u8; 4];
+        assert!(read_u32_le_at(&buf, usize::MAX - 1).is_err());
+        assert!(read_slice(&buf, usize::MAX, 2).is_err());
+    }
+
     #[test]
     fn write_and_slice() {
         let mut buf = [0u8; 8];
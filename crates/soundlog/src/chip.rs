@@ -4,6 +4,8 @@
 //! such as frequency-number conversions in the `fnumber` submodule.
 pub mod event;
 pub mod fnumber;
+pub mod patch;
+pub mod regmap;
 mod spec;
 pub mod state;
 
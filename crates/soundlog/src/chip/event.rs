@@ -114,6 +114,67 @@ impl ToneInfo {
     }
 }
 
+/// Percussion instrument of a `StateEvent::RhythmHit`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RhythmInstrument {
+    /// Bass drum
+    BassDrum,
+    /// Snare drum
+    SnareDrum,
+    /// Top cymbal
+    TopCymbal,
+    /// Hi-hat
+    HiHat,
+    /// Tom-tom
+    TomTom,
+    /// Rim shot
+    RimShot,
+    /// ADPCM-A sample played from ROM (YM2610), which has no fixed
+    /// instrument; identified by its start address in bytes
+    Sample {
+        /// ROM address of the first sample byte
+        start: u32,
+    },
+}
+
+impl RhythmInstrument {
+    /// Lower-case name of the instrument, e.g. `bass_drum`
+    pub fn name(&self) -> &'static str {
+        match self {
+            RhythmInstrument::BassDrum => "bass_drum",
+            RhythmInstrument::SnareDrum => "snare_drum",
+            RhythmInstrument::TopCymbal => "top_cymbal",
+            RhythmInstrument::HiHat => "hi_hat",
+            RhythmInstrument::TomTom => "tom_tom",
+            RhythmInstrument::RimShot => "rim_shot",
+            RhythmInstrument::Sample { .. } => "sample",
+        }
+    }
+
+    /// General MIDI percussion key (channel 10) for the instrument, None for
+    /// ROM samples
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use soundlog::chip::event::RhythmInstrument;
+    ///
+    /// assert_eq!(RhythmInstrument::BassDrum.gm_note(), Some(36));
+    /// assert_eq!(RhythmInstrument::Sample { start: 0 }.gm_note(), None);
+    /// ```
+    pub fn gm_note(&self) -> Option<u8> {
+        match self {
+            RhythmInstrument::BassDrum => Some(36),
+            RhythmInstrument::SnareDrum => Some(38),
+            RhythmInstrument::TopCymbal => Some(49),
+            RhythmInstrument::HiHat => Some(42),
+            RhythmInstrument::TomTom => Some(45),
+            RhythmInstrument::RimShot => Some(37),
+            RhythmInstrument::Sample { .. } => None,
+        }
+    }
+}
+
 /// Events that can be emitted from state tracking
 ///
 /// These events are generated when notable state changes occur,
@@ -150,6 +211,33 @@ pub enum StateEvent {
         /// New tone information
         tone: ToneInfo,
     },
+
+    /// Sample playback started
+    ///
+    /// Emitted by PCM chips whose sample addressing is decoded (currently
+    /// the OKIM6295 with its ROM loaded) when a channel starts a sample.
+    SampleStart {
+        /// Channel number that started playing
+        channel: u8,
+        /// Offset of the first sample byte in the ROM
+        offset: u32,
+        /// Length of the sample in bytes
+        length: u32,
+    },
+
+    /// Percussion hit
+    ///
+    /// Emitted by the rhythm sections (YM2608 rhythm, YM2610 ADPCM-A, OPL
+    /// rhythm mode) for every instrument keyed on. Hits are one-shots, so no
+    /// `KeyOff` follows.
+    RhythmHit {
+        /// Rhythm channel number, counted separately from the tone channels
+        channel: u8,
+        /// Instrument that was hit
+        instrument: RhythmInstrument,
+        /// Attenuation in 0.75 dB steps at the time of the hit, 0 is loudest
+        attenuation: u8,
+    },
 }
 
 #[cfg(test)]
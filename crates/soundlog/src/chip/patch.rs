@@ -0,0 +1,410 @@
+//! OPN (YM2612 family) FM patches and instrument files.
+//!
+//! `FmPatch` holds the registers that make up the sound of one FM channel:
+//! algorithm, feedback, LFO sensitivity and the four operators. Patches are
+//! read from chip registers (`FmPatch::from_registers`, or from a voice of a
+//! `vgm::usage` report with `FmPatch::from_voice`) and exchanged with other
+//! Mega Drive tools through two file formats:
+//!
+//! - VGI, the single instrument format of VGM Music Maker (`FmPatch::from_vgi`
+//!   / `to_vgi`), 43 bytes: algorithm, feedback, `AMS << 4 | FMS`, then per
+//!   operator in register order (S1, S3, S2, S4) multiple, detune, total
+//!   level, rate scaling, attack rate, `AM << 7 | decay rate`, sustain rate,
+//!   release rate, sustain level and SSG-EG.
+//! - GYB, the instrument bank format of GYBEdit (`GybBank`), versions 1 and
+//!   2: a `1A 0C` signature, the version, melody and drum instrument counts,
+//!   the General MIDI map (128 pairs of melody and drum instrument, `FF` for
+//!   none), the LFO register (version 2 only), the instruments and their names
+//!   as length-prefixed strings. An instrument is registers `0x30` to `0x90`
+//!   for the operators in register order, `0xB0`, `0xB4`, a transpose in
+//!   semitones and, in version 2, a padding byte. `GybBank::to_bytes` writes
+//!   version 2.
+//!
+//! Bits a register does not use are dropped; the output enable bits of
+//! `0xB4` are not part of a patch and are written as both on.
+//!
+//! ```rust
+//! use soundlog::chip::patch::{FmPatch, GybBank, GybInstrument};
+//!
+//! let patch = FmPatch::default();
+//! let vgi = patch.to_vgi();
+//! assert_eq!(vgi.len(), 43);
+//! assert_eq!(FmPatch::from_vgi(&vgi).unwrap(), patch);
+//!
+//! let mut bank = GybBank::default();
+//! bank.melody.push(GybInstrument {
+//!     name: "Plain".to_string(),
+//!     patch,
+//!     transpose: 0,
+//! });
+//! assert_eq!(GybBank::from_bytes(&bank.to_bytes()).unwrap(), bank);
+//! ```
+use crate::binutil::{ParseError, read_slice, read_u8_at};
+use crate::chip::Chip;
+use crate::chip::regmap::Registers;
+use crate::vgm::usage::Voice;
+
+/// Register values of one YM2612 operator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FmOperator {
+    /// Detune and multiple (`0x30`).
+    pub dt_mul: u8,
+    /// Total level (`0x40`), 0 is loudest.
+    pub tl: u8,
+    /// Key scale and attack rate (`0x50`).
+    pub ks_ar: u8,
+    /// AM enable and decay rate (`0x60`).
+    pub am_dr: u8,
+    /// Sustain rate (`0x70`).
+    pub sr: u8,
+    /// Sustain level and release rate (`0x80`).
+    pub sl_rr: u8,
+    /// SSG-EG (`0x90`).
+    pub ssg_eg: u8,
+}
+
+/// A YM2612 voice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FmPatch {
+    /// Algorithm, 0-7.
+    pub algorithm: u8,
+    /// Operator 1 feedback, 0-7.
+    pub feedback: u8,
+    /// Amplitude modulation sensitivity, 0-3.
+    pub ams: u8,
+    /// Frequency modulation sensitivity, 0-7.
+    pub fms: u8,
+    /// Operators in slot order S1, S2, S3, S4.
+    pub operators: [FmOperator; 4],
+}
+
+impl Default for FmPatch {
+    /// A plain tone: algorithm 7 with only S4 audible.
+    fn default() -> Self {
+        let silent = FmOperator {
+            tl: 0x7F,
+            ..FmOperator::default()
+        };
+        FmPatch {
+            algorithm: 7,
+            feedback: 0,
+            ams: 0,
+            fms: 0,
+            operators: [
+                silent,
+                silent,
+                silent,
+                FmOperator {
+                    dt_mul: 0x01,
+                    tl: 0x00,
+                    ks_ar: 0x1F,
+                    am_dr: 0x05,
+                    sr: 0x02,
+                    sl_rr: 0x27,
+                    ssg_eg: 0x00,
+                },
+            ],
+        }
+    }
+}
+
+/// Size of a VGI file.
+pub const VGI_SIZE: usize = 43;
+
+/// Register offsets of operators S1 to S4 within an OPN channel's operator
+/// registers (the chip orders them S1, S3, S2, S4).
+pub const OPN_SLOT_OFFSETS: [u8; 4] = [0x00, 0x08, 0x04, 0x0C];
+
+/// Register offsets of operators M1, C1, M2 and C2 within a YM2151 channel's
+/// operator registers, in channel steps of 8.
+pub const OPM_SLOT_OFFSETS: [u8; 4] = [0x00, 0x10, 0x08, 0x18];
+
+/// Carrier operators of each OPN/OPM algorithm, bit n for operator n + 1
+/// (S1 to S4, or M1, C1, M2, C2 on the YM2151).
+pub const FM_CARRIERS: [u8; 8] = [0x8, 0x8, 0x8, 0x8, 0xA, 0xE, 0xE, 0xF];
+
+// Operators in register order (offsets 0, 4, 8 and 12) as slot indices.
+const REGISTER_ORDER: [usize; 4] = [0, 2, 1, 3];
+
+impl FmPatch {
+    /// Feedback and algorithm as written to register `0xB0`.
+    pub fn feedback_algorithm(&self) -> u8 {
+        (self.feedback & 7) << 3 | self.algorithm & 7
+    }
+
+    /// LFO sensitivity as written to register `0xB4`, without the output
+    /// enable bits.
+    pub fn lfo_sensitivity(&self) -> u8 {
+        (self.ams & 3) << 4 | self.fms & 7
+    }
+
+    fn set_b0_b4(&mut self, b0: u8, b4: u8) {
+        self.algorithm = b0 & 7;
+        self.feedback = (b0 >> 3) & 7;
+        self.ams = (b4 >> 4) & 3;
+        self.fms = b4 & 7;
+    }
+
+    /// The patch of FM channel `channel` (0-5) in OPN family registers.
+    /// Registers that were never written read as 0.
+    pub fn from_registers(registers: &Registers, channel: u8) -> FmPatch {
+        let port = channel / 3;
+        let offset = (channel % 3) as u32;
+        let read = |register: u32| {
+            registers
+                .get(&(port, register + offset))
+                .copied()
+                .unwrap_or(0) as u8
+        };
+        let mut patch = FmPatch::default();
+        patch.set_b0_b4(read(0xB0), read(0xB4));
+        for (operator, slot) in patch
+            .operators
+            .iter_mut()
+            .zip(OPN_SLOT_OFFSETS.map(u32::from))
+        {
+            *operator = FmOperator {
+                dt_mul: read(0x30 + slot),
+                tl: read(0x40 + slot),
+                ks_ar: read(0x50 + slot),
+                am_dr: read(0x60 + slot),
+                sr: read(0x70 + slot),
+                sl_rr: read(0x80 + slot),
+                ssg_eg: read(0x90 + slot),
+            };
+        }
+        patch
+    }
+
+    /// The patch of an FM voice of an OPN family chip from a channel usage
+    /// report, `None` for other chips and SSG voices. The total level of
+    /// the carriers is not part of a voice and reads as 0.
+    pub fn from_voice(voice: &Voice) -> Option<FmPatch> {
+        let opn = matches!(
+            voice.chip,
+            Chip::Ym2203 | Chip::Ym2612 | Chip::Ym2608 | Chip::Ym2610b
+        );
+        if !opn || voice.registers.len() != 30 {
+            return None;
+        }
+        let registers: Vec<u8> = voice.registers.iter().map(|&r| r as u8).collect();
+        let mut patch = FmPatch::default();
+        patch.set_b0_b4(registers[0], registers[1]);
+        for (operator, values) in patch.operators.iter_mut().zip(registers[2..].chunks(7)) {
+            *operator = FmOperator {
+                dt_mul: values[0],
+                tl: values[1],
+                ks_ar: values[2],
+                am_dr: values[3],
+                sr: values[4],
+                sl_rr: values[5],
+                ssg_eg: values[6],
+            };
+        }
+        Some(patch)
+    }
+
+    /// Read a VGI instrument file.
+    pub fn from_vgi(bytes: &[u8]) -> Result<FmPatch, ParseError> {
+        let bytes = read_slice(bytes, 0, VGI_SIZE)?;
+        let mut patch = FmPatch {
+            algorithm: bytes[0] & 7,
+            feedback: bytes[1] & 7,
+            ams: (bytes[2] >> 4) & 3,
+            fms: bytes[2] & 7,
+            ..FmPatch::default()
+        };
+        for (slot, values) in REGISTER_ORDER.into_iter().zip(bytes[3..].chunks(10)) {
+            patch.operators[slot] = FmOperator {
+                dt_mul: (values[1] & 7) << 4 | values[0] & 0x0F,
+                tl: values[2] & 0x7F,
+                ks_ar: (values[3] & 3) << 6 | values[4] & 0x1F,
+                am_dr: values[5] & 0x9F,
+                sr: values[6] & 0x1F,
+                sl_rr: (values[8] & 0x0F) << 4 | values[7] & 0x0F,
+                ssg_eg: values[9] & 0x0F,
+            };
+        }
+        Ok(patch)
+    }
+
+    /// Write the patch as a VGI instrument file.
+    pub fn to_vgi(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(VGI_SIZE);
+        bytes.extend([
+            self.algorithm & 7,
+            self.feedback & 7,
+            self.lfo_sensitivity(),
+        ]);
+        for slot in REGISTER_ORDER {
+            let op = &self.operators[slot];
+            bytes.extend([
+                op.dt_mul & 0x0F,
+                (op.dt_mul >> 4) & 7,
+                op.tl & 0x7F,
+                op.ks_ar >> 6,
+                op.ks_ar & 0x1F,
+                op.am_dr & 0x9F,
+                op.sr & 0x1F,
+                op.sl_rr & 0x0F,
+                op.sl_rr >> 4,
+                op.ssg_eg & 0x0F,
+            ]);
+        }
+        bytes
+    }
+
+    // Registers 0x30-0x90 for the operators in register order, 0xB0, 0xB4.
+    fn to_gyb_registers(self) -> [u8; 30] {
+        let mut registers = [0; 30];
+        for (slot, index) in REGISTER_ORDER.into_iter().zip(0..) {
+            let op = &self.operators[slot];
+            let values = [
+                op.dt_mul & 0x7F,
+                op.tl & 0x7F,
+                op.ks_ar & 0xDF,
+                op.am_dr & 0x9F,
+                op.sr & 0x1F,
+                op.sl_rr,
+                op.ssg_eg & 0x0F,
+            ];
+            for (register, value) in values.into_iter().enumerate() {
+                registers[register * 4 + index] = value;
+            }
+        }
+        registers[28] = self.feedback_algorithm();
+        registers[29] = 0xC0 | self.lfo_sensitivity();
+        registers
+    }
+
+    fn from_gyb_registers(registers: &[u8]) -> FmPatch {
+        let mut patch = FmPatch::default();
+        patch.set_b0_b4(registers[28], registers[29]);
+        for (slot, index) in REGISTER_ORDER.into_iter().zip(0..) {
+            let value = |register: usize| registers[register * 4 + index];
+            patch.operators[slot] = FmOperator {
+                dt_mul: value(0) & 0x7F,
+                tl: value(1) & 0x7F,
+                ks_ar: value(2) & 0xDF,
+                am_dr: value(3) & 0x9F,
+                sr: value(4) & 0x1F,
+                sl_rr: value(5),
+                ssg_eg: value(6) & 0x0F,
+            };
+        }
+        patch
+    }
+}
+
+/// An instrument of a `GybBank`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GybInstrument {
+    pub name: String,
+    pub patch: FmPatch,
+    /// Semitones added to the notes played with the instrument.
+    pub transpose: i8,
+}
+
+/// A GYB instrument bank.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GybBank {
+    /// LFO register (`0x22`).
+    pub lfo: u8,
+    pub melody: Vec<GybInstrument>,
+    pub drums: Vec<GybInstrument>,
+    /// Melody instrument for every General MIDI program, by index into
+    /// `melody`.
+    pub melody_map: [Option<u8>; 128],
+    /// Drum instrument for every General MIDI drum key, by index into
+    /// `drums`.
+    pub drum_map: [Option<u8>; 128],
+}
+
+impl Default for GybBank {
+    fn default() -> Self {
+        GybBank {
+            lfo: 0,
+            melody: Vec::new(),
+            drums: Vec::new(),
+            melody_map: [None; 128],
+            drum_map: [None; 128],
+        }
+    }
+}
+
+const GYB_SIGNATURE: [u8; 2] = [0x1A, 0x0C];
+const GYB_VERSION: u8 = 2;
+
+impl GybBank {
+    /// Read a GYB bank of version 1 or 2.
+    pub fn from_bytes(bytes: &[u8]) -> Result<GybBank, ParseError> {
+        if read_slice(bytes, 0, 2)? != GYB_SIGNATURE {
+            return Err(ParseError::Other("GYB: invalid signature".to_string()));
+        }
+        let version = read_u8_at(bytes, 2)?;
+        if !(1..=2).contains(&version) {
+            return Err(ParseError::UnsupportedVersion(version as u32));
+        }
+        let melody_count = read_u8_at(bytes, 3)? as usize;
+        let drum_count = read_u8_at(bytes, 4)? as usize;
+        let mut bank = GybBank::default();
+        let map = read_slice(bytes, 5, 256)?;
+        let entry = |value: u8| (value != 0xFF).then_some(value);
+        for program in 0..128 {
+            bank.melody_map[program] = entry(map[program * 2]);
+            bank.drum_map[program] = entry(map[program * 2 + 1]);
+        }
+        let mut offset = 5 + 256;
+        if version == 2 {
+            bank.lfo = read_u8_at(bytes, offset)?;
+            offset += 1;
+        }
+        let size = if version == 2 { 32 } else { 31 };
+        let mut instruments = Vec::with_capacity(melody_count + drum_count);
+        for _ in 0..melody_count + drum_count {
+            let data = read_slice(bytes, offset, size)?;
+            instruments.push(GybInstrument {
+                name: String::new(),
+                patch: FmPatch::from_gyb_registers(data),
+                transpose: data[30] as i8,
+            });
+            offset += size;
+        }
+        for instrument in &mut instruments {
+            let len = read_u8_at(bytes, offset)? as usize;
+            instrument.name = String::from_utf8_lossy(read_slice(bytes, offset + 1, len)?).into();
+            offset += 1 + len;
+        }
+        bank.drums = instruments.split_off(melody_count);
+        bank.melody = instruments;
+        Ok(bank)
+    }
+
+    /// Write the bank as GYB version 2. Instruments past the 255th of each
+    /// kind are dropped and names are cut to 255 bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let melody = &self.melody[..self.melody.len().min(255)];
+        let drums = &self.drums[..self.drums.len().min(255)];
+        let mut bytes = Vec::new();
+        bytes.extend(GYB_SIGNATURE);
+        bytes.extend([GYB_VERSION, melody.len() as u8, drums.len() as u8]);
+        for program in 0..128 {
+            bytes.push(self.melody_map[program].unwrap_or(0xFF));
+            bytes.push(self.drum_map[program].unwrap_or(0xFF));
+        }
+        bytes.push(self.lfo);
+        for instrument in melody.iter().chain(drums) {
+            bytes.extend(instrument.patch.to_gyb_registers());
+            bytes.extend([instrument.transpose as u8, 0]);
+        }
+        for instrument in melody.iter().chain(drums) {
+            let mut len = instrument.name.len().min(255);
+            while !instrument.name.is_char_boundary(len) {
+                len -= 1;
+            }
+            bytes.push(len as u8);
+            bytes.extend(&instrument.name.as_bytes()[..len]);
+        }
+        bytes
+    }
+}
@@ -0,0 +1,974 @@
+//! Programmer's view of chip registers.
+//!
+//! `register_map` decodes the register values of one chip into named fields
+//! grouped by global settings, channels and operators, and `RegisterMap`
+//! prints them as a text listing. The register values usually come from a
+//! `vgm::snapshot::ChipSnapshot`.
+//!
+//! Registers that were never written read as 0, and fields whose registers
+//! were all never written are left out. Chips without a decoded layout are
+//! listed register by register.
+//!
+//! ```
+//! use std::collections::BTreeMap;
+//! use soundlog::chip::Chip;
+//! use soundlog::chip::regmap::register_map;
+//!
+//! let mut registers = BTreeMap::new();
+//! registers.insert((0, 0xA4), 0x22);
+//! registers.insert((0, 0xA0), 0x69);
+//! let map = register_map(&Chip::Ym2612, &registers);
+//! assert!(map.to_string().contains("F-Num 0x269 Block 4"));
+//! ```
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+
+use crate::chip::Chip;
+use crate::chip::patch::{FM_CARRIERS, OPM_SLOT_OFFSETS, OPN_SLOT_OFFSETS};
+
+/// Register values of one chip keyed by (port, register)
+///
+/// SN76489 registers are the eight latched registers (tone 1 period,
+/// tone 1 attenuation, ..., noise control, noise attenuation) on port 0 and
+/// the Game Gear stereo register on port 1.
+pub type Registers = BTreeMap<(u8, u32), u32>;
+
+/// Decoded registers of one chip
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegisterMap {
+    pub chip: Chip,
+    pub groups: Vec<RegisterGroup>,
+}
+
+/// Fields of one part of the chip, e.g. `Global` or `Channel 1`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegisterGroup {
+    pub name: String,
+    pub fields: Vec<RegisterField>,
+}
+
+/// One decoded setting
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegisterField {
+    pub name: String,
+    /// (port, register) addresses the field is decoded from
+    pub registers: Vec<(u8, u32)>,
+    /// Decoded value, e.g. `F-Num 0x269 Block 4`
+    pub meaning: String,
+}
+
+impl RegisterMap {
+    /// Field `name` of group `group`
+    pub fn field(&self, group: &str, name: &str) -> Option<&RegisterField> {
+        self.groups
+            .iter()
+            .find(|g| g.name == group)?
+            .fields
+            .iter()
+            .find(|f| f.name == name)
+    }
+
+    /// Render the map as a JSON object: the chip name and the groups with
+    /// their fields, each field carrying its `[port, register]` addresses.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        let _ = write!(out, "{{\"chip\":\"{:?}\",\"groups\":[", self.chip);
+        for (i, group) in self.groups.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "{{\"name\":\"{}\",\"fields\":[", group.name);
+            for (j, field) in group.fields.iter().enumerate() {
+                if j > 0 {
+                    out.push(',');
+                }
+                let registers: Vec<String> = field
+                    .registers
+                    .iter()
+                    .map(|(port, register)| format!("[{},{}]", port, register))
+                    .collect();
+                let _ = write!(
+                    out,
+                    "{{\"name\":\"{}\",\"registers\":[{}],\"meaning\":\"{}\"}}",
+                    field.name,
+                    registers.join(","),
+                    field.meaning
+                );
+            }
+            out.push_str("]}");
+        }
+        out.push_str("]}");
+        out
+    }
+}
+
+/// Prints one line per field under a line per group. The alternate form
+/// (`{:#}`) appends the register addresses of every field.
+impl fmt::Display for RegisterMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:?}", self.chip)?;
+        for group in &self.groups {
+            writeln!(f, "  {}", group.name)?;
+            for field in &group.fields {
+                write!(f, "    {:<16} {}", field.name, field.meaning)?;
+                if f.alternate() {
+                    let addresses: Vec<String> = field
+                        .registers
+                        .iter()
+                        .map(|&(port, register)| format!("{}:{:02X}", port, register))
+                        .collect();
+                    write!(f, "  [{}]", addresses.join(" "))?;
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decode `registers` of `chip` into a register map
+pub fn register_map(chip: &Chip, registers: &Registers) -> RegisterMap {
+    let mut decoder = Decoder {
+        registers,
+        groups: Vec::new(),
+    };
+    match chip {
+        Chip::Sn76489 => decoder.sn76489(),
+        Chip::Ay8910 => decoder.ay8910("Global", "Channel"),
+        Chip::Ym2203 | Chip::Ym2612 | Chip::Ym2608 | Chip::Ym2610b => decoder.opn(chip),
+        Chip::Ym2151 => decoder.opm(),
+        Chip::Ym2413 => decoder.opll(),
+        Chip::Ym3526 | Chip::Ym3812 | Chip::Y8950 | Chip::Ymf262 => decoder.opl(chip),
+        _ => decoder.raw(),
+    }
+    RegisterMap {
+        chip: chip.clone(),
+        groups: decoder
+            .groups
+            .into_iter()
+            .filter(|group| !group.fields.is_empty())
+            .collect(),
+    }
+}
+
+/// `true` for registers where writing the value already held has an effect
+/// of its own, so a repeated write must not be dropped as redundant
+///
+/// These are DAC and ADPCM data ports that feed a FIFO or the output
+/// directly (YM2612 0x2A, the YM2608 and Y8950 ADPCM data, OKIM6258,
+/// uPD7759, HuC6280 wave data, NES APU DMC load), command and start
+/// registers that play a sample on every write (OKIM6295, uPD7759, the
+/// ADPCM start and rhythm key registers of the YM2608/YM2610) and registers
+/// whose write restarts a channel (the AY8910 envelope shape, the Game Boy
+/// trigger registers and the NES APU length counter loads).
+pub fn is_trigger_register(chip: &Chip, port: u8, register: u32) -> bool {
+    match chip {
+        Chip::Ym2612 => port == 0 && register == 0x2A,
+        // rhythm key, ADPCM control and ADPCM data
+        Chip::Ym2608 => matches!((port, register), (0, 0x10) | (1, 0x00) | (1, 0x08)),
+        // ADPCM-B control and ADPCM-A key
+        Chip::Ym2610b => matches!((port, register), (0, 0x10) | (1, 0x00)),
+        // ADPCM control and data
+        Chip::Y8950 => matches!(register, 0x07 | 0x0F),
+        Chip::Okim6258 => register == 0x01,
+        Chip::Okim6295 => register == 0x00,
+        // start and FIFO data
+        Chip::Upd7759 => matches!(register, 0x01 | 0x02),
+        Chip::Huc6280 => register == 0x06,
+        Chip::Ay8910 => register == 0x0D,
+        // NR14, NR24, NR34 and NR44
+        Chip::GbDmg => matches!(register, 0x04 | 0x09 | 0x0E | 0x13),
+        Chip::NesApu => matches!(register, 0x03 | 0x07 | 0x0B | 0x0F | 0x11),
+        _ => false,
+    }
+}
+
+/// Registers that make up the sound (patch, duty or noise mode) of a key
+/// state channel, as numbered by the `chip::state` trackers, None for
+/// channels without one
+///
+/// FM voices are the operator and algorithm registers without the total
+/// level of the carriers, which usually carries the note volume. The YM2413
+/// voice is the instrument number, with the user instrument registers for
+/// instrument 0. PSG voices are the tone/noise mix and envelope use (AY8910
+/// and the SSG channels) or the noise mode.
+pub(crate) fn voice(chip: &Chip, registers: &Registers, channel: u8) -> Option<Vec<u32>> {
+    let read = |port: u8, register: u32| registers.get(&(port, register)).copied().unwrap_or(0);
+    let ssg = |channel: u32| {
+        let mixer = read(0, 0x07);
+        vec![
+            (mixer >> channel) & 1,
+            (mixer >> (channel + 3)) & 1,
+            (read(0, 0x08 + channel) >> 4) & 1,
+        ]
+    };
+    let channel = channel as u32;
+    match chip {
+        Chip::Sn76489 => (channel == 3).then(|| vec![read(0, 6) & 0x07]),
+        Chip::Ay8910 => Some(ssg(channel)),
+        Chip::Ym2203 | Chip::Ym2612 | Chip::Ym2608 | Chip::Ym2610b => {
+            let fm_channels = if *chip == Chip::Ym2203 { 3 } else { 6 };
+            if *chip != Chip::Ym2612 && channel >= fm_channels {
+                return Some(ssg(channel - fm_channels));
+            }
+            let port = (channel / 3) as u8;
+            let offset = channel % 3;
+            let algorithm = read(port, 0xB0 + offset);
+            let mut voice = vec![algorithm & 0x3F, read(port, 0xB4 + offset) & 0x37];
+            for (operator, slot) in OPN_SLOT_OFFSETS.map(u32::from).into_iter().enumerate() {
+                for base in (0x30..=0x90).step_by(0x10) {
+                    let carrier = FM_CARRIERS[(algorithm & 0x07) as usize] & (1 << operator) != 0;
+                    voice.push(if base == 0x40 && carrier {
+                        0
+                    } else {
+                        read(port, base + slot + offset)
+                    });
+                }
+            }
+            Some(voice)
+        }
+        Chip::Ym2151 => {
+            let algorithm = read(0, 0x20 + channel);
+            let mut voice = vec![algorithm & 0x3F, read(0, 0x38 + channel)];
+            for (operator, slot) in OPM_SLOT_OFFSETS.map(u32::from).into_iter().enumerate() {
+                for base in (0x40..=0xE0).step_by(0x20) {
+                    let carrier = FM_CARRIERS[(algorithm & 0x07) as usize] & (1 << operator) != 0;
+                    voice.push(if base == 0x60 && carrier {
+                        0
+                    } else {
+                        read(0, base + slot + channel)
+                    });
+                }
+            }
+            Some(voice)
+        }
+        Chip::Ym2413 => {
+            let instrument = read(0, 0x30 + channel) >> 4;
+            let mut voice = vec![instrument];
+            if instrument == 0 {
+                voice.extend((0..8).map(|register| read(0, register)));
+            }
+            Some(voice)
+        }
+        Chip::Ym3526 | Chip::Ym3812 | Chip::Y8950 | Chip::Ymf262 => {
+            let port = (channel / 9) as u8;
+            let offset = channel % 9;
+            let connection = read(port, 0xC0 + offset) & 0x0F;
+            let slot = OPL_SLOTS[offset as usize];
+            let mut voice = vec![connection];
+            for (operator, slot) in [slot, slot + 3].into_iter().enumerate() {
+                // The second operator is always a carrier, the first one
+                // with additive connection.
+                let carrier = operator == 1 || connection & 0x01 != 0;
+                voice.push(read(port, 0x20 + slot));
+                voice.push(read(port, 0x40 + slot) & if carrier { 0xC0 } else { 0xFF });
+                voice.push(read(port, 0x60 + slot));
+                voice.push(read(port, 0x80 + slot));
+                voice.push(read(port, 0xE0 + slot));
+            }
+            Some(voice)
+        }
+        _ => None,
+    }
+}
+
+/// Number of key state channels `channel_write` decodes for `chip`, None for
+/// chips it does not know
+///
+/// SN76489 attenuation writes are decoded by the caller, which has to track
+/// the latched register.
+pub(crate) fn channel_count(chip: &Chip) -> Option<u32> {
+    Some(match chip {
+        Chip::Sn76489 => 4,
+        Chip::Ay8910 => 3,
+        Chip::Ym2612 | Chip::Ym2203 => 6,
+        Chip::Ym2608 | Chip::Ym2610b => 9,
+        Chip::Ym2151 => 8,
+        Chip::Ym2413 | Chip::Ym3812 | Chip::Ym3526 | Chip::Y8950 => 9,
+        Chip::Ymf262 => 18,
+        _ => return None,
+    })
+}
+
+/// A key-on or level write of one channel, see `channel_write`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ChannelWrite {
+    /// Channel as numbered by the `chip::state` trackers
+    pub channel: u32,
+    /// `true` for level writes, `false` for key-on writes
+    pub level: bool,
+    /// The value of the write that keys the channel off or silences it
+    pub silent: u32,
+}
+
+/// Decodes a write of `value` to `register` on `port` that keys a channel
+/// on or off or sets its level, None for any other write
+///
+/// Key writes are the YM2612/OPN key register 0x28, the YM2151 key register
+/// 0x08 and the OPLL/OPL block registers; level writes are the AY8910 and
+/// SSG amplitude registers and the YM2612 DAC data, which are silenced at
+/// their midpoint.
+pub(crate) fn channel_write(
+    chip: &Chip,
+    port: u8,
+    register: u32,
+    value: u32,
+) -> Option<ChannelWrite> {
+    let (channel, level, silent) = match (chip, port, register) {
+        (Chip::Ay8910, _, 0x08..=0x0A) => (register - 0x08, true, 0),
+        (Chip::Ym2203 | Chip::Ym2608 | Chip::Ym2610b, 0, 0x08..=0x0A) => {
+            let fm = if *chip == Chip::Ym2203 { 3 } else { 6 };
+            (fm + register - 0x08, true, 0)
+        }
+        (Chip::Ym2612 | Chip::Ym2203 | Chip::Ym2608 | Chip::Ym2610b, 0, 0x28) => {
+            let high = value & 0x04 != 0;
+            if value & 0x03 == 0x03 || (high && *chip == Chip::Ym2203) {
+                return None;
+            }
+            (
+                (value & 0x03) + if high { 3 } else { 0 },
+                false,
+                value & 0x0F,
+            )
+        }
+        (Chip::Ym2612, 0, 0x2A) => (5, true, 0x80),
+        (Chip::Ym2151, _, 0x08) => (value & 0x07, false, value & 0x07),
+        (Chip::Ym2413, _, 0x20..=0x28) => (register - 0x20, false, value & !0x10),
+        (Chip::Ym3812 | Chip::Ym3526 | Chip::Y8950, _, 0xB0..=0xB8) => {
+            (register - 0xB0, false, value & !0x20)
+        }
+        (Chip::Ymf262, port, 0xB0..=0xB8) if port < 2 => {
+            (register - 0xB0 + port as u32 * 9, false, value & !0x20)
+        }
+        _ => return None,
+    };
+    Some(ChannelWrite {
+        channel,
+        level,
+        silent,
+    })
+}
+
+const ON_OFF: [&str; 2] = ["off", "on"];
+
+fn on_off(value: u32) -> &'static str {
+    ON_OFF[(value != 0) as usize]
+}
+
+// Left/right output bits as a label, `left` and `right` being the bit masks.
+fn pan(value: u32, left: u32, right: u32) -> &'static str {
+    match (value & left != 0, value & right != 0) {
+        (true, true) => "L+R",
+        (true, false) => "L",
+        (false, true) => "R",
+        (false, false) => "muted",
+    }
+}
+
+struct Decoder<'a> {
+    registers: &'a Registers,
+    groups: Vec<RegisterGroup>,
+}
+
+impl Decoder<'_> {
+    fn group(&mut self, name: impl Into<String>) {
+        self.groups.push(RegisterGroup {
+            name: name.into(),
+            fields: Vec::new(),
+        });
+    }
+
+    // Add a field decoded from `addresses` to the last group, unless none of
+    // them was written.
+    fn field(
+        &mut self,
+        name: impl Into<String>,
+        addresses: &[(u8, u32)],
+        decode: impl FnOnce(&[u32]) -> String,
+    ) {
+        let values: Vec<Option<u32>> = addresses
+            .iter()
+            .map(|address| self.registers.get(address).copied())
+            .collect();
+        if values.iter().all(Option::is_none) {
+            return;
+        }
+        let values: Vec<u32> = values.into_iter().map(Option::unwrap_or_default).collect();
+        let field = RegisterField {
+            name: name.into(),
+            registers: addresses.to_vec(),
+            meaning: decode(&values),
+        };
+        if let Some(group) = self.groups.last_mut() {
+            group.fields.push(field);
+        }
+    }
+
+    fn raw(&mut self) {
+        self.group("Registers");
+        for &(port, register) in self.registers.keys() {
+            self.field(
+                format!("{}:{:02X}", port, register),
+                &[(port, register)],
+                |v| format!("0x{:02X}", v[0]),
+            );
+        }
+    }
+
+    fn sn76489(&mut self) {
+        for channel in 0..3u32 {
+            self.group(format!("Tone {}", channel + 1));
+            self.field("Period", &[(0, channel * 2)], |v| format!("0x{:03X}", v[0]));
+            self.field("Attenuation", &[(0, channel * 2 + 1)], |v| {
+                format!("{}", v[0] & 0x0F)
+            });
+        }
+        self.group("Noise");
+        self.field("Control", &[(0, 6)], |v| {
+            let rate = match v[0] & 0x03 {
+                0 => "clock/512",
+                1 => "clock/1024",
+                2 => "clock/2048",
+                _ => "tone 3",
+            };
+            let kind = if v[0] & 0x04 != 0 {
+                "white"
+            } else {
+                "periodic"
+            };
+            format!("{} {}", kind, rate)
+        });
+        self.field("Attenuation", &[(0, 7)], |v| format!("{}", v[0] & 0x0F));
+        self.group("Game Gear");
+        self.field("Stereo", &[(1, 0)], |v| {
+            (0..4)
+                .map(|ch| pan(v[0], 0x10 << ch, 0x01 << ch))
+                .collect::<Vec<_>>()
+                .join(" ")
+        });
+    }
+
+    // AY8910 compatible PSG on port 0, also the SSG part of the OPN family.
+    fn ay8910(&mut self, global: &str, channel: &str) {
+        self.group(global);
+        self.field("Noise period", &[(0, 0x06)], |v| format!("{}", v[0] & 0x1F));
+        self.field("Envelope period", &[(0, 0x0B), (0, 0x0C)], |v| {
+            format!("0x{:04X}", v[0] | (v[1] << 8))
+        });
+        self.field("Envelope shape", &[(0, 0x0D)], |v| {
+            let flags: Vec<&str> = [(0x08, "CONT"), (0x04, "ATT"), (0x02, "ALT"), (0x01, "HOLD")]
+                .iter()
+                .filter(|(bit, _)| v[0] & bit != 0)
+                .map(|&(_, name)| name)
+                .collect();
+            format!("0x{:X} {}", v[0] & 0x0F, flags.join(" "))
+        });
+        for (index, name) in ["A", "B", "C"].iter().enumerate() {
+            let index = index as u32;
+            self.group(format!("{} {}", channel, name));
+            self.field("Period", &[(0, index * 2), (0, index * 2 + 1)], |v| {
+                format!("0x{:03X}", v[0] | ((v[1] & 0x0F) << 8))
+            });
+            self.field("Mixer", &[(0, 0x07)], |v| {
+                // Enable bits are active low.
+                format!(
+                    "tone {} noise {}",
+                    on_off(!v[0] & (1 << index)),
+                    on_off(!v[0] & (8 << index))
+                )
+            });
+            self.field("Volume", &[(0, 0x08 + index)], |v| {
+                if v[0] & 0x10 != 0 {
+                    "envelope".to_string()
+                } else {
+                    format!("{}", v[0] & 0x0F)
+                }
+            });
+        }
+    }
+
+    fn opn(&mut self, chip: &Chip) {
+        let (channels, stereo) = match chip {
+            Chip::Ym2203 => (3, false),
+            _ => (6, true),
+        };
+        self.group("Global");
+        if stereo {
+            self.field("LFO", &[(0, 0x22)], |v| {
+                if v[0] & 0x08 != 0 {
+                    format!("on rate {}", v[0] & 0x07)
+                } else {
+                    "off".to_string()
+                }
+            });
+        }
+        self.field("Channel 3 mode", &[(0, 0x27)], |v| {
+            match v[0] >> 6 {
+                0 => "normal",
+                1 => "special",
+                2 => "CSM",
+                _ => "special CSM",
+            }
+            .to_string()
+        });
+        self.field("Key (last write)", &[(0, 0x28)], |v| {
+            let channel = (v[0] & 0x03) + if v[0] & 0x04 != 0 { 3 } else { 0 };
+            format!("channel {} slots {:04b}", channel + 1, v[0] >> 4)
+        });
+        if *chip == Chip::Ym2612 {
+            self.field("DAC", &[(0, 0x2B)], |v| on_off(v[0] & 0x80).to_string());
+            self.field("DAC data", &[(0, 0x2A)], |v| format!("0x{:02X}", v[0]));
+        }
+        if *chip != Chip::Ym2612 {
+            self.ay8910("SSG", "SSG");
+        }
+        for channel in 0..channels {
+            let port = (channel / 3) as u8;
+            let offset = channel % 3;
+            self.group(format!("Channel {}", channel + 1));
+            self.field(
+                "Frequency",
+                &[(port, 0xA0 + offset), (port, 0xA4 + offset)],
+                |v| {
+                    format!(
+                        "F-Num 0x{:03X} Block {}",
+                        v[0] | ((v[1] & 0x07) << 8),
+                        (v[1] >> 3) & 0x07
+                    )
+                },
+            );
+            self.field("Algorithm", &[(port, 0xB0 + offset)], |v| {
+                format!("FB {} ALG {}", (v[0] >> 3) & 0x07, v[0] & 0x07)
+            });
+            if stereo {
+                self.field("Output", &[(port, 0xB4 + offset)], |v| {
+                    format!(
+                        "{} AMS {} FMS {}",
+                        pan(v[0], 0x80, 0x40),
+                        (v[0] >> 4) & 0x03,
+                        v[0] & 0x07
+                    )
+                });
+            }
+            // Operator registers are ordered slot 1, 3, 2, 4.
+            for (operator, slot) in [(1, 0), (2, 8), (3, 4), (4, 12)] {
+                let addresses: Vec<(u8, u32)> = (0x30..=0x90)
+                    .step_by(0x10)
+                    .map(|base| (port, base + slot + offset))
+                    .collect();
+                self.field(format!("Op{}", operator), &addresses, |v| {
+                    let ssg_eg = if v[6] & 0x08 != 0 {
+                        format!("0x{:X}", v[6] & 0x0F)
+                    } else {
+                        "off".to_string()
+                    };
+                    format!(
+                        "DT {} MUL {} TL {} KS {} AR {} AM {} DR {} SR {} SL {} RR {} SSG-EG {}",
+                        (v[0] >> 4) & 0x07,
+                        v[0] & 0x0F,
+                        v[1] & 0x7F,
+                        v[2] >> 6,
+                        v[2] & 0x1F,
+                        v[3] >> 7,
+                        v[3] & 0x1F,
+                        v[4] & 0x1F,
+                        v[5] >> 4,
+                        v[5] & 0x0F,
+                        ssg_eg
+                    )
+                });
+            }
+        }
+        match chip {
+            Chip::Ym2608 => {
+                self.group("Rhythm");
+                self.field("Total level", &[(0, 0x11)], |v| format!("{}", v[0] & 0x3F));
+                for (index, name) in ["BD", "SD", "TOP", "HH", "TOM", "RIM"].iter().enumerate() {
+                    self.field(*name, &[(0, 0x18 + index as u32)], |v| {
+                        format!("level {} {}", v[0] & 0x1F, pan(v[0], 0x80, 0x40))
+                    });
+                }
+            }
+            Chip::Ym2610b => {
+                self.group("ADPCM-A");
+                self.field("Total level", &[(1, 0x01)], |v| format!("{}", v[0] & 0x3F));
+                for index in 0..6u32 {
+                    let addresses = [0x08, 0x10, 0x18, 0x20, 0x28].map(|base| (1, base + index));
+                    self.field(format!("Channel {}", index + 1), &addresses, |v| {
+                        // Addresses are in 256-byte units.
+                        format!(
+                            "level {} {} start 0x{:06X} end 0x{:06X}",
+                            v[0] & 0x1F,
+                            pan(v[0], 0x80, 0x40),
+                            (v[1] | (v[2] << 8)) << 8,
+                            ((v[3] | (v[4] << 8)) << 8) | 0xFF
+                        )
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn opm(&mut self) {
+        self.group("Global");
+        self.field("Key (last write)", &[(0, 0x08)], |v| {
+            format!(
+                "channel {} slots {:04b}",
+                (v[0] & 0x07) + 1,
+                (v[0] >> 3) & 0x0F
+            )
+        });
+        self.field("Noise", &[(0, 0x0F)], |v| {
+            if v[0] & 0x80 != 0 {
+                format!("on frequency {}", v[0] & 0x1F)
+            } else {
+                "off".to_string()
+            }
+        });
+        self.field("LFO rate", &[(0, 0x18)], |v| format!("{}", v[0]));
+        self.field("LFO depth", &[(0, 0x19)], |v| {
+            // Bit 7 selects phase (PMD) or amplitude (AMD) depth, so only
+            // the last written one is known.
+            let kind = if v[0] & 0x80 != 0 { "PMD" } else { "AMD" };
+            format!("{} {} (last write)", kind, v[0] & 0x7F)
+        });
+        self.field("LFO waveform", &[(0, 0x1B)], |v| {
+            ["saw", "square", "triangle", "noise"][(v[0] & 0x03) as usize].to_string()
+        });
+        for channel in 0..8u32 {
+            self.group(format!("Channel {}", channel + 1));
+            self.field(
+                "Key code",
+                &[(0, 0x28 + channel), (0, 0x30 + channel)],
+                |v| {
+                    format!(
+                        "KC 0x{:02X} ({}) KF {}",
+                        v[0] & 0x7F,
+                        opm_note_name(v[0]),
+                        v[1] >> 2
+                    )
+                },
+            );
+            self.field("Algorithm", &[(0, 0x20 + channel)], |v| {
+                format!(
+                    "{} FB {} CON {}",
+                    pan(v[0], 0x40, 0x80),
+                    (v[0] >> 3) & 0x07,
+                    v[0] & 0x07
+                )
+            });
+            self.field("Modulation", &[(0, 0x38 + channel)], |v| {
+                format!("PMS {} AMS {}", (v[0] >> 4) & 0x07, v[0] & 0x03)
+            });
+            // Operator registers are ordered M1, M2, C1, C2.
+            for (name, slot) in [("M1", 0), ("C1", 16), ("M2", 8), ("C2", 24)] {
+                let addresses: Vec<(u8, u32)> = (0x40..=0xE0)
+                    .step_by(0x20)
+                    .map(|base| (0, base + slot + channel))
+                    .collect();
+                self.field(name, &addresses, |v| {
+                    format!(
+                        "DT1 {} MUL {} TL {} KS {} AR {} AM {} D1R {} DT2 {} D2R {} D1L {} RR {}",
+                        (v[0] >> 4) & 0x07,
+                        v[0] & 0x0F,
+                        v[1] & 0x7F,
+                        v[2] >> 6,
+                        v[2] & 0x1F,
+                        v[3] >> 7,
+                        v[3] & 0x1F,
+                        v[4] >> 6,
+                        v[4] & 0x1F,
+                        v[5] >> 4,
+                        v[5] & 0x0F
+                    )
+                });
+            }
+        }
+    }
+
+    fn opll(&mut self) {
+        self.group("Global");
+        self.field("Rhythm", &[(0, 0x0E)], |v| {
+            if v[0] & 0x20 != 0 {
+                format!("on keys {}", opl_rhythm_keys(v[0]))
+            } else {
+                "off".to_string()
+            }
+        });
+        self.group("User instrument");
+        for (name, slot) in [("Modulator", 0), ("Carrier", 1)] {
+            let addresses = [0x00, 0x02, 0x04, 0x06].map(|base| (0, base + slot));
+            self.field(name, &addresses, |v| {
+                format!(
+                    "AM {} VIB {} EG {} KSR {} MUL {} AR {} DR {} SL {} RR {}",
+                    v[0] >> 7,
+                    (v[0] >> 6) & 1,
+                    (v[0] >> 5) & 1,
+                    (v[0] >> 4) & 1,
+                    v[0] & 0x0F,
+                    v[2] >> 4,
+                    v[2] & 0x0F,
+                    v[3] >> 4,
+                    v[3] & 0x0F
+                )
+            });
+        }
+        self.field("Levels", &[(0, 0x02), (0, 0x03)], |v| {
+            format!(
+                "modulator KSL {} TL {} carrier KSL {}",
+                v[0] >> 6,
+                v[0] & 0x3F,
+                v[1] >> 6
+            )
+        });
+        self.field("Feedback", &[(0, 0x03)], |v| {
+            format!(
+                "FB {} modulator wave {} carrier wave {}",
+                v[0] & 0x07,
+                (v[0] >> 3) & 1,
+                (v[0] >> 4) & 1
+            )
+        });
+        for channel in 0..9u32 {
+            self.group(format!("Channel {}", channel + 1));
+            self.field(
+                "Frequency",
+                &[(0, 0x10 + channel), (0, 0x20 + channel)],
+                |v| {
+                    format!(
+                        "F-Num 0x{:03X} Block {} key {} sustain {}",
+                        v[0] | ((v[1] & 0x01) << 8),
+                        (v[1] >> 1) & 0x07,
+                        on_off(v[1] & 0x10),
+                        on_off(v[1] & 0x20)
+                    )
+                },
+            );
+            self.field("Instrument", &[(0, 0x30 + channel)], |v| {
+                format!(
+                    "{} volume {}",
+                    OPLL_INSTRUMENTS[(v[0] >> 4) as usize],
+                    v[0] & 0x0F
+                )
+            });
+        }
+    }
+
+    fn opl(&mut self, chip: &Chip) {
+        let ports: u8 = if *chip == Chip::Ymf262 { 2 } else { 1 };
+        let waveform = matches!(chip, Chip::Ym3812 | Chip::Ymf262);
+        self.group("Global");
+        if *chip == Chip::Ym3812 {
+            self.field("Waveform select", &[(0, 0x01)], |v| {
+                on_off(v[0] & 0x20).to_string()
+            });
+        }
+        if *chip == Chip::Ymf262 {
+            self.field("OPL3 mode", &[(1, 0x05)], |v| {
+                on_off(v[0] & 0x01).to_string()
+            });
+            self.field("4-op channels", &[(1, 0x04)], |v| {
+                format!("{:06b}", v[0] & 0x3F)
+            });
+        }
+        self.field("CSM/NTS", &[(0, 0x08)], |v| {
+            format!("CSM {} NTS {}", v[0] >> 7, (v[0] >> 6) & 1)
+        });
+        self.field("Rhythm/depth", &[(0, 0xBD)], |v| {
+            let rhythm = if v[0] & 0x20 != 0 {
+                format!("rhythm on keys {}", opl_rhythm_keys(v[0]))
+            } else {
+                "rhythm off".to_string()
+            };
+            format!(
+                "AM depth {} VIB depth {} {}",
+                v[0] >> 7,
+                (v[0] >> 6) & 1,
+                rhythm
+            )
+        });
+        for port in 0..ports {
+            for channel in 0..9u32 {
+                self.group(format!("Channel {}", port as u32 * 9 + channel + 1));
+                self.field(
+                    "Frequency",
+                    &[(port, 0xA0 + channel), (port, 0xB0 + channel)],
+                    |v| {
+                        format!(
+                            "F-Num 0x{:03X} Block {} key {}",
+                            v[0] | ((v[1] & 0x03) << 8),
+                            (v[1] >> 2) & 0x07,
+                            on_off(v[1] & 0x20)
+                        )
+                    },
+                );
+                self.field("Feedback", &[(port, 0xC0 + channel)], |v| {
+                    let mut text = format!("FB {} CNT {}", (v[0] >> 1) & 0x07, v[0] & 0x01);
+                    if ports == 2 {
+                        let outputs: Vec<&str> = ["A", "B", "C", "D"]
+                            .iter()
+                            .enumerate()
+                            .filter(|(bit, _)| v[0] & (0x10 << bit) != 0)
+                            .map(|(_, name)| *name)
+                            .collect();
+                        text.push_str(&format!(" output {}", outputs.join("")));
+                    }
+                    text
+                });
+                let slot = OPL_SLOTS[channel as usize];
+                for (operator, slot) in [(1, slot), (2, slot + 3)] {
+                    let mut addresses: Vec<(u8, u32)> = [0x20, 0x40, 0x60, 0x80]
+                        .iter()
+                        .map(|base| (port, base + slot))
+                        .collect();
+                    if waveform {
+                        addresses.push((port, 0xE0 + slot));
+                    }
+                    self.field(format!("Op{}", operator), &addresses, |v| {
+                        let mut text = format!(
+                            "AM {} VIB {} EGT {} KSR {} MUL {} KSL {} TL {} AR {} DR {} SL {} RR {}",
+                            v[0] >> 7,
+                            (v[0] >> 6) & 1,
+                            (v[0] >> 5) & 1,
+                            (v[0] >> 4) & 1,
+                            v[0] & 0x0F,
+                            v[1] >> 6,
+                            v[1] & 0x3F,
+                            v[2] >> 4,
+                            v[2] & 0x0F,
+                            v[3] >> 4,
+                            v[3] & 0x0F
+                        );
+                        if let Some(ws) = v.get(4) {
+                            text.push_str(&format!(" WS {}", ws & 0x07));
+                        }
+                        text
+                    });
+                }
+            }
+        }
+        if *chip == Chip::Y8950 {
+            self.group("ADPCM");
+            self.field("Control", &[(0, 0x07)], |v| {
+                let flags: Vec<&str> = [
+                    (0x80, "START"),
+                    (0x40, "REC"),
+                    (0x20, "MEMDATA"),
+                    (0x10, "REPEAT"),
+                    (0x01, "RESET"),
+                ]
+                .iter()
+                .filter(|(bit, _)| v[0] & bit != 0)
+                .map(|&(_, name)| name)
+                .collect();
+                format!("0x{:02X} {}", v[0], flags.join(" "))
+            });
+            self.field("Start", &[(0, 0x09), (0, 0x0A)], |v| {
+                format!("0x{:04X}", v[0] | (v[1] << 8))
+            });
+            self.field("Stop", &[(0, 0x0B), (0, 0x0C)], |v| {
+                format!("0x{:04X}", v[0] | (v[1] << 8))
+            });
+            self.field("Delta-N", &[(0, 0x10), (0, 0x11)], |v| {
+                format!("0x{:04X}", v[0] | (v[1] << 8))
+            });
+            self.field("Level", &[(0, 0x12)], |v| format!("{}", v[0]));
+        }
+    }
+}
+
+// First operator register offset of each OPL channel; the second operator
+// is 3 slots later.
+const OPL_SLOTS: [u32; 9] = [0, 1, 2, 8, 9, 10, 16, 17, 18];
+
+const OPLL_INSTRUMENTS: [&str; 16] = [
+    "user",
+    "violin",
+    "guitar",
+    "piano",
+    "flute",
+    "clarinet",
+    "oboe",
+    "trumpet",
+    "organ",
+    "horn",
+    "synthesizer",
+    "harpsichord",
+    "vibraphone",
+    "synth bass",
+    "acoustic bass",
+    "electric guitar",
+];
+
+// Keyed rhythm instruments of an OPL 0xBD or OPLL 0x0E value.
+fn opl_rhythm_keys(value: u32) -> String {
+    let keys: Vec<&str> = [
+        (0x10, "BD"),
+        (0x08, "SD"),
+        (0x04, "TOM"),
+        (0x02, "TC"),
+        (0x01, "HH"),
+    ]
+    .iter()
+    .filter(|(bit, _)| value & bit != 0)
+    .map(|&(_, name)| name)
+    .collect();
+    if keys.is_empty() {
+        "none".to_string()
+    } else {
+        keys.join(" ")
+    }
+}
+
+// YM2151 key code as a note name. Note codes skip every fourth value and
+// the last note of an octave is the C of the next one.
+fn opm_note_name(kc: u32) -> String {
+    const NAMES: [&str; 16] = [
+        "C#", "D", "D#", "?", "E", "F", "F#", "?", "G", "G#", "A", "?", "A#", "B", "C", "?",
+    ];
+    let octave = (kc >> 4) & 0x07;
+    let note = kc & 0x0F;
+    match NAMES[note as usize] {
+        "?" => "-".to_string(),
+        "C" => format!("C{}", octave + 1),
+        name => format!("{}{}", name, octave),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opm_note_names() {
+        assert_eq!(opm_note_name(0x4A), "A4");
+        assert_eq!(opm_note_name(0x3E), "C4");
+        assert_eq!(opm_note_name(0x40), "C#4");
+        assert_eq!(opm_note_name(0x43), "-");
+    }
+
+    #[test]
+    fn unwritten_fields_are_left_out() {
+        let mut registers = Registers::new();
+        registers.insert((1, 0xA5), 0x1A);
+        let map = register_map(&Chip::Ym2612, &registers);
+        assert_eq!(map.groups.len(), 1);
+        assert_eq!(map.groups[0].name, "Channel 5");
+        let field = map.field("Channel 5", "Frequency").unwrap();
+        assert_eq!(field.registers, vec![(1, 0xA1), (1, 0xA5)]);
+        assert_eq!(field.meaning, "F-Num 0x200 Block 3");
+    }
+
+    #[test]
+    fn unknown_chips_are_listed_raw() {
+        let mut registers = Registers::new();
+        registers.insert((0, 0x05), 0x7F);
+        let map = register_map(&Chip::Huc6280, &registers);
+        assert_eq!(map.field("Registers", "0:05").unwrap().meaning, "0x7F");
+        assert_eq!(
+            map.to_json(),
+            r#"{"chip":"Huc6280","groups":[{"name":"Registers","fields":[{"name":"0:05","registers":[[0,5]],"meaning":"0x7F"}]}]}"#
+        );
+    }
+}
@@ -58,6 +58,55 @@ pub enum Chip {
     Mikey,
 }
 
+impl Chip {
+    /// Every chip, in the order of the enum.
+    pub const ALL: [Chip; 43] = [
+        Chip::Sn76489,
+        Chip::Ym2413,
+        Chip::Ym2612,
+        Chip::Ym2151,
+        Chip::SegaPcm,
+        Chip::Rf5c68,
+        Chip::Ym2203,
+        Chip::Ym2608,
+        Chip::Ym2610b,
+        Chip::Ym3812,
+        Chip::Ym3526,
+        Chip::Y8950,
+        Chip::Ymf262,
+        Chip::Ymf278b,
+        Chip::Ymf271,
+        Chip::Ymz280b,
+        Chip::Rf5c164,
+        Chip::Pwm,
+        Chip::Ay8910,
+        Chip::GbDmg,
+        Chip::NesApu,
+        Chip::MultiPcm,
+        Chip::Upd7759,
+        Chip::Okim6258,
+        Chip::Okim6295,
+        Chip::K051649,
+        Chip::K054539,
+        Chip::Huc6280,
+        Chip::C140,
+        Chip::K053260,
+        Chip::Pokey,
+        Chip::Qsound,
+        Chip::Scsp,
+        Chip::WonderSwan,
+        Chip::Vsu,
+        Chip::Saa1099,
+        Chip::Es5503,
+        Chip::Es5506U8,
+        Chip::Es5506U16,
+        Chip::X1010,
+        Chip::C352,
+        Chip::Ga20,
+        Chip::Mikey,
+    ];
+}
+
 /// PSG (SN76489/SN76496) write specification.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct PsgSpec {
@@ -68,8 +68,10 @@ pub mod huc6280;
 pub mod k051649;
 pub mod mikey;
 pub mod nes_apu;
+pub mod okim6295;
 pub mod pcm;
 pub mod pokey;
+pub mod rhythm;
 pub mod saa1099;
 pub mod sn76489;
 pub mod storage;
@@ -97,18 +99,20 @@ pub use huc6280::Huc6280State;
 pub use k051649::K051649State;
 pub use mikey::MikeyState;
 pub use nes_apu::NesApuState;
+pub use okim6295::{Okim6295Sample, Okim6295State};
 pub use pcm::{
     C140State, C352State, Es5503State, Es5506State, Ga20State, K053260State, K054539State,
-    MultiPcmState, Okim6258State, Okim6295State, PwmState, QsoundState, Rf5c68State, Rf5c164State,
-    ScspState, SegaPcmState, Upd7759State, X1010State, Ymz280bState,
+    MultiPcmState, Okim6258State, PwmState, QsoundState, Rf5c68State, Rf5c164State, ScspState,
+    SegaPcmState, Upd7759State, X1010State, Ymz280bState,
 };
 pub use pokey::PokeyState;
+pub use rhythm::OplRhythm;
 pub use saa1099::Saa1099State;
 pub use sn76489::Sn76489State;
 pub use storage::{ArrayStorage, CompactStorage, RegisterStorage, SparseStorage};
 pub use vsu::VsuState;
 pub use wonderswan::WonderSwanState;
-pub use y8950::Y8950State;
+pub use y8950::{Y8950_ADPCM_CHANNEL, Y8950AdpcmSample, Y8950State};
 pub use ym2151::Ym2151State;
 pub use ym2203::Ym2203State;
 pub use ym2413::Ym2413State;
@@ -0,0 +1,371 @@
+//! OKIM6295 state implementation.
+//!
+//! The OKIM6295 plays 4-bit ADPCM samples from an external ROM. A sample is
+//! started with two command writes (register `0x00`): the phrase number with
+//! bit 7 set, then the channels to start in bits 4-7 (channel 0 is bit 4) and
+//! the attenuation in bits 0-3. The phrase number selects an 8-byte entry of
+//! the phrase table at the start of the ROM, holding the 18-bit start and end
+//! addresses of the sample. A command with bit 7 clear stops the channels in
+//! bits 3-6.
+//!
+//! The chip addresses 256 KiB. Larger ROMs are banked by the board; VGM
+//! files describe the banking with extra registers:
+//!
+//! - `0x0F`: the 256 KiB bank added to every address,
+//! - `0x0E`: NMK112 banking when non-zero. Registers `0x10`-`0x13` then
+//!   select a 64 KiB bank for each quarter of the address space, and with
+//!   bit 7 of `0x0E` set the first 1 KiB (the phrase table) is banked in
+//!   256 byte pages by the same registers.
+//!
+//! Load the ROM (data blocks of type `0x8B`) with `write_rom` to have the
+//! phrase table looked up: every started sample is then reported as a
+//! `StateEvent::SampleStart` with its offset and length in the ROM, and
+//! `channel_sample` returns the whole `Okim6295Sample`. Without the ROM the
+//! writes are only stored. Sample playback is not timed, so a sample that
+//! ends on its own produces no `KeyOff`; only stop commands do.
+
+use std::ops::Range;
+
+use super::chip_state::ChipState;
+use super::storage::{ArrayStorage, RegisterStorage};
+use crate::chip::event::StateEvent;
+
+/// Number of channels in OKIM6295
+const OKIM6295_CHANNELS: usize = 4;
+
+/// Bytes addressable by the chip itself (18 address bits)
+const ADDRESS_MASK: u32 = 0x3_FFFF;
+
+/// Size of an NMK112 bank
+const NMK_BANK_SIZE: u32 = 0x1_0000;
+
+/// Size of the NMK112 phrase table pages
+const NMK_TABLE_PAGE_SIZE: u32 = 0x100;
+
+/// Size of the phrase table banked in pages
+const NMK_TABLE_SIZE: u32 = 4 * NMK_TABLE_PAGE_SIZE;
+
+/// A sample started from the phrase table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Okim6295Sample {
+    /// Phrase number (0-127)
+    pub phrase: u8,
+    /// Offset of the phrase table entry in the ROM
+    pub entry: u32,
+    /// Start and end (inclusive) address as seen by the chip, before banking
+    pub start: u32,
+    pub end: u32,
+    /// Offset of the first sample byte in the ROM, after banking
+    pub offset: u32,
+    /// Length of the sample in bytes (two ADPCM samples per byte)
+    pub length: u32,
+}
+
+/// OKIM6295 state tracker
+///
+/// Tracks the command sequence, the bank registers and, with the ROM loaded,
+/// the sample started on each of the 4 channels.
+#[derive(Debug, Clone)]
+pub struct Okim6295State {
+    /// Global register storage for all written registers
+    registers: ArrayStorage<u8, 256>,
+    /// Phrase selected by the first command byte, waiting for the channels
+    phrase: Option<u8>,
+    /// Last sample started on each channel, `None` once stopped
+    channels: [Option<Okim6295Sample>; OKIM6295_CHANNELS],
+    /// ROM blocks as (start address, data), later blocks win
+    rom: Vec<(u32, Vec<u8>)>,
+}
+
+impl Okim6295State {
+    /// Create a new OKIM6295 state tracker
+    ///
+    /// The clock parameter is accepted for API consistency but not used.
+    ///
+    /// # Arguments
+    ///
+    /// * `_clock` - Clock frequency in Hz (unused, accepted for API consistency)
+    pub fn new(_clock: f32) -> Self {
+        Self {
+            registers: ArrayStorage::default(),
+            phrase: None,
+            channels: [None; OKIM6295_CHANNELS],
+            rom: Vec::new(),
+        }
+    }
+
+    /// Store `data` at `start_address` of the ROM image
+    ///
+    /// Blocks may overlap; the bytes written last are used. The ROM is kept
+    /// across `reset`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use soundlog::chip::event::StateEvent;
+    /// use soundlog::chip::state::{ChipState, Okim6295State};
+    ///
+    /// let mut state = Okim6295State::new(1_000_000.0f32);
+    /// // phrase 1: 0x000100..=0x00017F
+    /// state.write_rom(8, &[0x00, 0x01, 0x00, 0x00, 0x01, 0x7F]);
+    /// state.on_register_write(0x00, 0x81);
+    /// let events = state.on_register_write(0x00, 0x10).unwrap();
+    /// assert_eq!(
+    ///     events,
+    ///     vec![StateEvent::SampleStart { channel: 0, offset: 0x100, length: 0x80 }]
+    /// );
+    /// ```
+    pub fn write_rom(&mut self, start_address: u32, data: &[u8]) {
+        if !data.is_empty() {
+            self.rom.push((start_address, data.to_vec()));
+        }
+    }
+
+    /// Take over the ROM loaded into `previous`
+    pub(crate) fn inherit_rom(&mut self, previous: Okim6295State) {
+        self.rom = previous.rom;
+    }
+
+    /// Whether any ROM data has been loaded
+    pub fn has_rom(&self) -> bool {
+        !self.rom.is_empty()
+    }
+
+    /// Last sample started on `channel`, `None` when the channel was stopped
+    /// or nothing was started yet
+    pub fn channel_sample(&self, channel: u8) -> Option<Okim6295Sample> {
+        self.channels.get(channel as usize).copied().flatten()
+    }
+
+    /// ROM offset of the chip address `address` with the current banking
+    pub fn rom_offset(&self, address: u32) -> u32 {
+        let address = address & ADDRESS_MASK;
+        let nmk_mode = self.register(0x0E);
+        if nmk_mode == 0 {
+            return address | (self.register(0x0F) as u32) << 18;
+        }
+        let (bank, offset) = if nmk_mode & 0x80 != 0 && address < NMK_TABLE_SIZE {
+            (address / NMK_TABLE_PAGE_SIZE, address)
+        } else {
+            (address / NMK_BANK_SIZE, address % NMK_BANK_SIZE)
+        };
+        offset | (self.register(0x10 + bank as u8) as u32 * NMK_BANK_SIZE)
+    }
+
+    /// ROM ranges read by `sample` with the current banking, in play order
+    ///
+    /// A sample is contiguous in the ROM unless NMK112 banking maps its
+    /// 64 KiB quarters (or table pages) to separate banks.
+    pub fn rom_ranges(&self, sample: &Okim6295Sample) -> Vec<Range<u32>> {
+        let paged = self.register(0x0E) & 0x80 != 0;
+        let mut ranges: Vec<Range<u32>> = Vec::new();
+        let mut address = sample.start;
+        while address <= sample.end {
+            let boundary = if paged && address < NMK_TABLE_SIZE {
+                NMK_TABLE_PAGE_SIZE
+            } else {
+                NMK_BANK_SIZE
+            };
+            let next = ((address / boundary + 1) * boundary).min(sample.end + 1);
+            let offset = self.rom_offset(address);
+            let range = offset..offset + (next - address);
+            match ranges.last_mut() {
+                Some(last) if last.end == range.start => last.end = range.end,
+                _ => ranges.push(range),
+            }
+            address = next;
+        }
+        ranges
+    }
+
+    /// Look up `phrase` in the phrase table with the current banking, `None`
+    /// when its entry is not in the loaded ROM
+    pub fn phrase(&self, phrase: u8) -> Option<Okim6295Sample> {
+        let table = phrase as u32 * 8;
+        let address = |index: u32| -> Option<u32> {
+            let byte = |i: u32| self.rom_byte(self.rom_offset(table + index + i));
+            Some((byte(0)? as u32) << 16 | (byte(1)? as u32) << 8 | byte(2)? as u32)
+        };
+        let start = address(0)? & ADDRESS_MASK;
+        let end = address(3)? & ADDRESS_MASK;
+        Some(Okim6295Sample {
+            phrase,
+            entry: self.rom_offset(table),
+            start,
+            end,
+            offset: self.rom_offset(start),
+            length: end.saturating_sub(start) + 1,
+        })
+    }
+
+    fn register(&self, register: u8) -> u8 {
+        self.registers.read(register).unwrap_or(0)
+    }
+
+    fn rom_byte(&self, offset: u32) -> Option<u8> {
+        self.rom.iter().rev().find_map(|(start, data)| {
+            let index = offset.checked_sub(*start)? as usize;
+            data.get(index).copied()
+        })
+    }
+
+    /// Handle command write (0x00)
+    fn handle_command(&mut self, value: u8) -> Option<Vec<StateEvent>> {
+        if let Some(phrase) = self.phrase.take() {
+            // Second byte: channels to start in bits 4-7
+            let sample = self.phrase(phrase);
+            let mut events = Vec::new();
+            for channel in 0..OKIM6295_CHANNELS as u8 {
+                if value & (0x10 << channel) == 0 {
+                    continue;
+                }
+                self.channels[channel as usize] = sample;
+                if let Some(sample) = sample {
+                    events.push(StateEvent::SampleStart {
+                        channel,
+                        offset: sample.offset,
+                        length: sample.length,
+                    });
+                }
+            }
+            return (!events.is_empty()).then_some(events);
+        }
+        if value & 0x80 != 0 {
+            self.phrase = Some(value & 0x7F);
+            return None;
+        }
+        // Stop the channels in bits 3-6
+        let mut events = Vec::new();
+        for channel in 0..OKIM6295_CHANNELS as u8 {
+            if value & (0x08 << channel) != 0 && self.channels[channel as usize].take().is_some() {
+                events.push(StateEvent::KeyOff { channel });
+            }
+        }
+        (!events.is_empty()).then_some(events)
+    }
+}
+
+impl Default for Okim6295State {
+    fn default() -> Self {
+        Self::new(0.0f32)
+    }
+}
+
+impl ChipState for Okim6295State {
+    type Register = u8;
+    type Value = u8;
+
+    fn on_register_write(
+        &mut self,
+        register: Self::Register,
+        value: Self::Value,
+    ) -> Option<Vec<StateEvent>> {
+        self.registers.write(register, value);
+        match register {
+            0x00 => self.handle_command(value),
+            _ => None,
+        }
+    }
+
+    fn read_register(&self, register: Self::Register) -> Option<Self::Value> {
+        self.registers.read(register)
+    }
+
+    fn reset(&mut self) {
+        self.registers.clear();
+        self.phrase = None;
+        self.channels = [None; OKIM6295_CHANNELS];
+    }
+
+    fn channel_count(&self) -> usize {
+        OKIM6295_CHANNELS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Phrase table entry for `start..=end`.
+    fn entry(start: u32, end: u32) -> [u8; 6] {
+        let [_, s2, s1, s0] = start.to_be_bytes();
+        let [_, e2, e1, e0] = end.to_be_bytes();
+        [s2, s1, s0, e2, e1, e0]
+    }
+
+    #[test]
+    fn test_start_and_stop_channels() {
+        let mut state = Okim6295State::default();
+        state.write_rom(0x10, &entry(0x2000, 0x20FF));
+
+        state.on_register_write(0x00, 0x82);
+        let events = state.on_register_write(0x00, 0x50).unwrap();
+        assert_eq!(
+            events,
+            vec![
+                StateEvent::SampleStart {
+                    channel: 0,
+                    offset: 0x2000,
+                    length: 0x100
+                },
+                StateEvent::SampleStart {
+                    channel: 2,
+                    offset: 0x2000,
+                    length: 0x100
+                },
+            ]
+        );
+        assert_eq!(state.channel_sample(2).unwrap().entry, 0x10);
+
+        // stop channel 2 and the idle channel 1
+        let events = state.on_register_write(0x00, 0x30).unwrap();
+        assert_eq!(events, vec![StateEvent::KeyOff { channel: 2 }]);
+        assert_eq!(state.channel_sample(2), None);
+        assert!(state.channel_sample(0).is_some());
+    }
+
+    #[test]
+    fn test_bank_register_offsets_table_and_sample() {
+        let mut state = Okim6295State::default();
+        state.write_rom(0x8_0008, &entry(0x100, 0x1FF));
+        state.on_register_write(0x0F, 0x02);
+
+        state.on_register_write(0x00, 0x81);
+        state.on_register_write(0x00, 0x80);
+        let sample = state.channel_sample(3).unwrap();
+        assert_eq!(sample.entry, 0x8_0008);
+        assert_eq!((sample.start, sample.offset), (0x100, 0x8_0100));
+        assert_eq!(state.rom_ranges(&sample), vec![0x8_0100..0x8_0200]);
+    }
+
+    #[test]
+    fn test_nmk112_banks_quarters_and_table_pages() {
+        let mut state = Okim6295State::default();
+        // Table page 0 from bank 5, the sample crosses from quarter 0 to 1
+        state.write_rom(0x5_0008, &entry(0xFF00, 0x100FF));
+        state.on_register_write(0x0E, 0x80);
+        for (register, bank) in [(0x10, 5), (0x11, 7)] {
+            state.on_register_write(register, bank);
+        }
+
+        state.on_register_write(0x00, 0x81);
+        state.on_register_write(0x00, 0x10);
+        let sample = state.channel_sample(0).unwrap();
+        assert_eq!(sample.entry, 0x5_0008);
+        assert_eq!(sample.offset, 0x5_FF00);
+        assert_eq!(sample.length, 0x200);
+        assert_eq!(
+            state.rom_ranges(&sample),
+            vec![0x5_FF00..0x6_0000, 0x7_0000..0x7_0100]
+        );
+    }
+
+    #[test]
+    fn test_missing_rom_reports_nothing() {
+        let mut state = Okim6295State::default();
+        state.on_register_write(0x00, 0x81);
+        assert_eq!(state.on_register_write(0x00, 0xF0), None);
+        assert_eq!(state.channel_sample(0), None);
+        assert_eq!(state.read_register(0x00), Some(0xF0));
+    }
+}
@@ -325,13 +325,6 @@ impl_pcm_chip_u8_u8!(
     1
 );
 
-// OKIM6295 (register: u8, value: u8)
-impl_pcm_chip_u8_u8!(
-    /// OKIM6295 state (4 channels)
-    Okim6295State,
-    4
-);
-
 // K054539 (register: u16, value: u8)
 impl_pcm_chip_u16_u8!(
     /// K054539 state (8 channels)
@@ -467,6 +460,7 @@ impl ChipState for PwmState {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::chip::state::Okim6295State;
 
     #[test]
     fn test_sega_pcm_register_storage() {
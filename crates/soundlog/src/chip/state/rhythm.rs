@@ -0,0 +1,128 @@
+//! OPL rhythm mode tracking.
+//!
+//! This module provides the `OplRhythm` type shared by the OPL family
+//! (YM3526, YM3812, Y8950, YMF262), whose register 0xBD turns channels 6-8
+//! into five percussion instruments.
+
+use crate::chip::event::{RhythmInstrument, StateEvent};
+
+/// Instruments in key bit order of register 0xBD (bit 4 down to bit 0),
+/// with the register holding the total level of the operator heard
+const OPL_RHYTHM: [(RhythmInstrument, u8); 5] = [
+    (RhythmInstrument::BassDrum, 0x53),
+    (RhythmInstrument::SnareDrum, 0x54),
+    (RhythmInstrument::TomTom, 0x52),
+    (RhythmInstrument::TopCymbal, 0x55),
+    (RhythmInstrument::HiHat, 0x51),
+];
+
+/// OPL rhythm mode state
+///
+/// Register 0xBD format:
+/// - Bit 5: Rhythm mode enable
+/// - Bits 4-0: Key on of BD, SD, TOM, TC, HH
+///
+/// An instrument is hit when its key bit goes from 0 to 1 with rhythm mode
+/// enabled. Rhythm channels are numbered in the same order (BD is 0, HH is 4).
+#[derive(Debug, Clone, Default)]
+pub struct OplRhythm {
+    /// Key bits held, 0 while rhythm mode is disabled
+    keys: u8,
+}
+
+impl OplRhythm {
+    /// Create a new rhythm state with rhythm mode disabled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handle a write to register 0xBD
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - Value written to register 0xBD
+    /// * `read` - Reads a register of the chip, used for the total levels
+    ///   (0x51-0x55)
+    ///
+    /// # Returns
+    ///
+    /// A `RhythmHit` for every instrument keyed on, None otherwise
+    pub fn on_write(
+        &mut self,
+        value: u8,
+        read: impl Fn(u8) -> Option<u8>,
+    ) -> Option<Vec<StateEvent>> {
+        let keys = if value & 0x20 != 0 { value & 0x1F } else { 0 };
+        let hits = keys & !self.keys;
+        self.keys = keys;
+
+        let events: Vec<StateEvent> = OPL_RHYTHM
+            .iter()
+            .enumerate()
+            .filter(|(ch, _)| hits & (0x10 >> ch) != 0)
+            .map(|(ch, &(instrument, tl_reg))| StateEvent::RhythmHit {
+                channel: ch as u8,
+                instrument,
+                attenuation: read(tl_reg).unwrap_or(0) & 0x3F,
+            })
+            .collect();
+        (!events.is_empty()).then_some(events)
+    }
+
+    /// Whether rhythm mode is enabled with any instrument keyed on
+    pub fn is_active(&self) -> bool {
+        self.keys != 0
+    }
+
+    /// Clear the rhythm state
+    pub fn clear(&mut self) {
+        self.keys = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hits_on_rising_key_bits_only() {
+        let mut rhythm = OplRhythm::new();
+        let tl = |reg: u8| (reg == 0x53).then_some(0x48);
+
+        // rhythm mode off: no hits
+        assert!(rhythm.on_write(0x1F, tl).is_none());
+
+        let events = rhythm.on_write(0x31, tl).unwrap();
+        assert_eq!(
+            events,
+            vec![
+                StateEvent::RhythmHit {
+                    channel: 0,
+                    instrument: RhythmInstrument::BassDrum,
+                    attenuation: 0x08,
+                },
+                StateEvent::RhythmHit {
+                    channel: 4,
+                    instrument: RhythmInstrument::HiHat,
+                    attenuation: 0,
+                },
+            ]
+        );
+
+        // held keys do not hit again, a released and re-keyed one does
+        assert!(rhythm.on_write(0x31, tl).is_none());
+        rhythm.on_write(0x30, tl);
+        let events = rhythm.on_write(0x31, tl).unwrap();
+        assert!(matches!(
+            events[..],
+            [StateEvent::RhythmHit {
+                instrument: RhythmInstrument::HiHat,
+                ..
+            }]
+        ));
+
+        // disabling rhythm mode releases every key
+        rhythm.on_write(0x00, tl);
+        assert!(!rhythm.is_active());
+    }
+}
@@ -2,9 +2,20 @@
 //!
 //! This module provides state tracking for the Yamaha Y8950 FM synthesis chip,
 //! also known as MSX-Audio, with 9 FM channels and ADPCM support.
+//!
+//! The ADPCM (DELTA-T) unit plays 4-bit ADPCM from its own sample memory.
+//! The memory is filled by the CPU through register `0x0F` or, in VGM files,
+//! by data blocks of type `0x88` (load them with `write_memory`). Playback
+//! starts when bit 7 of register `0x07` is set and is reported as
+//! `StateEvent::SampleStart` on channel `Y8950_ADPCM_CHANNEL`, with the
+//! sample's byte address and length; `adpcm_sample` adds the delta-N rate and
+//! level, and `sample_data` extracts the ADPCM bytes from the memory written
+//! so far. Clearing the bit (or the reset bit) reports a `KeyOff`. Playback
+//! is not timed, so a sample that ends on its own produces no `KeyOff`.
 
 use super::channel::ChannelState;
 use super::chip_state::ChipState;
+use super::rhythm::OplRhythm;
 use super::storage::{RegisterStorage, SparseStorage};
 use crate::chip::event::{KeyState, StateEvent, ToneInfo};
 use crate::chip::fnumber::{self as fnumber, ChipTypeSpec};
@@ -12,6 +23,39 @@ use crate::chip::fnumber::{self as fnumber, ChipTypeSpec};
 /// Y8950 has 9 FM channels
 const Y8950_CHANNELS: usize = 9;
 
+/// Channel number of the ADPCM unit in `SampleStart` / `KeyOff` events
+pub const Y8950_ADPCM_CHANNEL: u8 = 9;
+
+/// ADPCM addresses are in 32 byte units
+const ADPCM_ADDRESS_SHIFT: u32 = 5;
+
+/// ADPCM output rate divider (master clock / 72)
+const ADPCM_CLOCK_DIVIDER: f32 = 72.0;
+
+/// An ADPCM sample as set up in the registers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Y8950AdpcmSample {
+    /// Byte address of the first sample byte
+    pub start: u32,
+    /// Byte address of the last sample byte (inclusive)
+    pub end: u32,
+    /// Delta-N (registers 0x10-0x11), the playback step in 1/65536 samples
+    pub delta_n: u16,
+    /// Playback rate in Hz derived from delta-N and the master clock
+    pub rate_hz: f32,
+    /// Output level (register 0x12, 255 is loudest)
+    pub level: u8,
+    /// Whether playback restarts at the start address when it ends
+    pub repeat: bool,
+}
+
+impl Y8950AdpcmSample {
+    /// Length of the sample in bytes (two ADPCM samples per byte)
+    pub fn length(&self) -> u32 {
+        self.end.saturating_sub(self.start) + 1
+    }
+}
+
 /// Y8950 recommended storage
 pub type Y8950Storage = SparseStorage<u8, u8>;
 
@@ -24,9 +68,14 @@ pub type Y8950Storage = SparseStorage<u8, u8>;
 ///
 /// - 0xA0-0xA8: F-Number low 8 bits for channels 0-8
 /// - 0xB0-0xB8: Key On (bit 5) + Block (bits 4-2) + F-Number high 2 bits (bits 1-0)
-/// - 0xBD: Rhythm mode control (percussion)
+/// - 0xBD: Rhythm mode control (percussion), hits reported as `RhythmHit`
 /// - 0x40-0x55: Key Scale Level / Total Level (volume)
-/// - 0x07-0x12: ADPCM registers (not tracked for tone)
+/// - 0x07: ADPCM control (bit 7 start, bit 6 record, bit 5 memory access,
+///   bit 4 repeat, bit 0 reset)
+/// - 0x09-0x0C: ADPCM start / stop address (low, high), in 32 byte units
+/// - 0x0F: ADPCM data written to memory by the CPU
+/// - 0x10-0x11: ADPCM delta-N (low, high)
+/// - 0x12: ADPCM output level
 #[derive(Debug, Clone)]
 pub struct Y8950State {
     /// Channel states for 9 FM channels
@@ -35,6 +84,15 @@ pub struct Y8950State {
     master_clock_hz: f32,
     /// Global register storage for all written registers
     registers: Y8950Storage,
+    /// Rhythm mode state (register 0xBD)
+    rhythm: OplRhythm,
+    /// Sample being played by the ADPCM unit, `None` when stopped
+    adpcm: Option<Y8950AdpcmSample>,
+    /// Next memory address of CPU data writes, `None` until the first write
+    /// after the start address is set
+    write_address: Option<u32>,
+    /// Sample memory as (start address, data), later blocks win
+    memory: Vec<(u32, Vec<u8>)>,
 }
 
 impl Y8950State {
@@ -60,9 +118,141 @@ impl Y8950State {
             channels: std::array::from_fn(|_| ChannelState::new()),
             master_clock_hz,
             registers: Y8950Storage::default(),
+            rhythm: OplRhythm::new(),
+            adpcm: None,
+            write_address: None,
+            memory: Vec::new(),
+        }
+    }
+
+    /// Store `data` at `start_address` of the ADPCM sample memory
+    ///
+    /// Blocks may overlap; the bytes written last are used. The memory is
+    /// kept across `reset`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use soundlog::chip::state::{ChipState, Y8950State};
+    ///
+    /// let mut state = Y8950State::new(3_579_545.0f32);
+    /// state.write_memory(0x40, &[0x12; 64]);
+    /// // start 0x40 (2 * 32), stop 0x7F (3 * 32 - 1), then start playback
+    /// for (register, value) in [(0x09, 2), (0x0A, 0), (0x0B, 2), (0x0C, 0), (0x07, 0xA0)] {
+    ///     state.on_register_write(register, value);
+    /// }
+    /// let sample = state.adpcm_sample().unwrap();
+    /// assert_eq!((sample.start, sample.length()), (0x40, 0x20));
+    /// assert_eq!(state.sample_data(&sample), Some(vec![0x12; 0x20]));
+    /// ```
+    pub fn write_memory(&mut self, start_address: u32, data: &[u8]) {
+        if !data.is_empty() {
+            self.memory.push((start_address, data.to_vec()));
         }
     }
 
+    /// Take over the sample memory of `previous`
+    pub(crate) fn inherit_memory(&mut self, previous: Y8950State) {
+        self.memory = previous.memory;
+    }
+
+    /// Whether any sample memory has been written
+    pub fn has_memory(&self) -> bool {
+        !self.memory.is_empty()
+    }
+
+    /// Sample being played by the ADPCM unit, `None` when stopped or nothing
+    /// was started yet
+    pub fn adpcm_sample(&self) -> Option<Y8950AdpcmSample> {
+        self.adpcm
+    }
+
+    /// ADPCM bytes of `sample` from the sample memory, `None` when part of it
+    /// was never written
+    pub fn sample_data(&self, sample: &Y8950AdpcmSample) -> Option<Vec<u8>> {
+        (sample.start..=sample.end)
+            .map(|address| self.memory_byte(address))
+            .collect()
+    }
+
+    fn memory_byte(&self, address: u32) -> Option<u8> {
+        self.memory.iter().rev().find_map(|(start, data)| {
+            let index = address.checked_sub(*start)? as usize;
+            data.get(index).copied()
+        })
+    }
+
+    fn register(&self, register: u8) -> u8 {
+        self.registers.read(register).unwrap_or(0)
+    }
+
+    /// Sample described by the ADPCM registers
+    fn current_adpcm_sample(&self) -> Y8950AdpcmSample {
+        let address = |low: u8| {
+            (u16::from_le_bytes([self.register(low), self.register(low + 1)]) as u32)
+                << ADPCM_ADDRESS_SHIFT
+        };
+        let delta_n = u16::from_le_bytes([self.register(0x10), self.register(0x11)]);
+        Y8950AdpcmSample {
+            start: address(0x09),
+            end: address(0x0B) | ((1 << ADPCM_ADDRESS_SHIFT) - 1),
+            delta_n,
+            rate_hz: self.master_clock_hz / ADPCM_CLOCK_DIVIDER * delta_n as f32 / 65536.0,
+            level: self.register(0x12),
+            repeat: self.register(0x07) & 0x10 != 0,
+        }
+    }
+
+    /// Handle ADPCM control register write (0x07)
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - Value written to register 0x07
+    ///
+    /// # Returns
+    ///
+    /// `SampleStart` when playback starts, `KeyOff` when it is stopped
+    fn handle_adpcm_control(&mut self, value: u8) -> Option<Vec<StateEvent>> {
+        self.write_address = None;
+        let playing = value & 0x81 == 0x80 && value & 0x40 == 0;
+        match (self.adpcm.is_some(), playing) {
+            (false, true) => {
+                let sample = self.current_adpcm_sample();
+                self.adpcm = Some(sample);
+                Some(vec![StateEvent::SampleStart {
+                    channel: Y8950_ADPCM_CHANNEL,
+                    offset: sample.start,
+                    length: sample.length(),
+                }])
+            }
+            (true, false) => {
+                self.adpcm = None;
+                Some(vec![StateEvent::KeyOff {
+                    channel: Y8950_ADPCM_CHANNEL,
+                }])
+            }
+            _ => None,
+        }
+    }
+
+    /// Handle ADPCM data register write (0x0F)
+    ///
+    /// With record and memory access set in register 0x07, the byte is
+    /// stored at the next memory address, starting at the start address.
+    fn handle_adpcm_data(&mut self, value: u8) {
+        if self.register(0x07) & 0x60 != 0x60 {
+            return;
+        }
+        let address = self
+            .write_address
+            .unwrap_or_else(|| self.current_adpcm_sample().start);
+        match self.memory.last_mut() {
+            Some((start, data)) if *start + data.len() as u32 == address => data.push(value),
+            _ => self.memory.push((address, vec![value])),
+        }
+        self.write_address = Some(address + 1);
+    }
+
     /// Get a reference to a channel's state
     ///
     /// # Arguments
@@ -251,7 +441,19 @@ impl ChipState for Y8950State {
             return self.handle_fnum_low(register);
         }
 
-        // ADPCM registers (0x07-0x12) - store but don't generate events
+        // Rhythm mode control (0xBD)
+        if register == 0xBD {
+            return self.rhythm.on_write(value, |r| self.registers.read(r));
+        }
+
+        // ADPCM registers (0x07-0x12)
+        match register {
+            0x07 => return self.handle_adpcm_control(value),
+            0x09 | 0x0A => self.write_address = None,
+            0x0F => self.handle_adpcm_data(value),
+            _ => {}
+        }
+
         // Other registers - store but don't generate events
         None
     }
@@ -261,6 +463,9 @@ impl ChipState for Y8950State {
             channel.clear();
         }
         self.registers.clear();
+        self.rhythm.clear();
+        self.adpcm = None;
+        self.write_address = None;
     }
 
     fn channel_count(&self) -> usize {
@@ -321,6 +526,77 @@ mod tests {
         assert!(matches!(&events[0], StateEvent::ToneChange { .. }));
     }
 
+    #[test]
+    fn test_y8950_adpcm_start_and_stop() {
+        let mut state = Y8950State::new(3_579_545.0f32);
+
+        // start 0x0100 (8 * 32), stop 0x01FF, delta-N 0x4000
+        for (register, value) in [
+            (0x09, 0x08),
+            (0x0A, 0x00),
+            (0x0B, 0x0F),
+            (0x0C, 0x00),
+            (0x10, 0x00),
+            (0x11, 0x40),
+            (0x12, 0xFF),
+        ] {
+            assert!(state.on_register_write(register, value).is_none());
+        }
+
+        let events = state.on_register_write(0x07, 0xB0).unwrap();
+        assert_eq!(
+            events,
+            vec![StateEvent::SampleStart {
+                channel: Y8950_ADPCM_CHANNEL,
+                offset: 0x100,
+                length: 0x100,
+            }]
+        );
+        let sample = state.adpcm_sample().unwrap();
+        assert_eq!(sample.delta_n, 0x4000);
+        assert!(sample.repeat);
+        assert!((sample.rate_hz - 3_579_545.0 / 72.0 / 4.0).abs() < 0.01);
+
+        let events = state.on_register_write(0x07, 0x01).unwrap();
+        assert_eq!(
+            events,
+            vec![StateEvent::KeyOff {
+                channel: Y8950_ADPCM_CHANNEL
+            }]
+        );
+        assert!(state.adpcm_sample().is_none());
+    }
+
+    #[test]
+    fn test_y8950_adpcm_cpu_memory_writes() {
+        let mut state = Y8950State::new(3_579_545.0f32);
+
+        // record to memory from 0x20
+        state.on_register_write(0x07, 0x60);
+        state.on_register_write(0x09, 0x01);
+        state.on_register_write(0x0A, 0x00);
+        for value in 0..0x20 {
+            state.on_register_write(0x0F, value);
+        }
+        // not recording: ignored
+        state.on_register_write(0x07, 0x00);
+        state.on_register_write(0x0F, 0xFF);
+
+        state.on_register_write(0x0B, 0x01);
+        state.on_register_write(0x0C, 0x00);
+        state.on_register_write(0x07, 0xA0);
+        let sample = state.adpcm_sample().unwrap();
+        assert_eq!(sample.start, 0x20);
+        assert_eq!(
+            state.sample_data(&sample),
+            Some((0..0x20).collect::<Vec<u8>>())
+        );
+
+        // recording does not start playback
+        state.on_register_write(0x07, 0x00);
+        assert!(state.on_register_write(0x07, 0xE0).is_none());
+    }
+
     #[test]
     fn test_y8950_channel_count() {
         let state = Y8950State::new(3_579_545.0f32);
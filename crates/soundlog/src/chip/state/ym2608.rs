@@ -2,11 +2,12 @@
 //!
 //! This module provides state tracking for the Yamaha YM2608 FM synthesis chip,
 //! which has 6 FM channels, 3 PSG (SSG) channels, and ADPCM capabilities.
+//! Key-ons of the rhythm section are reported as `StateEvent::RhythmHit`.
 
 use super::channel::ChannelState;
 use super::chip_state::ChipState;
 use super::storage::{RegisterStorage, SparseStorage};
-use crate::chip::event::{KeyState, StateEvent, ToneInfo};
+use crate::chip::event::{KeyState, RhythmInstrument, StateEvent, ToneInfo};
 use crate::chip::fnumber::{self as fnumber, ChipTypeSpec};
 
 /// YM2608 has 6 FM channels + 3 PSG channels = 9 total channels
@@ -14,6 +15,16 @@ use crate::chip::fnumber::{self as fnumber, ChipTypeSpec};
 const YM2608_CHANNELS: usize = 9;
 const YM2608_FM_CHANNELS: usize = 6;
 
+/// Rhythm instruments in key-on bit order (register 0x10 bits 0-5)
+const YM2608_RHYTHM: [RhythmInstrument; 6] = [
+    RhythmInstrument::BassDrum,
+    RhythmInstrument::SnareDrum,
+    RhythmInstrument::TopCymbal,
+    RhythmInstrument::HiHat,
+    RhythmInstrument::TomTom,
+    RhythmInstrument::RimShot,
+];
+
 /// YM2608 recommended storage
 pub type Ym2608Storage = SparseStorage<u16, u8>;
 
@@ -39,6 +50,12 @@ pub type Ym2608Storage = SparseStorage<u16, u8>;
 /// - 0x00-0x05: Tone period registers
 /// - 0x07: Mixer/Enable register
 /// - 0x08-0x0A: Volume registers
+///
+/// # Register Layout (rhythm part, port 0)
+///
+/// - 0x10: Key on (bits 0-5: BD, SD, TOP, HH, TOM, RIM), bit 7 dumps instead
+/// - 0x11: Rhythm total level (bits 0-5)
+/// - 0x18-0x1D: Pan (bits 7-6) + instrument level (bits 4-0)
 #[derive(Debug, Clone)]
 pub struct Ym2608State {
     /// Channel states for 6 FM + 3 PSG channels
@@ -380,6 +397,34 @@ impl Ym2608State {
             _ => None,
         }
     }
+
+    /// Handle rhythm key on register write (0x10)
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - Value written to register 0x10
+    ///
+    /// # Returns
+    ///
+    /// A `RhythmHit` for every instrument keyed on, None for dumps
+    fn handle_rhythm_key_on(&mut self, value: u8) -> Option<Vec<StateEvent>> {
+        if value & 0x80 != 0 {
+            return None;
+        }
+        let total_level = self.registers.read(0x11).unwrap_or(0) & 0x3F;
+        let events: Vec<StateEvent> = (0..YM2608_RHYTHM.len())
+            .filter(|ch| value & (1 << ch) != 0)
+            .map(|ch| {
+                let level = self.registers.read(0x18 + ch as u16).unwrap_or(0) & 0x1F;
+                StateEvent::RhythmHit {
+                    channel: ch as u8,
+                    instrument: YM2608_RHYTHM[ch],
+                    attenuation: (0x3F - total_level) + (0x1F - level),
+                }
+            })
+            .collect();
+        (!events.is_empty()).then_some(events)
+    }
 }
 
 impl ChipState for Ym2608State {
@@ -416,6 +461,11 @@ impl ChipState for Ym2608State {
             return self.handle_psg_register(register, value);
         }
 
+        // Rhythm key on (only on port 0)
+        if self.current_port == 0 && register == 0x10 {
+            return self.handle_rhythm_key_on(value);
+        }
+
         None
     }
 
@@ -484,6 +534,39 @@ mod tests {
         assert!(matches!(&events[0], StateEvent::KeyOn { channel: 6, .. }));
     }
 
+    #[test]
+    fn test_ym2608_rhythm_hit() {
+        let mut state = Ym2608State::new(8_000_000.0f32);
+
+        state.set_port(0);
+        state.on_register_write(0x11, 0x3F);
+        state.on_register_write(0x18, 0xDF);
+        state.on_register_write(0x1B, 0xC0);
+
+        let events = state.on_register_write(0x10, 0x09).unwrap();
+        assert_eq!(
+            events,
+            vec![
+                StateEvent::RhythmHit {
+                    channel: 0,
+                    instrument: RhythmInstrument::BassDrum,
+                    attenuation: 0,
+                },
+                StateEvent::RhythmHit {
+                    channel: 3,
+                    instrument: RhythmInstrument::HiHat,
+                    attenuation: 31,
+                },
+            ]
+        );
+
+        // dump
+        assert!(state.on_register_write(0x10, 0x89).is_none());
+        // port 1 register 0x10 is ADPCM
+        state.set_port(1);
+        assert!(state.on_register_write(0x10, 0x01).is_none());
+    }
+
     #[test]
     fn test_ym2608_channel_count() {
         let state = Ym2608State::new(8_000_000.0f32);
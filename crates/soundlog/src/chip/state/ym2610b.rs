@@ -3,17 +3,21 @@
 //! This module provides state tracking for the Yamaha YM2610B FM synthesis chip,
 //! which has 6 FM channels, 3 PSG (SSG) channels, and ADPCM capabilities.
 //! YM2610B is an enhanced version of YM2610 used in Neo Geo systems.
+//! Key-ons of the ADPCM-A channels are reported as `StateEvent::RhythmHit`.
+
+use std::ops::Range;
 
 use super::channel::ChannelState;
 use super::chip_state::ChipState;
 use super::storage::{RegisterStorage, SparseStorage};
-use crate::chip::event::{KeyState, StateEvent, ToneInfo};
+use crate::chip::event::{KeyState, RhythmInstrument, StateEvent, ToneInfo};
 use crate::chip::fnumber::{self as fnumber, ChipTypeSpec};
 
 /// YM2610B has 6 FM channels + 3 PSG channels = 9 total channels
 /// (ADPCM channels are not tracked for tone)
 const YM2610B_CHANNELS: usize = 9;
 const YM2610B_FM_CHANNELS: usize = 6;
+const YM2610B_ADPCM_A_CHANNELS: usize = 6;
 
 /// YM2610B recommended storage
 pub type Ym2610bStorage = SparseStorage<u16, u8>;
@@ -40,6 +44,19 @@ pub type Ym2610bStorage = SparseStorage<u16, u8>;
 /// - 0x00-0x05: Tone period registers
 /// - 0x07: Mixer/Enable register
 /// - 0x08-0x0A: Volume registers
+///
+/// # Register Layout (ADPCM-A part, port 1)
+///
+/// - 0x00: Key on (bits 0-5: channels 0-5), bit 7 dumps instead
+/// - 0x01: Total level (bits 0-5)
+/// - 0x08-0x0D: Pan (bits 7-6) + channel level (bits 4-0)
+/// - 0x10-0x15 / 0x18-0x1D: Start address low / high, in 256 byte units
+/// - 0x20-0x25 / 0x28-0x2D: End address low / high, in 256 byte units
+///
+/// # Register Layout (ADPCM-B part, port 0)
+///
+/// - 0x10: Control, bit 7 starts playback
+/// - 0x12-0x13 / 0x14-0x15: Start / end address, in 256 byte units
 #[derive(Debug, Clone)]
 pub struct Ym2610bState {
     /// Channel states for 6 FM + 3 PSG channels
@@ -132,6 +149,51 @@ impl Ym2610bState {
         self.current_port
     }
 
+    /// ROM address range of ADPCM-A channel `channel` (0-5) from its start
+    /// and end address registers, `None` for other channel numbers
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use soundlog::chip::state::{ChipState, Ym2610bState};
+    ///
+    /// let mut state = Ym2610bState::new(8_000_000.0f32);
+    /// state.set_port(1);
+    /// // start and end 0x0100 (in 256 byte units)
+    /// for (register, value) in [(0x10, 0x00), (0x18, 0x01), (0x20, 0x00), (0x28, 0x01)] {
+    ///     state.on_register_write(register, value);
+    /// }
+    /// assert_eq!(state.adpcm_a_range(0), Some(0x10000..0x10100));
+    /// ```
+    pub fn adpcm_a_range(&self, channel: u8) -> Option<Range<u32>> {
+        if channel as usize >= YM2610B_ADPCM_A_CHANNELS {
+            return None;
+        }
+        let ch = channel as u16;
+        Some(self.rom_range(0x110 + ch, 0x118 + ch, 0x120 + ch, 0x128 + ch))
+    }
+
+    /// ROM address range of ADPCM-B from its start and end address registers
+    /// (port 0, 0x12-0x15)
+    pub fn adpcm_b_range(&self) -> Range<u32> {
+        self.rom_range(0x012, 0x013, 0x014, 0x015)
+    }
+
+    // Range from start and end address registers in 256 byte units; the end
+    // address is inclusive.
+    fn rom_range(
+        &self,
+        start_low: u16,
+        start_high: u16,
+        end_low: u16,
+        end_high: u16,
+    ) -> Range<u32> {
+        let read = |register: u16| self.registers.read(register).unwrap_or(0);
+        let start = u32::from_le_bytes([0, read(start_low), read(start_high), 0]);
+        let end = u32::from_le_bytes([0xFF, read(end_low), read(end_high), 0]);
+        start..end.saturating_add(1)
+    }
+
     /// Extract fnum and block from register state for an FM channel
     ///
     /// YM2610B FM register layout:
@@ -381,6 +443,36 @@ impl Ym2610bState {
             _ => None,
         }
     }
+
+    /// Handle ADPCM-A key on register write (port 1, 0x00)
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - Value written to register 0x00 of port 1
+    ///
+    /// # Returns
+    ///
+    /// A `RhythmHit` for every channel keyed on, None for dumps
+    fn handle_adpcm_a_key_on(&mut self, value: u8) -> Option<Vec<StateEvent>> {
+        if value & 0x80 != 0 {
+            return None;
+        }
+        let read = |register: u16| self.registers.read(0x100 | register).unwrap_or(0);
+        let total_level = read(0x01) & 0x3F;
+        let events: Vec<StateEvent> = (0..YM2610B_ADPCM_A_CHANNELS as u16)
+            .filter(|ch| value & (1 << ch) != 0)
+            .map(|ch| {
+                let level = read(0x08 + ch) & 0x1F;
+                let start = u32::from_le_bytes([0, read(0x10 + ch), read(0x18 + ch), 0]);
+                StateEvent::RhythmHit {
+                    channel: ch as u8,
+                    instrument: RhythmInstrument::Sample { start },
+                    attenuation: (0x3F - total_level) + (0x1F - level),
+                }
+            })
+            .collect();
+        (!events.is_empty()).then_some(events)
+    }
 }
 
 impl ChipState for Ym2610bState {
@@ -417,6 +509,11 @@ impl ChipState for Ym2610bState {
             return self.handle_psg_register(register, value);
         }
 
+        // ADPCM-A key on (only on port 1)
+        if self.current_port == 1 && register == 0x00 {
+            return self.handle_adpcm_a_key_on(value);
+        }
+
         None
     }
 
@@ -485,6 +582,30 @@ mod tests {
         assert!(matches!(&events[0], StateEvent::KeyOn { channel: 6, .. }));
     }
 
+    #[test]
+    fn test_ym2610b_adpcm_a_rhythm_hit() {
+        let mut state = Ym2610bState::new(8_000_000.0f32);
+
+        state.set_port(1);
+        state.on_register_write(0x01, 0x3F);
+        state.on_register_write(0x0A, 0xDA);
+        state.on_register_write(0x12, 0x34);
+        state.on_register_write(0x1A, 0x12);
+
+        let events = state.on_register_write(0x00, 0x04).unwrap();
+        assert_eq!(
+            events,
+            vec![StateEvent::RhythmHit {
+                channel: 2,
+                instrument: RhythmInstrument::Sample { start: 0x12_3400 },
+                attenuation: 5,
+            }]
+        );
+
+        // dump
+        assert!(state.on_register_write(0x00, 0x84).is_none());
+    }
+
     #[test]
     fn test_ym2610b_channel_count() {
         let state = Ym2610bState::new(8_000_000.0f32);
@@ -5,6 +5,7 @@
 
 use super::channel::ChannelState;
 use super::chip_state::ChipState;
+use super::rhythm::OplRhythm;
 use super::storage::{RegisterStorage, SparseStorage};
 use crate::chip::event::{KeyState, StateEvent, ToneInfo};
 use crate::chip::fnumber::{self as fnumber, ChipTypeSpec};
@@ -24,7 +25,7 @@ pub type Ym3526Storage = SparseStorage<u8, u8>;
 ///
 /// - 0xA0-0xA8: F-Number low 8 bits for channels 0-8
 /// - 0xB0-0xB8: Key On (bit 5) + Block (bits 4-2) + F-Number high 2 bits (bits 1-0)
-/// - 0xBD: Rhythm mode control (percussion)
+/// - 0xBD: Rhythm mode control (percussion), hits reported as `RhythmHit`
 /// - 0x40-0x55: Key Scale Level / Total Level (volume)
 #[derive(Debug, Clone)]
 pub struct Ym3526State {
@@ -34,6 +35,8 @@ pub struct Ym3526State {
     master_clock_hz: f32,
     /// Global register storage for all written registers
     registers: Ym3526Storage,
+    /// Rhythm mode state (register 0xBD)
+    rhythm: OplRhythm,
 }
 
 impl Ym3526State {
@@ -59,6 +62,7 @@ impl Ym3526State {
             channels: std::array::from_fn(|_| ChannelState::new()),
             master_clock_hz,
             registers: Ym3526Storage::default(),
+            rhythm: OplRhythm::new(),
         }
     }
 
@@ -250,6 +254,11 @@ impl ChipState for Ym3526State {
             return self.handle_fnum_low(register);
         }
 
+        // Rhythm mode control (0xBD)
+        if register == 0xBD {
+            return self.rhythm.on_write(value, |r| self.registers.read(r));
+        }
+
         // Other registers - store but don't generate events
         None
     }
@@ -259,6 +268,7 @@ impl ChipState for Ym3526State {
             channel.clear();
         }
         self.registers.clear();
+        self.rhythm.clear();
     }
 
     fn channel_count(&self) -> usize {
@@ -5,6 +5,7 @@
 
 use super::channel::ChannelState;
 use super::chip_state::ChipState;
+use super::rhythm::OplRhythm;
 use super::storage::{RegisterStorage, SparseStorage};
 use crate::chip::event::{KeyState, StateEvent, ToneInfo};
 use crate::chip::fnumber::{self as fnumber, ChipTypeSpec};
@@ -33,7 +34,7 @@ pub type Ymf262Storage = SparseStorage<u16, u8>;
 ///
 /// - 0xA0-0xA8: F-Number low 8 bits for channels 0-8 (per port)
 /// - 0xB0-0xB8: Key On (bit 5) + Block (bits 4-2) + F-Number high 2 bits (bits 1-0)
-/// - 0xBD: Rhythm mode control (percussion)
+/// - 0xBD: Rhythm mode control (percussion), hits reported as `RhythmHit`
 /// - 0x40-0x55: Key Scale Level / Total Level (volume)
 /// - 0x105 (port 1, reg 0x05): OPL3 mode enable
 #[derive(Debug, Clone)]
@@ -49,6 +50,8 @@ pub struct Ymf262State {
     /// Global register storage for all written registers
     /// Uses u16 address space to encode port (port 0: 0x00-0xFF, port 1: 0x100-0x1FF)
     registers: Ymf262Storage,
+    /// Rhythm mode state (register 0xBD)
+    rhythm: OplRhythm,
 }
 
 impl Ymf262State {
@@ -75,6 +78,7 @@ impl Ymf262State {
             current_port: 0,
             opl3_mode: false,
             registers: Ymf262Storage::default(),
+            rhythm: OplRhythm::new(),
         }
     }
 
@@ -342,6 +346,13 @@ impl ChipState for Ymf262State {
             return self.handle_fnum_low(register, value);
         }
 
+        // Rhythm mode control (0xBD, port 0 only)
+        if self.current_port == 0 && register == 0xBD {
+            return self
+                .rhythm
+                .on_write(value, |r| self.registers.read(r as u16));
+        }
+
         // Other registers - store but don't generate events
         None
     }
@@ -353,6 +364,7 @@ impl ChipState for Ymf262State {
         self.current_port = 0;
         self.opl3_mode = false;
         self.registers.clear();
+        self.rhythm.clear();
     }
 
     fn channel_count(&self) -> usize {
@@ -0,0 +1,420 @@
+//! GYM sound log format (Sega Mega Drive / Genesis).
+//!
+//! GYM is the register log of the Gens emulator: YM2612 and SN76489 writes
+//! separated by frame waits of 1/60 s. This module reads and writes the
+//! format and converts it to VGM:
+//!
+//! - `GymDocument` / `GymHeader` / `GymCommand` are the in-memory
+//!   representation, parsed with `GymDocument::try_from(&[u8])` and
+//!   serialized with `Vec::<u8>::from(&document)`;
+//! - `to_vgm` translates a document to a `VgmDocument` for the NTSC
+//!   Mega Drive clocks.
+//!
+//! Plain GYM files are nothing but commands. GYMX files start with a 428
+//! byte header holding text fields, the loop frame and, for packed files,
+//! the unpacked size of the zlib compressed commands. Packed files are
+//! unpacked when the `vgz` feature is enabled and rejected otherwise;
+//! serialization always writes unpacked commands.
+//!
+//! ```rust
+//! use soundlog::gym::{GymCommand, GymDocument, to_vgm};
+//!
+//! let bytes = [0x01, 0x28, 0xF0, 0x03, 0x90, 0x00, 0x00];
+//! let gym = GymDocument::try_from(&bytes[..]).unwrap();
+//! assert_eq!(gym.commands[1], GymCommand::PsgWrite(0x90));
+//! assert_eq!(gym.frames(), 2);
+//!
+//! let vgm = to_vgm(&gym);
+//! assert_eq!(vgm.header.ym2612_clock, 7_670_454);
+//! assert_eq!(vgm.header.total_samples, 2 * 735);
+//! ```
+use crate::binutil::{ParseError, read_slice, read_u8_at, read_u32_le_at};
+use crate::chip::{Chip, PsgSpec, Ym2612Spec};
+use crate::meta::Gd3;
+use crate::vgm::command::{Instance, VgmCommand};
+use crate::vgm::header::{Sn76489Feedback, Sn76489ShiftRegisterWidth};
+use crate::vgm::transform::push_wait;
+use crate::vgm::{VgmBuilder, VgmDocument};
+
+/// Size of a GYMX header.
+pub const GYMX_HEADER_SIZE: usize = 428;
+
+/// YM2612 clock of an NTSC Mega Drive, used by `to_vgm`.
+pub const GYM_YM2612_CLOCK: u32 = 7_670_454;
+
+/// SN76489 clock of an NTSC Mega Drive, used by `to_vgm`.
+pub const GYM_SN76489_CLOCK: u32 = 3_579_545;
+
+/// Samples (44.1 kHz) of one frame of 1/60 s.
+pub const GYM_FRAME_SAMPLES: u64 = 735;
+
+const GYMX_MAGIC: &[u8] = b"GYMX";
+
+/// Text fields of a GYMX header with their sizes in bytes.
+const TEXT_FIELDS: [usize; 6] = [32, 32, 32, 32, 32, 256];
+
+const OP_WAIT: u8 = 0x00;
+const OP_YM2612_PORT0: u8 = 0x01;
+const OP_YM2612_PORT1: u8 = 0x02;
+const OP_PSG: u8 = 0x03;
+
+/// Header of a GYMX file.
+///
+/// Text fields are NUL padded in the file and read without the padding,
+/// bytes that are not UTF-8 replaced.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GymHeader {
+    pub song: String,
+    pub game: String,
+    pub publisher: String,
+    /// Emulator that recorded the log.
+    pub emulator: String,
+    /// Person who recorded the log.
+    pub dumper: String,
+    pub comment: String,
+    /// Frame the loop starts at, 1 being the first frame; `0` means no loop.
+    pub loop_start: u32,
+    /// Unpacked size of zlib compressed commands, `0` for unpacked files.
+    pub packed_size: u32,
+}
+
+impl GymHeader {
+    /// Parse a GYMX header.
+    ///
+    /// # Errors
+    /// Returns `ParseError::InvalidIdent` when the input does not start with
+    /// `GYMX` and `ParseError::HeaderTooShort` when it ends within the
+    /// header.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        let magic = read_slice(bytes, 0, 4)?;
+        if magic != GYMX_MAGIC {
+            return Err(ParseError::InvalidIdent(
+                magic.try_into().expect("four bytes"),
+            ));
+        }
+        if bytes.len() < GYMX_HEADER_SIZE {
+            return Err(ParseError::HeaderTooShort("GYMX header".into()));
+        }
+        let mut offset = 4;
+        let mut texts = TEXT_FIELDS.map(|len| {
+            let field = &bytes[offset..offset + len];
+            offset += len;
+            let end = field.iter().position(|&b| b == 0).unwrap_or(len);
+            String::from_utf8_lossy(&field[..end]).into_owned()
+        });
+        Ok(GymHeader {
+            song: std::mem::take(&mut texts[0]),
+            game: std::mem::take(&mut texts[1]),
+            publisher: std::mem::take(&mut texts[2]),
+            emulator: std::mem::take(&mut texts[3]),
+            dumper: std::mem::take(&mut texts[4]),
+            comment: std::mem::take(&mut texts[5]),
+            loop_start: read_u32_le_at(bytes, offset)?,
+            packed_size: read_u32_le_at(bytes, offset + 4)?,
+        })
+    }
+
+    /// Serialize the header. Text is cut to its field at a character
+    /// boundary, leaving room for a terminating NUL.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(GYMX_HEADER_SIZE);
+        out.extend_from_slice(GYMX_MAGIC);
+        let texts = [
+            &self.song,
+            &self.game,
+            &self.publisher,
+            &self.emulator,
+            &self.dumper,
+            &self.comment,
+        ];
+        for (text, size) in texts.into_iter().zip(TEXT_FIELDS) {
+            let mut len = text.len().min(size - 1);
+            while !text.is_char_boundary(len) {
+                len -= 1;
+            }
+            out.extend_from_slice(&text.as_bytes()[..len]);
+            out.resize(out.len() + size - len, 0);
+        }
+        out.extend_from_slice(&self.loop_start.to_le_bytes());
+        out.extend_from_slice(&self.packed_size.to_le_bytes());
+        out
+    }
+}
+
+/// A GYM command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GymCommand {
+    /// `00`: end of a frame, a wait of 1/60 s.
+    Wait,
+    /// `01 rr vv` / `02 rr vv`: YM2612 write to port 0 / 1.
+    Ym2612Write { port: u8, register: u8, value: u8 },
+    /// `03 vv`: SN76489 write.
+    PsgWrite(u8),
+}
+
+impl GymCommand {
+    /// Append the encoded command to `dest`.
+    pub fn to_gym_bytes(&self, dest: &mut Vec<u8>) {
+        match *self {
+            GymCommand::Wait => dest.push(OP_WAIT),
+            GymCommand::Ym2612Write {
+                port,
+                register,
+                value,
+            } => dest.extend([OP_YM2612_PORT0 + (port & 1), register, value]),
+            GymCommand::PsgWrite(value) => dest.extend([OP_PSG, value]),
+        }
+    }
+}
+
+/// Parse the command at `offset`, returning it with its length in bytes.
+///
+/// # Errors
+/// Returns a range error when the command is truncated and
+/// `ParseError::UnknownOpcode` for opcodes above `0x03`.
+pub fn parse_gym_command(bytes: &[u8], offset: usize) -> Result<(GymCommand, usize), ParseError> {
+    let opcode = read_u8_at(bytes, offset)?;
+    match opcode {
+        OP_WAIT => Ok((GymCommand::Wait, 1)),
+        OP_YM2612_PORT0 | OP_YM2612_PORT1 => {
+            let register = read_u8_at(bytes, offset + 1)?;
+            let value = read_u8_at(bytes, offset + 2)?;
+            let write = GymCommand::Ym2612Write {
+                port: opcode - OP_YM2612_PORT0,
+                register,
+                value,
+            };
+            Ok((write, 3))
+        }
+        OP_PSG => Ok((GymCommand::PsgWrite(read_u8_at(bytes, offset + 1)?), 2)),
+        _ => Err(ParseError::UnknownOpcode { opcode, offset }),
+    }
+}
+
+/// A GYM file: an optional GYMX header and the commands.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GymDocument {
+    pub header: Option<GymHeader>,
+    pub commands: Vec<GymCommand>,
+}
+
+impl GymDocument {
+    /// Iterate over the commands.
+    pub fn iter(&self) -> std::slice::Iter<'_, GymCommand> {
+        self.commands.iter()
+    }
+
+    /// Number of frame waits.
+    pub fn frames(&self) -> u64 {
+        self.commands
+            .iter()
+            .filter(|command| **command == GymCommand::Wait)
+            .count() as u64
+    }
+
+    /// Playback length at 60 frames per second.
+    pub fn duration(&self) -> std::time::Duration {
+        std::time::Duration::from_secs_f64(self.frames() as f64 / 60.0)
+    }
+
+    /// Index of the first command of the loop frame, `None` without a loop
+    /// or when the song has fewer frames.
+    pub fn loop_command_index(&self) -> Option<usize> {
+        let loop_start = self.header.as_ref()?.loop_start;
+        if loop_start == 0 {
+            return None;
+        }
+        let mut waits = 0;
+        for (index, command) in self.commands.iter().enumerate() {
+            if waits == loop_start - 1 {
+                return Some(index);
+            }
+            if *command == GymCommand::Wait {
+                waits += 1;
+            }
+        }
+        None
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut out = match &self.header {
+            Some(header) => GymHeader {
+                packed_size: 0,
+                ..header.clone()
+            }
+            .to_bytes(),
+            None => Vec::new(),
+        };
+        for command in &self.commands {
+            command.to_gym_bytes(&mut out);
+        }
+        out
+    }
+}
+
+impl TryFrom<&[u8]> for GymDocument {
+    type Error = ParseError;
+
+    /// Parse a plain GYM or a GYMX file.
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let (header, data) = if bytes.starts_with(GYMX_MAGIC) {
+            let header = GymHeader::from_bytes(bytes)?;
+            let data = &bytes[GYMX_HEADER_SIZE..];
+            let data = match header.packed_size {
+                0 => std::borrow::Cow::Borrowed(data),
+                size => std::borrow::Cow::Owned(unpack(data, size)?),
+            };
+            (Some(header), data)
+        } else {
+            (None, std::borrow::Cow::Borrowed(bytes))
+        };
+        let mut commands = Vec::new();
+        let mut offset = 0;
+        while offset < data.len() {
+            let (command, len) = parse_gym_command(&data, offset)?;
+            commands.push(command);
+            offset += len;
+        }
+        Ok(GymDocument { header, commands })
+    }
+}
+
+// Inflate the zlib stream of a packed GYMX file, reading no more than the
+// declared size.
+#[cfg(feature = "vgz")]
+fn unpack(data: &[u8], size: u32) -> Result<Vec<u8>, ParseError> {
+    use std::io::Read;
+    let mut out = Vec::new();
+    flate2::read::ZlibDecoder::new(data)
+        .take(size as u64)
+        .read_to_end(&mut out)
+        .map_err(|e| ParseError::Other(format!("GYM: cannot unpack commands: {}", e)))?;
+    if out.len() != size as usize {
+        return Err(ParseError::DataInconsistency(format!(
+            "GYM: unpacked {} bytes, header declares {}",
+            out.len(),
+            size
+        )));
+    }
+    Ok(out)
+}
+
+#[cfg(not(feature = "vgz"))]
+fn unpack(_data: &[u8], _size: u32) -> Result<Vec<u8>, ParseError> {
+    Err(ParseError::Other(
+        "GYM: packed files need the `vgz` feature".into(),
+    ))
+}
+
+impl From<&GymDocument> for Vec<u8> {
+    fn from(document: &GymDocument) -> Self {
+        document.to_bytes()
+    }
+}
+
+impl From<GymDocument> for Vec<u8> {
+    fn from(document: GymDocument) -> Self {
+        document.to_bytes()
+    }
+}
+
+/// Convert a GYM document to VGM.
+///
+/// The YM2612 and SN76489 are registered at `GYM_YM2612_CLOCK` and
+/// `GYM_SN76489_CLOCK` with the Mega Drive PSG noise settings, and every
+/// frame lasts `GYM_FRAME_SAMPLES`. GYM logs all writes of a frame at once,
+/// so the YM2612 DAC writes (`0x2A`) of a frame are spread evenly over it,
+/// the other writes staying in order between them. The header fields become
+/// GD3 tags: song, game, dumper (creator) and comment (notes); publisher
+/// and emulator have no GD3 field.
+pub fn to_vgm(document: &GymDocument) -> VgmDocument {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, GYM_YM2612_CLOCK);
+    builder.register_chip(Chip::Sn76489, Instance::Primary, GYM_SN76489_CLOCK);
+
+    let loop_index = document.loop_command_index();
+    let mut commands: Vec<VgmCommand> = Vec::new();
+    let mut vgm_loop_index = None;
+    let mut wait = 0;
+    let mut frame_start = 0;
+    while frame_start < document.commands.len() {
+        let frame_len = document.commands[frame_start..]
+            .iter()
+            .position(|command| *command == GymCommand::Wait)
+            .map_or(document.commands.len() - frame_start, |index| index + 1);
+        let frame = &document.commands[frame_start..frame_start + frame_len];
+        let complete = frame.last() == Some(&GymCommand::Wait);
+        let is_dac = |command: &GymCommand| {
+            matches!(
+                command,
+                GymCommand::Ym2612Write {
+                    port: 0,
+                    register: 0x2A,
+                    ..
+                }
+            )
+        };
+        let dac_writes = frame.iter().filter(|c| is_dac(c)).count() as u64;
+        // Sample of the frame the next DAC write plays at.
+        let mut dac = 0;
+        let mut elapsed = 0;
+        for (index, command) in frame.iter().enumerate() {
+            if Some(frame_start + index) == loop_index {
+                push_wait(&mut commands, std::mem::take(&mut wait));
+                vgm_loop_index = Some(commands.len());
+            }
+            if complete && is_dac(command) {
+                let at = dac * GYM_FRAME_SAMPLES / dac_writes;
+                wait += at - elapsed;
+                elapsed = at;
+                dac += 1;
+            }
+            let write = match *command {
+                GymCommand::Wait => {
+                    wait += GYM_FRAME_SAMPLES - elapsed;
+                    continue;
+                }
+                GymCommand::Ym2612Write {
+                    port,
+                    register,
+                    value,
+                } => VgmCommand::Ym2612Write(
+                    Instance::Primary,
+                    Ym2612Spec {
+                        port,
+                        register,
+                        value,
+                    },
+                ),
+                GymCommand::PsgWrite(value) => {
+                    VgmCommand::Sn76489Write(Instance::Primary, PsgSpec { value })
+                }
+            };
+            push_wait(&mut commands, std::mem::take(&mut wait));
+            commands.push(write);
+        }
+        frame_start += frame_len;
+    }
+    push_wait(&mut commands, wait);
+
+    builder.add_vgm_commands(commands);
+    if let Some(index) = vgm_loop_index {
+        builder.set_loop_offset(index);
+    }
+    if let Some(header) = &document.header {
+        let text = |text: &str| (!text.is_empty()).then(|| text.to_string());
+        builder.set_gd3(Gd3 {
+            track_name_en: text(&header.song),
+            game_name_en: text(&header.game),
+            system_name_en: Some("Sega Mega Drive / Genesis".to_string()),
+            creator: text(&header.dumper),
+            notes: text(&header.comment),
+            ..Gd3::default()
+        });
+    }
+
+    let mut vgm = builder.finalize();
+    vgm.header.sn76489_feedback = Sn76489Feedback::SegaVdp;
+    vgm.header.sn76489_shift_register_width = Sn76489ShiftRegisterWidth::SegaVdp;
+    vgm
+}
@@ -1,10 +1,15 @@
 #![doc = include_str!("../README.md")]
 mod binutil;
 pub mod chip;
+pub mod gym;
 pub mod meta;
+pub mod s98;
 pub mod vgm;
 
-pub use binutil::ParseError;
+pub use binutil::{FileOffset, ParseError, ResourceLimit};
 pub use vgm::command::*;
 pub use vgm::stream::StreamResult as VgmStreamResult;
-pub use vgm::{VgmBuilder, VgmCallbackStream, VgmDocument, VgmExtraHeader, VgmHeader, VgmStream};
+pub use vgm::{
+    BuildError, SharedVgmDocument, StrictVgmBuilder, VgmBuilder, VgmCallbackStream, VgmDocument,
+    VgmExtraHeader, VgmHeader, VgmStream, VgmStreamWriter,
+};
@@ -12,6 +12,76 @@
 //! on failure. `Gd3::to_bytes()` serializes the struct back into the raw
 //! Gd3 chunk bytes, preserving the raw `version` value stored in the
 //! parsed chunk.
+//!
+//! Command-line tools and scripts can build a `Gd3` from `key=value` style
+//! pairs with `Gd3::from_pairs`. Keys are the field names (`track_name_en`,
+//! `release_date`, ...) or their short aliases:
+//!
+//! | alias | field |
+//! |-------|-------|
+//! | `title`, `title-jp` | `track_name_en`, `track_name_origin` |
+//! | `game`, `game-jp` | `game_name_en`, `game_name_origin` |
+//! | `system`, `system-jp` | `system_name_en`, `system_name_origin` |
+//! | `author`, `author-jp` | `author_name_en`, `author_name_origin` |
+//! | `date` | `release_date` |
+//! | `ripper` | `creator` |
+//! | `notes` | `notes` |
+//!
+//! Keys are case-insensitive and `-` and `_` are interchangeable. An empty
+//! value clears the field.
+//!
+//! ```rust
+//! use soundlog::meta::Gd3;
+//!
+//! let gd3 = Gd3::from_pairs([("title", "Green Hill"), ("game", "Sonic"), ("ripper", "me")])
+//!     .unwrap();
+//! assert_eq!(gd3.track_name_en.as_deref(), Some("Green Hill"));
+//! assert_eq!(gd3.game_name_en.as_deref(), Some("Sonic"));
+//! assert_eq!(gd3.creator.as_deref(), Some("me"));
+//! assert!(Gd3::from_pairs([("composer", "x")]).is_err());
+//! ```
+//!
+//! Strings are stored as UTF-16LE, so characters outside the BMP take a
+//! surrogate pair (four bytes) and a byte order mark is kept as the
+//! character U+FEFF. A NUL character would end the field early and shift
+//! every following field, so `to_bytes` leaves NUL characters out.
+//! `Gd3::field_ranges` and `Gd3::encoded_len` give the byte positions and
+//! lengths of the fields as `to_bytes` writes them.
+//!
+//! `Gd3::normalize` cleans up tags typed on different systems: it can remove
+//! byte order marks and NUL characters, trim whitespace and, with the `nfc`
+//! feature, apply Unicode NFC (`e` + U+0301 to `é`, `か` + U+3099 to `が`).
+//!
+//! ```rust
+//! use soundlog::meta::{Gd3, Gd3Normalization};
+//!
+//! let mut gd3 = Gd3 {
+//!     track_name_origin: Some("\u{FEFF}\u{30AB}\u{30A4}\u{30A2} ".to_string()),
+//!     ..Default::default()
+//! };
+//! gd3.normalize(&Gd3Normalization::new().with_strip_bom(true).with_trim(true));
+//! assert_eq!(gd3.track_name_origin.as_deref(), Some("カイア"));
+//! ```
+//!
+//! For display, `Gd3::title`, `Gd3::game`, `Gd3::system` and `Gd3::author`
+//! return the field in the preferred `Gd3Language`, falling back to the
+//! other language when it is missing, as a single trimmed line:
+//!
+//! ```rust
+//! use soundlog::meta::{Gd3, Gd3Language};
+//!
+//! let gd3 = Gd3 {
+//!     track_name_en: Some("  Green Hill\r\nZone ".to_string()),
+//!     game_name_origin: Some("ソニック・ザ・ヘッジホッグ".to_string()),
+//!     ..Default::default()
+//! };
+//! assert_eq!(gd3.title(Gd3Language::Original).as_deref(), Some("Green Hill Zone"));
+//! assert_eq!(gd3.game(Gd3Language::English).as_deref(), Some("ソニック・ザ・ヘッジホッグ"));
+//! assert_eq!(gd3.author(Gd3Language::English), None);
+//! ```
+#[cfg(feature = "nfc")]
+use unicode_normalization::UnicodeNormalization;
+
 use crate::binutil::{ParseError, read_slice, read_u16_le_at, read_u32_le_at};
 
 #[derive(Debug, Clone, PartialEq)]
@@ -31,6 +101,52 @@ pub struct Gd3 {
     pub version: u32,
 }
 
+/// Preferred language of the localized `Gd3` getters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Gd3Language {
+    /// The `*_en` fields.
+    #[default]
+    English,
+    /// The `*_origin` fields, usually Japanese.
+    Original,
+}
+
+/// Options for `Gd3::normalize`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Gd3Normalization {
+    /// Apply Unicode Normalization Form C, composing combining sequences
+    /// into precomposed characters.
+    #[cfg(feature = "nfc")]
+    pub nfc: bool,
+    /// Remove byte order marks (U+FEFF) and NUL characters.
+    pub strip_bom: bool,
+    /// Remove leading and trailing whitespace.
+    pub trim: bool,
+}
+
+impl Gd3Normalization {
+    /// No normalization.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[cfg(feature = "nfc")]
+    pub fn with_nfc(mut self, nfc: bool) -> Self {
+        self.nfc = nfc;
+        self
+    }
+
+    pub fn with_strip_bom(mut self, strip_bom: bool) -> Self {
+        self.strip_bom = strip_bom;
+        self
+    }
+
+    pub fn with_trim(mut self, trim: bool) -> Self {
+        self.trim = trim;
+        self
+    }
+}
+
 // Note: raw-bytes preservation was removed. The parser now parses GD3 fields
 // and the serializer constructs canonical GD3 bytes from those fields.
 impl Default for Gd3 {
@@ -53,14 +169,74 @@ impl Default for Gd3 {
 }
 
 impl Gd3 {
-    pub fn to_bytes(&self) -> Vec<u8> {
-        let mut out: Vec<u8> = Vec::new();
+    /// Field names in chunk order, as accepted by `Gd3::field_mut`.
+    pub const FIELDS: [&'static str; 11] = [
+        "track_name_en",
+        "track_name_origin",
+        "game_name_en",
+        "game_name_origin",
+        "system_name_en",
+        "system_name_origin",
+        "author_name_en",
+        "author_name_origin",
+        "release_date",
+        "creator",
+        "notes",
+    ];
 
-        out.extend_from_slice(b"Gd3 ");
-        out.extend_from_slice(&self.version.to_le_bytes()); // use stored version
-        out.extend_from_slice(&0_u32.to_le_bytes());
+    /// Build a `Gd3` from `(key, value)` pairs, see the module
+    /// documentation for the accepted keys. Later pairs override earlier
+    /// ones and an empty value leaves the field unset.
+    pub fn from_pairs<'a>(
+        pairs: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Result<Gd3, ParseError> {
+        let mut gd3 = Gd3::default();
+        gd3.set_pairs(pairs)?;
+        Ok(gd3)
+    }
+
+    /// Set the fields named by `(key, value)` pairs like `from_pairs`, an
+    /// empty value clears the field. Fails on the first unknown key; the
+    /// pairs before it are already applied.
+    pub fn set_pairs<'a>(
+        &mut self,
+        pairs: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Result<(), ParseError> {
+        for (key, value) in pairs {
+            let Some(field) = self.field_mut(key) else {
+                return Err(ParseError::Other(format!(
+                    "unknown GD3 field {:?}, expected one of: {}",
+                    key,
+                    Gd3::FIELDS.join(", ")
+                )));
+            };
+            *field = (!value.is_empty()).then(|| value.to_string());
+        }
+        Ok(())
+    }
+
+    /// The field named `key`, a field name of `Gd3::FIELDS` or an alias.
+    pub fn field_mut(&mut self, key: &str) -> Option<&mut Option<String>> {
+        let key = key.trim().to_ascii_lowercase().replace('-', "_");
+        Some(match key.as_str() {
+            "track_name_en" | "title" => &mut self.track_name_en,
+            "track_name_origin" | "title_jp" => &mut self.track_name_origin,
+            "game_name_en" | "game" => &mut self.game_name_en,
+            "game_name_origin" | "game_jp" => &mut self.game_name_origin,
+            "system_name_en" | "system" => &mut self.system_name_en,
+            "system_name_origin" | "system_jp" => &mut self.system_name_origin,
+            "author_name_en" | "author" => &mut self.author_name_en,
+            "author_name_origin" | "author_jp" => &mut self.author_name_origin,
+            "release_date" | "date" => &mut self.release_date,
+            "creator" | "ripper" => &mut self.creator,
+            "notes" => &mut self.notes,
+            _ => return None,
+        })
+    }
 
-        let fields: [&Option<String>; 11] = [
+    /// The fields in chunk order, named as in `Gd3::FIELDS`.
+    pub fn fields(&self) -> [&Option<String>; 11] {
+        [
             &self.track_name_en,
             &self.track_name_origin,
             &self.game_name_en,
@@ -72,13 +248,140 @@ impl Gd3 {
             &self.release_date,
             &self.creator,
             &self.notes,
-        ];
+        ]
+    }
+
+    fn fields_mut(&mut self) -> [&mut Option<String>; 11] {
+        [
+            &mut self.track_name_en,
+            &mut self.track_name_origin,
+            &mut self.game_name_en,
+            &mut self.game_name_origin,
+            &mut self.system_name_en,
+            &mut self.system_name_origin,
+            &mut self.author_name_en,
+            &mut self.author_name_origin,
+            &mut self.release_date,
+            &mut self.creator,
+            &mut self.notes,
+        ]
+    }
+
+    /// Bytes `value` takes in the chunk, without its terminator: two per
+    /// UTF-16 code unit, NUL characters left out.
+    pub fn encoded_len(value: &str) -> usize {
+        value
+            .chars()
+            .filter(|&c| c != '\0')
+            .map(char::len_utf16)
+            .sum::<usize>()
+            * 2
+    }
+
+    /// `(offset, length)` of the string bytes of every field, relative to
+    /// the start of the chunk (the `"Gd3 "` identifier), in chunk order. The
+    /// length excludes the two-byte terminator and is `0` for empty fields.
+    pub fn field_ranges(&self) -> [(usize, usize); 11] {
+        let mut offset = 12;
+        self.fields().map(|field| {
+            let len = field.as_deref().map_or(0, Gd3::encoded_len);
+            let range = (offset, len);
+            offset += len + 2;
+            range
+        })
+    }
+
+    /// Length of the chunk `to_bytes` writes, header included.
+    pub fn byte_len(&self) -> usize {
+        self.field_ranges()
+            .last()
+            .map_or(12, |&(offset, len)| offset + len + 2)
+    }
+
+    /// Track name in `language`, or in the other language when it is
+    /// missing. See `Gd3::display_text` for the formatting removed.
+    pub fn title(&self, language: Gd3Language) -> Option<String> {
+        localized(&self.track_name_en, &self.track_name_origin, language)
+    }
+
+    /// Game name in `language`, falling back like `title`.
+    pub fn game(&self, language: Gd3Language) -> Option<String> {
+        localized(&self.game_name_en, &self.game_name_origin, language)
+    }
+
+    /// System name in `language`, falling back like `title`.
+    pub fn system(&self, language: Gd3Language) -> Option<String> {
+        localized(&self.system_name_en, &self.system_name_origin, language)
+    }
+
+    /// Author name in `language`, falling back like `title`.
+    pub fn author(&self, language: Gd3Language) -> Option<String> {
+        localized(&self.author_name_en, &self.author_name_origin, language)
+    }
+
+    /// `value` as a single line: byte order marks and control characters
+    /// are removed, runs of whitespace (line breaks included) become one
+    /// space and the ends are trimmed. `None` when nothing is left.
+    pub fn display_text(value: &str) -> Option<String> {
+        let mut out = String::with_capacity(value.len());
+        for word in value
+            .split(|c: char| c.is_whitespace())
+            .map(|word| {
+                word.chars()
+                    .filter(|&c| !c.is_control() && c != '\u{FEFF}')
+                    .collect::<String>()
+            })
+            .filter(|word| !word.is_empty())
+        {
+            if !out.is_empty() {
+                out.push(' ');
+            }
+            out.push_str(&word);
+        }
+        (!out.is_empty()).then_some(out)
+    }
+
+    /// Apply `options` to every field. Fields left empty are cleared.
+    pub fn normalize(&mut self, options: &Gd3Normalization) {
+        for field in self.fields_mut() {
+            let Some(value) = field.take() else {
+                continue;
+            };
+            let mut value: String = if options.strip_bom {
+                value
+                    .chars()
+                    .filter(|&c| c != '\u{FEFF}' && c != '\0')
+                    .collect()
+            } else {
+                value
+            };
+            if options.trim {
+                value = value.trim().to_string();
+            }
+            #[cfg(feature = "nfc")]
+            if options.nfc {
+                value = value.nfc().collect();
+            }
+            *field = (!value.is_empty()).then_some(value);
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out: Vec<u8> = Vec::with_capacity(self.byte_len());
+
+        out.extend_from_slice(b"Gd3 ");
+        out.extend_from_slice(&self.version.to_le_bytes()); // use stored version
+        out.extend_from_slice(&0_u32.to_le_bytes());
 
         let mut data: Vec<u8> = Vec::new();
-        for f in &fields {
+        for f in self.fields() {
             if let Some(s) = f {
-                for code in s.encode_utf16() {
-                    data.extend_from_slice(&code.to_le_bytes());
+                // An embedded NUL would terminate the field early.
+                for c in s.chars().filter(|&c| c != '\0') {
+                    let mut units = [0u16; 2];
+                    for code in c.encode_utf16(&mut units) {
+                        data.extend_from_slice(&code.to_le_bytes());
+                    }
                 }
             }
             data.extend_from_slice(&0_u16.to_le_bytes());
@@ -94,6 +397,23 @@ impl Gd3 {
     }
 }
 
+// The preferred field, or the other one when the preferred one is missing or
+// has nothing to display.
+fn localized(
+    en: &Option<String>,
+    origin: &Option<String>,
+    language: Gd3Language,
+) -> Option<String> {
+    let (first, second) = match language {
+        Gd3Language::English => (en, origin),
+        Gd3Language::Original => (origin, en),
+    };
+    first
+        .as_deref()
+        .and_then(Gd3::display_text)
+        .or_else(|| second.as_deref().and_then(Gd3::display_text))
+}
+
 /// Parse a Gd3 block from bytes (full Gd3 chunk starting at offset 0).
 /// Returns a populated `Gd3` or a `ParseError` on failure.
 pub(crate) fn parse_gd3(bytes: &[u8]) -> Result<Gd3, ParseError> {
@@ -0,0 +1,791 @@
+//! S98 sound log format (PC-88/PC-98 and other Japanese home computers).
+//!
+//! S98 is a register-write log like VGM: a header with a timer and a list of
+//! devices, followed by device writes and sync waits. This module reads and
+//! writes the format:
+//!
+//! - `S98Document` / `S98Header` / `S98Command` are the in-memory
+//!   representation, parsed with `S98Document::try_from(&[u8])` and
+//!   serialized with `Vec::<u8>::from(&document)`;
+//! - `S98Builder` assembles a document and computes the header offsets;
+//! - `stream::S98Stream` iterates the commands of bytes, a file or a
+//!   document one at a time, like `VgmStream`;
+//! - `convert` translates to and from `VgmDocument` for the chips both
+//!   formats share.
+//!
+//! Version 3 files list their devices in the header; version 1 and 2 files
+//! have no device list and use a single YM2608 at 7.9872 MHz
+//! (`S98Header::effective_devices`). A sync lasts
+//! `timer_numerator / timer_denominator` seconds, 10 ms when both are zero.
+//! Tags (`[S98]` key/value lines in version 3, a title string before) are
+//! kept as bytes, since files without a UTF-8 byte order mark use
+//! Shift_JIS.
+//!
+//! ```rust
+//! use soundlog::s98::{S98Builder, S98Command, S98DeviceType, S98Document};
+//!
+//! let mut builder = S98Builder::new();
+//! builder.add_device(S98DeviceType::Ym2608, 7_987_200);
+//! builder.add_write(0, 0, 0x28, 0xF0);
+//! builder.add_sync(100);
+//! let bytes: Vec<u8> = (&builder.finalize()).into();
+//! assert_eq!(&bytes[..4], b"S983");
+//!
+//! let document = S98Document::try_from(&bytes[..]).unwrap();
+//! assert_eq!(document.total_ticks(), 100);
+//! assert_eq!(document.commands.last(), Some(&S98Command::End));
+//! ```
+pub mod convert;
+pub mod stream;
+
+use crate::binutil::{ParseError, read_slice, read_u8_at, read_u32_le_at};
+use crate::chip::Chip;
+
+pub use stream::S98Stream;
+
+/// Size of the fixed part of the header.
+pub const S98_HEADER_SIZE: usize = 0x20;
+
+/// Size of one device entry in a version 3 header.
+pub const S98_DEVICE_INFO_SIZE: usize = 16;
+
+/// Timer numerator used when the header stores zero.
+pub const DEFAULT_TIMER_NUMERATOR: u32 = 10;
+
+/// Timer denominator used when the header stores zero.
+pub const DEFAULT_TIMER_DENOMINATOR: u32 = 1000;
+
+/// Clock of the device a file without a device list plays on.
+pub const DEFAULT_DEVICE_CLOCK: u32 = 7_987_200;
+
+/// Opcode of a one-sync wait.
+const OP_SYNC: u8 = 0xFF;
+/// Opcode of an n-sync wait followed by a variable-length count.
+const OP_SYNC_N: u8 = 0xFE;
+/// Opcode of the end of the data (or the jump to the loop point).
+const OP_END: u8 = 0xFD;
+
+/// Tag block marker of version 3 files.
+const TAG_MARKER: &[u8] = b"[S98]";
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+
+/// Device type of a version 3 device entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum S98DeviceType {
+    /// 0: no device.
+    None,
+    /// 1: YM2149 (SSG).
+    Ym2149,
+    /// 2: YM2203 (OPN).
+    Ym2203,
+    /// 3: YM2612 (OPN2).
+    Ym2612,
+    /// 4: YM2608 (OPNA).
+    Ym2608,
+    /// 5: YM2151 (OPM).
+    Ym2151,
+    /// 6: YM2413 (OPLL).
+    Ym2413,
+    /// 7: YM3526 (OPL).
+    Ym3526,
+    /// 8: YM3812 (OPL2).
+    Ym3812,
+    /// 9: YMF262 (OPL3).
+    Ymf262,
+    /// 15: AY-3-8910 (PSG).
+    Ay8910,
+    /// 16: SN76489 (DCSG).
+    Sn76489,
+    /// Any other value.
+    Unknown(u32),
+}
+
+impl S98DeviceType {
+    /// The chip this device writes to, `None` for `None` and unknown types.
+    /// YM2149 and AY-3-8910 are both `Chip::Ay8910`.
+    pub fn chip(&self) -> Option<Chip> {
+        Some(match self {
+            S98DeviceType::Ym2149 | S98DeviceType::Ay8910 => Chip::Ay8910,
+            S98DeviceType::Ym2203 => Chip::Ym2203,
+            S98DeviceType::Ym2612 => Chip::Ym2612,
+            S98DeviceType::Ym2608 => Chip::Ym2608,
+            S98DeviceType::Ym2151 => Chip::Ym2151,
+            S98DeviceType::Ym2413 => Chip::Ym2413,
+            S98DeviceType::Ym3526 => Chip::Ym3526,
+            S98DeviceType::Ym3812 => Chip::Ym3812,
+            S98DeviceType::Ymf262 => Chip::Ymf262,
+            S98DeviceType::Sn76489 => Chip::Sn76489,
+            S98DeviceType::None | S98DeviceType::Unknown(_) => return None,
+        })
+    }
+
+    /// The device type for `chip`, `None` when S98 has no such device.
+    /// `Chip::Ay8910` maps to `Ay8910`.
+    pub fn from_chip(chip: &Chip) -> Option<Self> {
+        Some(match chip {
+            Chip::Ay8910 => S98DeviceType::Ay8910,
+            Chip::Ym2203 => S98DeviceType::Ym2203,
+            Chip::Ym2612 => S98DeviceType::Ym2612,
+            Chip::Ym2608 => S98DeviceType::Ym2608,
+            Chip::Ym2151 => S98DeviceType::Ym2151,
+            Chip::Ym2413 => S98DeviceType::Ym2413,
+            Chip::Ym3526 => S98DeviceType::Ym3526,
+            Chip::Ym3812 => S98DeviceType::Ym3812,
+            Chip::Ymf262 => S98DeviceType::Ymf262,
+            Chip::Sn76489 => S98DeviceType::Sn76489,
+            _ => return None,
+        })
+    }
+}
+
+impl From<u32> for S98DeviceType {
+    fn from(value: u32) -> Self {
+        match value {
+            0 => S98DeviceType::None,
+            1 => S98DeviceType::Ym2149,
+            2 => S98DeviceType::Ym2203,
+            3 => S98DeviceType::Ym2612,
+            4 => S98DeviceType::Ym2608,
+            5 => S98DeviceType::Ym2151,
+            6 => S98DeviceType::Ym2413,
+            7 => S98DeviceType::Ym3526,
+            8 => S98DeviceType::Ym3812,
+            9 => S98DeviceType::Ymf262,
+            15 => S98DeviceType::Ay8910,
+            16 => S98DeviceType::Sn76489,
+            _ => S98DeviceType::Unknown(value),
+        }
+    }
+}
+
+impl From<S98DeviceType> for u32 {
+    fn from(value: S98DeviceType) -> Self {
+        match value {
+            S98DeviceType::None => 0,
+            S98DeviceType::Ym2149 => 1,
+            S98DeviceType::Ym2203 => 2,
+            S98DeviceType::Ym2612 => 3,
+            S98DeviceType::Ym2608 => 4,
+            S98DeviceType::Ym2151 => 5,
+            S98DeviceType::Ym2413 => 6,
+            S98DeviceType::Ym3526 => 7,
+            S98DeviceType::Ym3812 => 8,
+            S98DeviceType::Ymf262 => 9,
+            S98DeviceType::Ay8910 => 15,
+            S98DeviceType::Sn76489 => 16,
+            S98DeviceType::Unknown(v) => v,
+        }
+    }
+}
+
+/// A device entry of a version 3 header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct S98Device {
+    pub device_type: S98DeviceType,
+    /// Clock in Hz.
+    pub clock: u32,
+    /// Mute flags: bit 0 mutes the right channel, bit 1 the left one.
+    pub pan: u32,
+}
+
+/// S98 file header.
+///
+/// The offsets are absolute file offsets, as stored in the file; `0` means
+/// no tag / no loop. `S98Builder::finalize` and serialization compute them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct S98Header {
+    /// Format version, `1` to `3` (stored as an ASCII digit).
+    pub version: u8,
+    pub timer_numerator: u32,
+    pub timer_denominator: u32,
+    /// Deprecated compression flag; always `0` in files players accept.
+    pub compressing: u32,
+    pub tag_offset: u32,
+    pub data_offset: u32,
+    pub loop_offset: u32,
+    /// Device list of version 3 files; empty for earlier versions.
+    pub devices: Vec<S98Device>,
+}
+
+impl Default for S98Header {
+    fn default() -> Self {
+        S98Header {
+            version: 3,
+            timer_numerator: DEFAULT_TIMER_NUMERATOR,
+            timer_denominator: DEFAULT_TIMER_DENOMINATOR,
+            compressing: 0,
+            tag_offset: 0,
+            data_offset: 0,
+            loop_offset: 0,
+            devices: Vec::new(),
+        }
+    }
+}
+
+impl S98Header {
+    /// Parse the header (and the device list of version 3 files) at the
+    /// start of `bytes`.
+    ///
+    /// # Errors
+    /// Returns `ParseError::InvalidIdent` when the file does not start with
+    /// `S98`, `ParseError::UnsupportedVersion` for versions other than `1`
+    /// to `3`, and a range error when the header is truncated.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseError> {
+        if bytes.len() < S98_HEADER_SIZE {
+            return Err(ParseError::HeaderTooShort("S98 header".into()));
+        }
+        if &bytes[..3] != b"S98" {
+            return Err(ParseError::InvalidIdent([
+                bytes[0], bytes[1], bytes[2], bytes[3],
+            ]));
+        }
+        let version = bytes[3].wrapping_sub(b'0');
+        if !(1..=3).contains(&version) {
+            return Err(ParseError::UnsupportedVersion(bytes[3] as u32));
+        }
+        let mut header = S98Header {
+            version,
+            timer_numerator: read_u32_le_at(bytes, 0x04)?,
+            timer_denominator: read_u32_le_at(bytes, 0x08)?,
+            compressing: read_u32_le_at(bytes, 0x0C)?,
+            tag_offset: read_u32_le_at(bytes, 0x10)?,
+            data_offset: read_u32_le_at(bytes, 0x14)?,
+            loop_offset: read_u32_le_at(bytes, 0x18)?,
+            devices: Vec::new(),
+        };
+        if version >= 3 {
+            let count = read_u32_le_at(bytes, 0x1C)? as usize;
+            // Every entry must be present, so the count cannot make us
+            // reserve more than the input holds.
+            let table = read_slice(
+                bytes,
+                S98_HEADER_SIZE,
+                count.saturating_mul(S98_DEVICE_INFO_SIZE),
+            )?;
+            header.devices = table
+                .chunks_exact(S98_DEVICE_INFO_SIZE)
+                .map(|entry| S98Device {
+                    device_type: S98DeviceType::from(le_u32(&entry[0..4])),
+                    clock: le_u32(&entry[4..8]),
+                    pan: le_u32(&entry[8..12]),
+                })
+                .collect();
+        }
+        Ok(header)
+    }
+
+    /// The timer as `(numerator, denominator)` seconds per sync, with the
+    /// 10 ms default for zero fields.
+    pub fn effective_timer(&self) -> (u32, u32) {
+        let numerator = match self.timer_numerator {
+            0 => DEFAULT_TIMER_NUMERATOR,
+            n => n,
+        };
+        let denominator = match self.timer_denominator {
+            0 => DEFAULT_TIMER_DENOMINATOR,
+            d => d,
+        };
+        (numerator, denominator)
+    }
+
+    /// The devices the file plays on: the device list, or a single YM2608
+    /// at `DEFAULT_DEVICE_CLOCK` when it is empty.
+    pub fn effective_devices(&self) -> Vec<S98Device> {
+        if self.devices.is_empty() {
+            vec![S98Device {
+                device_type: S98DeviceType::Ym2608,
+                clock: DEFAULT_DEVICE_CLOCK,
+                pan: 0,
+            }]
+        } else {
+            self.devices.clone()
+        }
+    }
+
+    // Header and device list as written before the command data.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.header_len());
+        out.extend_from_slice(b"S98");
+        out.push(b'0' + self.version);
+        for value in [
+            self.timer_numerator,
+            self.timer_denominator,
+            self.compressing,
+            self.tag_offset,
+            self.data_offset,
+            self.loop_offset,
+        ] {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        if self.version >= 3 {
+            out.extend_from_slice(&(self.devices.len() as u32).to_le_bytes());
+            for device in &self.devices {
+                out.extend_from_slice(&u32::from(device.device_type).to_le_bytes());
+                out.extend_from_slice(&device.clock.to_le_bytes());
+                out.extend_from_slice(&device.pan.to_le_bytes());
+                out.extend_from_slice(&0u32.to_le_bytes());
+            }
+        } else {
+            out.extend_from_slice(&0u32.to_le_bytes());
+        }
+        out
+    }
+
+    fn header_len(&self) -> usize {
+        if self.version >= 3 {
+            S98_HEADER_SIZE + self.devices.len() * S98_DEVICE_INFO_SIZE
+        } else {
+            S98_HEADER_SIZE
+        }
+    }
+}
+
+/// A write to register `register` of a device.
+///
+/// `device` is the index into the device list (`0..=63`) and `port` selects
+/// the second register bank of OPNA/OPN2/OPL3 (`1`) or the first (`0`).
+/// SN76489 writes carry the data byte in `value`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct S98Write {
+    pub device: u8,
+    pub port: u8,
+    pub register: u8,
+    pub value: u8,
+}
+
+/// An S98 command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum S98Command {
+    /// Device write (`0x00`-`0x7F`).
+    Write(S98Write),
+    /// Wait for a number of syncs (`0xFF` for one, `0xFE` with a
+    /// variable-length count otherwise).
+    Sync(u32),
+    /// End of the data, or jump to the loop point (`0xFD`).
+    End,
+}
+
+impl S98Command {
+    /// Append the encoding of this command to `dest`. `Sync(0)` writes
+    /// nothing.
+    pub fn to_s98_bytes(&self, dest: &mut Vec<u8>) {
+        match *self {
+            S98Command::Write(write) => {
+                dest.push(((write.device & 0x3F) << 1) | (write.port & 1));
+                dest.push(write.register);
+                dest.push(write.value);
+            }
+            S98Command::Sync(0) => {}
+            S98Command::Sync(1) => dest.push(OP_SYNC),
+            S98Command::Sync(ticks) => {
+                dest.push(OP_SYNC_N);
+                // Seven bits per byte, least significant first, high bit set
+                // on all but the last byte; the count is stored minus two.
+                let mut rest = ticks - 2;
+                while rest >= 0x80 {
+                    dest.push((rest as u8 & 0x7F) | 0x80);
+                    rest >>= 7;
+                }
+                dest.push(rest as u8);
+            }
+            S98Command::End => dest.push(OP_END),
+        }
+    }
+
+    /// Syncs this command waits.
+    pub fn sync_ticks(&self) -> u32 {
+        match self {
+            S98Command::Sync(ticks) => *ticks,
+            _ => 0,
+        }
+    }
+}
+
+/// Parse the command at `offset`, returning it with its length in bytes.
+///
+/// # Errors
+/// Returns a range error when the command is truncated (streams treat it
+/// as needing more data), `ParseError::UnknownOpcode` for `0x80..=0xFC`
+/// and `ParseError::DataInconsistency` for a sync count above `u32::MAX`.
+pub fn parse_s98_command(bytes: &[u8], offset: usize) -> Result<(S98Command, usize), ParseError> {
+    let opcode = read_u8_at(bytes, offset)?;
+    match opcode {
+        0x00..=0x7F => {
+            let register = read_u8_at(bytes, offset + 1)?;
+            let value = read_u8_at(bytes, offset + 2)?;
+            let write = S98Write {
+                device: opcode >> 1,
+                port: opcode & 1,
+                register,
+                value,
+            };
+            Ok((S98Command::Write(write), 3))
+        }
+        OP_SYNC => Ok((S98Command::Sync(1), 1)),
+        OP_SYNC_N => {
+            let mut count: u64 = 0;
+            let mut len = 1;
+            loop {
+                let byte = read_u8_at(bytes, offset + len)?;
+                let shift = 7 * (len - 1);
+                if shift >= 35 {
+                    return Err(ParseError::DataInconsistency(format!(
+                        "S98 sync count at {:#X} is too long",
+                        offset
+                    )));
+                }
+                count |= ((byte & 0x7F) as u64) << shift;
+                len += 1;
+                if byte & 0x80 == 0 {
+                    break;
+                }
+            }
+            let ticks = u32::try_from(count + 2).map_err(|_| {
+                ParseError::DataInconsistency(format!(
+                    "S98 sync count {} at {:#X} does not fit in 32 bits",
+                    count + 2,
+                    offset
+                ))
+            })?;
+            Ok((S98Command::Sync(ticks), len))
+        }
+        OP_END => Ok((S98Command::End, 1)),
+        _ => Err(ParseError::UnknownOpcode { opcode, offset }),
+    }
+}
+
+/// Tags of an S98 file.
+///
+/// Version 3 files store `key=value` lines after a `[S98]` marker; earlier
+/// versions store a single title, read as the `title` entry. Values are the
+/// stored bytes: UTF-8 when `utf8` is set (the block starts with a byte
+/// order mark), Shift_JIS by convention otherwise. Common keys are `title`,
+/// `artist`, `game`, `year`, `genre`, `comment`, `copyright`, `s98by` and
+/// `system`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct S98Tags {
+    pub utf8: bool,
+    pub entries: Vec<(String, Vec<u8>)>,
+}
+
+impl S98Tags {
+    /// The value of the first entry named `key` (ASCII case-insensitive).
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        self.entries
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value.as_slice())
+    }
+
+    /// The value of `key` as text: UTF-8 values, and Shift_JIS values that
+    /// are plain ASCII. `None` for other Shift_JIS text, which this crate
+    /// does not decode.
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        let value = self.get(key)?;
+        if !self.utf8 && !value.is_ascii() {
+            return None;
+        }
+        std::str::from_utf8(value).ok()
+    }
+
+    /// Set `key` to `value`, replacing the first entry with that name or
+    /// appending a new one.
+    pub fn set(&mut self, key: &str, value: impl Into<Vec<u8>>) {
+        let value = value.into();
+        match self
+            .entries
+            .iter_mut()
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+        {
+            Some(entry) => entry.1 = value,
+            None => self.entries.push((key.to_string(), value)),
+        }
+    }
+
+    fn parse(bytes: &[u8], version: u8) -> Self {
+        let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+        let block = &bytes[..end];
+        let Some(lines) = block.strip_prefix(TAG_MARKER).filter(|_| version >= 3) else {
+            return S98Tags {
+                utf8: false,
+                entries: vec![("title".to_string(), block.to_vec())],
+            };
+        };
+        let (utf8, lines) = match lines.strip_prefix(UTF8_BOM) {
+            Some(rest) => (true, rest),
+            None => (false, lines),
+        };
+        let entries = lines
+            .split(|b| *b == b'\n')
+            .filter_map(|line| {
+                let line = line.strip_suffix(b"\r").unwrap_or(line);
+                let eq = line.iter().position(|b| *b == b'=')?;
+                let key = String::from_utf8_lossy(&line[..eq]).into_owned();
+                Some((key, line[eq + 1..].to_vec()))
+            })
+            .collect();
+        S98Tags { utf8, entries }
+    }
+
+    fn to_bytes(&self, version: u8) -> Vec<u8> {
+        let mut out = Vec::new();
+        if version >= 3 {
+            out.extend_from_slice(TAG_MARKER);
+            if self.utf8 {
+                out.extend_from_slice(UTF8_BOM);
+            }
+            for (key, value) in &self.entries {
+                out.extend_from_slice(key.as_bytes());
+                out.push(b'=');
+                out.extend_from_slice(value);
+                out.push(b'\n');
+            }
+        } else if let Some(title) = self.get("title") {
+            out.extend_from_slice(title);
+        }
+        out.push(0);
+        out
+    }
+}
+
+/// A complete S98 document: header, commands and optional tags.
+///
+/// Construct with `S98Builder` or parse with `S98Document::try_from`.
+/// Serialization (`Vec::<u8>::from(&document)`) lays the file out as header,
+/// device list, commands and tags, and recomputes the header offsets; the
+/// loop point stays on the command `loop_command_index` resolves to.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct S98Document {
+    pub header: S98Header,
+    pub commands: Vec<S98Command>,
+    pub tags: Option<S98Tags>,
+}
+
+impl S98Document {
+    /// Iterate over the commands.
+    pub fn iter(&self) -> std::slice::Iter<'_, S98Command> {
+        self.commands.iter()
+    }
+
+    /// Total syncs of the commands.
+    pub fn total_ticks(&self) -> u64 {
+        self.commands.iter().map(|c| c.sync_ticks() as u64).sum()
+    }
+
+    /// Playback length of one pass through the commands.
+    pub fn duration(&self) -> std::time::Duration {
+        ticks_to_duration(self.total_ticks(), self.header.effective_timer())
+    }
+
+    /// Index of the command `header.loop_offset` points to, `None` without a
+    /// loop or when the offset is not at a command.
+    pub fn loop_command_index(&self) -> Option<usize> {
+        if self.header.loop_offset == 0 {
+            return None;
+        }
+        let target =
+            (self.header.loop_offset as usize).checked_sub(self.header.data_offset as usize)?;
+        let mut offset = 0;
+        let mut bytes = Vec::new();
+        for (index, command) in self.commands.iter().enumerate() {
+            if offset == target {
+                return Some(index);
+            }
+            bytes.clear();
+            command.to_s98_bytes(&mut bytes);
+            offset += bytes.len();
+        }
+        None
+    }
+
+    // Header offsets for the canonical layout with the loop at `loop_index`.
+    fn layout(&mut self, loop_index: Option<usize>) -> Vec<u8> {
+        let header_len = self.header.header_len();
+        let mut data = Vec::new();
+        let mut loop_offset = 0;
+        for (index, command) in self.commands.iter().enumerate() {
+            if Some(index) == loop_index {
+                loop_offset = header_len + data.len();
+            }
+            command.to_s98_bytes(&mut data);
+        }
+        self.header.data_offset = header_len as u32;
+        self.header.loop_offset = loop_offset as u32;
+        self.header.tag_offset = match self.tags {
+            Some(_) => (header_len + data.len()) as u32,
+            None => 0,
+        };
+        data
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut document = self.clone();
+        let data = document.layout(self.loop_command_index());
+        let mut out = document.header.to_bytes();
+        out.extend_from_slice(&data);
+        if let Some(tags) = &document.tags {
+            out.extend_from_slice(&tags.to_bytes(document.header.version));
+        }
+        out
+    }
+}
+
+impl TryFrom<&[u8]> for S98Document {
+    type Error = ParseError;
+
+    /// Parse an S98 file. Commands are read from `data_offset` up to the
+    /// first `End`, the tag offset or the end of the input.
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let header = S98Header::from_bytes(bytes)?;
+        let start = header.data_offset as usize;
+        let tag_start = header.tag_offset as usize;
+        let end = if tag_start > start {
+            tag_start.min(bytes.len())
+        } else {
+            bytes.len()
+        };
+        if start > end {
+            return Err(ParseError::OffsetOutOfRange {
+                offset: start,
+                needed: 1,
+                available: bytes.len(),
+                context: Some("s98:data_offset".into()),
+            });
+        }
+        let mut commands = Vec::new();
+        let mut offset = start;
+        while offset < end {
+            let (command, len) = parse_s98_command(&bytes[..end], offset)?;
+            commands.push(command);
+            offset += len;
+            if command == S98Command::End {
+                break;
+            }
+        }
+        let tags = match tag_start {
+            0 => None,
+            _ => Some(S98Tags::parse(
+                read_slice(bytes, tag_start, bytes.len().saturating_sub(tag_start))?,
+                header.version,
+            )),
+        };
+        Ok(S98Document {
+            header,
+            commands,
+            tags,
+        })
+    }
+}
+
+impl From<&S98Document> for Vec<u8> {
+    fn from(document: &S98Document) -> Self {
+        document.to_bytes()
+    }
+}
+
+impl From<S98Document> for Vec<u8> {
+    fn from(document: S98Document) -> Self {
+        document.to_bytes()
+    }
+}
+
+/// Builder for assembling an `S98Document`.
+///
+/// Devices are numbered in the order they are added. `finalize` appends
+/// `S98Command::End` when it is missing and computes the header offsets.
+#[derive(Debug, Clone, Default)]
+pub struct S98Builder {
+    document: S98Document,
+    loop_index: Option<usize>,
+}
+
+impl S98Builder {
+    /// A builder for a version 3 file with the 10 ms default timer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the format version (`1` to `3`). Versions before 3 have no
+    /// device list.
+    pub fn set_version(&mut self, version: u8) -> &mut Self {
+        self.document.header.version = version;
+        self
+    }
+
+    /// Set the length of a sync to `numerator / denominator` seconds.
+    pub fn set_timer(&mut self, numerator: u32, denominator: u32) -> &mut Self {
+        self.document.header.timer_numerator = numerator;
+        self.document.header.timer_denominator = denominator;
+        self
+    }
+
+    /// Append a device to the device list.
+    pub fn add_device(&mut self, device_type: S98DeviceType, clock: u32) -> &mut Self {
+        self.document.header.devices.push(S98Device {
+            device_type,
+            clock,
+            pan: 0,
+        });
+        self
+    }
+
+    /// Append a command.
+    pub fn add_command(&mut self, command: S98Command) -> &mut Self {
+        self.document.commands.push(command);
+        self
+    }
+
+    /// Append a register write to device `device`.
+    pub fn add_write(&mut self, device: u8, port: u8, register: u8, value: u8) -> &mut Self {
+        self.add_command(S98Command::Write(S98Write {
+            device,
+            port,
+            register,
+            value,
+        }))
+    }
+
+    /// Append a wait of `ticks` syncs; zero adds nothing.
+    pub fn add_sync(&mut self, ticks: u32) -> &mut Self {
+        if ticks > 0 {
+            self.add_command(S98Command::Sync(ticks));
+        }
+        self
+    }
+
+    /// Loop back to the command at `index` of the finished document.
+    pub fn set_loop_index(&mut self, index: usize) -> &mut Self {
+        self.loop_index = Some(index);
+        self
+    }
+
+    /// Set the tags.
+    pub fn set_tags(&mut self, tags: S98Tags) -> &mut Self {
+        self.document.tags = Some(tags);
+        self
+    }
+
+    /// Finish the document.
+    pub fn finalize(mut self) -> S98Document {
+        if self.document.commands.last() != Some(&S98Command::End) {
+            self.document.commands.push(S98Command::End);
+        }
+        let loop_index = self
+            .loop_index
+            .filter(|index| *index < self.document.commands.len());
+        self.document.layout(loop_index);
+        self.document
+    }
+}
+
+pub(crate) fn ticks_to_duration(
+    ticks: u64,
+    (numerator, denominator): (u32, u32),
+) -> std::time::Duration {
+    let nanos = ticks as u128 * numerator as u128 * 1_000_000_000 / denominator as u128;
+    std::time::Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+}
+
+fn le_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
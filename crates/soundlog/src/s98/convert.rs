@@ -0,0 +1,284 @@
+//! Conversion between S98 and VGM.
+//!
+//! `to_vgm` and `from_vgm` translate register writes of the chips both
+//! formats support (AY-3-8910/YM2149, YM2203, YM2608, YM2612, YM2151,
+//! YM2413, YM3526, YM3812, YMF262 and SN76489), syncs and waits, the loop
+//! point and the tags. VGM holds at most two instances of a chip, so further
+//! S98 devices of the same type are dropped; VGM commands without an S98
+//! equivalent (data blocks, DAC streams, other chips) are dropped too. The
+//! numbers are returned in an `S98ConvertReport`.
+//!
+//! `from_vgm` writes a timer of one sync per VGM sample (1/44100 s), so
+//! waits are kept exactly. `to_vgm` rounds syncs to samples without
+//! accumulating drift.
+//!
+//! ```rust
+//! use soundlog::s98::convert::{from_vgm, to_vgm};
+//! use soundlog::s98::{S98Builder, S98DeviceType};
+//!
+//! let mut builder = S98Builder::new();
+//! builder.add_device(S98DeviceType::Ym2151, 4_000_000);
+//! builder.add_write(0, 0, 0x08, 0x78);
+//! builder.add_sync(100);
+//! let s98 = builder.finalize();
+//!
+//! let (vgm, report) = to_vgm(&s98);
+//! assert_eq!(report.dropped_commands, 0);
+//! assert_eq!(vgm.header.ym2151_clock, 4_000_000);
+//! // 100 syncs of 10 ms.
+//! assert_eq!(vgm.header.total_samples, 44_100);
+//!
+//! let (back, _) = from_vgm(&vgm);
+//! assert_eq!(back.duration(), s98.duration());
+//! ```
+use std::collections::HashMap;
+
+use crate::chip::Chip;
+use crate::meta::Gd3;
+use crate::s98::{S98Builder, S98Command, S98DeviceType, S98Document, S98Tags, S98Write};
+use crate::vgm::command::{Instance, RegisterWrite, VgmCommand, WaitSamples};
+use crate::vgm::header::{Ay8910ChipType, DEFAULT_SAMPLE_RATE};
+use crate::vgm::{VgmBuilder, VgmDocument};
+
+/// What a conversion could not carry over.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct S98ConvertReport {
+    /// Devices or chip instances the target format cannot hold.
+    pub dropped_devices: usize,
+    /// Commands with no equivalent in the target format, including writes
+    /// to dropped devices.
+    pub dropped_commands: usize,
+    /// Tags that could not be converted: Shift_JIS text in `to_vgm`.
+    pub dropped_tags: usize,
+}
+
+/// GD3 fields and the S98 tag keys they correspond to. The first field of
+/// a key is the one `to_vgm` fills.
+const TAG_FIELDS: [(&str, &str); 9] = [
+    ("title", "track_name_en"),
+    ("title", "track_name_origin"),
+    ("game", "game_name_en"),
+    ("game", "game_name_origin"),
+    ("system", "system_name_en"),
+    ("system", "system_name_origin"),
+    ("artist", "author_name_en"),
+    ("year", "release_date"),
+    ("s98by", "creator"),
+];
+
+/// Convert an S98 document to VGM.
+pub fn to_vgm(document: &S98Document) -> (VgmDocument, S98ConvertReport) {
+    let mut report = S98ConvertReport::default();
+    let mut builder = VgmBuilder::new();
+
+    // Device index to VGM chip instance.
+    let mut targets: Vec<Option<(Chip, Instance)>> = Vec::new();
+    let mut instances: HashMap<Chip, usize> = HashMap::new();
+    let mut ym2149 = false;
+    for device in document.header.effective_devices() {
+        let Some(chip) = device.device_type.chip() else {
+            if device.device_type != S98DeviceType::None {
+                report.dropped_devices += 1;
+            }
+            targets.push(None);
+            continue;
+        };
+        let count = instances.entry(chip.clone()).or_insert(0);
+        let instance = match *count {
+            0 => Instance::Primary,
+            1 => Instance::Secondary,
+            _ => {
+                report.dropped_devices += 1;
+                targets.push(None);
+                continue;
+            }
+        };
+        *count += 1;
+        ym2149 |= device.device_type == S98DeviceType::Ym2149;
+        builder.register_chip(chip.clone(), instance, device.clock);
+        targets.push(Some((chip, instance)));
+    }
+
+    let (numerator, denominator) = document.header.effective_timer();
+    let loop_index = document.loop_command_index();
+    let mut commands: Vec<VgmCommand> = Vec::new();
+    let mut vgm_loop_index = None;
+    let mut ticks: u128 = 0;
+    let mut samples: u128 = 0;
+    for (index, command) in document.commands.iter().enumerate() {
+        if Some(index) == loop_index {
+            vgm_loop_index = Some(commands.len());
+        }
+        match command {
+            S98Command::Write(write) => {
+                let converted = targets
+                    .get(write.device as usize)
+                    .cloned()
+                    .flatten()
+                    .and_then(|(chip, instance)| {
+                        RegisterWrite {
+                            register: match chip {
+                                Chip::Sn76489 => 0,
+                                _ => write.register as u32,
+                            },
+                            chip,
+                            instance,
+                            port: write.port,
+                            value: write.value as u32,
+                        }
+                        .to_command()
+                    });
+                match converted {
+                    Some(command) => commands.push(command),
+                    None => report.dropped_commands += 1,
+                }
+            }
+            S98Command::Sync(n) => {
+                ticks += *n as u128;
+                let target =
+                    ticks * numerator as u128 * DEFAULT_SAMPLE_RATE as u128 / denominator as u128;
+                let mut wait = target - samples;
+                samples = target;
+                while wait > 0 {
+                    let chunk = wait.min(u16::MAX as u128);
+                    commands.push(VgmCommand::WaitSamples(WaitSamples(chunk as u16)));
+                    wait -= chunk;
+                }
+            }
+            S98Command::End => break,
+        }
+    }
+    builder.add_vgm_commands(commands);
+    if let Some(index) = vgm_loop_index {
+        builder.set_loop_offset(index);
+    }
+    if let Some(tags) = &document.tags {
+        builder.set_gd3(tags_to_gd3(tags, &mut report));
+    }
+
+    let mut vgm = builder.finalize();
+    if instances.contains_key(&Chip::Ay8910) {
+        vgm.header.ay_chip_type = if ym2149 {
+            Ay8910ChipType::Ym2149
+        } else {
+            Ay8910ChipType::Ay8910
+        };
+    }
+    (vgm, report)
+}
+
+/// Convert a VGM document to S98 (version 3, one sync per sample).
+pub fn from_vgm(document: &VgmDocument) -> (S98Document, S98ConvertReport) {
+    let mut report = S98ConvertReport::default();
+    let mut builder = S98Builder::new();
+    builder.set_timer(1, DEFAULT_SAMPLE_RATE);
+
+    let mut devices: HashMap<(Chip, Instance), u8> = HashMap::new();
+    for (instance, chip, _) in document.header.chip_instances().iter() {
+        let Some(mut device_type) = S98DeviceType::from_chip(chip) else {
+            report.dropped_devices += 1;
+            continue;
+        };
+        if *chip == Chip::Ay8910 && document.header.ay_chip_type == Ay8910ChipType::Ym2149 {
+            device_type = S98DeviceType::Ym2149;
+        }
+        // Bits 30 and 31 of a clock field are flags.
+        let clock = document.header.get_chip_clock(chip) & 0x3FFF_FFFF;
+        devices.insert((chip.clone(), *instance), devices.len() as u8);
+        builder.add_device(device_type, clock);
+    }
+
+    let loop_index = document.loop_command_index();
+    let mut s98_loop_index = None;
+    let mut pending: u64 = 0;
+    let mut count = 0;
+    let flush = |builder: &mut S98Builder, pending: &mut u64, count: &mut usize| {
+        while *pending > 0 {
+            let ticks = (*pending).min(u32::MAX as u64) as u32;
+            builder.add_sync(ticks);
+            *pending -= ticks as u64;
+            *count += 1;
+        }
+    };
+    for (index, command) in document.commands.iter().enumerate() {
+        if Some(index) == loop_index {
+            flush(&mut builder, &mut pending, &mut count);
+            s98_loop_index = Some(count);
+        }
+        let wait = command.wait_samples();
+        let write = command.register_write().and_then(|write| {
+            let device = *devices.get(&(write.chip.clone(), write.instance))?;
+            Some(S98Write {
+                device,
+                port: write.port,
+                register: u8::try_from(write.register).ok()?,
+                value: u8::try_from(write.value).ok()?,
+            })
+        });
+        match (write, command) {
+            (Some(write), _) if write.port <= 1 => {
+                flush(&mut builder, &mut pending, &mut count);
+                builder.add_command(S98Command::Write(write));
+                count += 1;
+                // `0x8n` writes wait after writing.
+                pending += wait as u64;
+            }
+            (_, VgmCommand::EndOfData(_)) => break,
+            _ if wait > 0 && command.register_write().is_none() => pending += wait as u64,
+            _ => report.dropped_commands += 1,
+        }
+    }
+    flush(&mut builder, &mut pending, &mut count);
+    if let Some(index) = s98_loop_index {
+        builder.set_loop_index(index);
+    }
+    if let Some(gd3) = &document.gd3 {
+        builder.set_tags(gd3_to_tags(gd3));
+    }
+    (builder.finalize(), report)
+}
+
+fn tags_to_gd3(tags: &S98Tags, report: &mut S98ConvertReport) -> Gd3 {
+    let mut gd3 = Gd3::default();
+    for (key, _) in &tags.entries {
+        let field = TAG_FIELDS
+            .iter()
+            .find(|(tag, _)| tag.eq_ignore_ascii_case(key))
+            .map(|(_, field)| *field)
+            .or_else(|| key.eq_ignore_ascii_case("comment").then_some("notes"));
+        let Some(field) = field else {
+            continue;
+        };
+        match tags.get_str(key) {
+            Some(value) => {
+                if let Some(slot) = gd3.field_mut(field) {
+                    slot.get_or_insert_with(|| value.to_string());
+                }
+            }
+            None => report.dropped_tags += 1,
+        }
+    }
+    gd3
+}
+
+fn gd3_to_tags(gd3: &Gd3) -> S98Tags {
+    let mut tags = S98Tags {
+        utf8: true,
+        entries: Vec::new(),
+    };
+    let values = gd3.fields();
+    let fields = TAG_FIELDS.iter().chain(&[("comment", "notes")]);
+    for (key, field) in fields {
+        if tags.get(key).is_some() {
+            continue;
+        }
+        let value = Gd3::FIELDS
+            .iter()
+            .position(|name| name == field)
+            .and_then(|index| values[index].as_deref());
+        if let Some(value) = value.filter(|value| !value.is_empty()) {
+            tags.set(key, value);
+        }
+    }
+    tags
+}
@@ -0,0 +1,333 @@
+//! S98 streaming.
+//!
+//! `S98Stream` yields the commands of an S98 file one at a time, like
+//! `VgmStream` does for VGM: from raw command bytes fed in chunks
+//! (`push_chunk`), from a complete file (`from_s98`) or from a parsed
+//! document (`from_document`). `S98Command::End` is not yielded; it jumps to
+//! the loop point until the loop count is reached, then the stream returns
+//! `S98StreamResult::EndOfStream`.
+//!
+//! ```rust
+//! use soundlog::s98::stream::{S98Stream, S98StreamResult};
+//! use soundlog::s98::{S98Builder, S98Command};
+//!
+//! let mut builder = S98Builder::new();
+//! builder.add_sync(3);
+//! builder.set_loop_index(0);
+//! let bytes: Vec<u8> = builder.finalize().into();
+//!
+//! let mut stream = S98Stream::from_s98(bytes).unwrap();
+//! stream.set_loop_count(Some(2));
+//! let mut commands = Vec::new();
+//! while let Some(Ok(S98StreamResult::Command(command))) = stream.next() {
+//!     commands.push(command);
+//! }
+//! assert_eq!(commands, vec![S98Command::Sync(3), S98Command::Sync(3)]);
+//! assert_eq!(stream.ticks(), 6);
+//! ```
+use crate::binutil::ParseError;
+use crate::s98::{S98Command, S98Document, S98Header, parse_s98_command, ticks_to_duration};
+
+/// Maximum size of the buffer `push_chunk` fills, like `VgmStream`.
+const DEFAULT_MAX_BUFFER_SIZE: usize = 64 * 1024 * 1024;
+
+/// Result of `S98Stream::next`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum S98StreamResult {
+    /// A complete command was parsed.
+    Command(S98Command),
+    /// More data is needed to complete the current command.
+    NeedsMoreData,
+    /// The stream has ended.
+    EndOfStream,
+}
+
+#[derive(Debug)]
+enum S98StreamSource {
+    /// Command bytes fed with `push_chunk`; `pos` is the first unread byte.
+    Buffer { buffer: Vec<u8>, pos: usize },
+    /// A complete file.
+    File {
+        data: Vec<u8>,
+        data_start: usize,
+        current_pos: usize,
+        loop_pos: Option<usize>,
+    },
+    /// A parsed document.
+    Document {
+        document: S98Document,
+        current_index: usize,
+        loop_index: Option<usize>,
+    },
+}
+
+/// Iterator over the commands of an S98 file with loop handling.
+#[derive(Debug)]
+pub struct S98Stream {
+    source: S98StreamSource,
+    timer: (u32, u32),
+    loop_count: Option<u32>,
+    current_loops: u32,
+    ticks: u64,
+    ended: bool,
+    max_buffer_size: usize,
+}
+
+impl Default for S98Stream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl S98Stream {
+    /// A stream fed with `push_chunk`, using the default 10 ms timer and
+    /// playing once.
+    pub fn new() -> Self {
+        S98Stream {
+            source: S98StreamSource::Buffer {
+                buffer: Vec::new(),
+                pos: 0,
+            },
+            timer: S98Header::default().effective_timer(),
+            loop_count: Some(1),
+            current_loops: 0,
+            ticks: 0,
+            ended: false,
+            max_buffer_size: DEFAULT_MAX_BUFFER_SIZE,
+        }
+    }
+
+    /// A stream over the commands of `document`.
+    pub fn from_document(document: S98Document) -> Self {
+        let timer = document.header.effective_timer();
+        let loop_index = document.loop_command_index();
+        S98Stream {
+            source: S98StreamSource::Document {
+                document,
+                current_index: 0,
+                loop_index,
+            },
+            timer,
+            ..Self::new()
+        }
+    }
+
+    /// A stream over a complete S98 file, parsing commands as they are
+    /// read.
+    ///
+    /// # Errors
+    /// Returns an error when the header is invalid or the data offset lies
+    /// outside the file.
+    pub fn from_s98(data: impl Into<Vec<u8>>) -> Result<Self, ParseError> {
+        let data = data.into();
+        let header = S98Header::from_bytes(&data)?;
+        let data_start = header.data_offset as usize;
+        if data_start > data.len() {
+            return Err(ParseError::OffsetOutOfRange {
+                offset: data_start,
+                needed: 1,
+                available: data.len(),
+                context: Some("s98:data_offset".into()),
+            });
+        }
+        let loop_pos = Some(header.loop_offset as usize)
+            .filter(|pos| *pos != 0 && (data_start..data.len()).contains(pos));
+        Ok(S98Stream {
+            source: S98StreamSource::File {
+                data,
+                data_start,
+                current_pos: data_start,
+                loop_pos,
+            },
+            timer: header.effective_timer(),
+            ..Self::new()
+        })
+    }
+
+    /// Append command bytes to a stream created with `new`.
+    ///
+    /// # Errors
+    /// Returns `ParseError::Other` if the buffer would exceed its size limit
+    /// or the stream was not created with `new`.
+    pub fn push_chunk(&mut self, chunk: &[u8]) -> Result<(), ParseError> {
+        match &mut self.source {
+            S98StreamSource::Buffer { buffer, pos } => {
+                // Drop the bytes already parsed before growing the buffer.
+                buffer.drain(..*pos);
+                *pos = 0;
+                if buffer.len() + chunk.len() > self.max_buffer_size {
+                    return Err(ParseError::Other(format!(
+                        "Buffer size limit exceeded: current {} bytes, chunk {} bytes, limit {} bytes",
+                        buffer.len(),
+                        chunk.len(),
+                        self.max_buffer_size
+                    )));
+                }
+                buffer.extend_from_slice(chunk);
+                Ok(())
+            }
+            _ => Err(ParseError::Other(
+                "push_chunk() can only be called on an S98Stream created with S98Stream::new"
+                    .into(),
+            )),
+        }
+    }
+
+    /// Sets how many times to play through the loop; `None` loops forever
+    /// and `Some(0)` is the same as `Some(1)`.
+    pub fn set_loop_count(&mut self, count: Option<u32>) {
+        self.loop_count = match count {
+            Some(0) => Some(1),
+            _ => count,
+        };
+    }
+
+    /// Number of `End` commands passed so far.
+    pub fn current_loop_count(&self) -> u32 {
+        self.current_loops
+    }
+
+    /// Sets the sync length for streams created with `new`, which have no
+    /// header to read it from.
+    pub fn set_timer(&mut self, numerator: u32, denominator: u32) {
+        self.timer = S98Header {
+            timer_numerator: numerator,
+            timer_denominator: denominator,
+            ..S98Header::default()
+        }
+        .effective_timer();
+    }
+
+    /// Syncs yielded so far, across loops.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// Playback time of the syncs yielded so far.
+    pub fn elapsed(&self) -> std::time::Duration {
+        ticks_to_duration(self.ticks, self.timer)
+    }
+
+    /// Sets the maximum number of bytes `push_chunk` may buffer.
+    pub fn set_max_buffer_size(&mut self, max_size: usize) {
+        self.max_buffer_size = max_size;
+    }
+
+    fn next_raw_command(&mut self) -> Result<Option<S98Command>, ParseError> {
+        match &mut self.source {
+            S98StreamSource::Buffer { buffer, pos } => match parse_s98_command(buffer, *pos) {
+                Ok((command, len)) => {
+                    *pos += len;
+                    Ok(Some(command))
+                }
+                Err(ParseError::OffsetOutOfRange { .. } | ParseError::UnexpectedEof) => Ok(None),
+                Err(e) => Err(e),
+            },
+            S98StreamSource::File {
+                data, current_pos, ..
+            } => {
+                if *current_pos >= data.len() {
+                    // A file without `End` ends with its data.
+                    return Ok(Some(S98Command::End));
+                }
+                let (command, len) = parse_s98_command(data, *current_pos)?;
+                *current_pos += len;
+                Ok(Some(command))
+            }
+            S98StreamSource::Document {
+                document,
+                current_index,
+                ..
+            } => {
+                let command = document
+                    .commands
+                    .get(*current_index)
+                    .copied()
+                    .unwrap_or(S98Command::End);
+                *current_index += 1;
+                Ok(Some(command))
+            }
+        }
+    }
+
+    // Jump to the loop point; `false` when there is none.
+    fn jump_to_loop_point(&mut self) -> bool {
+        match &mut self.source {
+            S98StreamSource::Buffer { .. } => false,
+            S98StreamSource::File {
+                current_pos,
+                loop_pos,
+                ..
+            } => match loop_pos {
+                Some(pos) => {
+                    *current_pos = *pos;
+                    true
+                }
+                None => false,
+            },
+            S98StreamSource::Document {
+                current_index,
+                loop_index,
+                ..
+            } => match loop_index {
+                Some(index) => {
+                    *current_index = *index;
+                    true
+                }
+                None => false,
+            },
+        }
+    }
+
+    fn next_result(&mut self) -> Result<S98StreamResult, ParseError> {
+        // A loop without commands would jump forever.
+        let mut jumped = false;
+        loop {
+            if self.ended {
+                return Ok(S98StreamResult::EndOfStream);
+            }
+            let Some(command) = self.next_raw_command()? else {
+                return Ok(S98StreamResult::NeedsMoreData);
+            };
+            if command != S98Command::End {
+                self.ticks += command.sync_ticks() as u64;
+                return Ok(S98StreamResult::Command(command));
+            }
+            self.current_loops = self.current_loops.saturating_add(1);
+            let done = self
+                .loop_count
+                .is_some_and(|count| self.current_loops >= count);
+            if done || jumped || !self.jump_to_loop_point() {
+                self.ended = true;
+            }
+            jumped = true;
+        }
+    }
+
+    /// Restart from the beginning of a file or document.
+    pub fn reset(&mut self) {
+        match &mut self.source {
+            S98StreamSource::Buffer { buffer, pos } => {
+                buffer.clear();
+                *pos = 0;
+            }
+            S98StreamSource::File {
+                data_start,
+                current_pos,
+                ..
+            } => *current_pos = *data_start,
+            S98StreamSource::Document { current_index, .. } => *current_index = 0,
+        }
+        self.current_loops = 0;
+        self.ticks = 0;
+        self.ended = false;
+    }
+}
+
+impl Iterator for S98Stream {
+    type Item = Result<S98StreamResult, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_result())
+    }
+}
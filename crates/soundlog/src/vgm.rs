@@ -3,15 +3,52 @@
 //! This module exposes the VGM document and header types and re-exports
 //! submodules for command parsing/serialization and the GD3/extra-header
 //! handling utilities.
+pub mod align;
+pub mod analysis;
+pub mod annotation;
+pub mod borrowed;
 pub mod callback_stream;
 pub mod command;
+pub mod compression;
+pub mod cue;
 pub mod detail;
+pub mod diff;
 mod document;
+pub mod driver;
+pub mod export;
+pub mod frame;
+pub mod frame_rate;
 pub mod header;
+pub mod heatmap;
+pub mod incremental;
+pub mod ir;
+pub mod lint;
+pub mod loop_check;
+#[cfg(feature = "midi")]
+pub mod midi;
+pub mod opcode;
+#[cfg(feature = "osc")]
+pub mod osc;
 pub mod parser;
+pub mod repair;
+pub mod rle;
+pub mod rom;
+pub mod script;
+pub mod shared;
+pub mod snapshot;
 pub mod stream;
+pub mod transform;
+pub mod usage;
+pub mod verify;
+pub mod writer;
 
+pub use borrowed::VgmDocumentRef;
 pub use callback_stream::{VgmCallbackStream, WriteCallbackTarget};
-pub use document::{VgmBuilder, VgmDocument};
+pub use document::{
+    BuildError, MemoryFootprint, StrictVgmBuilder, Timeline, VgmBuilder, VgmDocument, WaitCount,
+    WaitStats,
+};
 pub use header::{VgmExtraHeader, VgmHeader, VgmHeaderField};
+pub use shared::SharedVgmDocument;
 pub use stream::VgmStream;
+pub use writer::VgmStreamWriter;
@@ -0,0 +1,325 @@
+//! Sample-accurate alignment of two renditions of the same song.
+//!
+//! Two rips of a song (from different emulators, loggers or a
+//! re-implementation of its sound driver) rarely start at the same sample,
+//! and a driver with a slightly wrong tempo drifts further apart over the
+//! track. `align_documents` lines the two up from their key-on events:
+//!
+//! 1. The key-ons of every channel are collected with `ChannelTimeline`.
+//! 2. A first estimate of the offset is the time difference shared by the
+//!    most key-on pairs on the same channel (a histogram of differences up
+//!    to `AlignOptions::max_offset`, refined to the median of the best bin).
+//! 3. Key-ons are matched channel by channel in time order, starting from
+//!    the estimate and following the drift: each match moves the expected
+//!    position of the next one, so a slow tempo difference stays matched as
+//!    long as two consecutive key-ons drift less than
+//!    `AlignOptions::tolerance` apart.
+//! 4. The start offset is the difference of the first matched pair, and the
+//!    drift of every pair (its difference minus the start offset) is
+//!    summarized per window of `AlignOptions::window` samples.
+//!
+//! Only chips with key state tracking are considered, see
+//! `vgm::analysis`. Times are in samples (44.1 kHz).
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::chip::{Chip, Ym2612Spec};
+//! use soundlog::vgm::align::{AlignOptions, align_documents};
+//! use soundlog::vgm::command::{Instance, WaitSamples};
+//!
+//! let build = |delay: u16| {
+//!     let mut builder = VgmBuilder::new();
+//!     builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_453);
+//!     builder.add_vgm_command(WaitSamples(delay));
+//!     for _ in 0..8 {
+//!         for value in [0xF0, 0x00] {
+//!             builder.add_chip_write(Instance::Primary, Ym2612Spec { port: 0, register: 0x28, value });
+//!             builder.add_vgm_command(WaitSamples(4410));
+//!         }
+//!     }
+//!     builder.finalize()
+//! };
+//!
+//! let alignment = align_documents(&build(1), &build(101), &AlignOptions::new());
+//! assert_eq!(alignment.offset, 100);
+//! assert_eq!(alignment.pairs.len(), 8);
+//! assert_eq!(alignment.max_drift(), 0);
+//! ```
+use std::collections::HashMap;
+
+use crate::chip::Chip;
+use crate::vgm::VgmDocument;
+use crate::vgm::analysis::{ChannelTimeline, run_analyses};
+use crate::vgm::command::Instance;
+
+/// Options for `align_documents`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlignOptions {
+    /// Largest start offset searched, in samples. Default: 2 seconds.
+    pub max_offset: u64,
+    /// Largest difference, in samples, between a key-on and the position
+    /// where its counterpart is expected. Default: 10 ms.
+    pub tolerance: u64,
+    /// Length of the drift report windows in samples (at least 1).
+    /// Default: 5 seconds.
+    pub window: u64,
+}
+
+impl Default for AlignOptions {
+    fn default() -> Self {
+        AlignOptions {
+            max_offset: 88_200,
+            tolerance: 441,
+            window: 220_500,
+        }
+    }
+}
+
+impl AlignOptions {
+    /// The default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Search start offsets up to `samples`.
+    pub fn with_max_offset(mut self, samples: u64) -> Self {
+        self.max_offset = samples;
+        self
+    }
+
+    /// Match key-ons up to `samples` away from their expected position.
+    pub fn with_tolerance(mut self, samples: u64) -> Self {
+        self.tolerance = samples;
+        self
+    }
+
+    /// Report the drift per window of `samples`.
+    pub fn with_window(mut self, samples: u64) -> Self {
+        self.window = samples;
+        self
+    }
+}
+
+/// A key-on of the left document matched with one of the right document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlignedEvent {
+    pub chip: Chip,
+    pub instance: Instance,
+    pub channel: u8,
+    /// Time of the key-on in the left document.
+    pub left: u64,
+    /// Time of the key-on in the right document.
+    pub right: u64,
+}
+
+impl AlignedEvent {
+    /// `right - left` in samples.
+    pub fn delta(&self) -> i64 {
+        self.right as i64 - self.left as i64
+    }
+}
+
+/// Drift of the pairs whose left key-on falls in one window.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DriftWindow {
+    /// Start of the window in samples of the left document.
+    pub start: u64,
+    /// Number of matched pairs in the window.
+    pub matched: usize,
+    /// Smallest, largest and mean drift in samples. The drift of a pair is
+    /// its delta minus `Alignment::offset`.
+    pub min_drift: i64,
+    pub max_drift: i64,
+    pub mean_drift: i64,
+}
+
+/// Result of `align_documents`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Alignment {
+    /// Start offset in samples: the first matched key-on at time `t` in the
+    /// left document is heard at `t + offset` in the right document. `0`
+    /// when no key-ons could be paired.
+    pub offset: i64,
+    /// Matched key-ons, in left document order.
+    pub pairs: Vec<AlignedEvent>,
+    /// Key-ons of the left document without a counterpart.
+    pub unmatched_left: usize,
+    /// Key-ons of the right document without a counterpart.
+    pub unmatched_right: usize,
+    /// Drift per window, windows without matched pairs are left out.
+    pub windows: Vec<DriftWindow>,
+    /// The window length used, see `AlignOptions::window`.
+    pub window: u64,
+}
+
+impl Alignment {
+    /// Largest absolute drift of a matched pair.
+    pub fn max_drift(&self) -> i64 {
+        self.pairs
+            .iter()
+            .map(|pair| (pair.delta() - self.offset).abs())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Drift of the last matched pair.
+    pub fn final_drift(&self) -> Option<i64> {
+        self.pairs.last().map(|pair| pair.delta() - self.offset)
+    }
+
+    /// Share of all key-ons that were matched, `0.0..=1.0`.
+    pub fn match_ratio(&self) -> f64 {
+        let total = 2 * self.pairs.len() + self.unmatched_left + self.unmatched_right;
+        if total == 0 {
+            return 0.0;
+        }
+        2.0 * self.pairs.len() as f64 / total as f64
+    }
+}
+
+type ChannelKey = (Chip, Instance, u8);
+
+/// Align `right` to `left` by their key-on events.
+pub fn align_documents(
+    left: &VgmDocument,
+    right: &VgmDocument,
+    options: &AlignOptions,
+) -> Alignment {
+    let left = key_ons(left);
+    let right_events = key_ons(right);
+    let mut right: HashMap<ChannelKey, Vec<u64>> = HashMap::new();
+    for (key, time) in right_events {
+        right.entry(key).or_default().push(time);
+    }
+    let tolerance = options.tolerance as i64;
+    let window = options.window.max(1);
+
+    let Some(estimate) = estimate_offset(&left, &right, options) else {
+        return Alignment {
+            unmatched_left: left.len(),
+            unmatched_right: right.values().map(Vec::len).sum(),
+            window,
+            ..Alignment::default()
+        };
+    };
+
+    // Match in left order, following the drift of the previous match.
+    let mut alignment = Alignment {
+        window,
+        ..Alignment::default()
+    };
+    let mut cursors: HashMap<&ChannelKey, usize> = HashMap::new();
+    let mut expected = estimate;
+    for (key, time) in &left {
+        let Some(times) = right.get(key) else {
+            alignment.unmatched_left += 1;
+            continue;
+        };
+        let cursor = cursors.entry(key).or_insert(0);
+        let target = *time as i64 + expected;
+        // Right key-ons well before the target have no counterpart.
+        while *cursor < times.len() && (times[*cursor] as i64) < target - tolerance {
+            *cursor += 1;
+            alignment.unmatched_right += 1;
+        }
+        // Take the closest of the key-ons within the tolerance.
+        while *cursor + 1 < times.len()
+            && (times[*cursor + 1] as i64 - target).abs() < (times[*cursor] as i64 - target).abs()
+        {
+            *cursor += 1;
+            alignment.unmatched_right += 1;
+        }
+        match times.get(*cursor) {
+            Some(&matched) if (matched as i64 - target).abs() <= tolerance => {
+                *cursor += 1;
+                expected = matched as i64 - *time as i64;
+                alignment.pairs.push(AlignedEvent {
+                    chip: key.0.clone(),
+                    instance: key.1,
+                    channel: key.2,
+                    left: *time,
+                    right: matched,
+                });
+            }
+            _ => alignment.unmatched_left += 1,
+        }
+    }
+    for (key, times) in &right {
+        alignment.unmatched_right += times.len() - cursors.get(key).copied().unwrap_or(0);
+    }
+
+    let offset = alignment.pairs.first().map_or(0, AlignedEvent::delta);
+    alignment.offset = offset;
+    for pair in &alignment.pairs {
+        let start = pair.left / window * window;
+        let drift = pair.delta() - offset;
+        match alignment.windows.last_mut() {
+            Some(last) if last.start == start => {
+                last.min_drift = last.min_drift.min(drift);
+                last.max_drift = last.max_drift.max(drift);
+                // Sum for now, divided below.
+                last.mean_drift += drift;
+                last.matched += 1;
+            }
+            _ => alignment.windows.push(DriftWindow {
+                start,
+                matched: 1,
+                min_drift: drift,
+                max_drift: drift,
+                mean_drift: drift,
+            }),
+        }
+    }
+    for window in &mut alignment.windows {
+        window.mean_drift /= window.matched as i64;
+    }
+    alignment
+}
+
+// Key-ons of every tracked channel, in time order.
+fn key_ons(document: &VgmDocument) -> Vec<(ChannelKey, u64)> {
+    let mut timeline = ChannelTimeline::new();
+    run_analyses(document, &mut [&mut timeline]);
+    timeline
+        .into_notes()
+        .into_iter()
+        .map(|note| ((note.chip, note.instance, note.channel), note.start))
+        .collect()
+}
+
+// The start offset shared by the most key-on pairs of the same channel.
+fn estimate_offset(
+    left: &[(ChannelKey, u64)],
+    right: &HashMap<ChannelKey, Vec<u64>>,
+    options: &AlignOptions,
+) -> Option<i64> {
+    let bin = options.tolerance.max(1) as i64;
+    let max_offset = options.max_offset as i64;
+    let mut deltas: Vec<i64> = Vec::new();
+    let mut votes: HashMap<i64, usize> = HashMap::new();
+    for (key, time) in left {
+        let Some(times) = right.get(key) else {
+            continue;
+        };
+        let time = *time as i64;
+        let first = times.partition_point(|&t| (t as i64) < time - max_offset);
+        for &t in times[first..]
+            .iter()
+            .take_while(|&&t| t as i64 <= time + max_offset)
+        {
+            let delta = t as i64 - time;
+            deltas.push(delta);
+            *votes.entry(delta.div_euclid(bin)).or_insert(0) += 1;
+        }
+    }
+    // Ties go to the offset closest to zero.
+    let (&best, _) = votes
+        .iter()
+        .max_by_key(|&(&b, &count)| (count, std::cmp::Reverse(b.abs()), b))?;
+    let mut near: Vec<i64> = deltas
+        .into_iter()
+        .filter(|delta| (delta.div_euclid(bin) - best).abs() <= 1)
+        .collect();
+    near.sort_unstable();
+    Some(near[near.len() / 2])
+}
@@ -0,0 +1,368 @@
+//! Single-pass analyses.
+//!
+//! Most analyses in this crate walk the command stream once, following the
+//! time and the register writes. Running several of them on a large document
+//! (say a heatmap, a lint and a channel timeline) walks it once per analysis,
+//! and every analysis that needs key states replays the chip state trackers
+//! again. `run_analyses` feeds any number of `Analysis` implementations from
+//! one pass instead:
+//!
+//! - every command is passed to `Analysis::command` together with an
+//!   `AnalysisContext` holding its index, its time in samples and its
+//!   register write,
+//! - when one of the analyses asks for key states (`Analysis::needs_keys`),
+//!   the `chip::state` trackers are replayed once and the context carries the
+//!   channels keyed on or off by the command. Key states are tracked for
+//!   SN76489, AY-3-8910, YM2612, YM2151, YM2203, YM2608, YM2610(B), YM2413,
+//!   YM3812, YM3526, Y8950 and YMF262,
+//! - `Analysis::finish` is called once after the last command.
+//!
+//! The analyses keep their result and return it from their own accessor:
+//! `HeatmapAnalysis`, `LintAnalysis` and `DriverAnalysis` are the single-pass
+//! forms of `register_heatmap`, `lint_with_options` and `driver_features`,
+//! and `ChannelTimeline` collects the notes played on every channel.
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::chip::{Chip, PsgSpec};
+//! use soundlog::vgm::analysis::{ChannelTimeline, run_analyses};
+//! use soundlog::vgm::command::{Instance, WaitSamples};
+//! use soundlog::vgm::heatmap::{HeatmapAnalysis, HeatmapOptions};
+//! use soundlog::vgm::lint::{LintAnalysis, LintOptions};
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+//! builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x8E });
+//! builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x0F });
+//! builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x90 });
+//! builder.add_vgm_command(WaitSamples(735));
+//! builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x9F });
+//! let doc = builder.finalize();
+//!
+//! let mut heatmap = HeatmapAnalysis::new(&HeatmapOptions::new());
+//! let mut lint = LintAnalysis::new(&LintOptions::default());
+//! let mut timeline = ChannelTimeline::new();
+//! run_analyses(&doc, &mut [&mut heatmap, &mut lint, &mut timeline]);
+//!
+//! assert_eq!(heatmap.into_heatmap().chips[0].total(), 4);
+//! assert!(lint.into_report().is_clean());
+//! let notes = timeline.into_notes();
+//! assert_eq!((notes[0].channel, notes[0].start, notes[0].end), (0, 0, Some(735)));
+//! ```
+use crate::chip::Chip;
+use crate::chip::event::{KeyState, ToneInfo};
+use crate::chip::state::{
+    Ay8910State, ChipState, Sn76489State, Y8950State, Ym2151State, Ym2203State, Ym2413State,
+    Ym2608State, Ym2610bState, Ym2612State, Ym3526State, Ym3812State, Ymf262State,
+};
+use crate::vgm::VgmDocument;
+use crate::vgm::command::{Instance, RegisterWrite, VgmCommand};
+
+/// An analysis fed by `run_analyses`.
+pub trait Analysis {
+    /// Whether the context should carry key states. The trackers are only
+    /// replayed when at least one analysis asks for them.
+    fn needs_keys(&self) -> bool {
+        false
+    }
+
+    /// Called for every command of the document, in order.
+    fn command(&mut self, context: &AnalysisContext<'_>, command: &VgmCommand);
+
+    /// Called once after the last command. The context has no write and its
+    /// time is the length of the document.
+    fn finish(&mut self, _context: &AnalysisContext<'_>) {}
+}
+
+/// A channel keyed on or off by a command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyChange {
+    pub chip: Chip,
+    pub instance: Instance,
+    pub channel: u8,
+    /// `true` for key-on.
+    pub on: bool,
+}
+
+/// Where `run_analyses` is in the document.
+pub struct AnalysisContext<'a> {
+    document: &'a VgmDocument,
+    index: usize,
+    time: u64,
+    write: Option<&'a RegisterWrite>,
+    key_changes: &'a [KeyChange],
+    chips: &'a [TrackedChip],
+}
+
+impl<'a> AnalysisContext<'a> {
+    /// The analyzed document.
+    pub fn document(&self) -> &'a VgmDocument {
+        self.document
+    }
+
+    /// Index of the current command, the number of commands in `finish`.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Time of the current command in samples (44.1 kHz).
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    /// The register write of the current command, see
+    /// `VgmCommand::register_write`.
+    pub fn write(&self) -> Option<&'a RegisterWrite> {
+        self.write
+    }
+
+    /// Channels keyed on or off by the current command. Always empty unless
+    /// an analysis asked for key states.
+    pub fn key_changes(&self) -> &'a [KeyChange] {
+        self.key_changes
+    }
+
+    /// Key state of every channel of a chip instance after the current
+    /// command, `None` when it has not been written yet, has no tracker or
+    /// no analysis asked for key states.
+    pub fn keys(&self, chip: &Chip, instance: Instance) -> Option<&'a [bool]> {
+        self.chips
+            .iter()
+            .find(|c| c.chip == *chip && c.instance == instance)
+            .map(|c| c.keys.as_slice())
+    }
+
+    /// Tone of a channel after the current command as decoded by its state
+    /// tracker, `None` when unknown or no analysis asked for key states.
+    pub fn tone(&self, chip: &Chip, instance: Instance, channel: u8) -> Option<ToneInfo> {
+        self.chips
+            .iter()
+            .find(|c| c.chip == *chip && c.instance == instance)?
+            .tracker
+            .tone(channel)
+    }
+}
+
+/// Run `analyses` over `document` in a single pass.
+pub fn run_analyses(document: &VgmDocument, analyses: &mut [&mut dyn Analysis]) {
+    let track_keys = analyses.iter().any(|analysis| analysis.needs_keys());
+    let clocks = document.header.chip_instances().0;
+    let mut chips: Vec<TrackedChip> = Vec::new();
+    let mut key_changes: Vec<KeyChange> = Vec::new();
+    let mut time = 0u64;
+    for (index, command) in document.commands.iter().enumerate() {
+        let write = command.register_write();
+        key_changes.clear();
+        if track_keys
+            && let Some(write) = &write
+            && !matches!(command, VgmCommand::GameGearPsgWrite(..))
+        {
+            replay(&mut chips, &clocks, write, &mut key_changes);
+        }
+        let context = AnalysisContext {
+            document,
+            index,
+            time,
+            write: write.as_ref(),
+            key_changes: &key_changes,
+            chips: &chips,
+        };
+        for analysis in analyses.iter_mut() {
+            analysis.command(&context, command);
+        }
+        time += command.wait_samples() as u64;
+    }
+    let context = AnalysisContext {
+        document,
+        index: document.commands.len(),
+        time,
+        write: None,
+        key_changes: &[],
+        chips: &chips,
+    };
+    for analysis in analyses.iter_mut() {
+        analysis.finish(&context);
+    }
+}
+
+struct TrackedChip {
+    chip: Chip,
+    instance: Instance,
+    tracker: Box<dyn KeyTracker>,
+    keys: Vec<bool>,
+}
+
+fn replay(
+    chips: &mut Vec<TrackedChip>,
+    clocks: &[(Instance, Chip, f32)],
+    write: &RegisterWrite,
+    key_changes: &mut Vec<KeyChange>,
+) {
+    let index = match chips
+        .iter()
+        .position(|c| c.chip == write.chip && c.instance == write.instance)
+    {
+        Some(index) => index,
+        None => {
+            let clock = clocks
+                .iter()
+                .find(|(i, c, _)| *c == write.chip && *i == write.instance)
+                .map_or(0.0, |(_, _, clock)| *clock);
+            let Some(tracker) = tracker(&write.chip, clock) else {
+                return;
+            };
+            chips.push(TrackedChip {
+                chip: write.chip.clone(),
+                instance: write.instance,
+                keys: tracker.keys(),
+                tracker,
+            });
+            chips.len() - 1
+        }
+    };
+    let chip = &mut chips[index];
+    chip.tracker
+        .write(write.port, write.register as u8, write.value as u8);
+    let keys = chip.tracker.keys();
+    for (channel, &on) in keys.iter().enumerate() {
+        if chip.keys.get(channel).copied().unwrap_or(false) != on {
+            key_changes.push(KeyChange {
+                chip: chip.chip.clone(),
+                instance: chip.instance,
+                channel: channel as u8,
+                on,
+            });
+        }
+    }
+    chip.keys = keys;
+}
+
+/// A note on one channel, from key-on to key-off.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Note {
+    pub chip: Chip,
+    pub instance: Instance,
+    pub channel: u8,
+    /// Time of the key-on in samples.
+    pub start: u64,
+    /// Time of the key-off in samples, `None` when the channel is still
+    /// keyed on at the end of the document.
+    pub end: Option<u64>,
+}
+
+/// Collects the notes of every tracked channel, in key-on order.
+#[derive(Debug, Clone, Default)]
+pub struct ChannelTimeline {
+    notes: Vec<Note>,
+}
+
+impl ChannelTimeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The collected notes.
+    pub fn into_notes(self) -> Vec<Note> {
+        self.notes
+    }
+}
+
+impl Analysis for ChannelTimeline {
+    fn needs_keys(&self) -> bool {
+        true
+    }
+
+    fn command(&mut self, context: &AnalysisContext<'_>, _command: &VgmCommand) {
+        for change in context.key_changes() {
+            if change.on {
+                self.notes.push(Note {
+                    chip: change.chip.clone(),
+                    instance: change.instance,
+                    channel: change.channel,
+                    start: context.time(),
+                    end: None,
+                });
+            } else if let Some(note) = self.notes.iter_mut().rev().find(|note| {
+                note.chip == change.chip
+                    && note.instance == change.instance
+                    && note.channel == change.channel
+            }) {
+                note.end = Some(context.time());
+            }
+        }
+    }
+}
+
+// Key state of the channels of one chip, following its register writes.
+pub(crate) trait KeyTracker {
+    fn write(&mut self, port: u8, register: u8, value: u8);
+    fn keys(&self) -> Vec<bool>;
+    fn tone(&self, channel: u8) -> Option<ToneInfo>;
+}
+
+macro_rules! key_tracker {
+    ($state:ty, |$s:ident, $port:ident, $register:ident, $value:ident| $write:expr) => {
+        impl KeyTracker for $state {
+            fn write(&mut self, $port: u8, $register: u8, $value: u8) {
+                let $s = self;
+                $write;
+            }
+
+            fn keys(&self) -> Vec<bool> {
+                (0..self.channel_count())
+                    .map(|channel| {
+                        self.channel(channel as u8)
+                            .is_some_and(|state| state.key_state == KeyState::On)
+                    })
+                    .collect()
+            }
+
+            fn tone(&self, channel: u8) -> Option<ToneInfo> {
+                self.channel(channel)?.tone
+            }
+        }
+    };
+    ($state:ty) => {
+        key_tracker!($state, |state, _port, register, value| state
+            .on_register_write(register, value));
+    };
+    ($state:ty, ports) => {
+        key_tracker!($state, |state, port, register, value| {
+            state.set_port(port);
+            state.on_register_write(register, value)
+        });
+    };
+}
+
+// The SN76489 takes the data byte as register.
+key_tracker!(Sn76489State, |state, _port, _register, value| state
+    .on_register_write(value, value));
+key_tracker!(Ay8910State);
+key_tracker!(Ym2151State);
+key_tracker!(Ym2203State);
+key_tracker!(Ym2413State);
+key_tracker!(Ym3812State);
+key_tracker!(Ym3526State);
+key_tracker!(Y8950State);
+key_tracker!(Ym2612State, ports);
+key_tracker!(Ym2608State, ports);
+key_tracker!(Ym2610bState, ports);
+key_tracker!(Ymf262State, ports);
+
+pub(crate) fn tracker(chip: &Chip, clock: f32) -> Option<Box<dyn KeyTracker>> {
+    Some(match chip {
+        Chip::Sn76489 => Box::new(Sn76489State::new(clock)),
+        Chip::Ay8910 => Box::new(Ay8910State::new(clock)),
+        Chip::Ym2151 => Box::new(Ym2151State::new(clock)),
+        Chip::Ym2203 => Box::new(Ym2203State::new(clock)),
+        Chip::Ym2413 => Box::new(Ym2413State::new(clock)),
+        Chip::Ym3812 => Box::new(Ym3812State::new(clock)),
+        Chip::Ym3526 => Box::new(Ym3526State::new(clock)),
+        Chip::Y8950 => Box::new(Y8950State::new(clock)),
+        Chip::Ym2612 => Box::new(Ym2612State::new(clock)),
+        Chip::Ym2608 => Box::new(Ym2608State::new(clock)),
+        Chip::Ym2610b => Box::new(Ym2610bState::new(clock)),
+        Chip::Ymf262 => Box::new(Ymf262State::new(clock)),
+        _ => return None,
+    })
+}
@@ -0,0 +1,268 @@
+//! Out-of-band annotations keyed by command index.
+//!
+//! `Annotations` attaches a label, an optional color and a comment to
+//! individual commands of a `VgmDocument` without changing the document
+//! itself. When commands are inserted or removed, call `insert_commands` /
+//! `remove_commands` (or `remap` for arbitrary reorderings) with the same
+//! edit so the annotations stay on the commands they describe.
+//!
+//! Annotations are stored next to the VGM file in a plain text sidecar
+//! (see `sidecar_path`). Each non-empty line that does not start with `#`
+//! holds one annotation as four tab-separated fields:
+//!
+//! ```text
+//! <command index>\t<#rrggbb or ->\t<label>\t<comment>
+//! ```
+//!
+//! Backslash, tab, carriage return and newline in the label and comment are
+//! escaped as `\\`, `\t`, `\r` and `\n`.
+//!
+//! ```rust
+//! use soundlog::vgm::annotation::{Annotation, Annotations};
+//!
+//! let mut notes = Annotations::new();
+//! notes.insert(10, Annotation::new("drum fill").with_color(0xFF8000));
+//! // two commands were inserted in front of the fill
+//! notes.insert_commands(4, 2);
+//! assert_eq!(notes.get(12).unwrap().label, "drum fill");
+//!
+//! let text = notes.to_sidecar();
+//! assert_eq!(Annotations::from_sidecar(&text).unwrap(), notes);
+//! ```
+use std::collections::BTreeMap;
+use std::collections::btree_map;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::binutil::ParseError;
+
+/// Extension appended to the VGM file name by `sidecar_path`.
+pub const SIDECAR_EXTENSION: &str = "annotations";
+
+const SIDECAR_HEADER: &str = "# soundlog annotations v1";
+
+/// A label, color and comment attached to one command.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Annotation {
+    /// Short text shown next to the command, e.g. in bookmark lists.
+    pub label: String,
+    /// Highlight color as `0xRRGGBB`.
+    pub color: Option<u32>,
+    /// Free-form longer text.
+    pub comment: String,
+}
+
+impl Annotation {
+    /// Creates an annotation with `label`, no color and an empty comment.
+    pub fn new(label: impl Into<String>) -> Self {
+        Annotation {
+            label: label.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Sets the color (`0xRRGGBB`; upper bits are ignored).
+    pub fn with_color(mut self, color: u32) -> Self {
+        self.color = Some(color & 0xFF_FFFF);
+        self
+    }
+
+    /// Sets the comment.
+    pub fn with_comment(mut self, comment: impl Into<String>) -> Self {
+        self.comment = comment.into();
+        self
+    }
+}
+
+/// Annotations of a document, ordered by command index.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Annotations {
+    entries: BTreeMap<usize, Annotation>,
+}
+
+impl Annotations {
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attaches `annotation` to the command at `index`, returning the
+    /// annotation it replaces.
+    pub fn insert(&mut self, index: usize, annotation: Annotation) -> Option<Annotation> {
+        self.entries.insert(index, annotation)
+    }
+
+    /// Gets the annotation of the command at `index`.
+    pub fn get(&self, index: usize) -> Option<&Annotation> {
+        self.entries.get(&index)
+    }
+
+    /// Gets the annotation of the command at `index` for editing.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Annotation> {
+        self.entries.get_mut(&index)
+    }
+
+    /// Detaches the annotation of the command at `index`.
+    pub fn remove(&mut self, index: usize) -> Option<Annotation> {
+        self.entries.remove(&index)
+    }
+
+    /// Number of annotated commands.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// `true` when no command is annotated.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterates `(command index, annotation)` in index order.
+    pub fn iter(&self) -> btree_map::Iter<'_, usize, Annotation> {
+        self.entries.iter()
+    }
+
+    /// Follows the insertion of `count` commands at `index`: annotations at
+    /// `index` and after move back by `count`.
+    pub fn insert_commands(&mut self, index: usize, count: usize) {
+        if count == 0 {
+            return;
+        }
+        let moved = self.entries.split_off(&index);
+        self.entries
+            .extend(moved.into_iter().map(|(i, a)| (i + count, a)));
+    }
+
+    /// Follows the removal of the commands in `range`: their annotations are
+    /// dropped and later ones move forward.
+    pub fn remove_commands(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        let count = range.end - range.start;
+        let mut tail = self.entries.split_off(&range.start);
+        let moved = tail.split_off(&range.end);
+        self.entries
+            .extend(moved.into_iter().map(|(i, a)| (i - count, a)));
+    }
+
+    /// Follows an arbitrary edit: `map` returns the new index of each
+    /// annotated command, or `None` when it was removed. When two
+    /// annotations map to the same index the later one is kept.
+    pub fn remap(&mut self, mut map: impl FnMut(usize) -> Option<usize>) {
+        let entries = std::mem::take(&mut self.entries);
+        self.entries = entries
+            .into_iter()
+            .filter_map(|(i, a)| map(i).map(|i| (i, a)))
+            .collect();
+    }
+
+    /// Serializes to the sidecar text format.
+    pub fn to_sidecar(&self) -> String {
+        let mut text = String::from(SIDECAR_HEADER);
+        text.push('\n');
+        for (index, annotation) in &self.entries {
+            let color = match annotation.color {
+                Some(color) => format!("#{:06x}", color),
+                None => "-".to_string(),
+            };
+            text.push_str(&format!(
+                "{}\t{}\t{}\t{}\n",
+                index,
+                color,
+                escape(&annotation.label),
+                escape(&annotation.comment)
+            ));
+        }
+        text
+    }
+
+    /// Parses the sidecar text format.
+    pub fn from_sidecar(text: &str) -> Result<Self, ParseError> {
+        let mut annotations = Annotations::new();
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |what: &str| {
+                ParseError::Other(format!("annotations line {}: {}", line_number + 1, what))
+            };
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [index, color, label, comment] = fields[..] else {
+                return Err(error("expected 4 tab-separated fields"));
+            };
+            let index = index
+                .parse::<usize>()
+                .map_err(|_| error("invalid command index"))?;
+            let color = match color {
+                "-" => None,
+                _ => Some(
+                    color
+                        .strip_prefix('#')
+                        .filter(|hex| hex.len() == 6)
+                        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                        .ok_or_else(|| error("invalid color"))?,
+                ),
+            };
+            let annotation = Annotation {
+                label: unescape(label).ok_or_else(|| error("invalid escape in label"))?,
+                color,
+                comment: unescape(comment).ok_or_else(|| error("invalid escape in comment"))?,
+            };
+            annotations.insert(index, annotation);
+        }
+        Ok(annotations)
+    }
+}
+
+impl<'a> IntoIterator for &'a Annotations {
+    type Item = (&'a usize, &'a Annotation);
+    type IntoIter = btree_map::Iter<'a, usize, Annotation>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Path of the sidecar for `vgm_path`: `song.vgm` becomes
+/// `song.vgm.annotations`.
+pub fn sidecar_path(vgm_path: &Path) -> PathBuf {
+    let mut name = vgm_path.as_os_str().to_owned();
+    name.push(".");
+    name.push(SIDECAR_EXTENSION);
+    PathBuf::from(name)
+}
+
+pub(crate) fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub(crate) fn unescape(s: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        unescaped.push(match chars.next()? {
+            '\\' => '\\',
+            't' => '\t',
+            'r' => '\r',
+            'n' => '\n',
+            _ => return None,
+        });
+    }
+    Some(unescaped)
+}
@@ -0,0 +1,364 @@
+//! Zero-copy parsing.
+//!
+//! `VgmDocumentRef` is a parsed VGM file that borrows from the input instead
+//! of owning it: data block (`0x67`) and PCM RAM write (`0x68`) payloads are
+//! slices of the input, and GD3 tags are the UTF-16LE bytes of the chunk.
+//! For rips with megabytes of PCM this keeps peak memory close to the size
+//! of the file itself, where `VgmDocument::try_from` holds a second copy of
+//! every payload.
+//!
+//! The parser accepts and rejects the same input as `VgmDocument::try_from`,
+//! and `VgmDocumentRef::to_document` gives the document it would have
+//! returned.
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::meta::Gd3;
+//! use soundlog::vgm::VgmDocumentRef;
+//! use soundlog::vgm::borrowed::VgmCommandRef;
+//! use soundlog::vgm::command::{DataBlock, WaitSamples};
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.add_vgm_command(DataBlock {
+//!     marker: 0x66,
+//!     chip_instance: 0,
+//!     data_type: 0x00,
+//!     size: 4,
+//!     data: vec![1, 2, 3, 4],
+//! });
+//! builder.add_vgm_command(WaitSamples(100));
+//! builder.set_gd3(Gd3 {
+//!     track_name_en: Some("Title".to_string()),
+//!     ..Gd3::default()
+//! });
+//! let bytes: Vec<u8> = builder.finalize().into();
+//!
+//! let doc = VgmDocumentRef::parse(&bytes).unwrap();
+//! let VgmCommandRef::DataBlock(block) = &doc.commands[0] else {
+//!     panic!("expected a data block");
+//! };
+//! assert_eq!(block.data, &[1, 2, 3, 4]);
+//! let title = doc.gd3.as_ref().and_then(|gd3| gd3.field("track_name_en"));
+//! assert_eq!(title.unwrap().to_string(), "Title");
+//! assert_eq!(doc.to_document(), soundlog::VgmDocument::try_from(&bytes[..]).unwrap());
+//! ```
+use std::fmt;
+
+use crate::binutil::{
+    ParseError, ResourceLimit, read_slice, read_u8_at, read_u24_be_at, read_u32_le_at,
+};
+use crate::meta::Gd3;
+use crate::vgm::VgmDocument;
+use crate::vgm::command::{DataBlock, PcmRamWrite, StreamChipType, VgmCommand};
+use crate::vgm::header::{VgmExtraHeader, VgmHeader};
+use crate::vgm::parser::{
+    ParseLimits, check_data_block, check_limit, gd3_chunk, gd3_start, parse_attached_extra_header,
+    parse_vgm_command, parse_vgm_header,
+};
+
+/// A parsed VGM file borrowing its payloads from the input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VgmDocumentRef<'a> {
+    pub header: VgmHeader,
+    pub extra_header: Option<VgmExtraHeader>,
+    pub commands: Vec<VgmCommandRef<'a>>,
+    pub gd3: Option<Gd3Ref<'a>>,
+}
+
+impl<'a> VgmDocumentRef<'a> {
+    /// Parse `bytes` without copying payloads.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, ParseError> {
+        Self::parse_with_limits(bytes, &ParseLimits::unlimited())
+    }
+
+    /// Parse `bytes` without copying payloads, stopping with
+    /// `ParseError::LimitExceeded` when the input exceeds `limits`.
+    pub fn parse_with_limits(bytes: &'a [u8], limits: &ParseLimits) -> Result<Self, ParseError> {
+        check_limit(ResourceLimit::FileSize, bytes.len(), limits.max_file_size)?;
+        let (header, mut off) = parse_vgm_header(bytes)?;
+        let gd3_start = gd3_start(&header)?;
+        let mut commands = Vec::new();
+        let mut data_block_total: usize = 0;
+        while off < bytes.len() {
+            if gd3_start.is_some_and(|start| off >= start) {
+                break;
+            }
+            check_limit(
+                ResourceLimit::CommandCount,
+                commands.len() + 1,
+                limits.max_commands,
+            )?;
+            let (command, consumed) = match read_u8_at(bytes, off)? {
+                0x67 => {
+                    let (block, consumed) = DataBlockRef::parse(bytes, off + 1)?;
+                    check_data_block(block.data_type, block.data, &mut data_block_total, limits)?;
+                    (VgmCommandRef::DataBlock(block), consumed)
+                }
+                0x68 => {
+                    let (write, consumed) = PcmRamWriteRef::parse(bytes, off + 1)?;
+                    (VgmCommandRef::PcmRamWrite(write), consumed)
+                }
+                _ => {
+                    let (command, consumed) = parse_vgm_command(bytes, off)?;
+                    (VgmCommandRef::Command(command), consumed)
+                }
+            };
+            off += consumed;
+            let end = matches!(command, VgmCommandRef::Command(VgmCommand::EndOfData(_)));
+            commands.push(command);
+            if end {
+                break;
+            }
+        }
+        let gd3 = match gd3_start {
+            Some(start) => Some(Gd3Ref::parse(gd3_chunk(bytes, start)?)?),
+            None => None,
+        };
+        let extra_header = parse_attached_extra_header(bytes, &header)?;
+        Ok(VgmDocumentRef {
+            header,
+            extra_header,
+            commands,
+            gd3,
+        })
+    }
+
+    /// Return an iterator over the commands.
+    pub fn iter(&self) -> std::slice::Iter<'_, VgmCommandRef<'a>> {
+        self.commands.iter()
+    }
+
+    /// Copy into an owned `VgmDocument`.
+    pub fn to_document(&self) -> VgmDocument {
+        VgmDocument {
+            header: self.header.clone(),
+            extra_header: self.extra_header.clone(),
+            commands: self
+                .commands
+                .iter()
+                .map(VgmCommandRef::to_command)
+                .collect(),
+            gd3: self.gd3.as_ref().map(Gd3Ref::to_gd3),
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for VgmDocumentRef<'a> {
+    type Error = ParseError;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        VgmDocumentRef::parse(bytes)
+    }
+}
+
+/// A command of a `VgmDocumentRef`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VgmCommandRef<'a> {
+    /// Data block (`0x67`) with a borrowed payload.
+    DataBlock(DataBlockRef<'a>),
+    /// PCM RAM write (`0x68`) with a borrowed payload.
+    PcmRamWrite(PcmRamWriteRef<'a>),
+    /// Any other command; these have no payload to borrow.
+    Command(VgmCommand),
+}
+
+impl VgmCommandRef<'_> {
+    /// Copy into an owned `VgmCommand`.
+    pub fn to_command(&self) -> VgmCommand {
+        match self {
+            VgmCommandRef::DataBlock(block) => VgmCommand::DataBlock(Box::new(DataBlock {
+                marker: block.marker,
+                chip_instance: block.chip_instance,
+                data_type: block.data_type,
+                size: block.size,
+                data: block.data.to_vec(),
+            })),
+            VgmCommandRef::PcmRamWrite(write) => VgmCommand::PcmRamWrite(Box::new(PcmRamWrite {
+                marker: write.marker,
+                chip_type: write.chip_type,
+                read_offset: write.read_offset,
+                write_offset: write.write_offset,
+                size: write.size,
+                data: write.data.to_vec(),
+            })),
+            VgmCommandRef::Command(command) => command.clone(),
+        }
+    }
+
+    /// Number of samples the command waits, see `VgmCommand::wait_samples`.
+    pub fn wait_samples(&self) -> u32 {
+        match self {
+            VgmCommandRef::Command(command) => command.wait_samples(),
+            _ => 0,
+        }
+    }
+}
+
+/// `DataBlock` borrowing its payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataBlockRef<'a> {
+    pub marker: u8,
+    pub chip_instance: u8,
+    pub data_type: u8,
+    pub size: u32,
+    pub data: &'a [u8],
+}
+
+impl<'a> DataBlockRef<'a> {
+    // Parse from the marker byte on; returns the bytes used with the opcode.
+    fn parse(bytes: &'a [u8], offset: usize) -> Result<(Self, usize), ParseError> {
+        let marker = read_u8_at(bytes, offset)?;
+        let data_type = read_u8_at(bytes, offset + 1)?;
+        let size = read_u32_le_at(bytes, offset + 2)?;
+        let chip_instance = (size >> 31) as u8;
+        let size = size & 0x7FFF_FFFF;
+        let data = read_slice(bytes, offset + 6, size as usize)?;
+        let block = DataBlockRef {
+            marker,
+            chip_instance,
+            data_type,
+            size,
+            data,
+        };
+        Ok((block, 1 + 1 + 1 + 4 + size as usize))
+    }
+}
+
+/// `PcmRamWrite` borrowing its payload.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PcmRamWriteRef<'a> {
+    pub marker: u8,
+    pub chip_type: StreamChipType,
+    pub read_offset: u32,
+    pub write_offset: u32,
+    pub size: u32,
+    pub data: &'a [u8],
+}
+
+impl<'a> PcmRamWriteRef<'a> {
+    // Parse from the marker byte on; returns the bytes used with the opcode.
+    fn parse(bytes: &'a [u8], offset: usize) -> Result<(Self, usize), ParseError> {
+        let size = read_u24_be_at(bytes, offset + 8)?;
+        let write = PcmRamWriteRef {
+            marker: read_u8_at(bytes, offset)?,
+            chip_type: StreamChipType::from(read_u8_at(bytes, offset + 1)?),
+            read_offset: read_u24_be_at(bytes, offset + 2)?,
+            write_offset: read_u24_be_at(bytes, offset + 5)?,
+            size,
+            data: read_slice(bytes, offset + 11, size as usize)?,
+        };
+        Ok((write, 1 + 1 + 1 + 3 + 3 + 3 + size as usize))
+    }
+}
+
+/// A GD3 tag as stored in the file: UTF-16LE without the terminator.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Gd3Str<'a>(&'a [u8]);
+
+impl<'a> Gd3Str<'a> {
+    /// The UTF-16LE bytes.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.0
+    }
+
+    /// Decode the tag.
+    pub fn chars(&self) -> impl Iterator<Item = char> + 'a {
+        char::decode_utf16(utf16_units(self.0)).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+}
+
+impl fmt::Display for Gd3Str<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.chars().try_for_each(|c| fmt::Write::write_char(f, c))
+    }
+}
+
+impl fmt::Debug for Gd3Str<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.to_string(), f)
+    }
+}
+
+/// `Gd3` borrowing its tags from the chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gd3Ref<'a> {
+    /// The Gd3 chunk version (as the raw u32 the chunk stores).
+    pub version: u32,
+    /// Tags in the order of `Gd3::FIELDS`; empty tags are `None`.
+    pub fields: [Option<Gd3Str<'a>>; 11],
+}
+
+impl<'a> Gd3Ref<'a> {
+    /// Parse a GD3 chunk like `Gd3::try_from`: a truncated chunk leaves the
+    /// remaining tags empty, invalid UTF-16 is an error.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, ParseError> {
+        if bytes.len() < 12 {
+            return Err(ParseError::HeaderTooShort("gd3".into()));
+        }
+        let ident = read_slice(bytes, 0, 4)?;
+        if ident != b"Gd3 " {
+            let mut id = [0; 4];
+            id.copy_from_slice(ident);
+            return Err(ParseError::InvalidIdent(id));
+        }
+        let version = read_u32_le_at(bytes, 4)?;
+        let data_len = read_u32_le_at(bytes, 8)? as usize;
+        let data = read_slice(bytes, 0x0C, data_len).map_err(|_| ParseError::OffsetOutOfRange {
+            offset: 0x0C,
+            needed: 1,
+            available: bytes.len(),
+            context: Some("meta:data_offset".into()),
+        })?;
+
+        let mut fields = [None; 11];
+        let mut start = 0;
+        for field in &mut fields {
+            let Some(len) = data[start..]
+                .chunks_exact(2)
+                .position(|unit| unit == [0, 0])
+            else {
+                break;
+            };
+            let tag = Gd3Str(&data[start..start + len * 2]);
+            if char::decode_utf16(utf16_units(tag.0)).any(|c| c.is_err()) {
+                // Only the error path allocates, to report what `parse_gd3` does.
+                let units: Vec<u16> = utf16_units(tag.0).collect();
+                if let Err(e) = String::from_utf16(&units) {
+                    return Err(ParseError::Other(format!("invalid utf16 in gd3: {}", e)));
+                }
+            }
+            if len > 0 {
+                *field = Some(tag);
+            }
+            start += len * 2 + 2;
+        }
+        Ok(Gd3Ref { version, fields })
+    }
+
+    /// The tag named `name` (see `Gd3::FIELDS`), `None` when it is empty
+    /// or the name is unknown.
+    pub fn field(&self, name: &str) -> Option<Gd3Str<'a>> {
+        let index = Gd3::FIELDS.iter().position(|field| *field == name)?;
+        self.fields[index]
+    }
+
+    /// Decode into an owned `Gd3`.
+    pub fn to_gd3(&self) -> Gd3 {
+        let mut gd3 = Gd3 {
+            version: self.version,
+            ..Gd3::default()
+        };
+        for (name, tag) in Gd3::FIELDS.iter().zip(self.fields) {
+            if let Some(field) = gd3.field_mut(name) {
+                *field = tag.map(|tag| tag.to_string());
+            }
+        }
+        gd3
+    }
+}
+
+fn utf16_units(bytes: &[u8]) -> impl Iterator<Item = u16> + '_ {
+    bytes
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+}
@@ -533,6 +533,22 @@ pub struct VgmCallbackStream<'a> {
     /// Stored tracker configurations so state can be re-initialized after a seek.
     /// Each entry re-creates one tracker with its original instance and clock.
     tracker_initializers: Vec<TrackerInitializer>,
+    /// Which sample clock is passed to callbacks.
+    sample_clock: SampleClock,
+}
+
+/// The sample position passed to `VgmCallbackStream` callbacks.
+///
+/// See `VgmCallbackStream::set_sample_clock`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SampleClock {
+    /// `VgmStream::current_sample`: samples since the start of the stream or
+    /// the last loop point, reset to 0 every time the stream loops.
+    #[default]
+    LoopRelative,
+    /// `VgmStream::absolute_sample`: samples since the start of the stream,
+    /// increasing across loop iterations and the fadeout.
+    Absolute,
 }
 
 type TrackerInitializer = Box<dyn Fn(&mut StateTrackers) + 'static>;
@@ -559,6 +575,7 @@ impl<'a> VgmCallbackStream<'a> {
             state_trackers: StateTrackers::default(),
             callbacks: Callbacks::default(),
             tracker_initializers: Vec::new(),
+            sample_clock: SampleClock::default(),
         }
     }
 
@@ -642,6 +659,59 @@ impl<'a> VgmCallbackStream<'a> {
         &mut self.stream
     }
 
+    /// Gets the loop-relative sample position of the underlying stream.
+    ///
+    /// See [`VgmStream::current_sample`].
+    pub fn current_sample(&self) -> usize {
+        self.stream.current_sample()
+    }
+
+    /// Gets the absolute sample position of the underlying stream.
+    ///
+    /// See [`VgmStream::absolute_sample`].
+    pub fn absolute_sample(&self) -> u64 {
+        self.stream.absolute_sample()
+    }
+
+    /// Selects the sample position passed to callbacks as `sample`.
+    ///
+    /// The default, `SampleClock::LoopRelative`, restarts at 0 every time the
+    /// stream loops, which suits per-iteration analysis. Export tools that
+    /// lay the writes out on a timeline should use `SampleClock::Absolute`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use soundlog::VgmBuilder;
+    /// use soundlog::chip::PsgSpec;
+    /// use soundlog::vgm::VgmCallbackStream;
+    /// use soundlog::vgm::callback_stream::SampleClock;
+    /// use soundlog::vgm::command::{Instance, WaitSamples};
+    ///
+    /// let mut builder = VgmBuilder::new();
+    /// builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x9F });
+    /// builder.add_vgm_command(WaitSamples(100));
+    /// builder.set_loop_offset(0);
+    ///
+    /// let mut samples = Vec::new();
+    /// let mut stream = VgmCallbackStream::from_document(builder.finalize());
+    /// stream.set_loop_count(Some(2));
+    /// stream.set_sample_clock(SampleClock::Absolute);
+    /// stream.on_write(|_inst, _spec: PsgSpec, sample, _event| samples.push(sample));
+    /// for _ in &mut stream {}
+    /// drop(stream);
+    ///
+    /// assert_eq!(samples, vec![0, 100]);
+    /// ```
+    pub fn set_sample_clock(&mut self, clock: SampleClock) {
+        self.sample_clock = clock;
+    }
+
+    /// Gets the sample clock passed to callbacks.
+    pub fn sample_clock(&self) -> SampleClock {
+        self.sample_clock
+    }
+
     /// Sets the loop count value.
     ///
     /// Forwarded to the underlying `VgmStream`. See [`VgmStream::set_loop_count`] for details.
@@ -673,6 +743,18 @@ impl<'a> VgmCallbackStream<'a> {
         self.stream.loop_modifier()
     }
 
+    /// Sets the sample rate that waits and sample positions are counted in.
+    ///
+    /// Forwarded to the underlying `VgmStream`. See [`VgmStream::set_sample_rate`] for details.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.stream.set_sample_rate(sample_rate);
+    }
+
+    /// Gets the sample rate that waits and sample positions are counted in.
+    pub fn sample_rate(&self) -> u32 {
+        self.stream.sample_rate()
+    }
+
     /// Sets the fadeout grace period in samples after loop end.
     ///
     /// Forwarded to the underlying `VgmStream`. See [`VgmStream::set_fadeout_samples`] for details.
@@ -709,7 +791,7 @@ impl<'a> VgmCallbackStream<'a> {
     /// Moves the stream to the specified sample position within the current loop iteration.
     ///
     /// Since the sample counter resets to 0 at the start of each loop, `target` refers
-    /// to a position within one loop iteration (measured in 44100 Hz samples from 0).
+    /// to a position within one loop iteration (measured in samples at the stream sample rate from 0).
     ///
     /// This method:
     /// 1. Resets all registered chip state trackers to their freshly-configured state.
@@ -738,10 +820,26 @@ impl<'a> VgmCallbackStream<'a> {
 
         // Rebuild state trackers from scratch so that fast-forward starts from a
         // clean state rather than accumulating on top of the previous playback position.
-        self.state_trackers = StateTrackers::default();
+        let previous = std::mem::take(&mut self.state_trackers);
         for init_fn in &self.tracker_initializers {
             init_fn(&mut self.state_trackers);
         }
+        // ROM data blocks usually sit before the loop point and are not replayed.
+        for (state, previous) in self
+            .state_trackers
+            .okim6295
+            .iter_mut()
+            .zip(previous.okim6295)
+        {
+            if let (Some(state), Some(previous)) = (state, previous) {
+                state.inherit_rom(previous);
+            }
+        }
+        for (state, previous) in self.state_trackers.y8950.iter_mut().zip(previous.y8950) {
+            if let (Some(state), Some(previous)) = (state, previous) {
+                state.inherit_memory(previous);
+            }
+        }
 
         // Suppress all user-facing callbacks during fast-forward by swapping them out.
         // State trackers (separate from callbacks) continue to receive every write.
@@ -977,7 +1075,8 @@ impl<'a> VgmCallbackStream<'a> {
     /// * `callback` - A closure that receives:
     ///   - `instance`: The chip instance (Primary or Secondary)
     ///   - `spec`: The chip-specific write specification
-    ///   - `sample`: Current sample position (at 44.1 kHz, resets to 0 on loop)
+    ///   - `sample`: Current sample position at 44.1 kHz; loop-relative (reset
+    ///     to 0 on loop) unless changed with `set_sample_clock`
     ///   - `event`: Optional state event detected from this write
     ///
     /// # Examples
@@ -1171,7 +1270,10 @@ impl<'a> VgmCallbackStream<'a> {
     ///
     /// This is called automatically by the iterator implementation.
     fn process_command(&mut self, cmd: &VgmCommand) {
-        let sample = self.stream.current_sample();
+        let sample = match self.sample_clock {
+            SampleClock::LoopRelative => self.stream.current_sample(),
+            SampleClock::Absolute => self.stream.absolute_sample() as usize,
+        };
 
         // Call the generic callback first if registered
         if let Some(ref mut cb) = self.callbacks.on_any_command {
@@ -1617,6 +1719,22 @@ impl<'a> VgmCallbackStream<'a> {
                 }
             }
             VgmCommand::DataBlock(spec) => {
+                // ROM dumps read by state trackers: rom size and start
+                // address, then the data
+                if matches!(spec.data_type, 0x88 | 0x8B) && spec.data.len() >= 8 {
+                    let start = &spec.data[4..8];
+                    let start = u32::from_le_bytes([start[0], start[1], start[2], start[3]]);
+                    let data = &spec.data[8..];
+                    let instance = spec.chip_instance as usize;
+                    if spec.data_type == 0x88 {
+                        if let Some(Some(state)) = self.state_trackers.y8950.get_mut(instance) {
+                            state.write_memory(start, data);
+                        }
+                    } else if let Some(Some(state)) = self.state_trackers.okim6295.get_mut(instance)
+                    {
+                        state.write_rom(start, data);
+                    }
+                }
                 if let Some(ref mut cb) = self.callbacks.on_data_block {
                     cb(spec, sample, None);
                 }
@@ -12,11 +12,12 @@
 //! - Helpers to convert single commands to bytes (`command_to_vgm_bytes`)
 //!   and compute per-command offsets/lengths used by `VgmDocument`.
 use crate::binutil::{
-    ParseError, read_i32_le_at, read_slice, read_u8_at, read_u24_be_at, read_u32_le_at,
+    FileOffset, ParseError, read_i32_le_at, read_slice, read_u8_at, read_u24_be_at, read_u32_le_at,
 };
 use crate::chip;
 use crate::vgm::document::VgmDocument;
 use crate::vgm::header::{VgmHeader, VgmHeaderField};
+use crate::vgm::parser::parse_vgm_command;
 // re-export
 pub use crate::vgm::detail::StreamChipType;
 pub use crate::vgm::header::ChipId;
@@ -80,7 +81,7 @@ impl From<DacStreamChipType> for u8 {
 }
 
 /// Chip instance identifier for VGM commands.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Instance {
     Primary = 0x0,
     Secondary = 0x1,
@@ -178,6 +179,582 @@ pub enum VgmCommand {
     UnknownCommand(UnknownSpec),
 }
 
+impl VgmCommand {
+    /// Decode the command at the start of `bytes`.
+    ///
+    /// Returns the command and the number of bytes it takes (opcode
+    /// included); bytes after it are ignored. This is the decoder used by
+    /// the document parser and `VgmStream`, for tools that carry VGM
+    /// commands in their own container or protocol. `command_to_vgm_bytes`
+    /// encodes a command back.
+    ///
+    /// When `bytes` ends inside the command, the error is
+    /// `ParseError::UnexpectedEof` or `ParseError::OffsetOutOfRange`: wait
+    /// for more data and try again. Other errors mean the bytes are not a
+    /// VGM command.
+    ///
+    /// ```rust
+    /// use soundlog::vgm::command::{VgmCommand, WaitSamples, command_to_vgm_bytes};
+    ///
+    /// let (command, len) = VgmCommand::parse_one(&[0x61, 0xDF, 0x02, 0x66]).unwrap();
+    /// assert_eq!(command, VgmCommand::WaitSamples(WaitSamples(735)));
+    /// assert_eq!(len, 3);
+    /// assert_eq!(command_to_vgm_bytes(&command).0, [0x61, 0xDF, 0x02]);
+    /// assert!(VgmCommand::parse_one(&[0x61, 0xDF]).is_err());
+    /// ```
+    pub fn parse_one(bytes: &[u8]) -> Result<(VgmCommand, usize), ParseError> {
+        let (command, len) = parse_vgm_command(bytes, 0)?;
+        if len > bytes.len() {
+            return Err(ParseError::OffsetOutOfRange {
+                offset: 0,
+                needed: len,
+                available: bytes.len(),
+                context: Some("VgmCommand::parse_one".into()),
+            });
+        }
+        Ok((command, len))
+    }
+
+    /// Number of samples this command advances the playback clock by.
+    ///
+    /// Returns `0` for commands that do not wait. For
+    /// `YM2612Port0Address2AWriteAndWaitN` only the wait part is counted.
+    pub fn wait_samples(&self) -> u32 {
+        match self {
+            VgmCommand::WaitSamples(s) => s.0 as u32,
+            VgmCommand::Wait735Samples(_) => 735,
+            VgmCommand::Wait882Samples(_) => 882,
+            VgmCommand::WaitNSample(s) => s.0 as u32 + 1,
+            VgmCommand::YM2612Port0Address2AWriteAndWaitN(s) => s.0 as u32,
+            _ => 0,
+        }
+    }
+
+    /// Chip-agnostic view of this command when it is a chip write.
+    ///
+    /// Returns `None` for waits, data blocks, stream control and other
+    /// commands that do not address a chip register or memory location.
+    pub fn register_write(&self) -> Option<RegisterWrite> {
+        use chip::Chip;
+        let (chip, instance, port, register, value) = match self {
+            VgmCommand::Sn76489Write(i, s) => (Chip::Sn76489, i, 0, 0, s.value as u32),
+            VgmCommand::GameGearPsgWrite(i, s) => (Chip::Sn76489, i, 1, 0, s.value as u32),
+            VgmCommand::Ym2413Write(i, s) => {
+                (Chip::Ym2413, i, 0, s.register as u32, s.value as u32)
+            }
+            VgmCommand::Ym2612Write(i, s) => {
+                (Chip::Ym2612, i, s.port, s.register as u32, s.value as u32)
+            }
+            VgmCommand::Ym2151Write(i, s) => {
+                (Chip::Ym2151, i, 0, s.register as u32, s.value as u32)
+            }
+            VgmCommand::SegaPcmWrite(i, s) => {
+                (Chip::SegaPcm, i, 0, s.offset as u32, s.value as u32)
+            }
+            VgmCommand::Rf5c68U8Write(i, s) => {
+                (Chip::Rf5c68, i, 0, s.offset as u32, s.value as u32)
+            }
+            VgmCommand::Rf5c68U16Write(i, s) => {
+                (Chip::Rf5c68, i, 1, s.offset as u32, s.value as u32)
+            }
+            VgmCommand::Ym2203Write(i, s) => {
+                (Chip::Ym2203, i, 0, s.register as u32, s.value as u32)
+            }
+            VgmCommand::Ym2608Write(i, s) => {
+                (Chip::Ym2608, i, s.port, s.register as u32, s.value as u32)
+            }
+            VgmCommand::Ym2610bWrite(i, s) => {
+                (Chip::Ym2610b, i, s.port, s.register as u32, s.value as u32)
+            }
+            VgmCommand::Ym3812Write(i, s) => {
+                (Chip::Ym3812, i, 0, s.register as u32, s.value as u32)
+            }
+            VgmCommand::Ym3526Write(i, s) => {
+                (Chip::Ym3526, i, 0, s.register as u32, s.value as u32)
+            }
+            VgmCommand::Y8950Write(i, s) => (Chip::Y8950, i, 0, s.register as u32, s.value as u32),
+            VgmCommand::Ymf262Write(i, s) => {
+                (Chip::Ymf262, i, s.port, s.register as u32, s.value as u32)
+            }
+            VgmCommand::Ymf278bWrite(i, s) => {
+                (Chip::Ymf278b, i, s.port, s.register as u32, s.value as u32)
+            }
+            VgmCommand::Ymf271Write(i, s) => {
+                (Chip::Ymf271, i, s.port, s.register as u32, s.value as u32)
+            }
+            VgmCommand::Scc1Write(i, s) => {
+                (Chip::K051649, i, s.port, s.register as u32, s.value as u32)
+            }
+            VgmCommand::Ymz280bWrite(i, s) => {
+                (Chip::Ymz280b, i, 0, s.register as u32, s.value as u32)
+            }
+            VgmCommand::Rf5c164U8Write(i, s) => {
+                (Chip::Rf5c164, i, 0, s.offset as u32, s.value as u32)
+            }
+            VgmCommand::Rf5c164U16Write(i, s) => {
+                (Chip::Rf5c164, i, 1, s.offset as u32, s.value as u32)
+            }
+            VgmCommand::PwmWrite(i, s) => (Chip::Pwm, i, 0, s.register as u32, s.value),
+            VgmCommand::Ay8910Write(i, s) => {
+                (Chip::Ay8910, i, 0, s.register as u32, s.value as u32)
+            }
+            VgmCommand::GbDmgWrite(i, s) => (Chip::GbDmg, i, 0, s.register as u32, s.value as u32),
+            VgmCommand::NesApuWrite(i, s) => {
+                (Chip::NesApu, i, 0, s.register as u32, s.value as u32)
+            }
+            VgmCommand::MultiPcmWrite(i, s) => {
+                (Chip::MultiPcm, i, 0, s.register as u32, s.value as u32)
+            }
+            VgmCommand::MultiPcmBankWrite(i, s) => {
+                (Chip::MultiPcm, i, 1, s.channel as u32, s.bank_offset as u32)
+            }
+            VgmCommand::Upd7759Write(i, s) => {
+                (Chip::Upd7759, i, 0, s.register as u32, s.value as u32)
+            }
+            VgmCommand::Okim6258Write(i, s) => {
+                (Chip::Okim6258, i, 0, s.register as u32, s.value as u32)
+            }
+            VgmCommand::Okim6295Write(i, s) => {
+                (Chip::Okim6295, i, 0, s.register as u32, s.value as u32)
+            }
+            VgmCommand::K054539Write(i, s) => {
+                (Chip::K054539, i, 0, s.register as u32, s.value as u32)
+            }
+            VgmCommand::Huc6280Write(i, s) => {
+                (Chip::Huc6280, i, 0, s.register as u32, s.value as u32)
+            }
+            VgmCommand::C140Write(i, s) => (Chip::C140, i, 0, s.register as u32, s.value as u32),
+            VgmCommand::K053260Write(i, s) => {
+                (Chip::K053260, i, 0, s.register as u32, s.value as u32)
+            }
+            VgmCommand::PokeyWrite(i, s) => (Chip::Pokey, i, 0, s.register as u32, s.value as u32),
+            VgmCommand::QsoundWrite(i, s) => {
+                (Chip::Qsound, i, 0, s.register as u32, s.value as u32)
+            }
+            VgmCommand::ScspWrite(i, s) => (Chip::Scsp, i, 0, s.offset as u32, s.value as u32),
+            VgmCommand::WonderSwanWrite(i, s) => {
+                (Chip::WonderSwan, i, 0, s.offset as u32, s.value as u32)
+            }
+            VgmCommand::WonderSwanRegWrite(i, s) => {
+                (Chip::WonderSwan, i, 1, s.register as u32, s.value as u32)
+            }
+            VgmCommand::VsuWrite(i, s) => (Chip::Vsu, i, 0, s.offset as u32, s.value as u32),
+            VgmCommand::Saa1099Write(i, s) => {
+                (Chip::Saa1099, i, 0, s.register as u32, s.value as u32)
+            }
+            VgmCommand::Es5503Write(i, s) => {
+                (Chip::Es5503, i, 0, s.register as u32, s.value as u32)
+            }
+            VgmCommand::Es5506BEWrite(i, s) => {
+                (Chip::Es5506U8, i, 0, s.register as u32, s.value as u32)
+            }
+            VgmCommand::Es5506D6Write(i, s) => {
+                (Chip::Es5506U16, i, 0, s.register as u32, s.value as u32)
+            }
+            VgmCommand::X1010Write(i, s) => (Chip::X1010, i, 0, s.offset as u32, s.value as u32),
+            VgmCommand::C352Write(i, s) => (Chip::C352, i, 0, s.register as u32, s.value as u32),
+            VgmCommand::Ga20Write(i, s) => (Chip::Ga20, i, 0, s.register as u32, s.value as u32),
+            VgmCommand::MikeyWrite(i, s) => (Chip::Mikey, i, 0, s.register as u32, s.value as u32),
+            _ => return None,
+        };
+        Some(RegisterWrite {
+            chip,
+            instance: *instance,
+            port,
+            register,
+            value,
+        })
+    }
+}
+
+/// Chip-agnostic description of a single chip write.
+///
+/// `port` selects the register bank for multi-port chips (for example
+/// YM2612 port 0/1). For chips that expose two address spaces through
+/// separate commands it distinguishes them instead: `1` is used for
+/// RF5C68/RF5C164 memory writes, MultiPCM bank writes, WonderSwan register
+/// writes and Game Gear stereo writes. `register` holds the register index
+/// or memory offset; PSG writes have no register index and use `0`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RegisterWrite {
+    pub chip: chip::Chip,
+    pub instance: Instance,
+    pub port: u8,
+    pub register: u32,
+    pub value: u32,
+}
+
+impl RegisterWrite {
+    /// The chip write command for this write, the inverse of
+    /// `VgmCommand::register_write`.
+    ///
+    /// Returns `None` when the chip has no write command for `port`, or when
+    /// `register` or `value` do not fit the command.
+    ///
+    /// ```rust
+    /// use soundlog::chip::Chip;
+    /// use soundlog::vgm::command::{Instance, RegisterWrite};
+    ///
+    /// let write = RegisterWrite {
+    ///     chip: Chip::Ym2612,
+    ///     instance: Instance::Secondary,
+    ///     port: 1,
+    ///     register: 0xB4,
+    ///     value: 0xC0,
+    /// };
+    /// let command = write.to_command().unwrap();
+    /// assert_eq!(command.register_write(), Some(write));
+    /// ```
+    pub fn to_command(&self) -> Option<VgmCommand> {
+        use chip::Chip;
+        let instance = self.instance;
+        Some(match (&self.chip, self.port) {
+            (Chip::Sn76489, 0) if self.register == 0 => VgmCommand::Sn76489Write(
+                instance,
+                chip::PsgSpec {
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Sn76489, 1) if self.register == 0 => VgmCommand::GameGearPsgWrite(
+                instance,
+                chip::GameGearPsgSpec {
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Ym2413, 0) => VgmCommand::Ym2413Write(
+                instance,
+                chip::Ym2413Spec {
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Ym2612, port @ (0 | 1)) => VgmCommand::Ym2612Write(
+                instance,
+                chip::Ym2612Spec {
+                    port,
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Ym2151, 0) => VgmCommand::Ym2151Write(
+                instance,
+                chip::Ym2151Spec {
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::SegaPcm, 0) => VgmCommand::SegaPcmWrite(
+                instance,
+                chip::SegaPcmSpec {
+                    offset: u16::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Rf5c68, 0) => VgmCommand::Rf5c68U8Write(
+                instance,
+                chip::Rf5c68U8Spec {
+                    offset: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Rf5c68, 1) => VgmCommand::Rf5c68U16Write(
+                instance,
+                chip::Rf5c68U16Spec {
+                    offset: u16::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Ym2203, 0) => VgmCommand::Ym2203Write(
+                instance,
+                chip::Ym2203Spec {
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Ym2608, port @ (0 | 1)) => VgmCommand::Ym2608Write(
+                instance,
+                chip::Ym2608Spec {
+                    port,
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Ym2610b, port @ (0 | 1)) => VgmCommand::Ym2610bWrite(
+                instance,
+                chip::Ym2610Spec {
+                    port,
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Ym3812, 0) => VgmCommand::Ym3812Write(
+                instance,
+                chip::Ym3812Spec {
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Ym3526, 0) => VgmCommand::Ym3526Write(
+                instance,
+                chip::Ym3526Spec {
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Y8950, 0) => VgmCommand::Y8950Write(
+                instance,
+                chip::Y8950Spec {
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Ymf262, port @ (0 | 1)) => VgmCommand::Ymf262Write(
+                instance,
+                chip::Ymf262Spec {
+                    port,
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Ymf278b, port) => VgmCommand::Ymf278bWrite(
+                instance,
+                chip::Ymf278bSpec {
+                    port,
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Ymf271, port) => VgmCommand::Ymf271Write(
+                instance,
+                chip::Ymf271Spec {
+                    port,
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::K051649, port) => VgmCommand::Scc1Write(
+                instance,
+                chip::Scc1Spec {
+                    port,
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Ymz280b, 0) => VgmCommand::Ymz280bWrite(
+                instance,
+                chip::Ymz280bSpec {
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Rf5c164, 0) => VgmCommand::Rf5c164U8Write(
+                instance,
+                chip::Rf5c164U8Spec {
+                    offset: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Rf5c164, 1) => VgmCommand::Rf5c164U16Write(
+                instance,
+                chip::Rf5c164U16Spec {
+                    offset: u16::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Pwm, 0) => VgmCommand::PwmWrite(
+                instance,
+                chip::PwmSpec {
+                    register: u8::try_from(self.register).ok()?,
+                    value: self.value,
+                },
+            ),
+            (Chip::Ay8910, 0) => VgmCommand::Ay8910Write(
+                instance,
+                chip::Ay8910Spec {
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::GbDmg, 0) => VgmCommand::GbDmgWrite(
+                instance,
+                chip::GbDmgSpec {
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::NesApu, 0) => VgmCommand::NesApuWrite(
+                instance,
+                chip::NesApuSpec {
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::MultiPcm, 0) => VgmCommand::MultiPcmWrite(
+                instance,
+                chip::MultiPcmSpec {
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::MultiPcm, 1) => VgmCommand::MultiPcmBankWrite(
+                instance,
+                chip::MultiPcmBankSpec {
+                    channel: u8::try_from(self.register).ok()?,
+                    bank_offset: u16::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Upd7759, 0) => VgmCommand::Upd7759Write(
+                instance,
+                chip::Upd7759Spec {
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Okim6258, 0) => VgmCommand::Okim6258Write(
+                instance,
+                chip::Okim6258Spec {
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Okim6295, 0) => VgmCommand::Okim6295Write(
+                instance,
+                chip::Okim6295Spec {
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::K054539, 0) => VgmCommand::K054539Write(
+                instance,
+                chip::K054539Spec {
+                    register: u16::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Huc6280, 0) => VgmCommand::Huc6280Write(
+                instance,
+                chip::Huc6280Spec {
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::C140, 0) => VgmCommand::C140Write(
+                instance,
+                chip::C140Spec {
+                    register: u16::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::K053260, 0) => VgmCommand::K053260Write(
+                instance,
+                chip::K053260Spec {
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Pokey, 0) => VgmCommand::PokeyWrite(
+                instance,
+                chip::PokeySpec {
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Qsound, 0) => VgmCommand::QsoundWrite(
+                instance,
+                chip::QsoundSpec {
+                    register: u8::try_from(self.register).ok()?,
+                    value: u16::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Scsp, 0) => VgmCommand::ScspWrite(
+                instance,
+                chip::ScspSpec {
+                    offset: u16::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::WonderSwan, 0) => VgmCommand::WonderSwanWrite(
+                instance,
+                chip::WonderSwanSpec {
+                    offset: u16::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::WonderSwan, 1) => VgmCommand::WonderSwanRegWrite(
+                instance,
+                chip::WonderSwanRegSpec {
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Vsu, 0) => VgmCommand::VsuWrite(
+                instance,
+                chip::VsuSpec {
+                    offset: u16::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Saa1099, 0) => VgmCommand::Saa1099Write(
+                instance,
+                chip::Saa1099Spec {
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Es5503, 0) => VgmCommand::Es5503Write(
+                instance,
+                chip::Es5503Spec {
+                    register: u16::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Es5506U8, 0) => VgmCommand::Es5506BEWrite(
+                instance,
+                chip::Es5506U8Spec {
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Es5506U16, 0) => VgmCommand::Es5506D6Write(
+                instance,
+                chip::Es5506U16Spec {
+                    register: u8::try_from(self.register).ok()?,
+                    value: u16::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::X1010, 0) => VgmCommand::X1010Write(
+                instance,
+                chip::X1010Spec {
+                    offset: u16::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::C352, 0) => VgmCommand::C352Write(
+                instance,
+                chip::C352Spec {
+                    register: u16::try_from(self.register).ok()?,
+                    value: u16::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Ga20, 0) => VgmCommand::Ga20Write(
+                instance,
+                chip::Ga20Spec {
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            (Chip::Mikey, 0) => VgmCommand::MikeyWrite(
+                instance,
+                chip::MikeySpec {
+                    register: u8::try_from(self.register).ok()?,
+                    value: u8::try_from(self.value).ok()?,
+                },
+            ),
+            _ => return None,
+        })
+    }
+}
+
 /// Trait for VGM command specifications.
 pub(crate) trait CommandSpec {
     fn opcode(&self) -> u8;
@@ -2883,14 +3460,7 @@ impl VgmDocument {
         self.commands
             .iter()
             .skip(index)
-            .map(|cmd| match cmd {
-                VgmCommand::WaitSamples(s) => s.0 as u32,
-                VgmCommand::Wait735Samples(_) => 735,
-                VgmCommand::Wait882Samples(_) => 882,
-                VgmCommand::WaitNSample(s) => s.0 as u32 + 1,
-                VgmCommand::YM2612Port0Address2AWriteAndWaitN(s) => s.0 as u32,
-                _ => 0,
-            })
+            .map(VgmCommand::wait_samples)
             .sum::<u32>()
     }
 
@@ -2913,6 +3483,13 @@ impl VgmDocument {
         // If an EndOfData opcode is required, it must be present in `self.commands`
         // before calling `to_bytes()`.
 
+        self.to_bytes_with_commands(&cmd_buf)
+    }
+
+    /// Like `to_bytes()`, with the already serialized command stream
+    /// `cmd_buf`. Used by the incremental serializer, which assembles the
+    /// command bytes from ranges of the original file.
+    pub(crate) fn to_bytes_with_commands(&self, cmd_buf: &[u8]) -> Vec<u8> {
         // data offset (DataOffset)
         // Compute effective data_offset using VgmHeader helper.
         let data_offset: u32 = VgmHeader::data_offset(self.header.version, self.header.data_offset);
@@ -2925,7 +3502,7 @@ impl VgmDocument {
         } else {
             VgmHeaderField::DataOffset
                 .offset()
-                .wrapping_add(data_offset as usize)
+                .saturating_add(data_offset as usize)
         };
 
         // Build extra_header bytes
@@ -2933,9 +3510,12 @@ impl VgmDocument {
             let extra_bytes = extra_header.to_bytes();
             if self.header.extra_header_offset != 0 {
                 let stored_offset = self.header.extra_header_offset;
-                let desired_start = stored_offset
-                    .wrapping_add(VgmHeaderField::ExtraHeaderOffset.offset() as u32)
-                    as usize;
+                // An offset that does not resolve is treated like one past the data.
+                let desired_start = FileOffset::from_relative(
+                    VgmHeaderField::ExtraHeaderOffset.offset(),
+                    stored_offset,
+                )
+                .map_or(usize::MAX, FileOffset::get);
 
                 // Ensure extra header placement doesn't overlap the data; clear stored offset if so.
                 let allowed_header_size = header_size;
@@ -2971,8 +3551,9 @@ impl VgmDocument {
                     }
                 }
             } else {
-                let extra_start = header.len() as u32;
-                let extra_offset_val = extra_start.wrapping_sub(0xBC_u32);
+                let extra_offset_val = FileOffset::new(header.len())
+                    .to_relative(VgmHeaderField::ExtraHeaderOffset.offset())
+                    .unwrap_or(0);
                 header.extend_from_slice(&extra_bytes);
                 let needed = 0xBC_usize + 4;
                 if header_size >= needed {
@@ -2996,15 +3577,16 @@ impl VgmDocument {
 
         // Append command stream. Note: do NOT append EndOfData automatically here.
         // Callers should include EndOfData in `self.commands` if desired.
-        header.extend_from_slice(&cmd_buf);
+        header.extend_from_slice(cmd_buf);
 
         // If GD3 metadata is present, append the full GD3 chunk and update
         // the header's GD3 offset field to point to its location. Only write
         // the GD3 offset into the main header if that field exists for the
         // computed header_size.
         if let Some(gd3) = &self.gd3 {
-            let gd3_start = header.len() as u32;
-            let gd3_offset_val = gd3_start.wrapping_sub(0x14_u32);
+            let gd3_offset_val = FileOffset::new(header.len())
+                .to_relative(VgmHeaderField::Gd3Offset.offset())
+                .unwrap_or(0);
             let gd3_bytes = gd3.to_bytes();
             header.extend_from_slice(&gd3_bytes);
             let gd3_off_bytes = gd3_offset_val.to_le_bytes();
@@ -3036,8 +3618,9 @@ impl VgmDocument {
             }
             // Only compute and write EOF if the header's stored eof_offset is zero.
             if self.header.eof_offset == 0 {
-                let file_size = header.len() as u32;
-                let eof_offset = file_size.wrapping_sub(4);
+                let eof_offset = FileOffset::new(header.len())
+                    .to_relative(VgmHeaderField::EofOffset.offset())
+                    .unwrap_or(0);
                 let eof_bytes = eof_offset.to_le_bytes();
                 header[0x04..0x08].copy_from_slice(&eof_bytes);
             }
@@ -3081,7 +3664,7 @@ impl VgmDocument {
             // `command_to_vgm_bytes` returns (bytes, len). We only need len here.
             let (_bytes, len) = command_to_vgm_bytes(cmd);
             out.push((offset, len));
-            offset = offset.wrapping_add(len);
+            offset += len;
         }
         out
     }
@@ -3093,17 +3676,8 @@ impl VgmDocument {
     /// `command_offsets_and_lengths()` so callers receive absolute file offsets
     /// suitable for highlighting bytes in the original serialized VGM file.
     pub fn sourcemap(&self) -> Vec<(usize, usize)> {
-        // compute data_offset the same way as to_bytes()
-        let data_offset: u32 = if self.header.data_offset == 0 {
-            (VgmHeader::fallback_header_size_for_version(self.header.version).wrapping_sub(0x34))
-                as u32
-        } else {
-            self.header.data_offset
-        };
-
-        // compute actual header length in file: fixed header (0x34) + data_offset region
-        // this matches the calculation in loop_command_index()
-        let header_len = (0x34 + data_offset) as usize;
+        // header length computed the same way as loop_command_index()
+        let header_len = VgmHeader::command_start(self.header.version, self.header.data_offset);
 
         self.command_offsets_and_lengths()
             .into_iter()
@@ -0,0 +1,449 @@
+//! Standalone compression and decompression of VGM stream data blocks.
+//!
+//! `VgmStream` decompresses compressed stream blocks (data types
+//! `0x40..=0x7E`) while parsing. This module exposes the same decoding as
+//! `decompress_block`, and `compress_block` as its inverse, so tools can turn
+//! uncompressed PCM blocks into compressed ones and shrink PCM-heavy files.
+//! `generate_table` builds the decompression table (data type `0x7F`) needed
+//! by DPCM and table-based bit packing; `transform::compress_data_blocks`
+//! applies all of this to a whole document.
+//!
+//! ```rust
+//! use soundlog::vgm::compression::{compress_block, decompress_block, BlockEncoding};
+//! use soundlog::vgm::detail::{BitPackingSubType, StreamChipType, UncompressedStream};
+//!
+//! let stream = UncompressedStream {
+//!     chip_type: StreamChipType::Ym2612Pcm,
+//!     data: vec![0x80, 0x81, 0x8F, 0x84],
+//! };
+//! let encoding = BlockEncoding::BitPacking {
+//!     bits_decompressed: 8,
+//!     bits_compressed: 4,
+//!     sub_type: BitPackingSubType::Copy,
+//!     add_value: 0x80,
+//! };
+//!
+//! let compressed = compress_block(&stream, &encoding, None).unwrap();
+//! assert_eq!(decompress_block(&compressed, None).unwrap(), stream.data);
+//! ```
+use std::collections::BTreeSet;
+
+use crate::binutil::{ParseError, ResourceLimit};
+use crate::vgm::detail::{
+    BitPackingCompression, BitPackingSubType, CompressedStream, CompressedStreamData,
+    CompressionType, DecompressionTable, DpcmCompression, UncompressedStream,
+};
+use crate::vgm::parser::{ParseLimits, check_limit};
+
+/// Parameters for `compress_block`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockEncoding {
+    /// Bit-packing compression (`CompressionType::BitPacking`).
+    ///
+    /// - `Copy` stores `value - add_value`; every value must fit in
+    ///   `bits_compressed` bits.
+    /// - `ShiftLeft` stores the high `bits_compressed` bits of
+    ///   `value - add_value`. The low bits are discarded, so this is lossy
+    ///   unless they are zero.
+    /// - `UseTable` stores the index of each value in the decompression table
+    ///   passed to `compress_block`; every value must be in the table.
+    BitPacking {
+        bits_decompressed: u8,
+        bits_compressed: u8,
+        sub_type: BitPackingSubType,
+        add_value: u16,
+    },
+    /// DPCM compression (`CompressionType::Dpcm`).
+    ///
+    /// The first value becomes the start value and every value is stored as
+    /// the index of its difference to the previous one in the decompression
+    /// table passed to `compress_block`; every difference must be in the
+    /// table. `bits_decompressed` is at most 16.
+    Dpcm {
+        bits_decompressed: u8,
+        bits_compressed: u8,
+    },
+}
+
+/// Decompress a compressed stream block.
+///
+/// `table` is the decompression table (data type `0x7F`) the block refers
+/// to; it is required for `BitPackingSubType::UseTable` and for DPCM. The
+/// result is at most `uncompressed_size` bytes long: padding bits at the end
+/// of the compressed data are not decoded. Like `VgmStream`, a block whose
+/// data ends early decodes to fewer bytes instead of failing.
+///
+/// The declared `uncompressed_size` is not trusted for allocation: output
+/// grows only as far as the compressed data can yield. Use
+/// `decompress_block_with_limits` to also reject a declared size above a
+/// configured limit before decoding.
+///
+/// # Errors
+/// Returns an error if a required table is missing or does not cover a
+/// compressed value, if a bit width is zero or cannot be read, or if the
+/// compression type or sub-type is unknown.
+pub fn decompress_block(
+    stream: &CompressedStream,
+    table: Option<&DecompressionTable>,
+) -> Result<Vec<u8>, ParseError> {
+    decompress_block_with_limits(stream, table, &ParseLimits::unlimited())
+}
+
+/// `decompress_block` for untrusted input: a block declaring more than
+/// `limits.max_decompressed_size` bytes fails with
+/// `ParseError::LimitExceeded` (`ResourceLimit::DecompressedSize`) before
+/// anything is decoded.
+///
+/// ```rust
+/// use soundlog::vgm::compression::decompress_block_with_limits;
+/// use soundlog::vgm::detail::{
+///     BitPackingCompression, BitPackingSubType, CompressedStream, CompressedStreamData,
+///     CompressionType, StreamChipType,
+/// };
+/// use soundlog::vgm::parser::ParseLimits;
+/// use soundlog::{ParseError, ResourceLimit};
+///
+/// // Eight bytes of input claiming to expand to 4 GiB.
+/// let stream = CompressedStream {
+///     chip_type: StreamChipType::Ym2612Pcm,
+///     compression_type: CompressionType::BitPacking,
+///     uncompressed_size: u32::MAX,
+///     compression: CompressedStreamData::BitPacking(BitPackingCompression {
+///         bits_decompressed: 8,
+///         bits_compressed: 1,
+///         sub_type: BitPackingSubType::Copy,
+///         add_value: 0,
+///         data: vec![0xFF; 8],
+///     }),
+/// };
+/// let err = decompress_block_with_limits(&stream, None, &ParseLimits::untrusted());
+/// assert!(matches!(
+///     err,
+///     Err(ParseError::LimitExceeded { limit: ResourceLimit::DecompressedSize, .. })
+/// ));
+/// ```
+///
+/// # Errors
+/// As `decompress_block`, plus the limit above.
+pub fn decompress_block_with_limits(
+    stream: &CompressedStream,
+    table: Option<&DecompressionTable>,
+    limits: &ParseLimits,
+) -> Result<Vec<u8>, ParseError> {
+    let uncompressed_size = stream.uncompressed_size as usize;
+    check_limit(
+        ResourceLimit::DecompressedSize,
+        uncompressed_size,
+        limits.max_decompressed_size,
+    )?;
+    // Padding bits at the end of the data must not decode to extra values.
+    let max_values = |bits_decompressed: u8| {
+        uncompressed_size.div_ceil(bits_decompressed.div_ceil(8).max(1) as usize)
+    };
+
+    let mut data = match &stream.compression {
+        CompressedStreamData::BitPacking(bp) => {
+            bp.decode(table, usize::MAX, max_values(bp.bits_decompressed))?
+        }
+        CompressedStreamData::Dpcm(dpcm) => {
+            let table = table.ok_or_else(|| {
+                ParseError::DataInconsistency("Decompression table required for DPCM".to_string())
+            })?;
+            dpcm.decode(table, usize::MAX, max_values(dpcm.bits_decompressed))?
+        }
+        CompressedStreamData::Unknown {
+            compression_type, ..
+        } => {
+            return Err(ParseError::Other(format!(
+                "Unknown compression type: {:#04X}",
+                compression_type
+            )));
+        }
+    };
+
+    data.truncate(uncompressed_size);
+    Ok(data)
+}
+
+/// Compress an uncompressed stream block.
+///
+/// The data of `stream` is read as little-endian values of
+/// `bits_decompressed.div_ceil(8)` bytes. The returned block keeps the chip
+/// type of `stream`, so `build_data_block` (or `VgmBuilder::attach_data_block`)
+/// stores it as data type `0x40` plus the uncompressed type.
+///
+/// `table` is required for `BitPackingSubType::UseTable` and DPCM; use
+/// `generate_table` to build one that covers the data.
+///
+/// # Errors
+/// Returns an error if the bit widths are out of range, if the data length is
+/// not a multiple of the value size, if a required table is missing, or if a
+/// value cannot be represented (see `BlockEncoding`).
+pub fn compress_block(
+    stream: &UncompressedStream,
+    encoding: &BlockEncoding,
+    table: Option<&DecompressionTable>,
+) -> Result<CompressedStream, ParseError> {
+    let (bits_decompressed, bits_compressed) = encoding.bit_widths();
+    let bytes_per_value = check_encoding(stream, encoding)?;
+    let value_mask = mask(bytes_per_value as u32 * 8);
+    let compressed_mask = mask(bits_compressed as u32);
+    let table_index = |value: u32| -> Result<u32, ParseError> {
+        let table = table.ok_or_else(|| {
+            ParseError::DataInconsistency(format!(
+                "Decompression table required for {:?}",
+                encoding
+            ))
+        })?;
+        table_values(&table.table_data, bytes_per_value)
+            .position(|entry| entry == value)
+            .map(|index| index as u32)
+            .ok_or_else(|| {
+                ParseError::DataInconsistency(format!(
+                    "Value {:#X} not found in decompression table",
+                    value
+                ))
+            })
+    };
+
+    let mut writer = BitStreamWriter::default();
+    let mut push = |compressed: u32, value: u32| -> Result<(), ParseError> {
+        if compressed & !compressed_mask != 0 {
+            return Err(ParseError::DataInconsistency(format!(
+                "Value {:#X} does not fit in {} bits",
+                value, bits_compressed
+            )));
+        }
+        writer.write_bits(compressed, bits_compressed);
+        Ok(())
+    };
+
+    let compression = match *encoding {
+        BlockEncoding::BitPacking {
+            sub_type,
+            add_value,
+            ..
+        } => {
+            for value in table_values(&stream.data, bytes_per_value) {
+                let compressed = match sub_type {
+                    BitPackingSubType::Copy => value.wrapping_sub(add_value as u32) & value_mask,
+                    BitPackingSubType::ShiftLeft => {
+                        (value.wrapping_sub(add_value as u32) & value_mask)
+                            >> (bits_decompressed - bits_compressed)
+                    }
+                    BitPackingSubType::UseTable => table_index(value)?,
+                    BitPackingSubType::Unknown(v) => {
+                        return Err(ParseError::Other(format!(
+                            "Unknown bit packing sub-type: {:#04X}",
+                            v
+                        )));
+                    }
+                };
+                push(compressed, value)?;
+            }
+            CompressedStreamData::BitPacking(BitPackingCompression {
+                bits_decompressed,
+                bits_compressed,
+                sub_type,
+                add_value,
+                data: writer.finish(),
+            })
+        }
+        BlockEncoding::Dpcm { .. } => {
+            let start_value = table_values(&stream.data, bytes_per_value)
+                .next()
+                .unwrap_or(0);
+            let mut state = start_value;
+            for value in table_values(&stream.data, bytes_per_value) {
+                let delta = value.wrapping_sub(state) & value_mask;
+                push(table_index(delta)?, value)?;
+                state = value;
+            }
+            CompressedStreamData::Dpcm(DpcmCompression {
+                bits_decompressed,
+                bits_compressed,
+                reserved: 0,
+                start_value: start_value as u16,
+                data: writer.finish(),
+            })
+        }
+    };
+
+    Ok(CompressedStream {
+        chip_type: stream.chip_type,
+        compression_type: encoding.compression_type(),
+        uncompressed_size: stream.data.len() as u32,
+        compression,
+    })
+}
+
+/// Build the smallest decompression table that lets `compress_block` encode
+/// every stream in `streams` with `encoding`.
+///
+/// For `BitPackingSubType::UseTable` the table holds every distinct value;
+/// for DPCM it holds every distinct delta between consecutive values, plus
+/// `0` for the first value. Entries are sorted in ascending order. One table
+/// can be shared by several blocks, which matters because a player only keeps
+/// the most recent table.
+///
+/// # Errors
+/// Returns an error if `encoding` does not use a table, if its parameters are
+/// invalid for one of the streams, or if there are more distinct entries than
+/// `bits_compressed` bits can index.
+pub fn generate_table(
+    streams: &[&UncompressedStream],
+    encoding: &BlockEncoding,
+) -> Result<DecompressionTable, ParseError> {
+    let (bits_decompressed, bits_compressed) = encoding.bit_widths();
+    let sub_type = match *encoding {
+        BlockEncoding::BitPacking {
+            sub_type: BitPackingSubType::UseTable,
+            ..
+        } => 0x02,
+        BlockEncoding::Dpcm { .. } => 0x00,
+        BlockEncoding::BitPacking { sub_type, .. } => {
+            return Err(ParseError::Other(format!(
+                "Bit packing sub-type {:?} does not use a decompression table",
+                sub_type
+            )));
+        }
+    };
+
+    let mut entries = BTreeSet::new();
+    let mut bytes_per_value = bits_decompressed.div_ceil(8) as usize;
+    for stream in streams {
+        bytes_per_value = check_encoding(stream, encoding)?;
+        let value_mask = mask(bytes_per_value as u32 * 8);
+        let mut values = table_values(&stream.data, bytes_per_value).peekable();
+        match encoding {
+            BlockEncoding::BitPacking { .. } => entries.extend(values),
+            BlockEncoding::Dpcm { .. } => {
+                let Some(mut state) = values.peek().copied() else {
+                    continue;
+                };
+                for value in values {
+                    entries.insert(value.wrapping_sub(state) & value_mask);
+                    state = value;
+                }
+            }
+        }
+    }
+
+    let capacity = (1usize << bits_compressed).min(u16::MAX as usize);
+    if entries.len() > capacity {
+        return Err(ParseError::DataInconsistency(format!(
+            "{} distinct table entries do not fit in {} bits",
+            entries.len(),
+            bits_compressed
+        )));
+    }
+    let mut table_data = Vec::with_capacity(entries.len() * bytes_per_value);
+    for entry in &entries {
+        table_data.extend_from_slice(&entry.to_le_bytes()[..bytes_per_value]);
+    }
+    Ok(DecompressionTable {
+        compression_type: encoding.compression_type(),
+        sub_type,
+        bits_decompressed,
+        bits_compressed,
+        value_count: entries.len() as u16,
+        table_data,
+    })
+}
+
+impl BlockEncoding {
+    fn bit_widths(&self) -> (u8, u8) {
+        match *self {
+            BlockEncoding::BitPacking {
+                bits_decompressed,
+                bits_compressed,
+                ..
+            }
+            | BlockEncoding::Dpcm {
+                bits_decompressed,
+                bits_compressed,
+            } => (bits_decompressed, bits_compressed),
+        }
+    }
+
+    fn compression_type(&self) -> CompressionType {
+        match self {
+            BlockEncoding::BitPacking { .. } => CompressionType::BitPacking,
+            BlockEncoding::Dpcm { .. } => CompressionType::Dpcm,
+        }
+    }
+}
+
+// Validate the bit widths of `encoding` against `stream` and return the size
+// of one value in bytes.
+fn check_encoding(
+    stream: &UncompressedStream,
+    encoding: &BlockEncoding,
+) -> Result<usize, ParseError> {
+    let (bits_decompressed, bits_compressed) = encoding.bit_widths();
+    // DPCM stores its start value in 16 bits.
+    let max_decompressed = match encoding {
+        BlockEncoding::BitPacking { .. } => 32,
+        BlockEncoding::Dpcm { .. } => 16,
+    };
+    if !(1..=max_decompressed).contains(&bits_decompressed)
+        || !(1..=32).contains(&bits_compressed)
+        || bits_compressed > bits_decompressed
+    {
+        return Err(ParseError::Other(format!(
+            "Invalid bit widths: {} bits compressed to {} bits",
+            bits_decompressed, bits_compressed
+        )));
+    }
+    let bytes_per_value = bits_decompressed.div_ceil(8) as usize;
+    if !stream.data.len().is_multiple_of(bytes_per_value) {
+        return Err(ParseError::DataInconsistency(format!(
+            "Data length {} is not a multiple of {} byte values",
+            stream.data.len(),
+            bytes_per_value
+        )));
+    }
+    Ok(bytes_per_value)
+}
+
+// Little-endian values of `bytes_per_value` bytes.
+fn table_values(data: &[u8], bytes_per_value: usize) -> impl Iterator<Item = u32> + '_ {
+    data.chunks_exact(bytes_per_value).map(|chunk| {
+        chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |acc, (i, b)| acc | (*b as u32) << (i * 8))
+    })
+}
+
+fn mask(bits: u32) -> u32 {
+    if bits >= 32 {
+        u32::MAX
+    } else {
+        (1u32 << bits) - 1
+    }
+}
+
+/// MSB-first bitstream writer, the counterpart of the reader in `detail`.
+#[derive(Default)]
+struct BitStreamWriter {
+    data: Vec<u8>,
+    bit_pos: u8, // bits used in the last byte, 0 means a new byte is needed
+}
+
+impl BitStreamWriter {
+    fn write_bits(&mut self, value: u32, num_bits: u8) {
+        for i in (0..num_bits).rev() {
+            if self.bit_pos == 0 {
+                self.data.push(0);
+            }
+            let bit = ((value >> i) & 1) as u8;
+            *self.data.last_mut().unwrap() |= bit << (7 - self.bit_pos);
+            self.bit_pos = (self.bit_pos + 1) % 8;
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
@@ -0,0 +1,348 @@
+//! Time-aligned cue points (subtitles, lyrics, visual events).
+//!
+//! A `CueTrack` holds text cues at sample positions (44.1 kHz, like
+//! `VgmStream::current_sample`). Players follow it during playback with a
+//! `CueCursor`, which returns the cues that became due since the last call
+//! and starts over when the stream jumps back to the loop point.
+//!
+//! Cues are stored next to the VGM file in a plain text sidecar (see
+//! `sidecar_path`), one per line as three tab-separated fields, escaped like
+//! the `annotation` sidecar:
+//!
+//! ```text
+//! <sample>\t<id>\t<text>
+//! ```
+//!
+//! To keep cues on the music when the document is edited, `embed_markers`
+//! writes one marker command per cue into the document: a reserved command
+//! `CUE_MARKER_OPCODE` whose four operand bytes hold the cue id (little
+//! endian). Players skip reserved commands, and `resync_markers` moves the
+//! cues back to wherever their markers ended up after an edit.
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::vgm::command::WaitSamples;
+//! use soundlog::vgm::cue::CueTrack;
+//!
+//! let mut cues = CueTrack::new();
+//! cues.add(0, "intro");
+//! cues.add(44_100, "verse 1");
+//!
+//! let mut cursor = cues.cursor();
+//! assert_eq!(cursor.advance(100)[0].text, "intro");
+//! assert!(cursor.advance(200).is_empty());
+//! assert_eq!(cursor.advance(44_100)[0].text, "verse 1");
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.add_vgm_command(WaitSamples(60_000));
+//! let doc = cues.embed_markers(&builder.finalize());
+//! assert_eq!(cues.clone().resync_markers(&doc), 0);
+//! assert_eq!(CueTrack::from_sidecar(&cues.to_sidecar()).unwrap(), cues);
+//! ```
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::binutil::ParseError;
+use crate::vgm::annotation::{escape, unescape};
+use crate::vgm::command::{ReservedU32, VgmCommand, Ym2612Port0Address2AWriteAndWaitN};
+use crate::vgm::transform::push_wait;
+use crate::vgm::{VgmBuilder, VgmDocument};
+
+/// Reserved command (`0xE2..=0xFF` range, four operand bytes) used as cue
+/// marker by `embed_markers`.
+pub const CUE_MARKER_OPCODE: u8 = 0xFE;
+
+/// Extension appended to the VGM file name by `sidecar_path`.
+pub const SIDECAR_EXTENSION: &str = "cues";
+
+const SIDECAR_HEADER: &str = "# soundlog cues v1";
+
+/// A text cue at a sample position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cue {
+    /// Position in samples from the start of the song.
+    pub sample: u64,
+    /// Identifier linking the cue to its marker command.
+    pub id: u32,
+    /// Subtitle, lyric line or event name.
+    pub text: String,
+}
+
+/// Cues of a document, ordered by sample position.
+///
+/// Cues at the same position keep the order they were added in.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CueTrack {
+    cues: Vec<Cue>,
+}
+
+impl CueTrack {
+    /// Creates an empty track.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a cue at `sample` with a new id, which is returned.
+    ///
+    /// The id follows the highest id in use; once that is `u32::MAX`, the
+    /// lowest unused id is taken instead.
+    pub fn add(&mut self, sample: u64, text: impl Into<String>) -> u32 {
+        let id = match self.cues.iter().map(|cue| cue.id).max() {
+            None => 0,
+            Some(max) => max.checked_add(1).unwrap_or_else(|| {
+                // Fewer than 2^32 cues fit in memory, so an id is free.
+                (0..u32::MAX)
+                    .find(|id| self.get(*id).is_none())
+                    .unwrap_or(u32::MAX)
+            }),
+        };
+        self.insert(Cue {
+            sample,
+            id,
+            text: text.into(),
+        });
+        id
+    }
+
+    /// Adds `cue`, replacing the cue with the same id.
+    pub fn insert(&mut self, cue: Cue) {
+        self.remove(cue.id);
+        let index = self.cues.partition_point(|c| c.sample <= cue.sample);
+        self.cues.insert(index, cue);
+    }
+
+    /// Removes the cue with `id`.
+    pub fn remove(&mut self, id: u32) -> Option<Cue> {
+        let index = self.cues.iter().position(|cue| cue.id == id)?;
+        Some(self.cues.remove(index))
+    }
+
+    /// Gets the cue with `id`.
+    pub fn get(&self, id: u32) -> Option<&Cue> {
+        self.cues.iter().find(|cue| cue.id == id)
+    }
+
+    /// Number of cues.
+    pub fn len(&self) -> usize {
+        self.cues.len()
+    }
+
+    /// `true` when the track has no cues.
+    pub fn is_empty(&self) -> bool {
+        self.cues.is_empty()
+    }
+
+    /// Iterates the cues in time order.
+    pub fn iter(&self) -> std::slice::Iter<'_, Cue> {
+        self.cues.iter()
+    }
+
+    /// Cues whose position lies in `samples`.
+    pub fn range(&self, samples: Range<u64>) -> &[Cue] {
+        let start = self.cues.partition_point(|c| c.sample < samples.start);
+        let end = self.cues.partition_point(|c| c.sample < samples.end);
+        &self.cues[start..end.max(start)]
+    }
+
+    /// Creates a cursor for following the track during playback.
+    pub fn cursor(&self) -> CueCursor<'_> {
+        CueCursor {
+            cues: &self.cues,
+            next: 0,
+            position: 0,
+        }
+    }
+
+    /// Returns `document` with one cue marker per cue inserted at the cue's
+    /// position. Existing cue markers are removed first, and waits are split
+    /// where a cue falls inside them. Cues at or after the end of the song
+    /// are placed before `EndOfData`.
+    pub fn embed_markers(&self, document: &VgmDocument) -> VgmDocument {
+        let loop_index = document.loop_command_index();
+        let mut new_loop_index = None;
+        let mut commands = Vec::with_capacity(document.commands.len() + self.cues.len());
+        let mut pending = self.cues.iter().peekable();
+        let mut time = 0u64;
+        for (index, command) in document.commands.iter().enumerate() {
+            if marker_id(command).is_some() {
+                continue;
+            }
+            if matches!(command, VgmCommand::EndOfData(_)) {
+                commands.extend(pending.by_ref().map(marker));
+            }
+            // markers at the loop point are inside the loop
+            if Some(index) == loop_index {
+                new_loop_index = Some(commands.len());
+            }
+            while let Some(cue) = pending.next_if(|cue| cue.sample <= time) {
+                commands.push(marker(cue));
+            }
+            let wait = command.wait_samples() as u64;
+            let end = time + wait;
+            if pending.peek().is_none_or(|cue| cue.sample >= end) {
+                commands.push(command.clone());
+                time = end;
+                continue;
+            }
+            // A cue falls inside this wait: split it around the markers.
+            if let VgmCommand::YM2612Port0Address2AWriteAndWaitN(_) = command {
+                // keep the DAC write, its wait is split below
+                commands.push(Ym2612Port0Address2AWriteAndWaitN(0).into());
+            }
+            while let Some(cue) = pending.next_if(|cue| cue.sample < end) {
+                push_wait(&mut commands, cue.sample - time);
+                commands.push(marker(cue));
+                time = cue.sample;
+            }
+            push_wait(&mut commands, end - time);
+            time = end;
+        }
+        commands.extend(pending.map(marker));
+
+        let mut builder = VgmBuilder::from(VgmDocument {
+            header: document.header.clone(),
+            extra_header: document.extra_header.clone(),
+            commands,
+            gd3: document.gd3.clone(),
+        });
+        if let Some(index) = new_loop_index {
+            builder.set_loop_index(index);
+        }
+        builder.finalize()
+    }
+
+    /// Moves every cue that has a marker in `document` to the marker's
+    /// position, returning the number of cues that moved.
+    pub fn resync_markers(&mut self, document: &VgmDocument) -> usize {
+        let mut moved = 0;
+        let mut time = 0u64;
+        for command in &document.commands {
+            if let Some(id) = marker_id(command)
+                && let Some(cue) = self.cues.iter_mut().find(|cue| cue.id == id)
+                && cue.sample != time
+            {
+                cue.sample = time;
+                moved += 1;
+            }
+            time += command.wait_samples() as u64;
+        }
+        self.cues.sort_by_key(|cue| cue.sample);
+        moved
+    }
+
+    /// Serializes to the sidecar text format.
+    pub fn to_sidecar(&self) -> String {
+        let mut text = String::from(SIDECAR_HEADER);
+        text.push('\n');
+        for cue in &self.cues {
+            text.push_str(&format!(
+                "{}\t{}\t{}\n",
+                cue.sample,
+                cue.id,
+                escape(&cue.text)
+            ));
+        }
+        text
+    }
+
+    /// Parses the sidecar text format.
+    pub fn from_sidecar(text: &str) -> Result<Self, ParseError> {
+        let mut track = CueTrack::new();
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error =
+                |what: &str| ParseError::Other(format!("cues line {}: {}", line_number + 1, what));
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [sample, id, text] = fields[..] else {
+                return Err(error("expected 3 tab-separated fields"));
+            };
+            track.insert(Cue {
+                sample: sample.parse().map_err(|_| error("invalid sample"))?,
+                id: id.parse().map_err(|_| error("invalid id"))?,
+                text: unescape(text).ok_or_else(|| error("invalid escape in text"))?,
+            });
+        }
+        Ok(track)
+    }
+}
+
+impl<'a> IntoIterator for &'a CueTrack {
+    type Item = &'a Cue;
+    type IntoIter = std::slice::Iter<'a, Cue>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Follows a `CueTrack` during playback.
+#[derive(Debug, Clone)]
+pub struct CueCursor<'a> {
+    cues: &'a [Cue],
+    next: usize,
+    position: u64,
+}
+
+impl<'a> CueCursor<'a> {
+    /// Moves to `sample` and returns the cues at or before it that were not
+    /// returned yet.
+    ///
+    /// Pass the song position (`VgmStream::current_sample`). When it goes
+    /// backwards, e.g. at the loop point, the cursor seeks first so the cues
+    /// of the loop are returned again.
+    pub fn advance(&mut self, sample: u64) -> &'a [Cue] {
+        if sample < self.position {
+            self.seek(sample);
+        }
+        self.position = sample;
+        let start = self.next;
+        self.next = start + self.cues[start..].partition_point(|c| c.sample <= sample);
+        &self.cues[start..self.next]
+    }
+
+    /// Moves to `sample` without returning cues: the next call to `advance`
+    /// returns the cues from `sample` on.
+    pub fn seek(&mut self, sample: u64) {
+        self.next = self.cues.partition_point(|c| c.sample < sample);
+        self.position = sample;
+    }
+
+    /// The most recent cue at or before the current position, e.g. the
+    /// subtitle to show after a seek.
+    pub fn current(&self) -> Option<&'a Cue> {
+        let end = self.cues.partition_point(|c| c.sample <= self.position);
+        end.checked_sub(1).map(|index| &self.cues[index])
+    }
+}
+
+/// The cue id of `command` when it is a cue marker.
+pub fn marker_id(command: &VgmCommand) -> Option<u32> {
+    match command {
+        VgmCommand::ReservedU32Write(spec) if spec.opcode == CUE_MARKER_OPCODE => {
+            Some(u32::from_le_bytes([spec.dd1, spec.dd2, spec.dd3, spec.dd4]))
+        }
+        _ => None,
+    }
+}
+
+/// Path of the sidecar for `vgm_path`: `song.vgm` becomes `song.vgm.cues`.
+pub fn sidecar_path(vgm_path: &Path) -> PathBuf {
+    let mut name = vgm_path.as_os_str().to_owned();
+    name.push(".");
+    name.push(SIDECAR_EXTENSION);
+    PathBuf::from(name)
+}
+
+fn marker(cue: &Cue) -> VgmCommand {
+    let [dd1, dd2, dd3, dd4] = cue.id.to_le_bytes();
+    VgmCommand::ReservedU32Write(ReservedU32 {
+        opcode: CUE_MARKER_OPCODE,
+        dd1,
+        dd2,
+        dd3,
+        dd4,
+    })
+}
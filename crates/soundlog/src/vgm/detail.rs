@@ -165,7 +165,7 @@ impl From<StreamChipType> for u8 {
 }
 
 /// ROM/RAM chip type for ROM/RAM dump blocks (data block types 0x80-0xBF).
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum RomRamChipType {
     /// Sega PCM ROM
     SegaPcmRom,
@@ -359,23 +359,53 @@ impl BitPackingCompression {
     /// Returns error if:
     /// - `sub_type` is `UseTable` but `table` is `None`
     /// - Table is provided but doesn't match compression parameters
+    /// - A bit width is zero, or `ShiftLeft` has more compressed
+    ///   than decompressed bits
     /// - Decompressed output size would exceed `max_size`
     pub fn decompress(
         &mut self,
         table: Option<&DecompressionTable>,
         max_size: usize,
     ) -> Result<(), ParseError> {
+        self.data = self.decode(table, max_size, usize::MAX)?;
+        Ok(())
+    }
+
+    /// Decode at most `max_values` values without modifying `self`.
+    pub(crate) fn decode(
+        &self,
+        table: Option<&DecompressionTable>,
+        max_size: usize,
+        max_values: usize,
+    ) -> Result<Vec<u8>, ParseError> {
         if matches!(self.sub_type, BitPackingSubType::UseTable) && table.is_none() {
             return Err(ParseError::DataInconsistency(
                 "Decompression table required for UseTable sub-type".to_string(),
             ));
         }
+        check_bit_widths(self.bits_decompressed, self.bits_compressed)?;
+        if matches!(self.sub_type, BitPackingSubType::ShiftLeft)
+            && self.bits_compressed > self.bits_decompressed
+        {
+            return Err(invalid_bit_widths(
+                self.bits_decompressed,
+                self.bits_compressed,
+            ));
+        }
 
         let bytes_per_value = self.bits_decompressed.div_ceil(8) as usize;
-        let mut result = Vec::new();
+        let mut result = Vec::with_capacity(decoded_capacity(
+            &self.data,
+            self.bits_compressed,
+            bytes_per_value,
+            max_size,
+            max_values,
+        ));
         let mut bitstream = BitStreamReader::new(&self.data);
 
-        while bitstream.bits_remaining() >= self.bits_compressed as usize {
+        while bitstream.bits_remaining() >= self.bits_compressed as usize
+            && result.len() / bytes_per_value < max_values
+        {
             // Check if adding another value would exceed max_size
             if result.len() + bytes_per_value > max_size {
                 return Err(ParseError::DataBlockSizeExceeded {
@@ -400,7 +430,7 @@ impl BitPackingCompression {
                     // Use table lookup
                     let table = table.unwrap(); // Already checked above
                     let index = compressed_value as usize;
-                    read_table_value(table, index, bytes_per_value)? as u32
+                    read_table_value(table, index, bytes_per_value)?
                 }
                 BitPackingSubType::Unknown(_) => {
                     return Err(ParseError::Other(format!(
@@ -412,9 +442,40 @@ impl BitPackingCompression {
             write_value_bytes(&mut result, decompressed_value, bytes_per_value);
         }
 
-        self.data = result;
-        Ok(())
+        Ok(result)
+    }
+}
+
+// Decoders read `bits_compressed` bits per value, so zero would decode
+// values forever without consuming input.
+fn check_bit_widths(bits_decompressed: u8, bits_compressed: u8) -> Result<(), ParseError> {
+    if bits_decompressed == 0 || bits_compressed == 0 {
+        return Err(invalid_bit_widths(bits_decompressed, bits_compressed));
     }
+    Ok(())
+}
+
+fn invalid_bit_widths(bits_decompressed: u8, bits_compressed: u8) -> ParseError {
+    ParseError::Other(format!(
+        "Invalid bit widths: {} bits compressed to {} bits",
+        bits_decompressed, bits_compressed
+    ))
+}
+
+// Bytes to reserve for decoding `data`: what the compressed bits can yield,
+// never more than the caller's limits, so a declared size is not trusted.
+fn decoded_capacity(
+    data: &[u8],
+    bits_compressed: u8,
+    bytes_per_value: usize,
+    max_size: usize,
+    max_values: usize,
+) -> usize {
+    let available = data.len().saturating_mul(8) / bits_compressed as usize;
+    available
+        .min(max_values)
+        .saturating_mul(bytes_per_value)
+        .min(max_size)
 }
 
 /// DPCM compression data and parameters.
@@ -442,18 +503,39 @@ impl DpcmCompression {
     /// # Errors
     /// Returns error if:
     /// - Table doesn't match compression parameters
+    /// - A bit width is zero
     /// - Decompressed output size would exceed `max_size`
     pub fn decompress(
         &mut self,
         table: &DecompressionTable,
         max_size: usize,
     ) -> Result<(), ParseError> {
+        self.data = self.decode(table, max_size, usize::MAX)?;
+        Ok(())
+    }
+
+    /// Decode at most `max_values` values without modifying `self`.
+    pub(crate) fn decode(
+        &self,
+        table: &DecompressionTable,
+        max_size: usize,
+        max_values: usize,
+    ) -> Result<Vec<u8>, ParseError> {
+        check_bit_widths(self.bits_decompressed, self.bits_compressed)?;
         let bytes_per_value = self.bits_decompressed.div_ceil(8) as usize;
-        let mut result = Vec::new();
+        let mut result = Vec::with_capacity(decoded_capacity(
+            &self.data,
+            self.bits_compressed,
+            bytes_per_value,
+            max_size,
+            max_values,
+        ));
         let mut bitstream = BitStreamReader::new(&self.data);
         let mut state = self.start_value as i32;
 
-        while bitstream.bits_remaining() >= self.bits_compressed as usize {
+        while bitstream.bits_remaining() >= self.bits_compressed as usize
+            && result.len() / bytes_per_value < max_values
+        {
             // Check if adding another value would exceed max_size
             if result.len() + bytes_per_value > max_size {
                 return Err(ParseError::DataBlockSizeExceeded {
@@ -469,8 +551,7 @@ impl DpcmCompression {
             write_value_bytes(&mut result, state as u32, bytes_per_value);
         }
 
-        self.data = result;
-        Ok(())
+        Ok(result)
     }
 }
 
@@ -1094,6 +1175,7 @@ fn read_table_value(
 /// Write a multi-byte value in little-endian format.
 fn write_value_bytes(output: &mut Vec<u8>, value: u32, bytes: usize) {
     for i in 0..bytes {
-        output.push(((value >> (i * 8)) & 0xFF) as u8);
+        // Values wider than 32 bits are zero-extended.
+        output.push((value.checked_shr(i as u32 * 8).unwrap_or(0) & 0xFF) as u8);
     }
 }
@@ -0,0 +1,325 @@
+//! Structural comparison of two `VgmDocument`s.
+//!
+//! `diff_documents` compares two documents field by field and command by
+//! command and classifies every difference as either:
+//!
+//! - `Severity::Benign`: an encoding difference that does not change what a
+//!   player hears, such as a different wait encoding (`0x62` vs
+//!   `0x61 0xDF 0x02`), data blocks of different types stored in a different
+//!   order, or header offsets that depend on the file layout (EOF, GD3,
+//!   data, extra header, loop offset);
+//! - `Severity::Loss`: a difference in chip writes, their timing, the loop
+//!   point, header values, the extra header or the GD3 tag.
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::chip;
+//! use soundlog::vgm::command::{Instance, Wait735Samples, WaitSamples};
+//! use soundlog::vgm::diff::diff_documents;
+//!
+//! let build = |wait_735: bool| {
+//!     let mut builder = VgmBuilder::new();
+//!     builder.register_chip(chip::Chip::Sn76489, Instance::Primary, 3_579_545);
+//!     if wait_735 {
+//!         builder.add_vgm_command(Wait735Samples);
+//!     } else {
+//!         builder.add_vgm_command(WaitSamples(735));
+//!     }
+//!     builder.add_chip_write(Instance::Primary, chip::PsgSpec { value: 0x9F });
+//!     builder.finalize()
+//! };
+//!
+//! let diff = diff_documents(&build(true), &build(false));
+//! assert!(!diff.is_identical());
+//! assert!(!diff.has_loss());
+//! ```
+use crate::vgm::VgmDocument;
+use crate::vgm::command::{VgmCommand, Ym2612Port0Address2AWriteAndWaitN};
+use crate::vgm::header::VgmHeaderField;
+
+/// How much a difference matters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Severity {
+    /// Encoding or layout difference with no audible effect.
+    Benign,
+    /// Difference that changes playback or metadata.
+    Loss,
+}
+
+/// Where a difference was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiffLocation {
+    /// A header field.
+    Header(VgmHeaderField),
+    /// The extra header.
+    ExtraHeader,
+    /// The command stream. Indices point into the `commands` of the left and
+    /// right documents; `None` means the difference is not tied to a single
+    /// command on that side (for example a missing command or data blocks).
+    Command {
+        left: Option<usize>,
+        right: Option<usize>,
+    },
+    /// The loop point.
+    LoopPoint,
+    /// The GD3 tag.
+    Gd3,
+}
+
+/// A single difference between two documents.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difference {
+    pub severity: Severity,
+    pub location: DiffLocation,
+    /// Human readable description.
+    pub message: String,
+}
+
+/// Result of `diff_documents`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DocumentDiff {
+    pub differences: Vec<Difference>,
+}
+
+impl DocumentDiff {
+    /// `true` when no difference at all was found.
+    pub fn is_identical(&self) -> bool {
+        self.differences.is_empty()
+    }
+
+    /// `true` when at least one difference changes playback or metadata.
+    pub fn has_loss(&self) -> bool {
+        self.losses().next().is_some()
+    }
+
+    /// Differences classified as `Severity::Benign`.
+    pub fn benign(&self) -> impl Iterator<Item = &Difference> {
+        self.differences
+            .iter()
+            .filter(|d| d.severity == Severity::Benign)
+    }
+
+    /// Differences classified as `Severity::Loss`.
+    pub fn losses(&self) -> impl Iterator<Item = &Difference> {
+        self.differences
+            .iter()
+            .filter(|d| d.severity == Severity::Loss)
+    }
+
+    fn push(&mut self, severity: Severity, location: DiffLocation, message: String) {
+        self.differences.push(Difference {
+            severity,
+            location,
+            message,
+        });
+    }
+}
+
+// Header fields whose value only depends on where things are placed in the
+// file.
+const LAYOUT_FIELDS: [VgmHeaderField; 5] = [
+    VgmHeaderField::EofOffset,
+    VgmHeaderField::Gd3Offset,
+    VgmHeaderField::LoopOffset,
+    VgmHeaderField::DataOffset,
+    VgmHeaderField::ExtraHeaderOffset,
+];
+
+/// Compare two documents and classify their differences.
+///
+/// Command streams are compared on a normalized timeline: waits are folded
+/// into absolute sample positions, `0x8n` commands are compared without their
+/// embedded wait, and data blocks are compared separately from the timeline.
+/// The comparison is positional; after an inserted or removed command every
+/// following position is reported, so callers usually only show the first
+/// few losses.
+pub fn diff_documents(left: &VgmDocument, right: &VgmDocument) -> DocumentDiff {
+    let mut diff = DocumentDiff::default();
+    diff_header(left, right, &mut diff);
+    diff_commands(left, right, &mut diff);
+
+    if left.extra_header != right.extra_header {
+        diff.push(
+            Severity::Loss,
+            DiffLocation::ExtraHeader,
+            "extra header differs".to_string(),
+        );
+    }
+    if left.gd3 != right.gd3 {
+        diff.push(Severity::Loss, DiffLocation::Gd3, "GD3 differs".to_string());
+    }
+    diff
+}
+
+fn diff_header(left: &VgmDocument, right: &VgmDocument, diff: &mut DocumentDiff) {
+    let left_bytes = left
+        .header
+        .to_bytes(left.header.gd3_offset, left.header.data_offset);
+    let right_bytes = right
+        .header
+        .to_bytes(right.header.gd3_offset, right.header.data_offset);
+    let field_bytes = |bytes: &[u8], field: VgmHeaderField| -> Vec<u8> {
+        (field.offset()..field.offset() + field.len())
+            .map(|i| bytes.get(i).copied().unwrap_or(0))
+            .collect()
+    };
+
+    for field in VgmHeaderField::ALL {
+        let l = field_bytes(&left_bytes, field);
+        let r = field_bytes(&right_bytes, field);
+        if l == r {
+            continue;
+        }
+        let severity = if LAYOUT_FIELDS.contains(&field) {
+            Severity::Benign
+        } else {
+            Severity::Loss
+        };
+        diff.push(
+            severity,
+            DiffLocation::Header(field),
+            format!("{:?}: {:02X?} != {:02X?}", field, l, r),
+        );
+    }
+}
+
+// Non-wait commands with their absolute sample time and original index.
+struct Timeline<'a> {
+    events: Vec<(u64, usize, VgmCommand)>,
+    data_blocks: Vec<&'a VgmCommand>,
+    loop_time: Option<u64>,
+}
+
+fn timeline(document: &VgmDocument) -> Timeline<'_> {
+    let loop_index = document.loop_command_index();
+    let mut timeline = Timeline {
+        events: Vec::new(),
+        data_blocks: Vec::new(),
+        loop_time: None,
+    };
+    let mut time: u64 = 0;
+    for (index, command) in document.commands.iter().enumerate() {
+        if loop_index == Some(index) {
+            timeline.loop_time = Some(time);
+        }
+        match command {
+            VgmCommand::WaitSamples(_)
+            | VgmCommand::Wait735Samples(_)
+            | VgmCommand::Wait882Samples(_)
+            | VgmCommand::WaitNSample(_)
+            | VgmCommand::EndOfData(_) => {}
+            VgmCommand::DataBlock(_) => timeline.data_blocks.push(command),
+            VgmCommand::YM2612Port0Address2AWriteAndWaitN(_) => {
+                timeline
+                    .events
+                    .push((time, index, Ym2612Port0Address2AWriteAndWaitN(0).into()))
+            }
+            other => timeline.events.push((time, index, other.clone())),
+        }
+        time += command.wait_samples() as u64;
+    }
+    timeline
+}
+
+fn diff_commands(left: &VgmDocument, right: &VgmDocument, diff: &mut DocumentDiff) {
+    if left.commands == right.commands {
+        return;
+    }
+    let l = timeline(left);
+    let r = timeline(right);
+
+    let mut timeline_equal = true;
+    for i in 0..l.events.len().max(r.events.len()) {
+        let (left_event, right_event) = (l.events.get(i), r.events.get(i));
+        let message = match (left_event, right_event) {
+            (Some((lt, _, lc)), Some((rt, _, rc))) if lc == rc && lt == rt => continue,
+            (Some((lt, _, lc)), Some((rt, _, rc))) if lc == rc => {
+                format!("{:?} moved from sample {} to {}", lc, lt, rt)
+            }
+            (Some((lt, _, lc)), Some((rt, _, rc))) => {
+                format!("{:?} @{} != {:?} @{}", lc, lt, rc, rt)
+            }
+            (Some((lt, _, lc)), None) => format!("{:?} @{} missing on right", lc, lt),
+            (None, Some((rt, _, rc))) => format!("{:?} @{} missing on left", rc, rt),
+            (None, None) => continue,
+        };
+        timeline_equal = false;
+        diff.push(
+            Severity::Loss,
+            DiffLocation::Command {
+                left: left_event.map(|e| e.1),
+                right: right_event.map(|e| e.1),
+            },
+            message,
+        );
+    }
+
+    let left_end: u32 = left.total_samples(0);
+    let right_end: u32 = right.total_samples(0);
+    if left_end != right_end {
+        timeline_equal = false;
+        diff.push(
+            Severity::Loss,
+            DiffLocation::Command {
+                left: None,
+                right: None,
+            },
+            format!("total length {} != {} samples", left_end, right_end),
+        );
+    }
+
+    if l.data_blocks != r.data_blocks {
+        let mut left_sorted = l.data_blocks.clone();
+        let mut right_sorted = r.data_blocks.clone();
+        // Only the order within one data type matters: blocks of the same
+        // type are concatenated into one bank.
+        let key = |c: &&VgmCommand| match c {
+            VgmCommand::DataBlock(b) => b.data_type,
+            _ => 0,
+        };
+        left_sorted.sort_by_key(key);
+        right_sorted.sort_by_key(key);
+        let (severity, message) = if left_sorted == right_sorted {
+            (
+                Severity::Benign,
+                "data blocks of different types interleaved differently",
+            )
+        } else {
+            (Severity::Loss, "data blocks differ")
+        };
+        diff.push(
+            severity,
+            DiffLocation::Command {
+                left: None,
+                right: None,
+            },
+            message.to_string(),
+        );
+    }
+
+    if l.loop_time != r.loop_time {
+        diff.push(
+            Severity::Loss,
+            DiffLocation::LoopPoint,
+            format!("loop point {:?} != {:?} samples", l.loop_time, r.loop_time),
+        );
+    }
+
+    let without_blocks = |d: &'_ VgmDocument| -> Vec<VgmCommand> {
+        d.commands
+            .iter()
+            .filter(|c| !matches!(c, VgmCommand::DataBlock(_)))
+            .cloned()
+            .collect()
+    };
+    if timeline_equal && without_blocks(left) != without_blocks(right) {
+        diff.push(
+            Severity::Benign,
+            DiffLocation::Command {
+                left: None,
+                right: None,
+            },
+            "wait or command encoding differs".to_string(),
+        );
+    }
+}
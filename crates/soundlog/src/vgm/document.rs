@@ -19,14 +19,39 @@
 //!   used across the crate (including `data_offset` fallbacks and stored
 //!   `extra_header_offset` semantics).
 //! - Most items are crate-visible and intended for use inside `soundlog`.
+//!
+//! Deterministic output:
+//! - Serialization depends only on the document: the same document always
+//!   gives the same bytes, and a builder fed the same calls always finalizes
+//!   to the same document. Nothing depends on hash order, time or the
+//!   environment.
+//! - The header is written field by field in file order up to the header
+//!   size of the version; fields without a value, and any gap up to the data
+//!   offset, are zero.
+//! - `finalize()` moves data blocks to the start of the command stream:
+//!   decompression tables (`0x7F`) first, then the other blocks, each in the
+//!   order they were added. All other commands keep their order.
+//! - The extra header follows the main header, the GD3 chunk follows the
+//!   command stream; no other padding is inserted.
+//! - `vgm::verify::verify_deterministic` checks a document against these
+//!   rules: its bytes are stable and parse and serialize back unchanged.
+use crate::binutil::{FileOffset, ParseError};
 use crate::chip;
 use crate::meta::Gd3;
 use crate::vgm::command::Instance;
 use crate::vgm::command::VgmCommand;
+use crate::vgm::command::{
+    BlockId, DataBlock, LengthMode, StartStream, StartStreamFastCall, StartStreamFastCallFlags,
+    StreamId,
+};
 use crate::vgm::detail;
 use crate::vgm::header::{VgmExtraHeader, VgmHeader, VgmHeaderField};
+use crate::vgm::lint::{self, LintIssue, LintOptions};
+use crate::vgm::opcode;
 use crate::vgm::parser;
+use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::ops::Range;
 
 #[derive(Debug, Clone, PartialEq, Default)]
 /// A complete VGM document, consisting of a header, an ordered command
@@ -53,6 +78,15 @@ pub struct VgmDocument {
 pub struct VgmBuilder {
     document: VgmDocument,
     loop_index: Option<usize>,
+    wait_error: f64,
+    auto_dual_chip: bool,
+    auto_version: bool,
+    dedupe_writes: bool,
+    deduped_writes: usize,
+    // Value of the latest write to each register since the last wait, for
+    // `set_dedupe_writes`, covering the first `last_writes_scanned` commands.
+    last_writes: HashMap<(chip::Chip, Instance, u8, u32), u32>,
+    last_writes_scanned: usize,
 }
 
 /// Implementation of `VgmBuilder` methods.
@@ -69,9 +103,30 @@ impl VgmBuilder {
         VgmBuilder {
             document: VgmDocument::default(),
             loop_index: None,
+            wait_error: 0.0,
+            auto_dual_chip: false,
+            auto_version: false,
+            dedupe_writes: false,
+            deduped_writes: 0,
+            last_writes: HashMap::new(),
+            last_writes_scanned: 0,
         }
     }
 
+    /// Create a new `VgmBuilder` with room for `capacity` commands, avoiding
+    /// reallocations while appending a known number of commands.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut builder = Self::new();
+        builder.document.commands.reserve_exact(capacity);
+        builder
+    }
+
+    /// Reserve room for at least `additional` more commands.
+    pub fn reserve(&mut self, additional: usize) -> &mut Self {
+        self.document.commands.reserve(additional);
+        self
+    }
+
     /// Register a chip in the VGM header with its master clock frequency.
     ///
     /// `c` is convertible to `chip::Chip`. `instance` selects which instance
@@ -153,6 +208,85 @@ impl VgmBuilder {
         self
     }
 
+    /// Switch to strict mode.
+    ///
+    /// The returned `StrictVgmBuilder` takes the same methods as this builder
+    /// but can only be finished with `try_finalize()`, which fails when a
+    /// command writes to a chip that was never given a clock with
+    /// `register_chip`, which would make the file play silent.
+    pub fn into_strict(self) -> StrictVgmBuilder {
+        StrictVgmBuilder { builder: self }
+    }
+
+    /// Enable or disable setting the dual-chip bit automatically.
+    ///
+    /// When enabled, `finalize()` sets the dual-chip bit (`0x8000_0000`) of
+    /// the header clock of every chip that has a primary clock and receives
+    /// `Instance::Secondary` writes, as if `register_chip` had been called for
+    /// the secondary instance. Default is `false`.
+    pub fn set_auto_dual_chip(&mut self, enabled: bool) -> &mut Self {
+        self.auto_dual_chip = enabled;
+        self
+    }
+
+    /// Gets whether the dual-chip bit is set automatically.
+    pub fn auto_dual_chip(&self) -> bool {
+        self.auto_dual_chip
+    }
+
+    /// Enable or disable choosing the VGM version automatically.
+    ///
+    /// When enabled, `finalize()` sets the header version to
+    /// `VgmDocument::required_version()`, the oldest version that holds every
+    /// command and chip of the document, replacing any `set_version` value.
+    /// Default is `false`.
+    pub fn set_auto_version(&mut self, enabled: bool) -> &mut Self {
+        self.auto_version = enabled;
+        self
+    }
+
+    /// Gets whether the VGM version is chosen automatically.
+    pub fn auto_version(&self) -> bool {
+        self.auto_version
+    }
+
+    /// Enable or disable dropping repeated writes.
+    ///
+    /// When enabled, `add_chip_write` and `add_vgm_command` drop a chip write
+    /// whose value equals the previous write to the same register of the same
+    /// chip instance when no wait lies between the two, trimming the
+    /// duplicates produced by naive logging front-ends. Writes to DAC data,
+    /// FIFO and trigger registers, where every write counts (see
+    /// `chip::regmap::is_trigger_register`), are always kept.
+    /// Default is `false`.
+    ///
+    /// ```rust
+    /// use soundlog::VgmBuilder;
+    /// use soundlog::chip::Ym2612Spec;
+    /// use soundlog::vgm::command::Instance;
+    ///
+    /// let mut builder = VgmBuilder::new();
+    /// builder.set_dedupe_writes(true);
+    /// let tl = Ym2612Spec { port: 0, register: 0x40, value: 0x7F };
+    /// builder.add_chip_write(Instance::Primary, tl.clone());
+    /// builder.add_chip_write(Instance::Primary, tl);
+    /// assert_eq!(builder.deduped_writes(), 1);
+    /// ```
+    pub fn set_dedupe_writes(&mut self, enabled: bool) -> &mut Self {
+        self.dedupe_writes = enabled;
+        self
+    }
+
+    /// Gets whether repeated writes are dropped.
+    pub fn dedupe_writes(&self) -> bool {
+        self.dedupe_writes
+    }
+
+    /// Number of writes dropped by `set_dedupe_writes` so far.
+    pub fn deduped_writes(&self) -> usize {
+        self.deduped_writes
+    }
+
     /// Append a VGM command to the builder.
     ///
     /// Accepts any type convertible into `VgmCommand` (via `Into`).
@@ -161,10 +295,110 @@ impl VgmBuilder {
     where
         C: Into<VgmCommand>,
     {
-        self.document.commands.push(command.into());
+        self.push_command(command.into())
+    }
+
+    /// Append every command of `commands`, like calling `add_vgm_command`
+    /// for each, reserving room from the iterator's size hint up front.
+    ///
+    /// ```rust
+    /// use soundlog::VgmBuilder;
+    /// use soundlog::vgm::command::WaitSamples;
+    ///
+    /// let mut builder = VgmBuilder::with_capacity(1001);
+    /// builder.add_vgm_commands((0..1000).map(|_| WaitSamples(10)));
+    /// assert_eq!(builder.finalize().header.total_samples, 10_000);
+    /// ```
+    pub fn add_vgm_commands<I>(&mut self, commands: I) -> &mut Self
+    where
+        I: IntoIterator,
+        I::Item: Into<VgmCommand>,
+    {
+        let commands = commands.into_iter().map(Into::into);
+        if self.dedupe_writes {
+            for command in commands {
+                self.push_command(command);
+            }
+        } else {
+            self.document.commands.extend(commands);
+        }
+        self
+    }
+
+    /// Append waits totalling `samples`, split into the shortest wait
+    /// commands (`0x62`/`0x63` for exact 1/60 s and 1/50 s, `0x7n` for up to
+    /// 16 samples, `0x61` chunks of at most 65535 samples otherwise).
+    pub fn add_wait_samples(&mut self, samples: u64) -> &mut Self {
+        crate::vgm::transform::push_wait(&mut self.document.commands, samples);
         self
     }
 
+    /// Append a wait of `seconds` at the header sample rate (see
+    /// `VgmHeader::effective_sample_rate`).
+    ///
+    /// Time is converted to whole samples by rounding, and the rounding error
+    /// is carried over to the next `wait_secs`, `wait_duration` or
+    /// `wait_ratio` call, so a sequence of waits never drifts by more than
+    /// half a sample from the exact total (see `wait_error`). Negative or
+    /// non-finite values append nothing.
+    ///
+    /// ```rust
+    /// use soundlog::VgmBuilder;
+    ///
+    /// let mut builder = VgmBuilder::new();
+    /// for _ in 0..60 {
+    ///     builder.wait_secs(0.0167);
+    /// }
+    /// // 60 * 0.0167 s = 1.002 s = 44188.2 samples at 44.1 kHz
+    /// assert_eq!(builder.finalize().header.total_samples, 44188);
+    /// ```
+    pub fn wait_secs(&mut self, seconds: f64) -> &mut Self {
+        if !seconds.is_finite() || seconds <= 0.0 {
+            return self;
+        }
+        let samples = seconds * self.document.header.effective_sample_rate() as f64;
+        self.add_exact_wait(samples.trunc() as u64, samples.fract())
+    }
+
+    /// Append a wait of `duration` at the header sample rate, carrying the
+    /// rounding error like `wait_secs`.
+    pub fn wait_duration(&mut self, duration: std::time::Duration) -> &mut Self {
+        self.wait_ratio(duration.as_nanos(), 1_000_000_000)
+    }
+
+    /// Append a wait of `numerator / denominator` seconds at the header sample
+    /// rate, carrying the rounding error like `wait_secs`. The whole part is
+    /// computed exactly, e.g. `wait_ratio(1, 60)` for one NTSC frame. A zero
+    /// `denominator` appends nothing.
+    pub fn wait_ratio(&mut self, numerator: u128, denominator: u128) -> &mut Self {
+        if denominator == 0 {
+            return self;
+        }
+        let scaled = numerator * self.document.header.effective_sample_rate() as u128;
+        let whole = (scaled / denominator) as u64;
+        let fraction = (scaled % denominator) as f64 / denominator as f64;
+        self.add_exact_wait(whole, fraction)
+    }
+
+    /// Accumulated rounding error of the time-based waits, in samples:
+    /// positive when more samples were appended than the exact total time.
+    /// Always within `-0.5..=0.5`.
+    pub fn wait_error(&self) -> f64 {
+        self.wait_error
+    }
+
+    // Append `whole + fraction` samples minus the carried error, rounded.
+    fn add_exact_wait(&mut self, whole: u64, fraction: f64) -> &mut Self {
+        if whole == 0 && fraction == 0.0 {
+            return self;
+        }
+        let rest = fraction - self.wait_error;
+        let rounded = rest.round();
+        self.wait_error = rounded - rest;
+        let samples = (whole as i64 + rounded as i64) as u64;
+        self.add_wait_samples(samples)
+    }
+
     /// Append a chip write produced by a chip-specific spec.
     ///
     /// `instance` selects the chip instance (`ChipId::Primary` or `ChipId::Secondary`).
@@ -175,10 +409,56 @@ impl VgmBuilder {
         I: Into<Instance>,
         (Instance, C): Into<VgmCommand>,
     {
-        self.document.commands.push((instance.into(), spec).into());
+        self.push_command((instance.into(), spec).into())
+    }
+
+    // Append `command` unless it is a repeated write dropped by
+    // `set_dedupe_writes`.
+    fn push_command(&mut self, command: VgmCommand) -> &mut Self {
+        if self.dedupe_writes && self.is_repeated_write(&command) {
+            self.deduped_writes += 1;
+        } else {
+            self.document.commands.push(command);
+        }
         self
     }
 
+    // `true` when `command` writes the value of the latest write to its
+    // register since the last wait.
+    fn is_repeated_write(&mut self, command: &VgmCommand) -> bool {
+        // Commands may have been appended without going through here (waits,
+        // data blocks, or anything added while deduplication was off).
+        for previous in &self.document.commands[self.last_writes_scanned..] {
+            // `0x8n` also writes YM2612 register 0x2A.
+            if previous.wait_samples() > 0
+                || matches!(previous, VgmCommand::YM2612Port0Address2AWriteAndWaitN(_))
+            {
+                self.last_writes.clear();
+            } else if let Some(previous) = previous.register_write() {
+                self.last_writes.insert(
+                    (
+                        previous.chip,
+                        previous.instance,
+                        previous.port,
+                        previous.register,
+                    ),
+                    previous.value,
+                );
+            }
+        }
+        self.last_writes_scanned = self.document.commands.len();
+
+        let Some(write) = command.register_write() else {
+            return false;
+        };
+        if chip::regmap::is_trigger_register(&write.chip, write.port, write.register) {
+            return false;
+        }
+        self.last_writes
+            .get(&(write.chip, write.instance, write.port, write.register))
+            == Some(&write.value)
+    }
+
     /// Attach a `DataBlock` described by a typed detail into the builder.
     ///
     /// Generic convenience helper that accepts any type convertible into
@@ -216,6 +496,175 @@ impl VgmBuilder {
         self
     }
 
+    /// Append an uncompressed data block to data bank `data_type`
+    /// (`0x00..=0x3F`, higher values are masked to it) and return its block id.
+    ///
+    /// Blocks of one type are concatenated into a single data bank, so a new
+    /// block starts where the earlier blocks of its bank end. Block ids number
+    /// every data block of the document in order except decompression tables
+    /// (`0x7F`), as `StartStreamFastCall` resolves them; blocks added with
+    /// `attach_data_block` or taken over by `VgmBuilder::from` count too.
+    /// Pass the id to `start_stream_from_block` or `data_block_range` instead
+    /// of computing bank offsets by hand.
+    ///
+    /// ```rust
+    /// use soundlog::VgmBuilder;
+    ///
+    /// let mut builder = VgmBuilder::new();
+    /// let kick = builder.add_data_block_for_bank(0x00, vec![0x80; 16]);
+    /// let tone = builder.add_data_block_for_bank(0x01, vec![0x40; 4]);
+    /// let snare = builder.add_data_block_for_bank(0x00, vec![0x70; 8]);
+    /// assert_eq!((kick, tone, snare), (0, 1, 2));
+    /// assert_eq!(builder.data_block_range(snare), Some(16..24));
+    /// ```
+    pub fn add_data_block_for_bank(&mut self, data_type: u8, data: impl Into<Vec<u8>>) -> BlockId {
+        let id = self.data_block_layout().len() as BlockId;
+        let data = data.into();
+        self.document
+            .commands
+            .push(VgmCommand::DataBlock(Box::new(DataBlock {
+                marker: 0x66,
+                chip_instance: Instance::Primary as u8,
+                data_type: data_type & 0x3F,
+                size: data.len() as u32,
+                data,
+            })));
+        id
+    }
+
+    /// Byte range of data block `block` within its data bank, or `None` when
+    /// the document has no such block.
+    ///
+    /// Compressed blocks occupy their uncompressed size in the bank of their
+    /// own data type, as `VgmStream` stores them.
+    pub fn data_block_range(&self, block: BlockId) -> Option<Range<u32>> {
+        self.data_block_layout()
+            .get(usize::from(block))
+            .map(|&(_, offset, size)| offset..offset + size)
+    }
+
+    /// Start stream `stream_id` on data block `block` with `length_mode`.
+    ///
+    /// `PlayUntilEnd` appends a `StartStreamFastCall`, which stops at the end
+    /// of the block. Any other mode appends a `StartStream` at the block's
+    /// offset in its data bank, with a length that covers the block for the
+    /// stream's latest `SetStreamData` step and `SetStreamFrequency`
+    /// (`Milliseconds` rounds down so the stream never plays into the next
+    /// block; `Ignore` keeps the previous length). Appends nothing when the
+    /// document has no such block.
+    ///
+    /// ```rust
+    /// use soundlog::VgmBuilder;
+    /// use soundlog::vgm::command::{LengthMode, StartStream, VgmCommand};
+    ///
+    /// let mut builder = VgmBuilder::new();
+    /// builder.add_data_block_for_bank(0x00, vec![0x80; 100]);
+    /// let snare = builder.add_data_block_for_bank(0x00, vec![0x70; 50]);
+    /// builder.start_stream_from_block(
+    ///     0,
+    ///     snare,
+    ///     LengthMode::CommandCount {
+    ///         reverse: false,
+    ///         looped: false,
+    ///     },
+    /// );
+    /// let doc = builder.finalize();
+    /// assert!(doc.iter().any(|command| matches!(
+    ///     command,
+    ///     VgmCommand::StartStream(StartStream {
+    ///         data_start_offset: 100,
+    ///         data_length: 50,
+    ///         ..
+    ///     })
+    /// )));
+    /// ```
+    pub fn start_stream_from_block(
+        &mut self,
+        stream_id: StreamId,
+        block: BlockId,
+        length_mode: LengthMode,
+    ) -> &mut Self {
+        let Some(range) = self.data_block_range(block) else {
+            return self;
+        };
+        if let LengthMode::PlayUntilEnd { reverse, looped } = length_mode {
+            return self.add_vgm_command(StartStreamFastCall {
+                stream_id,
+                block_id: block,
+                flags: StartStreamFastCallFlags { reverse, looped },
+            });
+        }
+        let (step_size, step_base, frequency) = self.stream_setup(stream_id);
+        let steps = (range.len() as u32)
+            .saturating_sub(step_base)
+            .div_ceil(step_size.max(1));
+        let data_length = match length_mode {
+            LengthMode::Ignore { .. } => 0,
+            LengthMode::Milliseconds { .. } if frequency == 0 => 0,
+            LengthMode::Milliseconds { .. } => {
+                (u64::from(steps) * 1000 / u64::from(frequency)) as u32
+            }
+            _ => steps,
+        };
+        self.add_vgm_command(StartStream {
+            stream_id,
+            data_start_offset: range.start as i32,
+            length_mode,
+            data_length,
+        })
+    }
+
+    // `(bank, offset, size)` of every data block a block id refers to, in
+    // block id order.
+    fn data_block_layout(&self) -> Vec<(u8, u32, u32)> {
+        let mut bank_sizes: HashMap<u8, u32> = HashMap::new();
+        let mut layout = Vec::new();
+        for command in &self.document.commands {
+            let VgmCommand::DataBlock(block) = command else {
+                continue;
+            };
+            let (bank, size) = match block.data_type {
+                0x7F => continue,
+                0x40..=0x7E => {
+                    let size = block.data.get(1..5).map_or(0, |size| {
+                        u32::from_le_bytes([size[0], size[1], size[2], size[3]])
+                    });
+                    (block.data_type, size)
+                }
+                data_type => (data_type, block.data.len() as u32),
+            };
+            let bank_size = bank_sizes.entry(bank).or_insert(0);
+            layout.push((bank, *bank_size, size));
+            *bank_size += size;
+        }
+        layout
+    }
+
+    // Step size, step base and frequency of the latest `SetStreamData` and
+    // `SetStreamFrequency` for `stream_id`.
+    fn stream_setup(&self, stream_id: StreamId) -> (u32, u32, u32) {
+        let mut data = None;
+        let mut frequency = None;
+        for command in self.document.commands.iter().rev() {
+            match command {
+                VgmCommand::SetStreamData(set) if set.stream_id == stream_id && data.is_none() => {
+                    data = Some((u32::from(set.step_size), u32::from(set.step_base)));
+                }
+                VgmCommand::SetStreamFrequency(set)
+                    if set.stream_id == stream_id && frequency.is_none() =>
+                {
+                    frequency = Some(set.frequency);
+                }
+                _ => {}
+            }
+            if data.is_some() && frequency.is_some() {
+                break;
+            }
+        }
+        let (step_size, step_base) = data.unwrap_or((1, 0));
+        (step_size, step_base, frequency.unwrap_or(0))
+    }
+
     /// Set GD3 metadata for the document under construction.
     ///
     /// This stores the provided `Gd3` into the builder's internal
@@ -266,7 +715,8 @@ impl VgmBuilder {
     /// that DataBlock entries appear at the beginning of the serialized VGM.
     /// DecompressionTable DataBlocks (those with `data_type == 0x7F`)
     /// are promoted ahead of other DataBlocks and thus placed at the very
-    /// start of the serialized document.
+    /// start of the serialized document. Both groups keep the order in which
+    /// the blocks were added.
     ///
     /// The method returns the complete document ready for serialization via
     /// `VgmDocument::to_bytes()`.
@@ -283,6 +733,13 @@ impl VgmBuilder {
                 .push(VgmCommand::EndOfData(crate::vgm::command::EndOfData {}));
         }
 
+        if self.auto_dual_chip {
+            self.set_dual_chip_bits();
+        }
+        if self.auto_version {
+            self.document.header.version = self.document.required_version();
+        }
+
         // Phase 1 (B): Extract DataBlocks that occur at-or-after loop_index,
         // adjust loop_index accordingly, but do NOT yet reinsert them at the front.
         // This extraction is now performed by a dedicated private helper.
@@ -305,8 +762,7 @@ impl VgmBuilder {
                     // DataOffset + 0x0C = 0x40 (64 bytes, minimum VGM data start)
                     0x0C
                 } else {
-                    (version_header_size as u32)
-                        .wrapping_sub(VgmHeaderField::DataOffset.offset() as u32)
+                    (version_header_size - VgmHeaderField::DataOffset.offset()) as u32
                 }
             }
             v => v,
@@ -314,17 +770,21 @@ impl VgmBuilder {
 
         // handle extra header offset
         if self.document.extra_header.is_some() && self.document.header.extra_header_offset == 0 {
-            let header_len = self.document.header.to_bytes(0, data_offset).len() as u32;
-            let extra_offset =
-                header_len.wrapping_sub(VgmHeaderField::ExtraHeaderOffset.offset() as u32);
+            let header_len = FileOffset::new(self.document.header.to_bytes(0, data_offset).len());
+            // Headers too short to hold the field keep the extra header
+            // offset unset; serialization then appends the extra header.
+            let extra_offset = header_len
+                .to_relative(VgmHeaderField::ExtraHeaderOffset.offset())
+                .unwrap_or(0);
             self.document.header.extra_header_offset = extra_offset;
 
             if let Some(eh) = &self.document.extra_header {
-                let extra_bytes_len = eh.to_bytes().len() as u32;
-                let new_header_len = header_len.wrapping_add(extra_bytes_len);
-                let new_data_offset =
-                    new_header_len.wrapping_sub(VgmHeaderField::DataOffset.offset() as u32);
-                self.document.header.data_offset = new_data_offset;
+                let new_data_offset = header_len
+                    .checked_add(eh.to_bytes().len())
+                    .and_then(|end| end.to_relative(VgmHeaderField::DataOffset.offset()));
+                if let Some(new_data_offset) = new_data_offset {
+                    self.document.header.data_offset = new_data_offset;
+                }
             }
         } else if self.document.header.data_offset == 0 {
             self.document.header.data_offset = data_offset;
@@ -337,16 +797,31 @@ impl VgmBuilder {
             let offsets = self.document.sourcemap();
             if index < offsets.len() {
                 let (cmd_offset, _cmd_len) = offsets[index];
-                let computed_loop_offset =
-                    cmd_offset.wrapping_sub(VgmHeaderField::LoopOffset.offset());
-                self.document.header.loop_offset = computed_loop_offset as u32;
-                self.document.header.loop_samples = self.document.total_samples(index);
+                if let Some(loop_offset) =
+                    FileOffset::new(cmd_offset).to_relative(VgmHeaderField::LoopOffset.offset())
+                {
+                    self.document.header.loop_offset = loop_offset;
+                    self.document.header.loop_samples = self.document.total_samples(index);
+                }
             }
         }
 
         self.document
     }
 
+    // Set the dual-chip bit of chips with a primary clock whose secondary
+    // instance is written.
+    fn set_dual_chip_bits(&mut self) {
+        let mut chips = self.document.header.chip_instances_mut();
+        for command in &self.document.commands {
+            if let Some(write) = command.register_write()
+                && write.instance == Instance::Secondary
+            {
+                chips.enable_secondary(&write.chip);
+            }
+        }
+    }
+
     // Relocate DataBlock in `VgmDocument`.
     //
     // Behavior:
@@ -419,6 +894,13 @@ impl From<VgmDocument> for VgmBuilder {
         VgmBuilder {
             document,
             loop_index: None,
+            wait_error: 0.0,
+            auto_dual_chip: false,
+            auto_version: false,
+            dedupe_writes: false,
+            deduped_writes: 0,
+            last_writes: HashMap::new(),
+            last_writes_scanned: 0,
         }
     }
 }
@@ -430,6 +912,85 @@ impl Default for VgmBuilder {
     }
 }
 
+/// A `VgmBuilder` in strict mode, see `VgmBuilder::into_strict`.
+///
+/// It dereferences to the `VgmBuilder` for adding commands and setting the
+/// header, and is finished with `try_finalize()`; `finalize()` cannot be
+/// called on it.
+pub struct StrictVgmBuilder {
+    builder: VgmBuilder,
+}
+
+impl StrictVgmBuilder {
+    /// Like `VgmBuilder::finalize()`, but first checks that every chip
+    /// written by a command has a clock in the header, and that the header
+    /// dual-chip bit is set for chips whose secondary instance is written.
+    ///
+    /// # Errors
+    /// Returns `BuildError::StrictViolation` with the lint issue of the first
+    /// offending chip, which names the index of the first command writing
+    /// to it.
+    ///
+    /// ```rust
+    /// use soundlog::VgmBuilder;
+    /// use soundlog::chip::PsgSpec;
+    /// use soundlog::vgm::command::Instance;
+    ///
+    /// let mut builder = VgmBuilder::new().into_strict();
+    /// builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x90 });
+    /// assert!(builder.try_finalize().is_err());
+    /// ```
+    pub fn try_finalize(self) -> Result<VgmDocument, BuildError> {
+        let document = self.builder.finalize();
+        let options = LintOptions {
+            strict: true,
+            ..LintOptions::default()
+        };
+        let report = lint::lint_with_options(&document, &options);
+        if let Some(issue) = report.issues.into_iter().find(|issue| {
+            matches!(
+                issue,
+                LintIssue::UnregisteredChip { .. } | LintIssue::MissingDualChipBit { .. }
+            )
+        }) {
+            return Err(BuildError::StrictViolation(issue));
+        }
+        Ok(document)
+    }
+}
+
+impl std::ops::Deref for StrictVgmBuilder {
+    type Target = VgmBuilder;
+
+    fn deref(&self) -> &VgmBuilder {
+        &self.builder
+    }
+}
+
+impl std::ops::DerefMut for StrictVgmBuilder {
+    fn deref_mut(&mut self) -> &mut VgmBuilder {
+        &mut self.builder
+    }
+}
+
+/// Error of `StrictVgmBuilder::try_finalize`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    /// A strict mode check failed. The issue is
+    /// `LintIssue::UnregisteredChip` or `LintIssue::MissingDualChipBit`.
+    StrictViolation(LintIssue),
+}
+
+impl std::fmt::Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildError::StrictViolation(issue) => write!(f, "strict mode: {}", issue),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
 /// Attempt to convert a raw VGM byte slice into a `VgmDocument`.
 ///
 /// This is a fallible conversion that delegates to `parser::parse_vgm` and
@@ -460,6 +1021,57 @@ impl From<&VgmDocument> for Vec<u8> {
 }
 
 impl VgmDocument {
+    /// Parse `bytes` like `VgmDocument::try_from`, reporting progress as
+    /// `(bytes_done, total)`, stopping with `ParseError::Cancelled` once the
+    /// `CancelToken` of `hooks` is cancelled and with
+    /// `ParseError::LimitExceeded` when the input exceeds the `ParseLimits`
+    /// of `hooks`, see `ParseHooks`. Use limits for untrusted input.
+    ///
+    /// ```rust
+    /// use soundlog::{ParseError, VgmDocument};
+    /// use soundlog::vgm::parser::{CancelToken, ParseHooks};
+    ///
+    /// let bytes: Vec<u8> = VgmDocument::default().into();
+    /// let mut reports = Vec::new();
+    /// let hooks = ParseHooks::new().on_progress(|done, total| reports.push((done, total)));
+    /// assert!(VgmDocument::parse_with_hooks(&bytes, hooks).is_ok());
+    /// assert_eq!(reports.last(), Some(&(bytes.len(), bytes.len())));
+    ///
+    /// let token = CancelToken::new();
+    /// token.clone().cancel();
+    /// let cancelled = VgmDocument::parse_with_hooks(&bytes, ParseHooks::new().with_cancel(token));
+    /// assert!(matches!(cancelled, Err(ParseError::Cancelled { .. })));
+    /// ```
+    pub fn parse_with_hooks(
+        bytes: &[u8],
+        hooks: parser::ParseHooks<'_>,
+    ) -> Result<Self, ParseError> {
+        parser::parse_vgm_with_hooks(bytes, hooks)
+    }
+
+    /// Serialize the document like `Vec::<u8>::from(&document)` and
+    /// compress the bytes with gzip, giving the contents of a `.vgz` file.
+    ///
+    /// Available with the `vgz` feature. The gzip header has no file name and
+    /// a modification time of 0, so the output stays deterministic.
+    ///
+    /// ```rust
+    /// use soundlog::VgmDocument;
+    ///
+    /// let vgz = VgmDocument::default().to_vgz_bytes();
+    /// assert_eq!(&vgz[..2], &[0x1F, 0x8B]);
+    /// ```
+    #[cfg(feature = "vgz")]
+    pub fn to_vgz_bytes(&self) -> Vec<u8> {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        encoder
+            .write_all(&self.to_bytes())
+            .expect("writing to a Vec cannot fail");
+        encoder.finish().expect("writing to a Vec cannot fail")
+    }
+
     /// Return an iterator over `VgmCommand` references.
     pub fn iter(&self) -> std::slice::Iter<'_, VgmCommand> {
         self.commands.iter()
@@ -481,31 +1093,385 @@ impl VgmDocument {
             return None;
         }
 
-        let data_offset = if self.header.data_offset == 0 {
-            use crate::vgm::header::VgmHeader;
-            (VgmHeader::fallback_header_size_for_version(self.header.version)
-                .wrapping_sub(VgmHeaderField::DataOffset.offset())) as u32
-        } else {
-            self.header.data_offset
-        };
-
-        // Calculate actual header length from data_offset
-        // data_offset is relative to DataOffset, so actual header length is DataOffset + data_offset
-        let header_len = (VgmHeaderField::DataOffset.offset() as u32).wrapping_add(data_offset);
-        let loop_abs_offset =
-            (VgmHeaderField::LoopOffset.offset() as u32).wrapping_add(self.header.loop_offset);
-        let loop_command_offset = loop_abs_offset.wrapping_sub(header_len);
+        // Offsets are resolved with checked math: a stored loop offset that
+        // points before the command stream or past `usize` has no command.
+        let header_len = FileOffset::new(VgmHeader::command_start(
+            self.header.version,
+            self.header.data_offset,
+        ));
+        let loop_abs_offset = FileOffset::from_relative(
+            VgmHeaderField::LoopOffset.offset(),
+            self.header.loop_offset,
+        )?;
+        let loop_command_offset = loop_abs_offset.offset_from(header_len)?;
 
         let offsets = self.command_offsets_and_lengths();
-        for (index, (cmd_offset, _len)) in offsets.iter().enumerate() {
-            if *cmd_offset as u32 == loop_command_offset {
-                return Some(index);
-            }
+        offsets
+            .iter()
+            .position(|&(cmd_offset, _len)| cmd_offset == loop_command_offset)
+    }
+
+    /// Lowest VGM version that can hold this document: the newest of its
+    /// commands (`opcode::min_version`), its header chip clocks
+    /// (`opcode::chip_min_version`, 1.51 for dual chips) and 1.70 when it
+    /// has an extra header. Never below 1.00.
+    ///
+    /// ```rust
+    /// use soundlog::VgmBuilder;
+    /// use soundlog::chip::{Chip, Ym2612Spec};
+    /// use soundlog::vgm::command::{Instance, WaitSamples};
+    ///
+    /// let mut builder = VgmBuilder::new();
+    /// builder.add_vgm_command(WaitSamples(735));
+    /// assert_eq!(builder.finalize().required_version(), 0x100);
+    ///
+    /// let mut builder = VgmBuilder::new();
+    /// builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_453);
+    /// builder.add_chip_write(Instance::Primary, Ym2612Spec { port: 0, register: 0x28, value: 0xF0 });
+    /// assert_eq!(builder.finalize().required_version(), 0x110);
+    /// ```
+    pub fn required_version(&self) -> u32 {
+        let commands = self
+            .commands
+            .iter()
+            .filter_map(|command| opcode::min_version(opcode::opcode_of(command)));
+        let chips = self
+            .header
+            .chip_instances()
+            .into_iter()
+            .map(|(instance, chip, _)| match instance {
+                Instance::Primary => opcode::chip_min_version(&chip),
+                Instance::Secondary => opcode::chip_min_version(&chip).max(0x151),
+            });
+        let extra = self.extra_header.as_ref().map(|_| 0x170);
+        commands.chain(chips).chain(extra).fold(0x100, u32::max)
+    }
+
+    /// Count the wait commands of this document by encoding.
+    ///
+    /// Each command is counted by `VgmCommand::wait_samples`, so the
+    /// encodings add up to the same length as every other sample clock.
+    ///
+    /// ```rust
+    /// use soundlog::VgmBuilder;
+    /// use soundlog::vgm::command::{Wait735Samples, WaitNSample, WaitSamples};
+    ///
+    /// let mut builder = VgmBuilder::new();
+    /// builder.add_vgm_command(Wait735Samples);
+    /// builder.add_vgm_command(Wait735Samples);
+    /// builder.add_vgm_command(WaitNSample(3));
+    /// builder.add_vgm_command(WaitSamples(1000));
+    /// let stats = builder.finalize().wait_stats();
+    ///
+    /// assert_eq!(stats.frame_waits().commands, 2);
+    /// assert_eq!(stats.wait_n.samples, 4);
+    /// assert_eq!(stats.wait_commands(), 4);
+    /// assert_eq!(stats.total_samples(), 2474);
+    /// ```
+    pub fn wait_stats(&self) -> WaitStats {
+        let mut stats = WaitStats::default();
+        for command in &self.commands {
+            let count = match command {
+                VgmCommand::WaitSamples(_) => &mut stats.wait_samples,
+                VgmCommand::Wait735Samples(_) => &mut stats.wait_735,
+                VgmCommand::Wait882Samples(_) => &mut stats.wait_882,
+                VgmCommand::WaitNSample(_) => &mut stats.wait_n,
+                VgmCommand::YM2612Port0Address2AWriteAndWaitN(_) => {
+                    &mut stats.ym2612_write_and_wait
+                }
+                _ => continue,
+            };
+            count.add(command.wait_samples());
+        }
+        stats
+    }
+
+    /// Estimate the memory held by this document.
+    ///
+    /// The estimate counts the allocated capacity of the command list, the
+    /// boxed data of data blocks and PCM RAM writes, and the GD3 strings and
+    /// extra header entries. Allocator overhead is not included.
+    ///
+    /// ```rust
+    /// use soundlog::VgmBuilder;
+    /// use soundlog::vgm::command::{DataBlock, WaitSamples};
+    ///
+    /// let mut builder = VgmBuilder::new();
+    /// builder.add_vgm_command(DataBlock {
+    ///     marker: 0x66,
+    ///     chip_instance: 0,
+    ///     data_type: 0x00,
+    ///     size: 4096,
+    ///     data: vec![0x80; 4096],
+    /// });
+    /// builder.add_vgm_command(WaitSamples(735));
+    /// let doc = builder.finalize();
+    ///
+    /// let footprint = doc.memory_footprint();
+    /// assert!(footprint.data >= 4096);
+    /// assert!(footprint.commands >= doc.commands.len() * std::mem::size_of::<soundlog::VgmCommand>());
+    /// assert_eq!(footprint.total(), footprint.commands + footprint.data + footprint.metadata);
+    /// ```
+    pub fn memory_footprint(&self) -> MemoryFootprint {
+        use std::mem::size_of;
+        let commands = self.commands.capacity() * size_of::<VgmCommand>();
+        let data = self
+            .commands
+            .iter()
+            .map(|cmd| match cmd {
+                VgmCommand::DataBlock(block) => {
+                    size_of::<crate::vgm::command::DataBlock>() + block.data.capacity()
+                }
+                VgmCommand::PcmRamWrite(write) => {
+                    size_of::<crate::vgm::command::PcmRamWrite>() + write.data.capacity()
+                }
+                _ => 0,
+            })
+            .sum();
+        let gd3 = self.gd3.as_ref().map_or(0, |gd3| {
+            gd3.fields()
+                .iter()
+                .map(|field| field.as_ref().map_or(0, String::capacity))
+                .sum()
+        });
+        let extra = self.extra_header.as_ref().map_or(0, |extra| {
+            extra.chip_clocks.capacity() * size_of::<crate::vgm::header::ChipClock>()
+                + extra.chip_volumes.capacity() * size_of::<crate::vgm::header::ChipVolume>()
+        });
+        MemoryFootprint {
+            commands,
+            data,
+            metadata: size_of::<Self>() + gd3 + extra,
+        }
+    }
+
+    /// Iterate the commands with their position in samples: the samples
+    /// waited by every command before them.
+    ///
+    /// Positions follow `VgmCommand::wait_samples`, so `0x8n` YM2612 writes
+    /// advance the time like wait commands. DAC stream commands do not wait;
+    /// the writes they start play alongside the commands that follow.
+    ///
+    /// ```rust
+    /// use soundlog::VgmBuilder;
+    /// use soundlog::chip::{Chip, PsgSpec};
+    /// use soundlog::vgm::command::{Instance, Wait735Samples};
+    ///
+    /// let mut builder = VgmBuilder::new();
+    /// builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+    /// builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x90 });
+    /// builder.add_vgm_command(Wait735Samples);
+    /// builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x9F });
+    /// let doc = builder.finalize();
+    ///
+    /// let positions: Vec<u64> = doc.timeline().map(|(position, _)| position).collect();
+    /// // the write, the wait, the write and EndOfData
+    /// assert_eq!(positions, vec![0, 0, 735, 735]);
+    /// ```
+    pub fn timeline(&self) -> Timeline<'_> {
+        Timeline {
+            commands: self.commands.iter(),
+            position: 0,
+        }
+    }
+
+    /// Length of the command stream in samples, as summed by `timeline`.
+    ///
+    /// Unlike `header.total_samples`, this is computed from the commands and
+    /// cannot be stale.
+    pub fn sample_length(&self) -> u64 {
+        self.commands
+            .iter()
+            .map(|command| command.wait_samples() as u64)
+            .sum()
+    }
+
+    /// Position in samples of the loop point (see `loop_command_index`),
+    /// `None` without a loop.
+    pub fn loop_start_sample(&self) -> Option<u64> {
+        let index = self.loop_command_index()?;
+        Some(
+            self.commands[..index]
+                .iter()
+                .map(|command| command.wait_samples() as u64)
+                .sum(),
+        )
+    }
+
+    /// Samples from the loop point to the end, `None` without a loop.
+    pub fn loop_sample_length(&self) -> Option<u64> {
+        let start = self.loop_start_sample()?;
+        Some(self.sample_length() - start)
+    }
+
+    /// Length of the command stream at the header sample rate (44.1 kHz
+    /// unless the header sets another rate).
+    ///
+    /// ```rust
+    /// use soundlog::VgmBuilder;
+    /// use soundlog::vgm::command::WaitSamples;
+    /// use std::time::Duration;
+    ///
+    /// let mut builder = VgmBuilder::new();
+    /// builder.add_vgm_command(WaitSamples(22_050));
+    /// builder.add_vgm_command(WaitSamples(44_100));
+    /// builder.set_loop_offset(1);
+    /// let doc = builder.finalize();
+    ///
+    /// assert_eq!(doc.duration(), Duration::from_millis(1500));
+    /// assert_eq!(doc.intro_duration(), Duration::from_millis(500));
+    /// assert_eq!(doc.loop_duration(), Some(Duration::from_secs(1)));
+    /// // once through, then the loop twice more
+    /// assert_eq!(doc.playback_duration(3), Duration::from_millis(3500));
+    /// ```
+    pub fn duration(&self) -> std::time::Duration {
+        self.samples_to_duration(self.sample_length())
+    }
+
+    /// Time before the loop point, the whole `duration` without a loop.
+    pub fn intro_duration(&self) -> std::time::Duration {
+        self.samples_to_duration(
+            self.loop_start_sample()
+                .unwrap_or_else(|| self.sample_length()),
+        )
+    }
+
+    /// Time from the loop point to the end, `None` without a loop.
+    pub fn loop_duration(&self) -> Option<std::time::Duration> {
+        Some(self.samples_to_duration(self.loop_sample_length()?))
+    }
+
+    /// Time taken by `plays` playthroughs as counted by
+    /// `VgmStream::set_loop_count`: the whole song once, then the loop
+    /// `plays - 1` more times. `0` counts as `1`, and a document without a
+    /// loop plays once. Fadeout and the header loop modifier are not
+    /// applied.
+    pub fn playback_duration(&self, plays: u32) -> std::time::Duration {
+        let repeats = plays.max(1) as u64 - 1;
+        let loop_length = self.loop_sample_length().unwrap_or(0);
+        self.samples_to_duration(self.sample_length() + loop_length * repeats)
+    }
+
+    // `samples` at the header sample rate, rounded down to the nanosecond.
+    fn samples_to_duration(&self, samples: u64) -> std::time::Duration {
+        let rate = self.header.effective_sample_rate() as u64;
+        let nanos = (samples % rate) as u128 * 1_000_000_000 / rate as u128;
+        std::time::Duration::new(samples / rate, nanos as u32)
+    }
+}
+
+/// Number of commands of one wait encoding and the samples they wait.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WaitCount {
+    pub commands: usize,
+    pub samples: u64,
+}
+
+impl WaitCount {
+    fn add(&mut self, samples: u32) {
+        self.commands += 1;
+        self.samples += samples as u64;
+    }
+}
+
+/// How the waits of a `VgmDocument` are encoded. See
+/// `VgmDocument::wait_stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WaitStats {
+    /// `0x61 nn nn`: wait n samples.
+    pub wait_samples: WaitCount,
+    /// `0x62`: wait one 60 Hz frame (735 samples).
+    pub wait_735: WaitCount,
+    /// `0x63`: wait one 50 Hz frame (882 samples).
+    pub wait_882: WaitCount,
+    /// `0x7n`: wait n+1 samples.
+    pub wait_n: WaitCount,
+    /// `0x8n`: YM2612 data bank write, then wait n samples. Every such
+    /// command is counted, including `0x80` which does not wait.
+    pub ym2612_write_and_wait: WaitCount,
+}
+
+impl WaitStats {
+    /// Frame waits: `0x62` and `0x63` together.
+    pub fn frame_waits(&self) -> WaitCount {
+        WaitCount {
+            commands: self.wait_735.commands + self.wait_882.commands,
+            samples: self.wait_735.samples + self.wait_882.samples,
         }
-        None
+    }
+
+    /// Commands that do nothing but wait (every encoding except `0x8n`).
+    pub fn wait_commands(&self) -> usize {
+        self.wait_samples.commands
+            + self.wait_735.commands
+            + self.wait_882.commands
+            + self.wait_n.commands
+    }
+
+    /// Samples waited by all encodings; the length of the document.
+    pub fn total_samples(&self) -> u64 {
+        self.wait_samples.samples
+            + self.wait_735.samples
+            + self.wait_882.samples
+            + self.wait_n.samples
+            + self.ym2612_write_and_wait.samples
     }
 }
 
+/// Estimated memory held by a `VgmDocument`, in bytes. See
+/// `VgmDocument::memory_footprint`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MemoryFootprint {
+    /// The command list: capacity times the size of a `VgmCommand`.
+    pub commands: usize,
+    /// Boxed data block and PCM RAM write payloads.
+    pub data: usize,
+    /// The document itself, GD3 strings and extra header entries.
+    pub metadata: usize,
+}
+
+impl MemoryFootprint {
+    /// Sum of all parts.
+    pub fn total(&self) -> usize {
+        self.commands + self.data + self.metadata
+    }
+}
+
+/// Commands of a `VgmDocument` with their position in samples. See
+/// `VgmDocument::timeline`.
+#[derive(Debug, Clone)]
+pub struct Timeline<'a> {
+    commands: std::slice::Iter<'a, VgmCommand>,
+    position: u64,
+}
+
+impl Timeline<'_> {
+    /// Position in samples of the next command; the length of the document
+    /// once the iterator is exhausted.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+}
+
+impl<'a> Iterator for Timeline<'a> {
+    type Item = (u64, &'a VgmCommand);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let command = self.commands.next()?;
+        let position = self.position;
+        self.position += command.wait_samples() as u64;
+        Some((position, command))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.commands.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Timeline<'_> {}
+
+impl std::iter::FusedIterator for Timeline<'_> {}
+
 /// Consume the document and iterate its commands by value.
 impl IntoIterator for VgmDocument {
     type Item = VgmCommand;
@@ -0,0 +1,281 @@
+//! Sound driver fingerprinting.
+//!
+//! Knowing which driver produced a log tells where to look next when reverse
+//! engineering a song (sequence format, instrument tables, tempo handling).
+//! `detect_driver` guesses it from patterns a driver leaves in the register
+//! writes:
+//!
+//! - the chip set (YM2612 + SN76489 on the Mega Drive, YM2608 / YM2203 on
+//!   PC-88 / PC-98, YM2151 + OKIM6258 on the X68000),
+//! - the update tick: the most common gap between groups of writes, which is
+//!   one video frame (735 or 882 samples) for drivers run from the vertical
+//!   blank interrupt,
+//! - the use of the chip timers, which timer-driven drivers load and reset on
+//!   every tick.
+//!
+//! | Driver    | Chips               | Tick                               |
+//! |-----------|---------------------|------------------------------------|
+//! | SMPS      | YM2612 (+ SN76489)  | video frame, timers unused         |
+//! | GEMS      | YM2612 (+ SN76489)  | timer B, reset on every tick       |
+//! | MUCOM88   | YM2608 / YM2203     | timer B only                       |
+//! | PMD       | YM2608 / YM2203     | timer B, timer A for effects       |
+//! | MXDRV     | YM2151 (+ OKIM6258) | timer B                            |
+//!
+//! These are heuristics: rips made from emulators that skip timer writes, or
+//! drivers that share the same habits, can be misidentified. Every guess
+//! lists the evidence it is based on.
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::chip::{Chip, PsgSpec, Ym2612Spec};
+//! use soundlog::vgm::command::{Instance, WaitSamples};
+//! use soundlog::vgm::driver::{Driver, detect_driver};
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_453);
+//! builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+//! for frame in 0..60 {
+//!     builder.add_chip_write(Instance::Primary, Ym2612Spec { port: 0, register: 0xA0, value: frame });
+//!     builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x90 });
+//!     builder.add_vgm_command(WaitSamples(735));
+//! }
+//! let doc = builder.finalize();
+//!
+//! let guesses = detect_driver(&doc);
+//! assert_eq!(guesses[0].driver, Driver::Smps);
+//! ```
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::chip::Chip;
+use crate::vgm::VgmDocument;
+use crate::vgm::analysis::{Analysis, AnalysisContext, run_analyses};
+use crate::vgm::command::VgmCommand;
+
+/// A sound driver recognized by `detect_driver`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Driver {
+    /// Sega's Sample Music Playback System (Mega Drive).
+    Smps,
+    /// Genesis Editor for Music and Sound (Mega Drive).
+    Gems,
+    /// MUCOM88 (PC-88).
+    Mucom88,
+    /// Professional Music Driver (PC-98).
+    Pmd,
+    /// MXDRV (X68000 MDX).
+    Mxdrv,
+}
+
+impl fmt::Display for Driver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Driver::Smps => "SMPS",
+            Driver::Gems => "GEMS",
+            Driver::Mucom88 => "MUCOM88",
+            Driver::Pmd => "PMD",
+            Driver::Mxdrv => "MXDRV",
+        };
+        f.write_str(name)
+    }
+}
+
+/// One candidate driver.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DriverGuess {
+    pub driver: Driver,
+    /// How well the document matches the driver's fingerprint, `0..=100`.
+    pub score: u8,
+    /// The matching patterns, e.g. `"timer B reloaded on every tick"`.
+    pub evidence: Vec<String>,
+}
+
+/// Patterns `detect_driver` looks at, exposed for tools that want to show
+/// them or apply their own rules.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DriverFeatures {
+    /// Written chips in order of their first write.
+    pub chips: Vec<Chip>,
+    /// Most common gap in samples between two groups of writes.
+    pub tick: Option<u32>,
+    /// Share of the gaps equal to `tick`, in percent.
+    pub tick_share: u8,
+    /// Number of groups of writes (ticks with at least one write).
+    pub write_groups: usize,
+    /// Timer control writes loading timer A.
+    pub timer_a_loads: usize,
+    /// Timer control writes loading timer B.
+    pub timer_b_loads: usize,
+    /// YM2612 DAC writes (`0x8n` or register `0x2A`) or DAC stream commands.
+    pub dac: bool,
+}
+
+impl DriverFeatures {
+    /// `true` when the tick is one NTSC or PAL video frame.
+    pub fn frame_tick(&self) -> bool {
+        self.tick
+            .is_some_and(|tick| tick.abs_diff(735) <= 2 || tick.abs_diff(882) <= 2)
+    }
+
+    fn has(&self, chip: Chip) -> bool {
+        self.chips.contains(&chip)
+    }
+}
+
+/// Collect the patterns used by `detect_driver`.
+pub fn driver_features(document: &VgmDocument) -> DriverFeatures {
+    let mut analysis = DriverAnalysis::new();
+    run_analyses(document, &mut [&mut analysis]);
+    analysis.into_features()
+}
+
+/// `driver_features` as an `Analysis`, to share a pass with other analyses.
+#[derive(Debug, Clone, Default)]
+pub struct DriverAnalysis {
+    features: DriverFeatures,
+    gaps: HashMap<u64, usize>,
+    last_write: Option<u64>,
+}
+
+impl DriverAnalysis {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The collected features, complete once the analysis has run.
+    pub fn into_features(self) -> DriverFeatures {
+        self.features
+    }
+}
+
+impl Analysis for DriverAnalysis {
+    fn command(&mut self, context: &AnalysisContext<'_>, command: &VgmCommand) {
+        let features = &mut self.features;
+        let write = context.write();
+        let time = context.time();
+        let is_write =
+            write.is_some() || matches!(command, VgmCommand::YM2612Port0Address2AWriteAndWaitN(_));
+        if is_write && self.last_write != Some(time) {
+            if let Some(last) = self.last_write {
+                *self.gaps.entry(time - last).or_insert(0) += 1;
+            }
+            self.last_write = Some(time);
+            features.write_groups += 1;
+        }
+        match command {
+            VgmCommand::YM2612Port0Address2AWriteAndWaitN(_)
+            | VgmCommand::StartStream(_)
+            | VgmCommand::StartStreamFastCall(_) => features.dac = true,
+            _ => {}
+        }
+        if let Some(write) = write {
+            if !features.chips.contains(&write.chip) {
+                features.chips.push(write.chip.clone());
+            }
+            let timer_control = match write.chip {
+                Chip::Ym2612 | Chip::Ym2203 | Chip::Ym2608 | Chip::Ym2610b => 0x27,
+                Chip::Ym2151 => 0x14,
+                _ => u32::MAX,
+            };
+            if write.port == 0 && write.register == timer_control {
+                features.timer_a_loads += (write.value & 0x01) as usize;
+                features.timer_b_loads += (write.value >> 1 & 0x01) as usize;
+            }
+            if write.chip == Chip::Ym2612 && write.port == 0 && write.register == 0x2A {
+                features.dac = true;
+            }
+        }
+    }
+
+    fn finish(&mut self, _context: &AnalysisContext<'_>) {
+        let total: usize = self.gaps.values().sum();
+        if let Some((&tick, &count)) = self
+            .gaps
+            .iter()
+            .max_by_key(|&(&gap, &count)| (count, std::cmp::Reverse(gap)))
+        {
+            self.features.tick = Some(tick.min(u32::MAX as u64) as u32);
+            self.features.tick_share = (count * 100 / total) as u8;
+        }
+    }
+}
+
+/// Guess the driver that produced `document`, best match first.
+///
+/// Drivers whose chips are not written are not listed, so the result is
+/// empty for unknown platforms.
+pub fn detect_driver(document: &VgmDocument) -> Vec<DriverGuess> {
+    guess_drivers(&driver_features(document))
+}
+
+/// Guess drivers from already collected features, best match first.
+pub fn guess_drivers(features: &DriverFeatures) -> Vec<DriverGuess> {
+    let mut guesses = Vec::new();
+    let timer_a = features.timer_a_loads > 0;
+    let timer_b = features.timer_b_loads > 0;
+    // A timer reloaded on most ticks drives the sequencer.
+    let timer_b_ticks = features.timer_b_loads * 2 >= features.write_groups.max(1);
+    let frame_tick = features.frame_tick();
+
+    if features.has(Chip::Ym2612) {
+        let mut smps = Guess::new(Driver::Smps, "YM2612 written");
+        smps.check(frame_tick, 30, "updated once per video frame");
+        smps.check(!timer_a && !timer_b, 20, "YM2612 timers unused");
+        smps.check(features.has(Chip::Sn76489), 10, "SN76489 written");
+        guesses.push(smps.finish());
+
+        let mut gems = Guess::new(Driver::Gems, "YM2612 written");
+        gems.check(timer_b, 30, "timer B loaded");
+        gems.check(timer_b_ticks, 20, "timer B reloaded on every tick");
+        gems.check(features.has(Chip::Sn76489), 10, "SN76489 written");
+        guesses.push(gems.finish());
+    }
+    if features.has(Chip::Ym2608) || features.has(Chip::Ym2203) {
+        let mut mucom = Guess::new(Driver::Mucom88, "YM2608 or YM2203 written");
+        mucom.check(timer_b, 30, "timer B loaded");
+        mucom.check(!timer_a, 20, "timer A unused");
+        mucom.check(!frame_tick, 10, "not updated per video frame");
+        guesses.push(mucom.finish());
+
+        let mut pmd = Guess::new(Driver::Pmd, "YM2608 or YM2203 written");
+        pmd.check(timer_b, 30, "timer B loaded");
+        pmd.check(timer_a, 20, "timer A loaded");
+        pmd.check(!frame_tick, 10, "not updated per video frame");
+        guesses.push(pmd.finish());
+    }
+    if features.has(Chip::Ym2151) {
+        let mut mxdrv = Guess::new(Driver::Mxdrv, "YM2151 written");
+        mxdrv.check(timer_b, 30, "timer B loaded");
+        mxdrv.check(!frame_tick, 20, "not updated per video frame");
+        mxdrv.check(features.has(Chip::Okim6258), 10, "OKIM6258 written");
+        guesses.push(mxdrv.finish());
+    }
+    // Stable: ties keep the order above.
+    guesses.sort_by_key(|guess| std::cmp::Reverse(guess.score));
+    guesses
+}
+
+// Builds a `DriverGuess` from weighted checks on top of a base score of 40
+// for the chip set.
+struct Guess(DriverGuess);
+
+impl Guess {
+    fn new(driver: Driver, chips: &str) -> Self {
+        Guess(DriverGuess {
+            driver,
+            score: 40,
+            evidence: vec![chips.to_string()],
+        })
+    }
+
+    fn check(&mut self, matched: bool, weight: u8, evidence: &str) {
+        if matched {
+            self.0.score += weight;
+            self.0.evidence.push(evidence.to_string());
+        }
+    }
+
+    fn finish(self) -> DriverGuess {
+        self.0
+    }
+}
@@ -0,0 +1,6 @@
+//! Exporters that convert a `VgmDocument` into other representations.
+//!
+//! - `frames`: per-tick register-delta listing (text or JSON) for importing
+//!   into tracker tooling such as Furnace or DefleMask.
+pub mod frames;
+pub mod xgm;
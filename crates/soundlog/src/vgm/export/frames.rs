@@ -0,0 +1,170 @@
+//! Per-frame register-delta export.
+//!
+//! `FrameExport` groups a document into ticks with `VgmDocument::frames`
+//! and keeps, for every tick, only the register writes that change the
+//! value last written to that register. The result can be rendered as plain
+//! text (one section per tick) or as JSON for tracker import scripts.
+//!
+//! Writes without an addressable register (SN76489 / Game Gear PSG bytes)
+//! are always kept because their meaning depends on the latch state rather
+//! than on the byte value alone. So are writes to DAC data, FIFO and
+//! trigger registers (`chip::regmap::is_trigger_register`), such as the
+//! YM2612 DAC or the OKIM6295 command register, where every write counts.
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::chip;
+//! use soundlog::vgm::command::{Instance, WaitSamples};
+//! use soundlog::vgm::export::frames::FrameExport;
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.register_chip(chip::Chip::Ym2151, Instance::Primary, 3_579_545);
+//! builder.add_chip_write(Instance::Primary, chip::Ym2151Spec { register: 0x20, value: 0xC7 });
+//! builder.add_vgm_command(WaitSamples(735));
+//! builder.add_chip_write(Instance::Primary, chip::Ym2151Spec { register: 0x20, value: 0xC7 });
+//! let doc = builder.finalize();
+//!
+//! let export = FrameExport::from_document(&doc, 735);
+//! assert_eq!(export.frames[0].writes.len(), 1);
+//! // the second write does not change the register and is dropped
+//! assert!(export.frames[1].writes.is_empty());
+//! assert!(export.to_text().contains("Ym2151#0 0:20=C7"));
+//! ```
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::chip::Chip;
+use crate::chip::regmap::is_trigger_register;
+use crate::vgm::VgmDocument;
+use crate::vgm::command::{Instance, RegisterWrite};
+
+/// Register writes that changed a value within one tick.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameDelta {
+    /// Zero-based tick number.
+    pub index: u64,
+    /// Absolute sample position of the start of the tick.
+    pub start_sample: u64,
+    /// Changed registers in command order.
+    pub writes: Vec<RegisterWrite>,
+}
+
+/// Register-delta listing of a whole document, one entry per tick.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameExport {
+    /// Tick length in samples.
+    pub samples_per_frame: u32,
+    /// Every tick of the document, including ticks without changes.
+    pub frames: Vec<FrameDelta>,
+}
+
+impl FrameExport {
+    /// Build the register-delta listing for `document`.
+    ///
+    /// DAC stream commands and `0x8n` data-bank writes are not expanded; run
+    /// the document through `VgmStream` first if those writes are needed.
+    pub fn from_document(document: &VgmDocument, samples_per_frame: u32) -> Self {
+        let mut registers: HashMap<(Chip, Instance, u8, u32), u32> = HashMap::new();
+        let frames = document
+            .frames(samples_per_frame)
+            .map(|frame| {
+                let writes = frame
+                    .commands
+                    .iter()
+                    .filter_map(|(_, command)| command.register_write())
+                    .filter(|write| {
+                        if write.chip == Chip::Sn76489
+                            || is_trigger_register(&write.chip, write.port, write.register)
+                        {
+                            return true;
+                        }
+                        let key = (
+                            write.chip.clone(),
+                            write.instance,
+                            write.port,
+                            write.register,
+                        );
+                        registers.insert(key, write.value) != Some(write.value)
+                    })
+                    .collect();
+                FrameDelta {
+                    index: frame.index,
+                    start_sample: frame.start_sample,
+                    writes,
+                }
+            })
+            .collect();
+
+        FrameExport {
+            samples_per_frame: samples_per_frame.max(1),
+            frames,
+        }
+    }
+
+    /// Render as plain text.
+    ///
+    /// Each tick starts with a `frame <index> @<sample>` line followed by one
+    /// indented `<Chip>#<instance> <port>:<register>=<value>` line (hex) per
+    /// changed register.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# samples_per_frame={}", self.samples_per_frame);
+        for frame in &self.frames {
+            let _ = writeln!(out, "frame {} @{}", frame.index, frame.start_sample);
+            for write in &frame.writes {
+                let _ = writeln!(
+                    out,
+                    "  {:?}#{} {}:{:02X}={:02X}",
+                    write.chip,
+                    usize::from(write.instance),
+                    write.port,
+                    write.register,
+                    write.value
+                );
+            }
+        }
+        out
+    }
+
+    /// Render as a JSON object.
+    ///
+    /// The layout is
+    /// `{"samples_per_frame":N,"frames":[{"frame":I,"sample":S,"writes":[...]}]}`
+    /// where every write is
+    /// `{"chip":"Ym2612","instance":0,"port":0,"register":40,"value":240}`.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        let _ = write!(
+            out,
+            "{{\"samples_per_frame\":{},\"frames\":[",
+            self.samples_per_frame
+        );
+        for (i, frame) in self.frames.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(
+                out,
+                "{{\"frame\":{},\"sample\":{},\"writes\":[",
+                frame.index, frame.start_sample
+            );
+            for (j, write) in frame.writes.iter().enumerate() {
+                if j > 0 {
+                    out.push(',');
+                }
+                let _ = write!(
+                    out,
+                    "{{\"chip\":\"{:?}\",\"instance\":{},\"port\":{},\"register\":{},\"value\":{}}}",
+                    write.chip,
+                    usize::from(write.instance),
+                    write.port,
+                    write.register,
+                    write.value
+                );
+            }
+            out.push_str("]}");
+        }
+        out.push_str("]}");
+        out
+    }
+}
@@ -0,0 +1,426 @@
+//! XGM (v1) export for the SGDK Mega Drive sound driver.
+//!
+//! `to_xgm` converts a YM2612 / SN76489 `VgmDocument` into an XGM v1 file:
+//!
+//! - register writes are grouped per frame (1/60 s, or 1/50 s for PAL) into
+//!   the XGM PSG, YM2612 port 0/1 and key on/off commands;
+//! - YM2612 DAC playback is converted into XGM PCM channels. Both DAC stream
+//!   control commands (`0x90`-`0x95`) and `0x8n` data-bank writes are
+//!   recognized; every distinct (offset, length) region of the PCM data bank
+//!   becomes one XGM sample, resampled to 14 kHz and converted to signed
+//!   8-bit;
+//! - the loop point is mapped to the start of the frame that contains it.
+//!
+//! Commands for other chips, secondary chip instances and direct DAC writes
+//! to register `0x2A` cannot be represented and are dropped, as are stream
+//! starts without a stream frequency; the number of dropped commands is
+//! returned in the `XgmReport`. Only XGM v1 is written: XGM v2 (`XGM2`, the
+//! SGDK 2.0 format) output is out of scope.
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::chip;
+//! use soundlog::vgm::command::{Instance, WaitSamples};
+//! use soundlog::vgm::export::xgm::{XgmOptions, to_xgm};
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.register_chip(chip::Chip::Ym2612, Instance::Primary, 7_670_454);
+//! builder.add_chip_write(Instance::Primary, chip::Ym2612Spec { port: 0, register: 0x28, value: 0xF0 });
+//! builder.add_vgm_command(WaitSamples(735));
+//! let doc = builder.finalize();
+//!
+//! let (bytes, report) = to_xgm(&doc, &XgmOptions::default()).unwrap();
+//! assert_eq!(&bytes[0..4], b"XGM ");
+//! assert_eq!(report.frames, 1);
+//! ```
+use std::collections::HashMap;
+
+use crate::binutil::ParseError;
+use crate::vgm::VgmDocument;
+use crate::vgm::command::{Instance, LengthMode, VgmCommand};
+use crate::vgm::header::ChipId;
+
+/// Sample rate of XGM v1 PCM samples.
+pub const XGM_SAMPLE_RATE: u32 = 14_000;
+
+/// Maximum number of samples an XGM v1 file can reference.
+pub const XGM_MAX_SAMPLES: usize = 63;
+
+/// Longest pause (in samples) between two `0x8n` writes that still belong to
+/// the same PCM sample.
+const DAC_RUN_MAX_GAP: u64 = 256;
+
+/// Options for `to_xgm`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct XgmOptions {
+    /// Produce a PAL (50 Hz) file instead of NTSC (60 Hz).
+    pub pal: bool,
+}
+
+impl XgmOptions {
+    /// Options matching the document's header rate (`50` selects PAL).
+    pub fn for_document(document: &VgmDocument) -> Self {
+        XgmOptions {
+            pal: document.header.sample_rate == 50,
+        }
+    }
+
+    fn samples_per_frame(&self) -> u32 {
+        if self.pal { 882 } else { 735 }
+    }
+}
+
+/// Summary of an XGM conversion.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct XgmReport {
+    /// Number of frames written.
+    pub frames: u64,
+    /// Number of distinct PCM samples stored in the file.
+    pub samples: usize,
+    /// Number of PCM play commands written.
+    pub pcm_plays: usize,
+    /// Number of commands that could not be represented in XGM.
+    pub dropped_commands: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Item {
+    Psg(u8),
+    Ym(u8, u8, u8),
+    Key(u8),
+    // channel and index into `runs`; `None` stops the channel
+    Pcm(u8, Option<usize>),
+}
+
+#[derive(Debug, Clone)]
+struct Run {
+    offset: usize,
+    length: usize,
+    rate: u32,
+}
+
+#[derive(Debug, Clone)]
+struct DacRun {
+    run: usize,
+    start: u64,
+    last: u64,
+    end: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+struct StreamInfo {
+    ym2612: bool,
+    frequency: u32,
+}
+
+/// Convert `document` into an XGM v1 file.
+///
+/// Returns the file bytes and a report. Fails when the document needs more
+/// than `XGM_MAX_SAMPLES` distinct samples or the sample data does not fit
+/// in the XGM sample block.
+pub fn to_xgm(
+    document: &VgmDocument,
+    options: &XgmOptions,
+) -> Result<(Vec<u8>, XgmReport), ParseError> {
+    let samples_per_frame = options.samples_per_frame();
+    let mut report = XgmReport::default();
+
+    // YM2612 PCM data bank (data block type 0x00) and its block boundaries
+    let mut bank: Vec<u8> = Vec::new();
+    let mut blocks: Vec<(usize, usize)> = Vec::new();
+    for command in document.iter() {
+        if let VgmCommand::DataBlock(block) = command
+            && block.data_type == 0x00
+        {
+            blocks.push((bank.len(), block.data.len()));
+            bank.extend_from_slice(&block.data);
+        }
+    }
+
+    let loop_frame = document.loop_command_index().map(|index| {
+        let time: u64 = document.commands[..index]
+            .iter()
+            .map(|c| c.wait_samples() as u64)
+            .sum();
+        time / samples_per_frame as u64
+    });
+
+    let mut runs: Vec<Run> = Vec::new();
+    let mut frames: Vec<Vec<Item>> = Vec::new();
+    let mut streams: HashMap<u8, StreamInfo> = HashMap::new();
+    let mut dac_run: Option<DacRun> = None;
+    let mut pcm_pos: usize = 0;
+
+    let close_dac_run = |dac_run: &mut Option<DacRun>, runs: &mut Vec<Run>| {
+        if let Some(r) = dac_run.take() {
+            let duration = r.end.saturating_sub(r.start);
+            let run = &mut runs[r.run];
+            run.rate = (run.length as u64 * 44_100)
+                .checked_div(duration)
+                .map_or(XGM_SAMPLE_RATE, |rate| rate as u32);
+        }
+    };
+
+    for frame in document.frames(samples_per_frame) {
+        let mut items = Vec::new();
+        for (time, command) in frame.commands {
+            match command {
+                VgmCommand::Sn76489Write(Instance::Primary, s) => items.push(Item::Psg(s.value)),
+                VgmCommand::Ym2612Write(Instance::Primary, s) => match (s.port, s.register) {
+                    (0, 0x28) => items.push(Item::Key(s.value)),
+                    (0, 0x2A) => report.dropped_commands += 1,
+                    _ => items.push(Item::Ym(s.port & 1, s.register, s.value)),
+                },
+                VgmCommand::DataBlock(_) | VgmCommand::SetStreamData(_) => {}
+                VgmCommand::SeekOffset(s) => {
+                    close_dac_run(&mut dac_run, &mut runs);
+                    pcm_pos = s.0 as usize;
+                }
+                VgmCommand::YM2612Port0Address2AWriteAndWaitN(s) => {
+                    let continues = dac_run
+                        .as_ref()
+                        .is_some_and(|r| time.saturating_sub(r.last) <= DAC_RUN_MAX_GAP);
+                    if !continues {
+                        close_dac_run(&mut dac_run, &mut runs);
+                        runs.push(Run {
+                            offset: pcm_pos,
+                            length: 0,
+                            rate: XGM_SAMPLE_RATE,
+                        });
+                        items.push(Item::Pcm(0, Some(runs.len() - 1)));
+                        dac_run = Some(DacRun {
+                            run: runs.len() - 1,
+                            start: time,
+                            last: time,
+                            end: time,
+                        });
+                    }
+                    if let Some(r) = dac_run.as_mut() {
+                        runs[r.run].length += 1;
+                        r.last = time;
+                        r.end = time + s.0 as u64;
+                    }
+                    pcm_pos += 1;
+                }
+                VgmCommand::SetupStreamControl(s) => {
+                    let info = streams.entry(s.stream_id).or_default();
+                    info.ym2612 = s.chip_type.chip_id == ChipId::Ym2612
+                        && s.chip_type.instance == Instance::Primary
+                        && s.write_command == 0x2A;
+                }
+                VgmCommand::SetStreamFrequency(s) => {
+                    streams.entry(s.stream_id).or_default().frequency = s.frequency;
+                }
+                VgmCommand::StartStream(s) => {
+                    let info = streams.get(&s.stream_id).cloned().unwrap_or_default();
+                    let offset = s.data_start_offset.max(0) as usize;
+                    let length = match s.length_mode {
+                        LengthMode::CommandCount { .. } => Some(s.data_length as usize),
+                        LengthMode::Milliseconds { .. } => {
+                            Some((s.data_length as u64 * info.frequency as u64 / 1000) as usize)
+                        }
+                        LengthMode::PlayUntilEnd { .. } => blocks
+                            .iter()
+                            .find(|(start, len)| offset >= *start && offset < start + len)
+                            .map(|(start, len)| start + len - offset),
+                        _ => None,
+                    };
+                    match length {
+                        Some(length) if info.ym2612 && info.frequency > 0 => {
+                            runs.push(Run {
+                                offset,
+                                length,
+                                rate: info.frequency,
+                            });
+                            items.push(Item::Pcm(s.stream_id & 3, Some(runs.len() - 1)));
+                        }
+                        _ => report.dropped_commands += 1,
+                    }
+                }
+                VgmCommand::StartStreamFastCall(s) => {
+                    let info = streams.get(&s.stream_id).cloned().unwrap_or_default();
+                    match blocks.get(s.block_id as usize) {
+                        Some(&(offset, length)) if info.ym2612 && info.frequency > 0 => {
+                            runs.push(Run {
+                                offset,
+                                length,
+                                rate: info.frequency,
+                            });
+                            items.push(Item::Pcm(s.stream_id & 3, Some(runs.len() - 1)));
+                        }
+                        _ => report.dropped_commands += 1,
+                    }
+                }
+                VgmCommand::StopStream(s) => {
+                    if s.stream_id == 0xFF {
+                        for channel in 0..4 {
+                            items.push(Item::Pcm(channel, None));
+                        }
+                    } else {
+                        items.push(Item::Pcm(s.stream_id & 3, None));
+                    }
+                }
+                _ => report.dropped_commands += 1,
+            }
+        }
+        frames.push(items);
+    }
+    close_dac_run(&mut dac_run, &mut runs);
+
+    // Deduplicate runs into samples and render their data
+    let mut sample_ids: HashMap<(usize, usize), u8> = HashMap::new();
+    let mut run_ids: Vec<u8> = Vec::with_capacity(runs.len());
+    let mut sample_data: Vec<u8> = Vec::new();
+    let mut sample_table: Vec<(u16, u16)> = Vec::new();
+    for run in &runs {
+        let key = (run.offset, run.length);
+        if let Some(id) = sample_ids.get(&key) {
+            run_ids.push(*id);
+            continue;
+        }
+        if sample_table.len() >= XGM_MAX_SAMPLES {
+            return Err(ParseError::Other(format!(
+                "XGM supports at most {} samples",
+                XGM_MAX_SAMPLES
+            )));
+        }
+        let end = (run.offset + run.length).min(bank.len());
+        let source = bank.get(run.offset..end).unwrap_or(&[]);
+        let start = sample_data.len();
+        if (start + resampled_length(source.len(), run.rate)) / 256 > u16::MAX as usize {
+            return Err(sample_block_exceeded());
+        }
+        sample_data.extend(resample(source, run.rate));
+        sample_data.resize(sample_data.len().div_ceil(256) * 256, 0);
+        sample_table.push((
+            (start / 256) as u16,
+            ((sample_data.len() - start) / 256) as u16,
+        ));
+        let id = sample_table.len() as u8;
+        sample_ids.insert(key, id);
+        run_ids.push(id);
+    }
+    if sample_data.len() / 256 > u16::MAX as usize {
+        return Err(sample_block_exceeded());
+    }
+
+    // Music data
+    let mut music: Vec<u8> = Vec::new();
+    let mut loop_offset = None;
+    for (index, items) in frames.iter().enumerate() {
+        if loop_frame == Some(index as u64) {
+            loop_offset = Some(music.len());
+        }
+        write_frame(&mut music, items, &run_ids, &mut report);
+        music.push(0x00);
+    }
+    match loop_offset {
+        Some(offset) => {
+            music.push(0x7E);
+            music.extend_from_slice(&(offset as u32).to_le_bytes()[..3]);
+        }
+        None => music.push(0x7F),
+    }
+
+    // File layout
+    let mut out = Vec::with_capacity(0x104 + sample_data.len() + 4 + music.len());
+    out.extend_from_slice(b"XGM ");
+    for index in 0..XGM_MAX_SAMPLES {
+        let (offset, size) = sample_table.get(index).copied().unwrap_or((0xFFFF, 0x0001));
+        out.extend_from_slice(&offset.to_le_bytes());
+        out.extend_from_slice(&size.to_le_bytes());
+    }
+    out.extend_from_slice(&((sample_data.len() / 256) as u16).to_le_bytes());
+    out.push(0x01);
+    out.push(if options.pal { 0x01 } else { 0x00 });
+    out.extend_from_slice(&sample_data);
+    out.extend_from_slice(&(music.len() as u32).to_le_bytes());
+    out.extend_from_slice(&music);
+
+    report.frames = frames.len() as u64;
+    report.samples = sample_table.len();
+    Ok((out, report))
+}
+
+// Serialize the items of one frame, grouping consecutive writes of the same
+// kind into XGM commands of up to 16 entries.
+fn write_frame(music: &mut Vec<u8>, items: &[Item], run_ids: &[u8], report: &mut XgmReport) {
+    let mut i = 0;
+    while i < items.len() {
+        match items[i] {
+            Item::Psg(_) => {
+                let group: Vec<u8> = items[i..]
+                    .iter()
+                    .take(16)
+                    .map_while(|item| match item {
+                        Item::Psg(v) => Some(*v),
+                        _ => None,
+                    })
+                    .collect();
+                music.push(0x10 | (group.len() as u8 - 1));
+                music.extend_from_slice(&group);
+                i += group.len();
+            }
+            Item::Key(_) => {
+                let group: Vec<u8> = items[i..]
+                    .iter()
+                    .take(16)
+                    .map_while(|item| match item {
+                        Item::Key(v) => Some(*v),
+                        _ => None,
+                    })
+                    .collect();
+                music.push(0x40 | (group.len() as u8 - 1));
+                music.extend_from_slice(&group);
+                i += group.len();
+            }
+            Item::Ym(port, _, _) => {
+                let group: Vec<(u8, u8)> = items[i..]
+                    .iter()
+                    .take(16)
+                    .map_while(|item| match item {
+                        Item::Ym(p, r, v) if *p == port => Some((*r, *v)),
+                        _ => None,
+                    })
+                    .collect();
+                music.push(if port == 0 { 0x20 } else { 0x30 } | (group.len() as u8 - 1));
+                for (register, value) in &group {
+                    music.push(*register);
+                    music.push(*value);
+                }
+                i += group.len();
+            }
+            Item::Pcm(channel, run) => {
+                music.push(0x50 | (channel & 3));
+                music.push(run.map(|r| run_ids[r]).unwrap_or(0));
+                report.pcm_plays += run.is_some() as usize;
+                i += 1;
+            }
+        }
+    }
+}
+
+fn sample_block_exceeded() -> ParseError {
+    ParseError::Other("XGM sample data exceeds the 16 MiB sample block".to_string())
+}
+
+// Length of `len` bytes at `rate` resampled to `XGM_SAMPLE_RATE`. `rate` must
+// not be zero: streams without a frequency are dropped before.
+fn resampled_length(len: usize, rate: u32) -> usize {
+    if len == 0 {
+        return 0;
+    }
+    (len as u64 * XGM_SAMPLE_RATE as u64 / rate as u64).max(1) as usize
+}
+
+// Nearest-neighbour resample of unsigned 8-bit PCM at `rate` to signed 8-bit
+// PCM at `XGM_SAMPLE_RATE`.
+fn resample(source: &[u8], rate: u32) -> Vec<u8> {
+    (0..resampled_length(source.len(), rate))
+        .map(|i| {
+            let index =
+                ((i as u64 * rate as u64 / XGM_SAMPLE_RATE as u64) as usize).min(source.len() - 1);
+            source[index] ^ 0x80
+        })
+        .collect()
+}
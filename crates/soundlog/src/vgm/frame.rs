@@ -0,0 +1,114 @@
+//! Frame-grouped iteration over a document's command stream.
+//!
+//! Frame-based sound drivers and trackers think in ticks (usually 1/60 s or
+//! 1/50 s) rather than in samples. `VgmDocument::frames` walks the command
+//! stream once and yields one `Frame` per tick, including empty ticks, with
+//! each non-wait command tagged with its absolute sample position.
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::chip;
+//! use soundlog::vgm::command::{Instance, WaitSamples};
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.register_chip(chip::Chip::Sn76489, Instance::Primary, 3_579_545);
+//! builder.add_chip_write(Instance::Primary, chip::PsgSpec { value: 0x9F });
+//! builder.add_vgm_command(WaitSamples(1470));
+//! builder.add_chip_write(Instance::Primary, chip::PsgSpec { value: 0x90 });
+//! builder.add_vgm_command(WaitSamples(735));
+//! let doc = builder.finalize();
+//!
+//! let frames: Vec<_> = doc.frames(735).collect();
+//! assert_eq!(frames.len(), 3);
+//! assert_eq!(frames[0].commands.len(), 1);
+//! assert!(frames[1].commands.is_empty());
+//! assert_eq!(frames[2].commands[0].0, 1470);
+//! ```
+use crate::vgm::VgmDocument;
+use crate::vgm::command::VgmCommand;
+
+/// Commands that start within one tick.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame<'a> {
+    /// Zero-based tick number.
+    pub index: u64,
+    /// Absolute sample position of the start of the tick.
+    pub start_sample: u64,
+    /// Non-wait commands in the tick paired with their absolute sample
+    /// position.
+    pub commands: Vec<(u64, &'a VgmCommand)>,
+}
+
+/// Iterator returned by `VgmDocument::frames`.
+#[derive(Debug, Clone)]
+pub struct Frames<'a> {
+    commands: &'a [VgmCommand],
+    position: usize,
+    time: u64,
+    index: u64,
+    samples_per_frame: u64,
+    end_sample: u64,
+}
+
+impl<'a> Iterator for Frames<'a> {
+    type Item = Frame<'a>;
+
+    fn next(&mut self) -> Option<Frame<'a>> {
+        let start_sample = self.index * self.samples_per_frame;
+        let next_start = start_sample + self.samples_per_frame;
+        if self.position >= self.commands.len() && start_sample >= self.end_sample {
+            return None;
+        }
+
+        let mut commands = Vec::new();
+        while let Some(command) = self.commands.get(self.position) {
+            match command {
+                VgmCommand::WaitSamples(_)
+                | VgmCommand::Wait735Samples(_)
+                | VgmCommand::Wait882Samples(_)
+                | VgmCommand::WaitNSample(_) => {
+                    self.time += command.wait_samples() as u64;
+                }
+                VgmCommand::EndOfData(_) => {
+                    self.position = self.commands.len();
+                    break;
+                }
+                _ if self.time >= next_start => break,
+                _ => {
+                    commands.push((self.time, command));
+                    // 0x8n carries its own wait after the write
+                    self.time += command.wait_samples() as u64;
+                }
+            }
+            self.position += 1;
+        }
+
+        let frame = Frame {
+            index: self.index,
+            start_sample,
+            commands,
+        };
+        self.index += 1;
+        Some(frame)
+    }
+}
+
+impl VgmDocument {
+    /// Iterate the command stream grouped into ticks of `samples_per_frame`
+    /// samples (735 for 1/60 s and 882 for 1/50 s at 44.1 kHz).
+    ///
+    /// Every tick between the start of the document and its total length is
+    /// yielded, so empty ticks produce a `Frame` with no commands. Iteration
+    /// stops at the first `EndOfData`. A `samples_per_frame` of `0` is treated
+    /// as `1`.
+    pub fn frames(&self, samples_per_frame: u32) -> Frames<'_> {
+        Frames {
+            commands: &self.commands,
+            position: 0,
+            time: 0,
+            index: 0,
+            samples_per_frame: samples_per_frame.max(1) as u64,
+            end_sample: self.total_samples(0) as u64,
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! Frame rate inference.
+//!
+//! Most game music drivers run from the vertical blank interrupt, so a log
+//! of them only waits in whole video frames: multiples of 735 samples for
+//! 60 Hz (NTSC) machines and of 882 samples for 50 Hz (PAL) ones. Drivers run
+//! from a chip timer, and logs with DAC streams, wait any number of samples.
+//! `infer_frame_rate` tells these apart from a histogram of the gaps between
+//! groups of register writes, which also picks the grid for
+//! `transform::quantize`.
+//!
+//! YM2612 DAC writes (`0x8n` and register `0x2A`) are left out of the
+//! groups, since a sample played at a few kHz would hide the tick of the
+//! sequencer.
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::chip::{Chip, Ym2612Spec};
+//! use soundlog::vgm::command::{Instance, WaitSamples};
+//! use soundlog::vgm::frame_rate::{FrameRate, infer_frame_rate};
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.register_chip(Chip::Ym2612, Instance::Primary, 7_600_489);
+//! for frame in 0..50 {
+//!     builder.add_chip_write(Instance::Primary, Ym2612Spec { port: 0, register: 0xA0, value: frame });
+//!     builder.add_vgm_command(WaitSamples(if frame % 10 == 9 { 1764 } else { 882 }));
+//! }
+//! let doc = builder.finalize();
+//!
+//! let guess = infer_frame_rate(&doc).unwrap();
+//! assert_eq!(guess.rate, FrameRate::Pal);
+//! assert_eq!(guess.pal_share, 100);
+//! assert_eq!(guess.quantize_options().unwrap().grid, 882);
+//! ```
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::chip::Chip;
+use crate::vgm::VgmDocument;
+use crate::vgm::analysis::{Analysis, AnalysisContext, run_analyses};
+use crate::vgm::command::VgmCommand;
+use crate::vgm::transform::QuantizeOptions;
+
+/// Share of the gaps, in percent, that must be whole frames for a frame rate
+/// to be reported.
+const MIN_SHARE: u8 = 90;
+
+/// Distance in samples from a whole number of frames still counted as one,
+/// for loggers that round the frame length.
+const TOLERANCE: u64 = 2;
+
+/// The timing a document was logged with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FrameRate {
+    /// Updated once per 60 Hz video frame (735 samples).
+    Ntsc,
+    /// Updated once per 50 Hz video frame (882 samples).
+    Pal,
+    /// Not tied to a video frame.
+    FreeRunning,
+}
+
+impl FrameRate {
+    /// Length of one frame in samples, `None` for `FreeRunning`.
+    pub fn frame_samples(&self) -> Option<u32> {
+        match self {
+            FrameRate::Ntsc => Some(QuantizeOptions::NTSC_FRAME),
+            FrameRate::Pal => Some(QuantizeOptions::PAL_FRAME),
+            FrameRate::FreeRunning => None,
+        }
+    }
+}
+
+impl fmt::Display for FrameRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FrameRate::Ntsc => "60 Hz",
+            FrameRate::Pal => "50 Hz",
+            FrameRate::FreeRunning => "free-running",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The result of `infer_frame_rate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameRateGuess {
+    pub rate: FrameRate,
+    /// Share of the gaps that are whole 60 Hz frames, in percent.
+    pub ntsc_share: u8,
+    /// Share of the gaps that are whole 50 Hz frames, in percent.
+    pub pal_share: u8,
+    /// Number of gaps between groups of writes.
+    pub gaps: usize,
+    /// Occurrences of every gap length in samples.
+    pub histogram: BTreeMap<u64, usize>,
+}
+
+impl FrameRateGuess {
+    /// Gap lengths with their occurrences, most common first.
+    pub fn most_common(&self) -> Vec<(u64, usize)> {
+        let mut gaps: Vec<(u64, usize)> = self
+            .histogram
+            .iter()
+            .map(|(&gap, &count)| (gap, count))
+            .collect();
+        gaps.sort_by_key(|&(gap, count)| (std::cmp::Reverse(count), gap));
+        gaps
+    }
+
+    /// Options snapping to one frame of the inferred rate, `None` when the
+    /// document is free-running.
+    pub fn quantize_options(&self) -> Option<QuantizeOptions> {
+        self.rate.frame_samples().map(QuantizeOptions::new)
+    }
+}
+
+/// Infer the frame rate `document` was logged at. Returns `None` when it has
+/// fewer than two groups of writes.
+pub fn infer_frame_rate(document: &VgmDocument) -> Option<FrameRateGuess> {
+    let mut analysis = FrameRateAnalysis::new();
+    run_analyses(document, &mut [&mut analysis]);
+    analysis.into_guess()
+}
+
+/// `infer_frame_rate` as an `Analysis`, to share a pass with other analyses.
+#[derive(Debug, Clone, Default)]
+pub struct FrameRateAnalysis {
+    histogram: BTreeMap<u64, usize>,
+    last_write: Option<u64>,
+}
+
+impl FrameRateAnalysis {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The guess, complete once the analysis has run.
+    pub fn into_guess(self) -> Option<FrameRateGuess> {
+        let gaps: usize = self.histogram.values().sum();
+        if gaps == 0 {
+            return None;
+        }
+        let share = |frame: u32| {
+            let frames: usize = self
+                .histogram
+                .iter()
+                .filter(|&(&gap, _)| whole_frames(gap, frame as u64))
+                .map(|(_, &count)| count)
+                .sum();
+            (frames * 100 / gaps) as u8
+        };
+        let ntsc_share = share(QuantizeOptions::NTSC_FRAME);
+        let pal_share = share(QuantizeOptions::PAL_FRAME);
+        // 4410 samples are both six NTSC and five PAL frames; a tie goes to
+        // the rate with more single-frame gaps.
+        let single = |frame: u32| {
+            self.histogram
+                .range(frame as u64 - TOLERANCE..=frame as u64 + TOLERANCE)
+                .map(|(_, &count)| count)
+                .sum::<usize>()
+        };
+        let rate = if ntsc_share.max(pal_share) < MIN_SHARE {
+            FrameRate::FreeRunning
+        } else if (ntsc_share, single(QuantizeOptions::NTSC_FRAME))
+            >= (pal_share, single(QuantizeOptions::PAL_FRAME))
+        {
+            FrameRate::Ntsc
+        } else {
+            FrameRate::Pal
+        };
+        Some(FrameRateGuess {
+            rate,
+            ntsc_share,
+            pal_share,
+            gaps,
+            histogram: self.histogram,
+        })
+    }
+}
+
+fn whole_frames(gap: u64, frame: u64) -> bool {
+    let frames = (gap + frame / 2) / frame;
+    frames > 0 && gap.abs_diff(frames * frame) <= TOLERANCE
+}
+
+impl Analysis for FrameRateAnalysis {
+    fn command(&mut self, context: &AnalysisContext<'_>, _command: &VgmCommand) {
+        let Some(write) = context.write() else {
+            return;
+        };
+        if write.chip == Chip::Ym2612 && write.port == 0 && write.register == 0x2A {
+            return;
+        }
+        let time = context.time();
+        if self.last_write != Some(time) {
+            if let Some(last) = self.last_write {
+                *self.histogram.entry(time - last).or_insert(0) += 1;
+            }
+            self.last_write = Some(time);
+        }
+    }
+}
@@ -15,7 +15,7 @@
 //! - The module exposes `VGM_MAX_HEADER_SIZE` constant and preserves the
 //!   writer/reader convention where `data_offset == 0` falls back to the
 //!   legacy header size.
-use crate::binutil::{ParseError, write_slice, write_u8, write_u16, write_u32};
+use crate::binutil::{FileOffset, ParseError, write_slice, write_u8, write_u16, write_u32};
 use crate::chip;
 use crate::vgm::command::Instance;
 use crate::vgm::parser::parse_vgm_header;
@@ -66,8 +66,103 @@ impl<'a> IntoIterator for &'a ChipInstances {
     }
 }
 
+/// Typed editor for the chip clocks of a `VgmHeader`.
+///
+/// Obtained from `VgmHeader::chip_instances_mut`. Every setter writes straight
+/// back to the header clock fields, so `VgmHeader::chip_instances` reflects
+/// the change immediately.
+///
+/// ```rust
+/// use soundlog::chip::Chip;
+/// use soundlog::vgm::command::Instance;
+/// use soundlog::vgm::header::VgmHeader;
+///
+/// let mut header = VgmHeader::default();
+/// let mut chips = header.chip_instances_mut();
+/// chips.set_clock(Chip::Ym2612, 7_670_453);
+/// chips.enable_secondary(&Chip::Ym2612);
+/// chips.set_clock(Chip::Ym2612, 7_670_454);
+/// chips.set_clock(Chip::Sn76489, 3_579_545);
+/// chips.remove_chip(&Chip::Sn76489);
+///
+/// assert_eq!(header.ym2612_clock, 7_670_454 | 0x8000_0000);
+/// assert_eq!(
+///     header.chip_instances().0,
+///     vec![
+///         (Instance::Primary, Chip::Ym2612, 7_670_454.0),
+///         (Instance::Secondary, Chip::Ym2612, 7_670_454.0),
+///     ]
+/// );
+/// ```
+#[derive(Debug)]
+pub struct ChipInstancesMut<'a> {
+    header: &'a mut VgmHeader,
+}
+
+impl ChipInstancesMut<'_> {
+    /// Returns the current chip instances, as `VgmHeader::chip_instances`.
+    pub fn get(&self) -> ChipInstances {
+        self.header.chip_instances()
+    }
+
+    /// Clock of `chip` in Hz without the dual-chip bit, or `None` when the
+    /// chip is not in the header.
+    pub fn clock(&self, chip: &chip::Chip) -> Option<u32> {
+        match self.header.get_chip_clock(chip) & 0x7FFF_FFFF {
+            0 => None,
+            clock => Some(clock),
+        }
+    }
+
+    /// `true` when the secondary instance of `chip` is enabled.
+    pub fn has_secondary(&self, chip: &chip::Chip) -> bool {
+        self.header.get_chip_clock(chip) & 0x8000_0000 != 0
+    }
+
+    /// Sets the clock of `chip` to `clock_hz`, adding the chip as a primary
+    /// instance when it is not present and keeping its secondary instance
+    /// otherwise. The dual-chip bit of `clock_hz` is ignored; a clock of `0`
+    /// removes the chip.
+    pub fn set_clock(&mut self, chip: chip::Chip, clock_hz: u32) -> &mut Self {
+        let clock_hz = clock_hz & 0x7FFF_FFFF;
+        let instance = if clock_hz != 0 && self.has_secondary(&chip) {
+            Instance::Secondary
+        } else {
+            Instance::Primary
+        };
+        self.header.set_chip_clock(chip, instance, clock_hz);
+        self
+    }
+
+    /// Sets the dual-chip bit of `chip`. Returns `false`, changing nothing,
+    /// when the chip has no clock.
+    pub fn enable_secondary(&mut self, chip: &chip::Chip) -> bool {
+        let Some(clock) = self.clock(chip) else {
+            return false;
+        };
+        self.header
+            .set_chip_clock(chip.clone(), Instance::Secondary, clock);
+        true
+    }
+
+    /// Clears the dual-chip bit of `chip`, keeping the primary instance.
+    pub fn disable_secondary(&mut self, chip: &chip::Chip) -> &mut Self {
+        let clock = self.clock(chip).unwrap_or(0);
+        self.header
+            .set_chip_clock(chip.clone(), Instance::Primary, clock);
+        self
+    }
+
+    /// Removes both instances of `chip` by clearing its clock.
+    pub fn remove_chip(&mut self, chip: &chip::Chip) -> &mut Self {
+        self.header
+            .set_chip_clock(chip.clone(), Instance::Primary, 0);
+        self
+    }
+}
+
 /// Enum identifying header fields and their on-disk offsets.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum VgmHeaderField {
     Ident,
     EofOffset,
@@ -531,7 +626,116 @@ impl From<K054539Flags> for u8 {
     }
 }
 
+/// SegaPCM interface register stored in the VGM header (4 bytes, little
+/// endian), as MAME's `segapcm_device::set_bank` takes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegaPcmInterface {
+    /// bits 0-7: bank shift, the address bit the bank number starts at
+    /// (11, 12 or 13 on known boards)
+    pub bank_shift: u8,
+    /// bits 16-23: bank mask applied to the channel bank register (`0` means
+    /// `0x70`)
+    pub bank_mask: u8,
+    /// bits 8-15 and 24-31, in place (unused)
+    pub reserved: u32,
+}
+
+impl From<u32> for SegaPcmInterface {
+    fn from(value: u32) -> Self {
+        SegaPcmInterface {
+            bank_shift: value as u8,
+            bank_mask: (value >> 16) as u8,
+            reserved: value & 0xFF00_FF00,
+        }
+    }
+}
+
+impl From<SegaPcmInterface> for u32 {
+    fn from(interface: SegaPcmInterface) -> Self {
+        interface.bank_shift as u32
+            | (interface.bank_mask as u32) << 16
+            | (interface.reserved & 0xFF00_FF00)
+    }
+}
+
 impl VgmHeaderField {
+    /// Every header field in on-disk order.
+    pub const ALL: [VgmHeaderField; 73] = [
+        VgmHeaderField::Ident,
+        VgmHeaderField::EofOffset,
+        VgmHeaderField::Version,
+        VgmHeaderField::Sn76489Clock,
+        VgmHeaderField::Ym2413Clock,
+        VgmHeaderField::Gd3Offset,
+        VgmHeaderField::TotalSamples,
+        VgmHeaderField::LoopOffset,
+        VgmHeaderField::LoopSamples,
+        VgmHeaderField::SampleRate,
+        VgmHeaderField::Sn76489Feedback,
+        VgmHeaderField::Sn76489ShiftRegisterWidth,
+        VgmHeaderField::Sn76489Flags,
+        VgmHeaderField::Ym2612Clock,
+        VgmHeaderField::Ym2151Clock,
+        VgmHeaderField::DataOffset,
+        VgmHeaderField::SegaPcmClock,
+        VgmHeaderField::SpcmInterface,
+        VgmHeaderField::Rf5c68Clock,
+        VgmHeaderField::Ym2203Clock,
+        VgmHeaderField::Ym2608Clock,
+        VgmHeaderField::Ym2610bClock,
+        VgmHeaderField::Ym3812Clock,
+        VgmHeaderField::Ym3526Clock,
+        VgmHeaderField::Y8950Clock,
+        VgmHeaderField::Ymf262Clock,
+        VgmHeaderField::Ymf278bClock,
+        VgmHeaderField::Ymf271Clock,
+        VgmHeaderField::Ymz280bClock,
+        VgmHeaderField::Rf5c164Clock,
+        VgmHeaderField::PwmClock,
+        VgmHeaderField::Ay8910Clock,
+        VgmHeaderField::Ay8910ChipType,
+        VgmHeaderField::Ay8910Flags,
+        VgmHeaderField::Ym2203Ay8910Flags,
+        VgmHeaderField::Ym2608Ay8910Flags,
+        VgmHeaderField::VolumeModifier,
+        VgmHeaderField::Reserved7D,
+        VgmHeaderField::LoopBase,
+        VgmHeaderField::LoopModifier,
+        VgmHeaderField::GbDmgClock,
+        VgmHeaderField::NesApuClock,
+        VgmHeaderField::MultipcmClock,
+        VgmHeaderField::Upd7759Clock,
+        VgmHeaderField::Okim6258Clock,
+        VgmHeaderField::Okim6258Flags,
+        VgmHeaderField::K054539Flags,
+        VgmHeaderField::C140ChipType,
+        VgmHeaderField::Okim6295Clock,
+        VgmHeaderField::K051649Clock,
+        VgmHeaderField::K054539Clock,
+        VgmHeaderField::Huc6280Clock,
+        VgmHeaderField::C140Clock,
+        VgmHeaderField::Reserved97,
+        VgmHeaderField::K053260Clock,
+        VgmHeaderField::PokeyClock,
+        VgmHeaderField::QsoundClock,
+        VgmHeaderField::ScspClock,
+        VgmHeaderField::ExtraHeaderOffset,
+        VgmHeaderField::WonderSwan,
+        VgmHeaderField::Vsu,
+        VgmHeaderField::Saa1099,
+        VgmHeaderField::Es5503,
+        VgmHeaderField::Es5506,
+        VgmHeaderField::Es5503OutputChannels,
+        VgmHeaderField::Es5506OutputChannels,
+        VgmHeaderField::C352ClockDivider,
+        VgmHeaderField::X1_010,
+        VgmHeaderField::C352,
+        VgmHeaderField::Ga20,
+        VgmHeaderField::Mikey,
+        VgmHeaderField::ReservedE8EF,
+        VgmHeaderField::ReservedF0FF,
+    ];
+
     pub fn offset(self) -> usize {
         match self {
             VgmHeaderField::Ident => 0x00,
@@ -694,6 +898,54 @@ impl VgmHeaderField {
         self.len() == 0
     }
 
+    /// The clock field of `chip`.
+    pub fn clock_of(chip: &chip::Chip) -> VgmHeaderField {
+        match chip {
+            chip::Chip::Sn76489 => VgmHeaderField::Sn76489Clock,
+            chip::Chip::Ym2413 => VgmHeaderField::Ym2413Clock,
+            chip::Chip::Ym2612 => VgmHeaderField::Ym2612Clock,
+            chip::Chip::Ym2151 => VgmHeaderField::Ym2151Clock,
+            chip::Chip::SegaPcm => VgmHeaderField::SegaPcmClock,
+            chip::Chip::Rf5c68 => VgmHeaderField::Rf5c68Clock,
+            chip::Chip::Ym2203 => VgmHeaderField::Ym2203Clock,
+            chip::Chip::Ym2608 => VgmHeaderField::Ym2608Clock,
+            chip::Chip::Ym2610b => VgmHeaderField::Ym2610bClock,
+            chip::Chip::Ym3812 => VgmHeaderField::Ym3812Clock,
+            chip::Chip::Ym3526 => VgmHeaderField::Ym3526Clock,
+            chip::Chip::Y8950 => VgmHeaderField::Y8950Clock,
+            chip::Chip::Ymf262 => VgmHeaderField::Ymf262Clock,
+            chip::Chip::Ymf278b => VgmHeaderField::Ymf278bClock,
+            chip::Chip::Ymf271 => VgmHeaderField::Ymf271Clock,
+            chip::Chip::Ymz280b => VgmHeaderField::Ymz280bClock,
+            chip::Chip::Rf5c164 => VgmHeaderField::Rf5c164Clock,
+            chip::Chip::Pwm => VgmHeaderField::PwmClock,
+            chip::Chip::Ay8910 => VgmHeaderField::Ay8910Clock,
+            chip::Chip::GbDmg => VgmHeaderField::GbDmgClock,
+            chip::Chip::NesApu => VgmHeaderField::NesApuClock,
+            chip::Chip::MultiPcm => VgmHeaderField::MultipcmClock,
+            chip::Chip::Upd7759 => VgmHeaderField::Upd7759Clock,
+            chip::Chip::Okim6258 => VgmHeaderField::Okim6258Clock,
+            chip::Chip::Okim6295 => VgmHeaderField::Okim6295Clock,
+            chip::Chip::K051649 => VgmHeaderField::K051649Clock,
+            chip::Chip::K054539 => VgmHeaderField::K054539Clock,
+            chip::Chip::Huc6280 => VgmHeaderField::Huc6280Clock,
+            chip::Chip::C140 => VgmHeaderField::C140Clock,
+            chip::Chip::K053260 => VgmHeaderField::K053260Clock,
+            chip::Chip::Pokey => VgmHeaderField::PokeyClock,
+            chip::Chip::Qsound => VgmHeaderField::QsoundClock,
+            chip::Chip::Scsp => VgmHeaderField::ScspClock,
+            chip::Chip::WonderSwan => VgmHeaderField::WonderSwan,
+            chip::Chip::Vsu => VgmHeaderField::Vsu,
+            chip::Chip::Saa1099 => VgmHeaderField::Saa1099,
+            chip::Chip::Es5503 => VgmHeaderField::Es5503,
+            chip::Chip::Es5506U8 | chip::Chip::Es5506U16 => VgmHeaderField::Es5506,
+            chip::Chip::X1010 => VgmHeaderField::X1_010,
+            chip::Chip::C352 => VgmHeaderField::C352,
+            chip::Chip::Ga20 => VgmHeaderField::Ga20,
+            chip::Chip::Mikey => VgmHeaderField::Mikey,
+        }
+    }
+
     /// Returns the minimum VGM version that introduced this field.
     /// Fields not present in a version should be treated as zero.
     ///
@@ -799,7 +1051,7 @@ impl VgmHeaderField {
         let header_size = if data_offset == 0 {
             VgmHeader::fallback_header_size_for_version(header_version)
         } else {
-            0x34_usize.wrapping_add(data_offset as usize)
+            0x34_usize.saturating_add(data_offset as usize)
         };
 
         let off = self.offset();
@@ -812,6 +1064,13 @@ impl VgmHeaderField {
     }
 }
 
+/// Sample rate of VGM wait commands unless the header says otherwise.
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+/// Smallest `VgmHeader::sample_rate` taken as a sample rate by
+/// `VgmHeader::effective_sample_rate`.
+pub const MIN_HEADER_SAMPLE_RATE: u32 = 1_000;
+
 #[derive(Debug, Clone, PartialEq)]
 /// VGM file header fields and utilities for serialization.
 pub struct VgmHeader {
@@ -902,7 +1161,7 @@ impl Default for VgmHeader {
             total_samples: 0,
             loop_offset: 0,
             loop_samples: 0,
-            sample_rate: 44100,
+            sample_rate: DEFAULT_SAMPLE_RATE,
             sn76489_feedback: Sn76489Feedback::Unknown(0),
             sn76489_shift_register_width: Sn76489ShiftRegisterWidth::Unknown(0),
             sn76489_flags: Sn76489Flags {
@@ -1405,7 +1664,7 @@ impl VgmHeader {
         let header_size = if data_offset == 0 {
             VgmHeader::fallback_header_size_for_version(self.version)
         } else {
-            0x34_usize.wrapping_add(data_offset as usize)
+            0x34_usize.saturating_add(data_offset as usize)
         };
         if header_size < buf.len() {
             buf.truncate(header_size);
@@ -1413,6 +1672,13 @@ impl VgmHeader {
         buf
     }
 
+    /// The SegaPCM interface register decoded. Write it back with
+    /// `spcm_interface = u32::from(interface)`, which restores the stored
+    /// value exactly.
+    pub fn sega_pcm_interface(&self) -> SegaPcmInterface {
+        SegaPcmInterface::from(self.spcm_interface)
+    }
+
     /// Get the raw stored clock field for a chip `ch`.
     ///
     /// Returns the raw clock value from the header, including the high bit
@@ -1603,6 +1869,11 @@ impl VgmHeader {
         ChipInstances(out)
     }
 
+    /// Returns a `ChipInstancesMut` editing the chip clocks of this header.
+    pub fn chip_instances_mut(&mut self) -> ChipInstancesMut<'_> {
+        ChipInstancesMut { header: self }
+    }
+
     /// Return the legacy header size to use when a stored `data_offset` is 0
     /// (older VGM versions omitted the data_offset field or used smaller
     /// headers). The returned size is the total header size in bytes and
@@ -1673,8 +1944,7 @@ impl VgmHeader {
                 // DataOffset + 0x0C = 0x40 (64 bytes)
                 (0x40usize - VgmHeaderField::DataOffset.offset()) as u32
             } else {
-                (version_header_size as u32)
-                    .wrapping_sub(VgmHeaderField::DataOffset.offset() as u32)
+                (version_header_size - VgmHeaderField::DataOffset.offset()) as u32
             }
         } else {
             data_offset
@@ -1690,7 +1960,7 @@ impl VgmHeader {
         } else {
             VgmHeaderField::DataOffset
                 .offset()
-                .wrapping_add(data_offset as usize)
+                .saturating_add(data_offset as usize)
         }
     }
 
@@ -1701,6 +1971,20 @@ impl VgmHeader {
         Self::total_header_size(version, data_offset)
     }
 
+    /// Sample rate that wait commands are counted in.
+    ///
+    /// Returns `sample_rate` when it is at least `MIN_HEADER_SAMPLE_RATE`, and
+    /// `DEFAULT_SAMPLE_RATE` (44100 Hz) otherwise: a zero field is unset, and
+    /// small values such as 50 or 60 are the playback rate that VGM 1.01+
+    /// files record at this offset, not a sample rate.
+    pub fn effective_sample_rate(&self) -> u32 {
+        if self.sample_rate >= MIN_HEADER_SAMPLE_RATE {
+            self.sample_rate
+        } else {
+            DEFAULT_SAMPLE_RATE
+        }
+    }
+
     /// Compute the loop restart position as a byte offset **relative to the
     /// start of the command region**, given the raw header fields and the total
     /// file length.
@@ -1749,11 +2033,9 @@ impl VgmHeader {
             return None;
         }
         let command_start = Self::command_start(version, data_offset);
-        let abs = VgmHeaderField::LoopOffset
-            .offset()
-            .wrapping_add(loop_offset as usize);
-        if abs >= command_start && abs < file_len {
-            Some(abs - command_start)
+        let abs = FileOffset::from_relative(VgmHeaderField::LoopOffset.offset(), loop_offset)?;
+        if abs.get() < file_len {
+            abs.offset_from(FileOffset::new(command_start))
         } else {
             None
         }
@@ -1986,7 +2268,9 @@ impl From<ChipId> for u8 {
 /// - additional data follows at offsets above
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct VgmExtraHeader {
-    /// Full extra header size (as stored on-disk)
+    /// Extra header size as stored on-disk. The specification defines it as
+    /// the size of the header fields (`0x0C`); some writers store the size
+    /// of the whole extra header instead. `0` lets `to_bytes` compute it.
     pub header_size: u32,
     /// Offset (relative to start of extra header) to chip clock list (0 if absent)
     pub chip_clock_offset: u32,
@@ -2224,53 +2508,87 @@ impl VgmExtraHeader {
     ///   \[4..8\]   chip_clock_offset (u32 LE, relative to buf\[4\])
     ///   \[8..12\]  chip_vol_offset   (u32 LE, relative to buf\[8\])
     ///   \[12..\]   chip_clock block (if any), then chip_volume block (if any)
+    ///
+    /// A parsed extra header is written back byte for byte: when
+    /// `chip_clock_offset` and `chip_vol_offset` place both blocks after the
+    /// header fields without overlapping, the blocks are written there (gaps
+    /// are zero-filled) and `header_size` is written as stored. A
+    /// `header_size` of `8` (v1.70 files without the chip volume offset) is
+    /// kept when there are no chip volumes. Otherwise the blocks are laid out
+    /// as above and `header_size` is the length of the produced buffer.
     pub fn to_bytes(&self) -> Vec<u8> {
-        // Reserve the 12-byte header area (three u32 fields).
-        let mut buf: Vec<u8> = vec![0u8; 12];
-
-        // ── Chip Clock block ─────────────────────────────────────────────────
-        // chip_clock_offset is relative to the field at buf[4].
-        let chip_clock_offset: u32 = if !self.chip_clocks.is_empty() {
-            // Block starts at current end of buf.
-            let block_start = buf.len();
+        let mut clocks: Vec<u8> = Vec::new();
+        if !self.chip_clocks.is_empty() {
             // count (1 byte)
-            buf.push(self.chip_clocks.len() as u8);
+            clocks.push(self.chip_clocks.len() as u8);
             // entries: chip_id (1 byte) + clock (4 bytes LE)
             for chip_clock in &self.chip_clocks {
-                buf.push(chip_clock.encoded_chip_id());
-                buf.extend_from_slice(&chip_clock.clock.to_le_bytes());
+                clocks.push(chip_clock.encoded_chip_id());
+                clocks.extend_from_slice(&chip_clock.clock.to_le_bytes());
             }
-            // Offset is relative to the field position (buf[4]).
-            (block_start - 4) as u32
-        } else {
-            0u32
-        };
-
-        // ── Chip Volume block ────────────────────────────────────────────────
-        // chip_vol_offset is relative to the field at buf[8].
-        let chip_vol_offset: u32 = if !self.chip_volumes.is_empty() {
-            // Block starts at current end of buf.
-            let block_start = buf.len();
+        }
+        let mut volumes: Vec<u8> = Vec::new();
+        if !self.chip_volumes.is_empty() {
             // count (1 byte)
-            buf.push(self.chip_volumes.len() as u8);
+            volumes.push(self.chip_volumes.len() as u8);
             // entries: chip_id (1 byte) + flags (1 byte) + volume (2 bytes LE)
             // Re-encode the relative flag into bit 15 of the volume word.
             for chip_vol in &self.chip_volumes {
-                buf.push(chip_vol.encoded_chip_id());
-                buf.push(chip_vol.encoded_flags());
-                buf.extend_from_slice(&chip_vol.encoded_volume().to_le_bytes());
+                volumes.push(chip_vol.encoded_chip_id());
+                volumes.push(chip_vol.encoded_flags());
+                volumes.extend_from_slice(&chip_vol.encoded_volume().to_le_bytes());
             }
-            // Offset is relative to the field position (buf[8]).
-            (block_start - 8) as u32
+        }
+
+        // Size of the header fields: v1.70 files may stop after the chip
+        // clock offset.
+        let fields: usize = if self.header_size == 8 && volumes.is_empty() {
+            8
         } else {
-            0u32
+            12
+        };
+        // Block start of a stored field-relative offset, `None` when absent
+        // or pointing into the header fields.
+        let stored = |offset: u32, field: usize| {
+            let start = field.checked_add(offset as usize)?;
+            (offset != 0 && start >= fields).then_some(start)
         };
+        let mut clock_at = stored(self.chip_clock_offset, 4).filter(|_| !clocks.is_empty());
+        let mut vol_at = stored(self.chip_vol_offset, 8).filter(|_| !volumes.is_empty());
+        let keep = (clocks.is_empty() || clock_at.is_some())
+            && (volumes.is_empty() || vol_at.is_some())
+            && match (clock_at, vol_at) {
+                (Some(c), Some(v)) => c + clocks.len() <= v || v + volumes.len() <= c,
+                _ => true,
+            };
+        if !keep {
+            clock_at = (!clocks.is_empty()).then_some(fields);
+            vol_at = (!volumes.is_empty()).then_some(fields + clocks.len());
+        }
+
+        let mut buf: Vec<u8> = vec![0u8; fields];
+        for (at, block) in [(clock_at, &clocks), (vol_at, &volumes)] {
+            if let Some(at) = at {
+                if buf.len() < at + block.len() {
+                    buf.resize(at + block.len(), 0);
+                }
+                buf[at..at + block.len()].copy_from_slice(block);
+            }
+        }
 
-        // Fill in the three header fields now that all block positions are known.
-        let header_size = buf.len() as u32;
+        // Fill in the header fields now that all block positions are known.
+        let header_size = if keep && self.header_size != 0 {
+            self.header_size
+        } else {
+            buf.len() as u32
+        };
         buf[0..4].copy_from_slice(&header_size.to_le_bytes());
-        buf[4..8].copy_from_slice(&chip_clock_offset.to_le_bytes());
-        buf[8..12].copy_from_slice(&chip_vol_offset.to_le_bytes());
+        let clock_offset = clock_at.map_or(0, |at| (at - 4) as u32);
+        buf[4..8].copy_from_slice(&clock_offset.to_le_bytes());
+        if fields == 12 {
+            let vol_offset = vol_at.map_or(0, |at| (at - 8) as u32);
+            buf[8..12].copy_from_slice(&vol_offset.to_le_bytes());
+        }
 
         buf
     }
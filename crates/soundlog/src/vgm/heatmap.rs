@@ -0,0 +1,211 @@
+//! Register write heatmaps.
+//!
+//! `register_heatmap` counts the writes to every register of every chip
+//! instance in a document, optionally split into fixed time windows. The
+//! counts show at a glance which features of a chip a sound driver uses:
+//! registers that are never written, registers written once at start-up
+//! and registers rewritten every frame.
+//!
+//! Writes are counted as they appear in the document. `0x8n` YM2612 DAC
+//! writes count as writes to register `0x2A`; writes generated by DAC streams
+//! are not counted. `ChipHeatmap::grid` lays the counts of one port out in
+//! rows of 16 registers for display.
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::chip::{Chip, Ym2612Spec};
+//! use soundlog::vgm::command::{Instance, WaitSamples};
+//! use soundlog::vgm::heatmap::{HeatmapOptions, register_heatmap};
+//!
+//! let mut builder = VgmBuilder::new();
+//! for value in [0x00, 0xF0, 0x00] {
+//!     builder.add_chip_write(Instance::Primary, Ym2612Spec { port: 0, register: 0x28, value });
+//!     builder.add_vgm_command(WaitSamples(735));
+//! }
+//! let doc = builder.finalize();
+//!
+//! let heatmap = register_heatmap(&doc, &HeatmapOptions::new().with_window(1470));
+//! let ym2612 = &heatmap.chips[0];
+//! assert_eq!(ym2612.chip, Chip::Ym2612);
+//! assert_eq!(ym2612.count(0, 0x28), 3);
+//! assert_eq!(ym2612.grid(0)[2][8], 3);
+//! assert_eq!(ym2612.windows.len(), 2);
+//! assert_eq!(ym2612.windows[1].get(&(0, 0x28)), Some(&1));
+//! ```
+use std::collections::BTreeMap;
+
+use crate::chip::Chip;
+use crate::vgm::VgmDocument;
+use crate::vgm::analysis::{Analysis, AnalysisContext, run_analyses};
+use crate::vgm::command::{Instance, VgmCommand};
+
+/// Options for `register_heatmap`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct HeatmapOptions {
+    /// Length of a time window in samples (44.1 kHz, at least 1). `None`
+    /// only counts the whole document.
+    pub window: Option<u64>,
+}
+
+impl HeatmapOptions {
+    /// Count the whole document only.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Additionally count per window of `samples` samples.
+    pub fn with_window(mut self, samples: u64) -> Self {
+        self.window = Some(samples);
+        self
+    }
+}
+
+/// Write counts of one chip instance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChipHeatmap {
+    pub chip: Chip,
+    pub instance: Instance,
+    /// Writes per `(port, register)` over the whole document.
+    pub counts: BTreeMap<(u8, u32), u64>,
+    /// Writes per `(port, register)` for every time window, starting at
+    /// sample 0. Empty when no window was requested.
+    pub windows: Vec<BTreeMap<(u8, u32), u64>>,
+}
+
+impl ChipHeatmap {
+    /// Writes to `register` on `port`.
+    pub fn count(&self, port: u8, register: u32) -> u64 {
+        self.counts.get(&(port, register)).copied().unwrap_or(0)
+    }
+
+    /// Writes to all registers.
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    /// Ports that were written, in ascending order.
+    pub fn ports(&self) -> Vec<u8> {
+        let mut ports: Vec<u8> = self.counts.keys().map(|&(port, _)| port).collect();
+        ports.dedup();
+        ports
+    }
+
+    /// Counts of `port` in rows of 16 registers: `grid(port)[row][column]` is
+    /// the count of register `row * 16 + column`. Rows go up to the highest
+    /// register written on the port.
+    pub fn grid(&self, port: u8) -> Vec<[u64; 16]> {
+        let highest = self
+            .counts
+            .range((port, 0)..=(port, u32::MAX))
+            .next_back()
+            .map(|(&(_, register), _)| register);
+        let Some(highest) = highest else {
+            return Vec::new();
+        };
+        let mut grid = vec![[0; 16]; highest as usize / 16 + 1];
+        for (&(_, register), &count) in self.counts.range((port, 0)..=(port, u32::MAX)) {
+            grid[register as usize / 16][register as usize % 16] = count;
+        }
+        grid
+    }
+}
+
+/// Result of `register_heatmap`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RegisterHeatmap {
+    /// One entry per written chip instance, in order of the first write.
+    pub chips: Vec<ChipHeatmap>,
+    /// The window length used, see `HeatmapOptions::window`.
+    pub window: Option<u64>,
+    /// Length of the document in samples.
+    pub samples: u64,
+}
+
+/// Count the register writes of `document` per chip instance.
+pub fn register_heatmap(document: &VgmDocument, options: &HeatmapOptions) -> RegisterHeatmap {
+    let mut analysis = HeatmapAnalysis::new(options);
+    run_analyses(document, &mut [&mut analysis]);
+    analysis.into_heatmap()
+}
+
+/// `register_heatmap` as an `Analysis`, to share a pass with other analyses.
+#[derive(Debug, Clone)]
+pub struct HeatmapAnalysis {
+    heatmap: RegisterHeatmap,
+}
+
+impl HeatmapAnalysis {
+    pub fn new(options: &HeatmapOptions) -> Self {
+        HeatmapAnalysis {
+            heatmap: RegisterHeatmap {
+                window: options.window.map(|samples| samples.max(1)),
+                ..RegisterHeatmap::default()
+            },
+        }
+    }
+
+    /// The counts, complete once the analysis has run.
+    pub fn into_heatmap(self) -> RegisterHeatmap {
+        self.heatmap
+    }
+}
+
+impl Analysis for HeatmapAnalysis {
+    fn command(&mut self, context: &AnalysisContext<'_>, command: &VgmCommand) {
+        let write = match command {
+            VgmCommand::YM2612Port0Address2AWriteAndWaitN(_) => {
+                Some((Chip::Ym2612, Instance::Primary, 0, 0x2A))
+            }
+            _ => context.write().map(|write| {
+                (
+                    write.chip.clone(),
+                    write.instance,
+                    write.port,
+                    write.register,
+                )
+            }),
+        };
+        let Some((chip, instance, port, register)) = write else {
+            return;
+        };
+        let heatmap = &mut self.heatmap;
+        let index = match heatmap
+            .chips
+            .iter()
+            .position(|c| c.chip == chip && c.instance == instance)
+        {
+            Some(index) => index,
+            None => {
+                heatmap.chips.push(ChipHeatmap {
+                    chip,
+                    instance,
+                    counts: BTreeMap::new(),
+                    windows: Vec::new(),
+                });
+                heatmap.chips.len() - 1
+            }
+        };
+        let chip = &mut heatmap.chips[index];
+        *chip.counts.entry((port, register)).or_insert(0) += 1;
+        if let Some(window) = heatmap.window {
+            let window = (context.time() / window) as usize;
+            if chip.windows.len() <= window {
+                chip.windows.resize(window + 1, BTreeMap::new());
+            }
+            *chip.windows[window].entry((port, register)).or_insert(0) += 1;
+        }
+    }
+
+    fn finish(&mut self, context: &AnalysisContext<'_>) {
+        let heatmap = &mut self.heatmap;
+        heatmap.samples = context.time();
+        // Every chip gets the same number of windows.
+        if let Some(window) = heatmap.window {
+            let windows = heatmap.samples.div_ceil(window).max(1) as usize;
+            for chip in &mut heatmap.chips {
+                chip.windows
+                    .resize(windows.max(chip.windows.len()), BTreeMap::new());
+            }
+        }
+    }
+}
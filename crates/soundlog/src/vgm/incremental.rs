@@ -0,0 +1,201 @@
+//! Incremental re-serialization.
+//!
+//! Editing the GD3 tags or a few commands of a large file and saving it with
+//! `Vec::<u8>::from(&document)` serializes every command again, including
+//! all data blocks. `serialize_incremental` copies the commands that did not
+//! change from the bytes the document was loaded from instead: the leading
+//! and trailing commands equal to the original ones are taken from the
+//! original buffer at the ranges given by its `VgmDocument::sourcemap`, and
+//! only the commands in between are serialized. The header, extra header and
+//! GD3 chunk are always written anew.
+//!
+//! The sourcemap must be the one of `original`, which must be the document
+//! parsed from (or serialized to) `original_bytes`. The commands at the
+//! boundaries of the copied ranges are checked against the buffer; when the
+//! buffer does not match, the commands are serialized as usual. Only those
+//! boundaries are checked, so the copied ranges keep whatever the original
+//! file has in between. For files written by this crate that is the same as
+//! serializing them again, and the result equals `Vec::<u8>::from(&document)`;
+//! a file from another tool may encode some of the copied commands
+//! differently or have bytes between them, which are kept as they are.
+//!
+//! Tag edits can avoid even that: `gd3_in_place` lays out the new GD3 chunk
+//! over the existing one when it is not larger, padding the rest of the
+//! chunk with zeros, so only those bytes of the file have to be written.
+//! The chunk keeps its length field and the file keeps its size, so no
+//! header offset changes.
+//!
+//! ```rust
+//! use soundlog::{VgmBuilder, VgmDocument};
+//! use soundlog::chip::PsgSpec;
+//! use soundlog::meta::Gd3;
+//! use soundlog::vgm::command::{Instance, WaitSamples};
+//! use soundlog::vgm::incremental::{rewrite_gd3_in_place, serialize_incremental};
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x90 });
+//! builder.add_vgm_command(WaitSamples(735));
+//! let bytes: Vec<u8> = builder.finalize().into();
+//!
+//! let original = VgmDocument::try_from(&bytes[..]).unwrap();
+//! let sourcemap = original.sourcemap();
+//! let mut edited = original.clone();
+//! edited.gd3 = Some(Gd3 {
+//!     track_name_en: Some("Title".to_string()),
+//!     ..Gd3::default()
+//! });
+//! edited.header.eof_offset = 0;
+//!
+//! let (out, report) = serialize_incremental(&edited, &original, &bytes, &sourcemap);
+//! assert_eq!(report.reused_commands, 3);
+//! assert_eq!(report.serialized_commands, 0);
+//! assert_eq!(out, Vec::<u8>::from(&edited));
+//!
+//! // A shorter title fits in the chunk written above.
+//! let mut patched = out.clone();
+//! let shorter = Gd3 {
+//!     track_name_en: Some("T".to_string()),
+//!     ..Gd3::default()
+//! };
+//! assert!(rewrite_gd3_in_place(&mut patched, &shorter).unwrap());
+//! assert_eq!(patched.len(), out.len());
+//! let reparsed = VgmDocument::try_from(&patched[..]).unwrap();
+//! assert_eq!(reparsed.gd3, Some(shorter));
+//! ```
+use crate::binutil::{ParseError, read_slice, read_u32_le_at};
+use crate::meta::Gd3;
+use crate::vgm::VgmDocument;
+use crate::vgm::command::command_to_vgm_bytes;
+use crate::vgm::header::VgmHeaderField;
+
+/// Result of `serialize_incremental`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct IncrementalReport {
+    /// Commands copied from the original buffer.
+    pub reused_commands: usize,
+    /// Bytes copied from the original buffer.
+    pub reused_bytes: usize,
+    /// Commands serialized because they changed (or could not be copied).
+    pub serialized_commands: usize,
+}
+
+/// Serialize `document`, copying the commands it shares with `original`
+/// from `original_bytes`.
+pub fn serialize_incremental(
+    document: &VgmDocument,
+    original: &VgmDocument,
+    original_bytes: &[u8],
+    sourcemap: &[(usize, usize)],
+) -> (Vec<u8>, IncrementalReport) {
+    let commands = &document.commands;
+    let (mut prefix, mut suffix) = (0, 0);
+    if sourcemap.len() == original.commands.len() {
+        prefix = commands
+            .iter()
+            .zip(&original.commands)
+            .take_while(|(a, b)| a == b)
+            .count();
+        suffix = commands[prefix..]
+            .iter()
+            .rev()
+            .zip(original.commands[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+    }
+    let matches = |index: usize| {
+        let (offset, len) = sourcemap[index];
+        original_bytes
+            .get(offset..offset.saturating_add(len))
+            .is_some_and(|bytes| command_to_vgm_bytes(&original.commands[index]).0 == bytes)
+    };
+    if prefix > 0 && !(matches(0) && matches(prefix - 1)) {
+        prefix = 0;
+    }
+    let suffix_start = original.commands.len() - suffix;
+    if suffix > 0 && !(matches(suffix_start) && matches(original.commands.len() - 1)) {
+        suffix = 0;
+    }
+    let range = |first: usize, last: usize| {
+        let start = sourcemap[first].0;
+        let (offset, len) = sourcemap[last];
+        &original_bytes[start..offset + len]
+    };
+
+    let mut report = IncrementalReport::default();
+    let mut cmd_buf: Vec<u8> = Vec::new();
+    let mut serialized_bytes = 0;
+    if prefix > 0 {
+        cmd_buf.extend_from_slice(range(0, prefix - 1));
+    }
+    for command in &commands[prefix..commands.len() - suffix] {
+        let (bytes, len) = command_to_vgm_bytes(command);
+        cmd_buf.extend_from_slice(&bytes);
+        serialized_bytes += len;
+        report.serialized_commands += 1;
+    }
+    if suffix > 0 {
+        cmd_buf.extend_from_slice(range(suffix_start, original.commands.len() - 1));
+    }
+    report.reused_commands = prefix + suffix;
+    report.reused_bytes = cmd_buf.len() - serialized_bytes;
+    (document.to_bytes_with_commands(&cmd_buf), report)
+}
+
+/// Replacement bytes for the GD3 chunk of a file, see `gd3_in_place`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gd3Patch {
+    /// File offset of the GD3 chunk.
+    pub offset: usize,
+    /// The new chunk, exactly as long as the one it replaces.
+    pub bytes: Vec<u8>,
+}
+
+/// Lay out `gd3` over the GD3 chunk of the VGM file `bytes`.
+///
+/// Returns `None` when the file has no GD3 chunk or the new chunk is larger
+/// than the existing one; the file has to be serialized again then.
+///
+/// # Errors
+/// Returns a `ParseError` when the header is too short or its GD3 offset does
+/// not point to a GD3 chunk inside `bytes`.
+pub fn gd3_in_place(bytes: &[u8], gd3: &Gd3) -> Result<Option<Gd3Patch>, ParseError> {
+    let field = VgmHeaderField::Gd3Offset.offset();
+    let gd3_offset = read_u32_le_at(bytes, field)?;
+    if gd3_offset == 0 {
+        return Ok(None);
+    }
+    let offset = (gd3_offset as usize).saturating_add(field);
+    let ident = read_slice(bytes, offset, 4)?;
+    if ident != b"Gd3 " {
+        let mut id: [u8; 4] = [0; 4];
+        id.copy_from_slice(ident);
+        return Err(ParseError::InvalidIdent(id));
+    }
+    let reserved = read_u32_le_at(bytes, offset + 8)? as usize;
+    read_slice(bytes, offset + 12, reserved)?;
+
+    let mut chunk = gd3.to_bytes();
+    if chunk.len() > 12 + reserved {
+        return Ok(None);
+    }
+    chunk.resize(12 + reserved, 0);
+    chunk[8..12].copy_from_slice(&(reserved as u32).to_le_bytes());
+    Ok(Some(Gd3Patch {
+        offset,
+        bytes: chunk,
+    }))
+}
+
+/// Rewrite the GD3 chunk of `bytes` with `gd3` when it fits, see
+/// `gd3_in_place`. Returns `false`, leaving `bytes` untouched, when it does
+/// not.
+///
+/// # Errors
+/// See `gd3_in_place`.
+pub fn rewrite_gd3_in_place(bytes: &mut [u8], gd3: &Gd3) -> Result<bool, ParseError> {
+    let Some(patch) = gd3_in_place(bytes, gd3)? else {
+        return Ok(false);
+    };
+    bytes[patch.offset..patch.offset + patch.bytes.len()].copy_from_slice(&patch.bytes);
+    Ok(true)
+}
@@ -0,0 +1,1127 @@
+//! Song intermediate representation for cross-chip porting.
+//!
+//! A `SongIr` describes what a song plays rather than how a chip was
+//! programmed: for every channel the notes with their pitch, FM patch and
+//! volume envelope, plus the PCM events of the song. Porting a track from
+//! one chip to another goes through it in two steps:
+//!
+//! - `SongIrAnalysis` builds the IR in a `run_analyses` pass (or in one call
+//!   with `SongIr::from_document`). Notes follow the key states of the
+//!   `chip::state` trackers; their pitch and volume are sampled after every
+//!   write to the chip, so vibrato, slides and software envelopes are kept.
+//! - `compose` writes the IR back as register writes of a target chip,
+//!   allocating its channels to the notes as they play.
+//!
+//! Times are in samples (44.1 kHz). Volumes are attenuations in dB, `0.0`
+//! being the loudest and `f32::INFINITY` silence. FM patches are read from
+//! the FM channels of the OPN family (YM2612, YM2203, YM2608, YM2610B) and
+//! stored with their loudest carrier at total level 0; the carrier level is
+//! part of the envelope instead, so the same voice played at different
+//! volumes is one patch. Volumes are read from OPN carriers, SN76489
+//! attenuation and AY-3-8910 style amplitude registers, SSG channels of the
+//! OPN family included (the hardware envelope counts as full volume); notes
+//! of other chips have an
+//! empty envelope. PCM events are the DAC stream, data bank seek and YM2612
+//! DAC commands of the song; they can only be played back by the chip that
+//! played them.
+//!
+//! The conversion is lossy: the IR has no chip-specific effects (LFO, noise
+//! modes, CSM, hardware envelopes), `compose` drops notes when the target
+//! has no free channel and `ComposeReport` counts what was left out,
+//! including the patch features the target chip has no equivalent for.
+//! Targets are YM2612, YM2151, the OPL chips (YM3812, YM3526, Y8950) and
+//! SN76489.
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::chip::{Chip, PsgSpec};
+//! use soundlog::vgm::command::{Instance, VgmCommand, WaitSamples};
+//! use soundlog::vgm::ir::{ComposeOptions, SongIr, compose};
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+//! // channel 0 tone period 0x0FE at full volume for one frame
+//! builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x8E });
+//! builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x0F });
+//! builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x90 });
+//! builder.add_vgm_command(WaitSamples(735));
+//! builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x9F });
+//! let doc = builder.finalize();
+//!
+//! let ir = SongIr::from_document(&doc);
+//! let note = &ir.channels[0].notes[0];
+//! assert_eq!((note.start, note.end), (0, 735));
+//! assert!((note.pitch[0].value - 440.4).abs() < 0.1);
+//!
+//! let (ported, report) = compose(&ir, &ComposeOptions::new(Chip::Ym2612, 7_670_454)).unwrap();
+//! assert_eq!(report.dropped_notes, 0);
+//! assert_eq!(ported.header.total_samples, 735);
+//! assert!(ported.iter().any(|cmd| matches!(cmd, VgmCommand::Ym2612Write(..))));
+//! ```
+use std::collections::{BTreeMap, HashMap};
+
+use crate::chip::Chip;
+use crate::chip::fnumber::{ChipTypeSpec, Opl2Spec, OpnaSpec};
+use crate::chip::patch::{FM_CARRIERS, FmOperator, FmPatch, OPM_SLOT_OFFSETS, OPN_SLOT_OFFSETS};
+use crate::chip::regmap::Registers;
+use crate::vgm::analysis::{Analysis, AnalysisContext, run_analyses};
+use crate::vgm::command::{
+    DataBlock, Instance, RegisterWrite, StreamId, VgmCommand, Ym2612Port0Address2AWriteAndWaitN,
+};
+use crate::vgm::lint::chip_of;
+use crate::vgm::transform::push_wait;
+use crate::vgm::{VgmBuilder, VgmDocument};
+
+// Attenuation of one YM2612 total level, SN76489 attenuation and AY-3-8910
+// amplitude step in dB.
+const TL_STEP_DB: f32 = 0.75;
+const PSG_STEP_DB: f32 = 2.0;
+const SSG_STEP_DB: f32 = 3.0;
+
+/// A song as notes, patches, envelopes and PCM events.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SongIr {
+    /// Length of the song in samples.
+    pub length: u64,
+    /// Time of the loop point in samples.
+    pub loop_start: Option<u64>,
+    /// FM patches referenced by `IrNote::patch`.
+    pub patches: Vec<FmPatch>,
+    /// Channels that played at least one note, in order of their first note.
+    pub channels: Vec<IrChannel>,
+    /// PCM events in time order.
+    pub pcm: Vec<IrPcmEvent>,
+    /// Data blocks of the song, referenced by the PCM events.
+    pub data_blocks: Vec<DataBlock>,
+}
+
+/// The notes played on one channel of the source document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IrChannel {
+    pub chip: Chip,
+    pub instance: Instance,
+    /// Channel number as used by the chip's state tracker.
+    pub channel: u8,
+    pub notes: Vec<IrNote>,
+}
+
+/// A note from key-on to key-off.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IrNote {
+    /// Time of the key-on in samples.
+    pub start: u64,
+    /// Time of the key-off in samples, the song length when the note is
+    /// still sounding at the end.
+    pub end: u64,
+    /// Pitch in Hz, first at `start`, then at every change. Empty when the
+    /// tracker could not compute it (noise channels, unknown clock).
+    pub pitch: Vec<IrPoint>,
+    /// Index into `SongIr::patches`, `None` for notes without an FM patch.
+    pub patch: Option<usize>,
+    /// Attenuation in dB, first at `start`, then at every change. Empty when
+    /// the volume of the chip is not tracked.
+    pub envelope: Vec<IrPoint>,
+}
+
+/// A value of a note at a time in samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IrPoint {
+    pub time: u64,
+    pub value: f32,
+}
+
+/// A PCM command of the source document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IrPcmEvent {
+    /// Time in samples.
+    pub time: u64,
+    /// The chip that plays the sample.
+    pub chip: Chip,
+    pub instance: Instance,
+    /// The command; `0x8n` YM2612 DAC writes are stored without their wait.
+    pub command: VgmCommand,
+}
+
+impl SongIr {
+    /// Build the IR of `document` in one pass.
+    pub fn from_document(document: &VgmDocument) -> SongIr {
+        let mut analysis = SongIrAnalysis::new();
+        run_analyses(document, &mut [&mut analysis]);
+        analysis.into_ir()
+    }
+}
+
+/// Builds a `SongIr` in a `run_analyses` pass.
+#[derive(Debug, Default)]
+pub struct SongIrAnalysis {
+    ir: SongIr,
+    registers: HashMap<(Chip, Instance), Registers>,
+    // Latched SN76489 register per instance: channel and volume flag.
+    psg_latch: [(u8, bool); 2],
+    // (chip, instance, channel) of every sounding note to its channel and
+    // note index.
+    open: HashMap<(Chip, Instance, u8), (usize, usize)>,
+    streams: HashMap<StreamId, (Chip, Instance)>,
+    // Loop command index of the document, looked up at the first command.
+    loop_index: Option<Option<usize>>,
+}
+
+impl SongIrAnalysis {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The collected IR.
+    pub fn into_ir(self) -> SongIr {
+        self.ir
+    }
+
+    fn update_registers(&mut self, write: &RegisterWrite) {
+        if write.chip == Chip::Sn76489 {
+            let latch = &mut self.psg_latch[usize::from(write.instance)];
+            let value = write.value as u8;
+            if value & 0x80 != 0 {
+                *latch = ((value >> 5) & 3, value & 0x10 != 0);
+            }
+            if latch.1 {
+                self.registers
+                    .entry((Chip::Sn76489, write.instance))
+                    .or_default()
+                    .insert((0, latch.0 as u32), (value & 0x0F) as u32);
+            }
+            return;
+        }
+        self.registers
+            .entry((write.chip.clone(), write.instance))
+            .or_default()
+            .insert((write.port, write.register), write.value);
+    }
+
+    // Attenuation of a channel in dB, `None` when its volume is not tracked.
+    fn level(&self, chip: &Chip, instance: Instance, channel: u8) -> Option<f32> {
+        let registers = self.registers.get(&(chip.clone(), instance));
+        let read = |port: u8, register: u32| {
+            registers
+                .and_then(|r| r.get(&(port, register)))
+                .copied()
+                .unwrap_or(0) as u8
+        };
+        match chip {
+            chip if channel < fm_channels(chip) => {
+                let patch = FmPatch::from_registers(registers?, channel);
+                Some(carrier_level(&patch) as f32 * TL_STEP_DB)
+            }
+            Chip::Ym2203 | Chip::Ym2608 | Chip::Ym2610b if channel < fm_channels(chip) + 3 => {
+                let amplitude = read(0, 0x08 + (channel - fm_channels(chip)) as u32);
+                Some(ssg_level(amplitude))
+            }
+            Chip::Sn76489 if channel < 4 => {
+                let attenuation = registers?.get(&(0, channel as u32)).copied()?;
+                Some(match attenuation {
+                    15 => f32::INFINITY,
+                    a => a as f32 * PSG_STEP_DB,
+                })
+            }
+            Chip::Ay8910 if channel < 3 => Some(ssg_level(read(0, 0x08 + channel as u32))),
+            _ => None,
+        }
+    }
+
+    // The patch of an OPN family FM channel, stored in `ir.patches` once.
+    fn patch(&mut self, chip: &Chip, instance: Instance, channel: u8) -> Option<usize> {
+        if channel >= fm_channels(chip) {
+            return None;
+        }
+        let registers = self.registers.get(&(chip.clone(), instance))?;
+        let mut patch = FmPatch::from_registers(registers, channel);
+        let level = carrier_level(&patch);
+        for slot in carriers(&patch).collect::<Vec<_>>() {
+            let tl = &mut patch.operators[slot].tl;
+            *tl = (*tl & 0x7F) - level;
+        }
+        Some(
+            match self.ir.patches.iter().position(|known| *known == patch) {
+                Some(index) => index,
+                None => {
+                    self.ir.patches.push(patch);
+                    self.ir.patches.len() - 1
+                }
+            },
+        )
+    }
+
+    fn pcm_event(&mut self, time: u64, command: &VgmCommand) {
+        let target = match command {
+            VgmCommand::SetupStreamControl(setup) => {
+                let Some(chip) = chip_of(setup.chip_type.chip_id) else {
+                    return;
+                };
+                self.streams
+                    .insert(setup.stream_id, (chip, setup.chip_type.instance));
+                self.streams.get(&setup.stream_id).cloned()
+            }
+            VgmCommand::SetStreamData(s) => self.streams.get(&s.stream_id).cloned(),
+            VgmCommand::SetStreamFrequency(s) => self.streams.get(&s.stream_id).cloned(),
+            VgmCommand::StartStream(s) => self.streams.get(&s.stream_id).cloned(),
+            VgmCommand::StopStream(s) => self.streams.get(&s.stream_id).cloned(),
+            VgmCommand::StartStreamFastCall(s) => self.streams.get(&s.stream_id).cloned(),
+            VgmCommand::SeekOffset(_) | VgmCommand::YM2612Port0Address2AWriteAndWaitN(_) => {
+                Some((Chip::Ym2612, Instance::Primary))
+            }
+            VgmCommand::Ym2612Write(instance, write)
+                if write.port == 0 && matches!(write.register, 0x2A | 0x2B) =>
+            {
+                Some((Chip::Ym2612, *instance))
+            }
+            _ => None,
+        };
+        let Some((chip, instance)) = target else {
+            return;
+        };
+        let command = match command {
+            VgmCommand::YM2612Port0Address2AWriteAndWaitN(_) => {
+                Ym2612Port0Address2AWriteAndWaitN(0).into()
+            }
+            command => command.clone(),
+        };
+        self.ir.pcm.push(IrPcmEvent {
+            time,
+            chip,
+            instance,
+            command,
+        });
+    }
+}
+
+impl Analysis for SongIrAnalysis {
+    fn needs_keys(&self) -> bool {
+        true
+    }
+
+    fn command(&mut self, context: &AnalysisContext<'_>, command: &VgmCommand) {
+        let time = context.time();
+        let loop_index = *self
+            .loop_index
+            .get_or_insert_with(|| context.document().loop_command_index());
+        if loop_index == Some(context.index()) {
+            self.ir.loop_start = Some(time);
+        }
+        if let VgmCommand::DataBlock(block) = command {
+            self.ir.data_blocks.push((**block).clone());
+            return;
+        }
+        self.pcm_event(time, command);
+        let Some(write) = context.write() else {
+            return;
+        };
+        if matches!(command, VgmCommand::GameGearPsgWrite(..)) {
+            return;
+        }
+        self.update_registers(write);
+
+        for change in context.key_changes() {
+            let key = (change.chip.clone(), change.instance, change.channel);
+            if let Some((channel, note)) = self.open.remove(&key) {
+                self.ir.channels[channel].notes[note].end = time;
+            }
+            if !change.on {
+                continue;
+            }
+            let channel = match self.ir.channels.iter().position(|c| {
+                c.chip == change.chip
+                    && c.instance == change.instance
+                    && c.channel == change.channel
+            }) {
+                Some(channel) => channel,
+                None => {
+                    self.ir.channels.push(IrChannel {
+                        chip: change.chip.clone(),
+                        instance: change.instance,
+                        channel: change.channel,
+                        notes: Vec::new(),
+                    });
+                    self.ir.channels.len() - 1
+                }
+            };
+            let patch = self.patch(&change.chip, change.instance, change.channel);
+            let notes = &mut self.ir.channels[channel].notes;
+            notes.push(IrNote {
+                start: time,
+                end: time,
+                pitch: Vec::new(),
+                patch,
+                envelope: Vec::new(),
+            });
+            self.open.insert(key, (channel, notes.len() - 1));
+        }
+
+        // Sample pitch and volume of the sounding notes of the written chip.
+        let open: Vec<_> = self
+            .open
+            .iter()
+            .filter(|((chip, instance, _), _)| *chip == write.chip && *instance == write.instance)
+            .map(|((chip, instance, number), &index)| (chip.clone(), *instance, *number, index))
+            .collect();
+        for (chip, instance, number, (channel, note)) in open {
+            let frequency = context
+                .tone(&chip, instance, number)
+                .and_then(|tone| tone.freq_hz);
+            let level = self.level(&chip, instance, number);
+            let note = &mut self.ir.channels[channel].notes[note];
+            push_point(&mut note.pitch, time, frequency);
+            push_point(&mut note.envelope, time, level);
+        }
+    }
+
+    fn finish(&mut self, context: &AnalysisContext<'_>) {
+        self.ir.length = context.time();
+        for (_, (channel, note)) in self.open.drain() {
+            self.ir.channels[channel].notes[note].end = context.time();
+        }
+    }
+}
+
+// Append `value` at `time` when it differs from the last point, replacing a
+// point of the same time.
+fn push_point(points: &mut Vec<IrPoint>, time: u64, value: Option<f32>) {
+    let Some(value) = value else {
+        return;
+    };
+    match points.last_mut() {
+        Some(last) if last.value == value => {}
+        Some(last) if last.time == time => last.value = value,
+        _ => points.push(IrPoint { time, value }),
+    }
+}
+
+// Number of FM channels of an OPN family chip, 0 for other chips. The
+// trackers number the SSG channels after them.
+fn fm_channels(chip: &Chip) -> u8 {
+    match chip {
+        Chip::Ym2612 | Chip::Ym2608 | Chip::Ym2610b => 6,
+        Chip::Ym2203 => 3,
+        _ => 0,
+    }
+}
+
+// Attenuation of an AY-3-8910 style amplitude register.
+fn ssg_level(amplitude: u8) -> f32 {
+    match amplitude {
+        a if a & 0x10 != 0 => 0.0,
+        0 => f32::INFINITY,
+        a => (15 - (a & 0x0F)) as f32 * SSG_STEP_DB,
+    }
+}
+
+// Total level of the loudest carrier of a patch.
+fn carrier_level(patch: &FmPatch) -> u8 {
+    carriers(patch)
+        .map(|slot| patch.operators[slot].tl & 0x7F)
+        .min()
+        .unwrap_or(0x7F)
+}
+
+fn carriers(patch: &FmPatch) -> impl Iterator<Item = usize> {
+    let mask = FM_CARRIERS[patch.algorithm as usize & 7];
+    (0..4).filter(move |slot| mask & (1 << slot) != 0)
+}
+
+/// Target chip of `compose`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComposeOptions {
+    /// `Chip::Ym2612`, `Chip::Ym2151`, `Chip::Ym3812`, `Chip::Ym3526`,
+    /// `Chip::Y8950` or `Chip::Sn76489`.
+    pub chip: Chip,
+    /// Clock of the target chip in Hz.
+    pub clock: u32,
+}
+
+impl ComposeOptions {
+    pub fn new(chip: Chip, clock: u32) -> Self {
+        ComposeOptions { chip, clock }
+    }
+}
+
+/// What `compose` could not carry over.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ComposeReport {
+    /// Notes written to the target.
+    pub composed_notes: usize,
+    /// Notes dropped because no target channel was free, or their pitch is
+    /// unknown or out of the target's range.
+    pub dropped_notes: usize,
+    /// PCM events of chips other than the target.
+    pub dropped_pcm_events: usize,
+    /// Composed notes whose patch uses a feature the target cannot play,
+    /// per feature.
+    pub untranslated: BTreeMap<PatchFeature, usize>,
+}
+
+/// An FM patch feature that is lost when a patch is ported to another chip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PatchFeature {
+    /// SSG-EG envelopes (YM2151, OPL).
+    SsgEg,
+    /// Audible operators besides the two of an OPL channel (OPL).
+    Operators,
+    /// Feedback of an operator that is not the OPL modulator (OPL).
+    Feedback,
+    /// Operator detune (OPL).
+    Detune,
+    /// Sustain rate; OPL envelopes hold the sustain level or release from
+    /// it (OPL).
+    SustainRate,
+    /// LFO sensitivity, played at the fixed OPL vibrato and tremolo depths
+    /// (OPL).
+    LfoSensitivity,
+}
+
+// `(port, register, value)` writes of a target chip.
+type Writes = Vec<(u8, u32, u8)>;
+
+// Register writes of a target chip, per target channel.
+trait Target {
+    fn channels(&self) -> usize;
+    fn init(&self) -> Writes;
+    // `None` when the pitch is out of range.
+    fn key_on(
+        &mut self,
+        channel: usize,
+        frequency: f32,
+        attenuation: f32,
+        patch: Option<&FmPatch>,
+    ) -> Option<Writes>;
+    fn pitch(&mut self, channel: usize, frequency: f32) -> Writes;
+    fn level(&mut self, channel: usize, attenuation: f32) -> Writes;
+    fn key_off(&mut self, channel: usize) -> Writes;
+    // Features of `patch` that `key_on` leaves out.
+    fn untranslated(&self, _patch: &FmPatch) -> Vec<PatchFeature> {
+        Vec::new()
+    }
+}
+
+struct Opn2Target {
+    clock: f32,
+    patches: [Option<FmPatch>; 6],
+}
+
+impl Opn2Target {
+    // F-number and block of `frequency`, the lowest block that fits.
+    fn fnumber(&self, frequency: f32) -> Option<(u16, u8)> {
+        if frequency.is_nan() || frequency <= 0.0 {
+            return None;
+        }
+        (0..8).find_map(|block| {
+            let fnum = OpnaSpec::ideal_fnum_for_freq(frequency, block, self.clock).round();
+            (fnum <= 0x7FF as f32).then_some((fnum as u16, block))
+        })
+    }
+
+    fn frequency_writes(&self, channel: usize, frequency: f32) -> Option<Writes> {
+        let (fnum, block) = self.fnumber(frequency)?;
+        let (port, ch) = ((channel / 3) as u8, (channel % 3) as u32);
+        Some(vec![
+            (port, 0xA4 + ch, (block << 3) | (fnum >> 8) as u8),
+            (port, 0xA0 + ch, fnum as u8),
+        ])
+    }
+}
+
+impl Target for Opn2Target {
+    fn channels(&self) -> usize {
+        6
+    }
+
+    fn init(&self) -> Writes {
+        // The DAC is enabled by the PCM events.
+        vec![(0, 0x22, 0x00), (0, 0x27, 0x00)]
+    }
+
+    fn key_on(
+        &mut self,
+        channel: usize,
+        frequency: f32,
+        attenuation: f32,
+        patch: Option<&FmPatch>,
+    ) -> Option<Writes> {
+        let mut writes = Vec::new();
+        let frequency = self.frequency_writes(channel, frequency)?;
+        let patch = patch.copied().unwrap_or_default();
+        if self.patches[channel] != Some(patch) {
+            let (port, ch) = ((channel / 3) as u8, (channel % 3) as u32);
+            for (op, offset) in patch.operators.iter().zip(OPN_SLOT_OFFSETS.map(u32::from)) {
+                let base = ch + offset;
+                writes.extend([
+                    (port, 0x30 + base, op.dt_mul),
+                    (port, 0x50 + base, op.ks_ar),
+                    (port, 0x60 + base, op.am_dr),
+                    (port, 0x70 + base, op.sr),
+                    (port, 0x80 + base, op.sl_rr),
+                    (port, 0x90 + base, op.ssg_eg),
+                    (port, 0x40 + base, op.tl),
+                ]);
+            }
+            writes.push((port, 0xB0 + ch, patch.feedback_algorithm()));
+            writes.push((port, 0xB4 + ch, 0xC0 | patch.lfo_sensitivity()));
+            self.patches[channel] = Some(patch);
+        }
+        writes.extend(self.level(channel, attenuation));
+        writes.extend(frequency);
+        let code = if channel < 3 { channel } else { channel + 1 } as u8;
+        writes.push((0, 0x28, 0xF0 | code));
+        Some(writes)
+    }
+
+    fn pitch(&mut self, channel: usize, frequency: f32) -> Writes {
+        self.frequency_writes(channel, frequency)
+            .unwrap_or_default()
+    }
+
+    fn level(&mut self, channel: usize, attenuation: f32) -> Writes {
+        let Some(patch) = self.patches[channel] else {
+            return Vec::new();
+        };
+        let (port, ch) = ((channel / 3) as u8, (channel % 3) as u32);
+        let extra = (attenuation / TL_STEP_DB).round().min(127.0) as u8;
+        carriers(&patch)
+            .map(|slot| {
+                let tl = patch.operators[slot].tl.saturating_add(extra).min(0x7F);
+                (port, 0x40 + ch + u32::from(OPN_SLOT_OFFSETS[slot]), tl)
+            })
+            .collect()
+    }
+
+    fn key_off(&mut self, channel: usize) -> Writes {
+        let code = if channel < 3 { channel } else { channel + 1 } as u8;
+        vec![(0, 0x28, code)]
+    }
+}
+
+struct OpmTarget {
+    clock: f32,
+    patches: [Option<FmPatch>; 8],
+}
+
+impl OpmTarget {
+    // Key code and key fraction of `frequency`: octave and note (C# to C,
+    // skipping every fourth code) of the semitones from C#0.
+    fn key_code(&self, frequency: f32) -> Option<(u8, u8)> {
+        const NOTES: [u8; 12] = [0, 1, 2, 4, 5, 6, 8, 9, 10, 12, 13, 14];
+        // KC 0x4A is A4 at 3.579545 MHz.
+        let semitones = 12.0 * (frequency * 3_579_545.0 / (440.0 * self.clock)).log2() + 56.0;
+        if semitones.is_nan() || !(0.0..96.0).contains(&semitones) {
+            return None;
+        }
+        let note = semitones.floor() as usize;
+        let fraction = ((semitones - note as f32) * 64.0) as u8;
+        Some((((note / 12) as u8) << 4 | NOTES[note % 12], fraction << 2))
+    }
+}
+
+impl Target for OpmTarget {
+    fn channels(&self) -> usize {
+        8
+    }
+
+    fn init(&self) -> Writes {
+        // LFO and noise off.
+        vec![
+            (0, 0x0F, 0x00),
+            (0, 0x18, 0x00),
+            (0, 0x19, 0x00),
+            (0, 0x19, 0x80),
+            (0, 0x1B, 0x00),
+        ]
+    }
+
+    fn key_on(
+        &mut self,
+        channel: usize,
+        frequency: f32,
+        attenuation: f32,
+        patch: Option<&FmPatch>,
+    ) -> Option<Writes> {
+        let mut writes = Vec::new();
+        self.key_code(frequency)?;
+        let patch = patch.copied().unwrap_or_default();
+        if self.patches[channel] != Some(patch) {
+            let ch = channel as u32;
+            for (op, offset) in patch.operators.iter().zip(OPM_SLOT_OFFSETS.map(u32::from)) {
+                let base = ch + offset;
+                writes.extend([
+                    (0, 0x40 + base, op.dt_mul & 0x7F),
+                    (0, 0x80 + base, op.ks_ar & 0xDF),
+                    (0, 0xA0 + base, op.am_dr & 0x9F),
+                    (0, 0xC0 + base, op.sr & 0x1F),
+                    (0, 0xE0 + base, op.sl_rr),
+                    (0, 0x60 + base, op.tl & 0x7F),
+                ]);
+            }
+            writes.push((0, 0x20 + ch, 0xC0 | patch.feedback_algorithm()));
+            writes.push((0, 0x38 + ch, (patch.fms & 7) << 4 | patch.ams & 3));
+            self.patches[channel] = Some(patch);
+        }
+        writes.extend(self.level(channel, attenuation));
+        writes.extend(self.pitch(channel, frequency));
+        writes.push((0, 0x08, 0x78 | channel as u8));
+        Some(writes)
+    }
+
+    fn pitch(&mut self, channel: usize, frequency: f32) -> Writes {
+        let Some((code, fraction)) = self.key_code(frequency) else {
+            return Vec::new();
+        };
+        let ch = channel as u32;
+        vec![(0, 0x28 + ch, code), (0, 0x30 + ch, fraction)]
+    }
+
+    fn level(&mut self, channel: usize, attenuation: f32) -> Writes {
+        let Some(patch) = self.patches[channel] else {
+            return Vec::new();
+        };
+        let extra = (attenuation / TL_STEP_DB).round().min(127.0) as u8;
+        carriers(&patch)
+            .map(|slot| {
+                let tl = patch.operators[slot].tl.saturating_add(extra).min(0x7F);
+                let offset = u32::from(OPM_SLOT_OFFSETS[slot]);
+                (0, 0x60 + channel as u32 + offset, tl)
+            })
+            .collect()
+    }
+
+    fn key_off(&mut self, channel: usize) -> Writes {
+        vec![(0, 0x08, channel as u8)]
+    }
+
+    fn untranslated(&self, patch: &FmPatch) -> Vec<PatchFeature> {
+        let ssg_eg = patch.operators.iter().any(|op| op.ssg_eg & 0x08 != 0);
+        ssg_eg.then_some(PatchFeature::SsgEg).into_iter().collect()
+    }
+}
+
+// Modulator and carrier slot an OPL channel plays of each OPN algorithm,
+// and whether the algorithm adds them instead of modulating the carrier.
+const OPL_PAIRS: [(usize, usize, bool); 8] = [
+    (2, 3, false),
+    (2, 3, false),
+    (0, 3, false),
+    (1, 3, false),
+    (0, 1, false),
+    (0, 3, false),
+    (0, 1, false),
+    (0, 3, true),
+];
+
+// Modulator operator register offsets of the nine OPL channels; the carrier
+// is 3 above.
+const OPL_OPERATOR_OFFSETS: [u32; 9] = [0x00, 0x01, 0x02, 0x08, 0x09, 0x0A, 0x10, 0x11, 0x12];
+
+// OPL channel (YM3812, YM3526, Y8950) playing two operators of each patch.
+struct OplTarget {
+    clock: f32,
+    patches: [Option<FmPatch>; 9],
+    // Register 0xB0 of each channel without the key bit.
+    frequencies: [u8; 9],
+    keyed: [bool; 9],
+}
+
+impl OplTarget {
+    // F-number and block of `frequency`, the lowest block that fits.
+    fn fnumber(&self, frequency: f32) -> Option<(u16, u8)> {
+        if frequency.is_nan() || frequency <= 0.0 {
+            return None;
+        }
+        (0..8).find_map(|block| {
+            let fnum = Opl2Spec::ideal_fnum_for_freq(frequency, block, self.clock).round();
+            (fnum <= 0x3FF as f32).then_some((fnum as u16, block))
+        })
+    }
+
+    // Registers 0x20, 0x60 and 0x80 of an operator: 5-bit OPN rates are
+    // halved to 4 bits, a zero sustain rate holds the sustain level.
+    fn operator(patch: &FmPatch, op: &FmOperator) -> [u8; 3] {
+        let rate = |rate: u8| (rate & 0x1F).div_ceil(2).min(15);
+        let am = if op.am_dr & 0x80 != 0 && patch.ams != 0 {
+            0x80
+        } else {
+            0
+        };
+        let vibrato = if patch.fms != 0 { 0x40 } else { 0 };
+        let sustain = if op.sr & 0x1F == 0 { 0x20 } else { 0 };
+        let ksr = if op.ks_ar >> 6 != 0 { 0x10 } else { 0 };
+        [
+            am | vibrato | sustain | ksr | op.dt_mul & 0x0F,
+            rate(op.ks_ar) << 4 | rate(op.am_dr),
+            op.sl_rr,
+        ]
+    }
+
+    // Audible slots of `patch` and their operator offset in the channel.
+    fn outputs(patch: &FmPatch) -> Vec<(usize, u32)> {
+        let (modulator, carrier, additive) = OPL_PAIRS[patch.algorithm as usize & 7];
+        let mut carriers = vec![(carrier, 3)];
+        if additive {
+            carriers.push((modulator, 0));
+        }
+        carriers
+    }
+}
+
+impl Target for OplTarget {
+    fn channels(&self) -> usize {
+        9
+    }
+
+    fn init(&self) -> Writes {
+        // Sine waves, no CSM, melody mode.
+        vec![(0, 0x01, 0x00), (0, 0x08, 0x00), (0, 0xBD, 0x00)]
+    }
+
+    fn key_on(
+        &mut self,
+        channel: usize,
+        frequency: f32,
+        attenuation: f32,
+        patch: Option<&FmPatch>,
+    ) -> Option<Writes> {
+        let mut writes = Vec::new();
+        self.fnumber(frequency)?;
+        let patch = patch.copied().unwrap_or_default();
+        if self.patches[channel] != Some(patch) {
+            let (modulator, carrier, additive) = OPL_PAIRS[patch.algorithm as usize & 7];
+            for (slot, offset) in [(modulator, 0), (carrier, 3)] {
+                let op = &patch.operators[slot];
+                let base = OPL_OPERATOR_OFFSETS[channel] + offset;
+                let [am_vib, ar_dr, sl_rr] = Self::operator(&patch, op);
+                writes.extend([
+                    (0, 0x20 + base, am_vib),
+                    (0, 0x60 + base, ar_dr),
+                    (0, 0x80 + base, sl_rr),
+                    (0, 0x40 + base, (op.tl & 0x7F).min(0x3F)),
+                ]);
+            }
+            let feedback = if modulator == 0 {
+                patch.feedback & 7
+            } else {
+                0
+            };
+            writes.push((0, 0xC0 + channel as u32, feedback << 1 | additive as u8));
+            self.patches[channel] = Some(patch);
+        }
+        writes.extend(self.level(channel, attenuation));
+        writes.extend(self.pitch(channel, frequency));
+        writes.push((0, 0xB0 + channel as u32, 0x20 | self.frequencies[channel]));
+        self.keyed[channel] = true;
+        Some(writes)
+    }
+
+    fn pitch(&mut self, channel: usize, frequency: f32) -> Writes {
+        let Some((fnum, block)) = self.fnumber(frequency) else {
+            return Vec::new();
+        };
+        let ch = channel as u32;
+        self.frequencies[channel] = block << 2 | (fnum >> 8) as u8;
+        let mut writes = vec![(0, 0xA0 + ch, fnum as u8)];
+        if self.keyed[channel] {
+            writes.push((0, 0xB0 + ch, 0x20 | self.frequencies[channel]));
+        }
+        writes
+    }
+
+    fn level(&mut self, channel: usize, attenuation: f32) -> Writes {
+        let Some(patch) = self.patches[channel] else {
+            return Vec::new();
+        };
+        let extra = (attenuation / TL_STEP_DB).round().min(63.0) as u8;
+        Self::outputs(&patch)
+            .into_iter()
+            .map(|(slot, offset)| {
+                let tl = (patch.operators[slot].tl & 0x7F).saturating_add(extra);
+                (
+                    0,
+                    0x40 + OPL_OPERATOR_OFFSETS[channel] + offset,
+                    tl.min(0x3F),
+                )
+            })
+            .collect()
+    }
+
+    fn key_off(&mut self, channel: usize) -> Writes {
+        self.keyed[channel] = false;
+        vec![(0, 0xB0 + channel as u32, self.frequencies[channel])]
+    }
+
+    fn untranslated(&self, patch: &FmPatch) -> Vec<PatchFeature> {
+        let (modulator, carrier, _) = OPL_PAIRS[patch.algorithm as usize & 7];
+        let pair = [&patch.operators[modulator], &patch.operators[carrier]];
+        let mut others = (0..4)
+            .filter(|slot| *slot != modulator && *slot != carrier)
+            .map(|slot| &patch.operators[slot]);
+        [
+            (
+                patch.operators.iter().any(|op| op.ssg_eg & 0x08 != 0),
+                PatchFeature::SsgEg,
+            ),
+            (
+                others.any(|op| op.tl & 0x7F != 0x7F),
+                PatchFeature::Operators,
+            ),
+            (
+                patch.feedback & 7 != 0 && modulator != 0,
+                PatchFeature::Feedback,
+            ),
+            (
+                pair.iter().any(|op| op.dt_mul & 0x70 != 0),
+                PatchFeature::Detune,
+            ),
+            (
+                pair.iter().any(|op| op.sr & 0x1F != 0),
+                PatchFeature::SustainRate,
+            ),
+            (
+                patch.fms != 0 || patch.ams != 0,
+                PatchFeature::LfoSensitivity,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(lost, feature)| lost.then_some(feature))
+        .collect()
+    }
+}
+
+struct PsgTarget {
+    clock: f32,
+}
+
+impl PsgTarget {
+    // 10-bit tone period of `frequency`, `None` when it is too low.
+    fn period(&self, frequency: f32) -> Option<u16> {
+        let period = (self.clock / (32.0 * frequency)).round();
+        if period.is_nan() || period > 0x3FF as f32 {
+            return None;
+        }
+        Some((period as u16).max(1))
+    }
+}
+
+impl Target for PsgTarget {
+    // The noise channel is not used.
+    fn channels(&self) -> usize {
+        3
+    }
+
+    fn init(&self) -> Writes {
+        (0..4).map(|ch| (0, 0, 0x9F | ch << 5)).collect()
+    }
+
+    fn key_on(
+        &mut self,
+        channel: usize,
+        frequency: f32,
+        attenuation: f32,
+        _patch: Option<&FmPatch>,
+    ) -> Option<Writes> {
+        self.period(frequency)?;
+        let mut writes = self.pitch(channel, frequency);
+        writes.extend(self.level(channel, attenuation));
+        Some(writes)
+    }
+
+    fn pitch(&mut self, channel: usize, frequency: f32) -> Writes {
+        let Some(period) = self.period(frequency) else {
+            return Vec::new();
+        };
+        let ch = channel as u8;
+        vec![
+            (0, 0, 0x80 | ch << 5 | (period & 0x0F) as u8),
+            (0, 0, (period >> 4) as u8 & 0x3F),
+        ]
+    }
+
+    fn level(&mut self, channel: usize, attenuation: f32) -> Writes {
+        let step = (attenuation / PSG_STEP_DB).round().min(15.0) as u8;
+        vec![(0, 0, 0x90 | (channel as u8) << 5 | step)]
+    }
+
+    fn key_off(&mut self, channel: usize) -> Writes {
+        vec![(0, 0, 0x9F | (channel as u8) << 5)]
+    }
+}
+
+enum Event<'a> {
+    Off(usize),
+    Pcm(&'a IrPcmEvent),
+    On(usize, &'a IrNote),
+    Pitch(usize, f32),
+    Level(usize, f32),
+}
+
+/// Write `ir` as a document for the chip of `options`, which is registered
+/// as the only chip.
+///
+/// Notes are given the first free target channel, preferring the one their
+/// source channel used last, and dropped when none is free. Notes without a
+/// patch play `FmPatch::default()` on the FM targets; patches are ignored on
+/// SN76489, which only uses its three tone channels. PCM events are kept
+/// when they belong to the primary instance of the target chip, together
+/// with the data blocks. Returns `None` for other target chips.
+///
+/// Patches are ported to the YM2151 register for register, its key codes
+/// derived from the pitch for the target clock; only SSG-EG is lost. An OPL
+/// channel plays two operators of a patch: the main carrier and the
+/// operator modulating it (both summed for algorithm 7), with 5-bit rates
+/// halved and the sustain rate reduced to holding or releasing the sustain
+/// level. `ComposeReport::untranslated` counts the notes that lost part of
+/// their patch.
+pub fn compose(ir: &SongIr, options: &ComposeOptions) -> Option<(VgmDocument, ComposeReport)> {
+    let mut target: Box<dyn Target> = match options.chip {
+        Chip::Ym2612 => Box::new(Opn2Target {
+            clock: options.clock as f32,
+            patches: [None; 6],
+        }),
+        Chip::Ym2151 => Box::new(OpmTarget {
+            clock: options.clock as f32,
+            patches: [None; 8],
+        }),
+        Chip::Ym3812 | Chip::Ym3526 | Chip::Y8950 => Box::new(OplTarget {
+            clock: options.clock as f32,
+            patches: [None; 9],
+            frequencies: [0; 9],
+            keyed: [false; 9],
+        }),
+        Chip::Sn76489 => Box::new(PsgTarget {
+            clock: options.clock as f32,
+        }),
+        _ => return None,
+    };
+    let mut report = ComposeReport::default();
+
+    let mut notes: Vec<(usize, &IrNote)> = ir
+        .channels
+        .iter()
+        .enumerate()
+        .flat_map(|(index, channel)| channel.notes.iter().map(move |note| (index, note)))
+        .collect();
+    notes.sort_by_key(|(index, note)| (note.start, *index));
+
+    // Allocate target channels; every event is `(time, rank, event)`.
+    let mut busy_until = vec![0u64; target.channels()];
+    let mut owner: Vec<Option<usize>> = vec![None; target.channels()];
+    let mut events: Vec<(u64, u8, Event<'_>)> = Vec::new();
+    for (source, note) in notes {
+        let free = |ch: &usize| busy_until[*ch] <= note.start;
+        let channel = (0..busy_until.len())
+            .filter(free)
+            .find(|ch| owner[*ch] == Some(source))
+            .or_else(|| (0..busy_until.len()).find(free));
+        let Some(channel) = channel.filter(|_| !note.pitch.is_empty()) else {
+            report.dropped_notes += 1;
+            continue;
+        };
+        busy_until[channel] = note.end.max(note.start + 1);
+        owner[channel] = Some(source);
+        events.push((note.start, 2, Event::On(channel, note)));
+        for point in &note.pitch[1..] {
+            events.push((point.time, 3, Event::Pitch(channel, point.value)));
+        }
+        for point in note.envelope.iter().skip(1) {
+            events.push((point.time, 3, Event::Level(channel, point.value)));
+        }
+        // A note without length is keyed off after its key-on.
+        let rank = if note.end == note.start { 4 } else { 0 };
+        events.push((note.end, rank, Event::Off(channel)));
+    }
+    events.extend(
+        ir.pcm
+            .iter()
+            .map(|event| (event.time, 1, Event::Pcm(event))),
+    );
+    events.sort_by_key(|(time, rank, _)| (*time, *rank));
+
+    let to_command = |(port, register, value): (u8, u32, u8)| {
+        RegisterWrite {
+            chip: options.chip.clone(),
+            instance: Instance::Primary,
+            port,
+            register,
+            value: value as u32,
+        }
+        .to_command()
+    };
+    let mut commands: Vec<VgmCommand> = target.init().into_iter().filter_map(to_command).collect();
+    let mut loop_index = None;
+    let mut time = 0u64;
+    let mut has_pcm = false;
+    let mut on = vec![false; busy_until.len()];
+    for (event_time, _, event) in events {
+        if let Some(loop_start) = ir.loop_start
+            && loop_index.is_none()
+            && loop_start <= event_time
+        {
+            push_wait(&mut commands, loop_start - time);
+            time = loop_start;
+            loop_index = Some(commands.len());
+        }
+        push_wait(&mut commands, event_time - time);
+        time = event_time;
+        let writes = match event {
+            Event::Off(channel) => {
+                on[channel] = false;
+                target.key_off(channel)
+            }
+            Event::Pcm(event) => {
+                if event.chip == options.chip && event.instance == Instance::Primary {
+                    commands.push(event.command.clone());
+                    has_pcm = true;
+                } else {
+                    report.dropped_pcm_events += 1;
+                }
+                Vec::new()
+            }
+            Event::On(channel, note) => {
+                let attenuation = note.envelope.first().map_or(0.0, |point| point.value);
+                let patch = note.patch.and_then(|index| ir.patches.get(index));
+                match target.key_on(channel, note.pitch[0].value, attenuation, patch) {
+                    Some(writes) => {
+                        on[channel] = true;
+                        report.composed_notes += 1;
+                        for feature in patch.map(|p| target.untranslated(p)).unwrap_or_default() {
+                            *report.untranslated.entry(feature).or_default() += 1;
+                        }
+                        writes
+                    }
+                    None => {
+                        report.dropped_notes += 1;
+                        Vec::new()
+                    }
+                }
+            }
+            Event::Pitch(channel, frequency) if on[channel] => target.pitch(channel, frequency),
+            Event::Level(channel, attenuation) if on[channel] => target.level(channel, attenuation),
+            Event::Pitch(..) | Event::Level(..) => Vec::new(),
+        };
+        commands.extend(writes.into_iter().filter_map(to_command));
+    }
+    if let Some(loop_start) = ir.loop_start
+        && loop_index.is_none()
+        && loop_start >= time
+    {
+        push_wait(&mut commands, loop_start - time);
+        time = loop_start;
+        loop_index = Some(commands.len());
+    }
+    push_wait(&mut commands, ir.length.saturating_sub(time));
+
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(options.chip.clone(), Instance::Primary, options.clock);
+    if has_pcm {
+        for block in &ir.data_blocks {
+            builder.add_vgm_command(block.clone());
+        }
+    }
+    builder.add_vgm_commands(commands);
+    if let Some(index) = loop_index {
+        builder.set_loop_offset(index);
+    }
+    Some((builder.finalize(), report))
+}
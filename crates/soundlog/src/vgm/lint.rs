@@ -0,0 +1,758 @@
+//! Checks for common artifacts of emulator logging.
+//!
+//! VGM files logged from emulators often carry chips that the game
+//! initialized but never played, or channels that are keyed on with their
+//! output fully attenuated. Both only bloat the file. `lint` reports them:
+//!
+//! - `LintIssue::UnusedChip`: a chip registered in the header (non-zero
+//!   clock) that no command writes to, directly, through a DAC stream or
+//!   through `0x8n` YM2612 writes.
+//! - `LintIssue::SilentChannel`: an FM channel that is keyed on at least once
+//!   but whose keyed-on carrier operators are at total level `0x7F`
+//!   (silent) for as long as it is keyed on. This is checked for the FM part
+//!   of YM2612, YM2203, YM2608, YM2610(B) and for YM2151.
+//!
+//! With `LintOptions::strict`, `lint_with_options` also reports
+//! `LintIssue::UnregisteredChip`: a chip that is written but has no clock in
+//! the header, so players skip it and the file plays silent, and
+//! `LintIssue::MissingDualChipBit`: a secondary instance that is written while
+//! the header only declares the primary one, which confuses many players.
+//! It also reports `LintIssue::CommandTooNew`: a command introduced by a
+//! newer VGM version than the header declares (see `opcode::min_version`),
+//! which players of the declared version skip or misread.
+//! A strict `VgmBuilder` (`VgmBuilder::into_strict`) runs the same check in
+//! `try_finalize`.
+//!
+//! Real hardware needs time between register writes, and players that drive
+//! it skip or garble writes that come too fast. `LintOptions::max_burst` and
+//! `LintOptions::max_writes_per_ms` report chips that are written faster than
+//! that:
+//!
+//! - `LintIssue::WriteBurst`: more than `max_burst` writes to one chip
+//!   without a wait in between,
+//! - `LintIssue::WriteRate`: more than `max_writes_per_ms` writes to one chip
+//!   within one millisecond (44.1 samples).
+//!
+//! Both checks are off by default. `BurstDetector` runs them on any command
+//! sequence, such as the output of `VgmStream`, and `lint_commands` wraps it
+//! for a whole sequence.
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::chip;
+//! use soundlog::vgm::command::Instance;
+//! use soundlog::vgm::lint::{LintIssue, lint};
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.register_chip(chip::Chip::Sn76489, Instance::Primary, 3_579_545);
+//! builder.register_chip(chip::Chip::Ym2413, Instance::Primary, 3_579_545);
+//! builder.add_chip_write(Instance::Primary, chip::PsgSpec { value: 0x90 });
+//! let doc = builder.finalize();
+//!
+//! let report = lint(&doc);
+//! assert_eq!(
+//!     report.issues,
+//!     vec![LintIssue::UnusedChip {
+//!         chip: chip::Chip::Ym2413,
+//!         instance: Instance::Primary,
+//!     }]
+//! );
+//! ```
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::chip::Chip;
+use crate::chip::patch::{FM_CARRIERS, OPM_SLOT_OFFSETS, OPN_SLOT_OFFSETS};
+use crate::vgm::VgmDocument;
+use crate::vgm::analysis::{Analysis, AnalysisContext, run_analyses};
+use crate::vgm::command::{Instance, VgmCommand};
+use crate::vgm::header::ChipId;
+use crate::vgm::opcode;
+
+/// A single finding of `lint`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LintIssue {
+    /// The chip has a clock in the header but is never written.
+    UnusedChip { chip: Chip, instance: Instance },
+    /// The chip is written (first by command `first_write`) but has no clock
+    /// in the header. Only reported in strict mode.
+    UnregisteredChip {
+        chip: Chip,
+        instance: Instance,
+        first_write: usize,
+    },
+    /// The secondary instance of the chip is written (first by command
+    /// `first_write`) but the header clock lacks the dual-chip bit. Only
+    /// reported in strict mode.
+    MissingDualChipBit { chip: Chip, first_write: usize },
+    /// Opcode `opcode` (first used by command `first_write`) needs VGM
+    /// version `min_version` but the header declares `version`. Only
+    /// reported in strict mode.
+    CommandTooNew {
+        opcode: u8,
+        first_write: usize,
+        min_version: u32,
+        version: u32,
+    },
+    /// The channel is keyed on `key_ons` times but never audible.
+    SilentChannel {
+        chip: Chip,
+        instance: Instance,
+        channel: u8,
+        key_ons: u32,
+    },
+    /// The chip is written `length` times in a row without a wait, starting
+    /// with command `first_write`. This is the longest of `bursts` runs
+    /// longer than `LintOptions::max_burst`.
+    WriteBurst {
+        chip: Chip,
+        instance: Instance,
+        first_write: usize,
+        length: usize,
+        bursts: usize,
+    },
+    /// The chip is written `writes` times within one millisecond, up to
+    /// command `index` at sample `time`. This is the highest rate seen and is
+    /// above `LintOptions::max_writes_per_ms`.
+    WriteRate {
+        chip: Chip,
+        instance: Instance,
+        index: usize,
+        time: u64,
+        writes: usize,
+    },
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LintIssue::UnusedChip { chip, instance } => write!(
+                f,
+                "{:?} ({:?}) is registered in the header but never written",
+                chip, instance
+            ),
+            LintIssue::UnregisteredChip {
+                chip,
+                instance,
+                first_write,
+            } => write!(
+                f,
+                "{:?} ({:?}) is written by command {} but has no clock in the header",
+                chip, instance, first_write
+            ),
+            LintIssue::MissingDualChipBit { chip, first_write } => write!(
+                f,
+                "{:?} (Secondary) is written by command {} but the header dual-chip bit is not set",
+                chip, first_write
+            ),
+            LintIssue::CommandTooNew {
+                opcode,
+                first_write,
+                min_version,
+                version,
+            } => write!(
+                f,
+                "opcode 0x{:02X} (command {}) needs VGM {:X}.{:02X} but the header declares {:X}.{:02X}",
+                opcode,
+                first_write,
+                min_version >> 8,
+                min_version & 0xFF,
+                version >> 8,
+                version & 0xFF
+            ),
+            LintIssue::SilentChannel {
+                chip,
+                instance,
+                channel,
+                key_ons,
+            } => write!(
+                f,
+                "{:?} ({:?}) channel {} is keyed on {} time(s) but always at zero volume",
+                chip, instance, channel, key_ons
+            ),
+            LintIssue::WriteBurst {
+                chip,
+                instance,
+                first_write,
+                length,
+                bursts,
+            } => write!(
+                f,
+                "{:?} ({:?}) is written {} times without a wait from command {} ({} burst(s) over the limit)",
+                chip, instance, length, first_write, bursts
+            ),
+            LintIssue::WriteRate {
+                chip,
+                instance,
+                index,
+                time,
+                writes,
+            } => write!(
+                f,
+                "{:?} ({:?}) is written {} times within 1 ms up to command {} (sample {})",
+                chip, instance, writes, index, time
+            ),
+        }
+    }
+}
+
+/// Result of `lint`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LintReport {
+    /// Unused chips in header order, then unregistered chips, commands too
+    /// new for the header version in the order they are first used, silent
+    /// channels and write bursts and rates in the order their chips are first
+    /// written.
+    pub issues: Vec<LintIssue>,
+}
+
+impl LintReport {
+    /// `true` when nothing was found.
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Options for `lint_with_options`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LintOptions {
+    /// Also report `LintIssue::UnregisteredChip`,
+    /// `LintIssue::MissingDualChipBit` and `LintIssue::CommandTooNew`.
+    pub strict: bool,
+    /// Report `LintIssue::WriteBurst` when a chip is written more than this
+    /// many times without a wait. Off when `None`.
+    pub max_burst: Option<usize>,
+    /// Report `LintIssue::WriteRate` when a chip is written more than this
+    /// many times within one millisecond. Off when `None`.
+    pub max_writes_per_ms: Option<usize>,
+}
+
+/// Look for unused chips and silent channels in `document`.
+///
+/// Commands after the first `EndOfData` are not played and are ignored.
+pub fn lint(document: &VgmDocument) -> LintReport {
+    lint_with_options(document, &LintOptions::default())
+}
+
+/// Like `lint`, also reporting unregistered chips when `options.strict` is
+/// set and write bursts when `options.max_burst` or
+/// `options.max_writes_per_ms` is set.
+pub fn lint_with_options(document: &VgmDocument, options: &LintOptions) -> LintReport {
+    let mut analysis = LintAnalysis::new(options);
+    run_analyses(document, &mut [&mut analysis]);
+    analysis.into_report()
+}
+
+/// Check `commands` for write bursts and write rates only, see
+/// `BurstDetector`. Useful on the output of `VgmStream`, where the unused
+/// chip and silent channel checks do not apply.
+pub fn lint_commands<'a>(
+    commands: impl IntoIterator<Item = &'a VgmCommand>,
+    options: &LintOptions,
+) -> LintReport {
+    let mut detector = BurstDetector::new(options);
+    for command in commands {
+        detector.command(command);
+    }
+    LintReport {
+        issues: detector.into_issues(),
+    }
+}
+
+/// `lint_with_options` as an `Analysis`, to share a pass with other
+/// analyses.
+pub struct LintAnalysis {
+    strict: bool,
+    ended: bool,
+    // (chip, instance, index of the first command using it)
+    used: Vec<(Chip, Instance, usize)>,
+    too_new: Vec<LintIssue>,
+    trackers: Vec<(Chip, Instance, FmTracker)>,
+    bursts: BurstDetector,
+    report: LintReport,
+}
+
+impl LintAnalysis {
+    pub fn new(options: &LintOptions) -> Self {
+        LintAnalysis {
+            strict: options.strict,
+            ended: false,
+            used: Vec::new(),
+            too_new: Vec::new(),
+            trackers: Vec::new(),
+            bursts: BurstDetector::new(options),
+            report: LintReport::default(),
+        }
+    }
+
+    /// The findings, complete once the analysis has run.
+    pub fn into_report(self) -> LintReport {
+        self.report
+    }
+
+    fn use_chip(&mut self, chip: Chip, instance: Instance, index: usize) {
+        let chip = same_chip(chip);
+        if !self
+            .used
+            .iter()
+            .any(|(c, i, _)| *c == chip && *i == instance)
+        {
+            self.used.push((chip, instance, index));
+        }
+    }
+
+    fn check_version(&mut self, context: &AnalysisContext<'_>, command: &VgmCommand) {
+        let version = context.document().header.version;
+        let opcode = opcode::opcode_of(command);
+        let Some(min_version) = opcode::min_version(opcode) else {
+            return;
+        };
+        if min_version > version
+            && !self.too_new.iter().any(
+                |issue| matches!(issue, LintIssue::CommandTooNew { opcode: o, .. } if *o == opcode),
+            )
+        {
+            self.too_new.push(LintIssue::CommandTooNew {
+                opcode,
+                first_write: context.index(),
+                min_version,
+                version,
+            });
+        }
+    }
+}
+
+impl Analysis for LintAnalysis {
+    fn command(&mut self, context: &AnalysisContext<'_>, command: &VgmCommand) {
+        if self.ended {
+            return;
+        }
+        let index = context.index();
+        self.bursts.command(command);
+        if self.strict {
+            self.check_version(context, command);
+        }
+        match command {
+            VgmCommand::EndOfData(_) => {
+                self.ended = true;
+                return;
+            }
+            VgmCommand::SetupStreamControl(setup) => {
+                if let Some(chip) = chip_of(setup.chip_type.chip_id) {
+                    self.use_chip(chip, setup.chip_type.instance, index);
+                }
+                return;
+            }
+            VgmCommand::YM2612Port0Address2AWriteAndWaitN(_) => {
+                self.use_chip(Chip::Ym2612, Instance::Primary, index);
+                return;
+            }
+            _ => {}
+        }
+        let Some(write) = context.write() else {
+            return;
+        };
+        self.use_chip(write.chip.clone(), write.instance, index);
+
+        let layout = match write.chip {
+            Chip::Ym2203 => FmLayout::Opn { channels: 3 },
+            Chip::Ym2608 | Chip::Ym2610b | Chip::Ym2612 => FmLayout::Opn { channels: 6 },
+            Chip::Ym2151 => FmLayout::Opm,
+            _ => return,
+        };
+        let index = match self
+            .trackers
+            .iter()
+            .position(|(chip, instance, _)| *chip == write.chip && *instance == write.instance)
+        {
+            Some(index) => index,
+            None => {
+                self.trackers
+                    .push((write.chip.clone(), write.instance, FmTracker::new(layout)));
+                self.trackers.len() - 1
+            }
+        };
+        self.trackers[index]
+            .2
+            .write(write.port, write.register as u8, write.value as u8);
+    }
+
+    fn finish(&mut self, context: &AnalysisContext<'_>) {
+        let report = &mut self.report;
+        let registered: Vec<(Chip, Instance)> = context
+            .document()
+            .header
+            .chip_instances()
+            .into_iter()
+            .map(|(instance, chip, _)| (chip, instance))
+            .collect();
+        for (chip, instance) in &registered {
+            let same = same_chip(chip.clone());
+            if !self
+                .used
+                .iter()
+                .any(|(c, i, _)| *c == same && i == instance)
+            {
+                report.issues.push(LintIssue::UnusedChip {
+                    chip: chip.clone(),
+                    instance: *instance,
+                });
+            }
+        }
+        if self.strict {
+            let is_registered = |chip: &Chip, instance: Instance| {
+                registered
+                    .iter()
+                    .any(|(c, i)| same_chip(c.clone()) == *chip && *i == instance)
+            };
+            for (chip, instance, first_write) in self.used.drain(..) {
+                if is_registered(&chip, instance) {
+                    continue;
+                }
+                if instance == Instance::Secondary && is_registered(&chip, Instance::Primary) {
+                    report
+                        .issues
+                        .push(LintIssue::MissingDualChipBit { chip, first_write });
+                } else {
+                    report.issues.push(LintIssue::UnregisteredChip {
+                        chip,
+                        instance,
+                        first_write,
+                    });
+                }
+            }
+            report.issues.append(&mut self.too_new);
+        }
+        for (chip, instance, tracker) in self.trackers.drain(..) {
+            for channel in 0..tracker.key_ons.len() {
+                if tracker.key_ons[channel] > 0 && !tracker.audible[channel] {
+                    report.issues.push(LintIssue::SilentChannel {
+                        chip: chip.clone(),
+                        instance,
+                        channel: channel as u8,
+                        key_ons: tracker.key_ons[channel],
+                    });
+                }
+            }
+        }
+        report.issues.extend(self.bursts.take_issues());
+    }
+}
+
+/// Write burst and write rate checks of `LintOptions`, fed one command at a
+/// time.
+///
+/// A burst is a run of writes to one chip instance without a wait in between;
+/// waits of any chip end the runs of every chip. The rate is the number of
+/// writes to one chip instance within the last millisecond. `0x8n` YM2612
+/// writes count as a write followed by their wait. Writes done by the player
+/// for DAC streams are not part of the command sequence and are not counted,
+/// so feed it the output of `VgmStream` to check them (after
+/// `VgmStream::set_write_rate_limit`, if one is used).
+pub struct BurstDetector {
+    max_burst: Option<usize>,
+    max_writes_per_ms: Option<usize>,
+    index: usize,
+    time: u64,
+    chips: Vec<BurstTracker>,
+}
+
+impl BurstDetector {
+    pub fn new(options: &LintOptions) -> Self {
+        BurstDetector {
+            max_burst: options.max_burst,
+            max_writes_per_ms: options.max_writes_per_ms,
+            index: 0,
+            time: 0,
+            chips: Vec::new(),
+        }
+    }
+
+    /// Feed the next command of the sequence.
+    pub fn command(&mut self, command: &VgmCommand) {
+        let index = self.index;
+        self.index += 1;
+        if self.max_burst.is_none() && self.max_writes_per_ms.is_none() {
+            return;
+        }
+        let written = match command {
+            VgmCommand::YM2612Port0Address2AWriteAndWaitN(_) => {
+                Some((Chip::Ym2612, Instance::Primary))
+            }
+            _ => command
+                .register_write()
+                .map(|write| (same_chip(write.chip), write.instance)),
+        };
+        if let Some((chip, instance)) = written {
+            let position = match self
+                .chips
+                .iter()
+                .position(|c| c.chip == chip && c.instance == instance)
+            {
+                Some(position) => position,
+                None => {
+                    self.chips.push(BurstTracker::new(chip, instance));
+                    self.chips.len() - 1
+                }
+            };
+            let tracker = &mut self.chips[position];
+            tracker.write(index, self.time);
+        }
+        let wait = command.wait_samples();
+        if wait > 0 {
+            for tracker in &mut self.chips {
+                tracker.end_run(self.max_burst);
+            }
+            self.time += wait as u64;
+        }
+    }
+
+    /// The findings, in the order the chips are first written.
+    pub fn into_issues(mut self) -> Vec<LintIssue> {
+        self.take_issues()
+    }
+
+    fn take_issues(&mut self) -> Vec<LintIssue> {
+        let mut issues = Vec::new();
+        for mut tracker in self.chips.drain(..) {
+            tracker.end_run(self.max_burst);
+            if tracker.bursts > 0 {
+                issues.push(LintIssue::WriteBurst {
+                    chip: tracker.chip.clone(),
+                    instance: tracker.instance,
+                    first_write: tracker.longest_start,
+                    length: tracker.longest,
+                    bursts: tracker.bursts,
+                });
+            }
+            if let Some(max) = self.max_writes_per_ms
+                && tracker.peak > max
+            {
+                issues.push(LintIssue::WriteRate {
+                    chip: tracker.chip.clone(),
+                    instance: tracker.instance,
+                    index: tracker.peak_index,
+                    time: tracker.peak_time,
+                    writes: tracker.peak,
+                });
+            }
+        }
+        issues
+    }
+}
+
+struct BurstTracker {
+    chip: Chip,
+    instance: Instance,
+    run: usize,
+    run_start: usize,
+    longest: usize,
+    longest_start: usize,
+    bursts: usize,
+    // Times of the writes within the last millisecond.
+    recent: VecDeque<u64>,
+    peak: usize,
+    peak_index: usize,
+    peak_time: u64,
+}
+
+impl BurstTracker {
+    fn new(chip: Chip, instance: Instance) -> Self {
+        BurstTracker {
+            chip,
+            instance,
+            run: 0,
+            run_start: 0,
+            longest: 0,
+            longest_start: 0,
+            bursts: 0,
+            recent: VecDeque::new(),
+            peak: 0,
+            peak_index: 0,
+            peak_time: 0,
+        }
+    }
+
+    fn write(&mut self, index: usize, time: u64) {
+        if self.run == 0 {
+            self.run_start = index;
+        }
+        self.run += 1;
+        if self.run > self.longest {
+            self.longest = self.run;
+            self.longest_start = self.run_start;
+        }
+        // 1 ms is 44.1 samples.
+        while let Some(&first) = self.recent.front()
+            && (time - first) * 10 >= 441
+        {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(time);
+        if self.recent.len() > self.peak {
+            self.peak = self.recent.len();
+            self.peak_index = index;
+            self.peak_time = time;
+        }
+    }
+
+    fn end_run(&mut self, max_burst: Option<usize>) {
+        if let Some(max) = max_burst
+            && self.run > max
+        {
+            self.bursts += 1;
+        }
+        self.run = 0;
+    }
+}
+
+// ES5506 is written with 8-bit or 16-bit commands but has one header clock.
+fn same_chip(chip: Chip) -> Chip {
+    match chip {
+        Chip::Es5506U16 => Chip::Es5506U8,
+        chip => chip,
+    }
+}
+
+pub(crate) fn chip_of(chip_id: ChipId) -> Option<Chip> {
+    Some(match chip_id {
+        ChipId::Sn76489 => Chip::Sn76489,
+        ChipId::Ym2413 => Chip::Ym2413,
+        ChipId::Ym2612 => Chip::Ym2612,
+        ChipId::Ym2151 => Chip::Ym2151,
+        ChipId::SegaPcm => Chip::SegaPcm,
+        ChipId::Rf5c68 => Chip::Rf5c68,
+        ChipId::Ym2203 => Chip::Ym2203,
+        ChipId::Ym2608 => Chip::Ym2608,
+        ChipId::Ym2610 => Chip::Ym2610b,
+        ChipId::Ym3812 => Chip::Ym3812,
+        ChipId::Ym3526 => Chip::Ym3526,
+        ChipId::Y8950 => Chip::Y8950,
+        ChipId::Ymf262 => Chip::Ymf262,
+        ChipId::Ymf278b => Chip::Ymf278b,
+        ChipId::Ymf271 => Chip::Ymf271,
+        ChipId::Ymz280b => Chip::Ymz280b,
+        ChipId::Rf5c164 => Chip::Rf5c164,
+        ChipId::Pwm => Chip::Pwm,
+        ChipId::Ay8910 => Chip::Ay8910,
+        ChipId::GbDmg => Chip::GbDmg,
+        ChipId::NesApu => Chip::NesApu,
+        ChipId::MultiPcm => Chip::MultiPcm,
+        ChipId::Upd7759 => Chip::Upd7759,
+        ChipId::Okim6258 => Chip::Okim6258,
+        ChipId::Okim6295 => Chip::Okim6295,
+        ChipId::K051649 => Chip::K051649,
+        ChipId::K054539 => Chip::K054539,
+        ChipId::Huc6280 => Chip::Huc6280,
+        ChipId::C140 => Chip::C140,
+        ChipId::K053260 => Chip::K053260,
+        ChipId::Pokey => Chip::Pokey,
+        ChipId::Qsound => Chip::Qsound,
+        ChipId::Scsp => Chip::Scsp,
+        ChipId::WonderSwan => Chip::WonderSwan,
+        ChipId::Vsu => Chip::Vsu,
+        ChipId::Saa1099 => Chip::Saa1099,
+        ChipId::Es5503 => Chip::Es5503,
+        ChipId::Es5506 => Chip::Es5506U8,
+        ChipId::X1010 => Chip::X1010,
+        ChipId::C352 => Chip::C352,
+        ChipId::Ga20 => Chip::Ga20,
+        ChipId::Mikey => Chip::Mikey,
+        ChipId::Unknown(_) => return None,
+    })
+}
+
+#[derive(Clone, Copy)]
+enum FmLayout {
+    // OPN family: key-on at 0x28, TL at 0x40-0x4F, algorithm at 0xB0-0xB2,
+    // channels 3-5 on port 1.
+    Opn { channels: usize },
+    // YM2151: key-on at 0x08, TL at 0x60-0x7F, connection at 0x20-0x27.
+    Opm,
+}
+
+// Key-on and carrier level tracking of an FM chip. Operators are numbered
+// 0-3 in key-on slot order (OPN S1-S4, OPM M1 C1 M2 C2).
+struct FmTracker {
+    layout: FmLayout,
+    regs: [[u8; 0x100]; 2],
+    slots: [u8; 8],
+    key_ons: Vec<u32>,
+    audible: Vec<bool>,
+}
+
+impl FmTracker {
+    fn new(layout: FmLayout) -> Self {
+        let channels = match layout {
+            FmLayout::Opn { channels } => channels,
+            FmLayout::Opm => 8,
+        };
+        FmTracker {
+            layout,
+            // TL resets to 0 (loudest), so only written levels can mute.
+            regs: [[0; 0x100]; 2],
+            slots: [0; 8],
+            key_ons: vec![0; channels],
+            audible: vec![false; channels],
+        }
+    }
+
+    fn write(&mut self, port: u8, register: u8, value: u8) {
+        let Some(regs) = self.regs.get_mut(port as usize) else {
+            return;
+        };
+        regs[register as usize] = value;
+        let channel = match (self.layout, register) {
+            (FmLayout::Opn { .. }, 0x28) if port == 0 => {
+                let channel = (value & 0x03) as usize + if value & 0x04 != 0 { 3 } else { 0 };
+                if value & 0x03 == 0x03 || channel >= self.key_ons.len() {
+                    return;
+                }
+                self.key_on(channel, value >> 4);
+                channel
+            }
+            (FmLayout::Opn { .. }, 0x40..=0x4F | 0xB0..=0xB2) if register & 0x03 != 0x03 => {
+                (register & 0x03) as usize + port as usize * 3
+            }
+            (FmLayout::Opm, 0x08) => {
+                let channel = (value & 0x07) as usize;
+                self.key_on(channel, (value >> 3) & 0x0F);
+                channel
+            }
+            (FmLayout::Opm, 0x20..=0x27 | 0x60..=0x7F) => (register & 0x07) as usize,
+            _ => return,
+        };
+        if channel < self.key_ons.len()
+            && self.slots[channel] != 0
+            && self.carrier_level_audible(channel)
+        {
+            self.audible[channel] = true;
+        }
+    }
+
+    fn key_on(&mut self, channel: usize, slots: u8) {
+        if self.slots[channel] == 0 && slots != 0 {
+            self.key_ons[channel] += 1;
+        }
+        self.slots[channel] = slots;
+    }
+
+    // `true` when a keyed-on carrier of `channel` is not at TL 0x7F.
+    fn carrier_level_audible(&self, channel: usize) -> bool {
+        let (algorithm, tl) = match self.layout {
+            FmLayout::Opn { .. } => {
+                let regs = &self.regs[channel / 3];
+                let ch = channel % 3;
+                let tl = OPN_SLOT_OFFSETS.map(|slot| regs[0x40 + slot as usize + ch]);
+                (regs[0xB0 + ch] & 0x07, tl)
+            }
+            FmLayout::Opm => {
+                let regs = &self.regs[0];
+                let tl = OPM_SLOT_OFFSETS.map(|slot| regs[0x60 + slot as usize + channel]);
+                (regs[0x20 + channel] & 0x07, tl)
+            }
+        };
+        let active = FM_CARRIERS[algorithm as usize] & self.slots[channel];
+        (0..4).any(|op| active & (1 << op) != 0 && tl[op] & 0x7F != 0x7F)
+    }
+}
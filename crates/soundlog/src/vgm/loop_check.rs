@@ -0,0 +1,604 @@
+//! Gapless loop verification.
+//!
+//! When a player jumps from the end of a song back to its loop point, the
+//! chips keep the state the end of the song left them in. If that differs
+//! from the state the loop was written for, the loop clicks, drops a note or
+//! leaves one hanging. `check_loop` plays the song once, then replays the
+//! start of the loop, and compares the chip state at the first sample of the
+//! loop on both passes:
+//!
+//! - `LoopIssue::HangingNote` / `LoopIssue::MissingNote`: a channel is keyed
+//!   on after the jump but not on the first pass, or the other way round.
+//!   Key states come from the `chip::state` trackers of SN76489, AY-3-8910,
+//!   YM2612, YM2151, YM2203, YM2608, YM2610(B), YM2413, YM3812, YM3526,
+//!   Y8950 and YMF262.
+//! - `LoopIssue::PatchMismatch`: an FM voice register (operator parameters,
+//!   algorithm and feedback, the YM2413 user instrument) holds a different
+//!   value, so the loop plays with the patch left by the end of the song.
+//! - `LoopIssue::StreamMismatch`: a DAC stream plays at a different data
+//!   position, or only on one of the passes.
+//!
+//! Writes at the loop point itself, before its first wait, are applied on
+//! both passes, so a loop that re-initializes what it needs is clean.
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::chip::{Chip, PsgSpec};
+//! use soundlog::vgm::command::{Instance, WaitSamples};
+//! use soundlog::vgm::loop_check::{LoopIssue, check_loop};
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+//! builder.add_vgm_command(WaitSamples(100));
+//! // loop: a note on channel 0 that is never silenced
+//! builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x8E });
+//! builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x0F });
+//! builder.add_vgm_command(WaitSamples(100));
+//! builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x90 });
+//! builder.add_vgm_command(WaitSamples(100));
+//! builder.set_loop_index(1);
+//! let doc = builder.finalize();
+//!
+//! let report = check_loop(&doc);
+//! assert_eq!(
+//!     report.issues,
+//!     vec![LoopIssue::HangingNote {
+//!         chip: Chip::Sn76489,
+//!         instance: Instance::Primary,
+//!         channel: 0,
+//!     }]
+//! );
+//! ```
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+use crate::chip::Chip;
+use crate::vgm::VgmDocument;
+use crate::vgm::analysis::{KeyTracker, tracker};
+use crate::vgm::command::{Instance, LengthMode, RegisterWrite, VgmCommand};
+use crate::vgm::detail::{DataBlockType, parse_data_block};
+
+/// A single finding of `check_loop`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoopIssue {
+    /// The channel is still keyed on when the song jumps back, but is silent
+    /// at the loop point on the first pass.
+    HangingNote {
+        chip: Chip,
+        instance: Instance,
+        channel: u8,
+    },
+    /// The channel is keyed on at the loop point on the first pass (by the
+    /// intro), but not after the jump.
+    MissingNote {
+        chip: Chip,
+        instance: Instance,
+        channel: u8,
+    },
+    /// The voice register holds `at_loop_start` on the first pass and
+    /// `after_jump` after the jump.
+    PatchMismatch {
+        chip: Chip,
+        instance: Instance,
+        port: u8,
+        register: u8,
+        at_loop_start: u8,
+        after_jump: u8,
+    },
+    /// The DAC stream plays at data bank position `at_loop_start` on the
+    /// first pass and `after_jump` after the jump (`None`: stopped).
+    StreamMismatch {
+        stream_id: u8,
+        at_loop_start: Option<u64>,
+        after_jump: Option<u64>,
+    },
+}
+
+impl fmt::Display for LoopIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let position = |p: &Option<u64>| match p {
+            Some(p) => format!("position 0x{:X}", p),
+            None => "stopped".to_string(),
+        };
+        match self {
+            LoopIssue::HangingNote {
+                chip,
+                instance,
+                channel,
+            } => write!(
+                f,
+                "{:?} ({:?}) channel {} is still keyed on when the song loops",
+                chip, instance, channel
+            ),
+            LoopIssue::MissingNote {
+                chip,
+                instance,
+                channel,
+            } => write!(
+                f,
+                "{:?} ({:?}) channel {} is keyed on at the loop point only on the first pass",
+                chip, instance, channel
+            ),
+            LoopIssue::PatchMismatch {
+                chip,
+                instance,
+                port,
+                register,
+                at_loop_start,
+                after_jump,
+            } => write!(
+                f,
+                "{:?} ({:?}) register {}:{:02X} is {:02X} at the loop point but {:02X} after looping",
+                chip, instance, port, register, at_loop_start, after_jump
+            ),
+            LoopIssue::StreamMismatch {
+                stream_id,
+                at_loop_start,
+                after_jump,
+            } => write!(
+                f,
+                "DAC stream {} is at {} at the loop point but at {} after looping",
+                stream_id,
+                position(at_loop_start),
+                position(after_jump)
+            ),
+        }
+    }
+}
+
+/// Result of `check_loop`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LoopCheckReport {
+    /// Sample position of the first sample of the loop, or `None` when the
+    /// document does not loop.
+    pub loop_start: Option<u64>,
+    /// Key state issues in the order their chips are first written, then
+    /// patch and DAC stream issues.
+    pub issues: Vec<LoopIssue>,
+}
+
+impl LoopCheckReport {
+    /// `true` when nothing was found.
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Compare the chip state at the loop point of `document` on the first pass
+/// with the state after jumping back from the end.
+///
+/// Documents without a loop point give an empty report.
+pub fn check_loop(document: &VgmDocument) -> LoopCheckReport {
+    match loop_states(document) {
+        Some(states) => LoopCheckReport {
+            loop_start: Some(states.at_loop_start.time),
+            issues: states.issues(),
+        },
+        None => LoopCheckReport::default(),
+    }
+}
+
+// Chip state at the loop point on the first pass and after the jump, shared
+// with `transform::patch_loop_state`.
+pub(crate) struct LoopStates {
+    pub(crate) loop_index: usize,
+    pub(crate) chips: Vec<LoopChip>,
+    pub(crate) at_loop_start: Snapshot,
+    pub(crate) after_jump: Snapshot,
+}
+
+pub(crate) struct LoopChip {
+    pub(crate) chip: Chip,
+    pub(crate) instance: Instance,
+    // First write command to the chip, template for new writes.
+    pub(crate) template: VgmCommand,
+}
+
+// Chip state relevant to looping at one point in time.
+pub(crate) struct Snapshot {
+    pub(crate) time: u64,
+    // Key state per channel, per entry of `LoopStates::chips`.
+    pub(crate) keys: Vec<Vec<bool>>,
+    // Register values per entry of `LoopStates::chips`, for chips with a key
+    // state tracker.
+    pub(crate) registers: Vec<BTreeMap<(u8, u8), u8>>,
+    // Every configured stream.
+    pub(crate) streams: BTreeMap<u8, StreamSnapshot>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct StreamSnapshot {
+    // Data bank position, `None` when stopped.
+    pub(crate) position: Option<u64>,
+    // Bytes left to play, `None` when stopped or unknown.
+    pub(crate) remaining: Option<u64>,
+    pub(crate) bank: u8,
+    pub(crate) frequency: u32,
+    pub(crate) step_size: u8,
+    pub(crate) looped: bool,
+    pub(crate) reverse: bool,
+}
+
+pub(crate) fn loop_states(document: &VgmDocument) -> Option<LoopStates> {
+    let loop_index = document.loop_command_index()?;
+    let end = document
+        .commands
+        .iter()
+        .position(|command| matches!(command, VgmCommand::EndOfData(_)))
+        .unwrap_or(document.commands.len());
+    if loop_index >= end {
+        return None;
+    }
+
+    let mut player = Player::new(document);
+    let mut at_loop_start = None;
+    for index in 0..end {
+        if index >= loop_index && at_loop_start.is_none() && player.would_wait(index) {
+            at_loop_start = Some(player.snapshot());
+        }
+        player.play(index);
+    }
+    let at_loop_start = at_loop_start.unwrap_or_else(|| player.snapshot());
+    // Replay the loop point writes on the second pass.
+    for index in loop_index..end {
+        if player.would_wait(index) {
+            break;
+        }
+        player.play(index);
+    }
+    let after_jump = player.snapshot();
+    Some(LoopStates {
+        loop_index,
+        chips: player
+            .chips
+            .into_iter()
+            .map(|chip| LoopChip {
+                chip: chip.chip,
+                instance: chip.instance,
+                template: chip.template,
+            })
+            .collect(),
+        at_loop_start,
+        after_jump,
+    })
+}
+
+impl LoopStates {
+    pub(crate) fn chip_index(&self, chip: &Chip, instance: Instance) -> usize {
+        self.chips
+            .iter()
+            .position(|c| c.chip == *chip && c.instance == instance)
+            .expect("issues refer to played chips")
+    }
+
+    pub(crate) fn issues(&self) -> Vec<LoopIssue> {
+        let (at_loop_start, after_jump) = (&self.at_loop_start, &self.after_jump);
+        let mut issues = Vec::new();
+        for (index, chip) in self.chips.iter().enumerate() {
+            let (start, after) = (&at_loop_start.keys[index], &after_jump.keys[index]);
+            for channel in 0..start.len().max(after.len()) {
+                let start = start.get(channel).copied().unwrap_or(false);
+                let after = after.get(channel).copied().unwrap_or(false);
+                let (chip, instance, channel) = (chip.chip.clone(), chip.instance, channel as u8);
+                match (start, after) {
+                    (false, true) => issues.push(LoopIssue::HangingNote {
+                        chip,
+                        instance,
+                        channel,
+                    }),
+                    (true, false) => issues.push(LoopIssue::MissingNote {
+                        chip,
+                        instance,
+                        channel,
+                    }),
+                    _ => {}
+                }
+            }
+        }
+        for (index, chip) in self.chips.iter().enumerate() {
+            let (start, after) = (
+                &at_loop_start.registers[index],
+                &after_jump.registers[index],
+            );
+            for (&(port, register), &after_value) in after {
+                let start_value = start.get(&(port, register)).copied();
+                if is_patch_register(&chip.chip, register) && start_value != Some(after_value) {
+                    issues.push(LoopIssue::PatchMismatch {
+                        chip: chip.chip.clone(),
+                        instance: chip.instance,
+                        port,
+                        register,
+                        at_loop_start: start_value.unwrap_or(0),
+                        after_jump: after_value,
+                    });
+                }
+            }
+        }
+        let stream_ids: BTreeSet<u8> = at_loop_start
+            .streams
+            .keys()
+            .chain(after_jump.streams.keys())
+            .copied()
+            .collect();
+        for stream_id in stream_ids {
+            let position = |snapshot: &Snapshot| {
+                snapshot
+                    .streams
+                    .get(&stream_id)
+                    .and_then(|stream| stream.position)
+            };
+            let (start, after) = (position(at_loop_start), position(after_jump));
+            if start != after {
+                issues.push(LoopIssue::StreamMismatch {
+                    stream_id,
+                    at_loop_start: start,
+                    after_jump: after,
+                });
+            }
+        }
+        issues
+    }
+}
+
+struct ChipPlayer {
+    chip: Chip,
+    instance: Instance,
+    template: VgmCommand,
+    tracker: Option<Box<dyn KeyTracker>>,
+    registers: BTreeMap<(u8, u8), u8>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct DacStream {
+    bank: u8,
+    step_size: u8,
+    frequency: u32,
+    play: Option<StreamPlay>,
+}
+
+#[derive(Clone, Copy)]
+struct StreamPlay {
+    start: u64,
+    offset: u64,
+    // Bytes to play, `None` when unknown.
+    length: Option<u64>,
+    looped: bool,
+    reverse: bool,
+}
+
+struct Player<'a> {
+    document: &'a VgmDocument,
+    time: u64,
+    sample_rate: u64,
+    clocks: Vec<(Instance, Chip, f32)>,
+    chips: Vec<ChipPlayer>,
+    streams: HashMap<u8, DacStream>,
+    // (bank, offset, size) per data block id.
+    blocks: Vec<(u8, u64, u64)>,
+    bank_sizes: HashMap<u8, u64>,
+}
+
+impl<'a> Player<'a> {
+    fn new(document: &'a VgmDocument) -> Self {
+        let mut blocks = Vec::new();
+        let mut bank_sizes: HashMap<u8, u64> = HashMap::new();
+        for command in &document.commands {
+            let VgmCommand::DataBlock(block) = command else {
+                continue;
+            };
+            if block.data_type == 0x7F {
+                continue;
+            }
+            let (bank, size) = match parse_data_block((**block).clone()) {
+                Ok(DataBlockType::UncompressedStream(stream)) => {
+                    (block.data_type, stream.data.len() as u64)
+                }
+                Ok(DataBlockType::CompressedStream(stream)) => {
+                    (block.data_type, stream.uncompressed_size as u64)
+                }
+                _ => (block.data_type, block.data.len() as u64),
+            };
+            let bank_size = bank_sizes.entry(bank).or_insert(0);
+            blocks.push((bank, *bank_size, size));
+            *bank_size += size;
+        }
+        Player {
+            document,
+            time: 0,
+            sample_rate: document.header.effective_sample_rate() as u64,
+            clocks: document.header.chip_instances().0,
+            chips: Vec::new(),
+            streams: HashMap::new(),
+            blocks,
+            bank_sizes,
+        }
+    }
+
+    fn would_wait(&self, index: usize) -> bool {
+        self.document.commands[index].wait_samples() > 0
+    }
+
+    fn play(&mut self, index: usize) {
+        let command = &self.document.commands[index];
+        match command {
+            VgmCommand::SetStreamData(data) => {
+                let stream = self.streams.entry(data.stream_id).or_default();
+                stream.bank = data.data_bank_id;
+                stream.step_size = data.step_size;
+            }
+            VgmCommand::SetStreamFrequency(frequency) => {
+                let stream = self.streams.entry(frequency.stream_id).or_default();
+                stream.frequency = frequency.frequency;
+            }
+            VgmCommand::StartStream(start) => {
+                let time = self.time;
+                let bank_size = {
+                    let bank = self.streams.entry(start.stream_id).or_default().bank;
+                    self.bank_sizes.get(&bank).copied()
+                };
+                let stream = self.streams.entry(start.stream_id).or_default();
+                let step = stream.step_size.max(1) as u64;
+                let offset = start.data_start_offset.max(0) as u64;
+                let (length, reverse, looped) = match start.length_mode {
+                    LengthMode::CommandCount { reverse, looped } => {
+                        (Some(start.data_length as u64 * step), reverse, looped)
+                    }
+                    LengthMode::Milliseconds { reverse, looped } => (
+                        Some(start.data_length as u64 * stream.frequency as u64 / 1000 * step),
+                        reverse,
+                        looped,
+                    ),
+                    LengthMode::PlayUntilEnd { reverse, looped }
+                    | LengthMode::Ignore { reverse, looped } => (
+                        bank_size.map(|size| size.saturating_sub(offset)),
+                        reverse,
+                        looped,
+                    ),
+                    LengthMode::Unknown(_) => (None, false, false),
+                };
+                stream.play = Some(StreamPlay {
+                    start: time,
+                    offset,
+                    length,
+                    looped,
+                    reverse,
+                });
+            }
+            VgmCommand::StartStreamFastCall(fast) => {
+                let block = self.blocks.get(fast.block_id as usize).copied();
+                let stream = self.streams.entry(fast.stream_id).or_default();
+                stream.play = Some(StreamPlay {
+                    start: self.time,
+                    offset: block.map_or(0, |(_, offset, _)| offset),
+                    length: block.map(|(_, _, size)| size),
+                    looped: fast.flags.looped,
+                    reverse: fast.flags.reverse,
+                });
+            }
+            VgmCommand::StopStream(stop) => {
+                if stop.stream_id == 0xFF {
+                    self.streams.values_mut().for_each(|s| s.play = None);
+                } else if let Some(stream) = self.streams.get_mut(&stop.stream_id) {
+                    stream.play = None;
+                }
+            }
+            VgmCommand::GameGearPsgWrite(..) => {}
+            _ => {
+                if let Some(write) = command.register_write() {
+                    self.write(command, write);
+                }
+            }
+        }
+        self.time += command.wait_samples() as u64;
+    }
+
+    fn write(&mut self, command: &VgmCommand, write: RegisterWrite) {
+        let RegisterWrite {
+            chip,
+            instance,
+            port,
+            register,
+            value,
+        } = write;
+        let index = match self
+            .chips
+            .iter()
+            .position(|c| c.chip == chip && c.instance == instance)
+        {
+            Some(index) => index,
+            None => {
+                let clock = self
+                    .clocks
+                    .iter()
+                    .find(|(i, c, _)| *c == chip && *i == instance)
+                    .map_or(0.0, |(_, _, clock)| *clock);
+                self.chips.push(ChipPlayer {
+                    tracker: tracker(&chip, clock),
+                    chip,
+                    instance,
+                    template: command.clone(),
+                    registers: BTreeMap::new(),
+                });
+                self.chips.len() - 1
+            }
+        };
+        let chip = &mut self.chips[index];
+        let (register, value) = (register as u8, value as u8);
+        if let Some(tracker) = chip.tracker.as_mut() {
+            chip.registers.insert((port, register), value);
+            tracker.write(port, register, value);
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            time: self.time,
+            keys: self
+                .chips
+                .iter()
+                .map(|chip| chip.tracker.as_ref().map(|t| t.keys()).unwrap_or_default())
+                .collect(),
+            registers: self
+                .chips
+                .iter()
+                .map(|chip| chip.registers.clone())
+                .collect(),
+            streams: self
+                .streams
+                .iter()
+                .map(|(&id, stream)| (id, self.stream_snapshot(stream)))
+                .collect(),
+        }
+    }
+
+    fn stream_snapshot(&self, stream: &DacStream) -> StreamSnapshot {
+        let mut snapshot = StreamSnapshot {
+            position: None,
+            remaining: None,
+            bank: stream.bank,
+            frequency: stream.frequency,
+            step_size: stream.step_size,
+            looped: false,
+            reverse: false,
+        };
+        let Some(play) = stream.play else {
+            return snapshot;
+        };
+        let steps = (self.time - play.start) * stream.frequency as u64 / self.sample_rate.max(1);
+        let mut bytes = steps * stream.step_size.max(1) as u64;
+        if let Some(length) = play.length
+            && bytes >= length
+        {
+            if !play.looped || length == 0 {
+                return snapshot;
+            }
+            bytes %= length;
+        }
+        snapshot.position = Some(if play.reverse {
+            play.offset.saturating_sub(bytes)
+        } else {
+            play.offset + bytes
+        });
+        snapshot.remaining = play.length.map(|length| length - bytes);
+        snapshot.looped = play.looped;
+        snapshot.reverse = play.reverse;
+        snapshot
+    }
+}
+
+// FM voice registers: operator parameters, algorithm/feedback and the
+// YM2413 user instrument and instrument/volume selection.
+pub(crate) fn is_patch_register(chip: &Chip, register: u8) -> bool {
+    match chip {
+        Chip::Ym2612 | Chip::Ym2203 | Chip::Ym2608 | Chip::Ym2610b => {
+            matches!(register, 0x30..=0x9F | 0xB0..=0xB6)
+        }
+        Chip::Ym2151 => matches!(register, 0x20..=0x27 | 0x38..=0xFF),
+        Chip::Ym3812 | Chip::Ym3526 | Chip::Y8950 | Chip::Ymf262 => matches!(
+            register,
+            0x20..=0x35 | 0x40..=0x55 | 0x60..=0x75 | 0x80..=0x95 | 0xC0..=0xC8 | 0xE0..=0xF5
+        ),
+        Chip::Ym2413 => matches!(register, 0x00..=0x07 | 0x30..=0x38),
+        _ => false,
+    }
+}
@@ -0,0 +1,377 @@
+//! MIDI input mapped to YM2612 register writes.
+//!
+//! Available with the `midi` feature. This turns a MIDI keyboard into a
+//! six-voice YM2612 synth whose register writes are logged as VGM, e.g. to
+//! capture a jam session on real hardware:
+//!
+//! - `MidiMessage::parse` decodes raw note on/off and control change messages
+//!   (running status is not supported; pass complete messages).
+//! - `FmPatch` is a YM2612 voice: algorithm, feedback and the four operators.
+//! - `MidiFmMapper` allocates the six FM channels to notes (the oldest note
+//!   is stolen when all are busy) and returns the writes for each message.
+//!   Velocity and CC 7 (volume) attenuate the carriers, CC 10 (pan) selects
+//!   left/center/right and CC 120/123 release the notes of the MIDI channel.
+//! - `MidiLogger` feeds a `MidiFmMapper` and records the writes with their
+//!   timing into a `VgmBuilder`.
+//!
+//! ```rust
+//! use soundlog::vgm::midi::{FmPatch, MidiLogger, MidiMessage};
+//!
+//! let mut logger = MidiLogger::new(7_670_454, FmPatch::default());
+//! // note on, middle C, then note off half a second later
+//! logger.record(0, &MidiMessage::parse(&[0x90, 60, 100]).unwrap());
+//! logger.record(22_050, &MidiMessage::parse(&[0x80, 60, 0]).unwrap());
+//! let doc = logger.finish();
+//!
+//! assert_eq!(doc.header.total_samples, 22_050);
+//! ```
+use crate::VgmDocument;
+use crate::chip::fnumber::{
+    FNumberEntry, OpnaSpec, find_and_tune_fnumber, generate_12edo_fnum_table,
+};
+use crate::chip::patch::{FM_CARRIERS, OPN_SLOT_OFFSETS};
+pub use crate::chip::patch::{FmOperator, FmPatch};
+use crate::chip::{Chip, Ym2612Spec};
+use crate::vgm::VgmBuilder;
+use crate::vgm::command::{Instance, WaitSamples};
+
+/// A channel voice message relevant to `MidiFmMapper`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MidiMessage {
+    /// Note on with a non-zero velocity.
+    NoteOn { channel: u8, note: u8, velocity: u8 },
+    /// Note off, or note on with velocity 0.
+    NoteOff { channel: u8, note: u8 },
+    /// Control change.
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+}
+
+impl MidiMessage {
+    /// Decodes one complete MIDI message. Returns `None` for other message
+    /// types and for truncated messages.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let (&status, data) = bytes.split_first()?;
+        let channel = status & 0x0F;
+        let (a, b) = (*data.first()? & 0x7F, *data.get(1)? & 0x7F);
+        match status & 0xF0 {
+            0x90 if b != 0 => Some(MidiMessage::NoteOn {
+                channel,
+                note: a,
+                velocity: b,
+            }),
+            0x80 | 0x90 => Some(MidiMessage::NoteOff { channel, note: a }),
+            0xB0 => Some(MidiMessage::ControlChange {
+                channel,
+                controller: a,
+                value: b,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Number of FM channels of the YM2612.
+pub const VOICES: usize = 6;
+
+#[derive(Debug, Clone, Copy)]
+struct Voice {
+    channel: u8,
+    note: u8,
+    velocity: u8,
+    // Order of note on, for stealing the oldest voice.
+    age: u64,
+}
+
+/// Maps MIDI messages to YM2612 writes.
+///
+/// All MIDI channels play the same patch; the MIDI channel only tells notes
+/// apart. Volume and pan are tracked per MIDI channel.
+#[derive(Debug, Clone)]
+pub struct MidiFmMapper {
+    master_clock: u32,
+    patch: FmPatch,
+    fnum_table: Option<[[Option<FNumberEntry>; 12]; 8]>,
+    voices: [Option<Voice>; VOICES],
+    next_age: u64,
+    volume: [u8; 16],
+    pan: [u8; 16],
+}
+
+impl MidiFmMapper {
+    /// Creates a mapper for a YM2612 running at `master_clock` Hz.
+    pub fn new(master_clock: u32, patch: FmPatch) -> Self {
+        MidiFmMapper {
+            master_clock,
+            patch,
+            fnum_table: generate_12edo_fnum_table::<OpnaSpec>(master_clock as f32).ok(),
+            voices: [None; VOICES],
+            next_age: 0,
+            volume: [100; 16],
+            pan: [64; 16],
+        }
+    }
+
+    /// The patch played by every voice.
+    pub fn patch(&self) -> &FmPatch {
+        &self.patch
+    }
+
+    /// Writes that set up the chip: LFO off, normal channel 3 mode, DAC off
+    /// and the patch on all six channels, panned center.
+    pub fn init_writes(&self) -> Vec<Ym2612Spec> {
+        let mut writes = vec![reg(0, 0x22, 0x00), reg(0, 0x27, 0x00), reg(0, 0x2B, 0x00)];
+        for fm in 0..VOICES as u8 {
+            writes.push(key(fm, false));
+            let (port, ch) = (fm / 3, fm % 3);
+            for (slot, op) in self.patch.operators.iter().enumerate() {
+                let base = ch + OPN_SLOT_OFFSETS[slot];
+                writes.extend([
+                    reg(port, 0x30 + base, op.dt_mul),
+                    reg(port, 0x40 + base, op.tl),
+                    reg(port, 0x50 + base, op.ks_ar),
+                    reg(port, 0x60 + base, op.am_dr),
+                    reg(port, 0x70 + base, op.sr),
+                    reg(port, 0x80 + base, op.sl_rr),
+                    reg(port, 0x90 + base, op.ssg_eg),
+                ]);
+            }
+            writes.push(reg(port, 0xB0 + ch, self.patch.feedback_algorithm()));
+            writes.push(reg(port, 0xB4 + ch, 0xC0 | self.patch.lfo_sensitivity()));
+        }
+        writes
+    }
+
+    /// Returns the writes for `message`; empty when it changes nothing.
+    pub fn handle(&mut self, message: &MidiMessage) -> Vec<Ym2612Spec> {
+        let mut writes = Vec::new();
+        match *message {
+            MidiMessage::NoteOn {
+                channel,
+                note,
+                velocity,
+            } => {
+                let Some((fnum, block)) = self.fnumber(note) else {
+                    return writes;
+                };
+                let fm = self.allocate(channel, note);
+                if self.voices[fm].is_some() {
+                    writes.push(key(fm as u8, false));
+                }
+                self.voices[fm] = Some(Voice {
+                    channel,
+                    note,
+                    velocity,
+                    age: self.next_age,
+                });
+                self.next_age += 1;
+
+                let (port, ch) = (fm as u8 / 3, fm as u8 % 3);
+                writes.push(reg(
+                    port,
+                    0xB4 + ch,
+                    pan_bits(self.pan[channel as usize]) | self.patch.lfo_sensitivity(),
+                ));
+                writes.extend(self.carrier_levels(fm));
+                writes.push(reg(port, 0xA4 + ch, (block << 3) | (fnum >> 8) as u8));
+                writes.push(reg(port, 0xA0 + ch, fnum as u8));
+                writes.push(key(fm as u8, true));
+            }
+            MidiMessage::NoteOff { channel, note } => {
+                if let Some(fm) = self
+                    .voices
+                    .iter()
+                    .position(|v| v.is_some_and(|v| v.channel == channel && v.note == note))
+                {
+                    self.voices[fm] = None;
+                    writes.push(key(fm as u8, false));
+                }
+            }
+            MidiMessage::ControlChange {
+                channel,
+                controller,
+                value,
+            } => match controller {
+                7 | 10 => {
+                    if controller == 7 {
+                        self.volume[channel as usize] = value;
+                    } else {
+                        self.pan[channel as usize] = value;
+                    }
+                    let lfo = self.patch.lfo_sensitivity();
+                    for fm in self.playing(channel) {
+                        if controller == 7 {
+                            writes.extend(self.carrier_levels(fm));
+                        } else {
+                            let (port, ch) = (fm as u8 / 3, fm as u8 % 3);
+                            writes.push(reg(port, 0xB4 + ch, pan_bits(value) | lfo));
+                        }
+                    }
+                }
+                120 | 123 => {
+                    for fm in self.playing(channel) {
+                        self.voices[fm] = None;
+                        writes.push(key(fm as u8, false));
+                    }
+                }
+                _ => {}
+            },
+        }
+        writes
+    }
+
+    // F-number and block of a MIDI note, `None` outside the chip's range.
+    fn fnumber(&self, note: u8) -> Option<(u16, u8)> {
+        let freq = 440.0 * 2f32.powf((note as f32 - 69.0) / 12.0);
+        let table = self.fnum_table.as_ref()?;
+        let f = find_and_tune_fnumber::<OpnaSpec>(table, freq, self.master_clock as f32).ok()?;
+        (f.f_num <= 0x7FF).then_some((f.f_num as u16, f.block))
+    }
+
+    // FM channel for a new note: the one already playing it, a free one, or
+    // the oldest.
+    fn allocate(&self, channel: u8, note: u8) -> usize {
+        let same = self
+            .voices
+            .iter()
+            .position(|v| v.is_some_and(|v| v.channel == channel && v.note == note));
+        same.or_else(|| self.voices.iter().position(Option::is_none))
+            .unwrap_or_else(|| {
+                (0..VOICES)
+                    .min_by_key(|&fm| self.voices[fm].map_or(0, |v| v.age))
+                    .unwrap_or(0)
+            })
+    }
+
+    fn playing(&self, channel: u8) -> Vec<usize> {
+        (0..VOICES)
+            .filter(|&fm| self.voices[fm].is_some_and(|v| v.channel == channel))
+            .collect()
+    }
+
+    // Carrier TL writes of FM channel `fm` for its velocity and the volume of
+    // its MIDI channel.
+    fn carrier_levels(&self, fm: usize) -> Vec<Ym2612Spec> {
+        let Some(voice) = self.voices[fm] else {
+            return Vec::new();
+        };
+        // Both scale the amplitude, so add their attenuations in dB; one TL
+        // step is 0.75 dB.
+        let attenuation = |level: u8| -> f32 {
+            if level == 0 {
+                f32::INFINITY
+            } else {
+                40.0 * (127.0 / level as f32).log10()
+            }
+        };
+        let db = attenuation(voice.velocity) + attenuation(self.volume[voice.channel as usize]);
+        let extra = (db / 0.75).round().min(127.0) as u8;
+
+        let (port, ch) = (fm as u8 / 3, fm as u8 % 3);
+        let carriers = FM_CARRIERS[self.patch.algorithm as usize & 7];
+        (0..4)
+            .filter(|slot| carriers & (1 << slot) != 0)
+            .map(|slot| {
+                let tl = self.patch.operators[slot]
+                    .tl
+                    .saturating_add(extra)
+                    .min(0x7F);
+                reg(port, 0x40 + ch + OPN_SLOT_OFFSETS[slot], tl)
+            })
+            .collect()
+    }
+}
+
+fn reg(port: u8, register: u8, value: u8) -> Ym2612Spec {
+    Ym2612Spec {
+        port,
+        register,
+        value,
+    }
+}
+
+// Key on/off of all four slots of FM channel `fm` (0-5).
+fn key(fm: u8, on: bool) -> Ym2612Spec {
+    let channel = if fm < 3 { fm } else { fm + 1 };
+    reg(0, 0x28, if on { 0xF0 } else { 0x00 } | channel)
+}
+
+// Output enable bits of register 0xB4 for a MIDI pan value.
+fn pan_bits(pan: u8) -> u8 {
+    match pan {
+        0..=42 => 0x80,
+        43..=84 => 0xC0,
+        _ => 0x40,
+    }
+}
+
+/// Records MIDI input as a YM2612 VGM.
+///
+/// Messages are recorded with the time they arrived at, in samples (44.1
+/// kHz) since the start of the recording; waits are inserted between them.
+pub struct MidiLogger {
+    builder: VgmBuilder,
+    mapper: MidiFmMapper,
+    sample: u64,
+}
+
+impl MidiLogger {
+    /// Starts a recording: registers a YM2612 at `master_clock` Hz and writes
+    /// `patch` to all channels.
+    pub fn new(master_clock: u32, patch: FmPatch) -> Self {
+        let mapper = MidiFmMapper::new(master_clock, patch);
+        let mut builder = VgmBuilder::new();
+        builder.register_chip(Chip::Ym2612, Instance::Primary, master_clock);
+        for write in mapper.init_writes() {
+            builder.add_chip_write(Instance::Primary, write);
+        }
+        MidiLogger {
+            builder,
+            mapper,
+            sample: 0,
+        }
+    }
+
+    /// The mapper, e.g. to mirror its writes to a live chip.
+    pub fn mapper(&self) -> &MidiFmMapper {
+        &self.mapper
+    }
+
+    /// Records `message` at `sample`. Times earlier than the previous message
+    /// are treated as simultaneous with it.
+    pub fn record(&mut self, sample: u64, message: &MidiMessage) -> Vec<Ym2612Spec> {
+        self.advance_to(sample);
+        let writes = self.mapper.handle(message);
+        for write in &writes {
+            self.builder
+                .add_chip_write(Instance::Primary, write.clone());
+        }
+        writes
+    }
+
+    /// Ends the recording at the time of the last message and returns the
+    /// document.
+    pub fn finish(self) -> VgmDocument {
+        self.builder.finalize()
+    }
+
+    /// Ends the recording at `sample` (so trailing release tails are kept)
+    /// and returns the document.
+    pub fn finish_at(mut self, sample: u64) -> VgmDocument {
+        self.advance_to(sample);
+        self.builder.finalize()
+    }
+
+    fn advance_to(&mut self, sample: u64) {
+        let mut wait = sample.saturating_sub(self.sample);
+        self.sample = self.sample.max(sample);
+        while wait > 0 {
+            let chunk = wait.min(u16::MAX as u64);
+            self.builder.add_vgm_command(WaitSamples(chunk as u16));
+            wait -= chunk;
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//! Length and kind of VGM command opcodes.
+//!
+//! Tools that scan a VGM command stream without decoding it (hex viewers,
+//! fuzzers, seek indexes) need the length of every command from its opcode.
+//! `length_of` gives it as the crate's own parser sees it, and `kind_of`
+//! tells waits, chip writes, data and DAC stream control apart:
+//!
+//! - most opcodes have a fixed length (`OpcodeLen::Fixed`), opcode included,
+//! - the `0x67` data block carries its payload size in the command
+//!   (`OpcodeLen::Variable`); `command_len` reads it,
+//! - opcodes the VGM specification does not define (`0x00`-`0x2F`, `0x60`,
+//!   `0x64`, `0x65`, `0x69`-`0x6F`, `0x96`-`0x9F`) are `OpcodeLen::Unknown`.
+//!   The parser keeps them as a one-byte `VgmCommand::UnknownCommand`.
+//!
+//! Opcodes reserved by the specification for future chips have the length
+//! the specification gives them and the kind `OpcodeKind::Reserved`.
+//!
+//! `min_version` and `chip_min_version` give the VGM version that
+//! introduced a command or a chip, in the BCD form of `VgmHeader::version`
+//! (`0x151` for 1.51). Players written for an older version skip or
+//! misread them. `VgmDocument::required_version` combines both for a
+//! document, and the strict `lint` reports commands newer than the header
+//! version.
+//!
+//! ```rust
+//! use soundlog::vgm::opcode::{OpcodeKind, OpcodeLen, command_len, kind_of, length_of};
+//!
+//! assert_eq!(length_of(0x52), OpcodeLen::Fixed(3));
+//! assert_eq!(kind_of(0x52), OpcodeKind::ChipWrite);
+//! assert_eq!(length_of(0x61), OpcodeLen::Fixed(3));
+//! assert_eq!(kind_of(0x61), OpcodeKind::Wait);
+//! assert_eq!(length_of(0x67), OpcodeLen::Variable);
+//! assert_eq!(command_len(&[0x67, 0x66, 0x00, 0x02, 0x00, 0x00, 0x00, 0xAA, 0xBB]), Some(9));
+//! assert_eq!(length_of(0x20), OpcodeLen::Unknown);
+//! ```
+use crate::chip::Chip;
+use crate::vgm::command::{VgmCommand, command_to_vgm_bytes};
+use crate::vgm::header::VgmHeaderField;
+
+/// Bytes of a `0x67` data block before its payload: opcode, `0x66`
+/// marker, data type and the 32-bit size.
+pub const DATA_BLOCK_HEADER_LEN: usize = 7;
+
+/// Length of a command, see `length_of`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpcodeLen {
+    /// The command always takes this many bytes, opcode included.
+    Fixed(usize),
+    /// A data block: `DATA_BLOCK_HEADER_LEN` bytes followed by the payload
+    /// size stored in bytes 3-6 (bit 31 selects the chip instance).
+    Variable,
+    /// The opcode is not defined. The parser reads it as a one-byte
+    /// `VgmCommand::UnknownCommand`.
+    Unknown,
+}
+
+/// What a command does, see `kind_of`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpcodeKind {
+    /// Waits (`0x61`-`0x63`, `0x70`-`0x7F`).
+    Wait,
+    /// A chip register or memory write, including the secondary instance
+    /// opcodes and the AY8910 stereo mask (`0x31`).
+    ChipWrite,
+    /// A YM2612 DAC write from the PCM data bank followed by a wait
+    /// (`0x80`-`0x8F`).
+    WriteAndWait,
+    /// Data blocks, PCM RAM writes and the PCM data bank seek (`0x67`,
+    /// `0x68`, `0xE0`).
+    Data,
+    /// DAC stream control (`0x90`-`0x95`).
+    StreamControl,
+    /// End of sound data (`0x66`).
+    EndOfData,
+    /// Reserved by the specification for future use.
+    Reserved,
+    /// Not defined.
+    Unknown,
+}
+
+/// Length of the command starting with `opcode`.
+pub fn length_of(opcode: u8) -> OpcodeLen {
+    OpcodeLen::Fixed(match opcode {
+        0x30..=0x3F | 0x4F | 0x50 | 0x94 => 2,
+        0x40..=0x4E | 0x51..=0x5F | 0x61 | 0xA0..=0xBF => 3,
+        0xC0..=0xDF => 4,
+        0x90 | 0x91 | 0x95 | 0xE0..=0xFF => 5,
+        0x92 => 6,
+        0x93 => 11,
+        0x68 => 12,
+        0x62 | 0x63 | 0x66 | 0x70..=0x8F => 1,
+        0x67 => return OpcodeLen::Variable,
+        _ => return OpcodeLen::Unknown,
+    })
+}
+
+/// Kind of the command starting with `opcode`.
+pub fn kind_of(opcode: u8) -> OpcodeKind {
+    match opcode {
+        0x61..=0x63 | 0x70..=0x7F => OpcodeKind::Wait,
+        0x30 | 0x31 | 0x3F | 0x40 | 0x4F..=0x5F | 0xA0..=0xC8 | 0xD0..=0xD6 | 0xE1 => {
+            OpcodeKind::ChipWrite
+        }
+        0x80..=0x8F => OpcodeKind::WriteAndWait,
+        0x67 | 0x68 | 0xE0 => OpcodeKind::Data,
+        0x90..=0x95 => OpcodeKind::StreamControl,
+        0x66 => OpcodeKind::EndOfData,
+        0x32..=0x3E | 0x41..=0x4E | 0xC9..=0xCF | 0xD7..=0xDF | 0xE2..=0xFF => OpcodeKind::Reserved,
+        _ => OpcodeKind::Unknown,
+    }
+}
+
+/// Length of the command at the start of `bytes`, reading the payload size
+/// of data blocks. Unknown opcodes count as one byte, like the parser reads
+/// them. `None` when `bytes` is empty or ends inside a data block header;
+/// the returned length may exceed `bytes.len()` for a truncated command.
+pub fn command_len(bytes: &[u8]) -> Option<usize> {
+    match length_of(*bytes.first()?) {
+        OpcodeLen::Fixed(len) => Some(len),
+        OpcodeLen::Unknown => Some(1),
+        OpcodeLen::Variable => {
+            let size = bytes.get(3..DATA_BLOCK_HEADER_LEN)?;
+            let size = u32::from_le_bytes([size[0], size[1], size[2], size[3]]) & 0x7FFF_FFFF;
+            Some(DATA_BLOCK_HEADER_LEN + size as usize)
+        }
+    }
+}
+
+/// Opcode `command` is written with, including the secondary instance
+/// opcodes.
+pub fn opcode_of(command: &VgmCommand) -> u8 {
+    match command {
+        // Skip copying the payload.
+        VgmCommand::DataBlock(_) => 0x67,
+        VgmCommand::PcmRamWrite(_) => 0x68,
+        command => command_to_vgm_bytes(command).0[0],
+    }
+}
+
+/// VGM version that introduced the command starting with `opcode`, `None`
+/// for reserved and undefined opcodes.
+///
+/// YM2612 and YM2151 writes (`0x52`-`0x54`) date from 1.00, when both chips
+/// took the YM2413 clock; their own clocks came with 1.10, see
+/// `chip_min_version`.
+pub fn min_version(opcode: u8) -> Option<u32> {
+    Some(match opcode {
+        0x4F..=0x54 | 0x61..=0x63 | 0x66 => 0x100,
+        0x67 | 0x70..=0x8F | 0xE0 => 0x150,
+        // Dual chip support came with 1.51.
+        0x30 | 0x3F | 0x55..=0x5F | 0xA0..=0xB2 | 0xC0..=0xC2 | 0xD0 | 0xD1 => 0x151,
+        0x68 | 0x90..=0x95 => 0x160,
+        0xB3..=0xBB | 0xC3 | 0xC4 | 0xD2..=0xD4 => 0x161,
+        0x31 | 0xBC..=0xBF | 0xC5..=0xC8 | 0xD5 | 0xD6 | 0xE1 => 0x171,
+        0x40 => 0x172,
+        _ => return None,
+    })
+}
+
+/// VGM version whose header has a clock for `chip`.
+pub fn chip_min_version(chip: &Chip) -> u32 {
+    VgmHeaderField::clock_of(chip).min_version()
+}
@@ -0,0 +1,185 @@
+//! Forward chip state events over OSC (Open Sound Control).
+//!
+//! Available with the `osc` feature. `OscBridge` sends every `StateEvent` as
+//! one OSC 1.1 message over UDP, so VJ software, lighting desks or LED
+//! visualizers can follow the music without parsing VGM themselves. Only the
+//! standard library is used.
+//!
+//! Messages are addressed `<prefix>/<chip>/<instance>/<event>`, e.g.
+//! `/soundlog/ym2612/0/key_on`, where `<chip>` is the lower-case chip name and
+//! `<instance>` is `0` (primary) or `1` (secondary). The first argument is
+//! always the sample position as a 64-bit integer (`h`):
+//!
+//! | event         | arguments                                                 |
+//! |---------------|-----------------------------------------------------------|
+//! | `key_on`      | `h` sample, `i` channel, `i` fnum, `i` block, `f` Hz or `N` |
+//! | `tone_change` | same as `key_on`                                          |
+//! | `key_off`     | `h` sample, `i` channel                                   |
+//! | `sample_start`| `h` sample, `i` channel, `i` ROM offset, `i` length         |
+//! | `rhythm_hit`  | `h` sample, `i` channel, `s` instrument, `i` attenuation    |
+//!
+//! The frequency is `N` (nil) when the state tracker could not compute it.
+//! The instrument is `RhythmInstrument::name`, e.g. `bass_drum`.
+//!
+//! Pass the absolute clock (`SampleClock::Absolute`) so timestamps keep
+//! increasing when the song loops:
+//!
+//! ```no_run
+//! use std::net::UdpSocket;
+//! use soundlog::VgmCallbackStream;
+//! use soundlog::chip::{Chip, Ym2612Spec, state::Ym2612State};
+//! use soundlog::vgm::callback_stream::SampleClock;
+//! use soundlog::vgm::command::Instance;
+//! use soundlog::vgm::osc::OscBridge;
+//!
+//! # let doc = soundlog::VgmDocument::default();
+//! let socket = UdpSocket::bind("0.0.0.0:0")?;
+//! socket.connect("127.0.0.1:9000")?;
+//! let bridge = OscBridge::new(socket);
+//!
+//! let mut stream = VgmCallbackStream::from_document(doc);
+//! stream.set_sample_clock(SampleClock::Absolute);
+//! stream.track_state::<Ym2612State>(Instance::Primary, 7_670_454.0);
+//! stream.on_write(move |instance, _spec: Ym2612Spec, sample, events| {
+//!     for event in events.iter().flatten() {
+//!         let _ = bridge.send(&Chip::Ym2612, instance, sample as u64, event);
+//!     }
+//! });
+//! for _ in stream {}
+//! # Ok::<(), std::io::Error>(())
+//! ```
+use std::io;
+use std::net::UdpSocket;
+
+use crate::chip::Chip;
+use crate::chip::event::{StateEvent, ToneInfo};
+use crate::vgm::command::Instance;
+
+/// Address prefix used unless changed with `OscBridge::set_prefix`.
+pub const DEFAULT_PREFIX: &str = "/soundlog";
+
+/// Sends `StateEvent`s as OSC messages over a connected UDP socket.
+#[derive(Debug)]
+pub struct OscBridge {
+    socket: UdpSocket,
+    prefix: String,
+}
+
+impl OscBridge {
+    /// Creates a bridge sending to the peer `socket` is connected to.
+    pub fn new(socket: UdpSocket) -> Self {
+        OscBridge {
+            socket,
+            prefix: DEFAULT_PREFIX.to_string(),
+        }
+    }
+
+    /// Sets the address prefix (default `/soundlog`).
+    pub fn set_prefix(&mut self, prefix: impl Into<String>) {
+        self.prefix = prefix.into();
+    }
+
+    /// Gets the address prefix.
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Sends `event` of `chip`/`instance` at `sample` as one datagram.
+    pub fn send(
+        &self,
+        chip: &Chip,
+        instance: Instance,
+        sample: u64,
+        event: &StateEvent,
+    ) -> io::Result<()> {
+        let message = event_message(&self.prefix, chip, instance, sample, event);
+        self.socket.send(&message).map(|_| ())
+    }
+}
+
+/// Encodes `event` as an OSC message with the layout described in the module
+/// documentation.
+pub fn event_message(
+    prefix: &str,
+    chip: &Chip,
+    instance: Instance,
+    sample: u64,
+    event: &StateEvent,
+) -> Vec<u8> {
+    let (name, channel, tone, sample_range, rhythm) = match event {
+        StateEvent::KeyOn { channel, tone } => ("key_on", channel, Some(tone), None, None),
+        StateEvent::ToneChange { channel, tone } => {
+            ("tone_change", channel, Some(tone), None, None)
+        }
+        StateEvent::KeyOff { channel } => ("key_off", channel, None, None, None),
+        StateEvent::SampleStart {
+            channel,
+            offset,
+            length,
+        } => ("sample_start", channel, None, Some((offset, length)), None),
+        StateEvent::RhythmHit {
+            channel,
+            instrument,
+            attenuation,
+        } => (
+            "rhythm_hit",
+            channel,
+            None,
+            None,
+            Some((instrument, attenuation)),
+        ),
+    };
+    let address = format!(
+        "{}/{}/{}/{}",
+        prefix,
+        format!("{:?}", chip).to_lowercase(),
+        usize::from(instance),
+        name
+    );
+
+    let mut tags = String::from(",hi");
+    let mut args = Vec::new();
+    args.extend_from_slice(&sample.to_be_bytes());
+    args.extend_from_slice(&(*channel as i32).to_be_bytes());
+    if let Some(ToneInfo {
+        fnum,
+        block,
+        freq_hz,
+        ..
+    }) = tone
+    {
+        tags.push_str("ii");
+        args.extend_from_slice(&(*fnum as i32).to_be_bytes());
+        args.extend_from_slice(&(*block as i32).to_be_bytes());
+        match freq_hz {
+            Some(hz) => {
+                tags.push('f');
+                args.extend_from_slice(&hz.to_be_bytes());
+            }
+            None => tags.push('N'),
+        }
+    }
+    if let Some((offset, length)) = sample_range {
+        tags.push_str("ii");
+        args.extend_from_slice(&(*offset as i32).to_be_bytes());
+        args.extend_from_slice(&(*length as i32).to_be_bytes());
+    }
+    if let Some((instrument, attenuation)) = rhythm {
+        tags.push_str("si");
+        push_string(&mut args, instrument.name());
+        args.extend_from_slice(&(*attenuation as i32).to_be_bytes());
+    }
+
+    let mut message = Vec::with_capacity(address.len() + tags.len() + args.len() + 8);
+    push_string(&mut message, &address);
+    push_string(&mut message, &tags);
+    message.extend_from_slice(&args);
+    message
+}
+
+// OSC strings are NUL terminated and padded to a multiple of 4 bytes.
+fn push_string(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(s.as_bytes());
+    let padding = 4 - s.len() % 4;
+    buf.extend(std::iter::repeat_n(0, padding));
+}
@@ -8,6 +8,8 @@
 //!
 //! Public (crate-visible) entry points:
 //! - `parse_vgm(bytes)` — parse an entire VGM file into a `VgmDocument`.
+//! - `parse_vgm_with_hooks(bytes, hooks)` — the same, driven by a
+//!   `ParseHooks` (a progress callback, a `CancelToken` and `ParseLimits`).
 //! - `parse_vgm_header(bytes)` — parse only the VGM header and return
 //!   the header plus the header size in bytes.
 //! - `parse_vgm_extra_header(bytes, offset)` — parse the v1.70+ extra
@@ -29,7 +31,13 @@
 //! - GD3 metadata, when present, is parsed via `crate::meta::parse_gd3`.
 //!   GD3 parsing errors are propagated to the caller when parsing the
 //!   full document.
-use crate::binutil::{ParseError, read_slice, read_u8_at, read_u16_le_at, read_u32_le_at};
+use crate::binutil::{
+    ParseError, ResourceLimit, read_slice, read_u8_at, read_u16_le_at, read_u32_le_at,
+    resolve_relative,
+};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use crate::chip;
 use crate::meta::parse_gd3;
 use crate::vgm::command::{
@@ -61,12 +69,189 @@ use crate::vgm::header::{
 /// Returns `Ok(VgmDocument)` on success or a `ParseError` if header or
 /// any command parsing fails.
 pub(crate) fn parse_vgm(bytes: &[u8]) -> Result<VgmDocument, ParseError> {
+    parse_vgm_with_limits(bytes, &ParseLimits::unlimited(), |_| true)
+}
+
+/// Shared flag that aborts a parse from another thread.
+///
+/// Clones share the flag: hand one to the parsing thread through
+/// `ParseHooks::with_cancel` and call `cancel` on another.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// A token that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask every parse holding a clone of this token to stop.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether `cancel` was called on this token or one of its clones.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Resource limits for parsing untrusted input.
+///
+/// The parser checks every limit as soon as the value is known and stops
+/// with `ParseError::LimitExceeded` naming the limit, before the rest of the
+/// input is read. `ParseLimits::default()` sets no limit, like
+/// `VgmDocument::try_from`; `ParseLimits::untrusted()` is a starting point
+/// for servers parsing uploads.
+///
+/// ```rust
+/// use soundlog::{ParseError, ResourceLimit, VgmDocument};
+/// use soundlog::vgm::parser::{ParseHooks, ParseLimits};
+///
+/// let bytes: Vec<u8> = VgmDocument::default().into();
+/// let limits = ParseLimits {
+///     max_file_size: 16,
+///     ..ParseLimits::untrusted()
+/// };
+/// let err = VgmDocument::parse_with_hooks(&bytes, ParseHooks::new().with_limits(limits))
+///     .unwrap_err();
+/// assert!(matches!(
+///     err,
+///     ParseError::LimitExceeded { limit: ResourceLimit::FileSize, .. }
+/// ));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseLimits {
+    /// Largest input in bytes.
+    pub max_file_size: usize,
+    /// Largest payload of one data block in bytes.
+    pub max_data_block_size: usize,
+    /// Largest payload of all data blocks together in bytes.
+    pub max_total_data_block_size: usize,
+    /// Most commands in the command stream, `EndOfData` included. Every
+    /// parsed command takes far more memory than its bytes in the file.
+    pub max_commands: usize,
+    /// Largest uncompressed size a compressed data block (`0x40..=0x7E`)
+    /// may declare, in bytes.
+    pub max_decompressed_size: usize,
+}
+
+impl ParseLimits {
+    /// No limits.
+    pub const fn unlimited() -> Self {
+        ParseLimits {
+            max_file_size: usize::MAX,
+            max_data_block_size: usize::MAX,
+            max_total_data_block_size: usize::MAX,
+            max_commands: usize::MAX,
+            max_decompressed_size: usize::MAX,
+        }
+    }
+
+    /// Limits that fit any real VGM file: 64 MiB of input, 32 MiB of data
+    /// blocks (the `VgmStream` defaults), 16 Mi commands and 32 MiB per
+    /// decompressed block.
+    pub const fn untrusted() -> Self {
+        ParseLimits {
+            max_file_size: 64 * 1024 * 1024,
+            max_data_block_size: 32 * 1024 * 1024,
+            max_total_data_block_size: 32 * 1024 * 1024,
+            max_commands: 16 * 1024 * 1024,
+            max_decompressed_size: 32 * 1024 * 1024,
+        }
+    }
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
+pub(crate) fn check_limit(
+    limit: ResourceLimit,
+    value: usize,
+    max: usize,
+) -> Result<(), ParseError> {
+    if value > max {
+        return Err(ParseError::LimitExceeded { limit, value, max });
+    }
+    Ok(())
+}
+
+/// Progress callback, cancellation and limits for
+/// `VgmDocument::parse_with_hooks`.
+#[derive(Default)]
+pub struct ParseHooks<'a> {
+    progress: Option<Box<dyn FnMut(usize, usize) + 'a>>,
+    cancel: Option<CancelToken>,
+    limits: ParseLimits,
+}
+
+impl<'a> ParseHooks<'a> {
+    /// No progress reports, no cancellation.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call `progress(bytes_done, total)` about every 64 KiB of command
+    /// stream and once when the command stream is done. `total` is the
+    /// length of the input.
+    pub fn on_progress(mut self, progress: impl FnMut(usize, usize) + 'a) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Stop with `ParseError::Cancelled` at the next progress report after
+    /// `token` is cancelled.
+    pub fn with_cancel(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Stop with `ParseError::LimitExceeded` when the input exceeds `limits`.
+    pub fn with_limits(mut self, limits: ParseLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+}
+
+/// `parse_vgm` driven by `hooks`.
+pub(crate) fn parse_vgm_with_hooks(
+    bytes: &[u8],
+    mut hooks: ParseHooks<'_>,
+) -> Result<VgmDocument, ParseError> {
+    let total = bytes.len();
+    let limits = hooks.limits;
+    parse_vgm_with_limits(bytes, &limits, |done| {
+        if let Some(progress) = hooks.progress.as_mut() {
+            progress(done, total);
+        }
+        !hooks.cancel.as_ref().is_some_and(CancelToken::is_cancelled)
+    })
+}
+
+/// Bytes of command stream parsed between two calls of the progress callback.
+const PROGRESS_INTERVAL: usize = 64 * 1024;
+
+/// `parse_vgm` stopping with `ParseError::LimitExceeded` when the input
+/// exceeds `limits`. `progress` is called with the number of bytes consumed
+/// about every `PROGRESS_INTERVAL` bytes of commands and once more when the
+/// command stream is done; returning `false` stops parsing with
+/// `ParseError::Cancelled`.
+pub(crate) fn parse_vgm_with_limits(
+    bytes: &[u8],
+    limits: &ParseLimits,
+    mut progress: impl FnMut(usize) -> bool,
+) -> Result<VgmDocument, ParseError> {
+    check_limit(ResourceLimit::FileSize, bytes.len(), limits.max_file_size)?;
     let (header, mut off) = parse_vgm_header(bytes)?;
+    let mut data_block_total: usize = 0;
+    let mut next_report = off + PROGRESS_INTERVAL;
 
     let mut commands: Vec<VgmCommand> = Vec::new();
 
-    let gd3_start_opt =
-        (header.gd3_offset != 0).then(|| header.gd3_offset.wrapping_add(0x14) as usize);
+    let gd3_start_opt = gd3_start(&header)?;
 
     while off < bytes.len() {
         if let Some(gd3_start) = gd3_start_opt
@@ -75,39 +260,92 @@ pub(crate) fn parse_vgm(bytes: &[u8]) -> Result<VgmDocument, ParseError> {
             break;
         }
 
+        check_limit(
+            ResourceLimit::CommandCount,
+            commands.len() + 1,
+            limits.max_commands,
+        )?;
         let (cmd, cons) = parse_vgm_command(bytes, off)?;
+        if let VgmCommand::DataBlock(block) = &cmd {
+            check_data_block(block.data_type, &block.data, &mut data_block_total, limits)?;
+        }
         commands.push(cmd.clone());
-        off = off.wrapping_add(cons);
+        off += cons;
 
         if let VgmCommand::EndOfData(_) = commands.last().unwrap() {
             break;
         }
+        if off >= next_report {
+            if !progress(off) {
+                return Err(ParseError::Cancelled { offset: off });
+            }
+            next_report = off + PROGRESS_INTERVAL;
+        }
+    }
+    if !progress(off.min(bytes.len())) {
+        return Err(ParseError::Cancelled { offset: off });
     }
 
     // Attach GD3 metadata if present (gd3_offset is stored as gd3_start - 0x14).
-    let gd3 = if header.gd3_offset != 0 {
-        let gd3_start = header.gd3_offset.wrapping_add(0x14) as usize;
-        // If the computed start is outside the buffer, treat it as an out-of-range offset.
-        if gd3_start >= bytes.len() {
-            return Err(ParseError::OffsetOutOfRange {
-                offset: gd3_start,
-                needed: 1,
-                available: bytes.len(),
-                context: Some("gd3_start".into()),
-            });
-        }
+    let gd3 = match gd3_start_opt {
         // Attempt to parse GD3 and propagate any parse error to the caller.
-        match parse_gd3(&bytes[gd3_start..]) {
-            Ok(g) => Some(g),
-            Err(e) => return Err(e),
-        }
-    } else {
-        None
+        Some(gd3_start) => Some(parse_gd3(gd3_chunk(bytes, gd3_start)?)?),
+        None => None,
     };
+    let extra_header = parse_attached_extra_header(bytes, &header)?;
+
+    Ok(VgmDocument {
+        header,
+        commands,
+        gd3,
+        extra_header,
+    })
+}
 
+/// Absolute start of the GD3 chunk, `None` when the header has no GD3
+/// offset. `gd3_offset` is stored relative to its own field (0x14).
+pub(crate) fn gd3_start(header: &VgmHeader) -> Result<Option<usize>, ParseError> {
+    if header.gd3_offset == 0 {
+        return Ok(None);
+    }
+    Ok(Some(
+        resolve_relative(
+            VgmHeaderField::Gd3Offset.offset(),
+            header.gd3_offset,
+            "gd3_offset",
+        )?
+        .get(),
+    ))
+}
+
+/// The bytes from the GD3 chunk at `gd3_start` to the end of the input.
+pub(crate) fn gd3_chunk(bytes: &[u8], gd3_start: usize) -> Result<&[u8], ParseError> {
+    // If the computed start is outside the buffer, treat it as an out-of-range offset.
+    if gd3_start >= bytes.len() {
+        return Err(ParseError::OffsetOutOfRange {
+            offset: gd3_start,
+            needed: 1,
+            available: bytes.len(),
+            context: Some("gd3_start".into()),
+        });
+    }
+    Ok(&bytes[gd3_start..])
+}
+
+/// The extra header `header` points to, `None` when it has no extra header
+/// offset.
+pub(crate) fn parse_attached_extra_header(
+    bytes: &[u8],
+    header: &VgmHeader,
+) -> Result<Option<VgmExtraHeader>, ParseError> {
     // Attach extra header if present (extra_header_offset stored at 0xBC in main header).
     let extra_header = if header.extra_header_offset != 0 {
-        let start = header.extra_header_offset.wrapping_add(0xBC) as usize;
+        let start = resolve_relative(
+            VgmHeaderField::ExtraHeaderOffset.offset(),
+            header.extra_header_offset,
+            "extra_header_offset",
+        )?
+        .get();
         // If the computed start is outside the buffer, treat it as an out-of-range offset.
         if start >= bytes.len() {
             return Err(ParseError::OffsetOutOfRange {
@@ -129,13 +367,40 @@ pub(crate) fn parse_vgm(bytes: &[u8]) -> Result<VgmDocument, ParseError> {
     } else {
         None
     };
+    Ok(extra_header)
+}
 
-    Ok(VgmDocument {
-        header,
-        commands,
-        gd3,
-        extra_header,
-    })
+// Check the payload of a data block of `data_type` against `limits`, adding
+// its size to `total`.
+pub(crate) fn check_data_block(
+    data_type: u8,
+    data: &[u8],
+    total: &mut usize,
+    limits: &ParseLimits,
+) -> Result<(), ParseError> {
+    let size = data.len();
+    check_limit(
+        ResourceLimit::DataBlockSize,
+        size,
+        limits.max_data_block_size,
+    )?;
+    *total = total.saturating_add(size);
+    check_limit(
+        ResourceLimit::TotalDataBlockSize,
+        *total,
+        limits.max_total_data_block_size,
+    )?;
+    // Compressed blocks start with the compression type and the
+    // uncompressed size.
+    if (0x40..=0x7E).contains(&data_type) && data.len() >= 5 {
+        let declared = u32::from_le_bytes([data[1], data[2], data[3], data[4]]);
+        check_limit(
+            ResourceLimit::DecompressedSize,
+            declared as usize,
+            limits.max_decompressed_size,
+        )?;
+    }
+    Ok(())
 }
 
 /// Parse a VGM header located at the start of `bytes`.
@@ -183,7 +448,12 @@ pub(crate) fn parse_vgm_header(bytes: &[u8]) -> Result<(VgmHeader, usize), Parse
         VgmHeader::fallback_header_size_for_version(version)
     } else {
         // VGM 1.50+: data_offset is non-zero, use it
-        0x34usize.wrapping_add(data_offset as usize)
+        resolve_relative(
+            VgmHeaderField::DataOffset.offset(),
+            data_offset,
+            "data_offset",
+        )?
+        .get()
     };
 
     // Determine the maximum header size allowed for this version.
@@ -220,15 +490,6 @@ pub(crate) fn parse_vgm_header(bytes: &[u8]) -> Result<(VgmHeader, usize), Parse
         version_max_header_size
     };
 
-    // Derive a usable data_offset value for subsequent calculations. When the
-    // stored data_offset is zero we compute an effective offset from the
-    // chosen fallback header size.
-    if data_offset == 0 {
-        version_max_header_size.wrapping_sub(VgmHeaderField::DataOffset.offset())
-    } else {
-        data_offset as usize
-    };
-
     if bytes.len() < total_header_size {
         return Err(ParseError::OffsetOutOfRange {
             offset: total_header_size,
@@ -276,6 +537,15 @@ pub(crate) fn parse_vgm_header(bytes: &[u8]) -> Result<(VgmHeader, usize), Parse
         let sz = field.len();
         let min_ver = field.min_version();
         let has_space = header_size_for_fields >= off + sz;
+        // The reserved tail belongs to no version; keep whatever the file
+        // stores there so it is written back unchanged.
+        if matches!(
+            field,
+            VgmHeaderField::ReservedE8EF | VgmHeaderField::ReservedF0FF
+        ) && version >= 0x00000150
+        {
+            return actual_data_start >= off + sz;
+        }
         if version >= 0x00000150 {
             // VGM 1.50+: Read if space is available (limited to version-defined fields)
             has_space
@@ -619,7 +889,13 @@ pub(crate) fn parse_vgm_extra_header(
     // Read the three header fields (12 bytes)
     let header_size = read_u32_le_at(bytes, offset)?;
     let chip_clock_offset = read_u32_le_at(bytes, offset + 4)?;
-    let chip_vol_offset = read_u32_le_at(bytes, offset + 8)?;
+    // v1.70 extra headers of 8 bytes end before the chip volume offset.
+    let fields: usize = if header_size == 8 { 8 } else { 12 };
+    let chip_vol_offset = if fields == 8 {
+        0
+    } else {
+        read_u32_le_at(bytes, offset + 8)?
+    };
 
     // Per the VGM specification, all pointer offsets are relative to the current
     // position in the file (i.e. relative to the field's own location).
@@ -631,8 +907,9 @@ pub(crate) fn parse_vgm_extra_header(
     // would land inside the header itself and are treated as invalid; in that case we
     // fall back to placing the block immediately after the 12-byte header area.
 
-    // Absolute position just past the 12-byte header (used as fallback base).
-    let data_base = offset.wrapping_add(12);
+    // Absolute position just past the header fields (used as fallback base).
+    // `offset + fields` cannot overflow: the header fields were read above.
+    let data_base = offset + fields;
 
     // Corrected (canonical) offset values that will be stored back into the struct
     // and later used by to_bytes().  They are always expressed as field-relative
@@ -653,15 +930,20 @@ pub(crate) fn parse_vgm_extra_header(
     if chip_clock_offset != 0 {
         // Absolute address of the chip-clock block.
         let cc_field_pos = offset + 4;
-        let cc_base = cc_field_pos.wrapping_add(chip_clock_offset as usize);
+        let cc_base = resolve_relative(
+            cc_field_pos,
+            chip_clock_offset,
+            "extra_header chip_clock_offset",
+        )?
+        .get();
 
         // Sanity-check: offset must not point back into the 12-byte header
         // (minimum valid field-relative value is 8, reaching just past offset+12).
-        let (actual_cc_base, corrected_cc_offset) = if chip_clock_offset < 8 {
+        let (actual_cc_base, corrected_cc_offset) = if (chip_clock_offset as usize) < fields - 4 {
             // Invalid offset – fall back to the first byte after the header.
             // The canonical field-relative offset for that position is
             // data_base - cc_field_pos = (offset+12) - (offset+4) = 8.
-            (data_base, 8u32)
+            (data_base, (fields - 4) as u32)
         } else {
             (cc_base, chip_clock_offset)
         };
@@ -685,7 +967,7 @@ pub(crate) fn parse_vgm_extra_header(
             let chip_id = read_u8_at(bytes, cur)?;
             let clock = read_u32_le_at(bytes, cur + 1)?;
             extra.chip_clocks.push(ChipClock::from_raw(chip_id, clock));
-            cur = cur.wrapping_add(5);
+            cur += 5;
         }
     }
 
@@ -693,7 +975,12 @@ pub(crate) fn parse_vgm_extra_header(
     // chip_vol_offset is relative to the field at (offset + 8).
     if chip_vol_offset != 0 {
         let cv_field_pos = offset + 8;
-        let cv_base = cv_field_pos.wrapping_add(chip_vol_offset as usize);
+        let cv_base = resolve_relative(
+            cv_field_pos,
+            chip_vol_offset,
+            "extra_header chip_vol_offset",
+        )?
+        .get();
 
         // Minimum valid field-relative value is 4 (reaching just past offset+12).
         let (actual_cv_base, corrected_cv_offset) = if chip_vol_offset < 4 {
@@ -734,7 +1021,7 @@ pub(crate) fn parse_vgm_extra_header(
             extra
                 .chip_volumes
                 .push(ChipVolume::from_raw(chip_id, flags, volume));
-            cur = cur.wrapping_add(4);
+            cur += 4;
         }
     }
 
@@ -743,23 +1030,8 @@ pub(crate) fn parse_vgm_extra_header(
     extra.chip_clock_offset = actual_chip_clock_offset;
     extra.chip_vol_offset = actual_chip_vol_offset;
 
-    // Recompute header_size to cover the actual data written.
-    // Layout: [header_size u32][cc_offset u32][cv_offset u32][cc block?][cv block?]
-    // The cc block (if any) starts at data_base (offset+12) and the cv block
-    // immediately follows.  header_size counts bytes from the start of the
-    // extra header (offset) to the end of the last block.
-    let mut calculated_size = 12u32; // three u32 fields
-
-    if !extra.chip_clocks.is_empty() {
-        calculated_size += 1 + (extra.chip_clocks.len() as u32 * 5);
-    }
-
-    if !extra.chip_volumes.is_empty() {
-        calculated_size += 1 + (extra.chip_volumes.len() as u32 * 4);
-    }
-
-    extra.header_size = calculated_size;
-
+    // `header_size` is kept as stored so that `to_bytes()` writes it back
+    // unchanged together with the block offsets.
     Ok((extra, header_size as usize))
 }
 
@@ -1307,7 +1579,7 @@ pub fn trace_vgm_commands_until_error(
         // Use absolute offset (binary address) instead of relative offset.
         let abs = off;
         out.push((abs, opcode, parsed));
-        off = off.wrapping_add(parsed);
+        off += parsed;
 
         if opcode == 0x66 {
             return (out, None);
@@ -0,0 +1,281 @@
+//! Repair of broken offset fields.
+//!
+//! Tag editors and hand-made rips sometimes leave offset fields pointing
+//! outside the file, which makes the whole file unparsable. The functions in
+//! this module work on the raw bytes: they clear or recompute the broken
+//! fields so the file parses again, and list every field they changed.
+//!
+//! `repair_offsets` recomputes the offsets of the main header from the file
+//! content:
+//!
+//! - `eof_offset` from the file length,
+//! - `data_offset` when the command stream would start inside the first
+//!   `0x40` bytes or after the end of the file (the default header size of
+//!   the version is used then),
+//! - `gd3_offset` when it does not point to a GD3 chunk inside the file: a
+//!   GD3 chunk ending at the end of the file is searched for instead,
+//! - `loop_offset` when it does not point to the start of a command: the
+//!   command from which `loop_samples` samples remain is used instead, or the
+//!   loop is removed when there is none,
+//!
+//! and repairs the extra header like `repair_extra_header`.
+//! `repair_document_offsets` does the same for a `VgmDocument` whose header
+//! fields went stale.
+//!
+//! `repair_extra_header` clears extra header offsets (the extra header
+//! offset in the main header, the chip clock and chip volume offsets in the
+//! extra header) whose target, including all its entries, does not lie in
+//! the file. The data the cleared offsets pointed to is lost; everything else
+//! is kept byte for byte.
+//!
+//! ```rust
+//! use soundlog::vgm::VgmExtraHeader;
+//! use soundlog::vgm::command::{Instance, WaitSamples};
+//! use soundlog::vgm::header::{ChipClock, ChipId};
+//! use soundlog::vgm::repair::{RepairedField, repair_extra_header};
+//! use soundlog::{VgmBuilder, VgmDocument};
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.add_vgm_command(WaitSamples(1));
+//! builder.set_extra_header(VgmExtraHeader {
+//!     chip_clocks: vec![ChipClock::new(ChipId::Ym2612, Instance::Primary, 7_670_454)],
+//!     ..VgmExtraHeader::default()
+//! });
+//! let mut bytes: Vec<u8> = builder.finalize().into();
+//! // break the chip clock offset
+//! let extra = u32::from_le_bytes(bytes[0xBC..0xC0].try_into().unwrap()) as usize + 0xBC;
+//! bytes[extra + 4..extra + 8].copy_from_slice(&0x1000u32.to_le_bytes());
+//! assert!(VgmDocument::try_from(&bytes[..]).is_err());
+//!
+//! let (repaired, fixes) = repair_extra_header(&bytes).unwrap();
+//! assert_eq!(fixes[0].field, RepairedField::ChipClockOffset);
+//! let doc = VgmDocument::try_from(&repaired[..]).unwrap();
+//! assert!(doc.extra_header.unwrap().chip_clocks.is_empty());
+//! ```
+use crate::binutil::{ParseError, read_slice, read_u8_at, read_u32_le_at};
+use crate::meta::parse_gd3;
+use crate::vgm::VgmDocument;
+use crate::vgm::command::VgmCommand;
+use crate::vgm::header::{VgmHeader, VgmHeaderField};
+use crate::vgm::parser::{parse_vgm_command, parse_vgm_header};
+
+/// An offset field changed by a repair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepairedField {
+    /// A field of the main header.
+    Header(VgmHeaderField),
+    /// The chip clock offset of the extra header.
+    ChipClockOffset,
+    /// The chip volume offset of the extra header.
+    ChipVolumeOffset,
+}
+
+/// One changed offset field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OffsetRepair {
+    pub field: RepairedField,
+    /// Value found in the file.
+    pub stored: u32,
+    /// Value written instead.
+    pub repaired: u32,
+}
+
+/// Clear the extra header offsets of the VGM file `bytes` that point outside
+/// the file. Returns the repaired bytes and the changed fields; the bytes are
+/// unchanged when the list is empty.
+///
+/// # Errors
+/// Returns a `ParseError` when the main header cannot be parsed.
+pub fn repair_extra_header(bytes: &[u8]) -> Result<(Vec<u8>, Vec<OffsetRepair>), ParseError> {
+    let (header, _) = parse_vgm_header(bytes)?;
+    let mut out = bytes.to_vec();
+    let mut repairs = Vec::new();
+    if header.extra_header_offset == 0 {
+        return Ok((out, repairs));
+    }
+    let mut clear = |out: &mut Vec<u8>, position: usize, field: RepairedField, stored: u32| {
+        out[position..position + 4].copy_from_slice(&0u32.to_le_bytes());
+        repairs.push(OffsetRepair {
+            field,
+            stored,
+            repaired: 0,
+        });
+    };
+
+    let field = VgmHeaderField::ExtraHeaderOffset.offset();
+    let start = field.saturating_add(header.extra_header_offset as usize);
+    let size = read_u32_le_at(bytes, start).ok();
+    // v1.70 extra headers of 8 bytes end before the chip volume offset.
+    let fields: usize = if size == Some(8) { 8 } else { 12 };
+    if start.saturating_add(fields) > bytes.len() {
+        clear(
+            &mut out,
+            field,
+            RepairedField::Header(VgmHeaderField::ExtraHeaderOffset),
+            header.extra_header_offset,
+        );
+        return Ok((out, repairs));
+    }
+
+    // (field position, entry size, repaired field)
+    let mut blocks = vec![(start + 4, 5, RepairedField::ChipClockOffset)];
+    if fields == 12 {
+        blocks.push((start + 8, 4, RepairedField::ChipVolumeOffset));
+    }
+    for (position, entry_size, field) in blocks {
+        let offset = read_u32_le_at(bytes, position)?;
+        if offset == 0 {
+            continue;
+        }
+        // Blocks pointing into the header fields are moved after them by
+        // the parser.
+        if position + (offset as usize) < start + fields {
+            continue;
+        }
+        let block = position.saturating_add(offset as usize);
+        let fits = read_u8_at(bytes, block).is_ok_and(|count| {
+            block.saturating_add(1 + count as usize * entry_size) <= bytes.len()
+        });
+        if !fits {
+            clear(&mut out, position, field, offset);
+        }
+    }
+    Ok((out, repairs))
+}
+
+/// Recompute the main header offsets of the VGM file `bytes` from its
+/// content and repair its extra header. Returns the repaired bytes and the
+/// changed fields, in header order.
+///
+/// # Errors
+/// Returns a `ParseError` when `bytes` has no VGM ident or is shorter than
+/// the `0x40` byte minimum header.
+pub fn repair_offsets(bytes: &[u8]) -> Result<(Vec<u8>, Vec<OffsetRepair>), ParseError> {
+    if bytes.len() < 0x40 {
+        return Err(ParseError::HeaderTooShort(
+            "vgm: minimum header (0x40)".into(),
+        ));
+    }
+    let ident = read_slice(bytes, VgmHeaderField::Ident.offset(), 4)?;
+    if ident != b"Vgm " {
+        let mut id: [u8; 4] = [0; 4];
+        id.copy_from_slice(ident);
+        return Err(ParseError::InvalidIdent(id));
+    }
+    let mut out = bytes.to_vec();
+    let mut repairs = Vec::new();
+    let mut set = |out: &mut Vec<u8>, field: VgmHeaderField, repaired: u32| {
+        let position = field.offset();
+        let stored = read_u32_le_at(out, position).unwrap_or(0);
+        if stored != repaired {
+            out[position..position + 4].copy_from_slice(&repaired.to_le_bytes());
+            repairs.push(OffsetRepair {
+                field: RepairedField::Header(field),
+                stored,
+                repaired,
+            });
+        }
+    };
+
+    set(
+        &mut out,
+        VgmHeaderField::EofOffset,
+        (bytes.len() - 4) as u32,
+    );
+
+    let version = read_u32_le_at(bytes, VgmHeaderField::Version.offset())?;
+    let data_field = VgmHeaderField::DataOffset.offset();
+    let mut data_start = 0x40;
+    if version >= 0x00000150 {
+        let stored = read_u32_le_at(bytes, data_field)?;
+        let fallback = VgmHeader::fallback_header_size_for_version(version).max(0x40);
+        data_start = match stored {
+            0 => fallback,
+            stored => data_field.saturating_add(stored as usize),
+        };
+        if !(0x40..=bytes.len()).contains(&data_start) {
+            data_start = fallback.min(bytes.len());
+            set(
+                &mut out,
+                VgmHeaderField::DataOffset,
+                (data_start - data_field) as u32,
+            );
+        }
+    }
+
+    let gd3_field = VgmHeaderField::Gd3Offset.offset();
+    let stored = read_u32_le_at(bytes, gd3_field)?;
+    let gd3_start = gd3_field.saturating_add(stored as usize);
+    let gd3_start = if stored != 0 && gd3_chunk_len(bytes, gd3_start).is_some() {
+        Some(gd3_start)
+    } else {
+        // A chunk written by a tag editor ends the file.
+        (data_start..bytes.len().saturating_sub(12))
+            .rev()
+            .find(|&start| gd3_chunk_len(bytes, start) == Some(bytes.len() - start))
+    };
+    set(
+        &mut out,
+        VgmHeaderField::Gd3Offset,
+        gd3_start.map_or(0, |start| (start - gd3_field) as u32),
+    );
+
+    // Command starts and the samples remaining from each of them.
+    let end = gd3_start.unwrap_or(bytes.len());
+    let mut starts: Vec<(usize, u64)> = Vec::new();
+    let mut position = data_start;
+    let mut samples = 0u64;
+    while position < end {
+        let Ok((command, length)) = parse_vgm_command(&bytes[..end], position) else {
+            break;
+        };
+        starts.push((position, samples));
+        samples += command.wait_samples() as u64;
+        position += length;
+        if matches!(command, VgmCommand::EndOfData(_)) {
+            break;
+        }
+    }
+    let loop_field = VgmHeaderField::LoopOffset.offset();
+    let stored = read_u32_le_at(bytes, loop_field)?;
+    if stored != 0 {
+        let loop_start = loop_field.saturating_add(stored as usize);
+        if !starts.iter().any(|&(start, _)| start == loop_start) {
+            let loop_samples = read_u32_le_at(bytes, VgmHeaderField::LoopSamples.offset())? as u64;
+            let repaired = starts
+                .iter()
+                .find(|&&(_, before)| loop_samples != 0 && samples - before == loop_samples)
+                .map_or(0, |&(start, _)| (start - loop_field) as u32);
+            set(&mut out, VgmHeaderField::LoopOffset, repaired);
+        }
+    }
+
+    let (out, extra) = repair_extra_header(&out)?;
+    repairs.extend(extra);
+    Ok((out, repairs))
+}
+
+/// Like `repair_offsets`, for a document: `document` is serialized, repaired
+/// and parsed again.
+///
+/// # Errors
+/// Returns a `ParseError` when the repaired bytes do not parse.
+pub fn repair_document_offsets(
+    document: &VgmDocument,
+) -> Result<(VgmDocument, Vec<OffsetRepair>), ParseError> {
+    let bytes: Vec<u8> = document.into();
+    let (bytes, repairs) = repair_offsets(&bytes)?;
+    Ok((VgmDocument::try_from(&bytes[..])?, repairs))
+}
+
+// Length of the GD3 chunk starting at `start`, `None` when there is no
+// parsable chunk inside `bytes`.
+fn gd3_chunk_len(bytes: &[u8], start: usize) -> Option<usize> {
+    if bytes.get(start..start.checked_add(4)?)? != b"Gd3 " {
+        return None;
+    }
+    let len = 12usize.checked_add(read_u32_le_at(bytes, start + 8).ok()? as usize)?;
+    let chunk = bytes.get(start..start.checked_add(len)?)?;
+    parse_gd3(chunk).ok()?;
+    Some(len)
+}
@@ -0,0 +1,203 @@
+//! Run-length grouped command lists.
+//!
+//! PCM-heavy rips often consist of millions of repetitions of a few commands,
+//! such as a YM2612 DAC write followed by a short wait. `RleCommands` keeps
+//! such a command list in memory as runs: a short pattern of commands stored
+//! once with the number of times it repeats. Commands that do not repeat are
+//! kept as literal runs.
+//!
+//! The grouping is transparent: `RleCommands` indexes and iterates like the
+//! original list and can be expanded back into it.
+//!
+//! ```rust
+//! use soundlog::vgm::command::{VgmCommand, WaitNSample, Ym2612Port0Address2AWriteAndWaitN};
+//! use soundlog::vgm::rle::RleCommands;
+//!
+//! let mut commands: Vec<VgmCommand> = Vec::new();
+//! for _ in 0..10_000 {
+//!     commands.push(Ym2612Port0Address2AWriteAndWaitN(2).into());
+//!     commands.push(WaitNSample(0).into());
+//! }
+//!
+//! let rle = RleCommands::encode(&commands);
+//! assert_eq!(rle.len(), 20_000);
+//! assert_eq!(rle.stored_commands(), 2);
+//! assert_eq!(rle.get(19_999), Some(&commands[19_999]));
+//! assert!(rle.iter().eq(commands.iter()));
+//! assert_eq!(rle.to_vec(), commands);
+//! ```
+use std::mem::size_of;
+
+use crate::vgm::command::VgmCommand;
+
+/// A pattern of `pattern` stored commands starting at `start`, repeated
+/// `repeat` times from command `offset` of the expanded list on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Run {
+    offset: usize,
+    start: u32,
+    pattern: u32,
+    repeat: u32,
+}
+
+impl Run {
+    fn len(&self) -> usize {
+        self.pattern as usize * self.repeat as usize
+    }
+}
+
+/// A command list grouped into repeated patterns. See the module
+/// documentation.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RleCommands {
+    commands: Vec<VgmCommand>,
+    runs: Vec<Run>,
+    len: usize,
+}
+
+impl RleCommands {
+    /// Longest pattern `encode` looks for.
+    pub const DEFAULT_MAX_PATTERN: usize = 8;
+
+    /// Group `commands` into runs of patterns of up to
+    /// `DEFAULT_MAX_PATTERN` commands.
+    pub fn encode(commands: &[VgmCommand]) -> Self {
+        Self::encode_with_max_pattern(commands, Self::DEFAULT_MAX_PATTERN)
+    }
+
+    /// Group `commands` into runs of patterns of up to `max_pattern`
+    /// commands. Longer patterns find more repetition but take longer to
+    /// encode: every position is compared against every pattern length.
+    pub fn encode_with_max_pattern(commands: &[VgmCommand], max_pattern: usize) -> Self {
+        let mut rle = RleCommands::default();
+        let mut index = 0;
+        while index < commands.len() {
+            // The pattern saving the most stored commands; ties go to the
+            // shorter pattern.
+            let mut best: Option<(usize, usize)> = None;
+            for pattern in 1..=max_pattern.min((commands.len() - index) / 2) {
+                let repeat = repeats(&commands[index..], pattern);
+                let saved = pattern * (repeat - 1);
+                if repeat > 1 && best.is_none_or(|(p, r)| saved > p * (r - 1)) {
+                    best = Some((pattern, repeat));
+                }
+            }
+            match best {
+                Some((pattern, repeat)) => {
+                    rle.push_run(&commands[index..index + pattern], repeat);
+                    index += pattern * repeat;
+                }
+                None => {
+                    rle.push_literal(&commands[index]);
+                    index += 1;
+                }
+            }
+        }
+        rle.commands.shrink_to_fit();
+        rle.runs.shrink_to_fit();
+        rle
+    }
+
+    fn push_run(&mut self, pattern: &[VgmCommand], repeat: usize) {
+        let start = self.commands.len() as u32;
+        self.commands.extend_from_slice(pattern);
+        let run = Run {
+            offset: self.len,
+            start,
+            pattern: pattern.len() as u32,
+            repeat: repeat as u32,
+        };
+        self.len += run.len();
+        self.runs.push(run);
+    }
+
+    fn push_literal(&mut self, command: &VgmCommand) {
+        self.commands.push(command.clone());
+        self.len += 1;
+        if let Some(last) = self.runs.last_mut()
+            && last.repeat == 1
+        {
+            last.pattern += 1;
+            return;
+        }
+        self.runs.push(Run {
+            offset: self.len - 1,
+            start: self.commands.len() as u32 - 1,
+            pattern: 1,
+            repeat: 1,
+        });
+    }
+
+    /// Number of commands in the expanded list.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// `true` when the list holds no command.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of commands actually stored: every pattern once plus the
+    /// literal commands.
+    pub fn stored_commands(&self) -> usize {
+        self.commands.len()
+    }
+
+    /// Number of runs, literal runs included.
+    pub fn runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// The command at `index` of the expanded list.
+    pub fn get(&self, index: usize) -> Option<&VgmCommand> {
+        if index >= self.len {
+            return None;
+        }
+        let run = &self.runs[self.runs.partition_point(|run| run.offset <= index) - 1];
+        let within = (index - run.offset) % run.pattern as usize;
+        self.commands.get(run.start as usize + within)
+    }
+
+    /// Iterate the expanded list.
+    pub fn iter(&self) -> impl Iterator<Item = &VgmCommand> + '_ {
+        self.runs.iter().flat_map(move |run| {
+            let pattern = &self.commands[run.start as usize..][..run.pattern as usize];
+            (0..run.repeat).flat_map(move |_| pattern.iter())
+        })
+    }
+
+    /// Expand back into the original command list.
+    pub fn to_vec(&self) -> Vec<VgmCommand> {
+        let mut commands = Vec::with_capacity(self.len);
+        commands.extend(self.iter().cloned());
+        commands
+    }
+
+    /// Estimated bytes held by the grouped list. Boxed data block payloads
+    /// are not included; they are stored once either way unless repeated.
+    pub fn memory_footprint(&self) -> usize {
+        self.commands.capacity() * size_of::<VgmCommand>() + self.runs.capacity() * size_of::<Run>()
+    }
+}
+
+impl From<&[VgmCommand]> for RleCommands {
+    fn from(commands: &[VgmCommand]) -> Self {
+        RleCommands::encode(commands)
+    }
+}
+
+impl From<&RleCommands> for Vec<VgmCommand> {
+    fn from(rle: &RleCommands) -> Self {
+        rle.to_vec()
+    }
+}
+
+// Number of consecutive repetitions of the first `pattern` commands.
+fn repeats(commands: &[VgmCommand], pattern: usize) -> usize {
+    let first = &commands[..pattern];
+    commands
+        .chunks_exact(pattern)
+        .take_while(|chunk| *chunk == first)
+        .count()
+}
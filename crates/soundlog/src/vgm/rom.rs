@@ -0,0 +1,384 @@
+//! ROM coverage analysis.
+//!
+//! Arcade rips usually embed whole sample ROMs (data types `0x80..=0xBF`)
+//! although a single song only plays a few of the samples. `rom_coverage`
+//! replays the register writes of a document through the `chip::state`
+//! trackers and reports which ROM address ranges the chips actually read;
+//! `transform::prune_rom_blocks` uses that to drop everything else.
+//!
+//! Coverage is only known for chips whose sample addressing is decoded here:
+//!
+//! - YM2610 ADPCM-A (`RomRamChipType::Ym2610AdpcmRom`) and ADPCM-B
+//!   (`RomRamChipType::Ym2610DeltaTRom`): the start/end address registers at
+//!   the time of a key-on.
+//! - OKIM6295 (`RomRamChipType::Okim6295Rom`): the phrase table entry and the
+//!   sample it points to for every phrase that is started, including the
+//!   bank selected with register `0x0F`. NMK112 banking is not decoded.
+//! - Y8950 ADPCM (`RomRamChipType::Y8950DeltaTRom`): the start/stop address
+//!   registers when playback starts.
+//!
+//! Other ROM types are reported with `read_ranges: None`.
+//!
+//! `y8950_adpcm_samples` lists the ADPCM samples an MSX-Audio song plays with
+//! their bytes, taken from the `0x88` data blocks and the memory writes of
+//! register `0x0F`.
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::chip;
+//! use soundlog::vgm::command::Instance;
+//! use soundlog::vgm::detail::{RomRamChipType, RomRamDump};
+//! use soundlog::vgm::rom::rom_coverage;
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.register_chip(chip::Chip::Ym2610b, Instance::Primary, 8_000_000);
+//! builder.attach_data_block(RomRamDump {
+//!     chip_type: RomRamChipType::Ym2610DeltaTRom,
+//!     rom_size: 0x10000,
+//!     start_address: 0,
+//!     data: vec![0; 0x10000],
+//! });
+//! // ADPCM-B start 0x0100, end 0x01FF (in 256 byte units), then start
+//! for (register, value) in [(0x12, 0x01), (0x13, 0x00), (0x14, 0x01), (0x15, 0x00), (0x10, 0x80)] {
+//!     builder.add_chip_write(Instance::Primary, chip::Ym2610Spec { port: 0, register, value });
+//! }
+//! let doc = builder.finalize();
+//!
+//! let coverage = rom_coverage(&doc);
+//! assert_eq!(coverage[0].read_ranges, Some(vec![0x100..0x200]));
+//! assert_eq!(coverage[0].read_bytes(), Some(0x100));
+//! ```
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+
+use crate::chip::Chip;
+use crate::chip::event::{RhythmInstrument, StateEvent};
+use crate::chip::state::{ChipState, Okim6295State, Y8950AdpcmSample, Y8950State, Ym2610bState};
+use crate::vgm::VgmDocument;
+use crate::vgm::command::VgmCommand;
+use crate::vgm::detail::{DataBlockType, RomRamChipType, RomRamDump, parse_data_block};
+
+/// ROM usage of one chip instance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomCoverage {
+    pub chip_type: RomRamChipType,
+    /// `0` for the primary chip, `1` for the secondary chip.
+    pub chip_instance: u8,
+    /// ROM size declared by the data blocks (the largest one).
+    pub rom_size: u32,
+    /// Address ranges stored in the document's data blocks, sorted and merged.
+    pub dumped_ranges: Vec<Range<u32>>,
+    /// Address ranges read during playback, sorted and merged, or `None`
+    /// when the sample addressing of this chip is not decoded.
+    pub read_ranges: Option<Vec<Range<u32>>>,
+}
+
+impl RomCoverage {
+    /// Number of ROM bytes stored in the document.
+    pub fn dumped_bytes(&self) -> u64 {
+        range_len(&self.dumped_ranges)
+    }
+
+    /// Number of stored ROM bytes that are read during playback, or `None`
+    /// when unknown.
+    pub fn read_bytes(&self) -> Option<u64> {
+        let read = self.read_ranges.as_ref()?;
+        Some(range_len(&intersect(&self.dumped_ranges, read)))
+    }
+}
+
+/// ADPCM sample played by a Y8950.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Y8950AdpcmData {
+    /// `0` for the primary chip, `1` for the secondary chip.
+    pub chip_instance: u8,
+    /// Sample position of the first start.
+    pub sample: u64,
+    /// Addresses, rate and level when the sample was first started.
+    pub adpcm: Y8950AdpcmSample,
+    /// ADPCM bytes of the sample, or `None` when part of it was never
+    /// written.
+    pub data: Option<Vec<u8>>,
+}
+
+/// Analyze which address ranges of the ROM data blocks of `document` are
+/// read during playback.
+///
+/// One entry is returned per ROM type and chip instance that has at least one
+/// ROM data block, in data type order. The whole command stream is scanned,
+/// so samples that only play after the loop point are included.
+pub fn rom_coverage(document: &VgmDocument) -> Vec<RomCoverage> {
+    let roms = rom_images(document);
+
+    let mut trackers: HashMap<(Chip, u8), Tracker> = HashMap::new();
+    let mut read: HashMap<(u8, u8), Vec<Range<u32>>> = HashMap::new();
+    let mut undecoded: Vec<(u8, u8)> = Vec::new();
+    for command in &document.commands {
+        if matches!(command, VgmCommand::EndOfData(_)) {
+            break;
+        }
+        let Some(write) = command.register_write() else {
+            continue;
+        };
+        let instance = usize::from(write.instance) as u8;
+        let tracker = match write.chip {
+            Chip::Ym2610b => trackers.entry((write.chip, instance)).or_insert_with(|| {
+                Tracker::Ym2610(Box::new(Ym2610bState::new(
+                    (document.header.ym2610b_clock & 0x7FFF_FFFF) as f32,
+                )))
+            }),
+            Chip::Okim6295 => trackers.entry((write.chip, instance)).or_insert_with(|| {
+                let mut chip = Okim6295State::default();
+                for block in roms
+                    .get(&(0x8B, instance))
+                    .iter()
+                    .flat_map(|rom| &rom.blocks)
+                {
+                    chip.write_rom(block.start_address, &block.data);
+                }
+                Tracker::Okim6295(Box::new(chip))
+            }),
+            Chip::Y8950 => trackers.entry((write.chip, instance)).or_insert_with(|| {
+                Tracker::Y8950(Box::new(y8950_with_rom(document, &roms, instance)))
+            }),
+            _ => continue,
+        };
+        let mut mark = |data_type: u8, range: Range<u32>| {
+            if range.start < range.end {
+                read.entry((data_type, instance)).or_default().push(range);
+            }
+        };
+        match tracker {
+            Tracker::Ym2610(chip) => {
+                let (register, value) = (write.register as u8, write.value as u8);
+                chip.set_port(write.port);
+                let events = chip.on_register_write(register, value);
+                // ADPCM-B start
+                if write.port == 0 && register == 0x10 && value & 0x80 != 0 {
+                    mark(0x83, chip.adpcm_b_range());
+                }
+                // ADPCM-A key-ons
+                for event in events.iter().flatten() {
+                    if let StateEvent::RhythmHit {
+                        channel,
+                        instrument: RhythmInstrument::Sample { .. },
+                        ..
+                    } = event
+                        && let Some(range) = chip.adpcm_a_range(*channel)
+                    {
+                        mark(0x82, range);
+                    }
+                }
+            }
+            Tracker::Okim6295(chip) => {
+                let (register, value) = (write.register as u8, write.value as u8);
+                // NMK112 banking is not decoded.
+                if register == 0x0E && value != 0 {
+                    undecoded.push((0x8B, instance));
+                }
+                let events = chip.on_register_write(register, value);
+                for event in events.iter().flatten() {
+                    let StateEvent::SampleStart { channel, .. } = event else {
+                        continue;
+                    };
+                    if let Some(sample) = chip.channel_sample(*channel) {
+                        mark(0x8B, sample.entry..sample.entry + 8);
+                        for range in chip.rom_ranges(&sample) {
+                            mark(0x8B, range);
+                        }
+                    }
+                }
+            }
+            Tracker::Y8950(chip) => {
+                let events = chip.on_register_write(write.register as u8, write.value as u8);
+                if events.is_some_and(|e| matches!(e[..], [StateEvent::SampleStart { .. }]))
+                    && let Some(sample) = chip.adpcm_sample()
+                {
+                    mark(0x88, sample.start..sample.end.saturating_add(1));
+                }
+            }
+        }
+    }
+
+    roms.into_iter()
+        .map(|((data_type, chip_instance), image)| {
+            let decoded = matches!(data_type, 0x82 | 0x83 | 0x88 | 0x8B)
+                && !undecoded.contains(&(data_type, chip_instance));
+            RomCoverage {
+                chip_type: RomRamChipType::from(data_type),
+                chip_instance,
+                rom_size: image.rom_size,
+                dumped_ranges: merge(image.blocks.iter().map(dump_range).collect(), 0),
+                read_ranges: decoded.then(|| {
+                    merge(
+                        read.remove(&(data_type, chip_instance)).unwrap_or_default(),
+                        0,
+                    )
+                }),
+            }
+        })
+        .collect()
+}
+
+/// List the ADPCM samples played by the Y8950 chips of `document`.
+///
+/// Every distinct sample (chip instance, start and stop address) is reported
+/// once, in order of its first start. The sample bytes come from the `0x88`
+/// data blocks and from the bytes the song writes to the ADPCM memory with
+/// register `0x0F` before the start.
+///
+/// ```rust
+/// use soundlog::VgmBuilder;
+/// use soundlog::chip::{Chip, Y8950Spec};
+/// use soundlog::vgm::command::Instance;
+/// use soundlog::vgm::detail::{RomRamChipType, RomRamDump};
+/// use soundlog::vgm::rom::y8950_adpcm_samples;
+///
+/// let mut builder = VgmBuilder::new();
+/// builder.register_chip(Chip::Y8950, Instance::Primary, 3_579_545);
+/// builder.attach_data_block(RomRamDump {
+///     chip_type: RomRamChipType::Y8950DeltaTRom,
+///     rom_size: 0x8000,
+///     start_address: 0,
+///     data: vec![0x77; 0x40],
+/// });
+/// // start 0x00, stop 0x3F (in 32 byte units), then start playback
+/// for (register, value) in [(0x09, 0), (0x0A, 0), (0x0B, 1), (0x0C, 0), (0x07, 0xA0)] {
+///     builder.add_chip_write(Instance::Primary, Y8950Spec { register, value });
+/// }
+/// let doc = builder.finalize();
+///
+/// let samples = y8950_adpcm_samples(&doc);
+/// assert_eq!(samples[0].adpcm.length(), 0x40);
+/// assert_eq!(samples[0].data, Some(vec![0x77; 0x40]));
+/// ```
+pub fn y8950_adpcm_samples(document: &VgmDocument) -> Vec<Y8950AdpcmData> {
+    let roms = rom_images(document);
+
+    let mut chips: [Option<Y8950State>; 2] = [None, None];
+    let mut samples: Vec<Y8950AdpcmData> = Vec::new();
+    let mut sample = 0u64;
+    for command in &document.commands {
+        if matches!(command, VgmCommand::EndOfData(_)) {
+            break;
+        }
+        sample += command.wait_samples() as u64;
+        let Some(write) = command.register_write() else {
+            continue;
+        };
+        if write.chip != Chip::Y8950 {
+            continue;
+        }
+        let instance = usize::from(write.instance) as u8;
+        let chip = chips[instance as usize & 1]
+            .get_or_insert_with(|| y8950_with_rom(document, &roms, instance));
+        let events = chip.on_register_write(write.register as u8, write.value as u8);
+        if !events.is_some_and(|e| matches!(e[..], [StateEvent::SampleStart { .. }])) {
+            continue;
+        }
+        let Some(adpcm) = chip.adpcm_sample() else {
+            continue;
+        };
+        if !samples.iter().any(|s| {
+            s.chip_instance == instance && s.adpcm.start == adpcm.start && s.adpcm.end == adpcm.end
+        }) {
+            samples.push(Y8950AdpcmData {
+                chip_instance: instance,
+                sample,
+                adpcm,
+                data: chip.sample_data(&adpcm),
+            });
+        }
+    }
+    samples
+}
+
+// A Y8950 tracker with the `0x88` data blocks of `instance` loaded.
+fn y8950_with_rom(
+    document: &VgmDocument,
+    roms: &BTreeMap<(u8, u8), RomImage>,
+    instance: u8,
+) -> Y8950State {
+    let mut chip = Y8950State::new((document.header.y8950_clock & 0x7FFF_FFFF) as f32);
+    for block in roms
+        .get(&(0x88, instance))
+        .iter()
+        .flat_map(|rom| &rom.blocks)
+    {
+        chip.write_memory(block.start_address, &block.data);
+    }
+    chip
+}
+
+/// Sort `ranges` and merge the ones that overlap or are less than `gap`
+/// bytes apart.
+pub(crate) fn merge(mut ranges: Vec<Range<u32>>, gap: u32) -> Vec<Range<u32>> {
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<Range<u32>> = Vec::with_capacity(ranges.len());
+    for range in ranges.into_iter().filter(|r| r.start < r.end) {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end.saturating_add(gap) => {
+                last.end = last.end.max(range.end);
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Parts of the sorted, merged `ranges` that are inside `within`.
+pub(crate) fn intersect(ranges: &[Range<u32>], within: &[Range<u32>]) -> Vec<Range<u32>> {
+    let mut result = Vec::new();
+    for a in ranges {
+        for b in within {
+            let range = a.start.max(b.start)..a.end.min(b.end);
+            if range.start < range.end {
+                result.push(range);
+            }
+        }
+    }
+    result
+}
+
+fn range_len(ranges: &[Range<u32>]) -> u64 {
+    ranges.iter().map(|r| (r.end - r.start) as u64).sum()
+}
+
+// ROM data blocks of one data type and chip instance.
+struct RomImage {
+    rom_size: u32,
+    blocks: Vec<RomRamDump>,
+}
+
+pub(crate) fn dump_range(dump: &RomRamDump) -> Range<u32> {
+    dump.start_address..dump.start_address.saturating_add(dump.data.len() as u32)
+}
+
+fn rom_images(document: &VgmDocument) -> BTreeMap<(u8, u8), RomImage> {
+    let mut images: BTreeMap<(u8, u8), RomImage> = BTreeMap::new();
+    for command in &document.commands {
+        let VgmCommand::DataBlock(block) = command else {
+            continue;
+        };
+        if !(0x80..=0xBF).contains(&block.data_type) {
+            continue;
+        }
+        if let Ok(DataBlockType::RomRamDump(dump)) = parse_data_block((**block).clone()) {
+            let image = images
+                .entry((block.data_type, block.chip_instance))
+                .or_insert(RomImage {
+                    rom_size: 0,
+                    blocks: Vec::new(),
+                });
+            image.rom_size = image.rom_size.max(dump.rom_size);
+            image.blocks.push(dump);
+        }
+    }
+    images
+}
+
+enum Tracker {
+    Ym2610(Box<Ym2610bState>),
+    Okim6295(Box<Okim6295State>),
+    Y8950(Box<Y8950State>),
+}
@@ -0,0 +1,365 @@
+//! Command scripts: a VGM document as editable text.
+//!
+//! `to_script` writes a document as plain text with one command per line,
+//! and `from_script` builds the document back, so a dump can be tweaked in a
+//! text editor (move a note, change a patch, drop a channel) without hex
+//! editing:
+//!
+//! ```text
+//! # soundlog script v1
+//! .header Version 0x00000171
+//! .header Ym2612Clock 0x00750AB5
+//! .gd3 track_name_en Green Hill Zone
+//! 0          ym2612 28 f0
+//! 0          ym2612 p1 b4 c0
+//! 735        loop
+//! 735        sn76489.2 9f
+//! 1470       dac
+//! 1473       raw 9400
+//! 2205       end
+//! ```
+//!
+//! - Lines starting with `#` are comments. A `#` after a command starts a
+//!   comment as well.
+//! - `.header <field> <value>` sets a `VgmHeaderField` (named as in its
+//!   `Debug` output) to a decimal or `0x` hexadecimal value. Offsets, lengths
+//!   and the total and loop sample counts are computed when the document is
+//!   built and are not listed.
+//! - `.gd3 <field> <text>` sets a GD3 field (see `Gd3::field_mut` for the
+//!   names). Backslash, tab, carriage return and newline are escaped as
+//!   `\\`, `\t`, `\r` and `\n`.
+//! - Every other line starts with the time of the command in samples
+//!   (44.1 kHz). Times never decrease; the waits between commands are
+//!   derived from them, so there are no wait lines.
+//! - Chip writes are `<chip>[.2] [p<port>] <register> <value>` in hex, with
+//!   the chip named as in `Chip`'s `Debug` output (case-insensitive) and
+//!   `.2` for the secondary instance. The fields are those of
+//!   `RegisterWrite`; SN76489 writes have no register.
+//! - `dac` is a YM2612 write from the PCM data bank (`0x8n`); the wait up
+//!   to the next line (at most 15 samples) is folded into it.
+//! - `loop` marks the loop point and `end` is the end of the sound data.
+//! - `raw <hex>` is any other command in its VGM encoding: data blocks, DAC
+//!   stream control, PCM RAM writes and so on.
+//!
+//! Building the document back chooses the wait encodings anew, so the
+//! rebuilt file plays the same but may not be byte-identical. The extra
+//! header is not kept.
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::chip::{Chip, Ym2612Spec};
+//! use soundlog::vgm::command::{Instance, WaitSamples};
+//! use soundlog::vgm::script::{from_script, to_script};
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_453);
+//! builder.add_chip_write(Instance::Primary, Ym2612Spec { port: 0, register: 0x28, value: 0xF0 });
+//! builder.add_vgm_command(WaitSamples(1000));
+//! let doc = builder.finalize();
+//!
+//! let script = to_script(&doc);
+//! assert!(script.contains("\n0          ym2612 28 f0\n"));
+//! // Move the key-on to sample 100.
+//! let edited = script.replace("0          ym2612", "100 ym2612");
+//! let rebuilt = from_script(&edited).unwrap();
+//! assert_eq!(rebuilt.header.ym2612_clock, 7_670_453);
+//! assert_eq!(rebuilt.header.total_samples, 1000);
+//! assert_eq!(rebuilt.commands[1], doc.commands[0]);
+//! ```
+use std::fmt::Write;
+
+use crate::binutil::ParseError;
+use crate::chip::Chip;
+use crate::meta::Gd3;
+use crate::vgm::VgmBuilder;
+use crate::vgm::VgmDocument;
+use crate::vgm::annotation::{escape, unescape};
+use crate::vgm::command::{
+    EndOfData, Instance, RegisterWrite, VgmCommand, Ym2612Port0Address2AWriteAndWaitN,
+    command_to_vgm_bytes,
+};
+use crate::vgm::header::{VgmHeader, VgmHeaderField};
+
+const SCRIPT_HEADER: &str = "# soundlog script v1";
+
+/// Header fields computed when a document is built.
+const DERIVED_FIELDS: [VgmHeaderField; 8] = [
+    VgmHeaderField::Ident,
+    VgmHeaderField::EofOffset,
+    VgmHeaderField::Gd3Offset,
+    VgmHeaderField::TotalSamples,
+    VgmHeaderField::LoopOffset,
+    VgmHeaderField::LoopSamples,
+    VgmHeaderField::DataOffset,
+    VgmHeaderField::ExtraHeaderOffset,
+];
+
+/// Size of the full v1.71 header the `.header` fields are written into.
+const HEADER_SIZE: usize = 0x100;
+
+/// Write `document` as a command script.
+pub fn to_script(document: &VgmDocument) -> String {
+    let mut text = String::from(SCRIPT_HEADER);
+    text.push('\n');
+
+    let header = &document.header;
+    let bytes = header.to_bytes(0, header.data_offset);
+    for field in VgmHeaderField::ALL {
+        if DERIVED_FIELDS.contains(&field) {
+            continue;
+        }
+        let Some((offset, len)) = field.byte_range(header.version, header.data_offset) else {
+            continue;
+        };
+        let Some(raw) = bytes.get(offset..offset + len) else {
+            continue;
+        };
+        if raw.iter().all(|&b| b == 0) {
+            continue;
+        }
+        let value = raw
+            .iter()
+            .rev()
+            .fold(0u128, |value, &b| value << 8 | b as u128);
+        let _ = writeln!(
+            text,
+            ".header {:?} 0x{:0width$X}",
+            field,
+            value,
+            width = len * 2
+        );
+    }
+    if let Some(gd3) = &document.gd3 {
+        for (name, field) in Gd3::FIELDS.iter().zip(gd3.fields()) {
+            if let Some(value) = field {
+                let _ = writeln!(text, ".gd3 {} {}", name, escape(value));
+            }
+        }
+    }
+
+    let loop_index = document.loop_command_index();
+    let mut time = 0u64;
+    for (index, command) in document.commands.iter().enumerate() {
+        if loop_index == Some(index) {
+            let _ = writeln!(text, "{:<10} loop", time);
+        }
+        if let Some(line) = command_line(command) {
+            let _ = writeln!(text, "{:<10} {}", time, line);
+        }
+        time += command.wait_samples() as u64;
+    }
+    text
+}
+
+// The script form of `command`, `None` for waits.
+fn command_line(command: &VgmCommand) -> Option<String> {
+    match command {
+        VgmCommand::WaitSamples(_)
+        | VgmCommand::Wait735Samples(_)
+        | VgmCommand::Wait882Samples(_)
+        | VgmCommand::WaitNSample(_) => return None,
+        VgmCommand::YM2612Port0Address2AWriteAndWaitN(_) => return Some("dac".to_string()),
+        VgmCommand::EndOfData(_) => return Some("end".to_string()),
+        _ => {}
+    }
+    let Some(write) = command.register_write() else {
+        let (bytes, _) = command_to_vgm_bytes(command);
+        let mut line = String::from("raw ");
+        for b in bytes {
+            let _ = write!(line, "{:02x}", b);
+        }
+        return Some(line);
+    };
+    let mut line = format!("{:?}", write.chip).to_ascii_lowercase();
+    if write.instance == Instance::Secondary {
+        line.push_str(".2");
+    }
+    if write.port != 0 {
+        let _ = write!(line, " p{}", write.port);
+    }
+    if write.chip != Chip::Sn76489 {
+        let _ = write!(line, " {}", hex(write.register));
+    }
+    let _ = write!(line, " {}", hex(write.value));
+    Some(line)
+}
+
+fn hex(value: u32) -> String {
+    match value {
+        0..=0xFF => format!("{:02x}", value),
+        0x100..=0xFFFF => format!("{:04x}", value),
+        _ => format!("{:08x}", value),
+    }
+}
+
+/// Build a document from a command script.
+///
+/// # Errors
+/// Returns `ParseError::Other` naming the line of the first malformed
+/// directive or command, or of a time earlier than the line before it.
+pub fn from_script(text: &str) -> Result<VgmDocument, ParseError> {
+    let mut header_bytes = vec![0u8; HEADER_SIZE];
+    header_bytes[..4].copy_from_slice(b"Vgm ");
+    let mut gd3: Option<Gd3> = None;
+    let mut commands: Vec<VgmCommand> = Vec::new();
+    let mut loop_index = None;
+    let mut time = 0u64;
+    // A `dac` line waiting for the time of the next line.
+    let mut pending_dac = false;
+
+    for (line_number, line) in text.lines().enumerate() {
+        let error =
+            |what: String| ParseError::Other(format!("script line {}: {}", line_number + 1, what));
+        let line = line.strip_suffix('\r').unwrap_or(line).trim_start();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(rest) = line.strip_prefix(".header ") {
+            let mut parts = rest.split_whitespace();
+            let (Some(name), Some(value), None) = (parts.next(), parts.next(), parts.next()) else {
+                return Err(error("expected .header <field> <value>".into()));
+            };
+            let field = VgmHeaderField::ALL
+                .into_iter()
+                .find(|field| format!("{:?}", field) == name)
+                .filter(|field| !DERIVED_FIELDS.contains(field))
+                .ok_or_else(|| error(format!("unknown header field {:?}", name)))?;
+            let value = parse_number(value)
+                .ok_or_else(|| error(format!("invalid header value {:?}", value)))?;
+            let (offset, len) = (field.offset(), field.len());
+            if len < 16 && value >> (len * 8) != 0 {
+                return Err(error(format!("{:?} does not fit {:?}", value, field)));
+            }
+            for (i, b) in header_bytes[offset..offset + len].iter_mut().enumerate() {
+                *b = (value >> (i * 8)) as u8;
+            }
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix(".gd3 ") {
+            let (name, value) = rest.split_once(' ').unwrap_or((rest, ""));
+            let value = unescape(value).ok_or_else(|| error("invalid escape".into()))?;
+            let gd3 = gd3.get_or_insert_with(Gd3::default);
+            let field = gd3
+                .field_mut(name)
+                .ok_or_else(|| error(format!("unknown GD3 field {:?}", name)))?;
+            *field = (!value.is_empty()).then_some(value);
+            continue;
+        }
+        if line.starts_with('.') {
+            return Err(error(format!("unknown directive {:?}", line)));
+        }
+
+        let line = line.split_once('#').map_or(line, |(line, _)| line);
+        let mut parts = line.split_whitespace();
+        let at = parts
+            .next()
+            .and_then(|t| t.parse::<u64>().ok())
+            .ok_or_else(|| error("expected a time in samples".into()))?;
+        if at < time {
+            return Err(error(format!("time {} is before {}", at, time)));
+        }
+        let mut gap = at - time;
+        if pending_dac {
+            let wait = gap.min(15);
+            commands.push(Ym2612Port0Address2AWriteAndWaitN(wait as u8).into());
+            gap -= wait;
+            pending_dac = false;
+        }
+        crate::vgm::transform::push_wait(&mut commands, gap);
+        time = at;
+
+        let args: Vec<&str> = parts.collect();
+        match args[..] {
+            ["loop"] => loop_index = Some(commands.len()),
+            ["end"] => commands.push(EndOfData.into()),
+            ["dac"] => pending_dac = true,
+            ["raw", ref hex @ ..] => {
+                let bytes = parse_hex_bytes(&hex.concat())
+                    .ok_or_else(|| error("invalid hex bytes".into()))?;
+                let (command, len) = VgmCommand::parse_one(&bytes)
+                    .map_err(|e| error(format!("invalid command: {}", e)))?;
+                if len != bytes.len() {
+                    return Err(error(format!(
+                        "command takes {} bytes, {} given",
+                        len,
+                        bytes.len()
+                    )));
+                }
+                commands.push(command);
+            }
+            [name, ref operands @ ..] => {
+                let command = chip_write(name, operands)
+                    .ok_or_else(|| error(format!("invalid command {:?}", args.join(" "))))?;
+                commands.push(command);
+            }
+            [] => return Err(error("expected a command after the time".into())),
+        }
+    }
+    if pending_dac {
+        commands.push(Ym2612Port0Address2AWriteAndWaitN(0).into());
+    }
+
+    let mut header =
+        VgmHeader::from_bytes(&header_bytes).map_err(|e| ParseError::Other(e.to_string()))?;
+    header.data_offset = 0;
+    if header.version == 0 {
+        header.version = VgmHeader::default().version;
+    }
+    let mut builder = VgmBuilder::from(VgmDocument {
+        header,
+        extra_header: None,
+        commands: Vec::new(),
+        gd3,
+    });
+    builder.add_vgm_commands(commands);
+    if let Some(index) = loop_index {
+        builder.set_loop_index(index);
+    }
+    Ok(builder.finalize())
+}
+
+// `<chip>[.2] [p<port>] [<register>] <value>`
+fn chip_write(name: &str, operands: &[&str]) -> Option<VgmCommand> {
+    let (name, instance) = match name.strip_suffix(".2") {
+        Some(name) => (name, Instance::Secondary),
+        None => (name, Instance::Primary),
+    };
+    let chip = Chip::ALL
+        .into_iter()
+        .find(|chip| format!("{:?}", chip).eq_ignore_ascii_case(name))?;
+    let (port, operands) = match operands {
+        [port, rest @ ..] if port.starts_with('p') => (port[1..].parse().ok()?, rest),
+        _ => (0, operands),
+    };
+    let (register, value) = match operands {
+        [value] => (0, *value),
+        [register, value] => (u32::from_str_radix(register, 16).ok()?, *value),
+        _ => return None,
+    };
+    RegisterWrite {
+        chip,
+        instance,
+        port,
+        register,
+        value: u32::from_str_radix(value, 16).ok()?,
+    }
+    .to_command()
+}
+
+fn parse_number(text: &str) -> Option<u128> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u128::from_str_radix(&hex.replace('_', ""), 16).ok(),
+        None => text.replace('_', "").parse().ok(),
+    }
+}
+
+fn parse_hex_bytes(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
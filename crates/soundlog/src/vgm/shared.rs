@@ -0,0 +1,141 @@
+//! Immutable documents shared between threads.
+//!
+//! `SharedVgmDocument` wraps a parsed `VgmDocument` in an `Arc` so UI code
+//! and worker threads can hold the same document without cloning its
+//! commands. Cloning a `SharedVgmDocument` only bumps a reference count.
+//!
+//! Data derived from the document that is expensive to compute and often
+//! needed by several workers is computed once on first use and shared by all
+//! clones:
+//!
+//! - `sourcemap()`: the absolute `(offset, length)` of every command, as
+//!   returned by `VgmDocument::sourcemap`,
+//! - `serialized()`: the serialized VGM bytes, as returned by
+//!   `Vec::<u8>::from(&VgmDocument)`.
+//!
+//! The document derefs to `VgmDocument`, so every read-only API (analyses,
+//! iteration, header access) works on it directly. Use `into_document` to
+//! get an owned, mutable document back.
+//!
+//! ```rust
+//! use soundlog::vgm::command::WaitSamples;
+//! use soundlog::{SharedVgmDocument, VgmBuilder};
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.add_vgm_command(WaitSamples(735));
+//! let shared = SharedVgmDocument::from(builder.finalize());
+//!
+//! let worker = shared.clone();
+//! let handle = std::thread::spawn(move || worker.sourcemap().len());
+//! assert_eq!(handle.join().unwrap(), shared.commands.len());
+//! assert!(std::sync::Arc::ptr_eq(&shared.serialized(), &shared.clone().serialized()));
+//! ```
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{Arc, OnceLock};
+
+use crate::binutil::ParseError;
+use crate::vgm::VgmDocument;
+
+/// An immutable `VgmDocument` behind an `Arc`, cheap to clone and safe to
+/// send to other threads.
+#[derive(Clone)]
+pub struct SharedVgmDocument {
+    inner: Arc<Shared>,
+}
+
+struct Shared {
+    document: VgmDocument,
+    sourcemap: OnceLock<Arc<[(usize, usize)]>>,
+    serialized: OnceLock<Arc<[u8]>>,
+}
+
+impl SharedVgmDocument {
+    /// Share `document`.
+    pub fn new(document: VgmDocument) -> Self {
+        SharedVgmDocument {
+            inner: Arc::new(Shared {
+                document,
+                sourcemap: OnceLock::new(),
+                serialized: OnceLock::new(),
+            }),
+        }
+    }
+
+    /// The shared document.
+    pub fn document(&self) -> &VgmDocument {
+        &self.inner.document
+    }
+
+    /// Absolute `(offset, length)` of every command, computed on first use.
+    pub fn sourcemap(&self) -> Arc<[(usize, usize)]> {
+        self.inner
+            .sourcemap
+            .get_or_init(|| self.inner.document.sourcemap().into())
+            .clone()
+    }
+
+    /// The serialized VGM bytes, computed on first use.
+    pub fn serialized(&self) -> Arc<[u8]> {
+        self.inner
+            .serialized
+            .get_or_init(|| Vec::from(&self.inner.document).into())
+            .clone()
+    }
+
+    /// `true` when both values share the same document.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    /// The owned document. The document is moved out when this is the last
+    /// clone and cloned otherwise.
+    pub fn into_document(self) -> VgmDocument {
+        match Arc::try_unwrap(self.inner) {
+            Ok(shared) => shared.document,
+            Err(inner) => inner.document.clone(),
+        }
+    }
+}
+
+impl Deref for SharedVgmDocument {
+    type Target = VgmDocument;
+
+    fn deref(&self) -> &VgmDocument {
+        &self.inner.document
+    }
+}
+
+impl AsRef<VgmDocument> for SharedVgmDocument {
+    fn as_ref(&self) -> &VgmDocument {
+        &self.inner.document
+    }
+}
+
+impl From<VgmDocument> for SharedVgmDocument {
+    fn from(document: VgmDocument) -> Self {
+        SharedVgmDocument::new(document)
+    }
+}
+
+impl TryFrom<&[u8]> for SharedVgmDocument {
+    type Error = ParseError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        VgmDocument::try_from(bytes).map(SharedVgmDocument::new)
+    }
+}
+
+impl PartialEq for SharedVgmDocument {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other) || self.inner.document == other.inner.document
+    }
+}
+
+impl fmt::Debug for SharedVgmDocument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SharedVgmDocument")
+            .field(&self.inner.document)
+            .finish()
+    }
+}
@@ -0,0 +1,252 @@
+//! Chip register snapshots at a point in the command stream.
+//!
+//! Replays the chip writes of a document up to a command index or a sample
+//! position and keeps the last value written to every register, per chip
+//! instance. `ChipSnapshot::register_map` decodes a snapshot into the
+//! programmer's view of `chip::regmap`, and `diff_states` lists the registers
+//! that differ between two sample positions.
+//!
+//! ```
+//! use soundlog::VgmBuilder;
+//! use soundlog::chip::{Chip, Ym2612Spec};
+//! use soundlog::vgm::command::Instance;
+//! use soundlog::vgm::snapshot::snapshot_at_sample;
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+//! builder.add_chip_write(Instance::Primary, Ym2612Spec { port: 0, register: 0xB0, value: 0x32 });
+//! builder.add_wait_samples(100);
+//! builder.add_chip_write(Instance::Primary, Ym2612Spec { port: 0, register: 0xB0, value: 0x07 });
+//! let document = builder.finalize();
+//!
+//! let snapshots = snapshot_at_sample(&document, 50);
+//! assert_eq!(snapshots[0].read(0, 0xB0), Some(0x32));
+//! let snapshots = snapshot_at_sample(&document, 100);
+//! assert_eq!(snapshots[0].read(0, 0xB0), Some(0x07));
+//! ```
+use crate::VgmDocument;
+use crate::chip::Chip;
+use crate::chip::regmap::{RegisterMap, Registers, register_map};
+use crate::vgm::command::{Instance, RegisterWrite, VgmCommand};
+
+/// Registers of one chip instance at a point in the command stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChipSnapshot {
+    pub chip: Chip,
+    pub instance: Instance,
+    /// Last value written to every register, see `chip::regmap::Registers`
+    pub registers: Registers,
+    // SN76489 register selected by the last latch byte.
+    latch: u32,
+}
+
+impl ChipSnapshot {
+    pub(crate) fn new(chip: Chip, instance: Instance) -> Self {
+        Self {
+            chip,
+            instance,
+            registers: Registers::new(),
+            latch: 0,
+        }
+    }
+
+    /// Last value written to `register` of `port`, None if never written
+    pub fn read(&self, port: u8, register: u32) -> Option<u32> {
+        self.registers.get(&(port, register)).copied()
+    }
+
+    /// Decoded registers of the chip
+    pub fn register_map(&self) -> RegisterMap {
+        register_map(&self.chip, &self.registers)
+    }
+
+    pub(crate) fn write(&mut self, port: u8, register: u32, value: u32) {
+        if self.chip != Chip::Sn76489 || port != 0 {
+            self.registers.insert((port, register), value);
+            return;
+        }
+        // SN76489 writes carry no register number: a latch byte selects the
+        // register and sets its low bits, a data byte sets the high bits of
+        // a tone period or replaces the other registers.
+        let tone = |latch: u32| latch < 6 && latch & 1 == 0;
+        if value & 0x80 != 0 {
+            self.latch = (value >> 4) & 0x07;
+            let entry = self.registers.entry((0, self.latch)).or_default();
+            if tone(self.latch) {
+                *entry = (*entry & 0x3F0) | (value & 0x0F);
+            } else {
+                *entry = value & 0x0F;
+            }
+        } else {
+            let entry = self.registers.entry((0, self.latch)).or_default();
+            if tone(self.latch) {
+                *entry = (*entry & 0x0F) | ((value & 0x3F) << 4);
+            } else {
+                *entry = value & 0x0F;
+            }
+        }
+    }
+}
+
+/// Registers of every written chip after the commands before `index`, that
+/// is, when command `index` is about to run
+///
+/// Chips are listed in the order of their first write.
+pub fn snapshot_at_command(document: &VgmDocument, index: usize) -> Vec<ChipSnapshot> {
+    let mut snapshots = Vec::new();
+    replay(
+        &mut snapshots,
+        &document.commands[..index.min(document.commands.len())],
+    );
+    snapshots
+}
+
+fn replay(snapshots: &mut Vec<ChipSnapshot>, commands: &[VgmCommand]) {
+    for command in commands {
+        let Some(RegisterWrite {
+            chip,
+            instance,
+            port,
+            register,
+            value,
+        }) = command.register_write()
+        else {
+            continue;
+        };
+        let position = match snapshots
+            .iter()
+            .position(|s| s.chip == chip && s.instance == instance)
+        {
+            Some(position) => position,
+            None => {
+                snapshots.push(ChipSnapshot::new(chip, instance));
+                snapshots.len() - 1
+            }
+        };
+        snapshots[position].write(port, register, value);
+    }
+}
+
+/// Registers of every written chip at sample `sample`, including the writes
+/// made at that sample
+pub fn snapshot_at_sample(document: &VgmDocument, sample: u64) -> Vec<ChipSnapshot> {
+    snapshot_at_command(document, command_at_sample(document, sample))
+}
+
+/// Index of the first command that runs after sample `sample`, or the
+/// command count when the document ends first
+pub fn command_at_sample(document: &VgmDocument, sample: u64) -> usize {
+    let mut time = 0u64;
+    for (index, command) in document.commands.iter().enumerate() {
+        if time > sample {
+            return index;
+        }
+        time += command.wait_samples() as u64;
+    }
+    document.commands.len()
+}
+
+/// Registers of one chip instance that differ between two positions
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChipStateDiff {
+    pub chip: Chip,
+    pub instance: Instance,
+    /// Changed registers in (port, register) order
+    pub changes: Vec<RegisterChange>,
+}
+
+/// Value of one register at both positions of a `diff_states`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterChange {
+    pub port: u8,
+    pub register: u32,
+    /// Value at the first position, None if not written yet
+    pub before: Option<u32>,
+    /// Value at the second position, None if not written yet
+    pub after: Option<u32>,
+}
+
+/// Registers whose value differs between sample `sample_a` and sample
+/// `sample_b`, per chip
+///
+/// Both states are taken as with `snapshot_at_sample`. Registers rewritten
+/// with their old value in between are not listed, and chips without
+/// changes are left out. `sample_b` may come before `sample_a`.
+///
+/// ```
+/// use soundlog::VgmBuilder;
+/// use soundlog::chip::Ym2413Spec;
+/// use soundlog::vgm::command::Instance;
+/// use soundlog::vgm::snapshot::diff_states;
+///
+/// let mut builder = VgmBuilder::new();
+/// builder.add_chip_write(Instance::Primary, Ym2413Spec { register: 0x30, value: 0x10 });
+/// builder.add_wait_samples(44100);
+/// builder.add_chip_write(Instance::Primary, Ym2413Spec { register: 0x30, value: 0x20 });
+/// let document = builder.finalize();
+///
+/// let diff = diff_states(&document, 0, 44100);
+/// assert_eq!(diff[0].changes[0].register, 0x30);
+/// assert_eq!(diff[0].changes[0].before, Some(0x10));
+/// assert_eq!(diff[0].changes[0].after, Some(0x20));
+/// ```
+pub fn diff_states(document: &VgmDocument, sample_a: u64, sample_b: u64) -> Vec<ChipStateDiff> {
+    let index_a = command_at_sample(document, sample_a);
+    let index_b = command_at_sample(document, sample_b);
+    let (first, last) = (index_a.min(index_b), index_a.max(index_b));
+    let mut earlier = Vec::new();
+    replay(&mut earlier, &document.commands[..first]);
+    let mut later = earlier.clone();
+    replay(&mut later, &document.commands[first..last]);
+    let (before, after) = if index_a <= index_b {
+        (earlier, later)
+    } else {
+        (later, earlier)
+    };
+
+    // Chips only ever get added by a replay, so the longer list has all.
+    let chips = if before.len() >= after.len() {
+        &before
+    } else {
+        &after
+    };
+    let empty = Registers::new();
+    fn find<'a>(
+        snapshots: &'a [ChipSnapshot],
+        chip: &ChipSnapshot,
+        empty: &'a Registers,
+    ) -> &'a Registers {
+        snapshots
+            .iter()
+            .find(|s| s.chip == chip.chip && s.instance == chip.instance)
+            .map_or(empty, |s| &s.registers)
+    }
+    chips
+        .iter()
+        .filter_map(|chip| {
+            let old = find(&before, chip, &empty);
+            let new = find(&after, chip, &empty);
+            let mut addresses: Vec<&(u8, u32)> = old.keys().chain(new.keys()).collect();
+            addresses.sort();
+            addresses.dedup();
+            let changes: Vec<RegisterChange> = addresses
+                .into_iter()
+                .filter_map(|&(port, register)| {
+                    let before = old.get(&(port, register)).copied();
+                    let after = new.get(&(port, register)).copied();
+                    (before != after).then_some(RegisterChange {
+                        port,
+                        register,
+                        before,
+                        after,
+                    })
+                })
+                .collect();
+            (!changes.is_empty()).then(|| ChipStateDiff {
+                chip: chip.chip.clone(),
+                instance: chip.instance,
+                changes,
+            })
+        })
+        .collect()
+}
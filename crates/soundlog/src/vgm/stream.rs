@@ -7,25 +7,38 @@
 //! - parsing VGM commands incrementally from a byte stream
 //! - handling DAC Stream Control (SetupStreamControl / Start/Stop / FastCall)
 //! - expanding Wait commands with interleaved stream-generated chip writes
+//! - resolving DAC streams that drive the same chip register
+//!   (`StreamOverlapPolicy`) and recording such collisions
 //! - storing and decompressing data blocks used by DAC streams
+//!   (`LateDataBlockPolicy` for blocks read after playback has started)
+//! - muting channels of FM and PSG chips (`set_channel_mask`) by rewriting
+//!   their key-on and level writes
+//!
+//! Stream data blocks (`0x00`-`0x7E`) are consumed by the stream and not
+//! yielded. Uncompressed ones are appended to the stream's data banks straight
+//! from the document or the parsed command, without cloning the command, so
+//! iterating a PCM-heavy file copies each sample once. ROM/RAM dumps
+//! (`0x80`-`0xFF`) are yielded as the original block.
 //!
 //! See the `VgmStream` type below for usage examples and more detailed docs.
 //!
 use crate::VgmDocument;
-use crate::binutil::ParseError;
+use crate::binutil::{FileOffset, ParseError};
 use crate::chip;
 use crate::vgm::command::{
-    DataBlock, Instance, LengthMode, SetStreamData, SetStreamFrequency, SetupStreamControl,
-    StartStream, StartStreamFastCall, StopStream, VgmCommand, WaitSamples,
+    DataBlock, Instance, LengthMode, RegisterWrite, SetStreamData, SetStreamFrequency,
+    SetupStreamControl, StartStream, StartStreamFastCall, StopStream, VgmCommand, WaitSamples,
     Ym2612Port0Address2AWriteAndWaitN,
 };
+use crate::vgm::compression::decompress_block_with_limits;
 use crate::vgm::detail::{
-    BitPackingSubType, CompressedStream, CompressedStreamData, DataBlockType, DecompressionTable,
-    StreamChipType, UncompressedStream, parse_data_block,
+    CompressedStream, DataBlockType, DecompressionTable, StreamChipType, UncompressedStream,
+    parse_data_block,
 };
-use crate::vgm::header::{ChipId, VgmHeader, VgmHeaderField};
-use crate::vgm::parser::parse_vgm_command;
-use std::collections::HashMap;
+use crate::vgm::header::{ChipId, DEFAULT_SAMPLE_RATE, VgmHeader, VgmHeaderField};
+use crate::vgm::parser::{ParseLimits, parse_vgm_command};
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
 
 /// Minimum buffer capacity (in bytes) at which we consider shrinking the
 /// parser's internal byte buffer. The shrink logic avoids attempting to reduce
@@ -179,13 +192,16 @@ struct StreamSnapshot {
     stream_start_sample: usize,
     /// Step index of the last byte already emitted (`None` = nothing emitted yet).
     last_emitted_step: Option<usize>,
+    /// Samples per second of `VgmStream::current_sample`.
+    sample_rate: u32,
 }
 
 impl StreamSnapshot {
-    /// Build a snapshot view from `state` and pre-fetched `data_bank_end`.
+    /// Build a snapshot view from `state`, pre-fetched `data_bank_end` and the
+    /// stream's `sample_rate`.
     /// Returns `None` if the stream is inactive, has no valid frequency, or is
     /// in `Ignore` mode (which moves the data position but never emits writes).
-    fn from_state(state: &StreamState, data_bank_end: usize) -> Option<Self> {
+    fn from_state(state: &StreamState, data_bank_end: usize, sample_rate: u32) -> Option<Self> {
         if !state.active {
             return None;
         }
@@ -213,6 +229,7 @@ impl StreamSnapshot {
             data_bank_end,
             stream_start_sample: state.stream_start_sample,
             last_emitted_step: state.last_emitted_step,
+            sample_rate,
         })
     }
 
@@ -245,11 +262,11 @@ impl StreamSnapshot {
 
     /// Sample number at which write #`n` (0-based) should be emitted.
     ///
-    /// Uses the formula  `start + n * 44100 / freq`  (integer arithmetic) which
+    /// Uses the formula  `start + n * sample_rate / freq`  (integer arithmetic) which
     /// guarantees that every write lands at the nearest integer sample boundary
     /// without floating-point accumulation error.
     fn write_sample_for_step(&self, n: usize) -> usize {
-        self.stream_start_sample + n * 44100 / self.freq as usize
+        self.stream_start_sample + n * self.sample_rate as usize / self.freq as usize
     }
 
     /// Position computation
@@ -431,6 +448,51 @@ impl StreamSnapshot {
     }
 }
 
+/// Nanoseconds since sample 0 of `sample` at `sample_rate`.
+fn sample_to_ns(sample: usize, sample_rate: u32) -> u64 {
+    sample as u64 * 1_000_000_000 / sample_rate as u64
+}
+
+/// First sample at `sample_rate` at or after `ns` nanoseconds.
+fn ns_to_sample_ceil(ns: u64, sample_rate: u32) -> usize {
+    (ns * sample_rate as u64).div_ceil(1_000_000_000) as usize
+}
+
+/// Appends `data` to data bank `bank`, or stores it in place of the bank's
+/// contents when `replace` is set. Returns the offset of `data` in the bank
+/// and the number of bytes removed.
+fn store_bank_data(
+    banks: &mut HashMap<u8, UncompressedStream>,
+    bank: u8,
+    chip_type: StreamChipType,
+    data: Cow<'_, [u8]>,
+    replace: bool,
+) -> (usize, usize) {
+    match banks.get_mut(&bank) {
+        Some(existing) if replace => {
+            let removed = existing.data.len();
+            existing.data.clear();
+            existing.data.extend_from_slice(&data);
+            (0, removed)
+        }
+        Some(existing) => {
+            let offset = existing.data.len();
+            existing.data.extend_from_slice(&data);
+            (offset, 0)
+        }
+        None => {
+            banks.insert(
+                bank,
+                UncompressedStream {
+                    chip_type,
+                    data: data.into_owned(),
+                },
+            );
+            (0, 0)
+        }
+    }
+}
+
 /// Result type for stream parsing operations.
 /// Default maximum size for accumulated data blocks (32 MiB).
 const DEFAULT_MAX_DATA_BLOCK_SIZE: usize = 32 * 1024 * 1024;
@@ -448,6 +510,218 @@ pub enum StreamResult {
     EndOfStream,
 }
 
+/// Result of `VgmStream::next_ref`: a `StreamResult` whose command may be
+/// borrowed from the stream's document.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamResultRef<'a> {
+    /// A complete command, borrowed for ROM/RAM dumps of a document source.
+    Command(Cow<'a, VgmCommand>),
+    /// More data is needed to complete the current command.
+    NeedsMoreData,
+    /// The VGM stream has ended.
+    EndOfStream,
+}
+
+impl StreamResultRef<'_> {
+    /// The owned `StreamResult`, copying a borrowed command.
+    pub fn into_owned(self) -> StreamResult {
+        match self {
+            StreamResultRef::Command(command) => StreamResult::Command(command.into_owned()),
+            StreamResultRef::NeedsMoreData => StreamResult::NeedsMoreData,
+            StreamResultRef::EndOfStream => StreamResult::EndOfStream,
+        }
+    }
+}
+
+impl From<StreamResult> for StreamResultRef<'_> {
+    fn from(result: StreamResult) -> Self {
+        match result {
+            StreamResult::Command(command) => StreamResultRef::Command(Cow::Owned(command)),
+            StreamResult::NeedsMoreData => StreamResultRef::NeedsMoreData,
+            StreamResult::EndOfStream => StreamResultRef::EndOfStream,
+        }
+    }
+}
+
+/// How `VgmStream` handles a DAC stream that is started while another active
+/// stream already writes to the same chip register.
+///
+/// The VGM specification does not define what happens when two streams drive
+/// the same register (for example two streams feeding the YM2612 DAC at
+/// `0x2A`). Every collision is recorded as a `StreamCollision` regardless of
+/// the policy; see `VgmStream::stream_collisions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StreamOverlapPolicy {
+    /// Both streams keep running and their writes are interleaved. This is
+    /// the historical behavior.
+    #[default]
+    Interleave,
+    /// The newly started stream takes over the register; the streams that were
+    /// driving it are stopped.
+    LastWins,
+    /// Starting a colliding stream fails with `ParseError::DataInconsistency`.
+    Forbid,
+    /// The stream with the higher priority (see `VgmStream::set_stream_priority`)
+    /// keeps the register. A new stream replaces active streams of lower or
+    /// equal priority and is not started if any of them has a higher priority.
+    Priority,
+}
+
+/// What `VgmStream` did about a `StreamCollision`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamCollisionResolution {
+    /// Both streams keep writing (`StreamOverlapPolicy::Interleave`).
+    Interleaved,
+    /// The already active stream was stopped.
+    StoppedActive,
+    /// The new stream was not started.
+    RejectedNew,
+    /// Processing failed with an error (`StreamOverlapPolicy::Forbid`).
+    Error,
+}
+
+/// What `VgmStream` does with a generated write that comes too soon after the
+/// previous generated write to the same chip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WriteRateLimitMode {
+    /// Hold the write back until the gap has elapsed. A held-back write is
+    /// replaced by a newer write to the same register, so only the latest
+    /// value reaches the chip.
+    #[default]
+    Coalesce,
+    /// Discard the write.
+    Drop,
+}
+
+/// Minimum spacing between DAC stream writes to one chip.
+///
+/// Real hardware players cannot push writes to a chip faster than its bus
+/// allows; a DAC stream running at a high frequency (or two streams feeding
+/// the same chip) easily exceeds that. See `VgmStream::set_write_rate_limit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteRateLimit {
+    /// Minimum distance in samples (44.1 kHz) between two generated writes
+    /// to the same chip instance. `0` disables the limit.
+    pub min_gap_samples: usize,
+    pub mode: WriteRateLimitMode,
+}
+
+/// Counters of writes affected by the `WriteRateLimit` and the chip write
+/// delays (`VgmStream::set_chip_write_delay`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WriteRateLimitReport {
+    /// Writes emitted later than scheduled.
+    pub delayed: usize,
+    /// Held-back writes replaced by a newer write to the same register.
+    pub coalesced: usize,
+    /// Writes discarded (`WriteRateLimitMode::Drop`).
+    pub dropped: usize,
+}
+
+/// Playback settings for `VgmStream::from_commands` that a VGM header
+/// would otherwise carry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamHint {
+    /// Index of the command playback jumps back to at the end, `None` to
+    /// play once.
+    pub loop_index: Option<usize>,
+    /// Sample rate in Hz. Default: 44100.
+    pub sample_rate: u32,
+    /// See `VgmStream::set_loop_base`.
+    pub loop_base: i8,
+    /// See `VgmStream::set_loop_modifier`.
+    pub loop_modifier: u8,
+}
+
+impl Default for StreamHint {
+    fn default() -> Self {
+        StreamHint {
+            loop_index: None,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            loop_base: 0,
+            loop_modifier: 0,
+        }
+    }
+}
+
+/// The sample rate and loop settings of `header`. The loop point is left
+/// out: the header loop offset points into serialized bytes, not at a
+/// command index.
+impl From<&VgmHeader> for StreamHint {
+    fn from(header: &VgmHeader) -> Self {
+        StreamHint {
+            loop_index: None,
+            sample_rate: header.effective_sample_rate(),
+            loop_base: header.loop_base,
+            loop_modifier: header.loop_modifier,
+        }
+    }
+}
+
+/// How `VgmStream` handles a stream data block (`0x00`-`0x7E`) read after
+/// playback has started, that is after the first wait.
+///
+/// Most files store all samples before the first wait, but some loggers
+/// write a data block right before the sound that uses it, and a loop point
+/// placed before a data block makes it reappear on every iteration. Every
+/// such block is recorded as a `LateDataBlock` regardless of the policy; see
+/// `VgmStream::late_data_blocks`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LateDataBlockPolicy {
+    /// The block is appended to its data bank, like blocks read before
+    /// playback. This is the historical behavior.
+    #[default]
+    Extend,
+    /// The block replaces the contents of its data bank. Block IDs of the
+    /// earlier blocks of the bank no longer point at their data.
+    Replace,
+    /// Reading the block fails with `ParseError::DataInconsistency`.
+    Forbid,
+}
+
+/// A stream data block read after playback had started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LateDataBlock {
+    /// `VgmStream::current_sample` when the block was read.
+    pub sample: usize,
+    /// Data bank (data type) the block was stored in.
+    pub bank: u8,
+    /// Length of the bank before the block.
+    pub previous_len: usize,
+    /// Length of the bank after the block, `previous_len` when the block
+    /// was rejected.
+    pub len: usize,
+    /// The policy the block was handled with.
+    pub policy: LateDataBlockPolicy,
+}
+
+/// A generated write held back by the rate limit or a busy chip.
+#[derive(Debug, Clone, Copy)]
+struct DeferredWrite {
+    chip_id: ChipId,
+    instance: Instance,
+    write_port: u8,
+    write_command: u8,
+    value: u8,
+}
+
+/// Two DAC streams targeting the same chip register at the same time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamCollision {
+    /// `VgmStream::current_sample` when the new stream was started.
+    pub sample: usize,
+    /// Stream being started.
+    pub stream_id: u8,
+    /// Stream that was already writing to the register.
+    pub active_stream_id: u8,
+    /// Shared write target.
+    pub chip_id: ChipId,
+    pub instance: Instance,
+    pub write_port: u8,
+    pub write_command: u8,
+    pub resolution: StreamCollisionResolution,
+}
+
 /// Memory-efficient streaming VGM parser.
 ///
 /// `VgmStream` is the primary entry point for incremental processing of VGM data.
@@ -484,7 +758,8 @@ pub enum StreamResult {
 ///    `set_max_buffer_size()` and query via `max_buffer_size()`.
 ///
 /// 2. **Data block size limit**: Controls the total size of accumulated data blocks
-///    (PCM, DAC stream data, etc.). Default is 32 MiB. Configure via
+///    (PCM, DAC stream data, etc.); compressed blocks count with their
+///    decompressed size. Default is 32 MiB. Configure via
 ///    `set_max_data_block_size()` and query via `max_data_block_size()` /
 ///    `total_data_block_size()`.
 ///
@@ -658,8 +933,13 @@ pub struct VgmStream {
     pending_data_block: Option<DataBlock>,
     /// DAC stream states indexed by stream ID
     stream_states: HashMap<u8, StreamState>,
-    /// Current sample position (at 44100 Hz)
+    /// Current sample position (at `sample_rate`)
     current_sample: usize,
+    /// Absolute sample at which `current_sample` started counting (the
+    /// samples of all completed loop iterations)
+    sample_base: u64,
+    /// Absolute sample of the command last returned by `next`
+    last_result_sample: u64,
     /// Pending stream write commands to emit
     pending_stream_writes: Vec<VgmCommand>,
     /// Pending wait time that hasn't been emitted yet (in samples)
@@ -684,9 +964,43 @@ pub struct VgmStream {
     /// Scales the effective loop count:
     ///  NumLoops = ProgramNumLoops * loop_modifier / 0x10
     loop_modifier: u8,
+    /// Samples per second of waits and sample positions
+    sample_rate: u32,
     /// Scratch buffer reused across `generate_stream_writes` calls to avoid
     /// repeated allocation when collecting active stream IDs.
     stream_id_scratch: Vec<u8>,
+    /// Policy applied when two DAC streams drive the same register
+    stream_overlap_policy: StreamOverlapPolicy,
+    /// Per-stream priorities used by `StreamOverlapPolicy::Priority`
+    stream_priorities: HashMap<u8, u8>,
+    /// DAC stream collisions recorded since the last reset / take
+    stream_collisions: Vec<StreamCollision>,
+    /// Policy applied to stream data blocks read after the first wait
+    late_data_block_policy: LateDataBlockPolicy,
+    /// Late stream data blocks recorded since the last reset / take
+    late_data_blocks: Vec<LateDataBlock>,
+    /// Whether a wait has been played since the last reset; later stream
+    /// data blocks are late
+    playback_started: bool,
+    /// Minimum gap between generated writes per chip (None = unlimited)
+    write_rate_limit: Option<WriteRateLimit>,
+    /// Sample of the last generated write per chip instance
+    last_generated_write: HashMap<(ChipId, Instance), usize>,
+    /// Generated writes held back by the rate limit, oldest first
+    deferred_writes: Vec<DeferredWrite>,
+    /// Writes affected by the rate limit since the last reset
+    write_rate_limit_report: WriteRateLimitReport,
+    /// Bus busy time in nanoseconds after each write, per chip
+    chip_write_delays: HashMap<chip::Chip, u32>,
+    /// Time (ns since the start of the loop iteration) at which each chip
+    /// instance's bus becomes free again
+    chip_busy_until: HashMap<(chip::Chip, Instance), u64>,
+    /// Documents queued with `append_document`, played after the current one
+    queued_documents: VecDeque<VgmDocument>,
+    /// Channel masks and the key-on / level writes seen, per chip instance
+    channel_mutes: HashMap<(chip::Chip, Instance), ChannelMute>,
+    /// Writes that apply a changed channel mask, emitted before the next command
+    mask_writes: VecDeque<VgmCommand>,
 }
 
 impl VgmStream {
@@ -757,6 +1071,8 @@ impl VgmStream {
             pending_data_block: None,
             stream_states: HashMap::new(),
             current_sample: 0,
+            sample_base: 0,
+            last_result_sample: 0,
             pending_stream_writes: Vec::new(),
             pending_wait: None,
             fadeout_samples: None,
@@ -767,7 +1083,23 @@ impl VgmStream {
             max_buffer_size: DEFAULT_MAX_BUFFER_SIZE,
             loop_base: 0,
             loop_modifier: 0,
+            sample_rate: DEFAULT_SAMPLE_RATE,
             stream_id_scratch: Vec::new(),
+            stream_overlap_policy: StreamOverlapPolicy::default(),
+            stream_priorities: HashMap::new(),
+            stream_collisions: Vec::new(),
+            late_data_block_policy: LateDataBlockPolicy::default(),
+            late_data_blocks: Vec::new(),
+            playback_started: false,
+            write_rate_limit: None,
+            last_generated_write: HashMap::new(),
+            deferred_writes: Vec::new(),
+            write_rate_limit_report: WriteRateLimitReport::default(),
+            chip_write_delays: HashMap::new(),
+            chip_busy_until: HashMap::new(),
+            queued_documents: VecDeque::new(),
+            channel_mutes: HashMap::new(),
+            mask_writes: VecDeque::new(),
         }
     }
 
@@ -812,6 +1144,7 @@ impl VgmStream {
         let loop_index = Self::calculate_loop_index(&document);
         let loop_base = document.header.loop_base;
         let loop_modifier = document.header.loop_modifier;
+        let sample_rate = document.header.effective_sample_rate();
         Self {
             source: VgmStreamSource::Document {
                 document: Box::new(document),
@@ -820,6 +1153,56 @@ impl VgmStream {
             },
             loop_base,
             loop_modifier,
+            sample_rate,
+            ..Self::default()
+        }
+    }
+
+    /// Creates a new VGM stream processor from generated or transformed
+    /// commands, without building a `VgmDocument` first.
+    ///
+    /// DAC streams, data blocks and loops are handled as for
+    /// `from_document`; `hint` supplies what the header would. An
+    /// `EndOfData` is appended when `commands` has none, so the loop at
+    /// `hint.loop_index` is taken. A loop index past the end is ignored.
+    ///
+    /// # Examples
+    /// ```
+    /// use soundlog::vgm::command::{VgmCommand, WaitSamples};
+    /// use soundlog::vgm::stream::{StreamHint, StreamResult, VgmStream};
+    ///
+    /// let commands = (0..4).map(|_| VgmCommand::from(WaitSamples(100)));
+    /// let hint = StreamHint {
+    ///     loop_index: Some(2),
+    ///     ..StreamHint::default()
+    /// };
+    /// let mut stream = VgmStream::from_commands(commands, hint);
+    /// stream.set_loop_count(Some(2));
+    /// while let Some(Ok(StreamResult::Command(_))) = stream.next() {}
+    /// assert_eq!(stream.absolute_sample(), 600);
+    /// ```
+    pub fn from_commands(commands: impl IntoIterator<Item = VgmCommand>, hint: StreamHint) -> Self {
+        let mut commands: Vec<VgmCommand> = commands.into_iter().collect();
+        if !commands
+            .iter()
+            .any(|c| matches!(c, VgmCommand::EndOfData(_)))
+        {
+            commands.push(VgmCommand::EndOfData(crate::vgm::command::EndOfData {}));
+        }
+        let loop_index = hint.loop_index.filter(|&index| index < commands.len());
+        let document = VgmDocument {
+            commands,
+            ..VgmDocument::default()
+        };
+        Self {
+            source: VgmStreamSource::Document {
+                document: Box::new(document),
+                current_index: 0,
+                loop_index,
+            },
+            loop_base: hint.loop_base,
+            loop_modifier: hint.loop_modifier,
+            sample_rate: hint.sample_rate,
             ..Self::default()
         }
     }
@@ -869,29 +1252,11 @@ impl VgmStream {
     /// ```
     pub fn from_vgm(data: impl Into<Vec<u8>>) -> Result<Self, ParseError> {
         let data = data.into();
-        let header = VgmHeader::from_bytes(&data)?;
-
-        // Absolute byte offset of the first command.
-        // Use the VgmHeader helper to compute the command start consistently.
-        let command_start = VgmHeader::command_start(header.version, header.data_offset);
-
-        // Absolute loop position: field at 0x1C is relative to that offset.
-        // Validate that it actually falls within the command region.
-        let loop_pos = if header.loop_offset != 0 {
-            let abs = VgmHeaderField::LoopOffset
-                .offset()
-                .wrapping_add(header.loop_offset as usize);
-            if abs >= command_start && abs < data.len() {
-                Some(abs)
-            } else {
-                None
-            }
-        } else {
-            None
-        };
+        let (header, command_start, loop_pos) = Self::file_layout(&data)?;
 
         let loop_base = header.loop_base;
         let loop_modifier = header.loop_modifier;
+        let sample_rate = header.effective_sample_rate();
 
         Ok(Self {
             source: VgmStreamSource::File {
@@ -902,10 +1267,33 @@ impl VgmStream {
             },
             loop_base,
             loop_modifier,
+            sample_rate,
             ..Self::default()
         })
     }
 
+    /// Parses the header of a raw VGM file and returns it together with the
+    /// absolute offsets of the first command and of the loop point.
+    fn file_layout(data: &[u8]) -> Result<(VgmHeader, usize, Option<usize>), ParseError> {
+        let header = VgmHeader::from_bytes(data)?;
+
+        // Absolute byte offset of the first command.
+        // Use the VgmHeader helper to compute the command start consistently.
+        let command_start = VgmHeader::command_start(header.version, header.data_offset);
+
+        // Absolute loop position: field at 0x1C is relative to that offset.
+        // Validate that it actually falls within the command region.
+        let loop_pos = if header.loop_offset != 0 {
+            FileOffset::from_relative(VgmHeaderField::LoopOffset.offset(), header.loop_offset)
+                .map(FileOffset::get)
+                .filter(|abs| (command_start..data.len()).contains(abs))
+        } else {
+            None
+        };
+
+        Ok((header, command_start, loop_pos))
+    }
+
     /// Calculates the command index corresponding to loop_offset in the header.
     fn calculate_loop_index(doc: &VgmDocument) -> Option<usize> {
         if doc.header.loop_offset == 0 {
@@ -914,21 +1302,18 @@ impl VgmStream {
 
         let data_offset = VgmHeader::data_offset(doc.header.version, doc.header.data_offset);
 
-        let mut header_len = doc.header.to_bytes(0, data_offset).len() as u32;
+        let mut header_len = FileOffset::new(doc.header.to_bytes(0, data_offset).len());
         if let Some(ref extra) = doc.extra_header {
-            header_len += extra.to_bytes().len() as u32;
+            header_len = header_len.checked_add(extra.to_bytes().len())?;
         }
         let loop_abs_offset =
-            (VgmHeaderField::LoopOffset.offset() as u32).wrapping_add(doc.header.loop_offset);
-        let loop_command_offset = loop_abs_offset.wrapping_sub(header_len);
+            FileOffset::from_relative(VgmHeaderField::LoopOffset.offset(), doc.header.loop_offset)?;
+        let loop_command_offset = loop_abs_offset.offset_from(header_len)?;
 
         let offsets = doc.command_offsets_and_lengths();
-        for (index, (cmd_offset, _len)) in offsets.iter().enumerate() {
-            if *cmd_offset as u32 == loop_command_offset {
-                return Some(index);
-            }
-        }
-        None
+        offsets
+            .iter()
+            .position(|&(cmd_offset, _len)| cmd_offset == loop_command_offset)
     }
 
     /// Adds new data to the internal buffer for parsing.
@@ -970,12 +1355,177 @@ impl VgmStream {
         }
     }
 
+    /// Queues another document to play after the current one.
+    ///
+    /// When the current document ends (after its loops and fadeout), the stream
+    /// continues with the first queued document instead of returning
+    /// `StreamResult::EndOfStream`, without a gap: the next document starts at
+    /// the sample where the previous one ended, so `absolute_sample` keeps
+    /// counting across tracks while `current_sample` restarts at 0.
+    ///
+    /// Each document starts from a clean slate, as if it had its own stream:
+    /// data blocks, DAC streams and the loop counter are cleared, and the loop
+    /// base and modifier are taken from its header. Settings such as the loop
+    /// count, the fadeout and the DAC stream policies apply to every document.
+    /// `reset` and `seek_to_sample` operate on the document that is currently
+    /// playing and leave the queue untouched.
+    ///
+    /// # Errors
+    /// Returns `ParseError::Other` if the stream was not created with
+    /// `VgmStream::from_document`.
+    ///
+    /// # Examples
+    /// ```
+    /// use soundlog::VgmBuilder;
+    /// use soundlog::vgm::command::WaitSamples;
+    /// use soundlog::vgm::stream::{StreamResult, VgmStream};
+    ///
+    /// let track = |wait| {
+    ///     let mut builder = VgmBuilder::new();
+    ///     builder.add_vgm_command(WaitSamples(wait));
+    ///     builder.finalize()
+    /// };
+    ///
+    /// let mut stream = VgmStream::from_document(track(100));
+    /// stream.append_document(track(50)).unwrap();
+    /// assert_eq!(stream.queued_documents(), 1);
+    ///
+    /// while let Some(Ok(StreamResult::Command(_))) = stream.next() {}
+    /// assert_eq!(stream.absolute_sample(), 150);
+    /// assert_eq!(stream.queued_documents(), 0);
+    /// ```
+    pub fn append_document(&mut self, document: VgmDocument) -> Result<(), ParseError> {
+        match self.source {
+            VgmStreamSource::Document { .. } => {
+                self.queued_documents.push_back(document);
+                Ok(())
+            }
+            _ => Err(ParseError::Other(
+                "append_document() can only be called on a VgmStream created from a document"
+                    .into(),
+            )),
+        }
+    }
+
+    /// Number of documents queued with `append_document` that have not started
+    /// playing yet.
+    pub fn queued_documents(&self) -> usize {
+        self.queued_documents.len()
+    }
+
+    /// Switches to the next queued document, continuing the absolute clock.
+    /// Returns `false` when the queue is empty.
+    fn start_next_document(&mut self) -> bool {
+        let Some(document) = self.queued_documents.pop_front() else {
+            return false;
+        };
+        let sample_base = self.absolute_sample();
+        let stream_collisions = std::mem::take(&mut self.stream_collisions);
+        let late_data_blocks = std::mem::take(&mut self.late_data_blocks);
+        let write_rate_limit_report = std::mem::take(&mut self.write_rate_limit_report);
+
+        self.load_document(document);
+        self.reset();
+
+        self.sample_base = sample_base;
+        self.last_result_sample = sample_base;
+        self.stream_collisions = stream_collisions;
+        self.late_data_blocks = late_data_blocks;
+        self.write_rate_limit_report = write_rate_limit_report;
+        true
+    }
+
+    /// Replaces the source with `document`, reusing the boxed document of a
+    /// document-backed stream. The caller resets the playback state.
+    fn load_document(&mut self, document: VgmDocument) {
+        self.loop_base = document.header.loop_base;
+        self.loop_modifier = document.header.loop_modifier;
+        self.sample_rate = document.header.effective_sample_rate();
+        let new_loop_index = Self::calculate_loop_index(&document);
+        match &mut self.source {
+            VgmStreamSource::Document {
+                document: current,
+                loop_index,
+                ..
+            } => {
+                **current = document;
+                *loop_index = new_loop_index;
+            }
+            source => {
+                *source = VgmStreamSource::Document {
+                    document: Box::new(document),
+                    current_index: 0,
+                    loop_index: new_loop_index,
+                };
+            }
+        }
+    }
+
+    /// Like `next()`, but a ROM/RAM dump data block (`0x80..=0xFF`) of a
+    /// document source is borrowed from the document instead of copied, so
+    /// iterating files with large dumps does not duplicate them. Every other
+    /// result is owned and equal to what `next()` returns.
+    ///
+    /// ```rust
+    /// use std::borrow::Cow;
+    ///
+    /// use soundlog::VgmBuilder;
+    /// use soundlog::vgm::command::{DataBlock, WaitSamples};
+    /// use soundlog::vgm::stream::{StreamResultRef, VgmStream};
+    ///
+    /// let mut builder = VgmBuilder::new();
+    /// builder.add_vgm_command(DataBlock {
+    ///     marker: 0x66,
+    ///     chip_instance: 0,
+    ///     data_type: 0x80,
+    ///     size: 8,
+    ///     data: vec![0x00, 0x10, 0, 0, 0, 0, 0, 0],
+    /// });
+    /// builder.add_vgm_command(WaitSamples(1));
+    /// let mut stream = VgmStream::from_document(builder.finalize());
+    ///
+    /// assert!(matches!(
+    ///     stream.next_ref(),
+    ///     Some(Ok(StreamResultRef::Command(Cow::Borrowed(_))))
+    /// ));
+    /// assert!(matches!(
+    ///     stream.next_ref(),
+    ///     Some(Ok(StreamResultRef::Command(Cow::Owned(_))))
+    /// ));
+    /// ```
+    pub fn next_ref(&mut self) -> Option<Result<StreamResultRef<'_>, ParseError>> {
+        match self.next_document_rom_block() {
+            Ok(Some(index)) => {
+                let VgmStreamSource::Document { document, .. } = &self.source else {
+                    unreachable!("checked by next_document_rom_block");
+                };
+                Some(Ok(StreamResultRef::Command(Cow::Borrowed(
+                    &document.commands[index],
+                ))))
+            }
+            Ok(None) => self.next().map(|result| result.map(StreamResultRef::from)),
+            Err(e) => Some(Err(e)),
+        }
+    }
+
+    /// Returns the next result, moving on to the next queued document when the
+    /// current one has ended.
+    fn next_command(&mut self) -> Result<StreamResult, ParseError> {
+        loop {
+            match self.next_command_in_document()? {
+                StreamResult::EndOfStream | StreamResult::NeedsMoreData
+                    if self.start_next_document() => {}
+                result => return Ok(result),
+            }
+        }
+    }
+
     /// Attempts to parse the next complete command from the buffer.
     ///
     /// Returns `StreamResult::Command` if a complete command was parsed,
     /// `StreamResult::NeedsMoreData` if more bytes are required, or
     /// `StreamResult::EndOfStream` if the stream has ended.
-    fn next_command(&mut self) -> Result<StreamResult, ParseError> {
+    fn next_command_in_document(&mut self) -> Result<StreamResult, ParseError> {
         if !self.pending_stream_writes.is_empty() {
             let cmd = self.pending_stream_writes.remove(0);
             return Ok(StreamResult::Command(cmd));
@@ -1014,6 +1564,7 @@ impl VgmStream {
             }
         }
 
+        while self.store_document_stream_block()? {}
         let command = match self.get_next_raw_command()? {
             Some(cmd) => cmd,
             None => return Ok(StreamResult::NeedsMoreData),
@@ -1022,6 +1573,141 @@ impl VgmStream {
         self.process_command(command)
     }
 
+    /// Appends the next command of a document source to the data banks
+    /// without cloning it, when it is an uncompressed stream data block.
+    /// Returns `false` when the next command is anything else.
+    fn store_document_stream_block(&mut self) -> Result<bool, ParseError> {
+        let (data_type, data_len) = match &self.source {
+            VgmStreamSource::Document {
+                document,
+                current_index,
+                ..
+            } => match document.commands.get(*current_index) {
+                Some(VgmCommand::DataBlock(block)) if block.data_type <= 0x3F => {
+                    (block.data_type, block.data.len())
+                }
+                _ => return Ok(false),
+            },
+            _ => return Ok(false),
+        };
+        let new_total = self.total_data_block_size.saturating_add(data_len);
+        if new_total > self.max_data_block_size {
+            return Err(ParseError::DataBlockSizeExceeded {
+                current_size: self.total_data_block_size,
+                limit: self.max_data_block_size,
+                attempted_size: data_len,
+            });
+        }
+        let replace = self.admit_bank_data(data_type, data_len)?;
+
+        let Self {
+            source:
+                VgmStreamSource::Document {
+                    document,
+                    current_index,
+                    ..
+                },
+            uncompressed_streams,
+            block_id_map,
+            total_data_block_size,
+            ..
+        } = self
+        else {
+            unreachable!("checked above");
+        };
+        let Some(VgmCommand::DataBlock(block)) = document.commands.get(*current_index) else {
+            unreachable!("checked above");
+        };
+        *current_index += 1;
+        let (offset, removed) = store_bank_data(
+            uncompressed_streams,
+            data_type,
+            StreamChipType::from(data_type),
+            Cow::Borrowed(&block.data),
+            replace,
+        );
+        block_id_map.push((data_type, offset, data_len));
+        *total_data_block_size = new_total.saturating_sub(removed);
+        Ok(true)
+    }
+
+    /// Advances a document source past its next command when `next()`
+    /// would yield it unchanged as a ROM/RAM dump, and returns its index.
+    /// Returns `None` when anything else comes first.
+    fn next_document_rom_block(&mut self) -> Result<Option<usize>, ParseError> {
+        if !self.mask_writes.is_empty()
+            || !self.pending_stream_writes.is_empty()
+            || self.pending_wait.is_some()
+            || self.pending_data_block.is_some()
+            || self.encountered_end
+        {
+            return Ok(None);
+        }
+        while self.store_document_stream_block()? {}
+        let VgmStreamSource::Document {
+            document,
+            current_index,
+            ..
+        } = &self.source
+        else {
+            return Ok(None);
+        };
+        let index = *current_index;
+        let (data_type, data_len) = match document.commands.get(index) {
+            Some(VgmCommand::DataBlock(block))
+                if block.data_type >= 0x80 && block.size as usize == block.data.len() =>
+            {
+                (block.data_type, block.data.len())
+            }
+            _ => return Ok(None),
+        };
+        let new_total = self.total_data_block_size.saturating_add(data_len);
+        if new_total > self.max_data_block_size {
+            return Err(ParseError::DataBlockSizeExceeded {
+                current_size: self.total_data_block_size,
+                limit: self.max_data_block_size,
+                attempted_size: data_len,
+            });
+        }
+        self.record_rom_block(data_type, data_len);
+        if let VgmStreamSource::Document { current_index, .. } = &mut self.source {
+            *current_index += 1;
+        }
+        self.last_result_sample = self.absolute_sample();
+        Ok(Some(index))
+    }
+
+    /// Applies the late data block policy before `len` bytes are stored in
+    /// `bank`. Returns `true` when the bank must be emptied first.
+    fn admit_bank_data(&mut self, bank: u8, len: usize) -> Result<bool, ParseError> {
+        if !self.playback_started {
+            return Ok(false);
+        }
+        let previous_len = self
+            .uncompressed_streams
+            .get(&bank)
+            .map_or(0, |stream| stream.data.len());
+        let policy = self.late_data_block_policy;
+        self.late_data_blocks.push(LateDataBlock {
+            sample: self.current_sample,
+            bank,
+            previous_len,
+            len: match policy {
+                LateDataBlockPolicy::Extend => previous_len + len,
+                LateDataBlockPolicy::Replace => len,
+                LateDataBlockPolicy::Forbid => previous_len,
+            },
+            policy,
+        });
+        if policy == LateDataBlockPolicy::Forbid {
+            return Err(ParseError::DataInconsistency(format!(
+                "data block for bank 0x{:02x} ({} bytes) read at sample {} after playback started",
+                bank, len, self.current_sample
+            )));
+        }
+        Ok(policy == LateDataBlockPolicy::Replace)
+    }
+
     /// Gets the next raw command from the internal source.
     fn get_next_raw_command(&mut self) -> Result<Option<VgmCommand>, ParseError> {
         match &mut self.source {
@@ -1093,14 +1779,15 @@ impl VgmStream {
 
     /// Processes a single VGM command, handling special cases and generating stream writes.
     fn process_command(&mut self, command: VgmCommand) -> Result<StreamResult, ParseError> {
+        let command = match command {
+            VgmCommand::DataBlock(block) => return self.handle_data_block(*block),
+            command => command,
+        };
         match &command {
             VgmCommand::EndOfData(_) => {
                 self.handle_end_of_data();
                 return self.next_command();
             }
-            VgmCommand::DataBlock(block) => {
-                return self.handle_data_block(*block.clone());
-            }
             VgmCommand::SetupStreamControl(setup) => {
                 self.handle_setup_stream_control(setup);
                 return self.next_command();
@@ -1149,6 +1836,7 @@ impl VgmStream {
             _ => {}
         }
 
+        self.note_parsed_write(&command);
         Ok(StreamResult::Command(command))
     }
 
@@ -1169,6 +1857,7 @@ impl VgmStream {
             );
 
             self.pcm_data_offset += 1;
+            self.note_parsed_write(&dac_write);
 
             if wait_samples > 0 {
                 // Emit the DAC write immediately; schedule the wait so the
@@ -1270,11 +1959,42 @@ impl VgmStream {
         self.loop_modifier
     }
 
+    /// Sets the sample rate that waits and sample positions are counted in.
+    ///
+    /// This is normally read automatically from the header when using
+    /// `from_document()` or `from_vgm()` (see
+    /// `VgmHeader::effective_sample_rate`). Call this when building a stream
+    /// via `new()` + `push_chunk()` for a log with a nonstandard rate. DAC
+    /// stream scheduling, chip write delays and `elapsed()` use it to convert
+    /// between samples and time.
+    ///
+    /// `0` is treated as the default of 44100 Hz.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = match sample_rate {
+            0 => DEFAULT_SAMPLE_RATE,
+            rate => rate,
+        };
+    }
+
+    /// Gets the sample rate that waits and sample positions are counted in.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Playback time since the start of the stream, i.e. `absolute_sample()`
+    /// converted at `sample_rate()`.
+    pub fn elapsed(&self) -> std::time::Duration {
+        let sample = self.absolute_sample();
+        let rate = self.sample_rate as u64;
+        std::time::Duration::from_secs(sample / rate)
+            + std::time::Duration::from_nanos((sample % rate) * 1_000_000_000 / rate)
+    }
+
     /// Sets the fadeout grace period in samples after loop end.
     ///
     /// When set, the stream will continue processing commands for the specified
     /// number of samples after reaching the loop end, allowing for fadeout effects.
-    /// This is measured at 44100 Hz sample rate.
+    /// This is measured in samples at `sample_rate()` (44100 Hz by default).
     ///
     /// # Arguments
     /// * `samples` - Number of samples to continue after loop end (None to disable)
@@ -1296,50 +2016,429 @@ impl VgmStream {
         self.fadeout_samples
     }
 
-    /// Gets the current sample position (at 44.1 kHz).
+    /// Sets how a DAC stream that starts while another active stream writes to
+    /// the same chip register is handled.
     ///
-    /// This returns the number of samples that have elapsed since the start of the stream
-    /// or since the last loop point. When the stream loops, this value is reset to 0.
+    /// Default is `StreamOverlapPolicy::Interleave`.
     ///
     /// # Examples
-    ///
     /// ```
-    /// use soundlog::vgm::VgmStream;
-    /// # let doc = soundlog::VgmDocument::default();
-    /// let stream = VgmStream::from_document(doc);
-    /// let position = stream.current_sample();
+    /// use soundlog::vgm::stream::{StreamOverlapPolicy, VgmStream};
+    ///
+    /// let mut stream = VgmStream::new();
+    /// stream.set_stream_overlap_policy(StreamOverlapPolicy::Priority);
+    /// stream.set_stream_priority(1, 10); // stream 1 (e.g. sound effects) wins over music
     /// ```
-    pub fn current_sample(&self) -> usize {
-        self.current_sample
+    pub fn set_stream_overlap_policy(&mut self, policy: StreamOverlapPolicy) {
+        self.stream_overlap_policy = policy;
     }
 
-    /// Sets the maximum allowed size for accumulated data blocks.
-    ///
-    /// When data blocks are added that would exceed this limit, a
-    /// `ParseError::DataBlockSizeExceeded` error will be returned.
-    ///
-    /// # Arguments
+    /// Gets the current DAC stream overlap policy.
+    pub fn stream_overlap_policy(&self) -> StreamOverlapPolicy {
+        self.stream_overlap_policy
+    }
+
+    /// Sets the priority of a DAC stream for `StreamOverlapPolicy::Priority`.
     ///
-    /// * `max_size` - Maximum size in bytes (default is 32 MiB)
-    pub fn set_max_data_block_size(&mut self, max_size: usize) {
-        self.max_data_block_size = max_size;
+    /// Streams without an explicit priority have priority `0`. Priorities are
+    /// configuration and are preserved across `reset()`.
+    pub fn set_stream_priority(&mut self, stream_id: u8, priority: u8) {
+        self.stream_priorities.insert(stream_id, priority);
     }
 
-    /// Gets the maximum allowed size for accumulated data blocks.
-    pub fn max_data_block_size(&self) -> usize {
-        self.max_data_block_size
+    /// Gets the priority of a DAC stream.
+    pub fn stream_priority(&self, stream_id: u8) -> u8 {
+        self.stream_priorities.get(&stream_id).copied().unwrap_or(0)
     }
 
-    /// Gets the current total size of accumulated data blocks.
-    pub fn total_data_block_size(&self) -> usize {
-        self.total_data_block_size
+    /// DAC stream collisions recorded so far, in the order they occurred.
+    pub fn stream_collisions(&self) -> &[StreamCollision] {
+        &self.stream_collisions
     }
 
-    /// Sets the maximum allowed size for the internal parsing buffer.
+    /// Returns and clears the recorded DAC stream collisions.
     ///
-    /// This limit applies to the raw byte buffer used when feeding data via
-    /// `push_chunk()`. When the buffer size would exceed this limit, `push_chunk()`
-    /// returns an error.
+    /// Long-running (for example infinitely looping) streams should drain the
+    /// list periodically.
+    pub fn take_stream_collisions(&mut self) -> Vec<StreamCollision> {
+        std::mem::take(&mut self.stream_collisions)
+    }
+
+    /// Sets how stream data blocks read after the first wait are stored.
+    ///
+    /// Default is `LateDataBlockPolicy::Extend`. The policy is preserved
+    /// across `reset()`.
+    ///
+    /// # Examples
+    /// ```
+    /// use soundlog::vgm::stream::{LateDataBlockPolicy, VgmStream};
+    ///
+    /// let mut stream = VgmStream::new();
+    /// // A data block inside the loop holds the samples of the next pass.
+    /// stream.set_late_data_block_policy(LateDataBlockPolicy::Replace);
+    /// ```
+    pub fn set_late_data_block_policy(&mut self, policy: LateDataBlockPolicy) {
+        self.late_data_block_policy = policy;
+    }
+
+    /// Gets the current late data block policy.
+    pub fn late_data_block_policy(&self) -> LateDataBlockPolicy {
+        self.late_data_block_policy
+    }
+
+    /// Stream data blocks read after the first wait so far, in the order
+    /// they were read, with the data bank length before and after each.
+    pub fn late_data_blocks(&self) -> &[LateDataBlock] {
+        &self.late_data_blocks
+    }
+
+    /// Returns and clears the recorded late data blocks.
+    pub fn take_late_data_blocks(&mut self) -> Vec<LateDataBlock> {
+        std::mem::take(&mut self.late_data_blocks)
+    }
+
+    /// Current length of every data bank, sorted by bank.
+    pub fn data_bank_lens(&self) -> Vec<(u8, usize)> {
+        let mut lens: Vec<(u8, usize)> = self
+            .uncompressed_streams
+            .iter()
+            .map(|(bank, stream)| (*bank, stream.data.len()))
+            .collect();
+        lens.sort_unstable();
+        lens
+    }
+
+    /// Enforces a minimum sample gap between DAC stream writes to each chip.
+    ///
+    /// Only writes generated from DAC stream control commands are limited;
+    /// writes parsed from the input are passed through unchanged. Writes held
+    /// back with `WriteRateLimitMode::Coalesce` are emitted as soon as the gap
+    /// has elapsed, one per chip at a time. Writes still held back when the
+    /// command stream ends are discarded.
+    ///
+    /// Default is `None` (no limit).
+    ///
+    /// # Examples
+    /// ```
+    /// use soundlog::vgm::stream::{VgmStream, WriteRateLimit, WriteRateLimitMode};
+    ///
+    /// let mut stream = VgmStream::new();
+    /// stream.set_write_rate_limit(Some(WriteRateLimit {
+    ///     min_gap_samples: 2, // at most 22050 writes per second per chip
+    ///     mode: WriteRateLimitMode::Coalesce,
+    /// }));
+    /// ```
+    pub fn set_write_rate_limit(&mut self, limit: Option<WriteRateLimit>) {
+        self.write_rate_limit = limit.filter(|l| l.min_gap_samples > 0);
+    }
+
+    /// Gets the current write rate limit.
+    pub fn write_rate_limit(&self) -> Option<WriteRateLimit> {
+        self.write_rate_limit
+    }
+
+    /// Writes delayed, coalesced or dropped by the write rate limit and the
+    /// chip write delays so far.
+    pub fn write_rate_limit_report(&self) -> WriteRateLimitReport {
+        self.write_rate_limit_report
+    }
+
+    /// Models the bus busy time of `chip` after every register write.
+    ///
+    /// Real chips cannot accept a new write until the previous one has been
+    /// latched (for example the YM2612 address/data write cycle). Every write
+    /// to a chip with a configured delay, parsed or generated, keeps that chip
+    /// instance busy for `busy_ns` nanoseconds; back-to-back writes queue up.
+    /// Parsed writes are never moved, but DAC stream writes are held back
+    /// until the bus is free, coalescing with newer writes to the same
+    /// register like `WriteRateLimitMode::Coalesce`. Pass `None` to remove the
+    /// delay of a chip.
+    ///
+    /// One sample at 44.1 kHz is about 22676 ns.
+    ///
+    /// # Examples
+    /// ```
+    /// use soundlog::chip::Chip;
+    /// use soundlog::vgm::VgmStream;
+    ///
+    /// let mut stream = VgmStream::new();
+    /// stream.set_chip_write_delay(Chip::Ym2612, Some(10_000));
+    /// assert_eq!(stream.chip_write_delay(&Chip::Ym2612), Some(10_000));
+    /// ```
+    pub fn set_chip_write_delay(&mut self, chip: chip::Chip, busy_ns: Option<u32>) {
+        match busy_ns.filter(|ns| *ns > 0) {
+            Some(ns) => {
+                self.chip_write_delays.insert(chip, ns);
+            }
+            None => {
+                self.chip_write_delays.remove(&chip);
+            }
+        }
+    }
+
+    /// Gets the bus busy time configured for `chip`, in nanoseconds.
+    pub fn chip_write_delay(&self, chip: &chip::Chip) -> Option<u32> {
+        self.chip_write_delays.get(chip).copied()
+    }
+
+    /// Mutes the channels of one chip instance; bit `n` of `mask` mutes
+    /// channel `n`. Pass `0` to unmute every channel.
+    ///
+    /// Muting works on the register writes the stream yields, so it applies
+    /// to any player: key-on writes of a muted channel are turned into
+    /// key-offs and level writes into silence. Channels are numbered as
+    /// follows; chips not listed have no channel knowledge and return
+    /// `false`.
+    ///
+    /// | Chip | Channels |
+    /// |------|----------|
+    /// | SN76489 | 0-2 tone, 3 noise |
+    /// | AY8910 | 0-2 |
+    /// | YM2612 | 0-5 FM (muting 5 also silences the DAC) |
+    /// | YM2203 | 0-2 FM, 3-5 SSG |
+    /// | YM2608, YM2610B | 0-5 FM, 6-8 SSG |
+    /// | YM2151 | 0-7 |
+    /// | YM2413, YM3812, YM3526, Y8950 | 0-8 |
+    /// | YMF262 | 0-17 (9-17 on port 1) |
+    ///
+    /// A channel that is sounding when it gets muted is silenced by a
+    /// generated write before the next command. Unmuting restores the last
+    /// level of a channel right away, while notes of key-on channels start
+    /// again with their next key-on. The mask is kept across `reset`.
+    ///
+    /// # Examples
+    /// ```
+    /// use soundlog::chip::Chip;
+    /// use soundlog::vgm::VgmStream;
+    /// use soundlog::vgm::command::Instance;
+    ///
+    /// let mut stream = VgmStream::new();
+    /// // solo the first YM2612 channel
+    /// assert!(stream.set_channel_mask(Chip::Ym2612, Instance::Primary, !0b1));
+    /// assert_eq!(stream.channel_mask(&Chip::Ym2612, Instance::Primary), 0b111110);
+    /// assert!(!stream.set_channel_mask(Chip::Okim6295, Instance::Primary, 1));
+    /// ```
+    pub fn set_channel_mask(&mut self, chip: chip::Chip, instance: Instance, mask: u32) -> bool {
+        let Some(channels) = chip::regmap::channel_count(&chip) else {
+            return false;
+        };
+        let mask = mask & ((1 << channels) - 1);
+        let mute = self.channel_mutes.entry((chip, instance)).or_default();
+        let changed = mute.mask ^ mask;
+        mute.mask = mask;
+        let mut writes: Vec<RegisterWrite> = Vec::new();
+        for (channel, control) in &mute.writes {
+            if changed & (1 << channel) == 0 {
+                continue;
+            }
+            if mask & (1 << channel) != 0 {
+                if control.write.value != control.silent {
+                    writes.push(control.silenced());
+                }
+            } else if control.level {
+                writes.push(control.write.clone());
+            }
+        }
+        if writes.is_empty() {
+            return true;
+        }
+        writes.sort_by_key(|write| (write.port, write.register, write.value));
+        if let Some(relatch) = mute.relatch(writes[0].clone()) {
+            writes.push(relatch);
+        }
+        self.mask_writes
+            .extend(writes.iter().filter_map(RegisterWrite::to_command));
+        true
+    }
+
+    /// Gets the channel mask of one chip instance, `0` when nothing is muted.
+    pub fn channel_mask(&self, chip: &chip::Chip, instance: Instance) -> u32 {
+        self.channel_mutes
+            .get(&(chip.clone(), instance))
+            .map_or(0, |mute| mute.mask)
+    }
+
+    /// Records the key-on and level writes of `command` and rewrites the
+    /// ones of muted channels.
+    fn apply_channel_masks(&mut self, command: VgmCommand) -> VgmCommand {
+        let Some(write) = command.register_write() else {
+            return command;
+        };
+        if chip::regmap::channel_count(&write.chip).is_none() {
+            return command;
+        }
+        let mute = self
+            .channel_mutes
+            .entry((write.chip.clone(), write.instance))
+            .or_default();
+        let Some(control) = mute.control(write) else {
+            return command;
+        };
+        let muted = mute.mask & (1 << control.channel) != 0;
+        let silenced = muted.then(|| control.silenced());
+        mute.writes.insert(control.channel, control);
+        silenced
+            .and_then(|write| write.to_command())
+            .unwrap_or(command)
+    }
+
+    /// Gets the current loop-relative sample position (at 44.1 kHz).
+    ///
+    /// This returns the number of samples that have elapsed since the start of the stream
+    /// or since the last loop point. When the stream loops, this value is reset to 0, so
+    /// during the second and later iterations it is the offset from the loop point.
+    /// `absolute_sample` is the matching position on a clock that never resets.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use soundlog::vgm::VgmStream;
+    /// # let doc = soundlog::VgmDocument::default();
+    /// let stream = VgmStream::from_document(doc);
+    /// let position = stream.current_sample();
+    /// ```
+    pub fn current_sample(&self) -> usize {
+        self.current_sample
+    }
+
+    /// Gets the absolute sample position (at 44.1 kHz).
+    ///
+    /// Unlike `current_sample`, this counts every sample played since the
+    /// start of the stream (or the last `reset`), including all loop
+    /// iterations and the fadeout, so it never goes backwards during playback.
+    /// After `seek_to_sample` it is the position of the target within the
+    /// first loop iteration.
+    pub fn absolute_sample(&self) -> u64 {
+        self.sample_base + self.current_sample as u64
+    }
+
+    /// Gets the absolute sample position of the command last returned by the
+    /// iterator.
+    ///
+    /// This is the sample at which the command takes effect; for a wait it is
+    /// the sample at which the wait starts. It is valid until the next call
+    /// to `next`, and is `0` before the first one. `VgmStream::timed` yields
+    /// the same value together with each result.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use soundlog::{VgmBuilder, vgm::stream::{StreamResult, VgmStream}};
+    /// use soundlog::chip::PsgSpec;
+    /// use soundlog::vgm::command::{Instance, WaitSamples};
+    ///
+    /// let mut builder = VgmBuilder::new();
+    /// builder.add_vgm_command(WaitSamples(100));
+    /// builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x9F });
+    /// let mut stream = VgmStream::from_document(builder.finalize());
+    ///
+    /// stream.next(); // the wait
+    /// assert_eq!(stream.last_result_sample(), 0);
+    /// stream.next(); // the write
+    /// assert_eq!(stream.last_result_sample(), 100);
+    /// ```
+    pub fn last_result_sample(&self) -> u64 {
+        self.last_result_sample
+    }
+
+    /// Iterates results together with their absolute sample position.
+    ///
+    /// Each item is `(sample, result)` where `sample` is
+    /// `last_result_sample()` for commands and `absolute_sample()` for
+    /// `NeedsMoreData` and `EndOfStream`. The positions keep increasing across
+    /// loop iterations and the fadeout, so callers do not have to sum waits
+    /// themselves.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use soundlog::{VgmBuilder, vgm::stream::{StreamResult, VgmStream}};
+    /// use soundlog::chip::PsgSpec;
+    /// use soundlog::vgm::command::{Instance, VgmCommand, WaitSamples};
+    ///
+    /// let mut builder = VgmBuilder::new();
+    /// builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x9F });
+    /// builder.add_vgm_command(WaitSamples(100));
+    /// builder.set_loop_offset(0);
+    /// let mut stream = VgmStream::from_document(builder.finalize());
+    /// stream.set_loop_count(Some(2));
+    ///
+    /// let writes: Vec<u64> = stream
+    ///     .timed()
+    ///     .map_while(|item| match item.unwrap() {
+    ///         (sample, StreamResult::Command(VgmCommand::Sn76489Write(..))) => Some(Some(sample)),
+    ///         (_, StreamResult::Command(_)) => Some(None),
+    ///         _ => None,
+    ///     })
+    ///     .flatten()
+    ///     .collect();
+    /// assert_eq!(writes, vec![0, 100]);
+    /// ```
+    pub fn timed(&mut self) -> TimedResults<'_> {
+        TimedResults { stream: self }
+    }
+
+    /// Absolute sample position of the loop point in the first loop
+    /// iteration: the total wait before it. `None` for `push_chunk` streams.
+    fn loop_point_sample(&self) -> Option<u64> {
+        match &self.source {
+            VgmStreamSource::Buffer { .. } => None,
+            VgmStreamSource::Document {
+                document,
+                loop_index,
+                ..
+            } => Some(
+                document.commands[..loop_index.unwrap_or(0)]
+                    .iter()
+                    .map(|command| command.wait_samples() as u64)
+                    .sum(),
+            ),
+            VgmStreamSource::File {
+                data,
+                command_start,
+                loop_pos,
+                ..
+            } => {
+                let loop_pos = loop_pos.unwrap_or(*command_start);
+                let mut pos = *command_start;
+                let mut samples = 0;
+                while pos < loop_pos {
+                    let (command, consumed) = parse_vgm_command(data, pos).ok()?;
+                    samples += command.wait_samples() as u64;
+                    pos += consumed.max(1);
+                }
+                Some(samples)
+            }
+        }
+    }
+
+    /// Sets the maximum allowed size for accumulated data blocks.
+    ///
+    /// When data blocks are added that would exceed this limit, a
+    /// `ParseError::DataBlockSizeExceeded` error will be returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_size` - Maximum size in bytes (default is 32 MiB)
+    pub fn set_max_data_block_size(&mut self, max_size: usize) {
+        self.max_data_block_size = max_size;
+    }
+
+    /// Gets the maximum allowed size for accumulated data blocks.
+    pub fn max_data_block_size(&self) -> usize {
+        self.max_data_block_size
+    }
+
+    /// Gets the current total size of accumulated data blocks.
+    pub fn total_data_block_size(&self) -> usize {
+        self.total_data_block_size
+    }
+
+    /// Sets the maximum allowed size for the internal parsing buffer.
+    ///
+    /// This limit applies to the raw byte buffer used when feeding data via
+    /// `push_chunk()`. When the buffer size would exceed this limit, `push_chunk()`
+    /// returns an error.
     ///
     /// # Arguments
     ///
@@ -1424,13 +2523,107 @@ impl VgmStream {
         self.pending_data_block = None;
         self.stream_states.clear();
         self.current_sample = 0;
+        self.sample_base = 0;
+        self.last_result_sample = 0;
         self.pending_stream_writes.clear();
         self.pending_wait = None;
         self.loop_end_sample = None;
         self.pcm_data_offset = 0;
         self.total_data_block_size = 0;
-        // loop_base and loop_modifier are header-derived configuration and are
-        // intentionally preserved across reset() calls.
+        self.stream_collisions.clear();
+        self.late_data_blocks.clear();
+        self.playback_started = false;
+        self.last_generated_write.clear();
+        self.deferred_writes.clear();
+        self.write_rate_limit_report = WriteRateLimitReport::default();
+        self.chip_busy_until.clear();
+        for mute in self.channel_mutes.values_mut() {
+            mute.clear_writes();
+        }
+        self.mask_writes.clear();
+        // loop_base, loop_modifier and sample_rate are header-derived configuration and are
+        // intentionally preserved across reset() calls, as are the stream
+        // overlap policy, stream priorities, late data block policy and
+        // channel masks.
+    }
+
+    /// Resets the stream and switches it to play `document`.
+    ///
+    /// This is equivalent to `VgmStream::from_document(document)` followed by
+    /// re-applying the settings of this stream (loop count, fadeout, buffer
+    /// limits, DAC stream policies, write rate limit and chip write delays),
+    /// but keeps the internal buffers, stream tables and scheduler queues
+    /// allocated, which avoids allocation churn when a player switches tracks
+    /// often. The loop base, loop modifier and sample rate are taken from the
+    /// new header and
+    /// documents queued with `append_document` are dropped.
+    ///
+    /// # Examples
+    /// ```
+    /// use soundlog::VgmBuilder;
+    /// use soundlog::vgm::command::WaitSamples;
+    /// use soundlog::vgm::stream::{StreamResult, VgmStream};
+    ///
+    /// let track = |wait| {
+    ///     let mut builder = VgmBuilder::new();
+    ///     builder.add_vgm_command(WaitSamples(wait));
+    ///     builder.finalize()
+    /// };
+    ///
+    /// let mut stream = VgmStream::from_document(track(100));
+    /// stream.set_loop_count(Some(1));
+    /// while let Some(Ok(StreamResult::Command(_))) = stream.next() {}
+    ///
+    /// stream.reset_with_document(track(50));
+    /// assert_eq!(stream.current_sample(), 0);
+    /// while let Some(Ok(StreamResult::Command(_))) = stream.next() {}
+    /// assert_eq!(stream.current_sample(), 50);
+    /// ```
+    pub fn reset_with_document(&mut self, document: VgmDocument) {
+        self.queued_documents.clear();
+        self.load_document(document);
+        self.reset();
+    }
+
+    /// Resets the stream and switches it to play the complete raw VGM file
+    /// `data`.
+    ///
+    /// The bytes are copied into the file buffer of a stream created with
+    /// `VgmStream::from_vgm`, reusing its allocation; otherwise this behaves
+    /// like `reset_with_document`.
+    ///
+    /// # Errors
+    /// Returns [`ParseError`] if the VGM header cannot be parsed. The stream is
+    /// left unchanged in that case.
+    pub fn reset_with_bytes(&mut self, data: &[u8]) -> Result<(), ParseError> {
+        let (header, new_command_start, new_loop_pos) = Self::file_layout(data)?;
+        self.loop_base = header.loop_base;
+        self.loop_modifier = header.loop_modifier;
+        self.sample_rate = header.effective_sample_rate();
+        match &mut self.source {
+            VgmStreamSource::File {
+                data: buffer,
+                command_start,
+                loop_pos,
+                ..
+            } => {
+                buffer.clear();
+                buffer.extend_from_slice(data);
+                *command_start = new_command_start;
+                *loop_pos = new_loop_pos;
+            }
+            source => {
+                *source = VgmStreamSource::File {
+                    data: data.to_vec(),
+                    command_start: new_command_start,
+                    current_pos: new_command_start,
+                    loop_pos: new_loop_pos,
+                };
+            }
+        }
+        self.queued_documents.clear();
+        self.reset();
+        Ok(())
     }
 
     /// Resets the stream position to the loop point (or start if no loop point exists),
@@ -1455,13 +2648,14 @@ impl VgmStream {
         }
         self.jump_to_loop_point();
         self.reset_loop_state();
+        self.sample_base = self.loop_point_sample().unwrap_or(0);
         Ok(())
     }
 
     /// Moves the stream to the specified sample position within the current loop iteration.
     ///
     /// Because the sample counter resets to 0 at the start of each loop, `target` refers
-    /// to a position within one loop iteration (measured in 44100 Hz samples, starting
+    /// to a position within one loop iteration (measured in samples at `sample_rate()`, starting
     /// from 0 at the loop point).  The stream is rewound to the loop point and then
     /// commands are consumed silently until the sample counter reaches `target`.
     ///
@@ -1492,7 +2686,7 @@ impl VgmStream {
             if self.current_sample >= target {
                 break;
             }
-            match self.next_command()? {
+            match self.next_command_in_document()? {
                 StreamResult::EndOfStream | StreamResult::NeedsMoreData => break,
                 StreamResult::Command(_) => {}
             }
@@ -1511,6 +2705,7 @@ impl VgmStream {
                     self.loop_end_sample = Some(self.current_sample);
                 }
             } else {
+                self.sample_base += self.current_sample as u64;
                 self.jump_to_loop_point();
                 self.reset_loop_state();
                 if self.current_loops.saturating_add(1) == max_loops
@@ -1523,6 +2718,7 @@ impl VgmStream {
             // No finite loop limit configured -> infinite loop behavior.
             // Jump to the configured loop point and reset loop-specific state
             // so playback continues indefinitely.
+            self.sample_base += self.current_sample as u64;
             self.jump_to_loop_point();
             self.reset_loop_state();
         }
@@ -1599,17 +2795,18 @@ impl VgmStream {
 
         self.pending_stream_writes.clear();
         self.pending_wait = None;
+        self.last_generated_write.clear();
+        self.deferred_writes.clear();
+        self.chip_busy_until.clear();
     }
 
     /// Handles a data block command by parsing it and storing or returning it.
-    fn handle_data_block(&mut self, block: DataBlock) -> Result<StreamResult, ParseError> {
+    fn handle_data_block(&mut self, mut block: DataBlock) -> Result<StreamResult, ParseError> {
         // block is passed by value (unboxed at call site)
         let block_size = block.size as usize;
         let block_data_type = block.data_type;
         let data_type = block.data_type;
         let data_len = block.data.len();
-        let marker = block.marker;
-        let chip_instance = block.chip_instance;
 
         // Check if adding this block would exceed the size limit
         let new_total = self.total_data_block_size.saturating_add(data_len);
@@ -1621,111 +2818,50 @@ impl VgmStream {
             });
         }
 
+        // ROM/RAM dumps are yielded as they are: splitting off their address
+        // header and joining it back would copy the payload twice.
+        if data_type >= 0x80 {
+            self.record_rom_block(data_type, data_len);
+            block.size = data_len as u32;
+            return Ok(StreamResult::Command(VgmCommand::DataBlock(Box::new(
+                block,
+            ))));
+        }
+
         match parse_data_block(block) {
-            Ok(parsed) => {
-                match parsed {
-                    DataBlockType::UncompressedStream(stream) => {
-                        let current_offset = self
-                            .uncompressed_streams
-                            .get(&data_type)
-                            .map(|s| s.data.len())
-                            .unwrap_or(0);
-                        self.block_id_map
-                            .push((data_type, current_offset, stream.data.len()));
-                        self.total_data_block_size += data_len;
-                        self.uncompressed_streams
-                            .entry(data_type)
-                            .and_modify(|existing| {
-                                existing.data.extend_from_slice(&stream.data);
-                            })
-                            .or_insert(stream);
-                        self.next_command()
-                    }
-                    DataBlockType::CompressedStream(stream) => {
-                        self.total_data_block_size += data_len;
-                        self.process_compressed_stream(data_type, stream)?;
-                        self.next_command()
-                    }
-                    DataBlockType::DecompressionTable(table) => {
-                        self.total_data_block_size += data_len;
-                        self.decompression_tables.insert(data_type, table);
-                        self.next_command()
-                    }
-                    DataBlockType::RomRamDump(dump) => {
-                        // For RomRamDump, reconstruct DataBlock and return
-                        let current_offset = *self.block_sizes.get(&data_type).unwrap_or(&0);
-                        self.block_id_map
-                            .push((data_type, current_offset, data_len));
-                        *self.block_sizes.entry(data_type).or_insert(0) += data_len;
-                        self.total_data_block_size += data_len;
-
-                        // Reconstruct the full data including rom_size and start_address header
-                        let mut full_data = Vec::with_capacity(8 + dump.data.len());
-                        full_data.extend_from_slice(&dump.rom_size.to_le_bytes());
-                        full_data.extend_from_slice(&dump.start_address.to_le_bytes());
-                        full_data.extend_from_slice(&dump.data);
-
-                        let block = DataBlock {
-                            marker,
-                            chip_instance,
-                            data_type,
-                            size: full_data.len() as u32,
-                            data: full_data,
-                        };
-                        Ok(StreamResult::Command(VgmCommand::DataBlock(Box::new(
-                            block,
-                        ))))
-                    }
-                    DataBlockType::RamWrite16(write) => {
-                        // For RamWrite16, reconstruct DataBlock and return
-                        let current_offset = *self.block_sizes.get(&data_type).unwrap_or(&0);
-                        self.block_id_map
-                            .push((data_type, current_offset, data_len));
-                        *self.block_sizes.entry(data_type).or_insert(0) += data_len;
-                        self.total_data_block_size += data_len;
-
-                        // Reconstruct the full data including start_address header
-                        let mut full_data = Vec::with_capacity(2 + write.data.len());
-                        full_data.extend_from_slice(&write.start_address.to_le_bytes());
-                        full_data.extend_from_slice(&write.data);
-
-                        let block = DataBlock {
-                            marker,
-                            chip_instance,
-                            data_type,
-                            size: full_data.len() as u32,
-                            data: full_data,
-                        };
-                        Ok(StreamResult::Command(VgmCommand::DataBlock(Box::new(
-                            block,
-                        ))))
-                    }
-                    DataBlockType::RamWrite32(write) => {
-                        // For RamWrite32, reconstruct DataBlock and return
-                        let current_offset = *self.block_sizes.get(&data_type).unwrap_or(&0);
-                        self.block_id_map
-                            .push((data_type, current_offset, data_len));
-                        *self.block_sizes.entry(data_type).or_insert(0) += data_len;
-                        self.total_data_block_size += data_len;
-
-                        // Reconstruct the full data including start_address header
-                        let mut full_data = Vec::with_capacity(4 + write.data.len());
-                        full_data.extend_from_slice(&write.start_address.to_le_bytes());
-                        full_data.extend_from_slice(&write.data);
-
-                        let block = DataBlock {
-                            marker,
-                            chip_instance,
-                            data_type,
-                            size: full_data.len() as u32,
-                            data: full_data,
-                        };
-                        Ok(StreamResult::Command(VgmCommand::DataBlock(Box::new(
-                            block,
-                        ))))
-                    }
+            Ok(parsed) => match parsed {
+                DataBlockType::UncompressedStream(stream) => {
+                    let stream_len = stream.data.len();
+                    let replace = self.admit_bank_data(data_type, stream_len)?;
+                    let (offset, removed) = store_bank_data(
+                        &mut self.uncompressed_streams,
+                        data_type,
+                        stream.chip_type,
+                        Cow::Owned(stream.data),
+                        replace,
+                    );
+                    self.block_id_map.push((data_type, offset, stream_len));
+                    self.total_data_block_size =
+                        (self.total_data_block_size + data_len).saturating_sub(removed);
+                    self.next_command()
                 }
-            }
+                DataBlockType::CompressedStream(stream) => {
+                    // Counted by its decompressed size, which is what stays
+                    // in memory.
+                    self.process_compressed_stream(data_type, stream)?;
+                    self.next_command()
+                }
+                DataBlockType::DecompressionTable(table) => {
+                    self.total_data_block_size += data_len;
+                    self.decompression_tables.insert(data_type, table);
+                    self.next_command()
+                }
+                DataBlockType::RomRamDump(_)
+                | DataBlockType::RamWrite16(_)
+                | DataBlockType::RamWrite32(_) => {
+                    unreachable!("ROM/RAM data blocks are returned unparsed")
+                }
+            },
             Err((original_block, _err)) => {
                 // If parsing fails, return the raw block without storing
                 let current_offset = *self.block_sizes.get(&data_type).unwrap_or(&0);
@@ -1740,70 +2876,64 @@ impl VgmStream {
         }
     }
 
-    /// Process a compressed stream: perform decompression using available
-    /// decompression tables and store the result as an UncompressedStream.
+    /// Records a ROM/RAM dump of `data_len` bytes that is yielded to the
+    /// caller. The size limit has been checked by the caller.
+    fn record_rom_block(&mut self, data_type: u8, data_len: usize) {
+        let current_offset = *self.block_sizes.get(&data_type).unwrap_or(&0);
+        self.block_id_map
+            .push((data_type, current_offset, data_len));
+        *self.block_sizes.entry(data_type).or_insert(0) += data_len;
+        self.total_data_block_size += data_len;
+    }
+
+    /// Process a compressed stream: perform decompression using the most
+    /// recent decompression table and store the result as an
+    /// UncompressedStream under the block's data type.
     fn process_compressed_stream(
         &mut self,
         data_type: u8,
-        mut stream: CompressedStream,
+        stream: CompressedStream,
     ) -> Result<(), ParseError> {
         // Calculate remaining space in data block limit
         let remaining_space = self
             .max_data_block_size
             .saturating_sub(self.total_data_block_size);
-
-        let decompressed_data = match &mut stream.compression {
-            CompressedStreamData::BitPacking(bp) => {
-                let table = if matches!(bp.sub_type, BitPackingSubType::UseTable) {
-                    Some(self.decompression_tables.get(&data_type).ok_or_else(|| {
-                        ParseError::DataInconsistency(format!(
-                            "DecompressionTable not found for data_type {}",
-                            data_type
-                        ))
-                    })?)
-                } else {
-                    None
-                };
-                bp.decompress(table, remaining_space)?;
-                bp.data.clone()
-            }
-            CompressedStreamData::Dpcm(dpcm) => {
-                let table = self.decompression_tables.get(&data_type).ok_or_else(|| {
-                    ParseError::DataInconsistency(format!(
-                        "DecompressionTable not found for data_type {}",
-                        data_type
-                    ))
-                })?;
-                dpcm.decompress(table, remaining_space)?;
-                dpcm.data.clone()
-            }
-            CompressedStreamData::Unknown { .. } => {
-                return Err(ParseError::Other(format!(
-                    "Unknown compression type for data_type {}",
-                    data_type
-                )));
-            }
+        let exceeded = |attempted_size| ParseError::DataBlockSizeExceeded {
+            current_size: self.total_data_block_size,
+            limit: self.max_data_block_size,
+            attempted_size,
         };
+        if stream.uncompressed_size as usize > remaining_space {
+            return Err(exceeded(stream.uncompressed_size as usize));
+        }
 
-        // Record block position and size in block_id_map
-        let current_offset = self
-            .uncompressed_streams
-            .get(&data_type)
-            .map(|s| s.data.len())
-            .unwrap_or(0);
-        self.block_id_map
-            .push((data_type, current_offset, decompressed_data.len()));
+        // Tables are stored under their own data type (0x7F); a new table
+        // replaces the previous one.
+        let table = self.decompression_tables.get(&0x7F);
+        let limits = ParseLimits {
+            max_decompressed_size: remaining_space,
+            ..ParseLimits::unlimited()
+        };
+        let decompressed_data = decompress_block_with_limits(&stream, table, &limits)?;
+        // The declared size is only an upper bound of the values decoded;
+        // check what was actually produced.
+        if decompressed_data.len() > remaining_space {
+            return Err(exceeded(decompressed_data.len()));
+        }
 
-        // Append decompressed data to existing stream or create new one
-        self.uncompressed_streams
-            .entry(data_type)
-            .and_modify(|existing| {
-                existing.data.extend_from_slice(&decompressed_data);
-            })
-            .or_insert_with(|| UncompressedStream {
-                chip_type: stream.chip_type,
-                data: decompressed_data,
-            });
+        // Append decompressed data to the bank and record its position and
+        // size in block_id_map
+        let len = decompressed_data.len();
+        let replace = self.admit_bank_data(data_type, len)?;
+        let (offset, removed) = store_bank_data(
+            &mut self.uncompressed_streams,
+            data_type,
+            stream.chip_type,
+            Cow::Owned(decompressed_data),
+            replace,
+        );
+        self.block_id_map.push((data_type, offset, len));
+        self.total_data_block_size = (self.total_data_block_size + len).saturating_sub(removed);
         Ok(())
     }
 
@@ -1917,6 +3047,13 @@ impl VgmStream {
         };
         let start_data_pos = base_offset + step_base as usize;
 
+        // Ignore mode only moves the data position and never writes.
+        if !matches!(start.length_mode, LengthMode::Ignore { .. })
+            && !self.resolve_stream_overlap(start.stream_id)?
+        {
+            return Ok(());
+        }
+
         if let Some(state) = self.stream_states.get_mut(&start.stream_id) {
             state.start_offset = Some(start.data_start_offset);
             state.length_mode = start.length_mode;
@@ -1958,6 +3095,10 @@ impl VgmStream {
         let block_end = block_offset + block_size;
         let start_data_pos = block_offset + step_base as usize;
 
+        if !self.resolve_stream_overlap(fast.stream_id)? {
+            return Ok(());
+        }
+
         if let Some(state) = self.stream_states.get_mut(&fast.stream_id) {
             state.active = true;
             state.start_data_pos = start_data_pos;
@@ -1973,6 +3114,86 @@ impl VgmStream {
         Ok(())
     }
 
+    /// Applies the stream overlap policy before `stream_id` is started.
+    ///
+    /// Looks for other active, writing streams with the same chip, instance,
+    /// port and register, records a `StreamCollision` for each and resolves it
+    /// according to `stream_overlap_policy`. Returns `false` when the new
+    /// stream must not be started.
+    fn resolve_stream_overlap(&mut self, stream_id: u8) -> Result<bool, ParseError> {
+        let Some(new) = self.stream_states.get(&stream_id) else {
+            return Ok(true);
+        };
+        let target = (
+            new.chip_type,
+            new.instance,
+            new.write_port,
+            new.write_command,
+        );
+        let mut colliding: Vec<u8> = self
+            .stream_states
+            .iter()
+            .filter(|(id, state)| {
+                **id != stream_id
+                    && state.active
+                    && !matches!(state.length_mode, LengthMode::Ignore { .. })
+                    && (
+                        state.chip_type,
+                        state.instance,
+                        state.write_port,
+                        state.write_command,
+                    ) == target
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        if colliding.is_empty() {
+            return Ok(true);
+        }
+        // Keep diagnostics deterministic regardless of HashMap order.
+        colliding.sort_unstable();
+
+        let priority = self.stream_priority(stream_id);
+        let start = match self.stream_overlap_policy {
+            StreamOverlapPolicy::Interleave | StreamOverlapPolicy::LastWins => true,
+            StreamOverlapPolicy::Forbid => false,
+            StreamOverlapPolicy::Priority => colliding
+                .iter()
+                .all(|id| self.stream_priority(*id) <= priority),
+        };
+        let resolution = match self.stream_overlap_policy {
+            StreamOverlapPolicy::Interleave => StreamCollisionResolution::Interleaved,
+            StreamOverlapPolicy::Forbid => StreamCollisionResolution::Error,
+            _ if start => StreamCollisionResolution::StoppedActive,
+            _ => StreamCollisionResolution::RejectedNew,
+        };
+
+        for active_stream_id in &colliding {
+            self.stream_collisions.push(StreamCollision {
+                sample: self.current_sample,
+                stream_id,
+                active_stream_id: *active_stream_id,
+                chip_id: target.0,
+                instance: target.1,
+                write_port: target.2,
+                write_command: target.3,
+                resolution,
+            });
+            if resolution == StreamCollisionResolution::StoppedActive
+                && let Some(state) = self.stream_states.get_mut(active_stream_id)
+            {
+                state.active = false;
+            }
+        }
+
+        if resolution == StreamCollisionResolution::Error {
+            return Err(ParseError::DataInconsistency(format!(
+                "DAC stream {} started at sample {} while stream {} writes to the same register ({:?} port {} reg 0x{:02x})",
+                stream_id, self.current_sample, colliding[0], target.0, target.2, target.3
+            )));
+        }
+        Ok(start)
+    }
+
     /// Gets the offset and size for a specific block_id within a data bank.
     ///
     /// The block_id is the global sequence number (order of appearance) of DataBlocks.
@@ -2032,7 +3253,7 @@ impl VgmStream {
             let snapshot = match self
                 .stream_states
                 .get(&stream_id)
-                .and_then(|s| StreamSnapshot::from_state(s, data_bank_end))
+                .and_then(|s| StreamSnapshot::from_state(s, data_bank_end, self.sample_rate))
             {
                 Some(c) => c,
                 None => continue,
@@ -2074,15 +3295,13 @@ impl VgmStream {
                 (Some(_), Some((data_pos, step))) => {
                     // Normal emit: read byte from data bank and emit chip-write command.
                     if let Some(data) = self.read_stream_byte_at(snapshot.data_bank_id, data_pos)? {
-                        if let Some(cmd) = Self::create_stream_write_command_static(
-                            snapshot.chip_id,
-                            snapshot.instance,
-                            snapshot.write_port,
-                            snapshot.write_command,
-                            data,
-                        ) {
-                            self.pending_stream_writes.push(cmd);
-                        }
+                        self.emit_stream_write(DeferredWrite {
+                            chip_id: snapshot.chip_id,
+                            instance: snapshot.instance,
+                            write_port: snapshot.write_port,
+                            write_command: snapshot.write_command,
+                            value: data,
+                        });
                         // Record that this step has been emitted regardless of whether
                         // create_stream_write_command_static produced a command (the
                         // chip type may be unmapped, but the position must still advance).
@@ -2101,9 +3320,153 @@ impl VgmStream {
             }
         }
 
+        self.flush_deferred_writes();
         Ok(())
     }
 
+    /// Whether the rate-limit gap of `chip` has elapsed at `current_sample`.
+    fn gap_elapsed(&self, chip: (ChipId, Instance)) -> bool {
+        match (self.write_rate_limit, self.last_generated_write.get(&chip)) {
+            (Some(limit), Some(&last)) => {
+                self.current_sample.saturating_sub(last) >= limit.min_gap_samples
+            }
+            _ => true,
+        }
+    }
+
+    /// Earliest sample at which a generated write may be emitted, taking the
+    /// rate-limit gap and the chip's bus busy time into account.
+    fn generated_write_due(&self, write: &DeferredWrite) -> usize {
+        let chip = (write.chip_id, write.instance);
+        let gap_due = match (self.write_rate_limit, self.last_generated_write.get(&chip)) {
+            (Some(limit), Some(&last)) => last + limit.min_gap_samples,
+            _ => 0,
+        };
+        let bus_due = Self::bus_key(write)
+            .and_then(|key| self.chip_busy_until.get(&key))
+            .map_or(0, |&ns| ns_to_sample_ceil(ns, self.sample_rate));
+        gap_due.max(bus_due)
+    }
+
+    /// Chip and instance whose bus a generated write occupies.
+    fn bus_key(write: &DeferredWrite) -> Option<(chip::Chip, Instance)> {
+        Self::create_stream_write_command_static(
+            write.chip_id,
+            write.instance,
+            write.write_port,
+            write.write_command,
+            write.value,
+        )
+        .and_then(|cmd| cmd.register_write())
+        .map(|w| (w.chip, w.instance))
+    }
+
+    /// Marks the bus of `key` busy for its configured write delay, starting
+    /// at `current_sample` or when the previous write has finished.
+    fn occupy_bus(&mut self, key: (chip::Chip, Instance)) {
+        let Some(&busy_ns) = self.chip_write_delays.get(&key.0) else {
+            return;
+        };
+        let now = sample_to_ns(self.current_sample, self.sample_rate);
+        let start = self
+            .chip_busy_until
+            .get(&key)
+            .map_or(now, |&until| until.max(now));
+        self.chip_busy_until.insert(key, start + busy_ns as u64);
+    }
+
+    /// Records the bus time taken by a write parsed from the input.
+    fn note_parsed_write(&mut self, command: &VgmCommand) {
+        if self.chip_write_delays.is_empty() {
+            return;
+        }
+        if let Some(write) = command.register_write() {
+            self.occupy_bus((write.chip, write.instance));
+        }
+    }
+
+    /// Emits a generated write now and records its timing.
+    fn emit_generated_write(&mut self, write: DeferredWrite) {
+        if self.write_rate_limit.is_some() {
+            self.last_generated_write
+                .insert((write.chip_id, write.instance), self.current_sample);
+        }
+        if let Some(key) = Self::bus_key(&write) {
+            self.occupy_bus(key);
+        }
+        self.push_stream_write(write);
+    }
+
+    /// Queues a generated stream write, applying the write rate limit and the
+    /// chip write delays.
+    fn emit_stream_write(&mut self, write: DeferredWrite) {
+        if self.write_rate_limit.is_none() && self.chip_write_delays.is_empty() {
+            self.push_stream_write(write);
+            return;
+        }
+        let chip = (write.chip_id, write.instance);
+        let chip_has_deferred = self
+            .deferred_writes
+            .iter()
+            .any(|d| (d.chip_id, d.instance) == chip);
+        if !chip_has_deferred && self.generated_write_due(&write) <= self.current_sample {
+            self.emit_generated_write(write);
+            return;
+        }
+
+        // A busy bus always delays; only the rate-limit gap may drop.
+        let drop = matches!(
+            self.write_rate_limit,
+            Some(WriteRateLimit {
+                mode: WriteRateLimitMode::Drop,
+                ..
+            })
+        ) && !self.gap_elapsed(chip);
+        if drop {
+            self.write_rate_limit_report.dropped += 1;
+        } else if let Some(held) = self.deferred_writes.iter_mut().find(|d| {
+            (d.chip_id, d.instance, d.write_port, d.write_command)
+                == (chip.0, chip.1, write.write_port, write.write_command)
+        }) {
+            held.value = write.value;
+            self.write_rate_limit_report.coalesced += 1;
+        } else {
+            self.deferred_writes.push(write);
+        }
+    }
+
+    /// Emits the oldest held-back write of every chip that may write again.
+    fn flush_deferred_writes(&mut self) {
+        let mut i = 0;
+        while i < self.deferred_writes.len() {
+            let write = self.deferred_writes[i];
+            let chip = (write.chip_id, write.instance);
+            let older_for_chip = self.deferred_writes[..i]
+                .iter()
+                .any(|d| (d.chip_id, d.instance) == chip);
+            if older_for_chip || self.generated_write_due(&write) > self.current_sample {
+                i += 1;
+                continue;
+            }
+            self.deferred_writes.remove(i);
+            self.write_rate_limit_report.delayed += 1;
+            self.emit_generated_write(write);
+        }
+    }
+
+    /// Converts a generated write to a chip-write command and queues it.
+    fn push_stream_write(&mut self, write: DeferredWrite) {
+        if let Some(cmd) = Self::create_stream_write_command_static(
+            write.chip_id,
+            write.instance,
+            write.write_port,
+            write.write_command,
+            write.value,
+        ) {
+            self.pending_stream_writes.push(cmd);
+        }
+    }
+
     /// Processes a wait command, generating stream writes and splitting the wait as needed.
     ///
     /// This method handles large wait periods by:
@@ -2115,6 +3478,7 @@ impl VgmStream {
         &mut self,
         wait_samples: usize,
     ) -> Result<StreamResult, ParseError> {
+        self.playback_started |= wait_samples > 0;
         let target_sample = self.current_sample.saturating_add(wait_samples);
 
         let next_stream_write_sample = self.find_next_stream_write_sample(target_sample);
@@ -2139,6 +3503,14 @@ impl VgmStream {
             } else if !self.pending_stream_writes.is_empty() {
                 let cmd = self.pending_stream_writes.remove(0);
                 return Ok(StreamResult::Command(cmd));
+            } else if self.deferred_writes.iter().any(|w| {
+                let due = self.generated_write_due(w);
+                due > self.current_sample && due <= target_sample
+            }) {
+                // Nothing was written at this sample because the write was
+                // held back, but it becomes due within the wait.
+                self.pending_wait = None;
+                return self.process_wait_with_streams(remaining_wait);
             }
         }
         self.current_sample = target_sample;
@@ -2162,7 +3534,7 @@ impl VgmStream {
                 .get(&state.data_bank_id)
                 .map(|s| s.data.len())
                 .unwrap_or(0);
-            let calc = match StreamSnapshot::from_state(state, data_bank_end) {
+            let calc = match StreamSnapshot::from_state(state, data_bank_end, self.sample_rate) {
                 Some(c) => c,
                 None => continue,
             };
@@ -2178,6 +3550,14 @@ impl VgmStream {
             }
         }
 
+        // Held-back writes become due once their chip may write again.
+        for write in &self.deferred_writes {
+            let due = self.generated_write_due(write).max(self.current_sample);
+            if due <= target_sample && earliest.is_none_or(|e| due < e) {
+                earliest = Some(due);
+            }
+        }
+
         earliest
     }
 
@@ -2530,6 +3910,113 @@ impl VgmStream {
     }
 }
 
+/// A key-on or level write of one channel.
+#[derive(Debug, Clone)]
+struct ChannelControl {
+    channel: u32,
+    /// `true` for level writes, which are restored on unmute
+    level: bool,
+    /// The write, with SN76489 data bytes expanded to latch bytes
+    write: RegisterWrite,
+    /// The value of `write` that keys the channel off or silences it
+    silent: u32,
+}
+
+impl ChannelControl {
+    fn silenced(&self) -> RegisterWrite {
+        RegisterWrite {
+            value: self.silent,
+            ..self.write.clone()
+        }
+    }
+}
+
+/// Channel mask of one chip instance and the writes it needs to apply a
+/// changed mask.
+#[derive(Debug, Default)]
+struct ChannelMute {
+    mask: u32,
+    /// Last key-on or level write per channel
+    writes: HashMap<u32, ChannelControl>,
+    /// SN76489 register selected by the last latch byte
+    latch: u8,
+    /// SN76489 register values, low 4 bits of the tone registers included
+    psg_registers: [u32; 8],
+}
+
+impl ChannelMute {
+    fn clear_writes(&mut self) {
+        self.writes.clear();
+        self.latch = 0;
+        self.psg_registers = [0; 8];
+    }
+
+    /// Decodes the key-on or level write in `write`, if any.
+    fn control(&mut self, write: RegisterWrite) -> Option<ChannelControl> {
+        if write.chip == chip::Chip::Sn76489 {
+            return if write.port == 0 {
+                self.psg_control(write)
+            } else {
+                None
+            };
+        }
+        let decoded =
+            chip::regmap::channel_write(&write.chip, write.port, write.register, write.value)?;
+        Some(ChannelControl {
+            channel: decoded.channel,
+            level: decoded.level,
+            write,
+            silent: decoded.silent,
+        })
+    }
+
+    /// Tracks the latch and registers of an SN76489 and decodes its
+    /// attenuation writes.
+    fn psg_control(&mut self, write: RegisterWrite) -> Option<ChannelControl> {
+        let value = write.value;
+        if value & 0x80 != 0 {
+            self.latch = ((value >> 4) & 0x07) as u8;
+            let register = &mut self.psg_registers[self.latch as usize];
+            *register = (*register & !0x0F) | (value & 0x0F);
+        } else {
+            let register = &mut self.psg_registers[self.latch as usize];
+            *register = if self.latch & 1 == 0 && self.latch != 6 {
+                (*register & 0x0F) | ((value & 0x3F) << 4)
+            } else {
+                value & 0x0F
+            };
+        }
+        if self.latch & 1 == 0 {
+            return None;
+        }
+        let value = 0x80 | (self.latch as u32) << 4 | self.psg_registers[self.latch as usize];
+        Some(ChannelControl {
+            channel: (self.latch >> 1) as u32,
+            level: true,
+            write: RegisterWrite { value, ..write },
+            silent: value | 0x0F,
+        })
+    }
+
+    /// The SN76489 latch byte that selects the latched register again after
+    /// generated writes such as `first`, so following data bytes still reach
+    /// it. Muted attenuation registers are latched as silent.
+    fn relatch(&self, first: RegisterWrite) -> Option<RegisterWrite> {
+        if first.chip != chip::Chip::Sn76489 {
+            return None;
+        }
+        let latch = self.latch as u32;
+        let mut low = self.psg_registers[latch as usize] & 0x0F;
+        if latch & 1 == 1 && self.mask & (1 << (latch >> 1)) != 0 {
+            low = 0x0F;
+        }
+        Some(RegisterWrite {
+            value: 0x80 | latch << 4 | low,
+            ..first
+        })
+    }
+}
+
 impl Default for VgmStream {
     fn default() -> Self {
         Self::new()
@@ -2541,9 +4028,41 @@ impl Iterator for VgmStream {
     type Item = Result<StreamResult, ParseError>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if let Some(command) = self.mask_writes.pop_front() {
+            self.last_result_sample = self.absolute_sample();
+            return Some(Ok(StreamResult::Command(command)));
+        }
         match self.next_command() {
-            Ok(stream_result) => Some(Ok(stream_result)),
+            Ok(stream_result) => {
+                let stream_result = match stream_result {
+                    StreamResult::Command(command) => {
+                        StreamResult::Command(self.apply_channel_masks(command))
+                    }
+                    result => result,
+                };
+                // Waits have already advanced the clock when they are returned.
+                let wait = match &stream_result {
+                    StreamResult::Command(command) => command.wait_samples() as u64,
+                    _ => 0,
+                };
+                self.last_result_sample = self.absolute_sample().saturating_sub(wait);
+                Some(Ok(stream_result))
+            }
             Err(e) => Some(Err(e)),
         }
     }
 }
+
+/// Iterator returned by `VgmStream::timed`.
+pub struct TimedResults<'a> {
+    stream: &'a mut VgmStream,
+}
+
+impl Iterator for TimedResults<'_> {
+    type Item = Result<(u64, StreamResult), ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.stream.next()?;
+        Some(result.map(|result| (self.stream.last_result_sample(), result)))
+    }
+}
@@ -0,0 +1,1234 @@
+//! Document-level transforms.
+//!
+//! The functions in this module take a `VgmDocument`, rewrite its command
+//! stream and return a new, finalized document together with a small report
+//! describing what changed. The input document is never modified.
+//!
+//! # Quantize
+//!
+//! `quantize` snaps the time of every non-wait command to a fixed grid
+//! (for example one NTSC frame, 735 samples at 44.1 kHz). This is useful to
+//! clean up jittery live captures before converting them to frame-based
+//! sound drivers.
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::chip;
+//! use soundlog::vgm::command::{Instance, WaitSamples};
+//! use soundlog::vgm::transform::{QuantizeOptions, Rounding, quantize};
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.register_chip(chip::Chip::Sn76489, Instance::Primary, 3_579_545);
+//! builder.add_vgm_command(WaitSamples(740));
+//! builder.add_chip_write(Instance::Primary, chip::PsgSpec { value: 0x9F });
+//! let doc = builder.finalize();
+//!
+//! let options = QuantizeOptions::new(QuantizeOptions::NTSC_FRAME).with_rounding(Rounding::Nearest);
+//! let (quantized, report) = quantize(&doc, &options);
+//!
+//! assert_eq!(report.max_displacement, 5);
+//! assert_eq!(quantized.header.total_samples, 735);
+//! ```
+//!
+//! # Compress data blocks
+//!
+//! `compress_data_blocks` rewrites uncompressed stream data blocks
+//! (`0x00..=0x3F`) as compressed blocks (`0x40..=0x7E`) with a chosen bit
+//! width, sharing one decompression table. The encoding is lossless: blocks
+//! that cannot be represented exactly are left as they are.
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::vgm::detail::{StreamChipType, UncompressedStream};
+//! use soundlog::vgm::transform::{CompressOptions, compress_data_blocks};
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.attach_data_block(UncompressedStream {
+//!     chip_type: StreamChipType::Ym2612Pcm,
+//!     data: (0..256).map(|i| 0x80 + (i % 8) as u8).collect(),
+//! });
+//! let doc = builder.finalize();
+//!
+//! let (optimized, report) = compress_data_blocks(&doc, &CompressOptions::new(2));
+//! assert_eq!(report.compressed_blocks, 1);
+//! assert!(report.bytes_after < report.bytes_before);
+//! assert!(Vec::<u8>::from(&optimized).len() < Vec::<u8>::from(&doc).len());
+//! ```
+//!
+//! # Deduplicate data blocks
+//!
+//! `dedupe_data_blocks` removes uncompressed stream data blocks whose payload
+//! is identical to, or contained in, another block of the same type, and
+//! rewrites `StartStreamFastCall` block ids, `StartStream` offsets and
+//! `SeekOffset` positions to point at the block that is kept.
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::vgm::command::VgmCommand;
+//! use soundlog::vgm::detail::{StreamChipType, UncompressedStream};
+//! use soundlog::vgm::transform::dedupe_data_blocks;
+//!
+//! let mut builder = VgmBuilder::new();
+//! for data in [vec![1, 2, 3, 4], vec![2, 3], vec![1, 2, 3, 4]] {
+//!     builder.attach_data_block(UncompressedStream {
+//!         chip_type: StreamChipType::Ym2612Pcm,
+//!         data,
+//!     });
+//! }
+//! let doc = builder.finalize();
+//!
+//! let (deduped, report) = dedupe_data_blocks(&doc);
+//! assert_eq!(report.removed_blocks, 2);
+//! assert_eq!(report.bytes_saved, 6);
+//! let blocks = deduped.iter().filter(|c| matches!(c, VgmCommand::DataBlock(_)));
+//! assert_eq!(blocks.count(), 1);
+//! ```
+//!
+//! # Prune ROM blocks
+//!
+//! `prune_rom_blocks` uses `rom::rom_coverage` to find the ROM address
+//! ranges the chips actually read and drops the rest of the ROM data blocks
+//! (`0x80..=0xBF`), which shrinks arcade rips that embed whole sample ROMs.
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::vgm::detail::{RomRamChipType, RomRamDump};
+//! use soundlog::vgm::transform::{PruneRomOptions, prune_rom_blocks};
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.attach_data_block(RomRamDump {
+//!     chip_type: RomRamChipType::Ym2610AdpcmRom,
+//!     rom_size: 0x100000,
+//!     start_address: 0,
+//!     data: vec![0; 0x10000],
+//! });
+//! let doc = builder.finalize();
+//!
+//! // no ADPCM-A channel is ever keyed on
+//! let (pruned, report) = prune_rom_blocks(&doc, &PruneRomOptions::default());
+//! assert_eq!(report.pruned_blocks, 1);
+//! assert_eq!(report.bytes_after, 0);
+//! assert!(Vec::<u8>::from(&pruned).len() < 0x100);
+//! ```
+//!
+//! # Correct a chip clock
+//!
+//! `correct_clock` changes the header clock of a chip and rewrites its
+//! F-number / block and tone period registers so that the notes keep the
+//! pitch they had at the old clock. Use it when a rip was logged with the
+//! wrong clock, or to move a song to a board with a different crystal.
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::chip::{Chip, PsgSpec};
+//! use soundlog::vgm::command::Instance;
+//! use soundlog::vgm::transform::correct_clock;
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+//! // channel 0 tone period 0x0FE
+//! builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x8E });
+//! builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x0F });
+//! let doc = builder.finalize();
+//!
+//! let (corrected, report) = correct_clock(&doc, Chip::Sn76489, 4_000_000);
+//! assert!(report.pitch_preserved);
+//! assert_eq!(corrected.header.sn76489_clock, 4_000_000);
+//! ```
+//!
+//! `match_clocks` applies the same correction to every chip a document
+//! shares with another header, so two documents that use the same chip at
+//! different clocks can be merged without detuning either of them.
+//!
+//! # Scale volumes
+//!
+//! `scale_volume` changes the output level of whole chips or single channels
+//! by a dB amount, rewriting carrier TL, PSG attenuation and PCM volume
+//! registers. This fixes mixing imbalances of multi-chip rips in the file
+//! itself.
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::chip::{Chip, PsgSpec};
+//! use soundlog::vgm::command::Instance;
+//! use soundlog::vgm::transform::{VolumeOptions, scale_volume};
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+//! // channel 0 attenuation 0
+//! builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x90 });
+//! let doc = builder.finalize();
+//!
+//! let options = VolumeOptions::new().with_chip(Chip::Sn76489, -6.0);
+//! let (quieter, report) = scale_volume(&doc, &options);
+//! assert_eq!(report.scaled_writes, 1);
+//! assert_ne!(quieter, doc);
+//! ```
+//!
+//! # Port PSG songs
+//!
+//! `port_psg` moves a song between the SN76489 and the AY-3-8910, converting
+//! tone and noise periods for the target clock and mapping the volume
+//! curves, so Master System tracks can be auditioned on MSX or Atari targets
+//! and vice versa. Features the target lacks are counted in the report.
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::chip::{Chip, PsgSpec};
+//! use soundlog::vgm::command::Instance;
+//! use soundlog::vgm::transform::port_psg;
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+//! // channel 0 tone period 0x0FE at full volume, periodic noise
+//! builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x8E });
+//! builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x0F });
+//! builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x90 });
+//! builder.add_chip_write(Instance::Primary, PsgSpec { value: 0xE0 });
+//! let doc = builder.finalize();
+//!
+//! let (msx, report) = port_psg(&doc, Chip::Ay8910, 1_789_772);
+//! assert_eq!(report.ported_writes, 4);
+//! assert_eq!(report.periodic_noise, 1);
+//! assert_eq!(msx.header.ay8910_clock, 1_789_772);
+//! assert_eq!(msx.header.sn76489_clock, 0);
+//! ```
+//!
+//! # Patch loop state
+//!
+//! `patch_loop_state` inserts writes at the loop point that reconcile the
+//! differences reported by `loop_check::check_loop`: FM voice registers are
+//! restored, hanging notes keyed off and DAC streams restarted or stopped at
+//! their first pass position, so that the song loops without clicks.
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::chip::{Chip, PsgSpec};
+//! use soundlog::vgm::command::{Instance, WaitSamples};
+//! use soundlog::vgm::loop_check::check_loop;
+//! use soundlog::vgm::transform::patch_loop_state;
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+//! builder.add_vgm_command(WaitSamples(100));
+//! // loop: a note on channel 0 that is never silenced
+//! builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x8E });
+//! builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x0F });
+//! builder.add_vgm_command(WaitSamples(100));
+//! builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x90 });
+//! builder.add_vgm_command(WaitSamples(100));
+//! builder.set_loop_index(1);
+//! let doc = builder.finalize();
+//!
+//! let (patched, report) = patch_loop_state(&doc);
+//! assert_eq!(report.inserted_commands, 1);
+//! assert!(check_loop(&patched).is_clean());
+//! ```
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::chip::{
+    Ay8910Spec, PsgSpec, Rf5c68U8Spec, Rf5c164U8Spec, SegaPcmSpec, Y8950Spec, Ym2151Spec,
+    Ym2203Spec, Ym2413Spec, Ym2608Spec, Ym2610Spec, Ym2612Spec, Ym3526Spec, Ym3812Spec, Ymf262Spec,
+};
+use crate::vgm::command::{
+    LengthMode, RegisterWrite, SeekOffset, VgmCommand, Wait735Samples, Wait882Samples, WaitNSample,
+    WaitSamples, Ym2612Port0Address2AWriteAndWaitN,
+};
+use crate::vgm::compression::{BlockEncoding, compress_block, generate_table};
+use crate::vgm::detail::{
+    BitPackingSubType, DataBlockType, RomRamChipType, RomRamDump, UncompressedStream,
+    build_data_block, parse_data_block,
+};
+use crate::vgm::rom::{dump_range, intersect, merge, rom_coverage};
+use crate::vgm::{VgmBuilder, VgmDocument, VgmHeader};
+
+mod clock;
+mod loop_state;
+mod psg;
+mod volume;
+
+pub use clock::{ClockCorrectionReport, ClockMatchReport, correct_clock, match_clocks};
+pub use loop_state::{LoopPatchReport, patch_loop_state};
+pub use psg::{PsgPortReport, port_psg};
+pub use volume::{VolumeAdjustment, VolumeOptions, VolumeReport, scale_volume};
+
+/// Rounding mode used when snapping a command time to the grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rounding {
+    /// Snap to the closest grid line. Ties are rounded up.
+    #[default]
+    Nearest,
+    /// Snap to the grid line at or before the original time.
+    Floor,
+    /// Snap to the grid line at or after the original time.
+    Ceil,
+}
+
+/// Options for `quantize`. `frame_rate::FrameRateGuess::quantize_options`
+/// picks the frame grid a document was logged with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuantizeOptions {
+    /// Grid size in samples. A value of `0` or `1` leaves times unchanged.
+    pub grid: u32,
+    /// How times between grid lines are snapped.
+    pub rounding: Rounding,
+}
+
+impl QuantizeOptions {
+    /// One NTSC frame (1/60 s) at 44.1 kHz.
+    pub const NTSC_FRAME: u32 = 735;
+    /// One PAL frame (1/50 s) at 44.1 kHz.
+    pub const PAL_FRAME: u32 = 882;
+
+    /// Create options for the given grid size using `Rounding::Nearest`.
+    pub fn new(grid: u32) -> Self {
+        QuantizeOptions {
+            grid,
+            rounding: Rounding::Nearest,
+        }
+    }
+
+    /// Set the rounding mode.
+    pub fn with_rounding(mut self, rounding: Rounding) -> Self {
+        self.rounding = rounding;
+        self
+    }
+
+    fn snap(&self, time: u64) -> u64 {
+        let grid = self.grid.max(1) as u64;
+        match self.rounding {
+            Rounding::Nearest => (time + grid / 2) / grid * grid,
+            Rounding::Floor => time / grid * grid,
+            Rounding::Ceil => time.div_ceil(grid) * grid,
+        }
+    }
+}
+
+impl Default for QuantizeOptions {
+    fn default() -> Self {
+        Self::new(Self::NTSC_FRAME)
+    }
+}
+
+/// Summary of the changes made by `quantize`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct QuantizeReport {
+    /// Largest distance (in samples) any command was moved.
+    pub max_displacement: u32,
+    /// Index in the source document of the command moved the furthest.
+    pub max_displacement_index: Option<usize>,
+    /// Number of non-wait commands whose time changed.
+    pub moved_commands: usize,
+    /// Total samples of the source document.
+    pub total_samples_before: u32,
+    /// Total samples of the quantized document.
+    pub total_samples_after: u32,
+}
+
+/// Snap the time of every non-wait command in `document` to a grid.
+///
+/// Waits are recomputed from the snapped times, so consecutive commands that
+/// land on the same grid line end up without a wait between them. The loop
+/// point, end of data and DAC `0x8n` writes are snapped as well; `0x8n`
+/// commands are rewritten as `0x80` followed by a separate wait so that their
+/// embedded wait does not shift later commands off the grid.
+///
+/// Returns the finalized document and a report with the maximum displacement.
+pub fn quantize(
+    document: &VgmDocument,
+    options: &QuantizeOptions,
+) -> (VgmDocument, QuantizeReport) {
+    let loop_index = document.loop_command_index();
+
+    let mut report = QuantizeReport {
+        total_samples_before: document.total_samples(0),
+        ..Default::default()
+    };
+    let mut commands: Vec<VgmCommand> = Vec::with_capacity(document.commands.len());
+    let mut new_loop_index = None;
+    let mut source_time: u64 = 0;
+    let mut output_time: u64 = 0;
+
+    // Emit waits so the output clock reaches the snapped `source_time` and
+    // return how far the command at `index` was moved.
+    let mut align = |commands: &mut Vec<VgmCommand>,
+                     report: &mut QuantizeReport,
+                     source_time: u64,
+                     index: usize|
+     -> u64 {
+        let snapped = options.snap(source_time).max(output_time);
+        push_wait(commands, snapped - output_time);
+        output_time = snapped;
+
+        let displacement = snapped.abs_diff(source_time);
+        if displacement > report.max_displacement as u64 {
+            report.max_displacement = displacement.min(u32::MAX as u64) as u32;
+            report.max_displacement_index = Some(index);
+        }
+        displacement
+    };
+
+    let mut ended = false;
+    for (index, command) in document.commands.iter().enumerate() {
+        if loop_index == Some(index) {
+            align(&mut commands, &mut report, source_time, index);
+            new_loop_index = Some(commands.len());
+        }
+
+        match command {
+            VgmCommand::WaitSamples(_)
+            | VgmCommand::Wait735Samples(_)
+            | VgmCommand::Wait882Samples(_)
+            | VgmCommand::WaitNSample(_) => {
+                source_time += command.wait_samples() as u64;
+            }
+            VgmCommand::YM2612Port0Address2AWriteAndWaitN(s) => {
+                if align(&mut commands, &mut report, source_time, index) > 0 {
+                    report.moved_commands += 1;
+                }
+                commands.push(Ym2612Port0Address2AWriteAndWaitN(0).into());
+                source_time += s.0 as u64;
+            }
+            other => {
+                if align(&mut commands, &mut report, source_time, index) > 0 {
+                    report.moved_commands += 1;
+                }
+                ended |= matches!(other, VgmCommand::EndOfData(_));
+                commands.push(other.clone());
+            }
+        }
+    }
+    if !ended {
+        align(
+            &mut commands,
+            &mut report,
+            source_time,
+            document.commands.len(),
+        );
+    }
+
+    let mut header = document.header.clone();
+    if new_loop_index.is_none() {
+        header.loop_offset = 0;
+        header.loop_samples = 0;
+    }
+    let mut builder = VgmBuilder::from(VgmDocument {
+        header,
+        extra_header: document.extra_header.clone(),
+        commands,
+        gd3: document.gd3.clone(),
+    });
+    if let Some(index) = new_loop_index {
+        builder.set_loop_index(index);
+    }
+    let quantized = builder.finalize();
+    report.total_samples_after = quantized.header.total_samples;
+
+    (quantized, report)
+}
+
+/// Append wait commands totalling `samples`, using the shortest encodings.
+pub(crate) fn push_wait(commands: &mut Vec<VgmCommand>, mut samples: u64) {
+    while samples > 0 {
+        let (command, consumed) = match samples {
+            735 => (Wait735Samples.into(), 735),
+            882 => (Wait882Samples.into(), 882),
+            1..=16 => (WaitNSample(samples as u8 - 1).into(), samples),
+            _ => {
+                let chunk = samples.min(u16::MAX as u64);
+                (WaitSamples(chunk as u16).into(), chunk)
+            }
+        };
+        commands.push(command);
+        samples -= consumed;
+    }
+}
+
+// Replacement `(port, register, value)` writes for one chip write.
+type ChipWrites = Vec<(u8, u32, u8)>;
+
+// Rebuild `document` with `header`, replacing every chip write for which
+// `rewrite` returns replacement writes (possibly none). The loop point stays
+// on the command it was on, or the first command replacing it.
+fn rewrite_chip_writes(
+    document: &VgmDocument,
+    header: VgmHeader,
+    mut rewrite: impl FnMut(&VgmCommand, RegisterWrite) -> Option<ChipWrites>,
+) -> VgmDocument {
+    rewrite_commands(document, header, |command| {
+        let writes = command
+            .register_write()
+            .and_then(|write| rewrite(command, write))?;
+        Some(
+            writes
+                .into_iter()
+                .map(|(port, register, value)| with_write(command, port, register, value))
+                .collect(),
+        )
+    })
+}
+
+// Rebuild `document` with `header`, replacing every command for which
+// `rewrite` returns replacement commands (possibly none). The loop point is
+// kept as in `rewrite_chip_writes`.
+fn rewrite_commands(
+    document: &VgmDocument,
+    header: VgmHeader,
+    mut rewrite: impl FnMut(&VgmCommand) -> Option<Vec<VgmCommand>>,
+) -> VgmDocument {
+    let loop_index = document.loop_command_index();
+    let mut new_loop_index = None;
+    let mut commands = Vec::with_capacity(document.commands.len());
+    for (index, command) in document.commands.iter().enumerate() {
+        if Some(index) == loop_index {
+            new_loop_index = Some(commands.len());
+        }
+        match rewrite(command) {
+            Some(replacement) => commands.extend(replacement),
+            None => commands.push(command.clone()),
+        }
+    }
+
+    let mut builder = VgmBuilder::from(VgmDocument {
+        header,
+        extra_header: document.extra_header.clone(),
+        commands,
+        gd3: document.gd3.clone(),
+    });
+    if let Some(index) = new_loop_index {
+        builder.set_loop_index(index);
+    }
+    builder.finalize()
+}
+
+// `template` with its register write replaced by `register`/`value` on `port`.
+fn with_write(template: &VgmCommand, port: u8, register: u32, value: u8) -> VgmCommand {
+    let offset = register as u16;
+    let register = register as u8;
+    match template {
+        VgmCommand::Sn76489Write(i, _) => VgmCommand::Sn76489Write(*i, PsgSpec { value }),
+        VgmCommand::Ay8910Write(i, _) => {
+            VgmCommand::Ay8910Write(*i, Ay8910Spec { register, value })
+        }
+        VgmCommand::Ym2151Write(i, _) => {
+            VgmCommand::Ym2151Write(*i, Ym2151Spec { register, value })
+        }
+        VgmCommand::Ym2203Write(i, _) => {
+            VgmCommand::Ym2203Write(*i, Ym2203Spec { register, value })
+        }
+        VgmCommand::Ym2608Write(i, _) => VgmCommand::Ym2608Write(
+            *i,
+            Ym2608Spec {
+                port,
+                register,
+                value,
+            },
+        ),
+        VgmCommand::Ym2610bWrite(i, _) => VgmCommand::Ym2610bWrite(
+            *i,
+            Ym2610Spec {
+                port,
+                register,
+                value,
+            },
+        ),
+        VgmCommand::Ym2612Write(i, _) => VgmCommand::Ym2612Write(
+            *i,
+            Ym2612Spec {
+                port,
+                register,
+                value,
+            },
+        ),
+        VgmCommand::Ym3812Write(i, _) => {
+            VgmCommand::Ym3812Write(*i, Ym3812Spec { register, value })
+        }
+        VgmCommand::Ym3526Write(i, _) => {
+            VgmCommand::Ym3526Write(*i, Ym3526Spec { register, value })
+        }
+        VgmCommand::Y8950Write(i, _) => VgmCommand::Y8950Write(*i, Y8950Spec { register, value }),
+        VgmCommand::Ymf262Write(i, _) => VgmCommand::Ymf262Write(
+            *i,
+            Ymf262Spec {
+                port,
+                register,
+                value,
+            },
+        ),
+        VgmCommand::Ym2413Write(i, _) => {
+            VgmCommand::Ym2413Write(*i, Ym2413Spec { register, value })
+        }
+        VgmCommand::SegaPcmWrite(i, _) => {
+            VgmCommand::SegaPcmWrite(*i, SegaPcmSpec { offset, value })
+        }
+        VgmCommand::Rf5c68U8Write(i, _) => VgmCommand::Rf5c68U8Write(
+            *i,
+            Rf5c68U8Spec {
+                offset: register,
+                value,
+            },
+        ),
+        VgmCommand::Rf5c164U8Write(i, _) => VgmCommand::Rf5c164U8Write(
+            *i,
+            Rf5c164U8Spec {
+                offset: register,
+                value,
+            },
+        ),
+        command => command.clone(),
+    }
+}
+
+/// Options for `compress_data_blocks`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressOptions {
+    /// Width of one compressed value in bits (`1..=7`; data is read as 8-bit
+    /// values, so 8 or more never saves space).
+    pub bits: u8,
+}
+
+impl CompressOptions {
+    /// Create options for the given compressed bit width.
+    pub fn new(bits: u8) -> Self {
+        CompressOptions { bits }
+    }
+}
+
+impl Default for CompressOptions {
+    fn default() -> Self {
+        Self::new(4)
+    }
+}
+
+/// Summary of the changes made by `compress_data_blocks`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CompressReport {
+    /// Encoding that was applied, or `None` when the document is unchanged.
+    pub encoding: Option<BlockEncoding>,
+    /// Number of data blocks rewritten as compressed blocks.
+    pub compressed_blocks: usize,
+    /// Payload bytes of the uncompressed stream blocks before the transform.
+    pub bytes_before: usize,
+    /// Payload bytes of the compressed blocks and the decompression table.
+    pub bytes_after: usize,
+}
+
+/// Rewrite the uncompressed stream data blocks of `document` as compressed
+/// blocks.
+///
+/// DPCM and table-based bit packing are tried with `options.bits` bits per
+/// value, each with one decompression table generated for all blocks, and the
+/// smaller result is used. The document is returned unchanged when neither
+/// encoding can represent every block exactly, when it does not save space,
+/// or when the document already contains a decompression table (players keep
+/// only the most recent table, so a second one would break existing
+/// compressed blocks).
+pub fn compress_data_blocks(
+    document: &VgmDocument,
+    options: &CompressOptions,
+) -> (VgmDocument, CompressReport) {
+    let mut report = CompressReport::default();
+    let unchanged = |report| (document.clone(), report);
+
+    let mut streams: Vec<(usize, UncompressedStream)> = Vec::new();
+    for (index, command) in document.commands.iter().enumerate() {
+        let VgmCommand::DataBlock(block) = command else {
+            continue;
+        };
+        if block.data_type == 0x7F {
+            return unchanged(report);
+        }
+        if let Ok(DataBlockType::UncompressedStream(stream)) = parse_data_block((**block).clone())
+            && !stream.data.is_empty()
+        {
+            report.bytes_before += stream.data.len();
+            streams.push((index, stream));
+        }
+    }
+    if streams.is_empty() {
+        return unchanged(report);
+    }
+
+    let candidates = [
+        BlockEncoding::Dpcm {
+            bits_decompressed: 8,
+            bits_compressed: options.bits,
+        },
+        BlockEncoding::BitPacking {
+            bits_decompressed: 8,
+            bits_compressed: options.bits,
+            sub_type: BitPackingSubType::UseTable,
+            add_value: 0,
+        },
+    ];
+    let stream_refs: Vec<&UncompressedStream> = streams.iter().map(|(_, s)| s).collect();
+    let best = candidates
+        .iter()
+        .filter_map(|encoding| {
+            let table = generate_table(&stream_refs, encoding).ok()?;
+            let blocks = streams
+                .iter()
+                .map(|(_, stream)| compress_block(stream, encoding, Some(&table)))
+                .collect::<Result<Vec<_>, _>>()
+                .ok()?;
+            let table = build_data_block(&DataBlockType::DecompressionTable(table));
+            let blocks: Vec<_> = blocks
+                .into_iter()
+                .map(|b| build_data_block(&DataBlockType::CompressedStream(b)))
+                .collect();
+            let size = table.data.len() + blocks.iter().map(|b| b.data.len()).sum::<usize>();
+            Some((size, *encoding, table, blocks))
+        })
+        .min_by_key(|(size, ..)| *size);
+    let Some((size, encoding, table, blocks)) = best else {
+        return unchanged(report);
+    };
+    // The table is an extra `0x67 0x66 tt ss ss ss ss` command.
+    if size + 7 >= report.bytes_before {
+        return unchanged(report);
+    }
+
+    let first_index = streams[0].0;
+    let mut commands = document.commands.clone();
+    for ((index, _), mut block) in streams.iter().zip(blocks) {
+        if let VgmCommand::DataBlock(original) = &commands[*index] {
+            block.chip_instance = original.chip_instance;
+        }
+        commands[*index] = VgmCommand::DataBlock(Box::new(block));
+    }
+    commands.insert(first_index, VgmCommand::DataBlock(Box::new(table)));
+
+    let mut builder = VgmBuilder::from(VgmDocument {
+        header: document.header.clone(),
+        extra_header: document.extra_header.clone(),
+        commands,
+        gd3: document.gd3.clone(),
+    });
+    if let Some(index) = document.loop_command_index() {
+        builder.set_loop_index(if index >= first_index {
+            index + 1
+        } else {
+            index
+        });
+    }
+
+    report.encoding = Some(encoding);
+    report.compressed_blocks = streams.len();
+    report.bytes_after = size;
+    (builder.finalize(), report)
+}
+
+/// Summary of the changes made by `dedupe_data_blocks`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DedupeReport {
+    /// Number of data blocks removed.
+    pub removed_blocks: usize,
+    /// Payload bytes of the removed blocks.
+    pub bytes_saved: usize,
+    /// Redundant blocks that were kept because a stream reference could not
+    /// be rewritten (for example a read crossing the end of the block).
+    pub kept_for_references: usize,
+}
+
+// A data block as seen by `VgmStream`: its global block id (the index among
+// all data blocks except decompression tables) and its place in a data bank.
+struct BankBlock {
+    command_index: usize,
+    bank: Option<u8>,
+    offset: usize,
+    size: usize,
+    // Uncompressed stream payload; only these blocks can be removed.
+    payload: Option<Vec<u8>>,
+    // Kept block that contains this block's payload, and where.
+    duplicate_of: Option<(usize, usize)>,
+    removed: bool,
+}
+
+// A place in the command stream that refers to data bank positions.
+enum DataReference {
+    // `StartStreamFastCall` at `command_index` playing block `block`.
+    Block {
+        command_index: usize,
+        block: usize,
+    },
+    // A read of `start..end` (`None`: up to the end of the bank). Without a
+    // command to rewrite, the range must stay where it is.
+    Range {
+        command_index: Option<usize>,
+        bank: u8,
+        start: usize,
+        end: Option<usize>,
+    },
+}
+
+/// Remove redundant uncompressed stream data blocks.
+///
+/// A block is redundant when an earlier block of the same data type has an
+/// identical payload or contains it. Removing a block shifts the bank
+/// offsets of later blocks and the ids of all later data blocks, so every
+/// stream reference is rewritten:
+///
+/// - `StartStreamFastCall` keeps playing the same data; a removed block can
+///   only be replaced by an identical one, since a fast call plays whole
+///   blocks.
+/// - `StartStream` and `SeekOffset` positions are moved. The range read
+///   from the position (known for `CommandCount` streams and for `0x8n`
+///   writes up to the next seek; otherwise the rest of the bank) must lie
+///   within one removed block or outside all of them.
+///
+/// Blocks that fail these checks are kept and counted in
+/// `DedupeReport::kept_for_references`.
+pub fn dedupe_data_blocks(document: &VgmDocument) -> (VgmDocument, DedupeReport) {
+    let mut report = DedupeReport::default();
+    let mut blocks = bank_blocks(document);
+
+    // Largest blocks first so that smaller blocks find their container; the
+    // stable sort keeps the first of several identical blocks.
+    let mut order: Vec<usize> = (0..blocks.len())
+        .filter(|&i| blocks[i].payload.as_ref().is_some_and(|p| !p.is_empty()))
+        .collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(blocks[i].size));
+    let mut kept: Vec<usize> = Vec::new();
+    for i in order {
+        let payload = blocks[i].payload.as_deref().unwrap_or_default();
+        // Only an earlier block can stand in: a later one is not loaded yet
+        // where this block is.
+        let container = kept.iter().find_map(|&k| {
+            let other = blocks[k].payload.as_deref().unwrap_or_default();
+            (blocks[k].bank == blocks[i].bank && blocks[k].command_index < blocks[i].command_index)
+                .then(|| find_subslice(other, payload))
+                .flatten()
+                .map(|pos| (k, pos))
+        });
+        match container {
+            Some(found) => {
+                blocks[i].duplicate_of = Some(found);
+                blocks[i].removed = true;
+            }
+            None => kept.push(i),
+        }
+    }
+    let redundant = blocks.iter().filter(|b| b.removed).count();
+    if redundant == 0 {
+        return (document.clone(), report);
+    }
+
+    // Keep blocks whose references cannot be rewritten until nothing changes.
+    let references = data_references(document, &blocks);
+    loop {
+        let mut changed = false;
+        for reference in &references {
+            for i in conflicting_blocks(reference, &blocks) {
+                blocks[i].removed = false;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let mut commands = document.commands.clone();
+    for reference in &references {
+        match *reference {
+            DataReference::Block {
+                command_index,
+                block,
+            } => {
+                let target = match blocks[block].duplicate_of {
+                    Some((k, _)) if blocks[block].removed => k,
+                    _ => block,
+                };
+                let new_id = blocks[..target].iter().filter(|b| !b.removed).count();
+                if let VgmCommand::StartStreamFastCall(fast) = &mut commands[command_index] {
+                    fast.block_id = new_id as u16;
+                }
+            }
+            DataReference::Range {
+                command_index: Some(command_index),
+                bank,
+                start,
+                ..
+            } => {
+                let new_start = new_bank_offset(&blocks, bank, start) as u32;
+                match &mut commands[command_index] {
+                    VgmCommand::StartStream(start) => start.data_start_offset = new_start as i32,
+                    VgmCommand::SeekOffset(seek) => *seek = SeekOffset(new_start),
+                    _ => {}
+                }
+            }
+            DataReference::Range { .. } => {}
+        }
+    }
+
+    let removed: Vec<usize> = blocks
+        .iter()
+        .filter(|b| b.removed)
+        .map(|b| b.command_index)
+        .collect();
+    report.removed_blocks = removed.len();
+    report.bytes_saved = blocks.iter().filter(|b| b.removed).map(|b| b.size).sum();
+    report.kept_for_references = redundant - removed.len();
+    if removed.is_empty() {
+        return (document.clone(), report);
+    }
+
+    let loop_index = document.loop_command_index();
+    let commands: Vec<VgmCommand> = commands
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| !removed.contains(index))
+        .map(|(_, command)| command)
+        .collect();
+    let mut builder = VgmBuilder::from(VgmDocument {
+        header: document.header.clone(),
+        extra_header: document.extra_header.clone(),
+        commands,
+        gd3: document.gd3.clone(),
+    });
+    if let Some(index) = loop_index {
+        builder.set_loop_index(index - removed.iter().filter(|&&r| r < index).count());
+    }
+    (builder.finalize(), report)
+}
+
+/// Options for `prune_rom_blocks`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PruneRomOptions {
+    /// Unread gaps shorter than this many bytes between read ranges are
+    /// kept, so that the ranges stay in one data block. Every extra block
+    /// costs 15 bytes of command and ROM header.
+    pub merge_gap: u32,
+}
+
+impl PruneRomOptions {
+    /// Create options with the given merge gap.
+    pub fn new(merge_gap: u32) -> Self {
+        PruneRomOptions { merge_gap }
+    }
+}
+
+impl Default for PruneRomOptions {
+    fn default() -> Self {
+        Self::new(256)
+    }
+}
+
+/// Summary of the changes made by `prune_rom_blocks`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PruneRomReport {
+    /// Number of ROM data blocks that were trimmed, split or removed.
+    pub pruned_blocks: usize,
+    /// ROM payload bytes of the analyzed ROM types before the transform.
+    pub bytes_before: usize,
+    /// ROM payload bytes of the analyzed ROM types after the transform.
+    pub bytes_after: usize,
+    /// ROM types left as they are because their coverage is unknown.
+    pub skipped: Vec<RomRamChipType>,
+}
+
+/// Drop the parts of ROM data blocks that are never read.
+///
+/// `rom::rom_coverage` decides which address ranges are read; each ROM block
+/// of a chip with known coverage is replaced by one block per read range it
+/// overlaps (after merging ranges closer than `options.merge_gap`), keeping
+/// the declared ROM size. Blocks that are not read at all are removed.
+/// Unread addresses then read as zero in a player, which is inaudible since
+/// no sample plays them. `StartStreamFastCall` block ids are rewritten
+/// because they count ROM blocks too.
+pub fn prune_rom_blocks(
+    document: &VgmDocument,
+    options: &PruneRomOptions,
+) -> (VgmDocument, PruneRomReport) {
+    let mut report = PruneRomReport::default();
+    let mut keep: HashMap<(RomRamChipType, u8), Vec<Range<u32>>> = HashMap::new();
+    for coverage in rom_coverage(document) {
+        let dumped_bytes = coverage.dumped_bytes() as usize;
+        match coverage.read_ranges {
+            Some(read) => {
+                report.bytes_before += dumped_bytes;
+                keep.insert(
+                    (coverage.chip_type, coverage.chip_instance),
+                    merge(read, options.merge_gap),
+                );
+            }
+            None if !report.skipped.contains(&coverage.chip_type) => {
+                report.skipped.push(coverage.chip_type)
+            }
+            None => {}
+        }
+    }
+
+    let loop_index = document.loop_command_index();
+    let mut new_loop_index = None;
+    let mut commands: Vec<VgmCommand> = Vec::with_capacity(document.commands.len());
+    // New id of every old data block id (decompression tables have no id).
+    let mut block_ids: Vec<usize> = Vec::new();
+    let mut next_id = 0;
+    for (index, command) in document.commands.iter().enumerate() {
+        if loop_index == Some(index) {
+            new_loop_index = Some(commands.len());
+        }
+        let VgmCommand::DataBlock(block) = command else {
+            commands.push(command.clone());
+            continue;
+        };
+        if block.data_type != 0x7F {
+            block_ids.push(next_id);
+        }
+        let pieces = match parse_data_block((**block).clone()) {
+            Ok(DataBlockType::RomRamDump(dump))
+                if let Some(ranges) = keep.get(&(dump.chip_type, block.chip_instance)) =>
+            {
+                let range = dump_range(&dump);
+                let pieces = intersect(std::slice::from_ref(&range), ranges);
+                report.bytes_after += pieces.iter().map(|p| p.len()).sum::<usize>();
+                if pieces != [range] {
+                    report.pruned_blocks += 1;
+                    Some((dump, pieces))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        let Some((dump, pieces)) = pieces else {
+            next_id += (block.data_type != 0x7F) as usize;
+            commands.push(command.clone());
+            continue;
+        };
+        for piece in pieces {
+            let offset = (piece.start - dump.start_address) as usize;
+            let mut pruned = build_data_block(&DataBlockType::RomRamDump(RomRamDump {
+                chip_type: dump.chip_type,
+                rom_size: dump.rom_size,
+                start_address: piece.start,
+                data: dump.data[offset..offset + piece.len()].to_vec(),
+            }));
+            pruned.chip_instance = block.chip_instance;
+            commands.push(VgmCommand::DataBlock(Box::new(pruned)));
+            next_id += 1;
+        }
+    }
+    if report.pruned_blocks == 0 {
+        return (document.clone(), report);
+    }
+
+    for command in &mut commands {
+        if let VgmCommand::StartStreamFastCall(fast) = command
+            && let Some(&id) = block_ids.get(fast.block_id as usize)
+        {
+            fast.block_id = id as u16;
+        }
+    }
+    let mut builder = VgmBuilder::from(VgmDocument {
+        header: document.header.clone(),
+        extra_header: document.extra_header.clone(),
+        commands,
+        gd3: document.gd3.clone(),
+    });
+    if let Some(index) = new_loop_index {
+        builder.set_loop_index(index);
+    }
+    (builder.finalize(), report)
+}
+
+// Data blocks in block id order with their bank placement.
+fn bank_blocks(document: &VgmDocument) -> Vec<BankBlock> {
+    let mut bank_sizes: HashMap<u8, usize> = HashMap::new();
+    let mut blocks = Vec::new();
+    for (command_index, command) in document.commands.iter().enumerate() {
+        let VgmCommand::DataBlock(block) = command else {
+            continue;
+        };
+        if block.data_type == 0x7F {
+            continue;
+        }
+        let (bank, size, payload) = match parse_data_block((**block).clone()) {
+            Ok(DataBlockType::UncompressedStream(stream)) => {
+                (Some(block.data_type), stream.data.len(), Some(stream.data))
+            }
+            Ok(DataBlockType::CompressedStream(stream)) => (
+                Some(block.data_type),
+                stream.uncompressed_size as usize,
+                None,
+            ),
+            _ => (None, block.data.len(), None),
+        };
+        let offset = match bank {
+            Some(bank) => {
+                let bank_size = bank_sizes.entry(bank).or_insert(0);
+                *bank_size += size;
+                *bank_size - size
+            }
+            None => 0,
+        };
+        blocks.push(BankBlock {
+            command_index,
+            bank,
+            offset,
+            size,
+            payload,
+            duplicate_of: None,
+            removed: false,
+        });
+    }
+    blocks
+}
+
+// Collect every command that refers to data bank contents.
+fn data_references(document: &VgmDocument, blocks: &[BankBlock]) -> Vec<DataReference> {
+    // stream id -> (bank, step size, step base)
+    let mut streams: HashMap<u8, (u8, usize, usize)> = HashMap::new();
+    // Current `0x8n` read: seek command, start offset and bytes read so far.
+    let mut dac_read: Option<(Option<usize>, usize, usize)> = None;
+    let mut references = Vec::new();
+    let finish_dac_read = |read: Option<(Option<usize>, usize, usize)>,
+                           references: &mut Vec<DataReference>| {
+        if let Some((command_index, start, count)) = read
+            && (count > 0 || command_index.is_some())
+        {
+            references.push(DataReference::Range {
+                command_index,
+                bank: 0x00,
+                start,
+                // Reads without a seek continue from an unknown position.
+                end: command_index.map(|_| start + count),
+            });
+        }
+    };
+    let loop_index = document.loop_command_index();
+
+    for (index, command) in document.commands.iter().enumerate() {
+        if loop_index == Some(index) && dac_read.is_some() {
+            // After looping, reads continue from wherever the first pass
+            // stopped.
+            finish_dac_read(dac_read.take(), &mut references);
+            dac_read = Some((None, 0, 0));
+        }
+        match command {
+            VgmCommand::SetStreamData(data) => {
+                streams.insert(
+                    data.stream_id,
+                    (
+                        data.data_bank_id,
+                        data.step_size.max(1) as usize,
+                        data.step_base as usize,
+                    ),
+                );
+            }
+            VgmCommand::StartStream(start) => {
+                let Some(&(bank, step_size, step_base)) = streams.get(&start.stream_id) else {
+                    continue;
+                };
+                let end = match start.length_mode {
+                    LengthMode::CommandCount {
+                        reverse: false,
+                        looped: false,
+                    } if start.data_start_offset >= 0 => Some(
+                        start.data_start_offset as usize
+                            + step_base
+                            + start.data_length as usize * step_size,
+                    ),
+                    _ => None,
+                };
+                references.push(DataReference::Range {
+                    command_index: (start.data_start_offset >= 0).then_some(index),
+                    bank,
+                    start: start.data_start_offset.max(0) as usize,
+                    end,
+                });
+            }
+            VgmCommand::StartStreamFastCall(fast) if (fast.block_id as usize) < blocks.len() => {
+                references.push(DataReference::Block {
+                    command_index: index,
+                    block: fast.block_id as usize,
+                });
+            }
+            VgmCommand::SeekOffset(seek) => {
+                finish_dac_read(dac_read.take(), &mut references);
+                dac_read = Some((Some(index), seek.0 as usize, 0));
+            }
+            VgmCommand::YM2612Port0Address2AWriteAndWaitN(_) => {
+                dac_read.get_or_insert((None, 0, 0)).2 += 1;
+            }
+            _ => {}
+        }
+    }
+    finish_dac_read(dac_read, &mut references);
+    references
+}
+
+// Removed blocks that make `reference` impossible to rewrite.
+fn conflicting_blocks(reference: &DataReference, blocks: &[BankBlock]) -> Vec<usize> {
+    let removed = blocks.iter().enumerate().filter(|(_, b)| b.removed);
+    match *reference {
+        DataReference::Block { block, .. } => match blocks[block].duplicate_of {
+            Some((k, _)) if blocks[block].removed && blocks[k].size != blocks[block].size => {
+                vec![block]
+            }
+            _ => Vec::new(),
+        },
+        DataReference::Range {
+            command_index,
+            bank,
+            start,
+            end,
+        } => {
+            let end = end.unwrap_or(usize::MAX).max(start + 1);
+            removed
+                .filter(|(_, b)| b.bank == Some(bank))
+                .filter(|(_, b)| {
+                    let block_end = b.offset + b.size;
+                    match command_index {
+                        // Fixed ranges must not move at all.
+                        None => b.offset < end,
+                        Some(_) => {
+                            let inside = start >= b.offset && end <= block_end;
+                            !inside && b.offset < end && start < block_end
+                        }
+                    }
+                })
+                .map(|(i, _)| i)
+                .collect()
+        }
+    }
+}
+
+// Position of old bank offset `offset` after removing blocks.
+fn new_bank_offset(blocks: &[BankBlock], bank: u8, offset: usize) -> usize {
+    let new_start = |index: usize| -> usize {
+        blocks[..index]
+            .iter()
+            .filter(|b| b.bank == Some(bank) && !b.removed)
+            .map(|b| b.size)
+            .sum()
+    };
+    let in_bank = || {
+        blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.bank == Some(bank))
+    };
+    match in_bank().find(|(_, b)| offset >= b.offset && offset < b.offset + b.size) {
+        Some((i, b)) => match b.duplicate_of {
+            Some((k, pos)) if b.removed => new_start(k) + pos + (offset - b.offset),
+            _ => new_start(i) + (offset - b.offset),
+        },
+        None => {
+            // Past the last block: keep the distance to the bank end.
+            let old_end: usize = in_bank().map(|(_, b)| b.size).sum();
+            new_start(blocks.len()) + offset.saturating_sub(old_end)
+        }
+    }
+}
+
+fn find_subslice(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.len() > haystack.len() {
+        return None;
+    }
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
@@ -0,0 +1,392 @@
+//! Clock correction with pitch preservation.
+use crate::chip::Chip;
+use crate::chip::fnumber::{
+    ChipTypeConfig, ChipTypeSpec, FNumberError, Opl2Spec, Opl3Spec, OpllSpec, OpnSpec, OpnaSpec,
+};
+use crate::vgm::command::{Instance, VgmCommand};
+use crate::vgm::{VgmDocument, VgmHeader};
+
+use super::rewrite_chip_writes;
+
+/// Summary of the changes made by `correct_clock`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ClockCorrectionReport {
+    /// Header clock of the chip before the transform (0 when absent).
+    pub old_clock: u32,
+    /// Header clock of the chip after the transform.
+    pub new_clock: u32,
+    /// `false` when the frequency registers of the chip are not rewritten,
+    /// so only the header clock changed and the pitch moves with it.
+    pub pitch_preserved: bool,
+    /// Number of frequency register writes that were rewritten.
+    pub rewritten_writes: usize,
+    /// Number of frequency updates that did not fit the register range at
+    /// the new clock and were clamped.
+    pub clamped: usize,
+}
+
+/// Change the header clock of `chip` to `clock_hz` and rewrite its frequency
+/// registers so every note keeps the pitch it had at the old clock.
+///
+/// Supported chips and registers:
+///
+/// - SN76489: tone periods (`Sn76489Write`; Game Gear stereo writes are kept).
+/// - AY-3-8910 and the SSG part of YM2203, YM2608 and YM2610(B): tone periods
+///   (registers `0x00..=0x05`).
+/// - YM2612, YM2203, YM2608, YM2610(B): F-number and block
+///   (`0xA0..=0xA6`, `0xA8..=0xAE`). The high byte is only latched by the
+///   chip, so it is emitted together with the low byte.
+/// - YM3812, YM3526, Y8950, YMF262: F-number and block (`0xA0..=0xB8`).
+/// - YM2413: F-number and block (`0x10..=0x28`).
+///
+/// The F-number conversion uses the chip's `fnumber::ChipTypeSpec` math and
+/// moves to a higher block when the F-number would overflow. For other chips
+/// only the header clock is changed and `ClockCorrectionReport::pitch_preserved`
+/// is `false`. When `chip` has no clock in the header or `clock_hz` is `0`,
+/// the document is returned unchanged.
+pub fn correct_clock(
+    document: &VgmDocument,
+    chip: Chip,
+    clock_hz: u32,
+) -> (VgmDocument, ClockCorrectionReport) {
+    let mut report = ClockCorrectionReport::default();
+    let mut header = document.header.clone();
+    let Some(old_clock) = header.chip_instances_mut().clock(&chip) else {
+        return (document.clone(), report);
+    };
+    let new_clock = clock_hz & 0x7FFF_FFFF;
+    report.old_clock = old_clock;
+    report.new_clock = old_clock;
+    if new_clock == 0 {
+        return (document.clone(), report);
+    }
+    report.new_clock = new_clock;
+    header
+        .chip_instances_mut()
+        .set_clock(chip.clone(), new_clock);
+
+    let Some(family) = Family::of(&chip) else {
+        let mut document = document.clone();
+        document.header = header;
+        return (document, report);
+    };
+    report.pitch_preserved = true;
+
+    let mut converters = [
+        Converter::new(family, old_clock, new_clock),
+        Converter::new(family, old_clock, new_clock),
+    ];
+    let document = rewrite_chip_writes(document, header, |command, write| {
+        if write.chip != chip || matches!(command, VgmCommand::GameGearPsgWrite(..)) {
+            return None;
+        }
+        let converter = &mut converters[usize::from(write.instance)];
+        let writes = converter.write(write.port, write.register as u8, write.value as u8)?;
+        report.rewritten_writes += 1;
+        Some(
+            writes
+                .into_iter()
+                .map(|(port, register, value)| (port, register as u32, value))
+                .collect(),
+        )
+    });
+    report.clamped = converters.iter().map(|c| c.clamped).sum();
+    (document, report)
+}
+
+/// Summary of the changes made by `match_clocks`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ClockMatchReport {
+    /// One `correct_clock` report per chip whose clock was changed, in
+    /// header order.
+    pub corrections: Vec<(Chip, ClockCorrectionReport)>,
+}
+
+impl ClockMatchReport {
+    /// `true` when every corrected chip kept its pitch.
+    pub fn pitch_preserved(&self) -> bool {
+        self.corrections
+            .iter()
+            .all(|(_, report)| report.pitch_preserved)
+    }
+}
+
+/// Move every chip that `document` shares with `reference` to the clock it
+/// has in `reference`, rewriting its frequency registers with
+/// `correct_clock`.
+///
+/// Run this before merging two documents that use the same chip at
+/// different clocks: the merged header can hold only one clock per chip, and
+/// without the rewrite the notes of `document` would play detuned. Chips
+/// that only one side uses, or that already run at the same clock, are left
+/// unchanged. Chips `correct_clock` cannot rewrite still get the new clock
+/// and are reported with `pitch_preserved` set to `false`.
+pub fn match_clocks(
+    document: &VgmDocument,
+    reference: &VgmHeader,
+) -> (VgmDocument, ClockMatchReport) {
+    let mut report = ClockMatchReport::default();
+    let mut document = document.clone();
+    let chips: Vec<Chip> = document
+        .header
+        .chip_instances()
+        .iter()
+        .filter(|(instance, _, _)| *instance == Instance::Primary)
+        .map(|(_, chip, _)| chip.clone())
+        .collect();
+    for chip in chips {
+        let clock = reference.get_chip_clock(&chip) & 0x7FFF_FFFF;
+        let old_clock = document.header.get_chip_clock(&chip) & 0x7FFF_FFFF;
+        if clock == 0 || clock == old_clock {
+            continue;
+        }
+        let (corrected, correction) = correct_clock(&document, chip.clone(), clock);
+        document = corrected;
+        report.corrections.push((chip, correction));
+    }
+    (document, report)
+}
+
+// Pitch math of a `ChipTypeSpec`, usable without generics.
+#[derive(Clone, Copy)]
+struct FnumMath {
+    config: ChipTypeConfig,
+    to_freq: fn(u32, u8, f32) -> Result<f32, FNumberError>,
+    ideal_fnum: fn(f32, u8, f32) -> f32,
+}
+
+impl FnumMath {
+    fn of<C: ChipTypeSpec>() -> Self {
+        FnumMath {
+            config: C::config(),
+            to_freq: C::fnum_block_to_freq,
+            ideal_fnum: C::ideal_fnum_for_freq,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Family {
+    // SN76489: 10-bit tone periods behind a latch/data byte protocol.
+    Psg,
+    // AY-3-8910: 12-bit tone periods in registers 0x00-0x05.
+    Ssg,
+    // OPN family: F-number low at 0xA0/0xA8, latched high byte (block and
+    // F-number bits 8-10) at 0xA4/0xAC, optionally with an SSG part.
+    Opn { math: FnumMath, ssg: bool },
+    // OPL family: F-number low at 0xA0, key-on/block/F-number high at 0xB0.
+    Opl { math: FnumMath },
+    // OPLL: F-number low at 0x10, sustain/key/block/F-number bit 8 at 0x20.
+    Opll { math: FnumMath },
+}
+
+impl Family {
+    fn of(chip: &Chip) -> Option<Self> {
+        Some(match chip {
+            Chip::Sn76489 => Family::Psg,
+            Chip::Ay8910 => Family::Ssg,
+            Chip::Ym2203 => Family::Opn {
+                math: FnumMath::of::<OpnSpec>(),
+                ssg: true,
+            },
+            Chip::Ym2608 | Chip::Ym2610b => Family::Opn {
+                math: FnumMath::of::<OpnaSpec>(),
+                ssg: true,
+            },
+            Chip::Ym2612 => Family::Opn {
+                math: FnumMath::of::<OpnaSpec>(),
+                ssg: false,
+            },
+            Chip::Ym3812 | Chip::Ym3526 | Chip::Y8950 => Family::Opl {
+                math: FnumMath::of::<Opl2Spec>(),
+            },
+            Chip::Ymf262 => Family::Opl {
+                math: FnumMath::of::<Opl3Spec>(),
+            },
+            Chip::Ym2413 => Family::Opll {
+                math: FnumMath::of::<OpllSpec>(),
+            },
+            _ => return None,
+        })
+    }
+}
+
+// Register state of one chip instance. `regs` holds the values as written
+// by the source, `out` the values emitted after conversion.
+struct Converter {
+    family: Family,
+    old_clock: f32,
+    new_clock: f32,
+    regs: [[u8; 0x100]; 2],
+    out: [[u8; 0x100]; 2],
+    psg_latch: u8,
+    psg_tone: [u16; 3],
+    psg_out: [u16; 3],
+    clamped: usize,
+}
+
+type Writes = Vec<(u8, u8, u8)>;
+
+impl Converter {
+    fn new(family: Family, old_clock: u32, new_clock: u32) -> Self {
+        Converter {
+            family,
+            old_clock: old_clock as f32,
+            new_clock: new_clock as f32,
+            regs: [[0; 0x100]; 2],
+            out: [[0; 0x100]; 2],
+            psg_latch: 0,
+            psg_tone: [0; 3],
+            psg_out: [0; 3],
+            clamped: 0,
+        }
+    }
+
+    // Replacement writes `(port, register, value)` for a write, or `None`
+    // when it does not touch a frequency register.
+    fn write(&mut self, port: u8, register: u8, value: u8) -> Option<Writes> {
+        let port = port as usize;
+        if port > 1 {
+            return None;
+        }
+        match self.family {
+            Family::Psg => self.psg_write(value),
+            Family::Ssg => self.ssg_write(register, value),
+            Family::Opn { ssg, .. } if ssg && port == 0 && register <= 0x05 => {
+                self.ssg_write(register, value)
+            }
+            Family::Opn { math, .. } => match register {
+                0xA4..=0xA6 | 0xAC..=0xAE => {
+                    // Only latched; emitted with the next low byte write.
+                    self.regs[port][register as usize] = value;
+                    Some(Vec::new())
+                }
+                0xA0..=0xA2 | 0xA8..=0xAA => {
+                    self.regs[port][register as usize] = value;
+                    let high = register + 4;
+                    let latch = self.regs[port][high as usize];
+                    let fnum = ((latch as u32 & 0x07) << 8) | value as u32;
+                    let (fnum, block) = self.convert_fnum(&math, fnum, (latch >> 3) & 0x07);
+                    let latch = (latch & 0xC0) | (block << 3) | (fnum >> 8) as u8;
+                    Some(vec![
+                        (port as u8, high, latch),
+                        (port as u8, register, fnum as u8),
+                    ])
+                }
+                _ => None,
+            },
+            Family::Opl { math } => match register {
+                0xA0..=0xA8 | 0xB0..=0xB8 => {
+                    self.regs[port][register as usize] = value;
+                    let (low, high) = (register & 0x0F | 0xA0, register & 0x0F | 0xB0);
+                    let latch = self.regs[port][high as usize];
+                    let fnum = ((latch as u32 & 0x03) << 8) | self.regs[port][low as usize] as u32;
+                    let (fnum, block) = self.convert_fnum(&math, fnum, (latch >> 2) & 0x07);
+                    let latch = (latch & 0xE0) | (block << 2) | (fnum >> 8) as u8;
+                    Some(self.emit_pair(port, register, [(low, fnum as u8), (high, latch)]))
+                }
+                _ => None,
+            },
+            Family::Opll { math } => match register {
+                0x10..=0x18 | 0x20..=0x28 => {
+                    self.regs[0][register as usize] = value;
+                    let (low, high) = (register & 0x0F | 0x10, register & 0x0F | 0x20);
+                    let latch = self.regs[0][high as usize];
+                    let fnum = ((latch as u32 & 0x01) << 8) | self.regs[0][low as usize] as u32;
+                    let (fnum, block) = self.convert_fnum(&math, fnum, (latch >> 1) & 0x07);
+                    let latch = (latch & 0xF0) | (block << 1) | (fnum >> 8) as u8;
+                    Some(self.emit_pair(0, register, [(low, fnum as u8), (high, latch)]))
+                }
+                _ => None,
+            },
+        }
+    }
+
+    fn ssg_write(&mut self, register: u8, value: u8) -> Option<Writes> {
+        if register > 0x05 {
+            return None;
+        }
+        self.regs[0][register as usize] = value;
+        let (fine, coarse) = (register & !1, register | 1);
+        let period = ((self.regs[0][coarse as usize] as u32 & 0x0F) << 8)
+            | self.regs[0][fine as usize] as u32;
+        let period = self.convert_period(period, 0xFFF);
+        let coarse_value = (self.regs[0][coarse as usize] & 0xF0) | (period >> 8) as u8;
+        Some(self.emit_pair(0, register, [(fine, period as u8), (coarse, coarse_value)]))
+    }
+
+    fn psg_write(&mut self, value: u8) -> Option<Writes> {
+        if value & 0x80 != 0 {
+            self.psg_latch = (value >> 4) & 0x07;
+        }
+        let channel = (self.psg_latch >> 1) as usize;
+        if self.psg_latch & 0x01 != 0 || channel >= 3 {
+            return None;
+        }
+        let tone = &mut self.psg_tone[channel];
+        *tone = if value & 0x80 != 0 {
+            (*tone & 0x3F0) | (value as u16 & 0x0F)
+        } else {
+            (*tone & 0x00F) | ((value as u16 & 0x3F) << 4)
+        };
+        let period = self.convert_period(self.psg_tone[channel] as u32, 0x3FF) as u16;
+        let previous = std::mem::replace(&mut self.psg_out[channel], period);
+        let latch = 0x80 | (channel as u8) << 5 | (period & 0x0F) as u8;
+        let data = (period >> 4) as u8;
+        // Emit the written byte and the other one only when it changed.
+        let mut writes = Vec::with_capacity(2);
+        if value & 0x80 != 0 || previous & 0x0F != period & 0x0F {
+            writes.push((0, 0, latch));
+        }
+        if value & 0x80 == 0 || previous >> 4 != period >> 4 {
+            writes.push((0, 0, data));
+        }
+        Some(writes)
+    }
+
+    // The written register is always emitted, its partner only when its
+    // converted value changed.
+    fn emit_pair(&mut self, port: usize, written: u8, pair: [(u8, u8); 2]) -> Writes {
+        let mut writes = Vec::with_capacity(2);
+        for (register, value) in pair {
+            let out = &mut self.out[port][register as usize];
+            if register == written || *out != value {
+                writes.push((port as u8, register, value));
+            }
+            *out = value;
+        }
+        writes
+    }
+
+    fn convert_period(&mut self, period: u32, max: u32) -> u32 {
+        if period == 0 {
+            return 0;
+        }
+        let converted = (period as f64 * self.new_clock as f64 / self.old_clock as f64).round();
+        if converted > max as f64 {
+            self.clamped += 1;
+        }
+        (converted as u32).clamp(1, max)
+    }
+
+    fn convert_fnum(&mut self, math: &FnumMath, fnum: u32, block: u8) -> (u32, u8) {
+        let Ok(freq) = (math.to_freq)(fnum, block, self.old_clock) else {
+            return (fnum, block);
+        };
+        if fnum == 0 {
+            return (0, block);
+        }
+        let max_fnum = (1u32 << math.config.fnum_bits) - 1;
+        let max_block = (1u8 << math.config.block_bits) - 1;
+        let mut block = block;
+        let mut ideal = (math.ideal_fnum)(freq, block, self.new_clock).round();
+        while ideal > max_fnum as f32 && block < max_block {
+            block += 1;
+            ideal = (math.ideal_fnum)(freq, block, self.new_clock).round();
+        }
+        if ideal > max_fnum as f32 {
+            self.clamped += 1;
+        }
+        ((ideal as u32).min(max_fnum), block)
+    }
+}
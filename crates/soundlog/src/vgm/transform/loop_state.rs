@@ -0,0 +1,174 @@
+//! Loop point state patching.
+use std::collections::BTreeMap;
+
+use crate::chip::Chip;
+use crate::vgm::command::{LengthMode, StartStream, StopStream, VgmCommand};
+use crate::vgm::loop_check::{LoopIssue, StreamSnapshot, loop_states};
+use crate::vgm::{VgmBuilder, VgmDocument};
+
+use super::with_write;
+
+/// Result of `patch_loop_state`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LoopPatchReport {
+    /// Commands inserted at the loop point.
+    pub inserted_commands: usize,
+    /// Issues of `loop_check::check_loop` fixed by the inserted commands.
+    pub resolved: Vec<LoopIssue>,
+    /// Issues left as they are: notes the intro keys on across the loop
+    /// point (keying them on again would restart them on the first pass),
+    /// looped DAC streams and streams whose bank or frequency changed.
+    pub unresolved: Vec<LoopIssue>,
+}
+
+/// Insert writes at the loop point of `document` that restore the chip state
+/// of the first pass after the jump back from the end.
+///
+/// The inserted commands restore FM voice registers, key off hanging notes
+/// and restart or stop DAC streams at their first pass position. On the first
+/// pass they write what the chips already hold, so the intro is unchanged.
+/// Documents without a loop point are returned unchanged.
+pub fn patch_loop_state(document: &VgmDocument) -> (VgmDocument, LoopPatchReport) {
+    let mut report = LoopPatchReport::default();
+    let Some(states) = loop_states(document) else {
+        return (document.clone(), report);
+    };
+
+    // Registers after the jump, updated as writes are inserted so that key
+    // offs sharing a register combine.
+    let mut registers = states.after_jump.registers.clone();
+    let mut inserted = Vec::new();
+    for issue in states.issues() {
+        let commands = match &issue {
+            LoopIssue::PatchMismatch {
+                chip,
+                instance,
+                port,
+                register,
+                at_loop_start,
+                ..
+            } => {
+                let index = states.chip_index(chip, *instance);
+                registers[index].insert((*port, *register), *at_loop_start);
+                let template = &states.chips[index].template;
+                vec![with_write(
+                    template,
+                    *port,
+                    *register as u32,
+                    *at_loop_start,
+                )]
+            }
+            LoopIssue::HangingNote {
+                chip,
+                instance,
+                channel,
+            } => {
+                let index = states.chip_index(chip, *instance);
+                let template = &states.chips[index].template;
+                key_off(chip, *channel, &mut registers[index])
+                    .map(|(port, register, value)| {
+                        with_write(template, port, register as u32, value)
+                    })
+                    .into_iter()
+                    .collect()
+            }
+            LoopIssue::MissingNote { .. } => Vec::new(),
+            LoopIssue::StreamMismatch { stream_id, .. } => {
+                let start = states.at_loop_start.streams.get(stream_id);
+                let after = states.after_jump.streams.get(stream_id);
+                restart_stream(*stream_id, start, after)
+            }
+        };
+        if commands.is_empty() {
+            report.unresolved.push(issue);
+        } else {
+            report.inserted_commands += commands.len();
+            inserted.extend(commands);
+            report.resolved.push(issue);
+        }
+    }
+    if inserted.is_empty() {
+        return (document.clone(), report);
+    }
+
+    let mut commands = document.commands.clone();
+    commands.splice(states.loop_index..states.loop_index, inserted);
+    let mut builder = VgmBuilder::from(VgmDocument {
+        header: document.header.clone(),
+        extra_header: document.extra_header.clone(),
+        commands,
+        gd3: document.gd3.clone(),
+    });
+    builder.set_loop_index(states.loop_index);
+    (builder.finalize(), report)
+}
+
+// `(port, register, value)` keying off `channel`; SSG channels are silenced
+// through the mixer register.
+fn key_off(
+    chip: &Chip,
+    channel: u8,
+    registers: &mut BTreeMap<(u8, u8), u8>,
+) -> Option<(u8, u8, u8)> {
+    let mut update = |port: u8, register: u8, set: u8, clear: u8| {
+        let value = registers.entry((port, register)).or_insert(0);
+        *value = (*value | set) & !clear;
+        Some((port, register, *value))
+    };
+    match chip {
+        Chip::Sn76489 => Some((0, 0, 0x9F | (channel << 5))),
+        Chip::Ay8910 => update(0, 0x07, 1 << channel, 0),
+        Chip::Ym2203 if channel >= 3 => update(0, 0x07, 1 << (channel - 3), 0),
+        Chip::Ym2608 | Chip::Ym2610b if channel >= 6 => update(0, 0x07, 1 << (channel - 6), 0),
+        Chip::Ym2612 | Chip::Ym2203 | Chip::Ym2608 | Chip::Ym2610b => {
+            Some((0, 0x28, channel + channel / 3))
+        }
+        Chip::Ym2151 => Some((0, 0x08, channel)),
+        Chip::Ym3812 | Chip::Ym3526 | Chip::Y8950 => update(0, 0xB0 + channel, 0, 0x20),
+        Chip::Ymf262 => update(channel / 9, 0xB0 + channel % 9, 0, 0x20),
+        Chip::Ym2413 => update(0, 0x20 + channel, 0, 0x10),
+        _ => None,
+    }
+}
+
+// Commands putting the stream back to `start` when it is in state `after`.
+fn restart_stream(
+    stream_id: u8,
+    start: Option<&StreamSnapshot>,
+    after: Option<&StreamSnapshot>,
+) -> Vec<VgmCommand> {
+    let Some(position) = start.and_then(|start| start.position) else {
+        return vec![VgmCommand::StopStream(StopStream { stream_id })];
+    };
+    let (Some(start), Some(after)) = (start, after) else {
+        return Vec::new();
+    };
+    // Restarting a looped stream would move its loop start.
+    let same_setup = (start.bank, start.frequency, start.step_size)
+        == (after.bank, after.frequency, after.step_size);
+    if start.looped || !same_setup {
+        return Vec::new();
+    }
+    let (length_mode, data_length) = match start.remaining {
+        Some(remaining) => (
+            LengthMode::CommandCount {
+                reverse: start.reverse,
+                looped: false,
+            },
+            (remaining / start.step_size.max(1) as u64) as u32,
+        ),
+        None => (
+            LengthMode::PlayUntilEnd {
+                reverse: start.reverse,
+                looped: false,
+            },
+            0,
+        ),
+    };
+    vec![VgmCommand::StartStream(StartStream {
+        stream_id,
+        data_start_offset: position as i32,
+        length_mode,
+        data_length,
+    })]
+}
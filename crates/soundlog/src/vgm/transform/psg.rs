@@ -0,0 +1,379 @@
+//! Porting between the SN76489 and AY-3-8910 PSG families.
+use crate::chip::{Ay8910Spec, Chip, PsgSpec};
+use crate::vgm::VgmDocument;
+use crate::vgm::command::VgmCommand;
+use crate::vgm::header::{Ay8910ChipType, Sn76489Feedback, Sn76489ShiftRegisterWidth};
+use crate::vgm::opcode::chip_min_version;
+
+use super::rewrite_commands;
+
+// AY-3-8910 registers written by the SN76489 port: tone periods, noise
+// period, mixer and the three amplitudes.
+const AY_REGISTERS: usize = 11;
+const AY_MIXER: usize = 7;
+
+/// Summary of the changes made by `port_psg`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PsgPortReport {
+    /// Chip the song was ported from, or `None` when the document was
+    /// returned unchanged.
+    pub source: Option<Chip>,
+    /// Number of writes of the source chip that were ported.
+    pub ported_writes: usize,
+    /// Number of tone and noise periods that did not fit the range of the
+    /// target and were clamped.
+    pub clamped: usize,
+    /// Number of SN76489 noise settings selecting periodic noise, which the
+    /// AY-3-8910 plays as white noise.
+    pub periodic_noise: usize,
+    /// Number of AY-3-8910 envelope writes and amplitudes in envelope mode.
+    /// The SN76489 has no envelope generator and plays those channels at
+    /// full volume.
+    pub envelope_writes: usize,
+    /// Number of writes without a counterpart on the target (Game Gear
+    /// stereo, AY-3-8910 I/O ports), which are dropped.
+    pub dropped_writes: usize,
+}
+
+/// Port the PSG part of `document` between the SN76489 and the AY-3-8910,
+/// playing it on `target` clocked at `clock_hz`.
+///
+/// `target` selects the direction: `Chip::Ay8910` ports SN76489 writes (a
+/// Master System or Game Gear track, say) to the AY-3-8910 of an MSX or an
+/// Atari ST, `Chip::Sn76489` does the opposite. Both instances are ported
+/// and the source chip is replaced by the target in the header.
+///
+/// - Tone periods are converted for the clocks of both chips, so notes keep
+///   their pitch.
+/// - Volumes follow the dB curves of the chips: SN76489 attenuation has
+///   2 dB steps, AY-3-8910 amplitude roughly 3 dB steps.
+/// - SN76489 noise plays on AY channel C, mixed with its tone. Channel C
+///   takes the louder of the two levels. Periodic noise is played as white
+///   noise.
+/// - AY noise plays on the SN76489 noise channel at the level of the
+///   loudest channel mixing it, using the nearest of the three fixed noise
+///   rates. Envelopes cannot be ported; channels in envelope mode play at
+///   full volume.
+///
+/// Approximations and dropped writes are counted in `PsgPortReport`. When
+/// `target` is not one of the two chips, the document has no source chip,
+/// already uses the target or `clock_hz` is `0`, the document is returned
+/// unchanged.
+pub fn port_psg(
+    document: &VgmDocument,
+    target: Chip,
+    clock_hz: u32,
+) -> (VgmDocument, PsgPortReport) {
+    let mut report = PsgPortReport::default();
+    let source = match target {
+        Chip::Ay8910 => Chip::Sn76489,
+        Chip::Sn76489 => Chip::Ay8910,
+        _ => return (document.clone(), report),
+    };
+    let clock = clock_hz & 0x7FFF_FFFF;
+    let mut header = document.header.clone();
+    let mut chips = header.chip_instances_mut();
+    let Some(source_clock) = chips.clock(&source) else {
+        return (document.clone(), report);
+    };
+    if clock == 0 || chips.clock(&target).is_some() {
+        return (document.clone(), report);
+    }
+    let secondary = chips.has_secondary(&source);
+    chips.remove_chip(&source).set_clock(target.clone(), clock);
+    if secondary {
+        chips.enable_secondary(&target);
+    }
+    let version = header.version.max(chip_min_version(&target));
+    if version != header.version {
+        header.version = version;
+        header.data_offset = 0;
+    }
+    // ratio of the target clock to the source clock
+    let ratio = clock as f64 / source_clock as f64;
+    let to_ay = target == Chip::Ay8910;
+    if to_ay {
+        header.ay_chip_type = Ay8910ChipType::Ay8910;
+    } else {
+        header.sn76489_feedback = Sn76489Feedback::SegaVdp;
+        header.sn76489_shift_register_width = Sn76489ShiftRegisterWidth::SegaVdp;
+        header.sn76489_flags.frequency_0_is_400 = false;
+    }
+    let mut sn_to_ay = [SnToAy::new(ratio), SnToAy::new(ratio)];
+    let mut ay_to_sn = [AyToSn::new(ratio), AyToSn::new(ratio)];
+
+    let document = rewrite_commands(document, header, |command| {
+        let writes = match command {
+            VgmCommand::Sn76489Write(i, spec) if to_ay => sn_to_ay[usize::from(*i)]
+                .write(spec.value)
+                .into_iter()
+                .map(|(register, value)| {
+                    VgmCommand::Ay8910Write(*i, Ay8910Spec { register, value })
+                })
+                .collect(),
+            VgmCommand::Ay8910Write(i, spec) if !to_ay => {
+                let porter = &mut ay_to_sn[usize::from(*i)];
+                match porter.write(spec.register, spec.value, &mut report) {
+                    Some(writes) => writes
+                        .into_iter()
+                        .map(|value| VgmCommand::Sn76489Write(*i, PsgSpec { value }))
+                        .collect(),
+                    None => {
+                        report.dropped_writes += 1;
+                        return Some(Vec::new());
+                    }
+                }
+            }
+            VgmCommand::GameGearPsgWrite(..) if to_ay => {
+                report.dropped_writes += 1;
+                return Some(Vec::new());
+            }
+            VgmCommand::AY8910StereoMask(..) if !to_ay => {
+                report.dropped_writes += 1;
+                return Some(Vec::new());
+            }
+            _ => return None,
+        };
+        report.ported_writes += 1;
+        Some(writes)
+    });
+    for porter in &sn_to_ay {
+        report.clamped += porter.clamped;
+        report.periodic_noise += porter.periodic_noise;
+    }
+    report.clamped += ay_to_sn.iter().map(|porter| porter.clamped).sum::<usize>();
+    report.source = Some(source);
+    (document, report)
+}
+
+// Convert a tone or noise period from the source to the target clock.
+// `scale` is the ratio of the target period unit to the source one, so
+// a period of `0` stays `0`.
+fn convert_period(period: u32, scale: f64, max: u32, clamped: &mut usize) -> u32 {
+    if period == 0 {
+        return 0;
+    }
+    let converted = (period as f64 * scale).round();
+    if converted > max as f64 {
+        *clamped += 1;
+    }
+    (converted as u32).clamp(1, max)
+}
+
+// SN76489 attenuation (2 dB steps, 15 off) to AY amplitude (3 dB steps,
+// 0 off).
+fn attenuation_to_amplitude(attenuation: u8) -> u8 {
+    match attenuation & 0x0F {
+        0x0F => 0,
+        attenuation => 15 - ((attenuation as u32 * 2 + 1) / 3) as u8,
+    }
+}
+
+// AY amplitude to SN76489 attenuation.
+fn amplitude_to_attenuation(amplitude: u8) -> u8 {
+    match amplitude & 0x0F {
+        0 => 0x0F,
+        amplitude => ((15 - amplitude) as u32 * 3).div_ceil(2).min(14) as u8,
+    }
+}
+
+// SN76489 writes of one instance replayed as AY-3-8910 registers.
+struct SnToAy {
+    // AY clock / SN76489 clock. A tone or noise period of the SN76489
+    // counts twice as many clocks as one of the AY.
+    ratio: f64,
+    latch: u8,
+    tone: [u16; 3],
+    attenuation: [u8; 4],
+    noise: u8,
+    regs: [u8; AY_REGISTERS],
+    out: [Option<u8>; AY_REGISTERS],
+    clamped: usize,
+    periodic_noise: usize,
+}
+
+impl SnToAy {
+    fn new(ratio: f64) -> Self {
+        let mut porter = SnToAy {
+            ratio,
+            latch: 0,
+            tone: [0; 3],
+            attenuation: [0x0F; 4],
+            noise: 0x04,
+            regs: [0; AY_REGISTERS],
+            out: [None; AY_REGISTERS],
+            clamped: 0,
+            periodic_noise: 0,
+        };
+        porter.update_noise();
+        porter
+    }
+
+    // AY `(register, value)` writes for an SN76489 byte. Only registers
+    // whose value changed are written.
+    fn write(&mut self, value: u8) -> Vec<(u8, u8)> {
+        if value & 0x80 != 0 {
+            self.latch = (value >> 4) & 0x07;
+        }
+        let channel = (self.latch >> 1) as usize;
+        if self.latch & 0x01 != 0 {
+            self.attenuation[channel] = value & 0x0F;
+        } else if channel < 3 {
+            let tone = &mut self.tone[channel];
+            *tone = if value & 0x80 != 0 {
+                (*tone & 0x3F0) | (value as u16 & 0x0F)
+            } else {
+                (*tone & 0x00F) | ((value as u16 & 0x3F) << 4)
+            };
+            let period = convert_period(
+                self.tone[channel] as u32,
+                2.0 * self.ratio,
+                0xFFF,
+                &mut self.clamped,
+            );
+            self.regs[channel * 2] = period as u8;
+            self.regs[channel * 2 + 1] = (period >> 8) as u8;
+            if channel == 2 && self.noise & 0x03 == 0x03 {
+                self.update_noise();
+            }
+        } else {
+            self.noise = value & 0x07;
+            if self.noise & 0x04 == 0 {
+                self.periodic_noise += 1;
+            }
+            self.update_noise();
+        }
+        self.update_mixer();
+
+        let mut writes = Vec::new();
+        for (register, value) in self.regs.iter().enumerate() {
+            if self.out[register] != Some(*value) {
+                self.out[register] = Some(*value);
+                writes.push((register as u8, *value));
+            }
+        }
+        writes
+    }
+
+    fn update_noise(&mut self) {
+        let counter = match self.noise & 0x03 {
+            0x03 => self.tone[2] as u32,
+            rate => 16 << rate,
+        };
+        let period = convert_period(counter, 2.0 * self.ratio, 0x1F, &mut self.clamped);
+        self.regs[6] = period.max(1) as u8;
+    }
+
+    // Tones play on channels A-C, noise on channel C. Channel C takes the
+    // louder of tone 2 and noise, and drops its tone when only the noise is
+    // audible.
+    fn update_mixer(&mut self) {
+        let [a, b, tone, noise] = self.attenuation.map(attenuation_to_amplitude);
+        let mut mixer = 0x38;
+        if noise != 0 {
+            mixer &= !0x20;
+            if tone == 0 {
+                mixer |= 0x04;
+            }
+        }
+        self.regs[AY_MIXER] = mixer;
+        self.regs[8] = a;
+        self.regs[9] = b;
+        self.regs[10] = tone.max(noise);
+    }
+}
+
+// AY-3-8910 writes of one instance replayed as SN76489 bytes.
+struct AyToSn {
+    // SN76489 clock / AY clock.
+    ratio: f64,
+    regs: [u8; 16],
+    tone: [u16; 3],
+    // latch/data bytes of the tone periods, attenuation bytes and the noise
+    // byte last written
+    out_tone: [Option<u16>; 3],
+    out: [Option<u8>; 5],
+    clamped: usize,
+}
+
+impl AyToSn {
+    fn new(ratio: f64) -> Self {
+        AyToSn {
+            ratio,
+            regs: [0; 16],
+            tone: [0; 3],
+            out_tone: [None; 3],
+            out: [None; 5],
+            clamped: 0,
+        }
+    }
+
+    // SN76489 bytes for an AY register write, or `None` for the I/O ports.
+    // Only settings that changed are written.
+    fn write(&mut self, register: u8, value: u8, report: &mut PsgPortReport) -> Option<Vec<u8>> {
+        match register {
+            0x00..=0x05 => {
+                self.regs[register as usize] = value;
+                let channel = (register / 2) as usize;
+                let period = ((self.regs[channel * 2 + 1] as u32 & 0x0F) << 8)
+                    | self.regs[channel * 2] as u32;
+                self.tone[channel] =
+                    convert_period(period, self.ratio / 2.0, 0x3FF, &mut self.clamped) as u16;
+            }
+            0x06 | 0x07 => self.regs[register as usize] = value,
+            0x08..=0x0A => {
+                if value & 0x10 != 0 {
+                    report.envelope_writes += 1;
+                }
+                self.regs[register as usize] = value;
+            }
+            0x0B..=0x0D => {
+                report.envelope_writes += 1;
+                return Some(Vec::new());
+            }
+            _ => return None,
+        }
+
+        let mixer = self.regs[7];
+        let amplitudes = [8, 9, 10].map(|register| match self.regs[register] {
+            level if level & 0x10 != 0 => 15,
+            level => level & 0x0F,
+        });
+        let mut writes = Vec::new();
+        let mut noise_level = 0;
+        for (channel, amplitude) in amplitudes.into_iter().enumerate() {
+            let period = self.tone[channel];
+            if self.out_tone[channel] != Some(period) {
+                self.out_tone[channel] = Some(period);
+                writes.push(0x80 | (channel as u8) << 5 | (period & 0x0F) as u8);
+                writes.push((period >> 4) as u8 & 0x3F);
+            }
+            let level = if mixer & (1 << channel) == 0 {
+                amplitude
+            } else {
+                0
+            };
+            if mixer & (8 << channel) == 0 {
+                noise_level = noise_level.max(amplitude);
+            }
+            self.push(
+                &mut writes,
+                channel,
+                0x90 | (channel as u8) << 5 | amplitude_to_attenuation(level),
+            );
+        }
+        // nearest of the noise rates 16, 32 and 64, white noise
+        let counter = ((self.regs[6] & 0x1F).max(1) as f64 * self.ratio / 2.0).log2();
+        let rate = (counter - 4.0).round().clamp(0.0, 2.0) as u8;
+        self.push(&mut writes, 4, 0xE4 | rate);
+        self.push(&mut writes, 3, 0xF0 | amplitude_to_attenuation(noise_level));
+        Some(writes)
+    }
+
+    fn push(&mut self, writes: &mut Vec<u8>, slot: usize, value: u8) {
+        if self.out[slot] != Some(value) {
+            self.out[slot] = Some(value);
+            writes.push(value);
+        }
+    }
+}
@@ -0,0 +1,462 @@
+//! Per-chip and per-channel volume scaling.
+use crate::chip::Chip;
+use crate::chip::patch::FM_CARRIERS;
+use crate::vgm::VgmDocument;
+use crate::vgm::command::VgmCommand;
+
+use super::{ChipWrites, rewrite_chip_writes};
+
+// Highest channel number of any supported chip plus one (YMF262).
+const MAX_CHANNELS: usize = 18;
+
+/// One volume change applied by `scale_volume`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VolumeAdjustment {
+    /// Chip whose output level changes. Both instances are affected.
+    pub chip: Chip,
+    /// Channel to change, or `None` for every channel of the chip.
+    pub channel: Option<u8>,
+    /// Level change in dB; negative values make the output quieter.
+    pub gain_db: f32,
+}
+
+/// Options for `scale_volume`.
+///
+/// Adjustments that match the same channel add up, so a chip-wide gain can be
+/// combined with corrections for single channels.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct VolumeOptions {
+    /// Volume changes to apply.
+    pub adjustments: Vec<VolumeAdjustment>,
+}
+
+impl VolumeOptions {
+    /// Create options without adjustments.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Change every channel of `chip` by `gain_db`.
+    pub fn with_chip(mut self, chip: Chip, gain_db: f32) -> Self {
+        self.adjustments.push(VolumeAdjustment {
+            chip,
+            channel: None,
+            gain_db,
+        });
+        self
+    }
+
+    /// Change one channel of `chip` by `gain_db`. See `scale_volume` for the
+    /// channel numbering.
+    pub fn with_channel(mut self, chip: Chip, channel: u8, gain_db: f32) -> Self {
+        self.adjustments.push(VolumeAdjustment {
+            chip,
+            channel: Some(channel),
+            gain_db,
+        });
+        self
+    }
+
+    // Total gain per channel of `chip`.
+    fn gains(&self, chip: &Chip) -> [f32; MAX_CHANNELS] {
+        let mut gains = [0.0; MAX_CHANNELS];
+        for adjustment in self.adjustments.iter().filter(|a| a.chip == *chip) {
+            match adjustment.channel {
+                Some(channel) => {
+                    if let Some(gain) = gains.get_mut(channel as usize) {
+                        *gain += adjustment.gain_db;
+                    }
+                }
+                None => gains.iter_mut().for_each(|g| *g += adjustment.gain_db),
+            }
+        }
+        gains
+    }
+}
+
+/// Summary of the changes made by `scale_volume`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct VolumeReport {
+    /// Number of level register writes whose value changed.
+    pub scaled_writes: usize,
+    /// Number of TL writes re-emitted because an algorithm change turned an
+    /// operator into a carrier or back into a modulator.
+    pub reemitted_writes: usize,
+    /// Number of levels that did not fit the register range and were clamped.
+    pub clipped: usize,
+    /// Adjusted chips whose levels cannot be scaled; they are left unchanged.
+    pub unsupported_chips: Vec<Chip>,
+}
+
+/// Scale the output level of chips or single channels in `document` by the
+/// dB amounts in `options`, rewriting the level registers.
+///
+/// Supported chips, their channel numbers and the registers that change:
+///
+/// | chip | channels | registers |
+/// |------|----------|-----------|
+/// | SN76489 | 0-2 tone, 3 noise | attenuation (2 dB steps) |
+/// | AY-3-8910 | 0-2 | amplitude `0x08..=0x0A` (3 dB steps) |
+/// | YM2612, YM2608, YM2610(B) | 0-5 FM, SSG 6-8 | carrier TL (0.75 dB steps), SSG amplitude |
+/// | YM2203 | 0-2 FM, SSG 3-5 | carrier TL, SSG amplitude |
+/// | YM2151 | 0-7 | carrier TL |
+/// | YM3812, YM3526, Y8950 | 0-8 | carrier TL |
+/// | YMF262 | 0-17 | carrier TL (2-operator connection) |
+/// | YM2413 | 0-8 | instrument volume (3 dB steps) |
+/// | SegaPCM | 0-15 | left/right volume (linear) |
+/// | RF5C68, RF5C164 | 0-7 | envelope (linear) |
+///
+/// Only carrier operators are changed, so the timbre of FM voices is kept.
+/// When an algorithm or connection write changes which operators are
+/// carriers, the affected TL values are written again. Silent levels (SN76489
+/// attenuation 15, amplitude 0) and AY envelope mode are left as they are.
+/// ADPCM and rhythm levels are not changed.
+pub fn scale_volume(
+    document: &VgmDocument,
+    options: &VolumeOptions,
+) -> (VgmDocument, VolumeReport) {
+    let mut report = VolumeReport::default();
+    let mut scalers: Vec<(Chip, [Scaler; 2])> = Vec::new();
+    for adjustment in &options.adjustments {
+        let chip = &adjustment.chip;
+        if scalers.iter().any(|(c, _)| c == chip) || report.unsupported_chips.contains(chip) {
+            continue;
+        }
+        let gains = options.gains(chip);
+        match Family::of(chip) {
+            Some(family) => scalers.push((
+                chip.clone(),
+                [Scaler::new(family, gains), Scaler::new(family, gains)],
+            )),
+            None => report.unsupported_chips.push(chip.clone()),
+        }
+    }
+
+    let document = rewrite_chip_writes(document, document.header.clone(), |command, write| {
+        if matches!(command, VgmCommand::GameGearPsgWrite(..)) {
+            return None;
+        }
+        let (_, scalers) = scalers.iter_mut().find(|(chip, _)| *chip == write.chip)?;
+        scalers[usize::from(write.instance)].write(write.port, write.register, write.value as u8)
+    });
+    for (_, scalers) in &scalers {
+        for scaler in scalers {
+            report.scaled_writes += scaler.scaled;
+            report.reemitted_writes += scaler.reemitted;
+            report.clipped += scaler.clipped;
+        }
+    }
+    (document, report)
+}
+
+#[derive(Clone, Copy)]
+enum Family {
+    // SN76489: 4-bit attenuation behind the latch/data byte protocol.
+    Psg,
+    // OPN FM with `fm_channels` channels (3 per port) and an optional SSG
+    // part numbered after the FM channels.
+    Opn { fm_channels: u8, ssg: bool },
+    // AY-3-8910: 4-bit amplitude with an envelope mode bit.
+    Ssg,
+    Opm,
+    Opl,
+    Opll,
+    SegaPcm,
+    Rf5c,
+}
+
+impl Family {
+    fn of(chip: &Chip) -> Option<Self> {
+        Some(match chip {
+            Chip::Sn76489 => Family::Psg,
+            Chip::Ay8910 => Family::Ssg,
+            Chip::Ym2203 => Family::Opn {
+                fm_channels: 3,
+                ssg: true,
+            },
+            Chip::Ym2608 | Chip::Ym2610b => Family::Opn {
+                fm_channels: 6,
+                ssg: true,
+            },
+            Chip::Ym2612 => Family::Opn {
+                fm_channels: 6,
+                ssg: false,
+            },
+            Chip::Ym2151 => Family::Opm,
+            Chip::Ym3812 | Chip::Ym3526 | Chip::Y8950 | Chip::Ymf262 => Family::Opl,
+            Chip::Ym2413 => Family::Opll,
+            Chip::SegaPcm => Family::SegaPcm,
+            Chip::Rf5c68 | Chip::Rf5c164 => Family::Rf5c,
+            _ => return None,
+        })
+    }
+}
+
+// Level state of one chip instance.
+struct Scaler {
+    family: Family,
+    gains: [f32; MAX_CHANNELS],
+    // Algorithm (OPN/OPM) or connection (OPL) per channel.
+    algorithm: [u8; MAX_CHANNELS],
+    // TL values as written by the source, per channel and register slot.
+    tl: [[Option<u8>; 4]; MAX_CHANNELS],
+    psg_latch: u8,
+    rf5c_channel: u8,
+    scaled: usize,
+    reemitted: usize,
+    clipped: usize,
+}
+
+impl Scaler {
+    fn new(family: Family, gains: [f32; MAX_CHANNELS]) -> Self {
+        Scaler {
+            family,
+            gains,
+            algorithm: [0; MAX_CHANNELS],
+            tl: [[None; 4]; MAX_CHANNELS],
+            psg_latch: 0,
+            rf5c_channel: 0,
+            scaled: 0,
+            reemitted: 0,
+            clipped: 0,
+        }
+    }
+
+    // Replacement writes for a write, or `None` to keep it unchanged.
+    fn write(&mut self, port: u8, register: u32, value: u8) -> Option<ChipWrites> {
+        let reg = register as u8;
+        let single = |value| Some(vec![(port, register, value)]);
+        match self.family {
+            Family::Psg => {
+                if value & 0x80 != 0 {
+                    self.psg_latch = (value >> 4) & 0x07;
+                }
+                if self.psg_latch & 0x01 == 0 || value & 0x0F == 0x0F {
+                    return None;
+                }
+                let channel = self.psg_latch >> 1;
+                let level = self.shift_level(value & 0x0F, 0x0E, channel, -2.0);
+                self.changed(value, (value & 0xF0) | level, single)
+            }
+            Family::Ssg => self.ssg_write(port, reg, value, 0),
+            Family::Opn { fm_channels, ssg } => {
+                if ssg && port == 0 && reg < 0x10 {
+                    return self.ssg_write(port, reg, value, fm_channels);
+                }
+                let (slot, channel) = ((reg >> 2) & 3, reg & 3);
+                if channel == 3 || port * 3 + channel >= fm_channels {
+                    return None;
+                }
+                let channel = port * 3 + channel;
+                match reg {
+                    0x40..=0x4F => self.tl_write(channel, slot, value, register),
+                    0xB0..=0xB2 => self.algorithm_write(channel, value & 0x07, register, value),
+                    _ => None,
+                }
+            }
+            Family::Opm => match reg {
+                0x60..=0x7F => self.tl_write(reg & 7, (reg >> 3) & 3, value, register),
+                0x20..=0x27 => self.algorithm_write(reg & 7, value & 0x07, register, value),
+                _ => None,
+            },
+            Family::Opl => match reg {
+                0x40..=0x55 => {
+                    let offset = reg - 0x40;
+                    let (group, index) = (offset / 8, offset % 8);
+                    if index > 5 {
+                        return None;
+                    }
+                    let channel = port * 9 + group * 3 + index % 3;
+                    self.tl_write(channel, index / 3, value, register)
+                }
+                0xC0..=0xC8 => {
+                    self.algorithm_write(port * 9 + reg - 0xC0, value & 0x01, register, value)
+                }
+                _ => None,
+            },
+            Family::Opll => match reg {
+                0x30..=0x38 => {
+                    let level = self.shift_level(value & 0x0F, 0x0F, reg - 0x30, -3.0);
+                    self.changed(value, (value & 0xF0) | level, single)
+                }
+                _ => None,
+            },
+            Family::SegaPcm => {
+                let (channel, index) = ((register >> 3) as u8, register & 7);
+                if register >= 0x80 || !(2..=3).contains(&index) {
+                    return None;
+                }
+                let level = self.scale_linear(value & 0x7F, 0x7F, channel);
+                self.changed(value, (value & 0x80) | level, single)
+            }
+            Family::Rf5c => {
+                if port != 0 {
+                    return None;
+                }
+                match reg {
+                    0x07 if value & 0x40 != 0 => {
+                        self.rf5c_channel = value & 0x07;
+                        None
+                    }
+                    0x00 => {
+                        let level = self.scale_linear(value, 0xFF, self.rf5c_channel);
+                        self.changed(value, level, single)
+                    }
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    fn ssg_write(&mut self, port: u8, register: u8, value: u8, first: u8) -> Option<ChipWrites> {
+        // Amplitude 0 is silent and bit 4 selects the envelope.
+        if !(0x08..=0x0A).contains(&register) || value & 0x10 != 0 || value & 0x0F == 0 {
+            return None;
+        }
+        let level = self.shift_level(value & 0x0F, 0x0F, first + register - 0x08, 3.0);
+        let level = level.max(1);
+        self.changed(value, (value & 0xF0) | level, |value| {
+            Some(vec![(port, register as u32, value)])
+        })
+    }
+
+    fn tl_write(&mut self, channel: u8, slot: u8, value: u8, register: u32) -> Option<ChipWrites> {
+        self.tl[channel as usize][slot as usize] = Some(value);
+        if !self.is_carrier(channel, slot) {
+            return None;
+        }
+        let scaled = self.scale_tl(channel, value);
+        self.changed(value, scaled, |value| Some(vec![(0, register, value)]))
+            .map(|writes| self.on_port(writes, channel))
+    }
+
+    fn algorithm_write(
+        &mut self,
+        channel: u8,
+        algorithm: u8,
+        register: u32,
+        value: u8,
+    ) -> Option<ChipWrites> {
+        let previous = std::mem::replace(&mut self.algorithm[channel as usize], algorithm);
+        if self.gains[channel as usize] == 0.0 {
+            return None;
+        }
+        let slots = match self.family {
+            Family::Opl => 2,
+            _ => 4,
+        };
+        // The algorithm write itself, followed by the TL values to correct.
+        let mut writes = vec![(0, register, value)];
+        for slot in 0..slots {
+            let Some(value) = self.tl[channel as usize][slot as usize] else {
+                continue;
+            };
+            let was_carrier = self.carriers(previous) & (1 << slot) != 0;
+            if was_carrier == self.is_carrier(channel, slot) {
+                continue;
+            }
+            let value = if was_carrier {
+                value
+            } else {
+                self.scale_tl(channel, value)
+            };
+            writes.push((0, self.tl_register(channel, slot), value));
+        }
+        if writes.len() == 1 {
+            return None;
+        }
+        self.reemitted += writes.len() - 1;
+        Some(self.on_port(writes, channel))
+    }
+
+    // Carrier mask of an algorithm (OPN/OPM) or connection (OPL) over the
+    // register slot order (operators 1, 3, 2 and 4 on OPN/OPM).
+    fn carriers(&self, algorithm: u8) -> u8 {
+        match self.family {
+            Family::Opl => 0b10 | algorithm,
+            _ => {
+                let operators = FM_CARRIERS[algorithm as usize];
+                [0, 2, 1, 3]
+                    .into_iter()
+                    .enumerate()
+                    .fold(0, |mask, (slot, operator)| {
+                        mask | ((operators >> operator) & 1) << slot
+                    })
+            }
+        }
+    }
+
+    fn is_carrier(&self, channel: u8, slot: u8) -> bool {
+        self.carriers(self.algorithm[channel as usize]) & (1 << slot) != 0
+    }
+
+    fn scale_tl(&mut self, channel: u8, value: u8) -> u8 {
+        let mask = match self.family {
+            // KSL in bits 6-7
+            Family::Opl => 0x3F,
+            _ => 0x7F,
+        };
+        (value & !mask) | self.shift_level(value & mask, mask, channel, -0.75)
+    }
+
+    // TL register of `slot` of `channel`, without the port.
+    fn tl_register(&self, channel: u8, slot: u8) -> u32 {
+        let register = match self.family {
+            Family::Opm => 0x60 + slot * 8 + channel,
+            Family::Opl => {
+                let channel = channel % 9;
+                0x40 + (channel / 3) * 8 + channel % 3 + slot * 3
+            }
+            _ => 0x40 + slot * 4 + channel % 3,
+        };
+        register as u32
+    }
+
+    // Move writes to the port of `channel`; the algorithm/TL registers of the
+    // 2-port chips are mirrored on port 1 for the upper channels.
+    fn on_port(&self, writes: ChipWrites, channel: u8) -> ChipWrites {
+        let port = match self.family {
+            Family::Opn { .. } => channel / 3,
+            Family::Opl => channel / 9,
+            _ => 0,
+        };
+        writes
+            .into_iter()
+            .map(|(_, register, value)| (port, register, value))
+            .collect()
+    }
+
+    // Replace `value` by `scaled` through `write` when they differ.
+    fn changed(
+        &mut self,
+        value: u8,
+        scaled: u8,
+        write: impl FnOnce(u8) -> Option<ChipWrites>,
+    ) -> Option<ChipWrites> {
+        if value == scaled {
+            return None;
+        }
+        self.scaled += 1;
+        write(scaled)
+    }
+
+    // Move a level by the gain of `channel` in steps of `step_db`. Positive
+    // steps raise an amplitude, negative ones lower an attenuation.
+    fn shift_level(&mut self, level: u8, max: u8, channel: u8, step_db: f32) -> u8 {
+        let gain = self.gains.get(channel as usize).copied().unwrap_or(0.0);
+        let shifted = level as i32 + (gain / step_db).round() as i32;
+        if shifted < 0 || shifted > max as i32 {
+            self.clipped += 1;
+        }
+        shifted.clamp(0, max as i32) as u8
+    }
+
+    fn scale_linear(&mut self, level: u8, max: u8, channel: u8) -> u8 {
+        let gain = self.gains.get(channel as usize).copied().unwrap_or(0.0);
+        let scaled = (level as f32 * 10f32.powf(gain / 20.0)).round();
+        if scaled > max as f32 {
+            self.clipped += 1;
+        }
+        scaled.min(max as f32) as u8
+    }
+}
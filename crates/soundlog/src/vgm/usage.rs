@@ -0,0 +1,244 @@
+//! Channel usage reports.
+//!
+//! `channel_usage` characterizes how a track uses every channel that has key
+//! state tracking (see `vgm::analysis`): how many notes it plays, their
+//! pitch range, which voices (FM patches, PSG duty and noise modes) are
+//! keyed on, and how much of the track the channel sounds.
+//!
+//! Voices are numbered per report in order of first use; the registers that
+//! make up a voice are listed in `ChannelUsageReport::voices`. A voice is
+//! taken from the chip registers at every key-on, so a patch change while a
+//! note sounds is counted with the next note.
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::chip::{Chip, Ym2413Spec};
+//! use soundlog::vgm::command::{Instance, WaitSamples};
+//! use soundlog::vgm::usage::channel_usage;
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.register_chip(Chip::Ym2413, Instance::Primary, 3_579_545);
+//! for (instrument, key) in [(0x10, 0x10), (0x10, 0x00), (0x30, 0x10)] {
+//!     builder.add_chip_write(Instance::Primary, Ym2413Spec { register: 0x30, value: instrument });
+//!     builder.add_chip_write(Instance::Primary, Ym2413Spec { register: 0x20, value: key | 0x08 });
+//!     builder.add_vgm_command(WaitSamples(100));
+//! }
+//! let doc = builder.finalize();
+//!
+//! let report = channel_usage(&doc);
+//! let channel = &report.channels[0];
+//! assert_eq!(channel.notes, 2);
+//! assert_eq!(channel.voices.len(), 2);
+//! assert_eq!(channel.active_samples, 200);
+//! ```
+use std::collections::BTreeMap;
+
+use crate::chip::Chip;
+use crate::chip::regmap::voice;
+use crate::vgm::VgmDocument;
+use crate::vgm::analysis::{Analysis, AnalysisContext, run_analyses};
+use crate::vgm::command::{Instance, VgmCommand};
+use crate::vgm::snapshot::ChipSnapshot;
+
+/// Usage of one channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelUsage {
+    pub chip: Chip,
+    pub instance: Instance,
+    /// Channel as numbered by the `chip::state` tracker of the chip.
+    pub channel: u8,
+    /// Number of key-ons.
+    pub notes: usize,
+    /// Lowest and highest key-on frequency in Hz, `None` when no key-on had
+    /// a known frequency.
+    pub pitch_range: Option<(f32, f32)>,
+    /// Key-ons per voice, by index into `ChannelUsageReport::voices`.
+    pub voices: BTreeMap<usize, usize>,
+    /// Samples during which the channel was keyed on.
+    pub active_samples: u64,
+    // Time of the key-on of the sounding note.
+    keyed_since: Option<u64>,
+}
+
+impl ChannelUsage {
+    /// Share of `samples` during which the channel was keyed on, in percent.
+    pub fn active_percent(&self, samples: u64) -> f64 {
+        if samples == 0 {
+            0.0
+        } else {
+            self.active_samples as f64 * 100.0 / samples as f64
+        }
+    }
+}
+
+/// A voice: the registers that define the sound of a channel at key-on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Voice {
+    pub chip: Chip,
+    pub instance: Instance,
+    /// Register values in a chip-specific order.
+    pub registers: Vec<u32>,
+}
+
+/// Usage of every channel keyed on at least once.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ChannelUsageReport {
+    /// Length of the document in samples.
+    pub samples: u64,
+    /// Channels in order of chip instance and channel.
+    pub channels: Vec<ChannelUsage>,
+    /// Every voice keyed on, in order of first use.
+    pub voices: Vec<Voice>,
+}
+
+/// Report the channel usage of `document`.
+pub fn channel_usage(document: &VgmDocument) -> ChannelUsageReport {
+    let mut analysis = ChannelUsageAnalysis::new();
+    run_analyses(document, &mut [&mut analysis]);
+    analysis.into_report()
+}
+
+/// `channel_usage` as an `Analysis`, to share a pass with other analyses.
+#[derive(Debug, Clone, Default)]
+pub struct ChannelUsageAnalysis {
+    report: ChannelUsageReport,
+    snapshots: Vec<ChipSnapshot>,
+}
+
+impl ChannelUsageAnalysis {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The report, complete once the analysis has run.
+    pub fn into_report(self) -> ChannelUsageReport {
+        self.report
+    }
+
+    fn usage(&mut self, chip: &Chip, instance: Instance, channel: u8) -> &mut ChannelUsage {
+        let channels = &mut self.report.channels;
+        let index = match channels
+            .iter()
+            .position(|c| c.chip == *chip && c.instance == instance && c.channel == channel)
+        {
+            Some(index) => index,
+            None => {
+                channels.push(ChannelUsage {
+                    chip: chip.clone(),
+                    instance,
+                    channel,
+                    notes: 0,
+                    pitch_range: None,
+                    voices: BTreeMap::new(),
+                    active_samples: 0,
+                    keyed_since: None,
+                });
+                channels.len() - 1
+            }
+        };
+        &mut channels[index]
+    }
+
+    fn voice_index(&mut self, chip: &Chip, instance: Instance, channel: u8) -> Option<usize> {
+        let snapshot = self
+            .snapshots
+            .iter()
+            .find(|s| s.chip == *chip && s.instance == instance)?;
+        let registers = voice(chip, &snapshot.registers, channel)?;
+        let voices = &mut self.report.voices;
+        Some(
+            match voices
+                .iter()
+                .position(|v| v.chip == *chip && v.instance == instance && v.registers == registers)
+            {
+                Some(index) => index,
+                None => {
+                    voices.push(Voice {
+                        chip: chip.clone(),
+                        instance,
+                        registers,
+                    });
+                    voices.len() - 1
+                }
+            },
+        )
+    }
+}
+
+impl Analysis for ChannelUsageAnalysis {
+    fn needs_keys(&self) -> bool {
+        true
+    }
+
+    fn command(&mut self, context: &AnalysisContext<'_>, _command: &VgmCommand) {
+        if let Some(write) = context.write() {
+            let index = match self
+                .snapshots
+                .iter()
+                .position(|s| s.chip == write.chip && s.instance == write.instance)
+            {
+                Some(index) => index,
+                None => {
+                    self.snapshots
+                        .push(ChipSnapshot::new(write.chip.clone(), write.instance));
+                    self.snapshots.len() - 1
+                }
+            };
+            self.snapshots[index].write(write.port, write.register, write.value);
+        }
+        let time = context.time();
+        for change in context.key_changes() {
+            let (chip, instance, channel) = (&change.chip, change.instance, change.channel);
+            if !change.on {
+                let usage = self.usage(chip, instance, channel);
+                if let Some(start) = usage.keyed_since.take() {
+                    usage.active_samples += time - start;
+                }
+                continue;
+            }
+            let voice = self.voice_index(chip, instance, channel);
+            let frequency = context
+                .tone(chip, instance, channel)
+                .and_then(|tone| tone.freq_hz)
+                .filter(|hz| hz.is_finite() && *hz > 0.0);
+            let usage = self.usage(chip, instance, channel);
+            usage.notes += 1;
+            usage.keyed_since = Some(time);
+            if let Some(voice) = voice {
+                *usage.voices.entry(voice).or_default() += 1;
+            }
+            if let Some(hz) = frequency {
+                usage.pitch_range = Some(match usage.pitch_range {
+                    Some((low, high)) => (low.min(hz), high.max(hz)),
+                    None => (hz, hz),
+                });
+            }
+        }
+    }
+
+    fn finish(&mut self, context: &AnalysisContext<'_>) {
+        let time = context.time();
+        self.report.samples = time;
+        for usage in &mut self.report.channels {
+            if let Some(start) = usage.keyed_since.take() {
+                usage.active_samples += time - start;
+            }
+        }
+        // Chip instances in order of first use, then channels in order.
+        let mut order: Vec<(Chip, Instance)> = Vec::new();
+        for usage in &self.report.channels {
+            if !order
+                .iter()
+                .any(|(chip, instance)| *chip == usage.chip && *instance == usage.instance)
+            {
+                order.push((usage.chip.clone(), usage.instance));
+            }
+        }
+        self.report.channels.sort_by_key(|usage| {
+            let chip = order
+                .iter()
+                .position(|(chip, instance)| *chip == usage.chip && *instance == usage.instance);
+            (chip, usage.channel)
+        });
+    }
+}
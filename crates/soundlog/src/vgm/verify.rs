@@ -0,0 +1,240 @@
+//! Invariant checks for code that rewrites command streams.
+//!
+//! Transforms that split, merge or re-encode waits (stream expansion,
+//! quantization with a grid of 1, redumps) must not change when anything is
+//! played. `verify_wait_conservation` checks that two documents have the same
+//! total length and, when they loop, the same intro and loop lengths.
+//!
+//! `verify_deterministic` checks that serializing a document is reproducible:
+//! the bytes are the same every time, and parsing and serializing them again
+//! gives the same bytes, so a rebuild in CI can be compared byte for byte.
+//!
+//! ```rust
+//! use soundlog::VgmBuilder;
+//! use soundlog::chip;
+//! use soundlog::vgm::command::{Instance, Wait735Samples, WaitSamples};
+//! use soundlog::vgm::verify::verify_wait_conservation;
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.register_chip(chip::Chip::Sn76489, Instance::Primary, 3_579_545);
+//! builder.add_vgm_command(Wait735Samples);
+//! let original = builder.finalize();
+//!
+//! let mut builder = VgmBuilder::new();
+//! builder.register_chip(chip::Chip::Sn76489, Instance::Primary, 3_579_545);
+//! builder.add_vgm_command(WaitSamples(700));
+//! builder.add_vgm_command(WaitSamples(35));
+//! let transformed = builder.finalize();
+//!
+//! let totals = verify_wait_conservation(&original, &transformed).unwrap();
+//! assert_eq!(totals.total, 735);
+//! ```
+use std::fmt;
+
+use crate::binutil::ParseError;
+use crate::vgm::VgmDocument;
+use crate::vgm::command::VgmCommand;
+
+/// Sample counts of a command stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WaitTotals {
+    /// Samples up to the first `EndOfData` (or the end of the stream).
+    pub total: u64,
+    /// Samples before the loop point; equal to `total` when there is no loop.
+    pub intro: u64,
+    /// Samples from the loop point to the end, or `None` without a loop.
+    pub looped: Option<u64>,
+}
+
+impl WaitTotals {
+    /// Sum the waits of `commands`, splitting at `loop_index` when given.
+    ///
+    /// `0x8n` commands contribute their embedded wait. Commands after the first
+    /// `EndOfData` are not played and are ignored.
+    pub fn from_commands(commands: &[VgmCommand], loop_index: Option<usize>) -> Self {
+        let mut totals = WaitTotals::default();
+        let mut loop_start = None;
+        for (index, command) in commands.iter().enumerate() {
+            if matches!(command, VgmCommand::EndOfData(_)) {
+                break;
+            }
+            if loop_index == Some(index) {
+                loop_start = Some(totals.total);
+            }
+            totals.total += command.wait_samples() as u64;
+        }
+        totals.intro = loop_start.unwrap_or(totals.total);
+        totals.looped = loop_start.map(|start| totals.total - start);
+        totals
+    }
+
+    /// Sample counts of `document`, using its header loop offset.
+    pub fn of(document: &VgmDocument) -> Self {
+        Self::from_commands(&document.commands, document.loop_command_index())
+    }
+}
+
+/// Which timing invariant a transform broke.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitConservationError {
+    /// The total length changed.
+    Total { original: u64, transformed: u64 },
+    /// The loop point moved (which changes the loop length), or the loop was
+    /// added or removed.
+    Loop {
+        original: Option<u64>,
+        transformed: Option<u64>,
+    },
+}
+
+impl fmt::Display for WaitConservationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WaitConservationError::Total {
+                original,
+                transformed,
+            } => write!(
+                f,
+                "total wait changed: {} -> {} samples",
+                original, transformed
+            ),
+            WaitConservationError::Loop {
+                original,
+                transformed,
+            } => write!(
+                f,
+                "loop length changed: {:?} -> {:?} samples",
+                original, transformed
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WaitConservationError {}
+
+/// Check that `transformed` plays for exactly as long as `original`.
+///
+/// The total length is compared first, then the presence and length of the
+/// loop; with equal totals an equal loop length also means the loop point
+/// did not move. On success the totals of `original` are returned.
+pub fn verify_wait_conservation(
+    original: &VgmDocument,
+    transformed: &VgmDocument,
+) -> Result<WaitTotals, WaitConservationError> {
+    compare_totals(WaitTotals::of(original), WaitTotals::of(transformed))
+}
+
+/// Same as `verify_wait_conservation` for plain command sequences without a
+/// loop, such as the output of `VgmStream`.
+pub fn verify_command_wait_conservation(
+    original: &[VgmCommand],
+    transformed: &[VgmCommand],
+) -> Result<WaitTotals, WaitConservationError> {
+    compare_totals(
+        WaitTotals::from_commands(original, None),
+        WaitTotals::from_commands(transformed, None),
+    )
+}
+
+fn compare_totals(
+    original: WaitTotals,
+    transformed: WaitTotals,
+) -> Result<WaitTotals, WaitConservationError> {
+    if original.total != transformed.total {
+        return Err(WaitConservationError::Total {
+            original: original.total,
+            transformed: transformed.total,
+        });
+    }
+    if original.looped != transformed.looped {
+        return Err(WaitConservationError::Loop {
+            original: original.looped,
+            transformed: transformed.looped,
+        });
+    }
+    Ok(original)
+}
+
+/// Why the serialization of a document is not reproducible.
+#[derive(Debug, Clone)]
+pub enum DeterminismError {
+    /// Serializing the document twice gave different bytes.
+    Unstable { offset: usize },
+    /// The serialized bytes did not parse.
+    Parse(ParseError),
+    /// Parsing the serialized bytes and serializing the result gave
+    /// different bytes.
+    NotFixedPoint {
+        offset: usize,
+        len: usize,
+        rebuilt_len: usize,
+    },
+}
+
+impl fmt::Display for DeterminismError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeterminismError::Unstable { offset } => {
+                write!(f, "serialization differs between runs at 0x{:X}", offset)
+            }
+            DeterminismError::Parse(e) => write!(f, "serialized bytes do not parse: {}", e),
+            DeterminismError::NotFixedPoint {
+                offset,
+                len,
+                rebuilt_len,
+            } => write!(
+                f,
+                "rebuilding the serialized file changes it at 0x{:X} ({} -> {} bytes)",
+                offset, len, rebuilt_len
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DeterminismError {}
+
+/// Check that `document` serializes to the same bytes every time and that
+/// those bytes survive a parse and serialize unchanged. On success the
+/// serialized bytes are returned.
+///
+/// ```rust
+/// use soundlog::VgmBuilder;
+/// use soundlog::chip::{Chip, PsgSpec};
+/// use soundlog::vgm::command::{Instance, WaitSamples};
+/// use soundlog::vgm::verify::verify_deterministic;
+///
+/// let mut builder = VgmBuilder::new();
+/// builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+/// builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x90 });
+/// builder.add_vgm_command(WaitSamples(735));
+/// let doc = builder.finalize();
+///
+/// let bytes = verify_deterministic(&doc).unwrap();
+/// assert_eq!(bytes, Vec::<u8>::from(&doc));
+/// ```
+pub fn verify_deterministic(document: &VgmDocument) -> Result<Vec<u8>, DeterminismError> {
+    let bytes = document.to_bytes();
+    let again = document.clone().to_bytes();
+    if let Some(offset) = first_difference(&bytes, &again) {
+        return Err(DeterminismError::Unstable { offset });
+    }
+    let reparsed = VgmDocument::try_from(&bytes[..]).map_err(DeterminismError::Parse)?;
+    let rebuilt = reparsed.to_bytes();
+    if let Some(offset) = first_difference(&bytes, &rebuilt) {
+        return Err(DeterminismError::NotFixedPoint {
+            offset,
+            len: bytes.len(),
+            rebuilt_len: rebuilt.len(),
+        });
+    }
+    Ok(bytes)
+}
+
+// Offset of the first differing byte, or the length of the shorter input
+// when one is a prefix of the other.
+fn first_difference(left: &[u8], right: &[u8]) -> Option<usize> {
+    left.iter()
+        .zip(right)
+        .position(|(l, r)| l != r)
+        .or((left.len() != right.len()).then(|| left.len().min(right.len())))
+}
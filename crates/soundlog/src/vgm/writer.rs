@@ -0,0 +1,228 @@
+//! Incremental VGM serialization.
+//!
+//! `VgmStreamWriter` writes commands to any `Write + Seek` target as they
+//! arrive instead of collecting them in a `VgmDocument` first, so a live
+//! capture of any length only needs the output file, not the whole command
+//! list in memory. The header is written up front with placeholder offsets
+//! and rewritten by `finish()` once the EOF, GD3 and loop positions and the
+//! sample counts are known.
+//!
+//! The file starts where the target is positioned when the writer is created,
+//! so it can be appended after other data, and every offset is relative to
+//! that start. Targets that cannot seek, such as stdout, a socket or a gzip
+//! encoder, can be written through a `Cursor<Vec<u8>>` whose bytes are copied
+//! to them after `finish()`, at the cost of holding the file in memory, or
+//! through a temporary file.
+//!
+//! ```rust
+//! use std::io::Cursor;
+//!
+//! use soundlog::chip::PsgSpec;
+//! use soundlog::meta::Gd3;
+//! use soundlog::vgm::command::{Instance, WaitSamples};
+//! use soundlog::{VgmDocument, VgmHeader, VgmStreamWriter};
+//!
+//! let mut writer = VgmStreamWriter::new(Cursor::new(Vec::new()), VgmHeader::default()).unwrap();
+//! writer.write_chip_write(Instance::Primary, PsgSpec { value: 0x9F }).unwrap();
+//! writer.write_command(WaitSamples(735)).unwrap();
+//! writer.mark_loop();
+//! writer.write_command(WaitSamples(441)).unwrap();
+//! writer.set_gd3(Gd3 {
+//!     track_name_en: Some("Capture".to_string()),
+//!     ..Default::default()
+//! });
+//! let bytes = writer.finish().unwrap().into_inner();
+//!
+//! let doc = VgmDocument::try_from(bytes.as_slice()).unwrap();
+//! assert_eq!(doc.header.total_samples, 1176);
+//! assert_eq!(doc.header.loop_samples, 441);
+//! assert_eq!(doc.loop_command_index(), Some(2));
+//! ```
+
+use std::io::{self, Seek, SeekFrom, Write};
+
+use crate::binutil::FileOffset;
+use crate::meta::Gd3;
+use crate::vgm::command::{EndOfData, Instance, VgmCommand, command_to_vgm_bytes};
+use crate::vgm::header::{VgmHeader, VgmHeaderField};
+
+/// Writes a VGM file command by command.
+///
+/// The header passed to `new` supplies the version, chip clocks and the other
+/// static fields. `eof_offset`, `gd3_offset`, `total_samples`, `loop_offset`
+/// and `loop_samples` are computed from the written commands and filled in
+/// by `finish()`, which needs `Seek` to go back to the header. An extra
+/// header is not written; the header's `extra_header_offset` is cleared.
+///
+/// The file starts at the position of the target when `new` is called, and
+/// `finish()` seeks back to that position, not to the start of the target.
+///
+/// Dropping the writer without calling `finish()` leaves a file whose header
+/// offsets are still zero.
+pub struct VgmStreamWriter<W: Write + Seek> {
+    writer: W,
+    header: VgmHeader,
+    data_offset: u32,
+    /// Position of the target where the file starts.
+    start: u64,
+    /// Position of the next command, relative to `start`.
+    position: u64,
+    total_samples: u64,
+    /// Position relative to `start` and sample position of the loop point.
+    loop_point: Option<(u64, u64)>,
+    gd3: Option<Gd3>,
+    ended: bool,
+}
+
+impl<W: Write + Seek> VgmStreamWriter<W> {
+    /// Write `header` to `writer` at its current position and return a writer
+    /// positioned at the start of the command data.
+    ///
+    /// A `data_offset` of `0` is replaced by the default for the header's
+    /// version, as `VgmBuilder::finalize()` does.
+    pub fn new(mut writer: W, mut header: VgmHeader) -> io::Result<Self> {
+        let data_offset = VgmHeader::data_offset(header.version, header.data_offset);
+        header.data_offset = data_offset;
+        header.extra_header_offset = 0;
+        header.eof_offset = 0;
+        header.total_samples = 0;
+        header.loop_offset = 0;
+        header.loop_samples = 0;
+
+        let start = writer.stream_position()?;
+        let bytes = Self::header_bytes(&header, 0, data_offset);
+        writer.write_all(&bytes)?;
+        Ok(Self {
+            writer,
+            header,
+            data_offset,
+            start,
+            position: bytes.len() as u64,
+            total_samples: 0,
+            loop_point: None,
+            gd3: None,
+            ended: false,
+        })
+    }
+
+    /// Serialize and write one command.
+    ///
+    /// An `EndOfData` command ends the command data; `finish()` then does not
+    /// append another one. Commands written after it are still written.
+    pub fn write_command<C>(&mut self, command: C) -> io::Result<()>
+    where
+        C: Into<VgmCommand>,
+    {
+        let command = command.into();
+        let (bytes, _len) = command_to_vgm_bytes(&command);
+        self.writer.write_all(&bytes)?;
+        self.position += bytes.len() as u64;
+        self.total_samples += u64::from(command.wait_samples());
+        if matches!(command, VgmCommand::EndOfData(_)) {
+            self.ended = true;
+        }
+        Ok(())
+    }
+
+    /// Write a chip register write, as `VgmBuilder::add_chip_write` adds one.
+    pub fn write_chip_write<C, I>(&mut self, instance: I, spec: C) -> io::Result<()>
+    where
+        I: Into<Instance>,
+        (Instance, C): Into<VgmCommand>,
+    {
+        self.write_command((instance.into(), spec))
+    }
+
+    /// Make the next written command the loop start.
+    ///
+    /// Calling it again moves the loop point; the last call wins.
+    pub fn mark_loop(&mut self) -> &mut Self {
+        self.loop_point = Some((self.position, self.total_samples));
+        self
+    }
+
+    /// Set the GD3 tags written by `finish()`.
+    pub fn set_gd3(&mut self, gd3: Gd3) -> &mut Self {
+        self.gd3 = Some(gd3);
+        self
+    }
+
+    /// Samples waited by the commands written so far.
+    pub fn total_samples(&self) -> u64 {
+        self.total_samples
+    }
+
+    /// Bytes written so far, header included.
+    pub fn bytes_written(&self) -> u64 {
+        self.position
+    }
+
+    /// Borrow the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Append `EndOfData` if no command ended the data, write the GD3 tags,
+    /// rewrite the header with the final offsets and sample counts, and
+    /// return the underlying writer positioned at the end of the file.
+    ///
+    /// # Errors
+    /// Returns an error when writing or seeking fails, or when the file grew
+    /// past the 4 GiB the header offsets can address.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.ended {
+            self.write_command(EndOfData)?;
+        }
+
+        let mut gd3_offset = 0;
+        if let Some(gd3) = &self.gd3 {
+            gd3_offset = relative(self.position, VgmHeaderField::Gd3Offset)?;
+            let bytes = gd3.to_bytes();
+            self.writer.write_all(&bytes)?;
+            self.position += bytes.len() as u64;
+        }
+
+        self.header.eof_offset = relative(self.position, VgmHeaderField::EofOffset)?;
+        self.header.total_samples = saturate(self.total_samples);
+        if let Some((offset, samples)) = self.loop_point {
+            self.header.loop_offset = relative(offset, VgmHeaderField::LoopOffset)?;
+            self.header.loop_samples = saturate(self.total_samples - samples);
+        }
+
+        let bytes = Self::header_bytes(&self.header, gd3_offset, self.data_offset);
+        self.writer.seek(SeekFrom::Start(self.start))?;
+        self.writer.write_all(&bytes)?;
+        self.writer
+            .seek(SeekFrom::Start(self.start + self.position))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    // Header bytes padded to the command data start.
+    fn header_bytes(header: &VgmHeader, gd3_offset: u32, data_offset: u32) -> Vec<u8> {
+        let mut bytes = header.to_bytes(gd3_offset, data_offset);
+        let data_start = VgmHeaderField::DataOffset
+            .offset()
+            .saturating_add(data_offset as usize);
+        bytes.resize(data_start, 0);
+        bytes
+    }
+}
+
+// Convert a position relative to the file start into the value stored in
+// `field`.
+fn relative(position: u64, field: VgmHeaderField) -> io::Result<u32> {
+    usize::try_from(position)
+        .ok()
+        .and_then(|position| FileOffset::new(position).to_relative(field.offset()))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("offset {:#x} does not fit the VGM header", position),
+            )
+        })
+}
+
+fn saturate(samples: u64) -> u32 {
+    u32::try_from(samples).unwrap_or(u32::MAX)
+}
@@ -0,0 +1,85 @@
+use soundlog::chip::{Chip, Ym2612Spec};
+use soundlog::vgm::align::{AlignOptions, align_documents};
+use soundlog::vgm::command::{Instance, WaitSamples};
+use soundlog::{VgmBuilder, VgmDocument};
+
+// Notes alternating between YM2612 channels 0 and 1, `gap` samples apart,
+// after `delay` samples of silence. Notes listed in `skip` are left out.
+fn build(delay: u16, gap: u16, notes: usize, skip: &[usize]) -> VgmDocument {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_453);
+    builder.add_vgm_command(WaitSamples(delay));
+    for note in 0..notes {
+        let channel = (note % 2) as u8;
+        if !skip.contains(&note) {
+            for value in [0xF0 | channel, channel] {
+                builder.add_chip_write(
+                    Instance::Primary,
+                    Ym2612Spec {
+                        port: 0,
+                        register: 0x28,
+                        value,
+                    },
+                );
+            }
+        }
+        builder.add_vgm_command(WaitSamples(gap));
+    }
+    builder.finalize()
+}
+
+#[test]
+fn align_finds_start_offset_and_reports_drift() {
+    // The right rendition starts 300 samples later and runs 2 samples slower
+    // per note.
+    let left = build(100, 4410, 40, &[]);
+    let right = build(400, 4412, 40, &[]);
+    let options = AlignOptions::new().with_window(44_100);
+    let alignment = align_documents(&left, &right, &options);
+
+    assert_eq!(alignment.offset, 300);
+    assert_eq!(alignment.pairs.len(), 40);
+    assert_eq!(
+        (alignment.unmatched_left, alignment.unmatched_right),
+        (0, 0)
+    );
+    assert_eq!(alignment.final_drift(), Some(78));
+    assert_eq!(alignment.max_drift(), 78);
+    assert_eq!(alignment.match_ratio(), 1.0);
+
+    // Ten notes per window; the drift grows from window to window.
+    assert_eq!(alignment.windows.len(), 4);
+    assert!(alignment.windows.iter().all(|w| w.matched == 10));
+    assert_eq!(alignment.windows[0].min_drift, 0);
+    assert!(
+        alignment
+            .windows
+            .windows(2)
+            .all(|w| w[0].mean_drift < w[1].mean_drift)
+    );
+}
+
+#[test]
+fn align_counts_missing_notes_on_both_sides() {
+    let left = build(0, 4410, 20, &[5]);
+    let right = build(1000, 4410, 20, &[12, 13]);
+    let alignment = align_documents(&left, &right, &AlignOptions::new());
+
+    assert_eq!(alignment.offset, 1000);
+    assert_eq!(alignment.pairs.len(), 17);
+    assert_eq!(alignment.unmatched_left, 2);
+    assert_eq!(alignment.unmatched_right, 1);
+    assert_eq!(alignment.max_drift(), 0);
+}
+
+#[test]
+fn align_without_common_key_ons_is_empty() {
+    let left = build(0, 4410, 4, &[]);
+    let right = VgmBuilder::new().finalize();
+    let alignment = align_documents(&left, &right, &AlignOptions::new());
+
+    assert_eq!(alignment.offset, 0);
+    assert!(alignment.pairs.is_empty());
+    assert_eq!(alignment.unmatched_left, 4);
+    assert_eq!(alignment.match_ratio(), 0.0);
+}
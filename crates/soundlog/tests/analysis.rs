@@ -0,0 +1,62 @@
+use soundlog::VgmBuilder;
+use soundlog::chip::{Chip, Ym2612Spec};
+use soundlog::vgm::analysis::{ChannelTimeline, Note, run_analyses};
+use soundlog::vgm::command::{Instance, WaitSamples};
+use soundlog::vgm::driver::{DriverAnalysis, driver_features};
+use soundlog::vgm::heatmap::{HeatmapAnalysis, HeatmapOptions, register_heatmap};
+use soundlog::vgm::lint::{LintAnalysis, LintOptions, lint_with_options};
+
+fn ym2612(port: u8, register: u8, value: u8) -> Ym2612Spec {
+    Ym2612Spec {
+        port,
+        register,
+        value,
+    }
+}
+
+#[test]
+fn one_pass_matches_separate_analyses() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+    builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0xA4, 0x22));
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0xA0, 0x69));
+    for frame in 0..4 {
+        builder.add_chip_write(Instance::Primary, ym2612(0, 0x28, 0xF0));
+        builder.add_vgm_command(WaitSamples(400));
+        builder.add_chip_write(Instance::Primary, ym2612(0, 0x28, 0x00));
+        builder.add_chip_write(Instance::Primary, ym2612(1, 0xA0, frame));
+        builder.add_vgm_command(WaitSamples(335));
+    }
+    let doc = builder.finalize();
+
+    let options = HeatmapOptions::new().with_window(1000);
+    let strict = LintOptions {
+        strict: true,
+        ..LintOptions::default()
+    };
+    let mut heatmap = HeatmapAnalysis::new(&options);
+    let mut lint = LintAnalysis::new(&strict);
+    let mut driver = DriverAnalysis::new();
+    let mut timeline = ChannelTimeline::new();
+    run_analyses(
+        &doc,
+        &mut [&mut heatmap, &mut lint, &mut driver, &mut timeline],
+    );
+
+    assert_eq!(heatmap.into_heatmap(), register_heatmap(&doc, &options));
+    assert_eq!(lint.into_report(), lint_with_options(&doc, &strict));
+    assert_eq!(driver.into_features(), driver_features(&doc));
+    let notes = timeline.into_notes();
+    assert_eq!(notes.len(), 4);
+    assert_eq!(
+        notes[1],
+        Note {
+            chip: Chip::Ym2612,
+            instance: Instance::Primary,
+            channel: 0,
+            start: 735,
+            end: Some(1135),
+        }
+    );
+}
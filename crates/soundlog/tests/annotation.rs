@@ -0,0 +1,63 @@
+use std::path::Path;
+
+use soundlog::vgm::annotation::{Annotation, Annotations, sidecar_path};
+
+fn indices(notes: &Annotations) -> Vec<usize> {
+    notes.iter().map(|(&index, _)| index).collect()
+}
+
+#[test]
+fn annotations_follow_inserted_and_removed_commands() {
+    let mut notes = Annotations::new();
+    notes.insert(2, Annotation::new("intro"));
+    notes.insert(5, Annotation::new("verse"));
+    notes.insert(9, Annotation::new("chorus"));
+
+    notes.insert_commands(5, 3);
+    assert_eq!(indices(&notes), vec![2, 8, 12]);
+    assert_eq!(notes.get(8).unwrap().label, "verse");
+
+    // drops "verse" and moves "chorus" forward
+    notes.remove_commands(6..10);
+    assert_eq!(indices(&notes), vec![2, 8]);
+    assert_eq!(notes.get(8).unwrap().label, "chorus");
+
+    notes.remap(|index| (index != 2).then_some(index * 2));
+    assert_eq!(indices(&notes), vec![16]);
+}
+
+#[test]
+fn sidecar_round_trips_escaped_text() {
+    let mut notes = Annotations::new();
+    notes.insert(
+        3,
+        Annotation::new("tab\there")
+            .with_color(0x12AB00)
+            .with_comment("line 1\nline 2 \\ end"),
+    );
+    notes.insert(0, Annotation::new(""));
+
+    let text = notes.to_sidecar();
+    assert_eq!(
+        text,
+        "# soundlog annotations v1\n0\t-\t\t\n3\t#12ab00\ttab\\there\tline 1\\nline 2 \\\\ end\n"
+    );
+    assert_eq!(Annotations::from_sidecar(&text).unwrap(), notes);
+}
+
+#[test]
+fn malformed_sidecar_lines_are_rejected() {
+    assert!(Annotations::from_sidecar("1\t-\tlabel").is_err());
+    assert!(Annotations::from_sidecar("x\t-\tlabel\t").is_err());
+    assert!(Annotations::from_sidecar("1\t#12345\tlabel\t").is_err());
+    assert!(Annotations::from_sidecar("1\t-\tbad\\q\t").is_err());
+    assert!(
+        Annotations::from_sidecar("\n# comment\r\n")
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(
+        sidecar_path(Path::new("dir/song.vgm")),
+        Path::new("dir/song.vgm.annotations")
+    );
+}
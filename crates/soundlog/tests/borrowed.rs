@@ -0,0 +1,101 @@
+use soundlog::meta::Gd3;
+use soundlog::vgm::VgmDocumentRef;
+use soundlog::vgm::borrowed::VgmCommandRef;
+use soundlog::vgm::command::{
+    DataBlock, EndOfData, PcmRamWrite, StreamChipType, Wait735Samples, WaitSamples,
+};
+use soundlog::vgm::parser::ParseLimits;
+use soundlog::{ParseError, ResourceLimit, VgmBuilder, VgmDocument};
+
+fn document_bytes() -> Vec<u8> {
+    let mut builder = VgmBuilder::new();
+    builder.add_vgm_command(DataBlock {
+        marker: 0x66,
+        chip_instance: 1,
+        data_type: 0x00,
+        size: 1000,
+        data: (0..1000).map(|i| i as u8).collect(),
+    });
+    builder.add_vgm_command(PcmRamWrite {
+        marker: 0x66,
+        chip_type: StreamChipType::Ym2612Pcm,
+        read_offset: 0x10,
+        write_offset: 0x20,
+        size: 3,
+        data: vec![7, 8, 9],
+    });
+    builder.add_vgm_command(WaitSamples(100));
+    builder.add_vgm_command(Wait735Samples);
+    builder.add_vgm_command(EndOfData);
+    builder.set_gd3(Gd3 {
+        track_name_en: Some("Track".to_string()),
+        game_name_origin: Some("ゲーム".to_string()),
+        notes: Some("last".to_string()),
+        ..Gd3::default()
+    });
+    builder.finalize().into()
+}
+
+#[test]
+fn borrowed_document_matches_owned_parse() {
+    let bytes = document_bytes();
+    let doc = VgmDocumentRef::parse(&bytes).unwrap();
+    assert_eq!(
+        doc.to_document(),
+        VgmDocument::try_from(&bytes[..]).unwrap()
+    );
+    assert_eq!(doc.commands.len(), 5);
+    assert_eq!(doc.iter().map(|c| c.wait_samples()).sum::<u32>(), 835);
+
+    // Payloads point into the input.
+    let input = bytes.as_ptr_range();
+    let VgmCommandRef::DataBlock(block) = &doc.commands[0] else {
+        panic!("expected a data block");
+    };
+    assert_eq!((block.chip_instance, block.size), (1, 1000));
+    assert!(input.contains(&block.data.as_ptr()));
+    let VgmCommandRef::PcmRamWrite(write) = &doc.commands[1] else {
+        panic!("expected a PCM RAM write");
+    };
+    assert_eq!(write.data, &[7, 8, 9]);
+    assert!(input.contains(&write.data.as_ptr()));
+
+    let gd3 = doc.gd3.as_ref().unwrap();
+    let game = gd3.field("game_name_origin").unwrap();
+    assert!(input.contains(&game.as_bytes().as_ptr()));
+    assert_eq!(game.to_string(), "ゲーム");
+    assert_eq!(game.as_bytes().len(), 6);
+    assert!(gd3.field("game_name_en").is_none());
+    assert!(gd3.field("unknown").is_none());
+    assert_eq!(gd3.field("notes").unwrap().to_string(), "last");
+}
+
+#[test]
+fn borrowed_parse_rejects_what_the_owned_parse_rejects() {
+    let bytes = document_bytes();
+    // Invalid UTF-16 (a lone surrogate) in the first GD3 tag.
+    let gd3 = bytes.windows(4).position(|w| w == b"Gd3 ").unwrap();
+    let mut broken = bytes.clone();
+    broken[gd3 + 12..gd3 + 14].copy_from_slice(&0xD800u16.to_le_bytes());
+    let owned = VgmDocument::try_from(&broken[..]).unwrap_err();
+    let borrowed = VgmDocumentRef::parse(&broken).unwrap_err();
+    assert_eq!(borrowed.to_string(), owned.to_string());
+
+    // A data block running past the end of the input.
+    let truncated = &bytes[..0x100];
+    assert!(VgmDocument::try_from(truncated).is_err());
+    assert!(VgmDocumentRef::parse(truncated).is_err());
+
+    let limits = ParseLimits {
+        max_data_block_size: 999,
+        ..ParseLimits::default()
+    };
+    assert!(matches!(
+        VgmDocumentRef::parse_with_limits(&bytes, &limits),
+        Err(ParseError::LimitExceeded {
+            limit: ResourceLimit::DataBlockSize,
+            value: 1000,
+            max: 999,
+        })
+    ));
+}
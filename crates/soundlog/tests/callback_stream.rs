@@ -2001,3 +2001,47 @@ fn test_misc_callbacks_invoked() {
         "EndOfData callback is reserved and should not be invoked by iteration"
     );
 }
+
+#[test]
+fn test_sample_clock_selects_loop_relative_or_absolute_samples() {
+    use soundlog::chip::PsgSpec;
+    use soundlog::vgm::callback_stream::SampleClock;
+    use soundlog::vgm::command::WaitSamples;
+
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+    builder.add_vgm_command(WaitSamples(10));
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x90 });
+    builder.add_vgm_command(WaitSamples(100));
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x91 });
+    builder.add_vgm_command(WaitSamples(50));
+    builder.set_loop_offset(3);
+    let doc = builder.finalize();
+
+    let run = |clock: SampleClock| {
+        let samples = Rc::new(RefCell::new(Vec::new()));
+        let sink = samples.clone();
+        let mut stream = VgmCallbackStream::from_document(doc.clone());
+        stream.set_loop_count(Some(3));
+        stream.set_sample_clock(clock);
+        stream.on_write(move |_inst, spec: PsgSpec, sample, _event| {
+            sink.borrow_mut().push((spec.value, sample));
+        });
+        for result in &mut stream {
+            result.unwrap();
+        }
+        assert_eq!(stream.absolute_sample(), 260);
+        assert_eq!(stream.current_sample(), 50);
+        samples.take()
+    };
+
+    assert_eq!(run(SampleClock::default()), run(SampleClock::LoopRelative));
+    assert_eq!(
+        run(SampleClock::LoopRelative),
+        vec![(0x90, 10), (0x91, 110), (0x91, 0), (0x91, 0)]
+    );
+    assert_eq!(
+        run(SampleClock::Absolute),
+        vec![(0x90, 10), (0x91, 110), (0x91, 160), (0x91, 210)]
+    );
+}
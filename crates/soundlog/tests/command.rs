@@ -6,14 +6,15 @@
 // serializes the document and feeds it to VgmStream to ensure the
 // generated writes are associated with the Secondary instance.
 
-use soundlog::VgmBuilder;
 use soundlog::vgm::command::ChipId;
 use soundlog::vgm::command::{
     DacStreamChipType, DataBlock, EndOfData, Instance, LengthMode, SetStreamData,
     SetStreamFrequency, SetupStreamControl, StartStream, StopStream, VgmCommand, WaitSamples,
+    command_to_vgm_bytes,
 };
 use soundlog::vgm::stream::StreamResult;
 use soundlog::vgm::stream::VgmStream;
+use soundlog::{ParseError, VgmBuilder};
 
 /// Construct a VGM document that routes a data bank to YM2612 Secondary
 /// instance and ensure the generated writes are tagged as Secondary.
@@ -1003,3 +1004,61 @@ fn test_parse_chip_write_all_opcodes_roundtrip() {
         assert!(found, "Roundtrip failed for command: {:?}", original);
     }
 }
+
+/// `VgmCommand::parse_one` decodes every command of a serialized document and
+/// reports truncated input as an out-of-range error.
+#[test]
+fn parse_one_decodes_single_commands() {
+    let mut builder = VgmBuilder::new();
+    builder.add_vgm_command(WaitSamples(735));
+    builder.add_vgm_command(DataBlock {
+        marker: 0x66,
+        chip_instance: 0,
+        data_type: 0x00,
+        size: 4,
+        data: vec![1, 2, 3, 4],
+    });
+    builder.add_vgm_command(SetStreamFrequency {
+        stream_id: 0,
+        frequency: 8000,
+    });
+    builder.add_vgm_command(EndOfData);
+    let doc = builder.finalize();
+
+    let mut bytes: Vec<u8> = Vec::new();
+    for command in &doc.commands {
+        bytes.extend_from_slice(&command_to_vgm_bytes(command).0);
+    }
+    let mut offset = 0;
+    for command in &doc.commands {
+        let (decoded, len) = VgmCommand::parse_one(&bytes[offset..]).unwrap();
+        assert_eq!(&decoded, command);
+        // Every shorter prefix is incomplete.
+        for end in offset..offset + len {
+            assert!(matches!(
+                VgmCommand::parse_one(&bytes[offset..end]),
+                Err(ParseError::OffsetOutOfRange { .. } | ParseError::UnexpectedEof)
+            ));
+        }
+        offset += len;
+    }
+    assert_eq!(offset, bytes.len());
+}
+
+#[test]
+fn register_write_to_command_inverts_register_write() {
+    let mut writes = 0;
+    for opcode in 0x30..=0xFFu8 {
+        for operand in [0x00, 0x81] {
+            let bytes = [opcode, operand, 0x2A, 0x5C, 0x7E];
+            let Ok((command, _)) = VgmCommand::parse_one(&bytes) else {
+                continue;
+            };
+            if let Some(write) = command.register_write() {
+                assert_eq!(write.to_command(), Some(command), "{:02X}", opcode);
+                writes += 1;
+            }
+        }
+    }
+    assert!(writes > 80, "{}", writes);
+}
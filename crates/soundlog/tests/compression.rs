@@ -0,0 +1,330 @@
+use soundlog::vgm::command::{EndOfData, VgmCommand};
+use soundlog::vgm::compression::{
+    BlockEncoding, compress_block, decompress_block, decompress_block_with_limits, generate_table,
+};
+use soundlog::vgm::detail::{
+    BitPackingCompression, BitPackingSubType, CompressedStream, CompressedStreamData,
+    CompressionType, DataBlockType, DecompressionTable, DpcmCompression, StreamChipType,
+    UncompressedStream, build_data_block, parse_data_block,
+};
+use soundlog::vgm::parser::ParseLimits;
+use soundlog::vgm::stream::{StreamResult, VgmStream};
+use soundlog::{ParseError, ResourceLimit, VgmBuilder};
+
+fn pcm(data: Vec<u8>) -> UncompressedStream {
+    UncompressedStream {
+        chip_type: StreamChipType::Ym2612Pcm,
+        data,
+    }
+}
+
+fn bit_packing(sub_type: BitPackingSubType, bits_compressed: u8, add_value: u16) -> BlockEncoding {
+    BlockEncoding::BitPacking {
+        bits_decompressed: 8,
+        bits_compressed,
+        sub_type,
+        add_value,
+    }
+}
+
+#[test]
+fn copy_round_trips_and_packs_msb_first() {
+    let stream = pcm(vec![0x10, 0x17, 0x11]);
+    let compressed = compress_block(
+        &stream,
+        &bit_packing(BitPackingSubType::Copy, 3, 0x10),
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(compressed.uncompressed_size, 3);
+    match &compressed.compression {
+        // 000 111 001 + padding
+        CompressedStreamData::BitPacking(bp) => assert_eq!(bp.data, vec![0x1C, 0x80]),
+        other => panic!("unexpected compression {other:?}"),
+    }
+    // the padding bits would decode to two extra values without truncation
+    assert_eq!(decompress_block(&compressed, None).unwrap(), stream.data);
+}
+
+#[test]
+fn compressed_block_survives_serialization_and_vgm_stream() {
+    let stream = pcm((0..64).map(|i| 0x80 + (i % 16) as u8).collect());
+    let compressed = compress_block(
+        &stream,
+        &bit_packing(BitPackingSubType::Copy, 4, 0x80),
+        None,
+    )
+    .unwrap();
+
+    let block = build_data_block(&DataBlockType::CompressedStream(compressed.clone()));
+    assert_eq!(block.data_type, 0x40);
+    assert_eq!(block.data.len(), 10 + 32);
+    assert_eq!(
+        parse_data_block(block).unwrap(),
+        DataBlockType::CompressedStream(compressed.clone())
+    );
+
+    let mut builder = VgmBuilder::new();
+    builder.attach_data_block(compressed);
+    builder.add_vgm_command(EndOfData);
+    let bytes: Vec<u8> = (&builder.finalize()).into();
+    let mut parser = VgmStream::new();
+    parser.push_chunk(&bytes).unwrap();
+    for result in &mut parser {
+        match result.unwrap() {
+            StreamResult::Command(VgmCommand::EndOfData(_)) | StreamResult::EndOfStream => break,
+            StreamResult::NeedsMoreData => break,
+            StreamResult::Command(_) => {}
+        }
+    }
+    assert_eq!(
+        parser.get_uncompressed_stream(0x40).unwrap().data,
+        stream.data
+    );
+}
+
+#[test]
+fn vgm_stream_limits_compressed_block_by_decompressed_size() {
+    let stream = pcm((0..64).map(|i| 0x80 + (i % 16) as u8).collect());
+    let compressed = compress_block(
+        &stream,
+        &bit_packing(BitPackingSubType::Copy, 4, 0x80),
+        None,
+    )
+    .unwrap();
+    let mut builder = VgmBuilder::new();
+    builder.attach_data_block(compressed);
+    builder.add_vgm_command(EndOfData);
+    let doc = builder.finalize();
+
+    // 42 bytes in the file, 64 bytes once decompressed.
+    let mut parser = VgmStream::from_document(doc.clone());
+    parser.set_max_data_block_size(48);
+    assert!(matches!(
+        parser.next(),
+        Some(Err(ParseError::DataBlockSizeExceeded {
+            attempted_size: 64,
+            ..
+        }))
+    ));
+
+    let mut parser = VgmStream::from_document(doc);
+    parser.set_max_data_block_size(64);
+    for result in &mut parser {
+        if let StreamResult::Command(VgmCommand::EndOfData(_)) | StreamResult::EndOfStream =
+            result.unwrap()
+        {
+            break;
+        }
+    }
+    assert_eq!(parser.total_data_block_size(), 64);
+}
+
+#[test]
+fn shift_left_keeps_high_bits() {
+    let stream = pcm(vec![0x00, 0x7F, 0x80, 0xFF]);
+    let compressed = compress_block(
+        &stream,
+        &bit_packing(BitPackingSubType::ShiftLeft, 4, 0),
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(
+        decompress_block(&compressed, None).unwrap(),
+        vec![0x00, 0x70, 0x80, 0xF0]
+    );
+}
+
+#[test]
+fn use_table_encodes_table_indices() {
+    let table = DecompressionTable {
+        compression_type: CompressionType::BitPacking,
+        sub_type: 0x02,
+        bits_decompressed: 8,
+        bits_compressed: 2,
+        value_count: 4,
+        table_data: vec![0x00, 0x40, 0x80, 0xC0],
+    };
+    let stream = pcm(vec![0xC0, 0x00, 0x80, 0x40]);
+    let encoding = bit_packing(BitPackingSubType::UseTable, 2, 0);
+
+    let compressed = compress_block(&stream, &encoding, Some(&table)).unwrap();
+    assert_eq!(
+        decompress_block(&compressed, Some(&table)).unwrap(),
+        stream.data
+    );
+
+    assert!(compress_block(&stream, &encoding, None).is_err());
+    assert!(compress_block(&pcm(vec![0x41]), &encoding, Some(&table)).is_err());
+}
+
+#[test]
+fn copy_rejects_values_that_do_not_fit() {
+    let encoding = bit_packing(BitPackingSubType::Copy, 4, 0x80);
+
+    assert!(compress_block(&pcm(vec![0x90]), &encoding, None).is_err());
+    // below add_value wraps around to a large value
+    assert!(compress_block(&pcm(vec![0x7F]), &encoding, None).is_err());
+}
+
+#[test]
+fn sixteen_bit_values_are_little_endian() {
+    let stream = pcm(vec![0x34, 0x12, 0x00, 0x10]);
+    let encoding = BlockEncoding::BitPacking {
+        bits_decompressed: 16,
+        bits_compressed: 12,
+        sub_type: BitPackingSubType::Copy,
+        add_value: 0x1000,
+    };
+
+    let compressed = compress_block(&stream, &encoding, None).unwrap();
+    assert_eq!(decompress_block(&compressed, None).unwrap(), stream.data);
+    assert!(compress_block(&pcm(vec![0x34]), &encoding, None).is_err());
+}
+
+#[test]
+fn decompress_dpcm_requires_table() {
+    let table = DecompressionTable {
+        compression_type: CompressionType::Dpcm,
+        sub_type: 0x00,
+        bits_decompressed: 8,
+        bits_compressed: 1,
+        value_count: 2,
+        table_data: vec![0x01, 0xFF],
+    };
+    let stream = CompressedStream {
+        chip_type: StreamChipType::Ym2612Pcm,
+        compression_type: CompressionType::Dpcm,
+        uncompressed_size: 4,
+        compression: CompressedStreamData::Dpcm(DpcmCompression {
+            bits_decompressed: 8,
+            bits_compressed: 1,
+            reserved: 0,
+            start_value: 0x80,
+            data: vec![0b0010_0000],
+        }),
+    };
+
+    assert!(decompress_block(&stream, None).is_err());
+    assert_eq!(
+        decompress_block(&stream, Some(&table)).unwrap(),
+        vec![0x81, 0x82, 0x81, 0x82]
+    );
+}
+
+#[test]
+fn dpcm_round_trips_with_generated_table() {
+    let stream = pcm(vec![0x80, 0x82, 0x84, 0x83, 0x7F, 0x7F, 0x81]);
+    let encoding = BlockEncoding::Dpcm {
+        bits_decompressed: 8,
+        bits_compressed: 2,
+    };
+
+    let table = generate_table(&[&stream], &encoding).unwrap();
+    // deltas 0, +2, -1, -4 (as 8-bit values), sorted
+    assert_eq!(table.table_data, vec![0x00, 0x02, 0xFC, 0xFF]);
+    assert_eq!(table.compression_type, CompressionType::Dpcm);
+
+    let compressed = compress_block(&stream, &encoding, Some(&table)).unwrap();
+    match &compressed.compression {
+        CompressedStreamData::Dpcm(dpcm) => assert_eq!(dpcm.start_value, 0x80),
+        other => panic!("unexpected compression {other:?}"),
+    }
+    assert_eq!(
+        decompress_block(&compressed, Some(&table)).unwrap(),
+        stream.data
+    );
+}
+
+#[test]
+fn generated_table_is_shared_and_bounded() {
+    let a = pcm(vec![0x10, 0x20]);
+    let b = pcm(vec![0x20, 0x30, 0x40]);
+    let use_table = bit_packing(BitPackingSubType::UseTable, 2, 0);
+
+    let table = generate_table(&[&a, &b], &use_table).unwrap();
+    assert_eq!(table.value_count, 4);
+    for stream in [&a, &b] {
+        let compressed = compress_block(stream, &use_table, Some(&table)).unwrap();
+        assert_eq!(
+            decompress_block(&compressed, Some(&table)).unwrap(),
+            stream.data
+        );
+    }
+
+    let too_many = pcm(vec![0x00, 0x01, 0x02, 0x03, 0x04]);
+    assert!(generate_table(&[&too_many], &use_table).is_err());
+    assert!(generate_table(&[&a], &bit_packing(BitPackingSubType::Copy, 2, 0)).is_err());
+}
+
+#[test]
+fn vgm_stream_uses_table_for_compressed_block() {
+    let stream = pcm((0..32).map(|i| (i * 3 % 16) as u8).collect());
+    let encoding = BlockEncoding::Dpcm {
+        bits_decompressed: 8,
+        bits_compressed: 4,
+    };
+    let table = generate_table(&[&stream], &encoding).unwrap();
+    let compressed = compress_block(&stream, &encoding, Some(&table)).unwrap();
+
+    let mut builder = VgmBuilder::new();
+    builder.attach_data_block(table);
+    builder.attach_data_block(compressed);
+    builder.add_vgm_command(EndOfData);
+    let bytes: Vec<u8> = (&builder.finalize()).into();
+    let mut parser = VgmStream::new();
+    parser.push_chunk(&bytes).unwrap();
+    for result in &mut parser {
+        match result.unwrap() {
+            StreamResult::Command(_) => {}
+            StreamResult::NeedsMoreData | StreamResult::EndOfStream => break,
+        }
+    }
+
+    assert_eq!(
+        parser.get_uncompressed_stream(0x40).unwrap().data,
+        stream.data
+    );
+}
+
+#[test]
+fn declared_size_is_not_trusted() {
+    let block = |bits_decompressed: u8, bits_compressed: u8, sub_type: BitPackingSubType| {
+        CompressedStream {
+            chip_type: StreamChipType::Ym2612Pcm,
+            compression_type: CompressionType::BitPacking,
+            uncompressed_size: u32::MAX,
+            compression: CompressedStreamData::BitPacking(BitPackingCompression {
+                bits_decompressed,
+                bits_compressed,
+                sub_type,
+                add_value: 0,
+                data: vec![0xA5; 2],
+            }),
+        }
+    };
+
+    // Output stops where the compressed data ends.
+    let decoded = decompress_block(&block(8, 4, BitPackingSubType::Copy), None).unwrap();
+    assert_eq!(decoded, vec![0x0A, 0x05, 0x0A, 0x05]);
+
+    // Zero-bit values would decode without consuming input.
+    assert!(decompress_block(&block(8, 0, BitPackingSubType::Copy), None).is_err());
+    assert!(decompress_block(&block(0, 4, BitPackingSubType::Copy), None).is_err());
+    assert!(decompress_block(&block(4, 8, BitPackingSubType::ShiftLeft), None).is_err());
+
+    let limits = ParseLimits {
+        max_decompressed_size: 1024,
+        ..ParseLimits::unlimited()
+    };
+    assert!(matches!(
+        decompress_block_with_limits(&block(8, 4, BitPackingSubType::Copy), None, &limits),
+        Err(ParseError::LimitExceeded {
+            limit: ResourceLimit::DecompressedSize,
+            value: 0xFFFF_FFFF,
+            max: 1024,
+        })
+    ));
+}
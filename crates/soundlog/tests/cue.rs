@@ -0,0 +1,106 @@
+use soundlog::chip::PsgSpec;
+use soundlog::vgm::command::{Instance, VgmCommand, WaitSamples};
+use soundlog::vgm::cue::{Cue, CueTrack, marker_id};
+use soundlog::vgm::transform::{QuantizeOptions, quantize};
+use soundlog::{VgmBuilder, VgmDocument};
+
+fn texts(cues: &[Cue]) -> Vec<&str> {
+    cues.iter().map(|cue| cue.text.as_str()).collect()
+}
+
+/// Helper: `(sample, id)` of every cue marker.
+fn markers(doc: &VgmDocument) -> Vec<(u64, u32)> {
+    let mut time = 0;
+    let mut found = Vec::new();
+    for cmd in doc.iter() {
+        if let Some(id) = marker_id(cmd) {
+            found.push((time, id));
+        }
+        time += cmd.wait_samples() as u64;
+    }
+    found
+}
+
+#[test]
+fn cursor_returns_due_cues_and_restarts_at_loop() {
+    let mut cues = CueTrack::new();
+    cues.add(500, "b");
+    cues.add(100, "a");
+    cues.add(500, "c");
+    assert_eq!(texts(cues.range(100..500)), vec!["a"]);
+
+    let mut cursor = cues.cursor();
+    assert!(cursor.advance(99).is_empty());
+    assert_eq!(texts(cursor.advance(600)), vec!["a", "b", "c"]);
+    assert_eq!(cursor.current().unwrap().text, "c");
+    // jumped back to a loop point at 200
+    assert_eq!(texts(cursor.advance(200)), Vec::<&str>::new());
+    assert_eq!(texts(cursor.advance(500)), vec!["b", "c"]);
+
+    cursor.seek(100);
+    assert_eq!(cursor.current().unwrap().text, "a");
+    assert_eq!(texts(cursor.advance(100)), vec!["a"]);
+}
+
+#[test]
+fn markers_follow_edits() {
+    let mut builder = VgmBuilder::new();
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x9F });
+    builder.add_vgm_command(WaitSamples(700));
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x90 });
+    builder.add_vgm_command(WaitSamples(800));
+    builder.set_loop_index(2);
+    let doc = builder.finalize();
+
+    let mut cues = CueTrack::new();
+    let verse = cues.add(700, "verse");
+    let bridge = cues.add(1000, "bridge");
+    let outro = cues.add(5000, "outro");
+    let marked = cues.embed_markers(&doc);
+    assert_eq!(
+        markers(&marked),
+        vec![(700, verse), (1000, bridge), (1500, outro)]
+    );
+    // the marker at the loop point is played on every loop
+    let loop_index = marked.loop_command_index().unwrap();
+    assert_eq!(marker_id(&marked.commands[loop_index]), Some(verse));
+    assert!(matches!(
+        marked.commands[loop_index + 1],
+        VgmCommand::Sn76489Write(..)
+    ));
+
+    // embedding again replaces the markers
+    assert_eq!(markers(&cues.embed_markers(&marked)), markers(&marked));
+
+    let (quantized, _) = quantize(&marked, &QuantizeOptions::new(735));
+    assert_eq!(cues.resync_markers(&quantized), 3);
+    assert_eq!(cues.get(verse).unwrap().sample, 735);
+    assert_eq!(cues.get(bridge).unwrap().sample, 735);
+    assert_eq!(cues.get(outro).unwrap().sample, 1470);
+}
+
+#[test]
+fn malformed_cue_sidecar_lines_are_rejected() {
+    let mut cues = CueTrack::new();
+    cues.add(44_100, "line 1\tline 2");
+    let text = cues.to_sidecar();
+    assert_eq!(text, "# soundlog cues v1\n44100\t0\tline 1\\tline 2\n");
+    assert_eq!(CueTrack::from_sidecar(&text).unwrap(), cues);
+
+    assert!(CueTrack::from_sidecar("1\t0").is_err());
+    assert!(CueTrack::from_sidecar("x\t0\ttext").is_err());
+    assert!(CueTrack::from_sidecar("1\t-1\ttext").is_err());
+}
+
+#[test]
+fn add_after_the_highest_id_reuses_a_free_id() {
+    let mut cues = CueTrack::new();
+    cues.insert(Cue {
+        sample: 0,
+        id: u32::MAX,
+        text: "last".to_string(),
+    });
+    assert_eq!(cues.add(10, "a"), 0);
+    assert_eq!(cues.add(20, "b"), 1);
+    assert_eq!(cues.get(u32::MAX).unwrap().text, "last");
+}
@@ -688,3 +688,74 @@ fn test_create_stream_write_command_static_chipid_mapping() {
         );
     }
 }
+
+#[test]
+fn test_add_data_block_for_bank_streams_blocks_at_their_bank_offsets() {
+    use soundlog::vgm::command::{
+        DacStreamChipType, Instance, LengthMode, SetStreamData, SetStreamFrequency,
+        SetupStreamControl, WaitSamples,
+    };
+    use soundlog::vgm::header::ChipId;
+
+    let mut b = VgmBuilder::new();
+    b.register_chip(soundlog::chip::Chip::Ym2612, Instance::Primary, 7_670_454);
+    let kick = b.add_data_block_for_bank(0x00, (0x10..0x20).collect::<Vec<u8>>());
+    let other = b.add_data_block_for_bank(0x01, vec![0xEE; 4]);
+    let snare = b.add_data_block_for_bank(0x00, (0x30..0x38).collect::<Vec<u8>>());
+    assert_eq!((kick, other, snare), (0, 1, 2));
+    assert_eq!(b.data_block_range(kick), Some(0..16));
+    assert_eq!(b.data_block_range(other), Some(0..4));
+    assert_eq!(b.data_block_range(snare), Some(16..24));
+    assert_eq!(b.data_block_range(3), None);
+
+    b.add_vgm_command(SetupStreamControl {
+        stream_id: 0,
+        chip_type: DacStreamChipType::new(ChipId::Ym2612, Instance::Primary),
+        write_port: 0,
+        write_command: 0x2A,
+    });
+    b.add_vgm_command(SetStreamData {
+        stream_id: 0,
+        data_bank_id: 0x00,
+        step_size: 1,
+        step_base: 0,
+    });
+    b.add_vgm_command(SetStreamFrequency {
+        stream_id: 0,
+        frequency: 44100,
+    });
+    b.start_stream_from_block(
+        0,
+        snare,
+        LengthMode::CommandCount {
+            reverse: false,
+            looped: false,
+        },
+    );
+    b.add_vgm_command(WaitSamples(32));
+    b.start_stream_from_block(
+        0,
+        kick,
+        LengthMode::PlayUntilEnd {
+            reverse: false,
+            looped: false,
+        },
+    );
+    b.add_vgm_command(WaitSamples(32));
+    b.add_vgm_command(EndOfData);
+
+    let mut parser = VgmStream::from_document(b.finalize());
+    let mut played = Vec::new();
+    for result in &mut parser {
+        match result {
+            Ok(StreamResult::Command(VgmCommand::Ym2612Write(_, write))) => {
+                played.push(write.value)
+            }
+            Ok(StreamResult::Command(_)) => {}
+            Ok(StreamResult::NeedsMoreData) | Ok(StreamResult::EndOfStream) => break,
+            Err(e) => panic!("stream error: {e:?}"),
+        }
+    }
+    let expected: Vec<u8> = (0x30..0x38).chain(0x10..0x20).collect();
+    assert_eq!(played, expected);
+}
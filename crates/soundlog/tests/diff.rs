@@ -0,0 +1,128 @@
+use soundlog::chip::{Chip, PsgSpec};
+use soundlog::vgm::command::{
+    DataBlock, Instance, VgmCommand, Wait735Samples, WaitNSample, WaitSamples,
+    Ym2612Port0Address2AWriteAndWaitN,
+};
+use soundlog::vgm::diff::{DiffLocation, Severity, diff_documents};
+use soundlog::vgm::header::VgmHeaderField;
+use soundlog::{VgmBuilder, VgmDocument};
+
+fn psg_doc(waits: &[VgmCommand]) -> VgmDocument {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+    for wait in waits {
+        builder.add_vgm_command(wait.clone());
+    }
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x9F });
+    builder.finalize()
+}
+
+#[test]
+fn identical_documents_have_no_differences() {
+    let doc = psg_doc(&[WaitSamples(100).into()]);
+    assert!(diff_documents(&doc, &doc.clone()).is_identical());
+}
+
+#[test]
+fn split_waits_are_benign() {
+    let left = psg_doc(&[Wait735Samples.into()]);
+    let right = psg_doc(&[WaitSamples(734).into(), WaitNSample(0).into()]);
+
+    let diff = diff_documents(&left, &right);
+    assert!(!diff.has_loss());
+    assert_eq!(diff.benign().count(), 1);
+}
+
+#[test]
+fn moved_write_is_loss() {
+    let left = psg_doc(&[WaitSamples(100).into()]);
+    let right = psg_doc(&[WaitSamples(101).into()]);
+
+    let diff = diff_documents(&left, &right);
+    let losses: Vec<_> = diff.losses().collect();
+    assert!(losses.iter().any(|d| d.location
+        == DiffLocation::Command {
+            left: Some(1),
+            right: Some(1)
+        }));
+    assert!(
+        losses
+            .iter()
+            .any(|d| d.location == DiffLocation::Header(VgmHeaderField::TotalSamples))
+    );
+}
+
+#[test]
+fn layout_header_fields_are_benign() {
+    let left = psg_doc(&[]);
+    let mut right = left.clone();
+    right.header.eof_offset += 4;
+    right.header.sn76489_clock = 4_000_000;
+
+    let diff = diff_documents(&left, &right);
+    let eof = diff
+        .differences
+        .iter()
+        .find(|d| d.location == DiffLocation::Header(VgmHeaderField::EofOffset))
+        .unwrap();
+    assert_eq!(eof.severity, Severity::Benign);
+    assert!(
+        diff.losses()
+            .any(|d| d.location == DiffLocation::Header(VgmHeaderField::Sn76489Clock))
+    );
+}
+
+#[test]
+fn dac_wait_encoding_is_benign() {
+    let build = |split: bool| {
+        let mut builder = VgmBuilder::new();
+        builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+        if split {
+            builder.add_vgm_command(Ym2612Port0Address2AWriteAndWaitN(0));
+            builder.add_vgm_command(WaitNSample(1));
+        } else {
+            builder.add_vgm_command(Ym2612Port0Address2AWriteAndWaitN(2));
+        }
+        builder.finalize()
+    };
+
+    let diff = diff_documents(&build(false), &build(true));
+    assert!(!diff.has_loss());
+    assert!(!diff.is_identical());
+}
+
+#[test]
+fn data_block_order_within_type_is_loss() {
+    let block = |data_type: u8, data: Vec<u8>| {
+        VgmCommand::DataBlock(Box::new(DataBlock {
+            marker: 0x66,
+            chip_instance: 0,
+            data_type,
+            size: data.len() as u32,
+            data,
+        }))
+    };
+    let build = |blocks: Vec<VgmCommand>| {
+        let mut doc = psg_doc(&[]);
+        for (i, b) in blocks.into_iter().enumerate() {
+            doc.commands.insert(i, b);
+        }
+        doc
+    };
+
+    let a = block(0x00, vec![1, 2]);
+    let b = block(0x01, vec![3]);
+    let c = block(0x00, vec![4]);
+
+    // different types interleaved differently: the banks are identical
+    let diff = diff_documents(
+        &build(vec![a.clone(), b.clone(), c.clone()]),
+        &build(vec![b.clone(), a.clone(), c.clone()]),
+    );
+    assert!(!diff.has_loss());
+    assert_eq!(diff.benign().count(), 1);
+
+    // same type reordered: bank offsets change
+    let diff = diff_documents(&build(vec![a.clone(), c.clone()]), &build(vec![c, a]));
+    assert!(diff.has_loss());
+}
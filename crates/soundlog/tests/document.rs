@@ -1,4 +1,7 @@
-use soundlog::vgm::command::{DataBlock, VgmCommand};
+use soundlog::vgm::command::{
+    DataBlock, StartStreamFastCall, StartStreamFastCallFlags, VgmCommand, WaitSamples,
+    Ym2612Port0Address2AWriteAndWaitN,
+};
 use soundlog::vgm::detail::{
     CompressionType, DecompressionTable, StreamChipType, UncompressedStream,
 };
@@ -600,3 +603,279 @@ fn readme_example_vgmbuilder() {
         }
     }
 }
+
+#[test]
+fn time_based_waits_carry_rounding_error() {
+    let mut builder = VgmBuilder::new();
+    // 1/3 s = 14700 samples exactly, 1/7 s = 6300 samples exactly
+    builder.wait_ratio(1, 3).wait_ratio(1, 7);
+    assert_eq!(builder.wait_error(), 0.0);
+
+    // 0.1 ms = 4.41 samples: 4, 5 (4.41 + 0.41), 4, ...
+    for _ in 0..100 {
+        builder.wait_duration(std::time::Duration::from_micros(100));
+        assert!(builder.wait_error().abs() <= 0.5);
+    }
+    builder.wait_secs(-1.0).wait_secs(f64::NAN);
+    let doc = builder.finalize();
+    assert_eq!(doc.header.total_samples, 14700 + 6300 + 441);
+    assert!(matches!(doc.commands[0], VgmCommand::WaitSamples(_)));
+}
+
+#[test]
+fn time_based_waits_use_header_sample_rate() {
+    let mut builder = VgmBuilder::new();
+    builder.set_sample_rate(48_000);
+    builder.wait_ratio(1, 60).wait_secs(2.0);
+    let doc = builder.finalize();
+    assert_eq!(doc.header.total_samples, 800 + 96_000);
+    // 96000 samples need two 0x61 commands
+    assert_eq!(
+        doc.commands
+            .iter()
+            .filter(|c| matches!(c, VgmCommand::WaitSamples(_)))
+            .count(),
+        3
+    );
+}
+
+#[test]
+fn dedupe_writes_drops_repeats_without_wait_only() {
+    use soundlog::chip::{PsgSpec, Y8950Spec, Ym2612Spec};
+    use soundlog::vgm::command::{Instance, WaitSamples};
+
+    let tl = |value| Ym2612Spec {
+        port: 0,
+        register: 0x40,
+        value,
+    };
+    let mut builder = VgmBuilder::new();
+    builder.set_dedupe_writes(true);
+    builder.add_chip_write(Instance::Primary, tl(0x10));
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x9F });
+    builder.add_chip_write(Instance::Primary, tl(0x10)); // dropped
+    builder.add_chip_write(Instance::Secondary, tl(0x10));
+    builder.add_vgm_command(VgmCommand::Ym2612Write(
+        Instance::Primary,
+        Ym2612Spec {
+            port: 1,
+            register: 0x40,
+            value: 0x10,
+        },
+    ));
+    builder.add_chip_write(Instance::Primary, tl(0x20));
+    builder.add_chip_write(Instance::Primary, tl(0x10));
+    builder.add_vgm_command(WaitSamples(1));
+    builder.add_chip_write(Instance::Primary, tl(0x10));
+    // ADPCM data bytes are consumed one by one
+    let adpcm = Y8950Spec {
+        register: 0x0F,
+        value: 0x88,
+    };
+    builder.add_chip_write(Instance::Primary, adpcm.clone());
+    builder.add_chip_write(Instance::Primary, adpcm);
+    assert_eq!(builder.deduped_writes(), 1);
+    let doc = builder.finalize();
+    assert_eq!(doc.commands.len(), 11);
+
+    let mut builder = VgmBuilder::new();
+    builder.add_chip_write(Instance::Primary, tl(0x10));
+    builder.add_chip_write(Instance::Primary, tl(0x10));
+    assert!(!builder.dedupe_writes());
+    assert_eq!(builder.finalize().commands.len(), 3);
+}
+
+#[test]
+fn dedupe_writes_sees_commands_appended_without_it() {
+    use soundlog::chip::{Upd7759Spec, Ym2612Spec};
+    use soundlog::vgm::command::Instance;
+
+    let tl = |value| Ym2612Spec {
+        port: 0,
+        register: 0x40,
+        value,
+    };
+    let mut builder = VgmBuilder::new();
+    builder.add_chip_write(Instance::Primary, tl(0x10));
+    builder.set_dedupe_writes(true);
+    builder.add_chip_write(Instance::Primary, tl(0x10)); // dropped
+    builder.add_wait_samples(10);
+    builder.add_chip_write(Instance::Primary, tl(0x10));
+    // uPD7759 FIFO bytes are consumed one by one
+    let fifo = Upd7759Spec {
+        register: 0x02,
+        value: 0x55,
+    };
+    builder.add_chip_write(Instance::Primary, fifo.clone());
+    builder.add_chip_write(Instance::Primary, fifo);
+    assert_eq!(builder.deduped_writes(), 1);
+    assert_eq!(builder.finalize().commands.len(), 6);
+}
+
+#[test]
+fn add_vgm_commands_matches_single_appends() {
+    use soundlog::chip::Ym2612Spec;
+    use soundlog::vgm::command::{Instance, WaitSamples};
+
+    let commands: Vec<VgmCommand> = [0x10, 0x10, 0x20]
+        .into_iter()
+        .map(|value| {
+            VgmCommand::Ym2612Write(
+                Instance::Primary,
+                Ym2612Spec {
+                    port: 0,
+                    register: 0x40,
+                    value,
+                },
+            )
+        })
+        .chain([WaitSamples(100).into()])
+        .collect();
+    for dedupe in [false, true] {
+        let mut single = VgmBuilder::new();
+        single.set_dedupe_writes(dedupe);
+        for command in commands.clone() {
+            single.add_vgm_command(command);
+        }
+        let mut bulk = VgmBuilder::with_capacity(commands.len());
+        bulk.set_dedupe_writes(dedupe);
+        bulk.add_vgm_commands(commands.clone());
+        assert_eq!(bulk.deduped_writes(), single.deduped_writes());
+        assert_eq!(bulk.finalize(), single.finalize());
+    }
+
+    let mut builder = VgmBuilder::new();
+    builder
+        .reserve(2)
+        .add_vgm_commands([WaitSamples(1), WaitSamples(2)]);
+    assert_eq!(builder.finalize().header.total_samples, 3);
+}
+
+#[test]
+fn memory_footprint_counts_commands_payloads_and_tags() {
+    use soundlog::meta::Gd3;
+    use soundlog::vgm::command::WaitSamples;
+
+    let build = |payload: usize, notes: &str| {
+        let mut builder = VgmBuilder::new();
+        builder.add_vgm_command(DataBlock {
+            marker: 0x66,
+            chip_instance: 0,
+            data_type: 0x00,
+            size: payload as u32,
+            data: vec![0x80; payload],
+        });
+        builder.add_vgm_command(WaitSamples(1));
+        builder.set_gd3(Gd3 {
+            notes: Some(notes.to_string()),
+            ..Default::default()
+        });
+        let bytes: Vec<u8> = builder.finalize().into();
+        VgmDocument::try_from(bytes.as_slice()).unwrap()
+    };
+
+    let small = build(16, "");
+    let large = build(16 + 1000, &"x".repeat(100));
+    let (small, large) = (small.memory_footprint(), large.memory_footprint());
+    assert_eq!(large.commands, small.commands);
+    assert_eq!(large.data - small.data, 1000);
+    assert!(large.metadata >= small.metadata + 100);
+    assert_eq!(large.total(), large.commands + large.data + large.metadata);
+}
+
+#[test]
+fn wait_stats_count_every_wait_encoding() {
+    use soundlog::vgm::command::{
+        Wait735Samples, Wait882Samples, WaitNSample, WaitSamples, Ym2612Port0Address2AWriteAndWaitN,
+    };
+
+    let mut builder = VgmBuilder::new();
+    builder.add_vgm_command(WaitSamples(100));
+    builder.add_vgm_command(Wait735Samples);
+    builder.add_vgm_command(Wait882Samples);
+    builder.add_vgm_command(Wait882Samples);
+    builder.add_vgm_command(WaitNSample(0));
+    builder.add_vgm_command(WaitNSample(15));
+    builder.add_vgm_command(Ym2612Port0Address2AWriteAndWaitN(0));
+    builder.add_vgm_command(Ym2612Port0Address2AWriteAndWaitN(7));
+    let bytes: Vec<u8> = builder.finalize().into();
+    let doc = VgmDocument::try_from(bytes.as_slice()).unwrap();
+
+    let stats = doc.wait_stats();
+    assert_eq!(
+        (stats.wait_samples.commands, stats.wait_samples.samples),
+        (1, 100)
+    );
+    assert_eq!((stats.wait_735.commands, stats.wait_735.samples), (1, 735));
+    assert_eq!((stats.wait_882.commands, stats.wait_882.samples), (2, 1764));
+    assert_eq!((stats.wait_n.commands, stats.wait_n.samples), (2, 17));
+    assert_eq!(
+        (
+            stats.ym2612_write_and_wait.commands,
+            stats.ym2612_write_and_wait.samples
+        ),
+        (2, 7)
+    );
+    assert_eq!(stats.frame_waits().commands, 3);
+    assert_eq!(stats.wait_commands(), 6);
+    assert_eq!(stats.total_samples(), doc.header.total_samples as u64);
+}
+
+#[test]
+fn timeline_counts_ym2612_write_and_wait_and_loop_lengths() {
+    let mut builder = VgmBuilder::new();
+    builder.add_vgm_command(DataBlock {
+        marker: 0x66,
+        chip_instance: 0,
+        data_type: 0x00,
+        size: 2,
+        data: vec![0x80, 0x90],
+    });
+    builder.add_vgm_command(WaitSamples(100));
+    builder.add_vgm_command(StartStreamFastCall {
+        stream_id: 0,
+        block_id: 0,
+        flags: StartStreamFastCallFlags {
+            reverse: false,
+            looped: false,
+        },
+    });
+    builder.add_vgm_command(Ym2612Port0Address2AWriteAndWaitN(5));
+    builder.add_vgm_command(Ym2612Port0Address2AWriteAndWaitN(0));
+    builder.add_vgm_command(WaitSamples(200));
+    builder.set_loop_offset(2);
+    let mut doc = builder.finalize();
+
+    let timeline: Vec<(u64, bool)> = doc
+        .timeline()
+        .map(|(position, command)| (position, matches!(command, VgmCommand::DataBlock(_))))
+        .collect();
+    assert_eq!(
+        timeline,
+        vec![
+            (0, true),
+            (0, false),
+            (100, false),
+            (100, false),
+            (105, false),
+            (105, false),
+            (305, false),
+        ]
+    );
+    let mut iter = doc.timeline();
+    assert_eq!(iter.len(), doc.commands.len());
+    iter.by_ref().for_each(drop);
+    assert_eq!(iter.position(), 305);
+
+    assert_eq!(doc.loop_start_sample(), Some(100));
+    assert_eq!(doc.loop_sample_length(), Some(205));
+    assert_eq!(doc.duration().as_nanos(), 305 * 1_000_000_000 / 44_100);
+    // the lengths come from the commands, not from a stale header
+    doc.header.total_samples = 0;
+    assert_eq!(doc.sample_length(), 305);
+    assert_eq!(doc.playback_duration(0), doc.playback_duration(1));
+    assert_eq!(
+        doc.playback_duration(2).as_nanos(),
+        (305u128 + 205) * 1_000_000_000 / 44_100
+    );
+}
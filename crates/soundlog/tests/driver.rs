@@ -0,0 +1,76 @@
+use soundlog::VgmBuilder;
+use soundlog::chip::{Chip, PsgSpec, Ym2151Spec, Ym2608Spec, Ym2612Spec};
+use soundlog::vgm::command::{Instance, WaitSamples};
+use soundlog::vgm::driver::{Driver, detect_driver, driver_features};
+
+#[test]
+fn timer_b_driven_mega_drive_song_is_gems() {
+    let mut builder = VgmBuilder::new();
+    let ym2612 = |register, value| Ym2612Spec {
+        port: 0,
+        register,
+        value,
+    };
+    builder.add_chip_write(Instance::Primary, ym2612(0x26, 0xC8));
+    for tick in 0..100 {
+        // acknowledge and reload timer B
+        builder.add_chip_write(Instance::Primary, ym2612(0x27, 0x2A));
+        if tick % 4 == 0 {
+            builder.add_chip_write(Instance::Primary, ym2612(0x28, 0xF0));
+            builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x90 });
+        }
+        builder.add_vgm_command(WaitSamples(560));
+    }
+    let doc = builder.finalize();
+
+    let features = driver_features(&doc);
+    assert_eq!(features.chips, vec![Chip::Ym2612, Chip::Sn76489]);
+    assert_eq!(features.tick, Some(560));
+    assert_eq!(features.timer_b_loads, 100);
+    assert!(!features.frame_tick());
+
+    let guesses = detect_driver(&doc);
+    assert_eq!(guesses[0].driver, Driver::Gems);
+    assert_eq!(guesses[0].score, 100);
+    assert!(
+        guesses[0]
+            .evidence
+            .contains(&"timer B reloaded on every tick".to_string())
+    );
+    assert_eq!(guesses[1].driver, Driver::Smps);
+}
+
+#[test]
+fn timer_usage_separates_pc_drivers() {
+    let build = |timer_a: bool| {
+        let mut builder = VgmBuilder::new();
+        let control = if timer_a { 0x3F } else { 0x2A };
+        for _ in 0..10 {
+            builder.add_chip_write(
+                Instance::Primary,
+                Ym2608Spec {
+                    port: 0,
+                    register: 0x27,
+                    value: control,
+                },
+            );
+            builder.add_vgm_command(WaitSamples(500));
+        }
+        builder.finalize()
+    };
+    assert_eq!(detect_driver(&build(false))[0].driver, Driver::Mucom88);
+    assert_eq!(detect_driver(&build(true))[0].driver, Driver::Pmd);
+
+    let mut builder = VgmBuilder::new();
+    builder.add_chip_write(
+        Instance::Primary,
+        Ym2151Spec {
+            register: 0x14,
+            value: 0x3A,
+        },
+    );
+    let guesses = detect_driver(&builder.finalize());
+    assert_eq!(guesses.len(), 1);
+    assert_eq!(guesses[0].driver, Driver::Mxdrv);
+    assert!(detect_driver(&VgmBuilder::new().finalize()).is_empty());
+}
@@ -1,7 +1,7 @@
 // YM2608 event test.
 use std::sync::{Arc, Mutex};
 
-use soundlog::chip::event::StateEvent;
+use soundlog::chip::event::{RhythmInstrument, StateEvent};
 use soundlog::chip::{self, Chip};
 use soundlog::vgm::command::Instance;
 use soundlog::{VgmBuilder, VgmCallbackStream};
@@ -397,3 +397,44 @@ fn test_ym2608_psg_channel_a_matches_a4() {
         diff
     );
 }
+
+#[test]
+fn test_ym2608_rhythm_hits_through_callback_stream() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2608, Instance::Primary, 8_000_000);
+
+    // Rhythm total level max, snare level 0x1C, then key on snare and rim shot
+    ym2608_write(&mut builder, Instance::Primary, 0, 0x11, 0x3F);
+    ym2608_write(&mut builder, Instance::Primary, 0, 0x19, 0xDC);
+    ym2608_write(&mut builder, Instance::Primary, 0, 0x1D, 0xDF);
+    ym2608_write(&mut builder, Instance::Primary, 0, 0x10, 0x22);
+    builder.add_vgm_command(soundlog::vgm::command::WaitSamples(WAIT_SAMPLES));
+    let doc = builder.finalize();
+
+    let mut callback_stream = VgmCallbackStream::from_document(doc);
+    callback_stream.track_state::<Ym2608State>(Instance::Primary, 8_000_000.0);
+
+    let hits = Arc::new(Mutex::new(Vec::new()));
+    let hits_cb = hits.clone();
+    callback_stream.on_write(move |_inst, _spec: chip::Ym2608Spec, _sample, event_opt| {
+        for ev in event_opt.into_iter().flatten() {
+            if let StateEvent::RhythmHit {
+                instrument,
+                attenuation,
+                ..
+            } = ev
+            {
+                hits_cb.lock().unwrap().push((instrument, attenuation));
+            }
+        }
+    });
+    for _ in (&mut callback_stream).take(100) {}
+
+    assert_eq!(
+        *hits.lock().unwrap(),
+        vec![
+            (RhythmInstrument::SnareDrum, 3),
+            (RhythmInstrument::RimShot, 0)
+        ]
+    );
+}
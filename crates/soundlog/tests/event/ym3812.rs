@@ -226,3 +226,50 @@ fn test_ym3812_fm_keyon_and_tone_freq_matches_a4() {
         "ToneInfo.freq_hz differs from target: got {freq} Hz, target {target_hz} Hz (diff {diff})"
     );
 }
+
+#[test]
+fn test_ym3812_rhythm_mode_hits_map_to_gm_percussion() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym3812, Instance::Primary, 3_579_545);
+
+    // Snare drum TL (channel 7 carrier) = 0x10, then rhythm mode with BD + SD,
+    // a held write that must not hit again, and a hi-hat
+    ym3812_write(&mut builder, Instance::Primary, 0x54, 0x10);
+    ym3812_write(&mut builder, Instance::Primary, 0xBD, 0x38);
+    builder.add_vgm_command(soundlog::vgm::command::WaitSamples(WAIT_SAMPLES));
+    ym3812_write(&mut builder, Instance::Primary, 0xBD, 0x38);
+    ym3812_write(&mut builder, Instance::Primary, 0xBD, 0x39);
+    builder.add_vgm_command(soundlog::vgm::command::WaitSamples(WAIT_SAMPLES));
+    let doc = builder.finalize();
+
+    let mut callback_stream = VgmCallbackStream::from_document(doc);
+    callback_stream.track_state::<Ym3812State>(Instance::Primary, 3_579_545.0);
+
+    let hits = Arc::new(Mutex::new(Vec::new()));
+    let hits_cb = hits.clone();
+    callback_stream.on_write(move |_inst, _spec: chip::Ym3812Spec, sample, event_opt| {
+        for ev in event_opt.into_iter().flatten() {
+            if let StateEvent::RhythmHit {
+                instrument,
+                attenuation,
+                ..
+            } = ev
+            {
+                hits_cb
+                    .lock()
+                    .unwrap()
+                    .push((sample, instrument.gm_note(), attenuation));
+            }
+        }
+    });
+    for _ in (&mut callback_stream).take(100) {}
+
+    assert_eq!(
+        *hits.lock().unwrap(),
+        vec![
+            (0, Some(36), 0),
+            (0, Some(38), 0x10),
+            (WAIT_SAMPLES as usize, Some(42), 0)
+        ]
+    );
+}
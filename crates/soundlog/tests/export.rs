@@ -0,0 +1,326 @@
+use soundlog::VgmBuilder;
+use soundlog::chip::{Chip, Okim6295Spec, PsgSpec, Ym2612Spec};
+use soundlog::vgm::command::{
+    DacStreamChipType, Instance, RegisterWrite, SeekOffset, SetStreamData, SetStreamFrequency,
+    SetupStreamControl, StartStreamFastCall, StartStreamFastCallFlags, StopStream, VgmCommand,
+    WaitSamples, Ym2612Port0Address2AWriteAndWaitN,
+};
+use soundlog::vgm::detail::{StreamChipType, UncompressedStream};
+use soundlog::vgm::export::frames::FrameExport;
+use soundlog::vgm::export::xgm::{XgmOptions, to_xgm};
+use soundlog::vgm::header::ChipId;
+
+fn ym2612(port: u8, register: u8, value: u8) -> Ym2612Spec {
+    Ym2612Spec {
+        port,
+        register,
+        value,
+    }
+}
+
+#[test]
+fn register_write_view_of_chip_commands() {
+    let cmd = VgmCommand::Ym2612Write(Instance::Secondary, ym2612(1, 0xA4, 0x22));
+    assert_eq!(
+        cmd.register_write(),
+        Some(RegisterWrite {
+            chip: Chip::Ym2612,
+            instance: Instance::Secondary,
+            port: 1,
+            register: 0xA4,
+            value: 0x22,
+        })
+    );
+    assert_eq!(
+        VgmCommand::WaitSamples(WaitSamples(10)).register_write(),
+        None
+    );
+}
+
+#[test]
+fn frames_include_empty_ticks_and_sample_positions() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0x28, 0xF0));
+    builder.add_vgm_command(WaitSamples(100));
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0x28, 0x00));
+    builder.add_vgm_command(WaitSamples(2000));
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0x28, 0xF0));
+    builder.add_vgm_command(WaitSamples(10));
+    let doc = builder.finalize();
+
+    let frames: Vec<_> = doc.frames(735).collect();
+    let positions: Vec<Vec<u64>> = frames
+        .iter()
+        .map(|f| f.commands.iter().map(|(s, _)| *s).collect())
+        .collect();
+
+    assert_eq!(positions, vec![vec![0, 100], vec![], vec![2100]]);
+    assert_eq!(frames[2].start_sample, 1470);
+}
+
+#[test]
+fn frame_export_keeps_only_register_changes() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+    builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0xA4, 0x22));
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x9F });
+    builder.add_vgm_command(WaitSamples(735));
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0xA4, 0x22));
+    builder.add_chip_write(Instance::Primary, ym2612(1, 0xA4, 0x22));
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x9F });
+    builder.add_vgm_command(WaitSamples(735));
+    let doc = builder.finalize();
+
+    let export = FrameExport::from_document(&doc, 735);
+    let counts: Vec<usize> = export.frames.iter().map(|f| f.writes.len()).collect();
+
+    // port 1 is a different register; PSG bytes are always kept
+    assert_eq!(counts, vec![2, 2]);
+    assert_eq!(export.frames[1].writes[0].port, 1);
+}
+
+#[test]
+fn frame_export_keeps_repeated_trigger_writes() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+    builder.register_chip(Chip::Okim6295, Instance::Primary, 1_000_000);
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0x2A, 0x80));
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0x2A, 0x80));
+    builder.add_chip_write(
+        Instance::Primary,
+        Okim6295Spec {
+            register: 0x00,
+            value: 0x81,
+        },
+    );
+    builder.add_vgm_command(WaitSamples(735));
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0x2A, 0x80));
+    builder.add_chip_write(
+        Instance::Primary,
+        Okim6295Spec {
+            register: 0x00,
+            value: 0x81,
+        },
+    );
+    builder.add_vgm_command(WaitSamples(735));
+    let doc = builder.finalize();
+
+    let export = FrameExport::from_document(&doc, 735);
+    let counts: Vec<usize> = export.frames.iter().map(|f| f.writes.len()).collect();
+
+    // DAC samples and OKIM6295 commands count on every write
+    assert_eq!(counts, vec![3, 2]);
+}
+
+#[test]
+fn frame_export_text_and_json() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+    builder.add_vgm_command(WaitSamples(735));
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0x28, 0xF0));
+    builder.add_vgm_command(WaitSamples(735));
+    let doc = builder.finalize();
+
+    let export = FrameExport::from_document(&doc, 735);
+
+    assert_eq!(
+        export.to_text(),
+        "# samples_per_frame=735\nframe 0 @0\nframe 1 @735\n  Ym2612#0 0:28=F0\n"
+    );
+    assert_eq!(
+        export.to_json(),
+        "{\"samples_per_frame\":735,\"frames\":[\
+         {\"frame\":0,\"sample\":0,\"writes\":[]},\
+         {\"frame\":1,\"sample\":735,\"writes\":[\
+         {\"chip\":\"Ym2612\",\"instance\":0,\"port\":0,\"register\":40,\"value\":240}]}]}"
+    );
+}
+
+/// Helper: split an XGM v1 file into (sample table, sample data, music data).
+fn split_xgm(bytes: &[u8]) -> (Vec<(u16, u16)>, &[u8], &[u8]) {
+    assert_eq!(&bytes[0..4], b"XGM ");
+    let table = (0..63)
+        .map(|i| {
+            let at = 4 + i * 4;
+            (
+                u16::from_le_bytes([bytes[at], bytes[at + 1]]),
+                u16::from_le_bytes([bytes[at + 2], bytes[at + 3]]),
+            )
+        })
+        .collect();
+    let sample_len = u16::from_le_bytes([bytes[0x100], bytes[0x101]]) as usize * 256;
+    let samples = &bytes[0x104..0x104 + sample_len];
+    let at = 0x104 + sample_len;
+    let music_len = u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap()) as usize;
+    (table, samples, &bytes[at + 4..at + 4 + music_len])
+}
+
+#[test]
+fn xgm_groups_writes_per_frame() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+    builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0xA4, 0x22));
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0xA0, 0x6D));
+    builder.add_chip_write(Instance::Primary, ym2612(1, 0xB4, 0xC0));
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0x28, 0xF0));
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x9F });
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0x2A, 0x80));
+    builder.add_vgm_command(WaitSamples(1470));
+    let doc = builder.finalize();
+
+    let (bytes, report) = to_xgm(&doc, &XgmOptions::default()).unwrap();
+    let (table, samples, music) = split_xgm(&bytes);
+
+    assert_eq!(bytes[0x102], 0x01); // version
+    assert_eq!(bytes[0x103], 0x00); // NTSC
+    assert!(table.iter().all(|e| *e == (0xFFFF, 0x0001)));
+    assert!(samples.is_empty());
+    assert_eq!(
+        music,
+        &[
+            0x21, 0xA4, 0x22, 0xA0, 0x6D, // port 0
+            0x30, 0xB4, 0xC0, // port 1
+            0x40, 0xF0, // key on/off
+            0x10, 0x9F, // PSG
+            0x00, // frame 0
+            0x00, // frame 1
+            0x7F, // end
+        ]
+    );
+    assert_eq!(report.frames, 2);
+    assert_eq!(report.dropped_commands, 1);
+}
+
+#[test]
+fn xgm_converts_dac_stream_to_pcm_channel() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+    builder.attach_data_block(UncompressedStream {
+        chip_type: StreamChipType::Ym2612Pcm,
+        data: vec![0x80, 0xFF, 0x00, 0x80],
+    });
+    builder.add_vgm_command(SetupStreamControl {
+        stream_id: 0,
+        chip_type: DacStreamChipType {
+            chip_id: ChipId::Ym2612,
+            instance: Instance::Primary,
+        },
+        write_port: 0,
+        write_command: 0x2A,
+    });
+    builder.add_vgm_command(SetStreamData {
+        stream_id: 0,
+        data_bank_id: 0,
+        step_size: 1,
+        step_base: 0,
+    });
+    builder.add_vgm_command(SetStreamFrequency {
+        stream_id: 0,
+        frequency: 14_000,
+    });
+    builder.add_vgm_command(StartStreamFastCall {
+        stream_id: 0,
+        block_id: 0,
+        flags: StartStreamFastCallFlags {
+            reverse: false,
+            looped: false,
+        },
+    });
+    builder.add_vgm_command(WaitSamples(735));
+    builder.add_vgm_command(StopStream { stream_id: 0 });
+    builder.add_vgm_command(WaitSamples(735));
+    builder.set_loop_offset(4);
+    let doc = builder.finalize();
+
+    let (bytes, report) = to_xgm(&doc, &XgmOptions::default()).unwrap();
+    let (table, samples, music) = split_xgm(&bytes);
+
+    assert_eq!(table[0], (0, 1));
+    assert_eq!(&samples[..4], &[0x00, 0x7F, 0x80, 0x00]);
+    assert_eq!(samples.len(), 256);
+    // play sample 1 on channel 0, stop it in the next frame, loop to frame 0
+    assert_eq!(
+        music,
+        &[0x50, 0x01, 0x00, 0x50, 0x00, 0x00, 0x7E, 0x00, 0x00, 0x00]
+    );
+    assert_eq!(report.samples, 1);
+    assert_eq!(report.pcm_plays, 1);
+}
+
+#[test]
+fn xgm_drops_dac_stream_without_frequency() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+    builder.attach_data_block(UncompressedStream {
+        chip_type: StreamChipType::Ym2612Pcm,
+        data: vec![0x80; 0x10000],
+    });
+    builder.add_vgm_command(SetupStreamControl {
+        stream_id: 0,
+        chip_type: DacStreamChipType {
+            chip_id: ChipId::Ym2612,
+            instance: Instance::Primary,
+        },
+        write_port: 0,
+        write_command: 0x2A,
+    });
+    builder.add_vgm_command(SetStreamFrequency {
+        stream_id: 0,
+        frequency: 0,
+    });
+    builder.add_vgm_command(StartStreamFastCall {
+        stream_id: 0,
+        block_id: 0,
+        flags: StartStreamFastCallFlags {
+            reverse: false,
+            looped: false,
+        },
+    });
+    builder.add_vgm_command(WaitSamples(735));
+    let doc = builder.finalize();
+
+    let (bytes, report) = to_xgm(&doc, &XgmOptions::default()).unwrap();
+    let (table, samples, _) = split_xgm(&bytes);
+
+    // an unused sample slot
+    assert_eq!(table[0], (0xFFFF, 0x0001));
+    assert!(samples.is_empty());
+    assert_eq!(report.samples, 0);
+    assert_eq!(report.dropped_commands, 1);
+}
+
+#[test]
+fn xgm_converts_data_bank_writes_to_samples() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+    builder.attach_data_block(UncompressedStream {
+        chip_type: StreamChipType::Ym2612Pcm,
+        data: vec![0x90; 8],
+    });
+    builder.add_vgm_command(SeekOffset(0));
+    for _ in 0..8 {
+        builder.add_vgm_command(Ym2612Port0Address2AWriteAndWaitN(3));
+    }
+    builder.add_vgm_command(WaitSamples(735));
+    // the same region played again must reuse the sample
+    builder.add_vgm_command(SeekOffset(0));
+    for _ in 0..8 {
+        builder.add_vgm_command(Ym2612Port0Address2AWriteAndWaitN(3));
+    }
+    let doc = builder.finalize();
+
+    let (bytes, report) = to_xgm(&doc, &XgmOptions { pal: true }).unwrap();
+    let (_, samples, _) = split_xgm(&bytes);
+
+    assert_eq!(bytes[0x103], 0x01);
+    assert_eq!(report.samples, 1);
+    assert_eq!(report.pcm_plays, 2);
+    // 8 bytes at 14700 Hz resample to 7 bytes at 14 kHz
+    assert_eq!(
+        &samples[..8],
+        &[0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x00]
+    );
+}
@@ -0,0 +1,67 @@
+use soundlog::VgmBuilder;
+use soundlog::chip::{PsgSpec, Ym2612Spec};
+use soundlog::vgm::command::{Instance, WaitSamples, Ym2612Port0Address2AWriteAndWaitN};
+use soundlog::vgm::frame_rate::{FrameRate, infer_frame_rate};
+
+#[test]
+fn frame_driven_song_with_dac_is_ntsc() {
+    let mut builder = VgmBuilder::new();
+    for frame in 0..60 {
+        builder.add_chip_write(
+            Instance::Primary,
+            PsgSpec {
+                value: 0x90 | (frame & 0x0F),
+            },
+        );
+        // A DAC sample between the frames is not a tick of the driver.
+        for _ in 0..5 {
+            builder.add_vgm_command(Ym2612Port0Address2AWriteAndWaitN(7));
+        }
+        builder.add_vgm_command(WaitSamples(735 - 35));
+    }
+    let doc = builder.finalize();
+
+    let guess = infer_frame_rate(&doc).unwrap();
+    assert_eq!(guess.rate, FrameRate::Ntsc);
+    assert_eq!(guess.gaps, 59);
+    assert_eq!(guess.ntsc_share, 100);
+    assert_eq!(guess.pal_share, 0);
+    assert_eq!(guess.most_common(), vec![(735, 59)]);
+    assert_eq!(guess.rate.to_string(), "60 Hz");
+}
+
+#[test]
+fn timer_driven_song_is_free_running() {
+    let mut builder = VgmBuilder::new();
+    for tick in 0..100u8 {
+        builder.add_chip_write(
+            Instance::Primary,
+            Ym2612Spec {
+                port: 0,
+                register: 0xA0,
+                value: tick,
+            },
+        );
+        // The DAC register alone does not make a group.
+        builder.add_vgm_command(WaitSamples(280));
+        builder.add_chip_write(
+            Instance::Primary,
+            Ym2612Spec {
+                port: 0,
+                register: 0x2A,
+                value: 0x80,
+            },
+        );
+        builder.add_vgm_command(WaitSamples(280));
+    }
+    let doc = builder.finalize();
+
+    let guess = infer_frame_rate(&doc).unwrap();
+    assert_eq!(guess.rate, FrameRate::FreeRunning);
+    assert_eq!(guess.most_common()[0], (560, 99));
+    assert!(guess.quantize_options().is_none());
+
+    let mut builder = VgmBuilder::new();
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x9F });
+    assert_eq!(infer_frame_rate(&builder.finalize()), None);
+}
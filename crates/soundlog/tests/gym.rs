@@ -0,0 +1,142 @@
+use soundlog::ParseError;
+use soundlog::chip::{PsgSpec, Ym2612Spec};
+use soundlog::gym::{GYMX_HEADER_SIZE, GymCommand, GymDocument, GymHeader, to_vgm};
+use soundlog::vgm::command::{Instance, VgmCommand, WaitSamples};
+use soundlog::vgm::header::Sn76489Feedback;
+
+fn dac(value: u8) -> GymCommand {
+    GymCommand::Ym2612Write {
+        port: 0,
+        register: 0x2A,
+        value,
+    }
+}
+
+fn document() -> GymDocument {
+    GymDocument {
+        header: Some(GymHeader {
+            song: "Green Hill".to_string(),
+            game: "Game".to_string(),
+            dumper: "someone".to_string(),
+            loop_start: 2,
+            ..GymHeader::default()
+        }),
+        commands: vec![
+            dac(1),
+            GymCommand::PsgWrite(0x9F),
+            dac(2),
+            dac(3),
+            GymCommand::Wait,
+            GymCommand::Ym2612Write {
+                port: 1,
+                register: 0xB4,
+                value: 0xC0,
+            },
+            GymCommand::Wait,
+            GymCommand::Wait,
+        ],
+    }
+}
+
+#[test]
+fn gym_round_trips() {
+    let doc = document();
+    assert_eq!(doc.frames(), 3);
+    assert_eq!(doc.duration().as_millis(), 50);
+    assert_eq!(doc.loop_command_index(), Some(5));
+
+    let bytes: Vec<u8> = (&doc).into();
+    assert_eq!(&bytes[..4], b"GYMX");
+    assert_eq!(&bytes[4..15], b"Green Hill\0");
+    assert_eq!(
+        &bytes[GYMX_HEADER_SIZE..GYMX_HEADER_SIZE + 7],
+        &[0x01, 0x2A, 0x01, 0x03, 0x9F, 0x01, 0x2A]
+    );
+    assert_eq!(&bytes[bytes.len() - 5..], &[0x02, 0xB4, 0xC0, 0x00, 0x00]);
+    assert_eq!(GymDocument::try_from(&bytes[..]).unwrap(), doc);
+
+    // Plain GYM is only commands.
+    let plain = GymDocument::try_from(&bytes[GYMX_HEADER_SIZE..]).unwrap();
+    assert_eq!(plain.header, None);
+    assert_eq!(plain.commands, doc.commands);
+    assert_eq!(plain.loop_command_index(), None);
+
+    assert!(matches!(
+        GymDocument::try_from(&[0x00, 0x04][..]),
+        Err(ParseError::UnknownOpcode {
+            opcode: 0x04,
+            offset: 1
+        })
+    ));
+    assert!(GymDocument::try_from(&[0x01, 0x28][..]).is_err());
+    assert!(matches!(
+        GymDocument::try_from(&bytes[..100]),
+        Err(ParseError::HeaderTooShort(_))
+    ));
+}
+
+#[test]
+fn gym_converts_to_vgm() {
+    let vgm = to_vgm(&document());
+    assert_eq!(vgm.header.ym2612_clock, 7_670_454);
+    assert_eq!(vgm.header.sn76489_clock, 3_579_545);
+    assert_eq!(vgm.header.sn76489_feedback, Sn76489Feedback::SegaVdp);
+    assert_eq!(vgm.header.total_samples, 3 * 735);
+    assert_eq!(vgm.header.loop_samples, 2 * 735);
+
+    let ym2612 = |port, register, value| {
+        VgmCommand::Ym2612Write(
+            Instance::Primary,
+            Ym2612Spec {
+                port,
+                register,
+                value,
+            },
+        )
+    };
+    // The three DAC writes of the first frame are spread over it.
+    assert_eq!(
+        vgm.commands[..9],
+        [
+            ym2612(0, 0x2A, 1),
+            VgmCommand::Sn76489Write(Instance::Primary, PsgSpec { value: 0x9F }),
+            WaitSamples(245).into(),
+            ym2612(0, 0x2A, 2),
+            WaitSamples(245).into(),
+            ym2612(0, 0x2A, 3),
+            WaitSamples(245).into(),
+            ym2612(1, 0xB4, 0xC0),
+            WaitSamples(1470).into(),
+        ]
+    );
+    assert_eq!(vgm.loop_command_index(), Some(7));
+    let gd3 = vgm.gd3.as_ref().unwrap();
+    assert_eq!(gd3.track_name_en.as_deref(), Some("Green Hill"));
+    assert_eq!(gd3.creator.as_deref(), Some("someone"));
+    assert_eq!(gd3.notes, None);
+}
+
+#[cfg(feature = "vgz")]
+#[test]
+fn gym_unpacks_packed_files() {
+    use std::io::Write;
+
+    let doc = document();
+    let bytes: Vec<u8> = (&doc).into();
+    let commands = &bytes[GYMX_HEADER_SIZE..];
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(commands).unwrap();
+    let mut packed = bytes[..GYMX_HEADER_SIZE].to_vec();
+    packed[424..428].copy_from_slice(&(commands.len() as u32).to_le_bytes());
+    packed.extend(encoder.finish().unwrap());
+
+    let parsed = GymDocument::try_from(&packed[..]).unwrap();
+    assert_eq!(parsed.commands, doc.commands);
+    assert_eq!(Vec::<u8>::from(&parsed), bytes);
+
+    packed[424] += 1;
+    assert!(matches!(
+        GymDocument::try_from(&packed[..]),
+        Err(ParseError::DataInconsistency(_))
+    ));
+}
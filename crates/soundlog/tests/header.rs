@@ -2,8 +2,8 @@ use soundlog::chip::Chip;
 use soundlog::vgm::command::Instance;
 use soundlog::vgm::header::{
     Ay8910ChipType, Ay8910Flags, C140ChipType, ChipClock, ChipId, ChipVolume, K054539Flags,
-    Okim6258Flags, Sn76489Feedback, Sn76489Flags, Sn76489ShiftRegisterWidth, Ym2203AyFlags,
-    Ym2608AyFlags,
+    Okim6258Flags, SegaPcmInterface, Sn76489Feedback, Sn76489Flags, Sn76489ShiftRegisterWidth,
+    Ym2203AyFlags, Ym2608AyFlags,
 };
 
 #[test]
@@ -247,6 +247,64 @@ fn test_vgm_header_roundtrip_all_fields() {
     // assert_eq!(ph.reserved_f0_ff, h.reserved_f0_ff);
 }
 
+#[test]
+fn test_header_flags_round_trip_every_byte() {
+    for raw in 0..=0xFFu8 {
+        assert_eq!(u8::from(Sn76489Flags::from(raw)), raw);
+        assert_eq!(u8::from(Ay8910Flags::from(raw)), raw);
+        assert_eq!(u8::from(Okim6258Flags::from(raw)), raw);
+        assert_eq!(u8::from(K054539Flags::from(raw)), raw);
+        assert_eq!(u8::from(Ay8910ChipType::from(raw)), raw);
+        assert_eq!(u8::from(C140ChipType::from(raw)), raw);
+    }
+    for raw in [0, 0x00F8_000D, 0x0070_000B, 0xFFFF_FFFF, 0x1234_5678] {
+        assert_eq!(u32::from(SegaPcmInterface::from(raw)), raw);
+    }
+    assert_eq!(
+        SegaPcmInterface::from(0x00F8_000D),
+        SegaPcmInterface {
+            bank_shift: 13,
+            bank_mask: 0xF8,
+            reserved: 0,
+        }
+    );
+}
+
+#[test]
+fn test_header_raw_bytes_survive_typed_round_trip() {
+    let mut builder = soundlog::VgmBuilder::new();
+    builder.add_vgm_command(soundlog::vgm::command::WaitSamples(1));
+    let mut doc = builder.finalize();
+    // A full 0x100 byte header, so the reserved arrays are kept.
+    doc.header.data_offset = 0x100 - 0x34;
+    doc.header.spcm_interface = 0x00F8_000D;
+    doc.header.sn76489_flags = Sn76489Flags::from(0xE5);
+    doc.header.ay8910_flags = Ay8910Flags::from(0xA1);
+    doc.header.okim6258_flags = Okim6258Flags::from(0xF6);
+    doc.header.k054539_flags = K054539Flags::from(0x79);
+    doc.header.reserved_7d = 0x7D;
+    doc.header.reserved_97 = 0x97;
+    doc.header.reserved_e8_ef = [0xE8, 0xE9, 0xEA, 0xEB, 0xEC, 0xED, 0xEE, 0xEF];
+    doc.header.reserved_f0_ff = std::array::from_fn(|i| 0xF0 + i as u8);
+
+    let bytes: Vec<u8> = (&doc).into();
+    // Multi-byte fields are little endian.
+    assert_eq!(bytes[0x3C..0x40], [0x0D, 0x00, 0xF8, 0x00]);
+    assert_eq!(bytes[0x2B], 0xE5);
+    assert_eq!(bytes[0x7D], 0x7D);
+    assert_eq!(bytes[0x97], 0x97);
+    assert_eq!(bytes[0xE8..0xF0], doc.header.reserved_e8_ef);
+    assert_eq!(bytes[0xF0..0x100], doc.header.reserved_f0_ff);
+
+    let parsed: soundlog::VgmDocument = bytes.as_slice().try_into().unwrap();
+    assert_eq!(parsed.header.sn76489_flags, doc.header.sn76489_flags);
+    assert_eq!(parsed.header.okim6258_flags, doc.header.okim6258_flags);
+    assert_eq!(parsed.header.reserved_e8_ef, doc.header.reserved_e8_ef);
+    assert_eq!(parsed.header.reserved_f0_ff, doc.header.reserved_f0_ff);
+    assert_eq!(parsed.header.sega_pcm_interface().bank_shift, 13);
+    assert_eq!(Vec::<u8>::from(&parsed), bytes);
+}
+
 #[test]
 fn test_chip_instances_substitute_ym2413_for_ym2612() {
     // Legacy behavior: when version <= 1.01 and ym2413_clock > 5_000_000 and ym2612_clock == 0,
@@ -557,3 +615,95 @@ fn test_parse_data_block_unexpected_eof_ramwrite32() {
         panic!("expected error");
     }
 }
+
+#[test]
+fn test_chip_instances_mut_writes_back_to_header() {
+    use soundlog::vgm::header::VgmHeader;
+
+    let mut header = VgmHeader::default();
+    let mut chips = header.chip_instances_mut();
+    assert!(!chips.enable_secondary(&Chip::Ym2151));
+    chips
+        .set_clock(Chip::Ym2151, 3_579_545)
+        .set_clock(Chip::Es5506U16, 16_000_000);
+    assert!(chips.enable_secondary(&Chip::Ym2151));
+    assert!(chips.has_secondary(&Chip::Ym2151));
+    // the dual-chip bit survives a clock correction
+    chips.set_clock(Chip::Ym2151, 4_000_000 | 0x8000_0000);
+    assert_eq!(chips.clock(&Chip::Ym2151), Some(4_000_000));
+    assert_eq!(chips.clock(&Chip::Es5506U8), Some(16_000_000));
+    assert_eq!(chips.get().len(), 3);
+
+    assert_eq!(header.ym2151_clock, 4_000_000 | 0x8000_0000);
+    assert_eq!(header.es5506_clock, 16_000_000);
+
+    let mut chips = header.chip_instances_mut();
+    chips.disable_secondary(&Chip::Ym2151);
+    assert_eq!(chips.clock(&Chip::Ym2151), Some(4_000_000));
+    assert!(!chips.has_secondary(&Chip::Ym2151));
+    chips.remove_chip(&Chip::Es5506U8);
+    assert_eq!(chips.clock(&Chip::Es5506U8), None);
+    assert_eq!(
+        header.chip_instances().0,
+        vec![(Instance::Primary, Chip::Ym2151, 4_000_000.0)]
+    );
+}
+
+// Serialize a document whose extra header holds one chip clock and, with
+// `volumes`, one chip volume. Returns the bytes and the extra header start.
+fn with_extra_header(volumes: bool) -> (Vec<u8>, usize) {
+    let mut builder = soundlog::VgmBuilder::new();
+    builder.add_vgm_command(soundlog::vgm::command::WaitSamples(1));
+    builder.set_extra_header(soundlog::vgm::VgmExtraHeader {
+        chip_clocks: vec![ChipClock::new(ChipId::Ym2612, Instance::Primary, 7_670_454)],
+        chip_volumes: if volumes {
+            vec![ChipVolume::new(ChipId::Sn76489, Instance::Primary, 0x80)]
+        } else {
+            vec![]
+        },
+        ..Default::default()
+    });
+    let bytes: Vec<u8> = builder.finalize().into();
+    let start = u32::from_le_bytes(bytes[0xBC..0xC0].try_into().unwrap()) as usize + 0xBC;
+    (bytes, start)
+}
+
+#[test]
+fn test_extra_header_layouts_roundtrip_byte_exact() {
+    let roundtrip = |bytes: &[u8]| {
+        let doc = soundlog::VgmDocument::try_from(bytes).unwrap();
+        assert_eq!(Vec::<u8>::from(&doc), bytes);
+        doc
+    };
+
+    // Header size as defined by the specification (size of the fields).
+    let (mut bytes, start) = with_extra_header(true);
+    bytes[start..start + 4].copy_from_slice(&0x0Cu32.to_le_bytes());
+    let doc = roundtrip(&bytes);
+    assert_eq!(doc.extra_header.unwrap().header_size, 0x0C);
+
+    // Volume block stored before the clock block.
+    let (mut bytes, start) = with_extra_header(true);
+    let clocks = bytes[start + 12..start + 18].to_vec();
+    let volumes = bytes[start + 18..start + 23].to_vec();
+    bytes[start + 12..start + 17].copy_from_slice(&volumes);
+    bytes[start + 17..start + 23].copy_from_slice(&clocks);
+    bytes[start + 4..start + 8].copy_from_slice(&13u32.to_le_bytes());
+    bytes[start + 8..start + 12].copy_from_slice(&4u32.to_le_bytes());
+    let doc = roundtrip(&bytes);
+    let extra = doc.extra_header.unwrap();
+    assert_eq!(extra.chip_clocks[0].clock, 7_670_454);
+    assert_eq!(extra.chip_volumes[0].volume, 0x80);
+
+    // v1.70 8-byte extra header without chip volume offset.
+    let (mut bytes, start) = with_extra_header(false);
+    let clocks = bytes[start + 12..start + 18].to_vec();
+    bytes[start..start + 4].copy_from_slice(&8u32.to_le_bytes());
+    bytes[start + 4..start + 8].copy_from_slice(&4u32.to_le_bytes());
+    bytes[start + 8..start + 14].copy_from_slice(&clocks);
+    bytes[start + 14..start + 18].fill(0);
+    let doc = roundtrip(&bytes);
+    let extra = doc.extra_header.unwrap();
+    assert_eq!(extra.chip_clocks.len(), 1);
+    assert!(extra.chip_volumes.is_empty());
+}
@@ -0,0 +1,57 @@
+use soundlog::VgmBuilder;
+use soundlog::chip::{Chip, PsgSpec, Ym2612Spec};
+use soundlog::vgm::command::{Instance, WaitSamples, Ym2612Port0Address2AWriteAndWaitN};
+use soundlog::vgm::heatmap::{HeatmapOptions, register_heatmap};
+
+#[test]
+fn heatmap_counts_per_chip_instance_and_window() {
+    let mut builder = VgmBuilder::new();
+    builder.add_chip_write(
+        Instance::Primary,
+        Ym2612Spec {
+            port: 1,
+            register: 0xB4,
+            value: 0xC0,
+        },
+    );
+    builder.add_chip_write(Instance::Secondary, PsgSpec { value: 0x9F });
+    builder.add_vgm_command(WaitSamples(1000));
+    builder.add_vgm_command(Ym2612Port0Address2AWriteAndWaitN(5));
+    builder.add_vgm_command(Ym2612Port0Address2AWriteAndWaitN(0));
+    builder.add_vgm_command(WaitSamples(2000));
+    builder.add_chip_write(Instance::Secondary, PsgSpec { value: 0x9F });
+    let doc = builder.finalize();
+
+    let whole = register_heatmap(&doc, &HeatmapOptions::new());
+    assert_eq!(whole.samples, 3005);
+    assert_eq!(whole.chips.len(), 2);
+    assert!(whole.chips.iter().all(|chip| chip.windows.is_empty()));
+
+    let heatmap = register_heatmap(&doc, &HeatmapOptions::new().with_window(1000));
+    let ym2612 = &heatmap.chips[0];
+    assert_eq!(
+        (ym2612.chip.clone(), ym2612.instance),
+        (Chip::Ym2612, Instance::Primary)
+    );
+    assert_eq!(ym2612.ports(), vec![0, 1]);
+    assert_eq!(ym2612.count(0, 0x2A), 2);
+    assert_eq!(ym2612.count(1, 0xB4), 1);
+    assert_eq!(ym2612.total(), 3);
+    assert_eq!(ym2612.grid(0).len(), 3);
+    assert_eq!(ym2612.grid(1)[0xB][4], 1);
+
+    let psg = &heatmap.chips[1];
+    assert_eq!(
+        (psg.chip.clone(), psg.instance),
+        (Chip::Sn76489, Instance::Secondary)
+    );
+    assert_eq!(psg.count(0, 0), 2);
+    // windows cover the whole song, also where the chip is not written
+    assert_eq!(ym2612.windows.len(), 4);
+    assert_eq!(psg.windows.len(), 4);
+    assert_eq!(
+        ym2612.windows.iter().map(|w| w.len()).collect::<Vec<_>>(),
+        vec![1, 1, 0, 0]
+    );
+    assert_eq!(psg.windows[3].get(&(0, 0)), Some(&1));
+}
@@ -0,0 +1,120 @@
+use soundlog::chip::{Chip, Ym2612Spec};
+use soundlog::meta::Gd3;
+use soundlog::vgm::command::{DataBlock, Instance, VgmCommand, WaitSamples};
+use soundlog::vgm::incremental::{gd3_in_place, rewrite_gd3_in_place, serialize_incremental};
+use soundlog::{VgmBuilder, VgmDocument};
+
+fn original_bytes() -> Vec<u8> {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+    builder.add_vgm_command(DataBlock {
+        marker: 0x66,
+        chip_instance: 0,
+        data_type: 0x00,
+        size: 4096,
+        data: vec![0x80; 4096],
+    });
+    for value in 0..8 {
+        builder.add_chip_write(
+            Instance::Primary,
+            Ym2612Spec {
+                port: 0,
+                register: 0xA0,
+                value,
+            },
+        );
+        builder.add_vgm_command(WaitSamples(735));
+    }
+    builder.set_gd3(Gd3 {
+        track_name_en: Some("Before".to_string()),
+        ..Gd3::default()
+    });
+    builder.finalize().into()
+}
+
+#[test]
+fn changed_commands_are_serialized_and_the_rest_copied() {
+    let bytes = original_bytes();
+    let original = VgmDocument::try_from(&bytes[..]).unwrap();
+    let sourcemap = original.sourcemap();
+
+    let mut edited = original.clone();
+    edited.commands[5] = VgmCommand::WaitSamples(WaitSamples(882));
+    edited
+        .commands
+        .insert(6, VgmCommand::WaitSamples(WaitSamples(1)));
+    edited.gd3.as_mut().unwrap().track_name_en = Some("After, longer".to_string());
+    edited.header.eof_offset = 0;
+
+    let (out, report) = serialize_incremental(&edited, &original, &bytes, &sourcemap);
+    assert_eq!(out, Vec::<u8>::from(&edited));
+    assert_eq!(report.serialized_commands, 2);
+    assert_eq!(report.reused_commands, original.commands.len() - 1);
+    assert!(report.reused_bytes > 4096);
+
+    // A buffer that does not match the sourcemap is not copied from.
+    let mut corrupt = bytes.clone();
+    let (offset, _) = sourcemap[original.commands.len() - 1];
+    corrupt[offset] = 0x00;
+    let (out, report) = serialize_incremental(&edited, &original, &corrupt, &sourcemap);
+    assert_eq!(out, Vec::<u8>::from(&edited));
+    assert_eq!(report.reused_commands, 5);
+}
+
+#[test]
+fn gd3_is_rewritten_in_place_only_when_it_fits() {
+    let bytes = original_bytes();
+    let gd3 = |title: &str| Gd3 {
+        track_name_en: Some(title.to_string()),
+        ..Gd3::default()
+    };
+
+    let mut patched = bytes.clone();
+    assert!(rewrite_gd3_in_place(&mut patched, &gd3("Afte")).unwrap());
+    assert_eq!(patched.len(), bytes.len());
+    let doc = VgmDocument::try_from(&patched[..]).unwrap();
+    assert_eq!(doc.gd3, Some(gd3("Afte")));
+    assert_eq!(
+        doc.commands,
+        VgmDocument::try_from(&bytes[..]).unwrap().commands
+    );
+
+    let mut unchanged = bytes.clone();
+    assert!(!rewrite_gd3_in_place(&mut unchanged, &gd3("Before!")).unwrap());
+    assert_eq!(unchanged, bytes);
+
+    let mut builder = VgmBuilder::new();
+    builder.add_vgm_command(WaitSamples(1));
+    let untagged: Vec<u8> = builder.finalize().into();
+    assert_eq!(gd3_in_place(&untagged, &gd3("")).unwrap(), None);
+
+    let mut broken = bytes.clone();
+    broken[0x14..0x18].copy_from_slice(&0x10u32.to_le_bytes());
+    assert!(gd3_in_place(&broken, &gd3("")).is_err());
+}
+
+#[test]
+fn gd3_edit_of_fixtures_matches_full_serialization() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/vgm");
+    let mut checked = 0;
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|ext| ext != "vgm") {
+            continue;
+        }
+        let bytes = std::fs::read(&path).unwrap();
+        let original = VgmDocument::try_from(&bytes[..]).unwrap();
+        let sourcemap = original.sourcemap();
+        let mut edited = original.clone();
+        edited.gd3 = Some(Gd3 {
+            track_name_en: Some("Edited".to_string()),
+            ..Gd3::default()
+        });
+
+        let (out, report) = serialize_incremental(&edited, &original, &bytes, &sourcemap);
+        assert_eq!(out, Vec::<u8>::from(&edited), "{}", path.display());
+        assert_eq!(report.serialized_commands, 0, "{}", path.display());
+        checked += 1;
+    }
+    assert!(checked > 0);
+}
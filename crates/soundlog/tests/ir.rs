@@ -0,0 +1,225 @@
+use soundlog::chip::fnumber::{ChipTypeSpec, OpnaSpec};
+use soundlog::chip::{Chip, Ym2612Spec};
+use soundlog::vgm::command::{
+    DataBlock, Instance, SeekOffset, VgmCommand, WaitSamples, Ym2612Port0Address2AWriteAndWaitN,
+};
+use soundlog::vgm::ir::{ComposeOptions, IrPoint, PatchFeature, SongIr, compose};
+use soundlog::{VgmBuilder, VgmDocument};
+
+const CLOCK: u32 = 7_670_454;
+
+fn ym2612(port: u8, register: u8, value: u8) -> Ym2612Spec {
+    Ym2612Spec {
+        port,
+        register,
+        value,
+    }
+}
+
+// Algorithm 0 on channel `ch` (0-2) with its carrier S4 at `tl` and the
+// modulators silent.
+fn patch(builder: &mut VgmBuilder, ch: u8, tl: u8) {
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0xB0 + ch, 0x00));
+    for (offset, level) in [(0x0, 0x7F), (0x8, 0x7F), (0x4, 0x7F), (0xC, tl)] {
+        builder.add_chip_write(Instance::Primary, ym2612(0, 0x40 + offset + ch, level));
+        builder.add_chip_write(Instance::Primary, ym2612(0, 0x50 + offset + ch, 0x1F));
+    }
+}
+
+fn note(builder: &mut VgmBuilder, ch: u8, block: u8, fnum: u16) {
+    builder.add_chip_write(
+        Instance::Primary,
+        ym2612(0, 0xA4 + ch, block << 3 | (fnum >> 8) as u8),
+    );
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0xA0 + ch, fnum as u8));
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0x28, 0xF0 | ch));
+}
+
+fn song() -> VgmDocument {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, CLOCK);
+    builder.add_vgm_command(DataBlock {
+        marker: 0x66,
+        chip_instance: 0,
+        data_type: 0x00,
+        size: 4,
+        data: vec![0x80, 0x90, 0xA0, 0xB0],
+    });
+    patch(&mut builder, 0, 0x10);
+    note(&mut builder, 0, 4, 0x26A);
+    builder.add_vgm_command(WaitSamples(100));
+    // carrier fade and a pitch bend during the note
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0x4C, 0x18));
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0xA0, 0x80));
+    builder.add_vgm_command(WaitSamples(100));
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0x28, 0x00));
+    // the same voice, louder, then three more channels at once
+    patch(&mut builder, 0, 0x04);
+    note(&mut builder, 0, 4, 0x26A);
+    for ch in 1..3 {
+        patch(&mut builder, ch, 0x00);
+        note(&mut builder, ch, 3, 0x300 + ch as u16);
+    }
+    builder.add_chip_write(Instance::Primary, ym2612(1, 0xB0, 0x07));
+    builder.add_chip_write(Instance::Primary, ym2612(1, 0x4C, 0x00));
+    builder.add_chip_write(Instance::Primary, ym2612(1, 0xA4, 0x1A));
+    builder.add_chip_write(Instance::Primary, ym2612(1, 0xA0, 0x00));
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0x28, 0xF4));
+    builder.add_vgm_command(SeekOffset(0));
+    builder.add_vgm_command(Ym2612Port0Address2AWriteAndWaitN(2));
+    builder.add_vgm_command(WaitSamples(300));
+    builder.set_loop_offset(17);
+    builder.finalize()
+}
+
+fn freq(block: u8, fnum: u16) -> f32 {
+    OpnaSpec::fnum_block_to_freq(fnum as u32, block, CLOCK as f32).unwrap()
+}
+
+#[test]
+fn song_ir_collects_notes_patches_and_pcm() {
+    let doc = song();
+    let ir = SongIr::from_document(&doc);
+    assert_eq!(ir.length, 502);
+    assert_eq!(ir.loop_start, Some(200));
+    assert_eq!(ir.data_blocks.len(), 1);
+    assert_eq!(ir.pcm.len(), 2);
+    assert_eq!(ir.pcm[1].time, 200);
+    assert_eq!(
+        ir.pcm[1].command,
+        VgmCommand::YM2612Port0Address2AWriteAndWaitN(Ym2612Port0Address2AWriteAndWaitN(0))
+    );
+
+    assert_eq!(ir.channels.len(), 4);
+    let first = &ir.channels[0].notes[0];
+    assert_eq!((first.start, first.end), (0, 200));
+    assert_eq!(first.pitch.len(), 2);
+    assert!((first.pitch[0].value - freq(4, 0x26A)).abs() < 0.01);
+    assert_eq!(first.pitch[1].time, 100);
+    assert!((first.pitch[1].value - freq(4, 0x280)).abs() < 0.01);
+    assert_eq!(first.envelope.len(), 2);
+    assert_eq!(first.envelope[0].value, 0x10 as f32 * 0.75);
+    assert_eq!(first.envelope[1].value, 0x18 as f32 * 0.75);
+
+    // Patches are stored with the carrier at full volume, so the louder
+    // second note shares the patch of the first.
+    let second = &ir.channels[0].notes[1];
+    assert_eq!((second.start, second.end), (200, 502));
+    assert_eq!(second.envelope[0].value, 3.0);
+    assert_eq!(second.patch, first.patch);
+    assert_eq!(ir.patches.len(), 2);
+    assert_eq!(ir.patches[0].operators[3].tl, 0);
+}
+
+#[test]
+fn compose_ports_notes_and_keeps_pcm_on_the_same_chip() {
+    let ir = SongIr::from_document(&song());
+
+    let (psg, report) = compose(&ir, &ComposeOptions::new(Chip::Sn76489, 3_579_545)).unwrap();
+    assert_eq!(report.composed_notes, 4);
+    assert_eq!(report.dropped_notes, 1);
+    assert_eq!(report.dropped_pcm_events, 2);
+    assert_eq!(psg.header.sn76489_clock, 3_579_545);
+    assert_eq!(psg.header.ym2612_clock, 0);
+    assert_eq!(psg.header.total_samples, 502);
+    assert!(
+        psg.iter()
+            .all(|cmd| !matches!(cmd, VgmCommand::DataBlock(_)))
+    );
+    let ported = SongIr::from_document(&psg);
+    let note = &ported.channels[0].notes[0];
+    assert_eq!((note.start, note.end), (0, 200));
+    // one PSG step is 2 dB: 12 dB, then 18 dB
+    let levels: Vec<f32> = note.envelope.iter().map(|point| point.value).collect();
+    assert_eq!(levels, vec![12.0, 18.0]);
+    let expected = freq(4, 0x26A);
+    assert!((note.pitch[0].value / expected - 1.0).abs() < 0.01);
+
+    let (opn2, report) = compose(&ir, &ComposeOptions::new(Chip::Ym2612, CLOCK)).unwrap();
+    assert_eq!(report.composed_notes, 5);
+    assert_eq!(report.dropped_notes, 0);
+    assert_eq!(report.dropped_pcm_events, 0);
+    let round = SongIr::from_document(&opn2);
+    assert_eq!(round.loop_start, Some(200));
+    assert_eq!(round.pcm, ir.pcm);
+    assert_eq!(round.data_blocks, ir.data_blocks);
+    assert_eq!(round.patches, ir.patches);
+    let notes = |ir: &SongIr| -> Vec<(u64, u64, Vec<IrPoint>)> {
+        let mut notes: Vec<_> = ir
+            .channels
+            .iter()
+            .flat_map(|c| c.notes.iter())
+            .map(|n| (n.start, n.end, n.envelope.clone()))
+            .collect();
+        notes.sort_by_key(|(start, end, _)| (*start, *end));
+        notes
+    };
+    assert_eq!(notes(&round), notes(&ir));
+
+    assert!(compose(&ir, &ComposeOptions::new(Chip::SegaPcm, 3_579_545)).is_none());
+}
+
+#[test]
+fn compose_ports_patches_to_opm_and_opl() {
+    let mut ir = SongIr::from_document(&song());
+    // Patch 0 plays the first four notes; give it features that do not port.
+    let patch = &mut ir.patches[0];
+    patch.feedback = 3;
+    patch.operators[0].tl = 0x20;
+    patch.operators[3].ssg_eg = 0x08;
+    patch.operators[3].dt_mul = 0x31;
+    let writes = |doc: &VgmDocument| -> Vec<(u8, u8)> {
+        doc.iter()
+            .filter_map(|cmd| cmd.register_write())
+            .map(|write| (write.register as u8, write.value as u8))
+            .collect()
+    };
+
+    let (opm, report) = compose(&ir, &ComposeOptions::new(Chip::Ym2151, 3_579_545)).unwrap();
+    assert_eq!(report.composed_notes, 5);
+    assert_eq!(report.dropped_pcm_events, 2);
+    assert_eq!(
+        report.untranslated.into_iter().collect::<Vec<_>>(),
+        vec![(PatchFeature::SsgEg, 4)]
+    );
+    let opm_writes = writes(&opm);
+    // 251.2 Hz is B3 (key code 0x3D) plus 18/64 of a semitone.
+    assert!(opm_writes.contains(&(0x28, 0x3D)));
+    assert!(opm_writes.contains(&(0x30, 18 << 2)));
+    assert!(opm_writes.contains(&(0x20, 0xC0 | 3 << 3)));
+    assert!(opm_writes.contains(&(0x08, 0x78)));
+    let ported = SongIr::from_document(&opm);
+    assert_eq!(ported.channels[0].notes[0].start, 0);
+    assert_eq!(ported.channels[0].notes[0].end, 200);
+
+    let (opl, report) = compose(&ir, &ComposeOptions::new(Chip::Ym3812, 3_579_545)).unwrap();
+    assert_eq!(report.composed_notes, 5);
+    // The algorithm 7 patch of the fifth note has four audible operators.
+    assert_eq!(
+        report.untranslated.into_iter().collect::<Vec<_>>(),
+        vec![
+            (PatchFeature::SsgEg, 4),
+            (PatchFeature::Operators, 5),
+            (PatchFeature::Feedback, 4),
+            (PatchFeature::Detune, 4),
+        ]
+    );
+    let opl_writes = writes(&opl);
+    // F-number 0x296 in block 3, keyed on and later off
+    assert!(opl_writes.contains(&(0xA0, 0x96)));
+    assert!(opl_writes.contains(&(0xB0, 0x2E)));
+    assert!(opl_writes.contains(&(0xB0, 0x0E)));
+    // S3 modulates S4 without the feedback of S1
+    assert!(opl_writes.contains(&(0xC0, 0x00)));
+    let ported = SongIr::from_document(&opl);
+    let notes: Vec<_> = ported
+        .channels
+        .iter()
+        .flat_map(|c| c.notes.iter())
+        .map(|n| (n.start, n.end))
+        .collect();
+    assert_eq!(notes.len(), 5);
+    assert!(notes.contains(&(0, 200)));
+    let note = &ported.channels[0].notes[0];
+    assert!((note.pitch[0].value / freq(4, 0x26A) - 1.0).abs() < 0.01);
+}
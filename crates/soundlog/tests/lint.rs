@@ -0,0 +1,364 @@
+use soundlog::chip::{Chip, PsgSpec, Ym2151Spec, Ym2612Spec};
+use soundlog::vgm::command::{DacStreamChipType, Instance, SetupStreamControl, WaitSamples};
+use soundlog::vgm::header::ChipId;
+use soundlog::vgm::lint::{LintIssue, LintOptions, lint, lint_commands, lint_with_options};
+use soundlog::vgm::stream::{StreamResult, VgmStream};
+use soundlog::{BuildError, VgmBuilder, VgmDocument};
+
+fn ym2612(builder: &mut VgmBuilder, port: u8, writes: &[(u8, u8)]) {
+    for &(register, value) in writes {
+        builder.add_chip_write(
+            Instance::Primary,
+            Ym2612Spec {
+                port,
+                register,
+                value,
+            },
+        );
+    }
+}
+
+fn ym2151(builder: &mut VgmBuilder, writes: &[(u8, u8)]) {
+    for &(register, value) in writes {
+        builder.add_chip_write(Instance::Primary, Ym2151Spec { register, value });
+        builder.add_vgm_command(WaitSamples(10));
+    }
+}
+
+fn silent_channels(doc: &VgmDocument) -> Vec<(Chip, u8, u32)> {
+    lint(doc)
+        .issues
+        .into_iter()
+        .filter_map(|issue| match issue {
+            LintIssue::SilentChannel {
+                chip,
+                channel,
+                key_ons,
+                ..
+            } => Some((chip, channel, key_ons)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn unused_secondary_instance_is_reported() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+    builder.register_chip(Chip::Sn76489, Instance::Secondary, 3_579_545);
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x90 });
+    let doc = builder.finalize();
+
+    let report = lint(&doc);
+
+    assert!(!report.is_clean());
+    assert_eq!(
+        report.issues,
+        vec![LintIssue::UnusedChip {
+            chip: Chip::Sn76489,
+            instance: Instance::Secondary,
+        }]
+    );
+    assert_eq!(
+        report.issues[0].to_string(),
+        "Sn76489 (Secondary) is registered in the header but never written"
+    );
+}
+
+#[test]
+fn chip_driven_by_dac_stream_is_used() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+    builder.add_vgm_command(SetupStreamControl {
+        stream_id: 0,
+        chip_type: DacStreamChipType {
+            chip_id: ChipId::Ym2612,
+            instance: Instance::Primary,
+        },
+        write_port: 0,
+        write_command: 0x2A,
+    });
+    let doc = builder.finalize();
+
+    assert!(lint(&doc).is_clean());
+}
+
+#[test]
+fn ym2612_channel_with_muted_carrier_is_silent() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+    // channel 0: algorithm 0, carrier S4 muted
+    ym2612(&mut builder, 0, &[(0xB0, 0x00), (0x4C, 0x7F), (0x28, 0xF0)]);
+    // channel 4 (port 1): algorithm 4, carriers S2 and S4 muted, S1 loud
+    ym2612(
+        &mut builder,
+        1,
+        &[(0xB1, 0x04), (0x41, 0x00), (0x49, 0x7F), (0x4D, 0x7F)],
+    );
+    ym2612(&mut builder, 0, &[(0x28, 0xF5)]);
+    // channel 1: muted at key-on, raised while keyed on
+    ym2612(&mut builder, 0, &[(0x4D, 0x7F), (0x28, 0xF1), (0x4D, 0x20)]);
+    // channel 2: keyed on with loud defaults
+    ym2612(&mut builder, 0, &[(0x28, 0xF2)]);
+    let doc = builder.finalize();
+
+    assert_eq!(
+        silent_channels(&doc),
+        vec![(Chip::Ym2612, 0, 1), (Chip::Ym2612, 4, 1)]
+    );
+}
+
+#[test]
+fn ym2151_counts_key_ons_of_silent_channel() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2151, Instance::Primary, 3_579_545);
+    // channel 3: algorithm 7, every operator muted
+    ym2151(
+        &mut builder,
+        &[
+            (0x23, 0x07),
+            (0x63, 0x7F),
+            (0x6B, 0x7F),
+            (0x73, 0x7F),
+            (0x7B, 0x7F),
+        ],
+    );
+    ym2151(&mut builder, &[(0x08, 0x7B), (0x08, 0x03), (0x08, 0x7B)]);
+    // channel 5: algorithm 4, only the M2 modulator keyed on
+    ym2151(&mut builder, &[(0x25, 0x04), (0x08, 0x25)]);
+    let doc = builder.finalize();
+
+    assert_eq!(
+        silent_channels(&doc),
+        vec![(Chip::Ym2151, 3, 2), (Chip::Ym2151, 5, 1)]
+    );
+    assert!(
+        lint(&doc)
+            .issues
+            .iter()
+            .all(|issue| !matches!(issue, LintIssue::UnusedChip { .. }))
+    );
+}
+
+#[test]
+fn strict_mode_reports_writes_to_chips_without_clock() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x90 });
+    builder.add_vgm_command(WaitSamples(10));
+    ym2612(&mut builder, 0, &[(0x22, 0x00), (0x27, 0x00)]);
+    builder.add_chip_write(Instance::Secondary, PsgSpec { value: 0x90 });
+    let doc = builder.finalize();
+
+    assert!(lint(&doc).is_clean());
+    let report = lint_with_options(
+        &doc,
+        &LintOptions {
+            strict: true,
+            ..LintOptions::default()
+        },
+    );
+    assert_eq!(
+        report.issues,
+        vec![
+            LintIssue::UnregisteredChip {
+                chip: Chip::Ym2612,
+                instance: Instance::Primary,
+                first_write: 2,
+            },
+            LintIssue::MissingDualChipBit {
+                chip: Chip::Sn76489,
+                first_write: 4,
+            },
+        ]
+    );
+    assert_eq!(
+        report.issues[0].to_string(),
+        "Ym2612 (Primary) is written by command 2 but has no clock in the header"
+    );
+}
+
+#[test]
+fn strict_builder_rejects_unregistered_chip() {
+    let build = |register: bool| {
+        let mut builder = VgmBuilder::new().into_strict();
+        if register {
+            builder.register_chip(Chip::Ym2151, Instance::Primary, 3_579_545);
+        }
+        ym2151(&mut builder, &[(0x08, 0x78)]);
+        builder
+    };
+
+    let error = build(false).try_finalize().unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "strict mode: Ym2151 (Primary) is written by command 0 but has no clock in the header"
+    );
+    match error {
+        BuildError::StrictViolation(issue) => assert_eq!(
+            issue,
+            LintIssue::UnregisteredChip {
+                chip: Chip::Ym2151,
+                instance: Instance::Primary,
+                first_write: 0,
+            }
+        ),
+    }
+    assert!(build(true).try_finalize().is_ok());
+}
+
+#[test]
+fn secondary_writes_need_dual_chip_bit() {
+    let build = |auto_dual_chip: bool| {
+        let mut builder = VgmBuilder::new();
+        builder.set_auto_dual_chip(auto_dual_chip);
+        builder.register_chip(Chip::Ym2151, Instance::Primary, 3_579_545);
+        ym2151(&mut builder, &[(0x20, 0xC7)]);
+        builder.add_chip_write(
+            Instance::Secondary,
+            Ym2151Spec {
+                register: 0x20,
+                value: 0xC7,
+            },
+        );
+        builder
+    };
+
+    let doc = build(false).finalize();
+    assert_eq!(
+        lint_with_options(
+            &doc,
+            &LintOptions {
+                strict: true,
+                ..LintOptions::default()
+            }
+        )
+        .issues,
+        vec![LintIssue::MissingDualChipBit {
+            chip: Chip::Ym2151,
+            first_write: 2,
+        }]
+    );
+    match build(false).into_strict().try_finalize() {
+        Err(BuildError::StrictViolation(issue)) => assert_eq!(
+            issue,
+            LintIssue::MissingDualChipBit {
+                chip: Chip::Ym2151,
+                first_write: 2,
+            }
+        ),
+        other => panic!("expected a dual-chip bit error, got {:?}", other),
+    }
+
+    let doc = build(true).into_strict().try_finalize().unwrap();
+    assert_eq!(doc.header.ym2151_clock, 3_579_545 | 0x8000_0000);
+}
+
+#[test]
+fn write_bursts_and_rates_are_reported_when_enabled() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+    builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+    // Bursts of 2, 5 and 4 YM2612 writes, the PSG write does not count.
+    ym2612(&mut builder, 0, &[(0x30, 0), (0x34, 0)]);
+    builder.add_vgm_command(WaitSamples(10));
+    ym2612(&mut builder, 0, &[(0x30, 0), (0x34, 0), (0x38, 0)]);
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x9F });
+    ym2612(&mut builder, 0, &[(0x3C, 0), (0x40, 0)]);
+    builder.add_vgm_command(WaitSamples(100));
+    ym2612(
+        &mut builder,
+        0,
+        &[(0x30, 0), (0x34, 0), (0x38, 0), (0x3C, 0)],
+    );
+    let doc = builder.finalize();
+
+    assert!(lint(&doc).is_clean());
+    let options = LintOptions {
+        max_burst: Some(3),
+        max_writes_per_ms: Some(6),
+        ..LintOptions::default()
+    };
+    let issues = lint_with_options(&doc, &options).issues;
+    assert_eq!(
+        issues,
+        vec![
+            LintIssue::WriteBurst {
+                chip: Chip::Ym2612,
+                instance: Instance::Primary,
+                first_write: 3,
+                length: 5,
+                bursts: 2,
+            },
+            LintIssue::WriteRate {
+                chip: Chip::Ym2612,
+                instance: Instance::Primary,
+                index: 8,
+                time: 10,
+                writes: 7,
+            },
+        ]
+    );
+    assert_eq!(
+        issues[0].to_string(),
+        "Ym2612 (Primary) is written 5 times without a wait from command 3 (2 burst(s) over the limit)"
+    );
+
+    // The same checks on the commands played back by a stream.
+    let mut stream = VgmStream::from_document(doc);
+    let mut commands = Vec::new();
+    while let Some(Ok(StreamResult::Command(command))) = stream.next() {
+        commands.push(command);
+    }
+    let report = lint_commands(&commands, &options);
+    assert_eq!(report.issues.len(), 2);
+    assert!(lint_commands(&commands, &LintOptions::default()).is_clean());
+}
+
+#[test]
+fn strict_mode_reports_commands_newer_than_header() {
+    let build = |auto_version: bool| {
+        let mut builder = VgmBuilder::new();
+        builder.set_version(0x150).set_auto_version(auto_version);
+        builder.register_chip(Chip::Ym2151, Instance::Primary, 3_579_545);
+        builder.register_chip(Chip::Ym2151, Instance::Secondary, 3_579_545);
+        ym2151(&mut builder, &[(0x08, 0x00)]);
+        for _ in 0..2 {
+            builder.add_chip_write(
+                Instance::Secondary,
+                Ym2151Spec {
+                    register: 0x08,
+                    value: 0x00,
+                },
+            );
+        }
+        builder.finalize()
+    };
+    let strict = LintOptions {
+        strict: true,
+        ..LintOptions::default()
+    };
+
+    let doc = build(false);
+    assert_eq!(doc.header.version, 0x150);
+    assert_eq!(doc.required_version(), 0x151);
+    let report = lint_with_options(&doc, &strict);
+    assert_eq!(
+        report.issues,
+        vec![LintIssue::CommandTooNew {
+            opcode: 0xA4,
+            first_write: 2,
+            min_version: 0x151,
+            version: 0x150,
+        }]
+    );
+    assert_eq!(
+        report.issues[0].to_string(),
+        "opcode 0xA4 (command 2) needs VGM 1.51 but the header declares 1.50"
+    );
+    assert!(lint(&doc).is_clean());
+
+    let doc = build(true);
+    assert_eq!(doc.header.version, 0x151);
+    assert!(lint_with_options(&doc, &strict).is_clean());
+}
@@ -0,0 +1,112 @@
+use soundlog::VgmBuilder;
+use soundlog::chip::{Chip, Ym2612Spec};
+use soundlog::vgm::command::{
+    DataBlock, Instance, LengthMode, SetStreamData, SetStreamFrequency, StartStream, WaitSamples,
+};
+use soundlog::vgm::loop_check::{LoopIssue, check_loop};
+
+fn ym2612(register: u8, value: u8) -> Ym2612Spec {
+    Ym2612Spec {
+        port: 0,
+        register,
+        value,
+    }
+}
+
+#[test]
+fn loop_end_state_is_compared_with_loop_start() {
+    let build = |reinit: bool| {
+        let mut builder = VgmBuilder::new();
+        builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+        builder.add_chip_write(Instance::Primary, ym2612(0x4C, 0x10));
+        builder.add_chip_write(Instance::Primary, ym2612(0x28, 0xF0));
+        builder.add_vgm_command(WaitSamples(100));
+        let loop_index = 3;
+        if reinit {
+            builder.add_chip_write(Instance::Primary, ym2612(0x4C, 0x10));
+            builder.add_chip_write(Instance::Primary, ym2612(0x28, 0xF0));
+        }
+        builder.add_vgm_command(WaitSamples(100));
+        builder.add_chip_write(Instance::Primary, ym2612(0x4C, 0x7F));
+        builder.add_chip_write(Instance::Primary, ym2612(0x28, 0x00));
+        builder.add_vgm_command(WaitSamples(100));
+        builder.set_loop_index(loop_index);
+        builder.finalize()
+    };
+
+    let report = check_loop(&build(false));
+    assert_eq!(report.loop_start, Some(100));
+    assert_eq!(
+        report.issues,
+        vec![
+            LoopIssue::MissingNote {
+                chip: Chip::Ym2612,
+                instance: Instance::Primary,
+                channel: 0,
+            },
+            LoopIssue::PatchMismatch {
+                chip: Chip::Ym2612,
+                instance: Instance::Primary,
+                port: 0,
+                register: 0x4C,
+                at_loop_start: 0x10,
+                after_jump: 0x7F,
+            },
+        ]
+    );
+    // writes at the loop point run again after the jump
+    assert!(check_loop(&build(true)).is_clean());
+}
+
+#[test]
+fn dac_stream_position_is_compared_at_loop_start() {
+    let build = |restart: bool| {
+        let mut builder = VgmBuilder::new();
+        builder.add_vgm_command(DataBlock {
+            marker: 0x66,
+            chip_instance: 0,
+            data_type: 0x00,
+            size: 1000,
+            data: vec![0x80; 1000],
+        });
+        builder.add_vgm_command(SetStreamData {
+            stream_id: 0,
+            data_bank_id: 0x00,
+            step_size: 1,
+            step_base: 0,
+        });
+        builder.add_vgm_command(SetStreamFrequency {
+            stream_id: 0,
+            frequency: 44_100,
+        });
+        let start = StartStream {
+            stream_id: 0,
+            data_start_offset: 0,
+            length_mode: LengthMode::PlayUntilEnd {
+                reverse: false,
+                looped: false,
+            },
+            data_length: 0,
+        };
+        builder.add_vgm_command(start.clone());
+        builder.add_vgm_command(WaitSamples(100));
+        let loop_index = 5;
+        if restart {
+            builder.add_vgm_command(start);
+        }
+        builder.add_vgm_command(WaitSamples(200));
+        builder.set_loop_index(loop_index);
+        builder.finalize()
+    };
+
+    let report = check_loop(&build(false));
+    assert_eq!(
+        report.issues,
+        vec![LoopIssue::StreamMismatch {
+            stream_id: 0,
+            at_loop_start: Some(100),
+            after_jump: Some(300),
+        }]
+    );
+    assert!(check_loop(&build(true)).is_clean());
+}
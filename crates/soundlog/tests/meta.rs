@@ -1,4 +1,4 @@
-use soundlog::meta::Gd3;
+use soundlog::meta::{Gd3, Gd3Language, Gd3Normalization};
 use soundlog::{VgmDocument, VgmHeader};
 
 #[test]
@@ -69,3 +69,154 @@ fn test_vgmdocument_includes_gd3_and_header_offset() {
     let hdr_off = u32::from_le_bytes(bytes[0x14..0x18].try_into().unwrap());
     assert_eq!(hdr_off, (pos as u32).wrapping_sub(0x14));
 }
+
+#[test]
+fn test_gd3_from_pairs_accepts_field_names_and_aliases() {
+    let gd3 = Gd3::from_pairs([
+        ("title", "Green Hill"),
+        ("Title-JP", "グリーンヒル"),
+        ("game_name_en", "Sonic"),
+        ("system", "Mega Drive"),
+        ("author", "someone"),
+        ("date", "1991"),
+        ("ripper", "me"),
+        ("notes", "first"),
+        ("notes", "second"),
+        ("game-jp", ""),
+    ])
+    .unwrap();
+
+    assert_eq!(
+        gd3,
+        Gd3 {
+            track_name_en: Some("Green Hill".to_string()),
+            track_name_origin: Some("グリーンヒル".to_string()),
+            game_name_en: Some("Sonic".to_string()),
+            system_name_en: Some("Mega Drive".to_string()),
+            author_name_en: Some("someone".to_string()),
+            release_date: Some("1991".to_string()),
+            creator: Some("me".to_string()),
+            notes: Some("second".to_string()),
+            ..Default::default()
+        }
+    );
+
+    let mut edited = gd3.clone();
+    edited
+        .set_pairs([("notes", ""), ("creator", "you")])
+        .unwrap();
+    assert_eq!(edited.notes, None);
+    assert_eq!(edited.creator.as_deref(), Some("you"));
+
+    match Gd3::from_pairs([("title", "x"), ("composer", "y")]) {
+        Err(soundlog::ParseError::Other(message)) => {
+            assert!(message.starts_with("unknown GD3 field \"composer\""))
+        }
+        other => panic!("expected an unknown field error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_gd3_round_trips_surrogate_pairs_and_bom() {
+    let gd3 = Gd3 {
+        track_name_en: Some("\u{FEFF}Title".to_string()),
+        track_name_origin: Some("𝄞 音楽 🎵".to_string()),
+        notes: Some("line1\nline2".to_string()),
+        ..Default::default()
+    };
+    let bytes = gd3.to_bytes();
+    assert_eq!(bytes.len(), gd3.byte_len());
+    assert_eq!(Gd3::try_from(bytes.as_slice()).unwrap(), gd3);
+
+    // The ranges point at the UTF-16LE bytes of every field.
+    let ranges = gd3.field_ranges();
+    for (field, (offset, len)) in gd3.fields().into_iter().zip(ranges) {
+        let units: Vec<u16> = bytes[offset..offset + len]
+            .chunks(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        assert_eq!(
+            String::from_utf16(&units).unwrap(),
+            field.clone().unwrap_or_default()
+        );
+        assert_eq!(&bytes[offset + len..offset + len + 2], &[0, 0]);
+    }
+    // Two code units for each character outside the BMP.
+    assert_eq!(Gd3::encoded_len("𝄞 音楽 🎵"), 16);
+    assert_eq!(ranges[1], (12 + 12 + 2, 16));
+}
+
+#[test]
+fn test_gd3_embedded_nul_does_not_shift_fields() {
+    let gd3 = Gd3 {
+        track_name_en: Some("a\0b".to_string()),
+        game_name_en: Some("Game".to_string()),
+        ..Default::default()
+    };
+    let parsed = Gd3::try_from(gd3.to_bytes().as_slice()).unwrap();
+    assert_eq!(parsed.track_name_en.as_deref(), Some("ab"));
+    assert_eq!(parsed.game_name_en.as_deref(), Some("Game"));
+    assert_eq!(gd3.field_ranges()[0], (12, 4));
+    assert_eq!(gd3.byte_len(), gd3.to_bytes().len());
+}
+
+#[test]
+fn test_gd3_normalize() {
+    let mut gd3 = Gd3 {
+        track_name_en: Some(" Cafe\u{301} ".to_string()),
+        track_name_origin: Some("\u{FEFF}ポケモン".to_string()),
+        notes: Some("\u{FEFF} ".to_string()),
+        ..Default::default()
+    };
+    let unchanged = gd3.clone();
+    gd3.normalize(&Gd3Normalization::new());
+    assert_eq!(gd3, unchanged);
+
+    gd3.normalize(&Gd3Normalization::new().with_strip_bom(true).with_trim(true));
+    assert_eq!(gd3.track_name_en.as_deref(), Some("Cafe\u{301}"));
+    assert_eq!(gd3.track_name_origin.as_deref(), Some("ポケモン"));
+    assert_eq!(gd3.notes, None);
+}
+
+#[cfg(feature = "nfc")]
+#[test]
+fn test_gd3_normalize_nfc() {
+    let mut gd3 = Gd3 {
+        // "Café" and "ポケモン" with combining marks.
+        track_name_en: Some("Cafe\u{301}".to_string()),
+        track_name_origin: Some("ホ\u{309A}ケモン".to_string()),
+        // Vietnamese: two marks on one base, not in canonical order.
+        author_name_en: Some("e\u{302}\u{323}".to_string()),
+        ..Default::default()
+    };
+    gd3.normalize(&Gd3Normalization::new().with_nfc(true));
+    assert_eq!(gd3.track_name_en.as_deref(), Some("Café"));
+    assert_eq!(gd3.track_name_origin.as_deref(), Some("ポケモン"));
+    assert_eq!(gd3.author_name_en.as_deref(), Some("ệ"));
+}
+
+#[test]
+fn test_gd3_localized_getters_fall_back() {
+    let gd3 = Gd3 {
+        track_name_en: Some("Title".to_string()),
+        track_name_origin: Some("タイトル".to_string()),
+        game_name_en: Some(" \u{FEFF}\t".to_string()),
+        game_name_origin: Some("ゲーム".to_string()),
+        system_name_en: Some("Sega\tMega Drive /\r\n Genesis".to_string()),
+        ..Default::default()
+    };
+
+    assert_eq!(gd3.title(Gd3Language::English).as_deref(), Some("Title"));
+    assert_eq!(
+        gd3.title(Gd3Language::Original).as_deref(),
+        Some("タイトル")
+    );
+    // A field with nothing to display falls back too.
+    assert_eq!(gd3.game(Gd3Language::English).as_deref(), Some("ゲーム"));
+    assert_eq!(
+        gd3.system(Gd3Language::Original).as_deref(),
+        Some("Sega Mega Drive / Genesis")
+    );
+    assert_eq!(gd3.author(Gd3Language::default()), None);
+    assert_eq!(Gd3::display_text("a\u{7}b  c"), Some("ab c".to_string()));
+}
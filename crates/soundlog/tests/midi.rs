@@ -0,0 +1,145 @@
+#![cfg(feature = "midi")]
+
+use soundlog::chip::Ym2612Spec;
+use soundlog::vgm::command::VgmCommand;
+use soundlog::vgm::midi::{FmPatch, MidiFmMapper, MidiLogger, MidiMessage, VOICES};
+
+const CLOCK: u32 = 7_670_454;
+
+fn note_on(channel: u8, note: u8, velocity: u8) -> MidiMessage {
+    MidiMessage::NoteOn {
+        channel,
+        note,
+        velocity,
+    }
+}
+
+fn writes_to(writes: &[Ym2612Spec], port: u8, register: u8) -> Vec<u8> {
+    writes
+        .iter()
+        .filter(|w| w.port == port && w.register == register)
+        .map(|w| w.value)
+        .collect()
+}
+
+#[test]
+fn parses_note_and_control_messages() {
+    assert_eq!(
+        MidiMessage::parse(&[0x93, 60, 100]),
+        Some(note_on(3, 60, 100))
+    );
+    assert_eq!(
+        MidiMessage::parse(&[0x93, 60, 0]),
+        Some(MidiMessage::NoteOff {
+            channel: 3,
+            note: 60
+        })
+    );
+    assert_eq!(
+        MidiMessage::parse(&[0xB0, 7, 90]),
+        Some(MidiMessage::ControlChange {
+            channel: 0,
+            controller: 7,
+            value: 90
+        })
+    );
+    // program change and truncated messages are not mapped
+    assert_eq!(MidiMessage::parse(&[0xC0, 5]), None);
+    assert_eq!(MidiMessage::parse(&[0x90, 60]), None);
+}
+
+#[test]
+fn note_on_sets_frequency_and_keys_on() {
+    let mut mapper = MidiFmMapper::new(CLOCK, FmPatch::default());
+
+    let writes = mapper.handle(&note_on(0, 69, 127));
+
+    // A4 = 440 Hz: block 4, fnum 1083 at the NTSC clock
+    assert_eq!(
+        writes_to(&writes, 0, 0xA4),
+        vec![(4 << 3) | (1083u16 >> 8) as u8]
+    );
+    assert_eq!(writes_to(&writes, 0, 0xA0), vec![(1083 & 0xFF) as u8]);
+    // full velocity and the default volume 100 leave S4 slightly attenuated
+    assert_eq!(writes_to(&writes, 0, 0x4C), vec![6]);
+    assert_eq!(writes.last().unwrap().value, 0xF0);
+
+    let writes = mapper.handle(&MidiMessage::NoteOff {
+        channel: 0,
+        note: 69,
+    });
+    assert_eq!(writes_to(&writes, 0, 0x28), vec![0x00]);
+}
+
+#[test]
+fn oldest_voice_is_stolen() {
+    let mut mapper = MidiFmMapper::new(CLOCK, FmPatch::default());
+    let key_ons: Vec<u8> = (0..VOICES as u8)
+        .map(|i| {
+            *writes_to(&mapper.handle(&note_on(0, 60 + i, 100)), 0, 0x28)
+                .last()
+                .unwrap()
+        })
+        .collect();
+    // channels 3-5 are on port 1 (key-on channel codes 4-6)
+    assert_eq!(key_ons, vec![0xF0, 0xF1, 0xF2, 0xF4, 0xF5, 0xF6]);
+
+    // the 7th note takes channel 0: key off, then key on
+    let writes = mapper.handle(&note_on(0, 80, 100));
+    assert_eq!(writes_to(&writes, 0, 0x28), vec![0x00, 0xF0]);
+    // the stolen note no longer releases anything
+    let writes = mapper.handle(&MidiMessage::NoteOff {
+        channel: 0,
+        note: 60,
+    });
+    assert!(writes.is_empty());
+}
+
+#[test]
+fn volume_and_pan_follow_control_changes() {
+    let mut mapper = MidiFmMapper::new(CLOCK, FmPatch::default());
+    mapper.handle(&note_on(1, 60, 127));
+    mapper.handle(&note_on(2, 64, 127));
+
+    let cc = |controller, value| MidiMessage::ControlChange {
+        channel: 1,
+        controller,
+        value,
+    };
+    // only the voice of MIDI channel 1 (FM channel 0) changes
+    let writes = mapper.handle(&cc(7, 127));
+    assert_eq!(writes_to(&writes, 0, 0x4C), vec![0]);
+    assert!(writes.iter().all(|w| w.port == 0 && w.register & 3 == 0));
+    assert_eq!(writes_to(&mapper.handle(&cc(10, 0)), 0, 0xB4), vec![0x80]);
+    assert_eq!(writes_to(&mapper.handle(&cc(7, 0)), 0, 0x4C), vec![0x7F]);
+    assert_eq!(writes_to(&mapper.handle(&cc(123, 0)), 0, 0x28), vec![0x00]);
+}
+
+#[test]
+fn logger_records_writes_with_timing() {
+    let mut logger = MidiLogger::new(CLOCK, FmPatch::default());
+    let init = logger.mapper().init_writes().len();
+
+    logger.record(100, &note_on(0, 60, 100));
+    logger.record(
+        100_000,
+        &MidiMessage::NoteOff {
+            channel: 0,
+            note: 60,
+        },
+    );
+    let doc = logger.finish_at(144_100);
+
+    assert_eq!(doc.header.ym2612_clock, CLOCK);
+    assert_eq!(doc.header.total_samples, 144_100);
+    let waits: Vec<u16> = doc
+        .commands
+        .iter()
+        .filter_map(|c| match c {
+            VgmCommand::WaitSamples(w) => Some(w.0),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(waits, vec![100, 65_535, 34_365, 44_100]);
+    assert!(matches!(doc.commands[init], VgmCommand::WaitSamples(_)));
+}
@@ -0,0 +1,106 @@
+use soundlog::chip::Chip;
+use soundlog::vgm::command::VgmCommand;
+use soundlog::vgm::opcode::{
+    OpcodeKind, OpcodeLen, chip_min_version, command_len, kind_of, length_of, min_version,
+    opcode_of,
+};
+
+// A command with the given opcode and zero operands, long enough for every
+// fixed-length command. The `0x66` marker makes data blocks and PCM RAM
+// writes valid.
+fn command_bytes(opcode: u8) -> Vec<u8> {
+    let mut bytes = vec![opcode, 0x66];
+    bytes.resize(16, 0);
+    bytes
+}
+
+#[test]
+fn length_table_agrees_with_parser() {
+    for opcode in 0..=0xFFu8 {
+        let bytes = command_bytes(opcode);
+        let (command, len) = VgmCommand::parse_one(&bytes)
+            .unwrap_or_else(|e| panic!("opcode {:#04X}: {:?}", opcode, e));
+        assert_eq!(command_len(&bytes), Some(len), "opcode {:#04X}", opcode);
+        match length_of(opcode) {
+            OpcodeLen::Fixed(fixed) => assert_eq!(fixed, len, "opcode {:#04X}", opcode),
+            OpcodeLen::Variable => assert_eq!(len, 7, "opcode {:#04X}", opcode),
+            OpcodeLen::Unknown => {
+                assert!(
+                    matches!(command, VgmCommand::UnknownCommand(_)),
+                    "opcode {:#04X}: {:?}",
+                    opcode,
+                    command
+                );
+                assert_eq!(kind_of(opcode), OpcodeKind::Unknown);
+            }
+        }
+    }
+}
+
+#[test]
+fn kinds_agree_with_parsed_commands() {
+    for opcode in 0..=0xFFu8 {
+        let (command, _) = VgmCommand::parse_one(&command_bytes(opcode)).unwrap();
+        let kind = kind_of(opcode);
+        let expected = match command {
+            VgmCommand::YM2612Port0Address2AWriteAndWaitN(_) => OpcodeKind::WriteAndWait,
+            VgmCommand::AY8910StereoMask(_) => OpcodeKind::ChipWrite,
+            _ if command.register_write().is_some() => OpcodeKind::ChipWrite,
+            _ if command.wait_samples() > 0 => OpcodeKind::Wait,
+            VgmCommand::DataBlock(_) | VgmCommand::PcmRamWrite(_) | VgmCommand::SeekOffset(_) => {
+                OpcodeKind::Data
+            }
+            VgmCommand::SetupStreamControl(_)
+            | VgmCommand::SetStreamData(_)
+            | VgmCommand::SetStreamFrequency(_)
+            | VgmCommand::StartStream(_)
+            | VgmCommand::StopStream(_)
+            | VgmCommand::StartStreamFastCall(_) => OpcodeKind::StreamControl,
+            VgmCommand::EndOfData(_) => OpcodeKind::EndOfData,
+            VgmCommand::ReservedU8Write(_)
+            | VgmCommand::ReservedU16Write(_)
+            | VgmCommand::ReservedU24Write(_)
+            | VgmCommand::ReservedU32Write(_) => OpcodeKind::Reserved,
+            VgmCommand::UnknownCommand(_) => OpcodeKind::Unknown,
+            ref other => panic!("opcode {:#04X}: unexpected {:?}", opcode, other),
+        };
+        assert_eq!(kind, expected, "opcode {:#04X}: {:?}", opcode, command);
+    }
+}
+
+#[test]
+fn command_len_reads_data_block_size() {
+    let mut block = vec![0x67, 0x66, 0x00];
+    block.extend_from_slice(&(0x8000_0000u32 | 300).to_le_bytes());
+    assert_eq!(command_len(&block), Some(307));
+    assert_eq!(command_len(&block[..5]), None);
+    assert_eq!(command_len(&[]), None);
+    assert_eq!(command_len(&[0x00]), Some(1));
+}
+
+#[test]
+fn min_versions_cover_defined_opcodes_and_their_chips() {
+    for opcode in 0..=0xFFu8 {
+        let version = min_version(opcode);
+        let defined = !matches!(kind_of(opcode), OpcodeKind::Reserved | OpcodeKind::Unknown);
+        assert_eq!(version.is_some(), defined, "opcode {:#04X}", opcode);
+
+        let (command, _) = VgmCommand::parse_one(&command_bytes(opcode)).unwrap();
+        if defined {
+            assert_eq!(opcode_of(&command), opcode);
+        }
+        // YM2612 and YM2151 were written before they had clocks of their own.
+        if let Some(write) = command.register_write()
+            && !(0x52..=0x54).contains(&opcode)
+        {
+            assert!(
+                version.unwrap() >= chip_min_version(&write.chip),
+                "opcode {:#04X}: {:?}",
+                opcode,
+                write.chip
+            );
+        }
+    }
+    assert_eq!(chip_min_version(&Chip::Ym2612), 0x110);
+    assert_eq!(min_version(0x40), Some(0x172));
+}
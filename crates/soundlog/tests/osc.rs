@@ -0,0 +1,156 @@
+#![cfg(feature = "osc")]
+
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use soundlog::VgmBuilder;
+use soundlog::VgmCallbackStream;
+use soundlog::chip::event::{RhythmInstrument, StateEvent, ToneInfo};
+use soundlog::chip::state::Ym2612State;
+use soundlog::chip::{Chip, Ym2612Spec};
+use soundlog::vgm::callback_stream::SampleClock;
+use soundlog::vgm::command::{Instance, WaitSamples};
+use soundlog::vgm::osc::{OscBridge, event_message};
+
+fn connected_pair() -> (UdpSocket, UdpSocket) {
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    receiver
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+    sender.connect(receiver.local_addr().unwrap()).unwrap();
+    (sender, receiver)
+}
+
+fn recv(socket: &UdpSocket) -> Vec<u8> {
+    let mut buf = [0u8; 1024];
+    let n = socket.recv(&mut buf).unwrap();
+    buf[..n].to_vec()
+}
+
+#[test]
+fn key_on_message_layout() {
+    let event = StateEvent::KeyOn {
+        channel: 2,
+        tone: ToneInfo::new(0x29B, 4, Some(440.0)),
+    };
+
+    let message = event_message("/vj", &Chip::Ym2612, Instance::Secondary, 1000, &event);
+
+    let mut expected = b"/vj/ym2612/1/key_on\0".to_vec();
+    expected.extend_from_slice(b",hiiif\0\0");
+    expected.extend_from_slice(&1000u64.to_be_bytes());
+    expected.extend_from_slice(&2i32.to_be_bytes());
+    expected.extend_from_slice(&0x29Bi32.to_be_bytes());
+    expected.extend_from_slice(&4i32.to_be_bytes());
+    expected.extend_from_slice(&440.0f32.to_be_bytes());
+    assert_eq!(message, expected);
+}
+
+#[test]
+fn unknown_frequency_is_nil_and_key_off_has_no_tone() {
+    let tone_change = StateEvent::ToneChange {
+        channel: 0,
+        tone: ToneInfo::without_freq(0x100, 3),
+    };
+    let message = event_message(
+        "/soundlog",
+        &Chip::Ym2151,
+        Instance::Primary,
+        7,
+        &tone_change,
+    );
+    assert!(message.starts_with(b"/soundlog/ym2151/0/tone_change\0\0,hiiiN\0\0"));
+    assert_eq!(message.len() % 4, 0);
+
+    let key_off = StateEvent::KeyOff { channel: 5 };
+    let message = event_message("/soundlog", &Chip::Ym2151, Instance::Primary, 7, &key_off);
+    let mut expected = b"/soundlog/ym2151/0/key_off\0\0,hi\0".to_vec();
+    expected.extend_from_slice(&7u64.to_be_bytes());
+    expected.extend_from_slice(&5i32.to_be_bytes());
+    assert_eq!(message, expected);
+}
+
+#[test]
+fn rhythm_hit_message_carries_instrument_name() {
+    let event = StateEvent::RhythmHit {
+        channel: 3,
+        instrument: RhythmInstrument::HiHat,
+        attenuation: 12,
+    };
+
+    let message = event_message("/vj", &Chip::Ym2608, Instance::Primary, 64, &event);
+
+    let mut expected = b"/vj/ym2608/0/rhythm_hit\0".to_vec();
+    expected.extend_from_slice(b",hisi\0\0\0");
+    expected.extend_from_slice(&64u64.to_be_bytes());
+    expected.extend_from_slice(&3i32.to_be_bytes());
+    expected.extend_from_slice(b"hi_hat\0\0");
+    expected.extend_from_slice(&12i32.to_be_bytes());
+    assert_eq!(message, expected);
+}
+
+#[test]
+fn bridge_forwards_callback_events_with_absolute_samples() {
+    let (sender, receiver) = connected_pair();
+    let mut bridge = OscBridge::new(sender);
+    bridge.set_prefix("/test");
+    assert_eq!(bridge.prefix(), "/test");
+
+    let reg = |register, value| Ym2612Spec {
+        port: 0,
+        register,
+        value,
+    };
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+    builder.add_vgm_command(WaitSamples(10));
+    builder.add_chip_write(Instance::Primary, reg(0xA4, 0x22));
+    builder.add_chip_write(Instance::Primary, reg(0xA0, 0x9B));
+    builder.add_chip_write(Instance::Primary, reg(0x28, 0xF0));
+    builder.add_vgm_command(WaitSamples(100));
+    builder.add_chip_write(Instance::Primary, reg(0x28, 0x00));
+    builder.add_vgm_command(WaitSamples(10));
+    builder.set_loop_offset(0);
+
+    let mut stream = VgmCallbackStream::from_document(builder.finalize());
+    stream.set_loop_count(Some(2));
+    stream.set_sample_clock(SampleClock::Absolute);
+    stream.track_state::<Ym2612State>(Instance::Primary, 7_670_454.0);
+    stream.on_write(move |instance, _spec: Ym2612Spec, sample, events| {
+        for event in events.iter().flatten() {
+            bridge
+                .send(&Chip::Ym2612, instance, sample as u64, event)
+                .unwrap();
+        }
+    });
+    for result in stream {
+        result.unwrap();
+    }
+
+    let messages: Vec<(String, u64)> = (0..4)
+        .map(|_| {
+            let message = recv(&receiver);
+            // padded end of the NUL terminated string at `start`
+            let string_end = |start: usize| {
+                let nul = start + message[start..].iter().position(|&b| b == 0).unwrap();
+                (nul / 4 + 1) * 4
+            };
+            let tags = string_end(0);
+            let address = String::from_utf8(message[..tags].to_vec()).unwrap();
+            let address = address.trim_end_matches('\0').to_string();
+            let args = string_end(tags);
+            let sample = u64::from_be_bytes(message[args..args + 8].try_into().unwrap());
+            (address, sample)
+        })
+        .collect();
+    assert_eq!(
+        messages,
+        vec![
+            ("/test/ym2612/0/key_on".to_string(), 10),
+            ("/test/ym2612/0/key_off".to_string(), 110),
+            ("/test/ym2612/0/key_on".to_string(), 130),
+            ("/test/ym2612/0/key_off".to_string(), 230),
+        ]
+    );
+}
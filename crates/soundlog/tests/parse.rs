@@ -374,6 +374,99 @@ fn test_parse_error_gd3_offset_out_of_range() {
     }
 }
 
+#[test]
+fn test_parse_error_header_offsets_do_not_wrap() {
+    use soundlog::ParseError;
+    use soundlog::VgmBuilder;
+    use soundlog::VgmDocument;
+    use soundlog::vgm::VgmExtraHeader;
+    use soundlog::vgm::command::WaitSamples;
+
+    let mut builder = VgmBuilder::new();
+    builder.add_vgm_command(WaitSamples(1));
+    builder.set_extra_header(VgmExtraHeader {
+        header_size: 0,
+        chip_clock_offset: 0,
+        chip_vol_offset: 0,
+        chip_clocks: vec![soundlog::vgm::header::ChipClock::new(
+            soundlog::vgm::header::ChipId::from(1u8),
+            soundlog::vgm::command::Instance::Primary,
+            12345u32,
+        )],
+        chip_volumes: Vec::new(),
+    });
+    let serialized: Vec<u8> = builder.finalize().into();
+
+    // Stored values that wrap around to the start of the file in 32-bit
+    // arithmetic (0x14 + 0xFFFF_FFF0 and 0xBC + 0xFFFF_FF44) must not be
+    // read as small offsets inside the header.
+    for (field, bad_offset) in [(0x14, 0xFFFF_FFF0u32), (0xBC, 0xFFFF_FF44u32)] {
+        let mut corrupted = serialized.clone();
+        corrupted[field..field + 4].copy_from_slice(&bad_offset.to_le_bytes());
+        let res: Result<VgmDocument, ParseError> = corrupted.as_slice().try_into();
+        match res {
+            Err(ParseError::OffsetOutOfRange { offset, .. }) => {
+                assert!(
+                    offset > corrupted.len(),
+                    "field 0x{:X}: 0x{:X}",
+                    field,
+                    offset
+                );
+            }
+            Err(ParseError::OffsetOverflow { .. }) => {}
+            other => panic!(
+                "field 0x{:X}: expected an offset error, got {:?}",
+                field,
+                other.map(|_| ())
+            ),
+        }
+    }
+}
+
+#[test]
+fn test_wrapping_loop_and_extra_header_offsets_are_ignored() {
+    use soundlog::VgmBuilder;
+    use soundlog::vgm::VgmExtraHeader;
+    use soundlog::vgm::command::WaitSamples;
+
+    let mut builder = VgmBuilder::new();
+    builder.add_vgm_command(WaitSamples(1));
+    builder.add_vgm_command(WaitSamples(2));
+    builder.set_extra_header(VgmExtraHeader {
+        header_size: 0,
+        chip_clock_offset: 0,
+        chip_vol_offset: 0,
+        chip_clocks: Vec::new(),
+        chip_volumes: vec![soundlog::vgm::header::ChipVolume::new(
+            soundlog::vgm::header::ChipId::from(2u8),
+            soundlog::vgm::command::Instance::Primary,
+            1000u16,
+        )],
+    });
+    let mut doc = builder.finalize();
+
+    // 0x1C + 0xFFFF_FFE4 wraps to 0 in 32-bit arithmetic.
+    doc.header.loop_offset = 0xFFFF_FFE4;
+    assert_eq!(doc.loop_command_index(), None);
+    assert_eq!(
+        VgmHeader::loop_pos_in_commands(
+            doc.header.version,
+            doc.header.loop_offset,
+            doc.header.data_offset,
+            0x1000,
+        ),
+        None
+    );
+
+    // 0xBC + 0xFFFF_FF44 wraps to 0: the extra header must not be written
+    // over the start of the header.
+    doc.header.loop_offset = 0;
+    doc.header.extra_header_offset = 0xFFFF_FF44;
+    let bytes: Vec<u8> = (&doc).into();
+    assert_eq!(&bytes[0..4], b"Vgm ");
+    assert_eq!(&bytes[0xBC..0xC0], &[0, 0, 0, 0]);
+}
+
 use soundlog::{ParseError, VgmHeader};
 
 #[test]
@@ -735,3 +828,178 @@ fn test_parse_vgm_command_unknown_opcode_yields_unknown_command() {
         }
     }
 }
+
+#[test]
+fn test_parse_with_hooks_reports_progress_and_cancels() {
+    use soundlog::VgmBuilder;
+    use soundlog::chip::Ym2612Spec;
+    use soundlog::vgm::command::{Instance, WaitSamples};
+    use soundlog::vgm::parser::{CancelToken, ParseHooks};
+
+    let mut builder = VgmBuilder::new();
+    for i in 0..100_000u32 {
+        builder.add_chip_write(
+            Instance::Primary,
+            Ym2612Spec {
+                port: 0,
+                register: 0x28,
+                value: (i & 0xF0) as u8,
+            },
+        );
+        builder.add_vgm_command(WaitSamples(1));
+    }
+    let bytes: Vec<u8> = builder.finalize().into();
+    let expected: VgmDocument = bytes.as_slice().try_into().unwrap();
+
+    let mut reports = Vec::new();
+    let hooks = ParseHooks::new().on_progress(|done, _| reports.push(done));
+    let doc = VgmDocument::parse_with_hooks(&bytes, hooks).unwrap();
+    assert_eq!(doc, expected);
+    assert!(reports.len() > 2, "reports: {:?}", reports);
+    assert!(reports.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(*reports.last().unwrap(), bytes.len());
+
+    // stop at the second report
+    let token = CancelToken::new();
+    let mut calls = 0;
+    let hooks = ParseHooks::new()
+        .on_progress(|_, _| {
+            calls += 1;
+            if calls == 2 {
+                token.cancel();
+            }
+        })
+        .with_cancel(token.clone());
+    match VgmDocument::parse_with_hooks(&bytes, hooks) {
+        Err(ParseError::Cancelled { offset }) => assert_eq!(offset, reports[1]),
+        other => panic!("expected Cancelled, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn test_parse_with_hooks_reports_total_and_cancels_from_token() {
+    use soundlog::VgmBuilder;
+    use soundlog::vgm::command::WaitSamples;
+    use soundlog::vgm::parser::{CancelToken, ParseHooks};
+
+    let mut builder = VgmBuilder::new();
+    for _ in 0..50_000 {
+        builder.add_vgm_command(WaitSamples(1));
+    }
+    let bytes: Vec<u8> = builder.finalize().into();
+
+    let mut reports = Vec::new();
+    let hooks = ParseHooks::new().on_progress(|done, total| reports.push((done, total)));
+    assert!(VgmDocument::parse_with_hooks(&bytes, hooks).is_ok());
+    assert!(reports.len() > 1, "reports: {:?}", reports);
+    assert!(reports.iter().all(|&(_, total)| total == bytes.len()));
+    assert_eq!(reports.last().unwrap().0, bytes.len());
+
+    // Cancelled through a clone, as another thread would.
+    let token = CancelToken::new();
+    let remote = token.clone();
+    let mut calls = 0;
+    let hooks = ParseHooks::new()
+        .on_progress(|_, _| {
+            calls += 1;
+            remote.cancel();
+        })
+        .with_cancel(token.clone());
+    match VgmDocument::parse_with_hooks(&bytes, hooks) {
+        Err(ParseError::Cancelled { offset }) => assert_eq!(offset, reports[0].0),
+        other => panic!("expected Cancelled, got {:?}", other.map(|_| ())),
+    }
+    assert_eq!(calls, 1);
+    assert!(token.is_cancelled());
+}
+
+/// `ParseLimits` stop the parse with a typed error naming the limit.
+#[test]
+fn test_parse_limits() {
+    use soundlog::vgm::command::{DataBlock, WaitSamples};
+    use soundlog::vgm::parser::{ParseHooks, ParseLimits};
+    use soundlog::{ParseError, ResourceLimit, VgmBuilder};
+
+    let block = |data_type: u8, data: Vec<u8>| DataBlock {
+        marker: 0x66,
+        chip_instance: 0,
+        data_type,
+        size: data.len() as u32,
+        data,
+    };
+    let mut builder = VgmBuilder::new();
+    builder.add_vgm_command(block(0x00, vec![0; 100]));
+    builder.add_vgm_command(block(0x00, vec![0; 60]));
+    // Bit packing, 16 MiB declared uncompressed size.
+    builder.add_vgm_command(block(0x40, vec![0x00, 0, 0, 0, 1, 8, 8, 0, 0, 0]));
+    for _ in 0..10 {
+        builder.add_vgm_command(WaitSamples(1));
+    }
+    let bytes: Vec<u8> = builder.finalize().into();
+
+    let parse = |limits: ParseLimits| {
+        VgmDocument::parse_with_hooks(&bytes, ParseHooks::new().with_limits(limits))
+    };
+    let exceeded = |limits: ParseLimits| match parse(limits) {
+        Err(ParseError::LimitExceeded { limit, value, max }) => (limit, value, max),
+        other => panic!("expected LimitExceeded, got {:?}", other.map(|_| ())),
+    };
+
+    assert!(parse(ParseLimits::untrusted()).is_ok());
+    assert!(parse(ParseLimits::default()).is_ok());
+    assert_eq!(
+        exceeded(ParseLimits {
+            max_file_size: 100,
+            ..ParseLimits::default()
+        }),
+        (ResourceLimit::FileSize, bytes.len(), 100)
+    );
+    assert_eq!(
+        exceeded(ParseLimits {
+            max_data_block_size: 99,
+            ..ParseLimits::default()
+        }),
+        (ResourceLimit::DataBlockSize, 100, 99)
+    );
+    assert_eq!(
+        exceeded(ParseLimits {
+            max_total_data_block_size: 159,
+            ..ParseLimits::default()
+        }),
+        (ResourceLimit::TotalDataBlockSize, 160, 159)
+    );
+    assert_eq!(
+        exceeded(ParseLimits {
+            max_decompressed_size: 0xFF_FFFF,
+            ..ParseLimits::default()
+        }),
+        (ResourceLimit::DecompressedSize, 0x100_0000, 0xFF_FFFF)
+    );
+    // Three data blocks, ten waits and the end of data.
+    assert!(
+        parse(ParseLimits {
+            max_commands: 14,
+            ..ParseLimits::default()
+        })
+        .is_ok()
+    );
+    assert_eq!(
+        exceeded(ParseLimits {
+            max_commands: 13,
+            ..ParseLimits::default()
+        }),
+        (ResourceLimit::CommandCount, 14, 13)
+    );
+
+    let hooks = ParseHooks::new().with_limits(ParseLimits {
+        max_commands: 1,
+        ..ParseLimits::default()
+    });
+    assert!(matches!(
+        VgmDocument::parse_with_hooks(&bytes, hooks),
+        Err(ParseError::LimitExceeded {
+            limit: ResourceLimit::CommandCount,
+            ..
+        })
+    ));
+}
@@ -0,0 +1,168 @@
+use soundlog::chip::patch::{FmOperator, FmPatch, GybBank, GybInstrument, VGI_SIZE};
+use soundlog::chip::{Chip, Ym2612Spec};
+use soundlog::vgm::command::{Instance, WaitSamples};
+use soundlog::vgm::usage::channel_usage;
+use soundlog::{ParseError, VgmBuilder};
+
+fn patch() -> FmPatch {
+    let op = |n: u8| FmOperator {
+        dt_mul: 0x10 | n,
+        tl: 0x20 + n,
+        ks_ar: 0x40 | (0x10 + n),
+        am_dr: 0x80 | n,
+        sr: n,
+        sl_rr: 0x30 | n,
+        ssg_eg: if n == 4 { 0x08 } else { 0 },
+    };
+    FmPatch {
+        algorithm: 4,
+        feedback: 5,
+        ams: 2,
+        fms: 3,
+        operators: [op(1), op(2), op(3), op(4)],
+    }
+}
+
+#[test]
+fn vgi_round_trips_and_uses_register_order() {
+    let patch = patch();
+    let vgi = patch.to_vgi();
+    assert_eq!(vgi.len(), VGI_SIZE);
+    assert_eq!(&vgi[..3], &[4, 5, 0x23]);
+    // S3 is the second operator in the file.
+    assert_eq!(&vgi[13..23], &[3, 1, 0x23, 1, 0x13, 0x83, 3, 3, 3, 0]);
+    assert_eq!(FmPatch::from_vgi(&vgi).unwrap(), patch);
+
+    assert!(matches!(
+        FmPatch::from_vgi(&vgi[..42]),
+        Err(ParseError::OffsetOutOfRange { .. })
+    ));
+}
+
+#[test]
+fn gyb_round_trips_a_bank() {
+    let mut bank = GybBank {
+        lfo: 0x0B,
+        ..GybBank::default()
+    };
+    bank.melody.push(GybInstrument {
+        name: "Bass".to_string(),
+        patch: patch(),
+        transpose: -12,
+    });
+    bank.melody.push(GybInstrument {
+        name: "Plain".to_string(),
+        patch: FmPatch::default(),
+        transpose: 0,
+    });
+    bank.drums.push(GybInstrument {
+        name: "Kick".to_string(),
+        patch: patch(),
+        transpose: 0,
+    });
+    bank.melody_map[0] = Some(1);
+    bank.melody_map[33] = Some(0);
+    bank.drum_map[36] = Some(0);
+
+    let bytes = bank.to_bytes();
+    assert_eq!(&bytes[..5], &[0x1A, 0x0C, 2, 2, 1]);
+    assert_eq!(&bytes[5..7], &[1, 0xFF]);
+    assert_eq!(bytes[5 + 256], 0x0B);
+    // Three 32-byte instruments, then the names.
+    assert_eq!(&bytes[5 + 257 + 96..][..5], b"\x04Bass");
+    assert_eq!(GybBank::from_bytes(&bytes).unwrap(), bank);
+
+    let mut bad = bytes.clone();
+    bad[2] = 3;
+    assert!(matches!(
+        GybBank::from_bytes(&bad),
+        Err(ParseError::UnsupportedVersion(3))
+    ));
+    bad[0] = 0;
+    assert!(GybBank::from_bytes(&bad).is_err());
+    assert!(GybBank::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+}
+
+#[test]
+fn gyb_reads_version_1() {
+    let mut bytes = vec![0x1A, 0x0C, 1, 1, 0];
+    bytes.extend([0xFF; 256]);
+    let mut registers = [0u8; 31];
+    registers[28] = 0x3A;
+    registers[29] = 0xC5;
+    registers[30] = 2;
+    bytes.extend(registers);
+    bytes.extend(b"\x03Lead");
+    let bank = GybBank::from_bytes(&bytes[..bytes.len() - 1]).unwrap();
+    assert_eq!(bank.lfo, 0);
+    assert_eq!(bank.melody.len(), 1);
+    let instrument = &bank.melody[0];
+    assert_eq!(instrument.name, "Lea");
+    assert_eq!(instrument.transpose, 2);
+    assert_eq!(
+        (instrument.patch.algorithm, instrument.patch.feedback),
+        (2, 7)
+    );
+    assert_eq!(instrument.patch.fms, 5);
+}
+
+#[test]
+fn patches_are_extracted_from_registers_and_voices() {
+    let patch = patch();
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+    let mut write = |register: u8, value: u8| {
+        builder.add_chip_write(
+            Instance::Primary,
+            Ym2612Spec {
+                port: 1,
+                register,
+                value,
+            },
+        );
+    };
+    // Channel 5 is port 1, offset 1.
+    for (op, slot) in patch.operators.iter().zip([0, 8, 4, 12]) {
+        for (base, value) in [
+            (0x30, op.dt_mul),
+            (0x40, op.tl),
+            (0x50, op.ks_ar),
+            (0x60, op.am_dr),
+            (0x70, op.sr),
+            (0x80, op.sl_rr),
+            (0x90, op.ssg_eg),
+        ] {
+            write(base + slot + 1, value);
+        }
+    }
+    write(0xB1, patch.feedback_algorithm());
+    write(0xB5, 0xC0 | patch.lfo_sensitivity());
+    builder.add_chip_write(
+        Instance::Primary,
+        Ym2612Spec {
+            port: 0,
+            register: 0x28,
+            value: 0xF5,
+        },
+    );
+    builder.add_vgm_command(WaitSamples(100));
+    let doc = builder.finalize();
+
+    let report = channel_usage(&doc);
+    assert_eq!(report.voices.len(), 1);
+    let extracted = FmPatch::from_voice(&report.voices[0]).unwrap();
+    // Algorithm 4 has carriers S2 and S4, whose total level is not part of
+    // the voice.
+    let mut expected = patch;
+    expected.operators[1].tl = 0;
+    expected.operators[3].tl = 0;
+    assert_eq!(extracted, expected);
+
+    let mut registers = std::collections::BTreeMap::new();
+    for command in &doc.commands {
+        if let Some(write) = command.register_write() {
+            registers.insert((write.port, write.register), write.value);
+        }
+    }
+    assert_eq!(FmPatch::from_registers(&registers, 4), patch);
+}
@@ -0,0 +1,135 @@
+use soundlog::VgmBuilder;
+use soundlog::VgmDocument;
+use soundlog::chip::PsgSpec;
+use soundlog::meta::Gd3;
+use soundlog::vgm::VgmExtraHeader;
+use soundlog::vgm::command::{Instance, WaitSamples};
+use soundlog::vgm::header::{ChipClock, ChipId, ChipVolume, VgmHeaderField};
+use soundlog::vgm::repair::{
+    OffsetRepair, RepairedField, repair_document_offsets, repair_extra_header, repair_offsets,
+};
+
+fn extra_header_file() -> (Vec<u8>, usize) {
+    let mut builder = VgmBuilder::new();
+    builder.add_vgm_command(WaitSamples(1));
+    builder.set_extra_header(VgmExtraHeader {
+        chip_clocks: vec![ChipClock::new(ChipId::Ym2612, Instance::Primary, 7_670_454)],
+        chip_volumes: vec![ChipVolume::new(ChipId::Sn76489, Instance::Primary, 0x80)],
+        ..VgmExtraHeader::default()
+    });
+    let bytes: Vec<u8> = builder.finalize().into();
+    let start = u32::from_le_bytes(bytes[0xBC..0xC0].try_into().unwrap()) as usize + 0xBC;
+    (bytes, start)
+}
+
+#[test]
+fn extra_header_offsets_outside_the_file_are_cleared() {
+    let (bytes, start) = extra_header_file();
+    let (repaired, repairs) = repair_extra_header(&bytes).unwrap();
+    assert!(repairs.is_empty());
+    assert_eq!(repaired, bytes);
+
+    let mut broken = bytes.clone();
+    broken[start + 8..start + 12].copy_from_slice(&0xFFFF_FFF0u32.to_le_bytes());
+    assert!(VgmDocument::try_from(&broken[..]).is_err());
+    let (repaired, repairs) = repair_extra_header(&broken).unwrap();
+    assert_eq!(
+        repairs,
+        vec![OffsetRepair {
+            field: RepairedField::ChipVolumeOffset,
+            stored: 0xFFFF_FFF0,
+            repaired: 0,
+        }]
+    );
+    let extra = VgmDocument::try_from(&repaired[..])
+        .unwrap()
+        .extra_header
+        .unwrap();
+    assert_eq!(extra.chip_clocks.len(), 1);
+    assert!(extra.chip_volumes.is_empty());
+
+    let mut broken = bytes.clone();
+    broken[0xBC..0xC0].copy_from_slice(&(bytes.len() as u32).to_le_bytes());
+    let (repaired, repairs) = repair_extra_header(&broken).unwrap();
+    assert_eq!(
+        repairs[0].field,
+        RepairedField::Header(VgmHeaderField::ExtraHeaderOffset)
+    );
+    assert!(
+        VgmDocument::try_from(&repaired[..])
+            .unwrap()
+            .extra_header
+            .is_none()
+    );
+}
+
+fn looped_file() -> Vec<u8> {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(soundlog::chip::Chip::Sn76489, Instance::Primary, 3_579_545);
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x9F });
+    builder.add_vgm_command(WaitSamples(100));
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x90 });
+    builder.add_vgm_command(WaitSamples(200));
+    builder.set_loop_index(2);
+    builder.set_gd3(Gd3 {
+        track_name_en: Some("Title".to_string()),
+        ..Gd3::default()
+    });
+    builder.finalize().into()
+}
+
+fn write_u32(bytes: &mut [u8], field: VgmHeaderField, value: u32) {
+    let offset = field.offset();
+    bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+#[test]
+fn header_offsets_are_recomputed_from_the_content() {
+    let bytes = looped_file();
+    let (repaired, repairs) = repair_offsets(&bytes).unwrap();
+    assert!(repairs.is_empty());
+    assert_eq!(repaired, bytes);
+
+    let mut broken = bytes.clone();
+    write_u32(&mut broken, VgmHeaderField::EofOffset, 0x10);
+    write_u32(&mut broken, VgmHeaderField::Gd3Offset, 0xFFFF);
+    write_u32(&mut broken, VgmHeaderField::LoopOffset, 0xFFFF);
+    write_u32(&mut broken, VgmHeaderField::DataOffset, 0xFFFF);
+    assert!(VgmDocument::try_from(&broken[..]).is_err());
+
+    let (repaired, repairs) = repair_offsets(&broken).unwrap();
+    assert_eq!(repaired, bytes);
+    let fields: Vec<RepairedField> = repairs.iter().map(|repair| repair.field).collect();
+    assert_eq!(
+        fields,
+        [
+            VgmHeaderField::EofOffset,
+            VgmHeaderField::DataOffset,
+            VgmHeaderField::Gd3Offset,
+            VgmHeaderField::LoopOffset,
+        ]
+        .map(RepairedField::Header)
+    );
+
+    // Without loop samples the broken loop is removed.
+    write_u32(&mut broken, VgmHeaderField::LoopSamples, 0);
+    let (repaired, _) = repair_offsets(&broken).unwrap();
+    let doc = VgmDocument::try_from(&repaired[..]).unwrap();
+    assert_eq!(doc.header.loop_offset, 0);
+    assert_eq!(doc.gd3.unwrap().track_name_en.as_deref(), Some("Title"));
+}
+
+#[test]
+fn stale_document_offsets_are_repaired() {
+    let bytes = looped_file();
+    let mut doc = VgmDocument::try_from(&bytes[..]).unwrap();
+    doc.header.loop_offset += 1;
+    assert_eq!(doc.loop_command_index(), None);
+
+    let (repaired, repairs) = repair_document_offsets(&doc).unwrap();
+    assert_eq!(repaired.loop_command_index(), Some(2));
+    assert_eq!(
+        repairs[0].field,
+        RepairedField::Header(VgmHeaderField::LoopOffset)
+    );
+}
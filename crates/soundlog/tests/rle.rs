@@ -0,0 +1,41 @@
+use soundlog::vgm::command::{
+    EndOfData, VgmCommand, Wait735Samples, WaitNSample, WaitSamples,
+    Ym2612Port0Address2AWriteAndWaitN,
+};
+use soundlog::vgm::rle::RleCommands;
+
+#[test]
+fn rle_commands_index_and_iterate_like_the_original() {
+    let mut commands: Vec<VgmCommand> = vec![WaitSamples(1).into(), WaitSamples(2).into()];
+    for _ in 0..1000 {
+        commands.push(Ym2612Port0Address2AWriteAndWaitN(1).into());
+        commands.push(Ym2612Port0Address2AWriteAndWaitN(2).into());
+        commands.push(WaitNSample(3).into());
+    }
+    commands.push(WaitSamples(3).into());
+    for _ in 0..500 {
+        commands.push(Wait735Samples.into());
+    }
+    commands.push(EndOfData.into());
+
+    let rle = RleCommands::encode(&commands);
+    assert_eq!(rle.len(), commands.len());
+    // Two literal runs of one and two commands around the two patterns.
+    assert_eq!(rle.runs(), 5);
+    assert_eq!(rle.stored_commands(), 2 + 3 + 1 + 1 + 1);
+    for (index, command) in commands.iter().enumerate() {
+        assert_eq!(rle.get(index), Some(command), "{}", index);
+    }
+    assert_eq!(rle.get(commands.len()), None);
+    assert!(rle.iter().eq(commands.iter()));
+    assert_eq!(Vec::from(&rle), commands);
+    assert!(rle.memory_footprint() * 100 < commands.len() * size_of::<VgmCommand>());
+
+    let literal = RleCommands::encode_with_max_pattern(&commands, 0);
+    assert_eq!(literal.runs(), 1);
+    assert_eq!(literal.to_vec(), commands);
+
+    let empty = RleCommands::encode(&[]);
+    assert!(empty.is_empty());
+    assert_eq!(empty.iter().count(), 0);
+}
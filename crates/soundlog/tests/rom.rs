@@ -0,0 +1,185 @@
+use soundlog::VgmBuilder;
+use soundlog::VgmDocument;
+use soundlog::chip::{Chip, Okim6295Spec, Y8950Spec, Ym2610Spec};
+use soundlog::vgm::command::{Instance, WaitSamples};
+use soundlog::vgm::detail::{RomRamChipType, RomRamDump};
+use soundlog::vgm::rom::{rom_coverage, y8950_adpcm_samples};
+
+fn ym2610(builder: &mut VgmBuilder, port: u8, writes: &[(u8, u8)]) {
+    for &(register, value) in writes {
+        builder.add_chip_write(
+            Instance::Primary,
+            Ym2610Spec {
+                port,
+                register,
+                value,
+            },
+        );
+    }
+}
+
+fn y8950(builder: &mut VgmBuilder, writes: &[(u8, u8)]) {
+    for &(register, value) in writes {
+        builder.add_chip_write(Instance::Primary, Y8950Spec { register, value });
+    }
+}
+
+fn oki_doc(rom: Vec<u8>, writes: &[(u8, u8)]) -> VgmDocument {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Okim6295, Instance::Primary, 1_000_000);
+    builder.attach_data_block(RomRamDump {
+        chip_type: RomRamChipType::Okim6295Rom,
+        rom_size: 0x80000,
+        start_address: 0,
+        data: rom,
+    });
+    for &(register, value) in writes {
+        builder.add_chip_write(Instance::Primary, Okim6295Spec { register, value });
+        builder.add_vgm_command(WaitSamples(10));
+    }
+    builder.finalize()
+}
+
+#[test]
+fn ym2610_adpcm_a_key_on_marks_channel_ranges() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2610b, Instance::Primary, 8_000_000);
+    builder.attach_data_block(RomRamDump {
+        chip_type: RomRamChipType::Ym2610AdpcmRom,
+        rom_size: 0x100000,
+        start_address: 0,
+        data: vec![0; 0x8000],
+    });
+    // channel 0: 0x0000..=0x01FF, channel 2: 0x1000..=0x10FF
+    ym2610(
+        &mut builder,
+        1,
+        &[
+            (0x10, 0x00),
+            (0x18, 0x00),
+            (0x20, 0x01),
+            (0x28, 0x00),
+            (0x12, 0x10),
+            (0x1A, 0x00),
+            (0x22, 0x10),
+            (0x2A, 0x00),
+        ],
+    );
+    // dump (key-off) does not read
+    ym2610(&mut builder, 1, &[(0x00, 0x84)]);
+    ym2610(&mut builder, 1, &[(0x00, 0x01)]);
+    builder.add_vgm_command(WaitSamples(100));
+    ym2610(&mut builder, 1, &[(0x00, 0x04)]);
+    let doc = builder.finalize();
+
+    let coverage = rom_coverage(&doc);
+
+    assert_eq!(coverage.len(), 1);
+    assert_eq!(coverage[0].chip_type, RomRamChipType::Ym2610AdpcmRom);
+    assert_eq!(coverage[0].rom_size, 0x100000);
+    assert_eq!(coverage[0].dumped_ranges, vec![0..0x8000]);
+    assert_eq!(
+        coverage[0].read_ranges,
+        Some(vec![0x0000..0x0200, 0x1000..0x1100])
+    );
+    assert_eq!(coverage[0].dumped_bytes(), 0x8000);
+    assert_eq!(coverage[0].read_bytes(), Some(0x300));
+}
+
+#[test]
+fn okim6295_reads_phrase_table_and_sample() {
+    let mut rom = vec![0u8; 0x2000];
+    // phrase 1: 0x01000..=0x0107F
+    rom[8..14].copy_from_slice(&[0x00, 0x10, 0x00, 0x00, 0x10, 0x7F]);
+    // select phrase 1, then start it on channel 0
+    let doc = oki_doc(rom, &[(0x00, 0x81), (0x00, 0x10)]);
+
+    let coverage = rom_coverage(&doc);
+
+    assert_eq!(coverage[0].chip_type, RomRamChipType::Okim6295Rom);
+    assert_eq!(coverage[0].read_ranges, Some(vec![8..16, 0x1000..0x1080]));
+}
+
+#[test]
+fn okim6295_bank_offsets_addresses() {
+    let mut rom = vec![0u8; 0x40100];
+    rom[0x40000..0x40006].copy_from_slice(&[0x00, 0x00, 0x20, 0x00, 0x00, 0x3F]);
+    let doc = oki_doc(rom, &[(0x0F, 0x01), (0x00, 0x80), (0x00, 0x10)]);
+
+    let coverage = rom_coverage(&doc);
+
+    assert_eq!(
+        coverage[0].read_ranges,
+        Some(vec![0x40000..0x40008, 0x40020..0x40040])
+    );
+}
+
+#[test]
+fn undecoded_roms_have_unknown_coverage() {
+    // NMK112 banking is not decoded
+    let doc = oki_doc(vec![0; 0x100], &[(0x0E, 0x01), (0x00, 0x80), (0x00, 0x10)]);
+    assert_eq!(rom_coverage(&doc)[0].read_ranges, None);
+
+    let mut builder = VgmBuilder::new();
+    builder.attach_data_block(RomRamDump {
+        chip_type: RomRamChipType::SegaPcmRom,
+        rom_size: 0x10000,
+        start_address: 0,
+        data: vec![0; 0x100],
+    });
+    let coverage = rom_coverage(&builder.finalize());
+    assert_eq!(coverage[0].chip_type, RomRamChipType::SegaPcmRom);
+    assert_eq!(coverage[0].read_ranges, None);
+    assert_eq!(coverage[0].read_bytes(), None);
+}
+
+#[test]
+fn y8950_adpcm_samples_from_data_blocks_and_memory_writes() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Y8950, Instance::Primary, 3_579_545);
+    builder.attach_data_block(RomRamDump {
+        chip_type: RomRamChipType::Y8950DeltaTRom,
+        rom_size: 0x8000,
+        start_address: 0,
+        data: vec![0x11; 0x100],
+    });
+    // block sample 0x20..=0x5F at delta-N 0x8000
+    y8950(
+        &mut builder,
+        &[
+            (0x09, 0x01),
+            (0x0A, 0x00),
+            (0x0B, 0x02),
+            (0x0C, 0x00),
+            (0x10, 0x00),
+            (0x11, 0x80),
+            (0x07, 0xA0),
+        ],
+    );
+    builder.add_vgm_command(WaitSamples(100));
+    // record 32 bytes at 0x200 through register 0x0F, then play them twice
+    y8950(&mut builder, &[(0x07, 0x60), (0x09, 0x10), (0x0A, 0x00)]);
+    for value in 0..0x20 {
+        y8950(&mut builder, &[(0x0F, value)]);
+    }
+    y8950(&mut builder, &[(0x0B, 0x10), (0x0C, 0x00), (0x07, 0xA0)]);
+    builder.add_vgm_command(WaitSamples(100));
+    y8950(&mut builder, &[(0x07, 0x00), (0x07, 0xA0)]);
+    let doc = builder.finalize();
+
+    let samples = y8950_adpcm_samples(&doc);
+    assert_eq!(samples.len(), 2);
+    assert_eq!((samples[0].sample, samples[0].adpcm.start), (0, 0x20));
+    assert_eq!(samples[0].adpcm.delta_n, 0x8000);
+    assert_eq!(samples[0].data, Some(vec![0x11; 0x40]));
+    assert_eq!((samples[1].sample, samples[1].adpcm.start), (100, 0x200));
+    assert_eq!(samples[1].data, Some((0..0x20).collect::<Vec<u8>>()));
+
+    let coverage = rom_coverage(&doc);
+    assert_eq!(coverage[0].chip_type, RomRamChipType::Y8950DeltaTRom);
+    assert_eq!(
+        coverage[0].read_ranges,
+        Some(vec![0x20..0x60, 0x200..0x220])
+    );
+    assert_eq!(coverage[0].read_bytes(), Some(0x40));
+}
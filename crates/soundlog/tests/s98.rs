@@ -0,0 +1,261 @@
+use soundlog::chip::{Chip, Ym2151Spec, Ym2612Spec};
+use soundlog::meta::Gd3;
+use soundlog::s98::convert::{from_vgm, to_vgm};
+use soundlog::s98::stream::{S98Stream, S98StreamResult};
+use soundlog::s98::{
+    S98Builder, S98Command, S98DeviceType, S98Document, S98Tags, S98Write, parse_s98_command,
+};
+use soundlog::vgm::command::{DataBlock, Instance, VgmCommand, WaitSamples};
+use soundlog::vgm::header::Ay8910ChipType;
+use soundlog::{ParseError, VgmBuilder};
+
+fn document() -> S98Document {
+    let mut builder = S98Builder::new();
+    builder
+        .add_device(S98DeviceType::Ym2608, 7_987_200)
+        .add_device(S98DeviceType::Ym2151, 4_000_000)
+        .add_write(0, 1, 0x30, 0x71)
+        .add_sync(1)
+        .add_write(1, 0, 0x08, 0x78)
+        .add_sync(2)
+        .add_sync(200)
+        .add_sync(70_000)
+        .set_loop_index(2);
+    let mut tags = S98Tags {
+        utf8: true,
+        ..S98Tags::default()
+    };
+    tags.set("title", "Opening");
+    tags.set("game", "ゲーム");
+    builder.set_tags(tags);
+    builder.finalize()
+}
+
+fn commands(stream: &mut S98Stream) -> Vec<S98Command> {
+    let mut commands = Vec::new();
+    while let Some(Ok(S98StreamResult::Command(command))) = stream.next() {
+        commands.push(command);
+    }
+    commands
+}
+
+#[test]
+fn s98_round_trips() {
+    let doc = document();
+    assert_eq!(doc.header.data_offset, 0x20 + 2 * 16);
+    assert_eq!(doc.loop_command_index(), Some(2));
+    assert_eq!(doc.total_ticks(), 70_203);
+    assert_eq!(doc.commands.last(), Some(&S98Command::End));
+
+    let bytes: Vec<u8> = (&doc).into();
+    assert_eq!(&bytes[..4], b"S983");
+    let data = &bytes[doc.header.data_offset as usize..];
+    // Port 1 of device 0, one sync, then 200 syncs stored as 198.
+    assert_eq!(&data[..4], &[0x01, 0x30, 0x71, 0xFF]);
+    assert_eq!(&data[7..13], &[0xFE, 0x00, 0xFE, 0xC6, 0x01, 0xFE]);
+    let tag = &bytes[doc.header.tag_offset as usize..];
+    assert!(tag.starts_with(b"[S98]\xEF\xBB\xBFtitle=Opening\ngame="));
+    assert_eq!(bytes.last(), Some(&0));
+
+    let parsed = S98Document::try_from(&bytes[..]).unwrap();
+    assert_eq!(parsed, doc);
+    assert_eq!(Vec::<u8>::from(&parsed), bytes);
+    let tags = parsed.tags.as_ref().unwrap();
+    assert_eq!(tags.get_str("GAME"), Some("ゲーム"));
+}
+
+#[test]
+fn s98_parse_errors_and_old_versions() {
+    let bytes: Vec<u8> = document().into();
+    let mut bad = bytes.clone();
+    bad[0] = b'X';
+    assert!(matches!(
+        S98Document::try_from(&bad[..]),
+        Err(ParseError::InvalidIdent(_))
+    ));
+    bad = bytes.clone();
+    bad[3] = b'4';
+    assert!(matches!(
+        S98Document::try_from(&bad[..]),
+        Err(ParseError::UnsupportedVersion(0x34))
+    ));
+    assert!(S98Document::try_from(&bytes[..0x30]).is_err());
+    assert!(matches!(
+        parse_s98_command(&[0x80], 0),
+        Err(ParseError::UnknownOpcode { opcode: 0x80, .. })
+    ));
+    assert!(parse_s98_command(&[0xFE, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01], 0).is_err());
+
+    // Version 1: no device list and a Shift_JIS title.
+    let mut v1 = b"S981".to_vec();
+    v1.extend([0u8; 0x1C]);
+    let tag_offset = v1.len() + 4;
+    v1[0x10] = tag_offset as u8;
+    v1[0x14] = 0x20;
+    v1.extend([0x00, 0x28, 0xF0, 0xFD]);
+    v1.extend(b"\x83\x65st\0");
+    let doc = S98Document::try_from(&v1[..]).unwrap();
+    assert_eq!(doc.header.effective_timer(), (10, 1000));
+    let devices = doc.header.effective_devices();
+    assert_eq!(devices.len(), 1);
+    assert_eq!(devices[0].device_type, S98DeviceType::Ym2608);
+    let tags = doc.tags.as_ref().unwrap();
+    assert_eq!(tags.get("title"), Some(&b"\x83\x65st"[..]));
+    assert_eq!(tags.get_str("title"), None);
+    assert_eq!(Vec::<u8>::from(&doc), v1);
+}
+
+#[test]
+fn s98_stream_matches_document_and_loops() {
+    let doc = document();
+    let bytes: Vec<u8> = (&doc).into();
+    let expected: Vec<S98Command> = doc.commands[..doc.commands.len() - 1].to_vec();
+
+    let mut stream = S98Stream::from_s98(bytes.clone()).unwrap();
+    assert_eq!(commands(&mut stream), expected);
+    assert_eq!(stream.ticks(), 70_203);
+    assert_eq!(stream.elapsed().as_millis(), 702_030);
+
+    let mut stream = S98Stream::from_document(doc.clone());
+    stream.set_loop_count(Some(2));
+    let looped = commands(&mut stream);
+    assert_eq!(looped.len(), expected.len() + 4);
+    assert_eq!(&looped[expected.len()..], &expected[2..]);
+    assert_eq!(stream.current_loop_count(), 2);
+
+    // Chunks split inside commands.
+    let data = &bytes[doc.header.data_offset as usize..doc.header.tag_offset as usize];
+    let mut stream = S98Stream::new();
+    let mut chunked = Vec::new();
+    for chunk in data.chunks(2) {
+        stream.push_chunk(chunk).unwrap();
+        chunked.extend(commands(&mut stream));
+    }
+    assert_eq!(chunked, expected);
+    assert_eq!(
+        stream.next().unwrap().unwrap(),
+        S98StreamResult::EndOfStream
+    );
+    assert!(
+        S98Stream::from_s98(bytes)
+            .unwrap()
+            .push_chunk(&[0xFF])
+            .is_err()
+    );
+}
+
+#[test]
+fn s98_converts_to_vgm() {
+    let mut builder = S98Builder::new();
+    builder
+        .set_timer(1, 300)
+        .add_device(S98DeviceType::Ym2149, 2_000_000)
+        .add_device(S98DeviceType::Ym2151, 4_000_000)
+        .add_device(S98DeviceType::Ym2151, 4_000_000)
+        .add_device(S98DeviceType::Ym2151, 4_000_000)
+        .add_write(0, 0, 0x07, 0x38)
+        .add_sync(1)
+        .add_write(1, 0, 0x08, 0x00)
+        .add_write(2, 0, 0x08, 0x01)
+        .add_write(3, 0, 0x08, 0x02)
+        .add_write(1, 1, 0x08, 0x03)
+        .add_sync(3)
+        .set_loop_index(2);
+    let mut tags = S98Tags::default();
+    tags.set("title", "Title");
+    tags.set("artist", b"\x83\x65".to_vec());
+    builder.set_tags(tags);
+    let s98 = builder.finalize();
+
+    let (vgm, report) = to_vgm(&s98);
+    // The third YM2151, its write and the port 1 write are dropped.
+    assert_eq!(report.dropped_devices, 1);
+    assert_eq!(report.dropped_commands, 2);
+    assert_eq!(report.dropped_tags, 1);
+    assert_eq!(vgm.header.ay8910_clock, 2_000_000);
+    assert_eq!(vgm.header.ay_chip_type, Ay8910ChipType::Ym2149);
+    assert_eq!(vgm.header.ym2151_clock, 0x8000_0000 | 4_000_000);
+    // 1/300 s is exactly 147 samples.
+    assert_eq!(vgm.header.total_samples, 4 * 147);
+    assert_eq!(vgm.loop_command_index(), Some(2));
+    assert_eq!(
+        vgm.commands[2],
+        VgmCommand::Ym2151Write(
+            Instance::Primary,
+            Ym2151Spec {
+                register: 0x08,
+                value: 0x00
+            }
+        )
+    );
+    let gd3 = vgm.gd3.as_ref().unwrap();
+    assert_eq!(gd3.track_name_en.as_deref(), Some("Title"));
+    assert_eq!(gd3.author_name_en, None);
+}
+
+#[test]
+fn vgm_converts_to_s98_and_back() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+    builder.register_chip(Chip::Okim6295, Instance::Primary, 1_000_000);
+    let write = |register: u8, value: u8| Ym2612Spec {
+        port: 1,
+        register,
+        value,
+    };
+    builder.add_vgm_command(DataBlock {
+        marker: 0x66,
+        chip_instance: 0,
+        data_type: 0x00,
+        size: 2,
+        data: vec![1, 2],
+    });
+    builder.add_chip_write(Instance::Primary, write(0x30, 0x01));
+    builder.add_vgm_command(WaitSamples(100));
+    builder.add_chip_write(Instance::Primary, write(0x40, 0x7F));
+    builder.add_vgm_command(WaitSamples(735));
+    builder.set_loop_offset(2);
+    builder.set_gd3(Gd3 {
+        track_name_en: Some("Stage 1".to_string()),
+        notes: Some("Ported".to_string()),
+        ..Gd3::default()
+    });
+    let vgm = builder.finalize();
+
+    let (s98, report) = from_vgm(&vgm);
+    assert_eq!(report.dropped_devices, 1);
+    assert_eq!(report.dropped_commands, 1);
+    assert_eq!(s98.header.effective_timer(), (1, 44_100));
+    assert_eq!(s98.header.devices.len(), 1);
+    assert_eq!(s98.header.devices[0].device_type, S98DeviceType::Ym2612);
+    assert_eq!(
+        s98.commands,
+        vec![
+            S98Command::Write(S98Write {
+                device: 0,
+                port: 1,
+                register: 0x30,
+                value: 0x01
+            }),
+            S98Command::Sync(100),
+            S98Command::Write(S98Write {
+                device: 0,
+                port: 1,
+                register: 0x40,
+                value: 0x7F
+            }),
+            S98Command::Sync(735),
+            S98Command::End,
+        ]
+    );
+    assert_eq!(s98.loop_command_index(), Some(2));
+    let tags = s98.tags.as_ref().unwrap();
+    assert_eq!(tags.get_str("title"), Some("Stage 1"));
+    assert_eq!(tags.get_str("comment"), Some("Ported"));
+
+    let (back, report) = to_vgm(&s98);
+    assert_eq!(report, Default::default());
+    assert_eq!(back.commands, vgm.commands[1..]);
+    assert_eq!(back.loop_command_index(), Some(2));
+    assert_eq!(back.gd3, vgm.gd3);
+}
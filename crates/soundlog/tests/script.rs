@@ -0,0 +1,91 @@
+use soundlog::chip::{Chip, PsgSpec, Ym2612Spec};
+use soundlog::meta::Gd3;
+use soundlog::vgm::command::{DataBlock, Instance, VgmCommand, Ym2612Port0Address2AWriteAndWaitN};
+use soundlog::vgm::script::{from_script, to_script};
+use soundlog::{VgmBuilder, VgmDocument};
+
+fn sample_document() -> VgmDocument {
+    let ym2612 = |port, register, value| Ym2612Spec {
+        port,
+        register,
+        value,
+    };
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_453);
+    builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+    builder.register_chip(Chip::Sn76489, Instance::Secondary, 3_579_545);
+    builder.set_gd3(Gd3 {
+        track_name_en: Some("Tab\tand \\ backslash".to_string()),
+        notes: Some("two\nlines".to_string()),
+        ..Default::default()
+    });
+    builder.add_vgm_command(DataBlock {
+        marker: 0x66,
+        chip_instance: 0,
+        data_type: 0x00,
+        size: 4,
+        data: vec![0x80, 0x90, 0xA0, 0xB0],
+    });
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0x28, 0xF0));
+    builder.add_chip_write(Instance::Primary, ym2612(1, 0xB4, 0xC0));
+    builder.add_wait_samples(735);
+    builder.add_chip_write(Instance::Secondary, PsgSpec { value: 0x9F });
+    for n in [3, 0, 15] {
+        builder.add_vgm_command(Ym2612Port0Address2AWriteAndWaitN(n));
+    }
+    builder.add_wait_samples(100_000);
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x90 });
+    builder.set_loop_index(4);
+    builder.finalize()
+}
+
+#[test]
+fn script_round_trips_a_document() {
+    let doc = sample_document();
+    let script = to_script(&doc);
+    assert!(
+        script.contains("\n.header Ym2612Clock 0x00750AB5\n"),
+        "{}",
+        script
+    );
+    assert!(script.contains("\n.gd3 notes two\\nlines\n"), "{}", script);
+    assert!(script.contains("\n735        loop\n735        sn76489.2 9f\n"));
+    assert!(
+        script
+            .contains("\n735        dac\n738        dac\n738        dac\n100753     sn76489 90\n")
+    );
+    assert!(script.contains("\n0          ym2612 p1 b4 c0\n"));
+
+    let rebuilt = from_script(&script).unwrap();
+    assert_eq!(rebuilt.gd3, doc.gd3);
+    assert_eq!(rebuilt.commands, doc.commands);
+    assert_eq!(Vec::<u8>::from(&rebuilt), Vec::<u8>::from(&doc));
+}
+
+#[test]
+fn script_edits_and_errors() {
+    let script = "\
+# soundlog script v1
+.header Version 0x171
+.header Ym2151Clock 3579545
+0 ym2151 08 00  # key off
+10 YM2151 20 c7
+10 dac
+30 end
+";
+    let doc = from_script(script).unwrap();
+    assert_eq!(doc.header.version, 0x171);
+    assert_eq!(doc.header.ym2151_clock, 3_579_545);
+    assert_eq!(doc.header.total_samples, 30);
+    assert_eq!(
+        doc.commands[3],
+        VgmCommand::from(Ym2612Port0Address2AWriteAndWaitN(15))
+    );
+
+    let error = |script: &str| from_script(script).unwrap_err().to_string();
+    assert!(error("10 end\n5 end\n").contains("line 2"));
+    assert!(error("0 ym2612 p2 28 00\n").contains("invalid command"));
+    assert!(error("0 raw 61dd\n").contains("line 1"));
+    assert!(error(".header TotalSamples 10\n").contains("unknown header field"));
+    assert!(error(".header LoopBase 0x1FF\n").contains("does not fit"));
+}
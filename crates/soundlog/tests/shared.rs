@@ -0,0 +1,74 @@
+use std::sync::Arc;
+
+use soundlog::chip::Ym2612Spec;
+use soundlog::vgm::command::{Instance, WaitSamples};
+use soundlog::{SharedVgmDocument, VgmBuilder, VgmDocument};
+
+fn build_document() -> VgmDocument {
+    let mut builder = VgmBuilder::new();
+    for value in 0..16u8 {
+        builder.add_chip_write(
+            Instance::Primary,
+            Ym2612Spec {
+                port: 0,
+                register: 0x28,
+                value,
+            },
+        );
+        builder.add_vgm_command(WaitSamples(735));
+    }
+    builder.finalize()
+}
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn shared_document_is_send_and_sync() {
+    assert_send_sync::<SharedVgmDocument>();
+}
+
+#[test]
+fn clones_share_document_and_derived_data() {
+    let doc = build_document();
+    let shared = SharedVgmDocument::from(doc.clone());
+    let clone = shared.clone();
+
+    assert!(shared.ptr_eq(&clone));
+    assert_eq!(shared.document(), &doc);
+    assert_eq!(clone.commands.len(), doc.commands.len());
+
+    // Derived data is computed once and shared by every clone.
+    assert!(Arc::ptr_eq(&shared.sourcemap(), &clone.sourcemap()));
+    assert_eq!(&shared.sourcemap()[..], &doc.sourcemap()[..]);
+    assert!(Arc::ptr_eq(&shared.serialized(), &clone.serialized()));
+    assert_eq!(&shared.serialized()[..], &Vec::<u8>::from(&doc)[..]);
+}
+
+#[test]
+fn shared_document_parses_and_is_used_from_threads() {
+    let bytes: Vec<u8> = build_document().into();
+    let shared = SharedVgmDocument::try_from(bytes.as_slice()).unwrap();
+
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let worker = shared.clone();
+            std::thread::spawn(move || (worker.commands.len(), worker.serialized()))
+        })
+        .collect();
+    for handle in handles {
+        let (commands, serialized) = handle.join().unwrap();
+        assert_eq!(commands, shared.commands.len());
+        assert_eq!(&serialized[..], &bytes[..]);
+    }
+}
+
+#[test]
+fn into_document_moves_or_clones() {
+    let doc = build_document();
+    let shared = SharedVgmDocument::new(doc.clone());
+    let clone = shared.clone();
+    // Still shared: the document is cloned.
+    assert_eq!(shared.into_document(), doc);
+    // Last owner: the document is moved out.
+    assert_eq!(clone.into_document(), doc);
+}
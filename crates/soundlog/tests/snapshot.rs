@@ -0,0 +1,123 @@
+use soundlog::VgmBuilder;
+use soundlog::chip::{Chip, PsgSpec, Ym2151Spec, Ym2612Spec};
+use soundlog::vgm::command::{Instance, WaitSamples};
+use soundlog::vgm::snapshot::{
+    RegisterChange, command_at_sample, diff_states, snapshot_at_command, snapshot_at_sample,
+};
+
+#[test]
+fn snapshots_follow_the_command_stream() {
+    let mut builder = VgmBuilder::new();
+    builder.add_chip_write(
+        Instance::Primary,
+        Ym2151Spec {
+            register: 0x28,
+            value: 0x4A,
+        },
+    );
+    builder.add_vgm_command(WaitSamples(735));
+    builder.add_chip_write(
+        Instance::Primary,
+        Ym2151Spec {
+            register: 0x28,
+            value: 0x3E,
+        },
+    );
+    builder.add_chip_write(Instance::Secondary, PsgSpec { value: 0x9F });
+    let doc = builder.finalize();
+
+    assert_eq!(command_at_sample(&doc, 0), 2);
+    assert_eq!(command_at_sample(&doc, 734), 2);
+    assert_eq!(command_at_sample(&doc, 735), doc.commands.len());
+
+    assert!(snapshot_at_command(&doc, 0).is_empty());
+    let before = snapshot_at_sample(&doc, 100);
+    assert_eq!(before.len(), 1);
+    assert_eq!(before[0].read(0, 0x28), Some(0x4A));
+    let map = before[0].register_map();
+    assert_eq!(
+        map.field("Channel 1", "Key code").unwrap().meaning,
+        "KC 0x4A (A4) KF 0"
+    );
+
+    let after = snapshot_at_sample(&doc, 735);
+    assert_eq!(after.len(), 2);
+    assert_eq!(after[0].read(0, 0x28), Some(0x3E));
+    assert_eq!(
+        (after[1].chip.clone(), after[1].instance),
+        (Chip::Sn76489, Instance::Secondary)
+    );
+}
+
+#[test]
+fn sn76489_latch_writes_become_registers() {
+    let mut builder = VgmBuilder::new();
+    // Tone 2 period 0x1FE, tone 2 attenuation 4, white noise.
+    for value in [0xAE, 0x1F, 0xB4, 0xE4] {
+        builder.add_chip_write(Instance::Primary, PsgSpec { value });
+    }
+    let doc = builder.finalize();
+
+    let snapshots = snapshot_at_command(&doc, doc.commands.len());
+    let psg = &snapshots[0];
+    assert_eq!(psg.read(0, 2), Some(0x1FE));
+    assert_eq!(psg.read(0, 3), Some(4));
+    let map = psg.register_map();
+    assert_eq!(map.field("Tone 2", "Period").unwrap().meaning, "0x1FE");
+    assert_eq!(
+        map.field("Noise", "Control").unwrap().meaning,
+        "white clock/512"
+    );
+
+    let text = format!("{:#}", map);
+    assert!(text.starts_with("Sn76489\n  Tone 2\n"));
+    assert!(text.contains("Attenuation      4  [0:03]"));
+}
+
+#[test]
+fn diff_states_lists_changed_registers_per_chip() {
+    let ym2612 = |port, register, value| Ym2612Spec {
+        port,
+        register,
+        value,
+    };
+    let mut builder = VgmBuilder::new();
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0xB0, 0x32));
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0xA4, 0x22));
+    builder.add_vgm_command(WaitSamples(100));
+    // Rewritten with the same value, then a new register and a new chip.
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0xB0, 0x07));
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0xB0, 0x32));
+    builder.add_chip_write(Instance::Primary, ym2612(1, 0xA4, 0x1A));
+    builder.add_chip_write(Instance::Primary, ym2612(0, 0xA4, 0x23));
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x9F });
+    let doc = builder.finalize();
+
+    let diff = diff_states(&doc, 0, 100);
+    assert_eq!(diff.len(), 2);
+    assert_eq!(diff[0].chip, Chip::Ym2612);
+    assert_eq!(
+        diff[0].changes,
+        vec![
+            RegisterChange {
+                port: 0,
+                register: 0xA4,
+                before: Some(0x22),
+                after: Some(0x23),
+            },
+            RegisterChange {
+                port: 1,
+                register: 0xA4,
+                before: None,
+                after: Some(0x1A),
+            },
+        ]
+    );
+    assert_eq!(diff[1].chip, Chip::Sn76489);
+    assert_eq!(diff[1].changes[0].after, Some(0x0F));
+
+    let reversed = diff_states(&doc, 100, 0);
+    assert_eq!(reversed[0].changes[0].before, Some(0x23));
+    assert_eq!(reversed[0].changes[0].after, Some(0x22));
+    assert!(diff_states(&doc, 0, 99).is_empty());
+}
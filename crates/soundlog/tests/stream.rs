@@ -1,3 +1,7 @@
+// Newer clippy releases ask for the `if` inside some match arms below to be
+// folded into match guards; the tests keep their original shape.
+#![allow(clippy::collapsible_match)]
+
 use soundlog::VgmBuilder;
 use soundlog::VgmDocument;
 use soundlog::vgm::command::DacStreamChipType;
@@ -5,7 +9,8 @@ use soundlog::vgm::command::{
     EndOfData, Instance, VgmCommand, Wait735Samples, Wait882Samples, WaitNSample, WaitSamples,
 };
 use soundlog::vgm::header::ChipId;
-use soundlog::vgm::stream::{StreamResult, VgmStream};
+use soundlog::vgm::lint::{LintOptions, lint_commands};
+use soundlog::vgm::stream::{StreamHint, StreamResult, VgmStream};
 use soundlog::{VgmCallbackStream, chip};
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -2960,6 +2965,13 @@ fn test_multiple_dac_streams_wait_interleaving() {
         "Stream 2 should not have bursts of more than 3 consecutive writes, got {}",
         max_consecutive_stream2
     );
+    // The same check as a lint, per chip: the two YM2612 streams share one
+    // chip.
+    let options = LintOptions {
+        max_burst: Some(6),
+        ..LintOptions::default()
+    };
+    assert!(lint_commands(&commands, &options).is_clean());
 
     println!("\n=== Test passed! ===");
     println!("All streams properly interleaved with Wait commands");
@@ -7947,3 +7959,803 @@ fn test_push_chunk_one_byte_at_a_time_dual_chip2_write() {
     }
     assert!(found, "Okim6258Write must be emitted after all three bytes");
 }
+
+/// Build a document where streams 0 and 1 both feed the YM2612 DAC.
+///
+/// Stream 0 plays bank 0 (bytes 0x10..) from sample 0, stream 1 plays bank 1
+/// (bytes 0x80..) from sample 10; both run at 44100 Hz.
+fn overlapping_dac_streams_doc() -> VgmDocument {
+    use soundlog::vgm::command::{
+        LengthMode, SetStreamData, SetStreamFrequency, SetupStreamControl, StartStream,
+    };
+    use soundlog::vgm::detail::{StreamChipType, UncompressedStream};
+
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(chip::Chip::Ym2612, Instance::Primary, 7_670_454);
+    builder.attach_data_block(UncompressedStream {
+        chip_type: StreamChipType::Ym2612Pcm,
+        data: (0x10..0x30).collect(),
+    });
+    builder.attach_data_block(UncompressedStream {
+        chip_type: StreamChipType::Rf5c68Pcm,
+        data: (0x80..0xA0).collect(),
+    });
+    for (stream_id, bank) in [(0u8, 0x00u8), (1, 0x01)] {
+        builder.add_vgm_command(SetupStreamControl {
+            stream_id,
+            chip_type: DacStreamChipType {
+                chip_id: ChipId::Ym2612,
+                instance: Instance::Primary,
+            },
+            write_port: 0,
+            write_command: 0x2A,
+        });
+        builder.add_vgm_command(SetStreamData {
+            stream_id,
+            data_bank_id: bank,
+            step_size: 1,
+            step_base: 0,
+        });
+        builder.add_vgm_command(SetStreamFrequency {
+            stream_id,
+            frequency: 44_100,
+        });
+    }
+    let start = |stream_id| StartStream {
+        stream_id,
+        data_start_offset: 0,
+        length_mode: LengthMode::PlayUntilEnd {
+            reverse: false,
+            looped: false,
+        },
+        data_length: 0,
+    };
+    builder.add_vgm_command(start(0));
+    builder.add_vgm_command(WaitSamples(10));
+    builder.add_vgm_command(start(1));
+    builder.add_vgm_command(WaitSamples(10));
+    builder.finalize()
+}
+
+/// Run `stream` to the end and collect the DAC bytes it generated.
+fn collect_dac_bytes(stream: &mut VgmStream) -> Result<Vec<u8>, soundlog::ParseError> {
+    let mut bytes = Vec::new();
+    for item in stream {
+        match item? {
+            StreamResult::Command(VgmCommand::Ym2612Write(_, spec)) if spec.register == 0x2A => {
+                bytes.push(spec.value)
+            }
+            StreamResult::Command(_) => {}
+            StreamResult::EndOfStream | StreamResult::NeedsMoreData => break,
+        }
+    }
+    Ok(bytes)
+}
+
+#[test]
+fn test_stream_overlap_interleave_records_collision() {
+    use soundlog::vgm::stream::StreamCollisionResolution;
+
+    let mut stream = VgmStream::from_document(overlapping_dac_streams_doc());
+    let bytes = collect_dac_bytes(&mut stream).unwrap();
+
+    // both streams keep writing after sample 10
+    assert!(bytes.contains(&0x1A) && bytes.contains(&0x80));
+    let collisions = stream.stream_collisions();
+    assert_eq!(collisions.len(), 1);
+    assert_eq!(collisions[0].sample, 10);
+    assert_eq!(collisions[0].stream_id, 1);
+    assert_eq!(collisions[0].active_stream_id, 0);
+    assert_eq!(
+        collisions[0].resolution,
+        StreamCollisionResolution::Interleaved
+    );
+}
+
+#[test]
+fn test_stream_overlap_last_wins_stops_active_stream() {
+    use soundlog::vgm::stream::StreamOverlapPolicy;
+
+    let mut stream = VgmStream::from_document(overlapping_dac_streams_doc());
+    stream.set_stream_overlap_policy(StreamOverlapPolicy::LastWins);
+    let bytes = collect_dac_bytes(&mut stream).unwrap();
+
+    // writes land on both ends of each wait: samples 0..=10 and 10..=20
+    let expected: Vec<u8> = (0x10..=0x1A).chain(0x80..=0x8A).collect();
+    assert_eq!(bytes, expected);
+}
+
+#[test]
+fn test_stream_overlap_priority_rejects_lower_priority_stream() {
+    use soundlog::vgm::stream::{StreamCollisionResolution, StreamOverlapPolicy};
+
+    let mut stream = VgmStream::from_document(overlapping_dac_streams_doc());
+    stream.set_stream_overlap_policy(StreamOverlapPolicy::Priority);
+    stream.set_stream_priority(0, 5);
+    let bytes = collect_dac_bytes(&mut stream).unwrap();
+
+    let expected: Vec<u8> = (0x10..=0x24).collect();
+    assert_eq!(bytes, expected);
+    assert_eq!(
+        stream.stream_collisions()[0].resolution,
+        StreamCollisionResolution::RejectedNew
+    );
+}
+
+#[test]
+fn test_stream_overlap_forbid_is_an_error() {
+    use soundlog::vgm::stream::StreamOverlapPolicy;
+
+    let mut stream = VgmStream::from_document(overlapping_dac_streams_doc());
+    stream.set_stream_overlap_policy(StreamOverlapPolicy::Forbid);
+    let err = collect_dac_bytes(&mut stream).unwrap_err();
+    assert!(err.to_string().contains("DAC stream 1"));
+}
+
+/// Build a document with one 44100 Hz YM2612 DAC stream playing `data` once,
+/// followed by a wait of `wait` samples.
+fn single_dac_stream_doc(data: Vec<u8>, wait: u16) -> VgmDocument {
+    use soundlog::vgm::command::{
+        LengthMode, SetStreamData, SetStreamFrequency, SetupStreamControl, StartStream,
+    };
+    use soundlog::vgm::detail::{StreamChipType, UncompressedStream};
+
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(chip::Chip::Ym2612, Instance::Primary, 7_670_454);
+    builder.attach_data_block(UncompressedStream {
+        chip_type: StreamChipType::Ym2612Pcm,
+        data,
+    });
+    builder.add_vgm_command(SetupStreamControl {
+        stream_id: 0,
+        chip_type: DacStreamChipType {
+            chip_id: ChipId::Ym2612,
+            instance: Instance::Primary,
+        },
+        write_port: 0,
+        write_command: 0x2A,
+    });
+    builder.add_vgm_command(SetStreamData {
+        stream_id: 0,
+        data_bank_id: 0x00,
+        step_size: 1,
+        step_base: 0,
+    });
+    builder.add_vgm_command(SetStreamFrequency {
+        stream_id: 0,
+        frequency: 44_100,
+    });
+    builder.add_vgm_command(StartStream {
+        stream_id: 0,
+        data_start_offset: 0,
+        length_mode: LengthMode::PlayUntilEnd {
+            reverse: false,
+            looped: false,
+        },
+        data_length: 0,
+    });
+    builder.add_vgm_command(WaitSamples(wait));
+    builder.finalize()
+}
+
+#[test]
+fn test_write_rate_limit_drop() {
+    use soundlog::vgm::stream::{WriteRateLimit, WriteRateLimitMode, WriteRateLimitReport};
+
+    let mut stream = VgmStream::from_document(single_dac_stream_doc((0..=20).collect(), 20));
+    stream.set_write_rate_limit(Some(WriteRateLimit {
+        min_gap_samples: 2,
+        mode: WriteRateLimitMode::Drop,
+    }));
+    let bytes = collect_dac_bytes(&mut stream).unwrap();
+
+    assert_eq!(bytes, (0..=20).step_by(2).collect::<Vec<u8>>());
+    assert_eq!(
+        stream.write_rate_limit_report(),
+        WriteRateLimitReport {
+            delayed: 0,
+            coalesced: 0,
+            dropped: 10,
+        }
+    );
+}
+
+#[test]
+fn test_write_rate_limit_coalesce_emits_latest_value_late() {
+    use soundlog::vgm::stream::{WriteRateLimit, WriteRateLimitMode, WriteRateLimitReport};
+
+    let mut stream = VgmStream::from_document(single_dac_stream_doc(vec![0xA0, 0xA1, 0xA2], 10));
+    stream.set_write_rate_limit(Some(WriteRateLimit {
+        min_gap_samples: 4,
+        mode: WriteRateLimitMode::Coalesce,
+    }));
+
+    let mut time = 0;
+    let mut writes = Vec::new();
+    let mut total_wait = 0;
+    for item in &mut stream {
+        match item.unwrap() {
+            StreamResult::Command(VgmCommand::Ym2612Write(_, spec)) => {
+                writes.push((time, spec.value))
+            }
+            StreamResult::Command(VgmCommand::WaitSamples(w)) => {
+                time += w.0 as usize;
+                total_wait += w.0 as usize;
+            }
+            StreamResult::Command(_) => {}
+            StreamResult::EndOfStream | StreamResult::NeedsMoreData => break,
+        }
+    }
+
+    // 0xA1 is replaced by 0xA2, which is held back until sample 4
+    assert_eq!(writes, vec![(0, 0xA0), (4, 0xA2)]);
+    assert_eq!(total_wait, 10);
+    assert_eq!(
+        stream.write_rate_limit_report(),
+        WriteRateLimitReport {
+            delayed: 1,
+            coalesced: 1,
+            dropped: 0,
+        }
+    );
+}
+
+#[test]
+fn test_chip_write_delay_holds_dac_writes_until_bus_is_free() {
+    let mut doc = single_dac_stream_doc((0..8).collect(), 10);
+    // three parsed writes at sample 0 keep the YM2612 busy for 90 us (~4 samples)
+    for i in 0..3 {
+        doc.commands.insert(
+            1,
+            VgmCommand::Ym2612Write(
+                Instance::Primary,
+                chip::Ym2612Spec {
+                    port: 0,
+                    register: 0x30 + i,
+                    value: 0,
+                },
+            ),
+        );
+    }
+    let mut stream = VgmStream::from_document(doc);
+    stream.set_chip_write_delay(chip::Chip::Ym2612, Some(30_000));
+
+    let mut time = 0;
+    let mut dac_writes = Vec::new();
+    for item in &mut stream {
+        match item.unwrap() {
+            StreamResult::Command(VgmCommand::Ym2612Write(_, spec)) if spec.register == 0x2A => {
+                dac_writes.push((time, spec.value))
+            }
+            StreamResult::Command(VgmCommand::WaitSamples(w)) => time += w.0 as usize,
+            StreamResult::Command(_) => {}
+            StreamResult::EndOfStream | StreamResult::NeedsMoreData => break,
+        }
+    }
+
+    // each write then keeps the bus busy for a little over one sample
+    assert_eq!(dac_writes, vec![(4, 0x04), (6, 0x06), (8, 0x07)]);
+    assert_eq!(time, 10);
+    let report = stream.write_rate_limit_report();
+    assert_eq!(report.dropped, 0);
+    assert_eq!(report.delayed, 3);
+}
+
+/// Intro write, 100 samples, loop point, write, 50 samples.
+fn looped_psg_doc() -> VgmDocument {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(chip::Chip::Sn76489, Instance::Primary, 3_579_545);
+    builder.add_chip_write(Instance::Primary, chip::PsgSpec { value: 0x90 });
+    builder.add_vgm_command(WaitSamples(100));
+    builder.add_chip_write(Instance::Primary, chip::PsgSpec { value: 0x91 });
+    builder.add_vgm_command(WaitSamples(50));
+    builder.set_loop_offset(2);
+    builder.finalize()
+}
+
+/// Helper: (absolute sample, value) of every PSG write and the end position.
+fn timed_psg_writes(stream: &mut VgmStream) -> (Vec<(u64, u8)>, u64) {
+    let mut writes = Vec::new();
+    for item in stream.timed() {
+        match item.unwrap() {
+            (sample, StreamResult::Command(VgmCommand::Sn76489Write(_, spec))) => {
+                writes.push((sample, spec.value))
+            }
+            (_, StreamResult::Command(_)) => {}
+            (sample, StreamResult::EndOfStream | StreamResult::NeedsMoreData) => {
+                return (writes, sample);
+            }
+        }
+    }
+    unreachable!("stream ended without EndOfStream")
+}
+
+#[test]
+fn test_timed_positions_continue_across_loops_and_fadeout() {
+    let mut stream = VgmStream::from_document(looped_psg_doc());
+    stream.set_loop_count(Some(3));
+    stream.set_fadeout_samples(Some(30));
+
+    let (writes, end) = timed_psg_writes(&mut stream);
+
+    assert_eq!(
+        writes,
+        vec![(0, 0x90), (100, 0x91), (150, 0x91), (200, 0x91)]
+    );
+    // the fadeout extends the end by 30 samples
+    assert_eq!(end, 280);
+    assert_eq!(stream.absolute_sample(), 280);
+    // the loop-relative counter restarted at the last loop point
+    assert_eq!(stream.current_sample(), 80);
+}
+
+#[test]
+fn test_timed_positions_of_generated_dac_writes() {
+    let mut stream = VgmStream::from_document(single_dac_stream_doc((0..4).collect(), 10));
+
+    let mut writes = Vec::new();
+    for item in stream.timed() {
+        match item.unwrap() {
+            (sample, StreamResult::Command(VgmCommand::Ym2612Write(_, spec))) => {
+                writes.push((sample, spec.value))
+            }
+            (_, StreamResult::Command(_)) => {}
+            _ => break,
+        }
+    }
+
+    assert_eq!(writes, vec![(0, 0), (1, 1), (2, 2), (3, 3)]);
+    assert_eq!(stream.absolute_sample(), 10);
+    assert_eq!(stream.last_result_sample(), 10);
+}
+
+#[test]
+fn test_seek_sets_absolute_sample_within_first_loop() {
+    let bytes: Vec<u8> = (&looped_psg_doc()).into();
+    for mut stream in [
+        VgmStream::from_document(looped_psg_doc()),
+        VgmStream::from_vgm(bytes).unwrap(),
+    ] {
+        stream.set_loop_count(Some(2));
+        stream.seek_to_sample(20).unwrap();
+        assert_eq!(stream.absolute_sample(), 150);
+
+        stream.reset();
+        assert_eq!(stream.absolute_sample(), 0);
+        assert_eq!(stream.last_result_sample(), 0);
+    }
+}
+
+#[test]
+fn test_append_document_plays_tracks_gaplessly() {
+    let mut stream = VgmStream::from_document(single_dac_stream_doc(vec![1, 2], 10));
+    stream.append_document(looped_psg_doc()).unwrap();
+    stream
+        .append_document(single_dac_stream_doc(vec![7, 8, 9], 20))
+        .unwrap();
+    assert_eq!(stream.queued_documents(), 2);
+
+    let mut writes = Vec::new();
+    let end = loop {
+        match stream.timed().next().unwrap().unwrap() {
+            (sample, StreamResult::Command(VgmCommand::Ym2612Write(_, spec))) => {
+                writes.push((sample, spec.value))
+            }
+            (sample, StreamResult::Command(VgmCommand::Sn76489Write(_, spec))) => {
+                writes.push((sample, spec.value))
+            }
+            (_, StreamResult::Command(_)) => {}
+            (sample, _) => break sample,
+        }
+    };
+
+    // the second DAC track reads its own data block, not the first track's
+    assert_eq!(
+        writes,
+        vec![
+            (0, 1),
+            (1, 2),
+            (10, 0x90),
+            (110, 0x91),
+            (160, 7),
+            (161, 8),
+            (162, 9),
+        ]
+    );
+    assert_eq!(end, 180);
+    assert_eq!(stream.current_sample(), 20);
+    assert_eq!(stream.queued_documents(), 0);
+}
+
+#[test]
+fn test_append_document_requires_document_source() {
+    let bytes: Vec<u8> = (&looped_psg_doc()).into();
+    let mut file = VgmStream::from_vgm(bytes).unwrap();
+    assert!(file.append_document(looped_psg_doc()).is_err());
+
+    let mut buffer = VgmStream::new();
+    assert!(buffer.append_document(looped_psg_doc()).is_err());
+    assert_eq!(buffer.queued_documents(), 0);
+}
+
+#[test]
+fn test_reset_with_document_and_bytes_match_fresh_streams() {
+    let collect = |stream: &mut VgmStream| {
+        stream
+            .timed()
+            .map(Result::unwrap)
+            .take_while(|(_, result)| matches!(result, StreamResult::Command(_)))
+            .collect::<Vec<_>>()
+    };
+    let dac = single_dac_stream_doc(vec![1, 2, 3], 10);
+    let psg = looped_psg_doc();
+    let psg_bytes: Vec<u8> = (&psg).into();
+
+    let mut fresh_dac = VgmStream::from_document(dac.clone());
+    fresh_dac.set_loop_count(Some(2));
+    let mut fresh_psg = VgmStream::from_document(psg.clone());
+    fresh_psg.set_loop_count(Some(2));
+    let mut fresh_psg_file = VgmStream::from_vgm(psg_bytes.clone()).unwrap();
+    fresh_psg_file.set_loop_count(Some(2));
+
+    let mut stream = VgmStream::from_document(psg.clone());
+    stream.set_loop_count(Some(2));
+    stream.append_document(psg.clone()).unwrap();
+    collect(&mut stream);
+
+    stream.reset_with_document(dac);
+    assert_eq!(stream.queued_documents(), 0);
+    assert_eq!(collect(&mut stream), collect(&mut fresh_dac));
+
+    stream.reset_with_bytes(&psg_bytes).unwrap();
+    assert_eq!(collect(&mut stream), collect(&mut fresh_psg_file));
+
+    // a second file reuses the file buffer
+    stream.reset_with_bytes(&psg_bytes).unwrap();
+    assert_eq!(stream.absolute_sample(), 0);
+    assert_eq!(stream.last_result_sample(), 0);
+    assert_eq!(collect(&mut stream).len(), 6);
+
+    stream.reset_with_document(psg);
+    assert_eq!(collect(&mut stream), collect(&mut fresh_psg));
+
+    // a bad header leaves the stream as it was
+    assert!(stream.reset_with_bytes(&[0; 16]).is_err());
+    assert_eq!(stream.absolute_sample(), 200);
+}
+
+#[test]
+fn test_header_sample_rate_scales_dac_stream_timing() {
+    let dac_write_samples = |stream: &mut VgmStream| {
+        stream
+            .timed()
+            .map(Result::unwrap)
+            .take_while(|(_, result)| matches!(result, StreamResult::Command(_)))
+            .filter_map(|(sample, result)| match result {
+                StreamResult::Command(VgmCommand::Ym2612Write(_, _)) => Some(sample),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut doc = single_dac_stream_doc(vec![1, 2, 3, 4], 22_050);
+    let mut stream = VgmStream::from_document(doc.clone());
+    assert_eq!(stream.sample_rate(), 44_100);
+    assert_eq!(dac_write_samples(&mut stream), vec![0, 1, 2, 3]);
+
+    // a 44.1 kHz stream played from a 22.05 kHz log: two writes per sample
+    doc.header.sample_rate = 22_050;
+    let mut stream = VgmStream::from_document(doc.clone());
+    assert_eq!(stream.sample_rate(), 22_050);
+    assert_eq!(dac_write_samples(&mut stream), vec![0, 0, 1, 1]);
+    assert_eq!(stream.elapsed(), std::time::Duration::from_secs(1));
+
+    // the VGM 1.01 playback rate is not a sample rate
+    doc.header.sample_rate = 60;
+    let bytes: Vec<u8> = (&doc).into();
+    let mut stream = VgmStream::from_vgm(bytes).unwrap();
+    assert_eq!(stream.sample_rate(), 44_100);
+    stream.set_sample_rate(22_050);
+    assert_eq!(dac_write_samples(&mut stream), vec![0, 0, 1, 1]);
+    assert_eq!(stream.elapsed(), std::time::Duration::from_secs(1));
+}
+
+#[test]
+fn test_from_commands_matches_from_document() {
+    let collect = |stream: &mut VgmStream| {
+        stream.set_loop_count(Some(2));
+        let mut out = Vec::new();
+        while let Some(Ok(StreamResult::Command(command))) = stream.next() {
+            out.push((stream.current_sample(), command));
+        }
+        out
+    };
+
+    let mut doc = single_dac_stream_doc(vec![1, 2, 3, 4], 22_050);
+    doc.header.sample_rate = 22_050;
+    let expected = collect(&mut VgmStream::from_document(doc.clone()));
+    let hint = StreamHint::from(&doc.header);
+    assert_eq!(hint.sample_rate, 22_050);
+    let mut stream = VgmStream::from_commands(doc.commands.clone(), hint);
+    assert_eq!(collect(&mut stream), expected);
+    assert!(
+        expected
+            .iter()
+            .any(|(_, command)| matches!(command, VgmCommand::Ym2612Write(_, _)))
+    );
+
+    // Looping back past the data block plays the 4 bytes once more instead
+    // of appending a second copy of them.
+    let commands = doc
+        .commands
+        .iter()
+        .filter(|command| !matches!(command, VgmCommand::EndOfData(_)))
+        .cloned();
+    let hint = StreamHint {
+        loop_index: Some(1),
+        ..hint
+    };
+    let writes = |results: &[(usize, VgmCommand)]| {
+        results
+            .iter()
+            .filter(|(_, command)| matches!(command, VgmCommand::Ym2612Write(_, _)))
+            .count()
+    };
+    assert_eq!(writes(&expected), 12);
+    let looped = collect(&mut VgmStream::from_commands(commands, hint));
+    assert_eq!(writes(&looped), 8);
+}
+
+#[test]
+fn test_data_blocks_from_document_are_stored_or_yielded_unchanged() {
+    use soundlog::vgm::command::DataBlock;
+
+    let block = |data_type: u8, data: Vec<u8>| DataBlock {
+        marker: 0x66,
+        chip_instance: 0,
+        data_type,
+        size: data.len() as u32,
+        data,
+    };
+    let rom = block(0x80, vec![0x00, 0x10, 0, 0, 0x00, 0x00, 0, 0, 0xAA, 0xBB]);
+    let short_rom = block(0x81, vec![0x01, 0x02]);
+    let ram = block(0xC0, vec![0x00, 0x02, 0x11, 0x22]);
+    let mut builder = VgmBuilder::new();
+    builder.add_vgm_command(block(0x00, vec![0x80; 1024]));
+    builder.add_vgm_command(rom.clone());
+    builder.add_vgm_command(short_rom.clone());
+    builder.add_vgm_command(ram.clone());
+    builder.add_vgm_command(block(0x00, vec![0x7F; 1024]));
+    builder.add_vgm_command(WaitSamples(1));
+    let doc = builder.finalize();
+
+    let mut stream = VgmStream::from_document(doc.clone());
+    stream.set_loop_count(Some(1));
+    let mut yielded = Vec::new();
+    while let Some(Ok(StreamResult::Command(command))) = stream.next() {
+        if let VgmCommand::DataBlock(block) = command {
+            yielded.push(*block);
+        }
+    }
+    assert_eq!(yielded, vec![rom, short_rom, ram]);
+    assert_eq!(
+        stream.get_uncompressed_stream(0x00).unwrap().data.len(),
+        2048
+    );
+    assert_eq!(stream.total_data_block_size(), 2048 + 10 + 2 + 4);
+
+    let mut stream = VgmStream::from_document(doc);
+    stream.set_max_data_block_size(1500);
+    let result = stream
+        .find(|result| result.is_err())
+        .expect("size limit error");
+    assert!(matches!(
+        result,
+        Err(soundlog::ParseError::DataBlockSizeExceeded {
+            current_size: 1040,
+            limit: 1500,
+            attempted_size: 1024,
+        })
+    ));
+}
+
+#[test]
+fn test_late_data_block_policy_extends_replaces_or_rejects() {
+    use soundlog::vgm::command::DataBlock;
+    use soundlog::vgm::stream::{LateDataBlock, LateDataBlockPolicy};
+
+    let block = |data: Vec<u8>| {
+        VgmCommand::from(DataBlock {
+            marker: 0x66,
+            chip_instance: 0,
+            data_type: 0x00,
+            size: data.len() as u32,
+            data,
+        })
+    };
+    let commands = vec![
+        block(vec![1, 2, 3, 4]),
+        WaitSamples(10).into(),
+        block(vec![5, 6]),
+        WaitSamples(10).into(),
+    ];
+    let run = |policy| {
+        let mut stream = VgmStream::from_commands(commands.clone(), StreamHint::default());
+        stream.set_late_data_block_policy(policy);
+        let result = loop {
+            match stream.next() {
+                Some(Ok(StreamResult::Command(_))) => {}
+                other => break other,
+            }
+        };
+        (stream, result)
+    };
+    let late = |len, policy| LateDataBlock {
+        sample: 10,
+        bank: 0x00,
+        previous_len: 4,
+        len,
+        policy,
+    };
+
+    let (stream, result) = run(LateDataBlockPolicy::Extend);
+    assert!(matches!(result, Some(Ok(StreamResult::EndOfStream))));
+    assert_eq!(stream.data_bank_lens(), vec![(0x00, 6)]);
+    assert_eq!(
+        stream.late_data_blocks(),
+        &[late(6, LateDataBlockPolicy::Extend)]
+    );
+    assert_eq!(stream.total_data_block_size(), 6);
+
+    let (stream, result) = run(LateDataBlockPolicy::Replace);
+    assert!(matches!(result, Some(Ok(StreamResult::EndOfStream))));
+    assert_eq!(
+        stream.get_uncompressed_stream(0x00).unwrap().data,
+        vec![5, 6]
+    );
+    assert_eq!(
+        stream.late_data_blocks(),
+        &[late(2, LateDataBlockPolicy::Replace)]
+    );
+    assert_eq!(stream.total_data_block_size(), 2);
+
+    let (stream, result) = run(LateDataBlockPolicy::Forbid);
+    assert!(matches!(
+        result,
+        Some(Err(soundlog::ParseError::DataInconsistency(_)))
+    ));
+    assert_eq!(stream.data_bank_lens(), vec![(0x00, 4)]);
+    assert_eq!(
+        stream.late_data_blocks(),
+        &[late(4, LateDataBlockPolicy::Forbid)]
+    );
+}
+
+#[test]
+fn test_channel_mask_mutes_key_ons_and_levels() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(chip::Chip::Ym2612, Instance::Primary, 7_670_454);
+    builder.register_chip(chip::Chip::Sn76489, Instance::Primary, 3_579_545);
+    let key = |value| chip::Ym2612Spec {
+        port: 0,
+        register: 0x28,
+        value,
+    };
+    let psg = |value| chip::PsgSpec { value };
+    builder.add_chip_write(Instance::Primary, key(0xF0));
+    builder.add_chip_write(Instance::Primary, key(0xF1));
+    builder.add_chip_write(Instance::Primary, psg(0x92));
+    builder.add_chip_write(Instance::Primary, psg(0xB4));
+    builder.add_chip_write(Instance::Primary, psg(0x8A));
+    builder.add_vgm_command(WaitSamples(10));
+    // a data byte for the latched tone register
+    builder.add_chip_write(Instance::Primary, psg(0x01));
+    builder.add_chip_write(Instance::Primary, key(0xF1));
+    builder.add_chip_write(Instance::Primary, psg(0xB3));
+    builder.add_vgm_command(WaitSamples(10));
+    let doc = builder.finalize();
+
+    let writes = |stream: &mut VgmStream| -> Vec<u32> {
+        let mut values = Vec::new();
+        while let Some(Ok(StreamResult::Command(command))) = stream.next() {
+            match command.register_write() {
+                Some(write) => values.push(write.value),
+                None => break,
+            }
+        }
+        values
+    };
+
+    let mut stream = VgmStream::from_document(doc);
+    assert!(stream.set_channel_mask(chip::Chip::Ym2612, Instance::Primary, 0b10));
+    assert!(stream.set_channel_mask(chip::Chip::Sn76489, Instance::Primary, 0b1));
+    assert!(!stream.set_channel_mask(chip::Chip::SegaPcm, Instance::Primary, 0b1));
+    assert_eq!(writes(&mut stream), vec![0xF0, 0x01, 0x9F, 0xB4, 0x8A]);
+
+    // Unmuting PSG channel 0 restores its level, muting channel 1 silences
+    // it, and the tone register is latched again for the data byte.
+    stream.set_channel_mask(chip::Chip::Ym2612, Instance::Primary, 0);
+    stream.set_channel_mask(chip::Chip::Sn76489, Instance::Primary, 0b10);
+    assert_eq!(
+        stream.channel_mask(&chip::Chip::Sn76489, Instance::Primary),
+        0b10
+    );
+    assert_eq!(
+        writes(&mut stream),
+        vec![0x92, 0xBF, 0x8A, 0x01, 0xF1, 0xBF]
+    );
+
+    // The mask survives a reset, the writes seen before it do not.
+    stream.reset();
+    assert_eq!(
+        stream.channel_mask(&chip::Chip::Sn76489, Instance::Primary),
+        0b10
+    );
+    assert!(stream.set_channel_mask(chip::Chip::Sn76489, Instance::Primary, 0));
+    assert_eq!(writes(&mut stream), vec![0xF0, 0xF1, 0x92, 0xB4, 0x8A]);
+}
+
+#[test]
+fn test_next_ref_borrows_rom_dumps_from_document() {
+    use soundlog::vgm::command::DataBlock;
+    use soundlog::vgm::stream::StreamResultRef;
+    use std::borrow::Cow;
+
+    let rom = DataBlock {
+        marker: 0x66,
+        chip_instance: 0,
+        data_type: 0x80,
+        size: 8 + 4096,
+        data: [vec![0x00, 0x10, 0, 0, 0, 0, 0, 0], vec![0xAA; 4096]].concat(),
+    };
+    let mut builder = VgmBuilder::new();
+    builder.add_vgm_command(DataBlock {
+        marker: 0x66,
+        chip_instance: 0,
+        data_type: 0x00,
+        size: 4,
+        data: vec![0x80; 4],
+    });
+    builder.add_vgm_command(rom.clone());
+    builder.add_vgm_command(WaitSamples(10));
+    builder.add_vgm_command(rom.clone());
+    builder.add_vgm_command(WaitSamples(10));
+    let doc = builder.finalize();
+
+    let mut owned = VgmStream::from_document(doc.clone());
+    owned.set_loop_count(Some(1));
+    let mut expected = Vec::new();
+    for result in &mut owned {
+        let result = result.unwrap();
+        let end = result == StreamResult::EndOfStream;
+        expected.push(result);
+        if end {
+            break;
+        }
+    }
+
+    let mut stream = VgmStream::from_document(doc);
+    stream.set_loop_count(Some(1));
+    let mut results = Vec::new();
+    let mut borrowed = 0;
+    while let Some(result) = stream.next_ref() {
+        let result = result.unwrap();
+        if let StreamResultRef::Command(Cow::Borrowed(VgmCommand::DataBlock(block))) = &result {
+            // the dump is the document's own block, not a copy
+            assert_eq!(**block, rom);
+            borrowed += 1;
+        }
+        let end = matches!(result, StreamResultRef::EndOfStream);
+        results.push(result.into_owned());
+        if end {
+            break;
+        }
+    }
+
+    assert_eq!(borrowed, 2);
+    assert_eq!(results, expected);
+    assert_eq!(
+        stream.total_data_block_size(),
+        owned.total_data_block_size()
+    );
+}
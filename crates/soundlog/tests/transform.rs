@@ -0,0 +1,888 @@
+use soundlog::chip::fnumber::{ChipTypeSpec, OpnaSpec};
+use soundlog::chip::{
+    Ay8910Spec, Chip, GameGearPsgSpec, PsgSpec, SegaPcmSpec, Ym2151Spec, Ym2610Spec, Ym2612Spec,
+};
+use soundlog::vgm::command::{
+    DacStreamChipType, DataBlock, Instance, LengthMode, SeekOffset, SetStreamData,
+    SetStreamFrequency, SetupStreamControl, StartStream, StartStreamFastCall,
+    StartStreamFastCallFlags, VgmCommand, WaitSamples, Ym2612Port0Address2AWriteAndWaitN,
+};
+use soundlog::vgm::compression::BlockEncoding;
+use soundlog::vgm::detail::{
+    DataBlockType, RomRamChipType, RomRamDump, StreamChipType, UncompressedStream, parse_data_block,
+};
+use soundlog::vgm::header::ChipId;
+use soundlog::vgm::loop_check::{LoopIssue, check_loop};
+use soundlog::vgm::stream::{StreamResult, VgmStream};
+use soundlog::vgm::transform::{
+    CompressOptions, PruneRomOptions, QuantizeOptions, Rounding, VolumeOptions,
+    compress_data_blocks, correct_clock, dedupe_data_blocks, match_clocks, patch_loop_state,
+    port_psg, prune_rom_blocks, quantize, scale_volume,
+};
+use soundlog::vgm::verify::verify_wait_conservation;
+use soundlog::{VgmBuilder, VgmDocument};
+
+/// Helper: absolute sample time of every non-wait command.
+fn write_times(doc: &VgmDocument) -> Vec<u32> {
+    let mut time = 0;
+    let mut times = Vec::new();
+    for cmd in doc.iter() {
+        match cmd {
+            VgmCommand::WaitSamples(_)
+            | VgmCommand::Wait735Samples(_)
+            | VgmCommand::Wait882Samples(_)
+            | VgmCommand::WaitNSample(_) => time += cmd.wait_samples(),
+            _ => {
+                times.push(time);
+                time += cmd.wait_samples();
+            }
+        }
+    }
+    times
+}
+
+fn jittery_doc() -> VgmDocument {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+    builder.add_vgm_command(WaitSamples(730));
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x90 });
+    builder.add_vgm_command(WaitSamples(745));
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x91 });
+    builder.add_vgm_command(WaitSamples(735));
+    builder.finalize()
+}
+
+#[test]
+fn quantize_nearest_snaps_to_frames() {
+    let doc = jittery_doc();
+    let (quantized, report) = quantize(&doc, &QuantizeOptions::new(735));
+
+    // writes at 730 and 1475, end at 2210
+    assert_eq!(write_times(&quantized), vec![735, 1470, 2205]);
+    assert_eq!(report.max_displacement, 5);
+    assert_eq!(report.moved_commands, 3);
+    assert_eq!(report.total_samples_before, 2210);
+    assert_eq!(report.total_samples_after, 2205);
+    assert_eq!(quantized.header.total_samples, 2205);
+}
+
+#[test]
+fn quantize_floor_and_ceil() {
+    let doc = jittery_doc();
+
+    let floor = QuantizeOptions::new(735).with_rounding(Rounding::Floor);
+    let (quantized, report) = quantize(&doc, &floor);
+    assert_eq!(write_times(&quantized), vec![0, 1470, 2205]);
+    assert_eq!(report.max_displacement, 730);
+    assert_eq!(report.max_displacement_index, Some(1));
+
+    let ceil = QuantizeOptions::new(735).with_rounding(Rounding::Ceil);
+    let (quantized, _) = quantize(&doc, &ceil);
+    assert_eq!(write_times(&quantized), vec![735, 2205, 2940]);
+}
+
+#[test]
+fn quantize_grid_of_one_is_identity_in_time() {
+    let doc = jittery_doc();
+    let (quantized, report) = quantize(&doc, &QuantizeOptions::new(1));
+
+    assert_eq!(write_times(&quantized), write_times(&doc));
+    assert_eq!(report.max_displacement, 0);
+    assert_eq!(report.moved_commands, 0);
+}
+
+#[test]
+fn quantize_preserves_loop_point() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+    builder.add_vgm_command(WaitSamples(100));
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x90 });
+    builder.add_vgm_command(WaitSamples(740));
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x91 });
+    builder.add_vgm_command(WaitSamples(735));
+    builder.set_loop_index(3);
+    let doc = builder.finalize();
+
+    let (quantized, _) = quantize(&doc, &QuantizeOptions::new(735));
+    let loop_index = quantized.loop_command_index().expect("loop preserved");
+
+    assert_eq!(
+        quantized.commands[loop_index],
+        VgmCommand::Sn76489Write(Instance::Primary, PsgSpec { value: 0x91 })
+    );
+    assert_eq!(quantized.header.loop_samples, 735);
+}
+
+#[test]
+fn quantize_splits_ym2612_dac_wait() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+    builder.add_vgm_command(Ym2612Port0Address2AWriteAndWaitN(3));
+    builder.add_vgm_command(Ym2612Port0Address2AWriteAndWaitN(3));
+    let doc = builder.finalize();
+
+    let (quantized, _) = quantize(&doc, &QuantizeOptions::new(4));
+
+    assert_eq!(
+        quantized.commands[0],
+        VgmCommand::YM2612Port0Address2AWriteAndWaitN(Ym2612Port0Address2AWriteAndWaitN(0))
+    );
+    assert_eq!(write_times(&quantized), vec![0, 4, 8]);
+}
+
+fn pcm_doc(blocks: &[Vec<u8>]) -> VgmDocument {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+    for data in blocks {
+        builder.attach_data_block(UncompressedStream {
+            chip_type: StreamChipType::Ym2612Pcm,
+            data: data.clone(),
+        });
+    }
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x90 });
+    builder.add_vgm_command(WaitSamples(735));
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x91 });
+    builder.add_vgm_command(WaitSamples(735));
+    builder.set_loop_offset(2);
+    builder.finalize()
+}
+
+/// Helper: data bank `bank` as seen by `VgmStream`, which keeps compressed
+/// blocks under their own data type.
+fn pcm_bank(doc: &VgmDocument, bank: u8) -> Vec<u8> {
+    let mut parser = VgmStream::from_document(doc.clone());
+    for result in &mut parser {
+        match result.unwrap() {
+            StreamResult::Command(_) => {}
+            StreamResult::NeedsMoreData | StreamResult::EndOfStream => break,
+        }
+    }
+    parser.get_uncompressed_stream(bank).unwrap().data.clone()
+}
+
+#[test]
+fn compress_data_blocks_is_lossless_and_keeps_loop() {
+    let doc = pcm_doc(&[
+        (0..200).map(|i| 0x80 + (i % 5) as u8).collect(),
+        (0..100).map(|i| 0x84 - (i % 5) as u8).collect(),
+    ]);
+
+    let (optimized, report) = compress_data_blocks(&doc, &CompressOptions::new(3));
+
+    assert_eq!(report.compressed_blocks, 2);
+    assert_eq!(report.bytes_before, 300);
+    assert!(matches!(report.encoding, Some(BlockEncoding::Dpcm { .. })));
+    assert!(report.bytes_after < report.bytes_before / 2);
+    assert_eq!(pcm_bank(&optimized, 0x40), pcm_bank(&doc, 0x00));
+    verify_wait_conservation(&doc, &optimized).unwrap();
+    let loop_index = optimized.loop_command_index().expect("loop preserved");
+    assert_eq!(
+        optimized.commands[loop_index],
+        VgmCommand::Sn76489Write(Instance::Primary, PsgSpec { value: 0x91 })
+    );
+}
+
+#[test]
+fn compress_data_blocks_leaves_incompressible_data() {
+    let doc = pcm_doc(&[(0..=255u8).map(|i| i.wrapping_mul(i)).collect()]);
+
+    let (optimized, report) = compress_data_blocks(&doc, &CompressOptions::new(4));
+
+    assert_eq!(report.encoding, None);
+    assert_eq!(report.compressed_blocks, 0);
+    assert_eq!(optimized, doc);
+}
+
+/// Helper: every command `VgmStream` produces, with DAC streams and `0x8n`
+/// writes expanded and data blocks dropped.
+fn expanded_commands(doc: &VgmDocument) -> Vec<VgmCommand> {
+    let mut parser = VgmStream::from_document(doc.clone());
+    let mut commands = Vec::new();
+    for result in &mut parser {
+        match result.unwrap() {
+            StreamResult::Command(VgmCommand::DataBlock(_)) => {}
+            StreamResult::Command(command) => commands.push(command),
+            StreamResult::NeedsMoreData | StreamResult::EndOfStream => break,
+        }
+    }
+    commands
+}
+
+fn dac_doc(blocks: &[Vec<u8>], body: Vec<VgmCommand>) -> VgmDocument {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+    for data in blocks {
+        builder.attach_data_block(UncompressedStream {
+            chip_type: StreamChipType::Ym2612Pcm,
+            data: data.clone(),
+        });
+    }
+    builder.add_vgm_command(SetupStreamControl {
+        stream_id: 0,
+        chip_type: DacStreamChipType {
+            chip_id: ChipId::Ym2612,
+            instance: Instance::Primary,
+        },
+        write_port: 0,
+        write_command: 0x2A,
+    });
+    builder.add_vgm_command(SetStreamData {
+        stream_id: 0,
+        data_bank_id: 0,
+        step_size: 1,
+        step_base: 0,
+    });
+    builder.add_vgm_command(SetStreamFrequency {
+        stream_id: 0,
+        frequency: 22_050,
+    });
+    for command in body {
+        builder.add_vgm_command(command);
+    }
+    builder.finalize()
+}
+
+fn fast_call(block_id: u16) -> VgmCommand {
+    StartStreamFastCall {
+        stream_id: 0,
+        block_id,
+        flags: StartStreamFastCallFlags {
+            reverse: false,
+            looped: false,
+        },
+    }
+    .into()
+}
+
+fn block_ids(doc: &VgmDocument) -> Vec<u16> {
+    doc.iter()
+        .filter_map(|c| match c {
+            VgmCommand::StartStreamFastCall(f) => Some(f.block_id),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn dedupe_remaps_fast_call_block_ids() {
+    let a: Vec<u8> = (0x10..0x18).collect();
+    let doc = dac_doc(
+        &[a.clone(), a, vec![0x90, 0x91, 0x92]],
+        vec![
+            fast_call(1),
+            WaitSamples(20).into(),
+            fast_call(2),
+            WaitSamples(20).into(),
+        ],
+    );
+
+    let (deduped, report) = dedupe_data_blocks(&doc);
+
+    assert_eq!(report.removed_blocks, 1);
+    assert_eq!(report.bytes_saved, 8);
+    assert_eq!(block_ids(&deduped), vec![0, 1]);
+    assert_eq!(expanded_commands(&deduped), expanded_commands(&doc));
+    verify_wait_conservation(&doc, &deduped).unwrap();
+}
+
+#[test]
+fn dedupe_moves_seek_offsets_into_containing_block() {
+    let a: Vec<u8> = (0x20..0x30).collect();
+    let contained = a[4..8].to_vec();
+    let mut body = vec![SeekOffset(16).into()];
+    body.extend((0..4).map(|_| Ym2612Port0Address2AWriteAndWaitN(2).into()));
+    body.push(SeekOffset(21).into());
+    body.extend((0..2).map(|_| Ym2612Port0Address2AWriteAndWaitN(2).into()));
+    let doc = dac_doc(&[a, contained, vec![0x40, 0x41, 0x42]], body);
+
+    let (deduped, report) = dedupe_data_blocks(&doc);
+
+    assert_eq!(report.removed_blocks, 1);
+    let seeks: Vec<u32> = deduped
+        .iter()
+        .filter_map(|c| match c {
+            VgmCommand::SeekOffset(s) => Some(s.0),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(seeks, vec![4, 17]);
+    assert_eq!(expanded_commands(&deduped), expanded_commands(&doc));
+}
+
+#[test]
+fn dedupe_does_not_replace_block_with_later_container() {
+    let a: Vec<u8> = (0x20..0x30).collect();
+    let contained = a[4..8].to_vec();
+    let mut body = vec![SeekOffset(0).into()];
+    body.extend((0..4).map(|_| Ym2612Port0Address2AWriteAndWaitN(2).into()));
+    let doc = dac_doc(&[contained, a], body);
+
+    let (deduped, report) = dedupe_data_blocks(&doc);
+
+    assert_eq!(report.removed_blocks, 0);
+    assert_eq!(report.kept_for_references, 0);
+    assert_eq!(deduped, doc);
+}
+
+#[test]
+fn dedupe_keeps_contained_block_played_by_fast_call() {
+    let a: Vec<u8> = (0x20..0x30).collect();
+    let contained = a[4..8].to_vec();
+    let doc = dac_doc(&[a, contained], vec![fast_call(1), WaitSamples(20).into()]);
+
+    let (deduped, report) = dedupe_data_blocks(&doc);
+
+    assert_eq!(report.removed_blocks, 0);
+    assert_eq!(report.kept_for_references, 1);
+    assert_eq!(deduped, doc);
+}
+
+/// Helper: ROM blocks as (start address, data).
+fn rom_blocks(doc: &VgmDocument) -> Vec<(u32, Vec<u8>)> {
+    doc.iter()
+        .filter_map(|c| match c {
+            VgmCommand::DataBlock(b) => match parse_data_block((**b).clone()) {
+                Ok(DataBlockType::RomRamDump(d)) => Some((d.start_address, d.data)),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+/// ADPCM-B plays `0x0100..0x0200` and `0x0400..0x0500` of a 0x1000 byte
+/// ROM block; a PCM block follows and is played by fast call.
+fn adpcm_doc() -> VgmDocument {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2610b, Instance::Primary, 8_000_000);
+    builder.attach_data_block(RomRamDump {
+        chip_type: RomRamChipType::Ym2610DeltaTRom,
+        rom_size: 0x40000,
+        start_address: 0,
+        data: (0..0x1000).map(|i| (i / 0x100) as u8).collect(),
+    });
+    builder.attach_data_block(RomRamDump {
+        chip_type: RomRamChipType::Ym2610DeltaTRom,
+        rom_size: 0x40000,
+        start_address: 0x8000,
+        data: vec![0xEE; 0x100],
+    });
+    builder.attach_data_block(UncompressedStream {
+        chip_type: StreamChipType::Ym2612Pcm,
+        data: vec![0x80, 0x81],
+    });
+    for (start, end) in [(0x01, 0x01), (0x04, 0x04)] {
+        for (register, value) in [
+            (0x12, start),
+            (0x13, 0),
+            (0x14, end),
+            (0x15, 0),
+            (0x10, 0x80),
+        ] {
+            builder.add_chip_write(
+                Instance::Primary,
+                Ym2610Spec {
+                    port: 0,
+                    register,
+                    value,
+                },
+            );
+        }
+        builder.add_vgm_command(WaitSamples(100));
+    }
+    builder.add_vgm_command(fast_call(2));
+    builder.add_vgm_command(WaitSamples(100));
+    builder.set_loop_offset(5);
+    builder.finalize()
+}
+
+#[test]
+fn prune_rom_blocks_keeps_read_ranges_only() {
+    let doc = adpcm_doc();
+
+    let (pruned, report) = prune_rom_blocks(&doc, &PruneRomOptions::new(0));
+
+    assert_eq!(report.pruned_blocks, 2);
+    assert_eq!(report.bytes_before, 0x1100);
+    assert_eq!(report.bytes_after, 0x200);
+    assert!(report.skipped.is_empty());
+    assert_eq!(
+        rom_blocks(&pruned),
+        vec![(0x100, vec![0x01; 0x100]), (0x400, vec![0x04; 0x100])]
+    );
+    // one ROM block became two and one was removed: the PCM block keeps id 2
+    assert_eq!(block_ids(&pruned), vec![2]);
+    verify_wait_conservation(&doc, &pruned).unwrap();
+    let loop_index = pruned.loop_command_index().expect("loop preserved");
+    assert_eq!(
+        pruned.commands[loop_index],
+        doc.commands[doc.loop_command_index().unwrap()]
+    );
+}
+
+#[test]
+fn prune_rom_blocks_merges_small_gaps() {
+    let doc = adpcm_doc();
+
+    let (pruned, report) = prune_rom_blocks(&doc, &PruneRomOptions::new(0x200));
+
+    assert_eq!(report.bytes_after, 0x400);
+    let blocks = rom_blocks(&pruned);
+    assert_eq!(blocks.len(), 1);
+    assert_eq!(blocks[0].0, 0x100);
+    assert_eq!(blocks[0].1.len(), 0x400);
+    assert_eq!(block_ids(&pruned), vec![1]);
+}
+
+#[test]
+fn prune_rom_blocks_skips_unknown_coverage() {
+    let mut builder = VgmBuilder::new();
+    builder.attach_data_block(RomRamDump {
+        chip_type: RomRamChipType::SegaPcmRom,
+        rom_size: 0x10000,
+        start_address: 0,
+        data: vec![0x55; 0x100],
+    });
+    let doc = builder.finalize();
+
+    let (pruned, report) = prune_rom_blocks(&doc, &PruneRomOptions::default());
+
+    assert_eq!(report.pruned_blocks, 0);
+    assert_eq!(report.skipped, vec![RomRamChipType::SegaPcmRom]);
+    assert_eq!(pruned, doc);
+}
+
+fn ym2612_writes(doc: &VgmDocument) -> Vec<(u8, u8, u8)> {
+    doc.iter()
+        .filter_map(|cmd| match cmd {
+            VgmCommand::Ym2612Write(_, s) => Some((s.port, s.register, s.value)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn correct_clock_preserves_ym2612_pitch() {
+    let old_clock = 7_670_454;
+    let write = |port, register, value| Ym2612Spec {
+        port,
+        register,
+        value,
+    };
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, old_clock);
+    // block 4, fnum 0x26A on port 0 channel 0
+    builder.add_chip_write(Instance::Primary, write(0, 0xA4, 0x22));
+    builder.add_chip_write(Instance::Primary, write(0, 0xA0, 0x6A));
+    builder.add_vgm_command(WaitSamples(100));
+    // block 2, fnum 0x7F0 on port 1: overflows at a lower clock
+    builder.add_chip_write(Instance::Primary, write(1, 0xA5, 0x17));
+    builder.add_chip_write(Instance::Primary, write(1, 0xA1, 0xF0));
+    builder.add_chip_write(Instance::Primary, write(0, 0x28, 0xF0));
+    builder.set_loop_index(2);
+    let doc = builder.finalize();
+
+    let new_clock = 7_000_000;
+    let (corrected, report) = correct_clock(&doc, Chip::Ym2612, new_clock);
+    assert_eq!(corrected.header.ym2612_clock, new_clock);
+    assert_eq!(report.old_clock, old_clock);
+    assert!(report.pitch_preserved);
+    assert_eq!(report.rewritten_writes, 4);
+    assert_eq!(report.clamped, 0);
+    assert_eq!(corrected.loop_command_index(), Some(2));
+
+    let writes = ym2612_writes(&corrected);
+    assert_eq!(writes.len(), 5);
+    assert_eq!(writes[4], (0, 0x28, 0xF0));
+    for pair in [&writes[0..2], &writes[2..4]] {
+        assert!(matches!(pair[0].1, 0xA4..=0xA6));
+        assert_eq!(pair[1].1, pair[0].1 - 4);
+    }
+    let freq = |clock: u32, high: u8, low: u8| {
+        let fnum = ((high as u32 & 0x07) << 8) | low as u32;
+        OpnaSpec::fnum_block_to_freq(fnum, (high >> 3) & 0x07, clock as f32).unwrap()
+    };
+    for (before, after) in [((0x22, 0x6A), &writes[0..2]), ((0x17, 0xF0), &writes[2..4])] {
+        let expected = freq(old_clock, before.0, before.1);
+        let actual = freq(new_clock, after[0].2, after[1].2);
+        assert!(
+            (actual / expected - 1.0).abs() < 0.002,
+            "{expected} -> {actual}"
+        );
+    }
+    // the second note moved up one block
+    assert_eq!((writes[2].2 >> 3) & 0x07, 3);
+}
+
+#[test]
+fn correct_clock_rescales_psg_periods_and_skips_unsupported_chips() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+    builder.register_chip(Chip::Ym2151, Instance::Primary, 3_579_545);
+    // channel 1 tone period 0x1FE, then a volume write
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0xAE });
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x1F });
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0xB0 });
+    builder.add_chip_write(
+        Instance::Primary,
+        Ym2151Spec {
+            register: 0x28,
+            value: 0x4A,
+        },
+    );
+    let doc = builder.finalize();
+
+    let (corrected, report) = correct_clock(&doc, Chip::Sn76489, 3_579_545 * 2);
+    assert!(report.pitch_preserved);
+    assert_eq!(report.rewritten_writes, 2);
+    let psg: Vec<u8> = corrected
+        .iter()
+        .filter_map(|cmd| match cmd {
+            VgmCommand::Sn76489Write(_, s) => Some(s.value),
+            _ => None,
+        })
+        .collect();
+    // the latch byte alone sets period 0x00E (0x01C after doubling), the
+    // data byte completes 0x1FE (0x3FC)
+    assert_eq!(psg, vec![0xAC, 0x01, 0x3F, 0xB0]);
+
+    let (corrected, report) = correct_clock(&doc, Chip::Ym2151, 4_000_000);
+    assert!(!report.pitch_preserved);
+    assert_eq!(corrected.header.ym2151_clock, 4_000_000);
+    assert_eq!(corrected.commands, doc.commands);
+
+    let (unchanged, report) = correct_clock(&doc, Chip::Ym2413, 3_579_545);
+    assert_eq!(report, Default::default());
+    assert_eq!(unchanged, doc);
+}
+
+#[test]
+fn match_clocks_rewrites_shared_chips_only() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+    builder.register_chip(Chip::Ym2151, Instance::Primary, 3_579_545);
+    builder.register_chip(Chip::Ym2413, Instance::Primary, 3_579_545);
+    // channel 1 tone period 0x1FE
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0xAE });
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x1F });
+    let doc = builder.finalize();
+
+    let mut reference = VgmBuilder::new();
+    reference.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545 * 2);
+    reference.register_chip(Chip::Ym2151, Instance::Primary, 4_000_000);
+    reference.register_chip(Chip::Ym2413, Instance::Primary, 3_579_545);
+    reference.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+    let reference = reference.finalize();
+
+    let (matched, report) = match_clocks(&doc, &reference.header);
+    let chips: Vec<&Chip> = report.corrections.iter().map(|(chip, _)| chip).collect();
+    assert_eq!(chips, vec![&Chip::Sn76489, &Chip::Ym2151]);
+    assert!(!report.pitch_preserved());
+    assert_eq!(report.corrections[0].1.rewritten_writes, 2);
+    assert_eq!(matched.header.sn76489_clock, 3_579_545 * 2);
+    assert_eq!(matched.header.ym2151_clock, 4_000_000);
+    assert_eq!(matched.header.ym2413_clock, 3_579_545);
+    assert_eq!(matched.header.ym2612_clock, 0);
+    // the same notes as `correct_clock` on the chip alone
+    let (expected, _) = correct_clock(&doc, Chip::Sn76489, 3_579_545 * 2);
+    assert_eq!(matched.commands, expected.commands);
+
+    let (unchanged, report) = match_clocks(&doc, &doc.header);
+    assert_eq!(report, Default::default());
+    assert!(report.pitch_preserved());
+    assert_eq!(unchanged, doc);
+}
+
+#[test]
+fn scale_volume_changes_carrier_tl_only() {
+    let write = |port, register, value| Ym2612Spec {
+        port,
+        register,
+        value,
+    };
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+    // channel 0, algorithm 4: operators 2 and 4 are carriers
+    builder.add_chip_write(Instance::Primary, write(0, 0xB0, 0x04));
+    for (register, tl) in [(0x40, 0x20), (0x44, 0x10), (0x48, 0x08), (0x4C, 0x08)] {
+        builder.add_chip_write(Instance::Primary, write(0, register, tl));
+    }
+    // channel 3, algorithm 7 (every operator is a carrier)
+    builder.add_chip_write(Instance::Primary, write(1, 0xB0, 0x07));
+    builder.add_chip_write(Instance::Primary, write(1, 0x4C, 0x02));
+    builder.add_vgm_command(WaitSamples(10));
+    // switching channel 0 to algorithm 7 makes operators 1 and 3 carriers
+    builder.add_chip_write(Instance::Primary, write(0, 0xB0, 0x07));
+    let doc = builder.finalize();
+
+    let options = VolumeOptions::new()
+        .with_chip(Chip::Ym2612, -6.0)
+        .with_channel(Chip::Ym2612, 3, 9.0)
+        .with_chip(Chip::Pwm, 3.0);
+    let (scaled, report) = scale_volume(&doc, &options);
+    assert_eq!(
+        ym2612_writes(&scaled),
+        vec![
+            (0, 0xB0, 0x04),
+            (0, 0x40, 0x20),
+            (0, 0x44, 0x10),
+            (0, 0x48, 0x10),
+            (0, 0x4C, 0x10),
+            (1, 0xB0, 0x07),
+            (1, 0x4C, 0x00),
+            (0, 0xB0, 0x07),
+            (0, 0x40, 0x28),
+            (0, 0x44, 0x18),
+        ]
+    );
+    assert_eq!(report.scaled_writes, 3);
+    assert_eq!(report.reemitted_writes, 2);
+    assert_eq!(report.clipped, 1);
+    assert_eq!(report.unsupported_chips, vec![Chip::Pwm]);
+}
+
+#[test]
+fn scale_volume_changes_psg_attenuation_and_pcm_levels() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+    builder.register_chip(Chip::SegaPcm, Instance::Primary, 4_000_000);
+    // channel 0 volume 0, noise off, noise volume 4, tone data byte
+    for value in [0x90, 0xFF, 0xF4, 0x05] {
+        builder.add_chip_write(Instance::Primary, PsgSpec { value });
+    }
+    // channel 1 left volume
+    builder.add_chip_write(
+        Instance::Primary,
+        SegaPcmSpec {
+            offset: 0x0A,
+            value: 0x40,
+        },
+    );
+    let doc = builder.finalize();
+
+    let options = VolumeOptions::new()
+        .with_chip(Chip::Sn76489, -4.0)
+        .with_channel(Chip::Sn76489, 3, -2.0)
+        .with_channel(Chip::SegaPcm, 1, -6.0);
+    let (scaled, report) = scale_volume(&doc, &options);
+    let psg: Vec<u8> = scaled
+        .iter()
+        .filter_map(|cmd| match cmd {
+            VgmCommand::Sn76489Write(_, s) => Some(s.value),
+            _ => None,
+        })
+        .collect();
+    // the data byte follows the noise volume latch
+    assert_eq!(psg, vec![0x92, 0xFF, 0xF7, 0x08]);
+    assert!(scaled.iter().any(|cmd| matches!(
+        cmd,
+        VgmCommand::SegaPcmWrite(
+            _,
+            SegaPcmSpec {
+                offset: 0x0A,
+                value: 0x20
+            }
+        )
+    )));
+    assert_eq!(report.scaled_writes, 4);
+    assert_eq!(report.clipped, 0);
+}
+
+#[test]
+fn patch_loop_state_keys_off_and_restores_patches() {
+    let ym2612 = |register, value| Ym2612Spec {
+        port: 0,
+        register,
+        value,
+    };
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+    builder.add_chip_write(Instance::Primary, ym2612(0x4C, 0x10));
+    builder.add_chip_write(Instance::Primary, ym2612(0x28, 0xF0));
+    builder.add_vgm_command(WaitSamples(100));
+    // loop: channel 0 off, channel 3 on and left on
+    builder.add_vgm_command(WaitSamples(100));
+    builder.add_chip_write(Instance::Primary, ym2612(0x4C, 0x7F));
+    builder.add_chip_write(Instance::Primary, ym2612(0x28, 0x00));
+    builder.add_chip_write(Instance::Primary, ym2612(0x28, 0xF4));
+    builder.add_vgm_command(WaitSamples(100));
+    builder.set_loop_index(3);
+    let doc = builder.finalize();
+
+    let (patched, report) = patch_loop_state(&doc);
+    assert_eq!(report.inserted_commands, 2);
+    assert_eq!(report.resolved.len(), 2);
+    let missing = LoopIssue::MissingNote {
+        chip: Chip::Ym2612,
+        instance: Instance::Primary,
+        channel: 0,
+    };
+    assert_eq!(report.unresolved, vec![missing.clone()]);
+    assert_eq!(check_loop(&patched).issues, vec![missing]);
+
+    assert_eq!(patched.loop_command_index(), Some(3));
+    assert_eq!(
+        patched.commands[3..5],
+        [
+            VgmCommand::Ym2612Write(Instance::Primary, ym2612(0x28, 0x04)),
+            VgmCommand::Ym2612Write(Instance::Primary, ym2612(0x4C, 0x10)),
+        ]
+    );
+    assert!(verify_wait_conservation(&doc, &patched).is_ok());
+}
+
+#[test]
+fn patch_loop_state_restarts_dac_stream_at_first_pass_position() {
+    let mut builder = VgmBuilder::new();
+    builder.add_vgm_command(DataBlock {
+        marker: 0x66,
+        chip_instance: 0,
+        data_type: 0x00,
+        size: 1000,
+        data: vec![0x80; 1000],
+    });
+    builder.add_vgm_command(SetStreamData {
+        stream_id: 0,
+        data_bank_id: 0x00,
+        step_size: 1,
+        step_base: 0,
+    });
+    builder.add_vgm_command(SetStreamFrequency {
+        stream_id: 0,
+        frequency: 44_100,
+    });
+    builder.add_vgm_command(StartStream {
+        stream_id: 0,
+        data_start_offset: 0,
+        length_mode: LengthMode::PlayUntilEnd {
+            reverse: false,
+            looped: false,
+        },
+        data_length: 0,
+    });
+    builder.add_vgm_command(WaitSamples(100));
+    builder.add_vgm_command(WaitSamples(200));
+    builder.set_loop_index(5);
+    let doc = builder.finalize();
+
+    let (patched, report) = patch_loop_state(&doc);
+    assert_eq!(report.inserted_commands, 1);
+    assert!(report.unresolved.is_empty());
+    assert_eq!(
+        patched.commands[5],
+        VgmCommand::StartStream(StartStream {
+            stream_id: 0,
+            data_start_offset: 100,
+            length_mode: LengthMode::CommandCount {
+                reverse: false,
+                looped: false,
+            },
+            data_length: 900,
+        })
+    );
+    assert!(check_loop(&patched).is_clean());
+}
+
+#[test]
+fn port_psg_moves_sn76489_songs_to_the_ay8910_and_back() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+    // channel 0 tone period 0x0FE at full volume
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x8E });
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x0F });
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x90 });
+    builder.add_vgm_command(WaitSamples(100));
+    // loop: white noise at rate 1, attenuation 2, then periodic noise
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0xE5 });
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0xF2 });
+    builder.add_chip_write(Instance::Primary, GameGearPsgSpec { value: 0xFF });
+    builder.add_vgm_command(WaitSamples(100));
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0xE0 });
+    builder.add_vgm_command(WaitSamples(100));
+    builder.set_loop_index(4);
+    let doc = builder.finalize();
+
+    let (ay, report) = port_psg(&doc, Chip::Ay8910, 1_789_772);
+    assert_eq!(report.source, Some(Chip::Sn76489));
+    assert_eq!(report.ported_writes, 6);
+    assert_eq!(report.dropped_writes, 1);
+    assert_eq!(report.periodic_noise, 1);
+    // noise rate 1 needs a noise period of 32
+    assert_eq!(report.clamped, 1);
+    assert_eq!(ay.header.sn76489_clock, 0);
+    assert_eq!(ay.header.ay8910_clock, 1_789_772);
+    assert!(ay.header.version >= 0x151);
+    assert_eq!(ay.header.total_samples, 300);
+    assert!(matches!(
+        ay.commands[ay.loop_command_index().unwrap()],
+        VgmCommand::Ay8910Write(..)
+    ));
+    let mut regs = [None; 16];
+    for cmd in ay.iter() {
+        assert!(!matches!(
+            cmd,
+            VgmCommand::Sn76489Write(..) | VgmCommand::GameGearPsgWrite(..)
+        ));
+        if let VgmCommand::Ay8910Write(_, s) = cmd {
+            regs[s.register as usize] = Some(s.value);
+        }
+    }
+    assert_eq!(regs[0], Some(0xFE));
+    assert_eq!(regs[1], Some(0x00));
+    // the last noise setting is periodic noise at rate 0
+    assert_eq!(regs[6], Some(16));
+    // noise only on channel C, whose tone is silent
+    assert_eq!(regs[7], Some(0x1C));
+    assert_eq!(regs[8], Some(15));
+    assert_eq!(regs[10], Some(14));
+
+    let (psg, report) = port_psg(&ay, Chip::Sn76489, 3_579_545);
+    assert_eq!(report.source, Some(Chip::Ay8910));
+    assert_eq!(report.envelope_writes, 0);
+    assert_eq!(psg.header.ay8910_clock, 0);
+    assert_eq!(psg.header.sn76489_clock, 3_579_545);
+    let bytes: Vec<u8> = psg
+        .iter()
+        .filter_map(|cmd| match cmd {
+            VgmCommand::Sn76489Write(_, s) => Some(s.value),
+            _ => None,
+        })
+        .collect();
+    for value in [0x8E, 0x0F, 0x90, 0xE5, 0xF2] {
+        assert!(
+            bytes.contains(&value),
+            "{value:#04X} missing in {bytes:02X?}"
+        );
+    }
+
+    let (unchanged, report) = port_psg(&doc, Chip::Ym2612, 7_670_454);
+    assert_eq!(report, Default::default());
+    assert_eq!(unchanged, doc);
+}
+
+#[test]
+fn port_psg_plays_ay8910_envelopes_at_full_volume() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ay8910, Instance::Primary, 1_789_772);
+    for (register, value) in [(0x07, 0x3E), (0x08, 0x10), (0x0D, 0x08), (0x0E, 0xFF)] {
+        builder.add_chip_write(Instance::Primary, Ay8910Spec { register, value });
+    }
+    let doc = builder.finalize();
+
+    let (psg, report) = port_psg(&doc, Chip::Sn76489, 3_579_545);
+    assert_eq!(report.ported_writes, 3);
+    assert_eq!(report.envelope_writes, 2);
+    assert_eq!(report.dropped_writes, 1);
+    let bytes: Vec<u8> = psg
+        .iter()
+        .filter_map(|cmd| match cmd {
+            VgmCommand::Sn76489Write(_, s) => Some(s.value),
+            _ => None,
+        })
+        .collect();
+    // channel A at full volume, the others and the noise silent
+    assert!(bytes.contains(&0x90));
+    assert!(bytes.contains(&0xBF));
+    assert!(bytes.contains(&0xDF));
+    assert!(bytes.contains(&0xFF));
+}
@@ -0,0 +1,46 @@
+use soundlog::VgmBuilder;
+use soundlog::chip::{Chip, PsgSpec};
+use soundlog::vgm::command::{Instance, WaitSamples};
+use soundlog::vgm::usage::channel_usage;
+
+#[test]
+fn channel_usage_counts_notes_pitch_voices_and_time() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+    let mut psg = |values: &[u8], wait: u16| {
+        for &value in values {
+            builder.add_chip_write(Instance::Primary, PsgSpec { value });
+        }
+        builder.add_vgm_command(WaitSamples(wait));
+    };
+    // Tone 1 at period 0x0FE, then 0x1FC; white noise, then periodic noise.
+    psg(&[0x8E, 0x0F, 0x90, 0xE4, 0xF0], 1000);
+    psg(&[0x9F, 0xFF], 1000);
+    psg(&[0x8C, 0x1F, 0x90, 0xE0, 0xF0], 2000);
+    psg(&[0x9F], 0);
+    let doc = builder.finalize();
+
+    let report = channel_usage(&doc);
+    assert_eq!(report.samples, 4000);
+    assert_eq!(report.channels.len(), 2);
+
+    let tone = &report.channels[0];
+    assert_eq!(tone.channel, 0);
+    assert_eq!(tone.notes, 2);
+    assert_eq!(tone.active_samples, 3000);
+    assert_eq!(tone.active_percent(report.samples), 75.0);
+    assert!(tone.voices.is_empty());
+    let (low, high) = tone.pitch_range.unwrap();
+    assert!((low - 220.2).abs() < 1.0, "{}", low);
+    assert!((high - 440.4).abs() < 1.0, "{}", high);
+
+    let noise = &report.channels[1];
+    assert_eq!(noise.channel, 3);
+    assert_eq!(noise.notes, 2);
+    assert_eq!(noise.voices.len(), 2);
+    assert_eq!(report.voices.len(), 2);
+    assert_eq!(report.voices[0].registers, vec![0x04]);
+    assert_eq!(report.voices[1].registers, vec![0x00]);
+    // The noise channel is still keyed on at the end.
+    assert_eq!(noise.active_samples, 3000);
+}
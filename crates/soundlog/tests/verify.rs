@@ -0,0 +1,132 @@
+use soundlog::VgmBuilder;
+use soundlog::chip::{Chip, PsgSpec};
+use soundlog::meta::Gd3;
+use soundlog::vgm::command::{DataBlock, Instance, VgmCommand, Wait735Samples, WaitSamples};
+use soundlog::vgm::transform::{QuantizeOptions, quantize};
+use soundlog::vgm::verify::{
+    DeterminismError, WaitConservationError, WaitTotals, verify_command_wait_conservation,
+    verify_deterministic, verify_wait_conservation,
+};
+
+fn looping_doc(intro: u16, body: u16) -> soundlog::VgmDocument {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+    builder.add_vgm_command(WaitSamples(intro));
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x90 });
+    builder.add_vgm_command(WaitSamples(body));
+    builder.set_loop_offset(1);
+    builder.finalize()
+}
+
+#[test]
+fn wait_totals_split_at_loop_point() {
+    let totals = WaitTotals::of(&looping_doc(100, 735));
+    assert_eq!(
+        totals,
+        WaitTotals {
+            total: 835,
+            intro: 100,
+            looped: Some(735),
+        }
+    );
+}
+
+#[test]
+fn moved_loop_point_is_reported() {
+    let result = verify_wait_conservation(&looping_doc(100, 735), &looping_doc(200, 635));
+    assert_eq!(
+        result,
+        Err(WaitConservationError::Loop {
+            original: Some(735),
+            transformed: Some(635),
+        })
+    );
+
+    let result = verify_wait_conservation(&looping_doc(100, 735), &looping_doc(100, 736));
+    assert!(matches!(result, Err(WaitConservationError::Total { .. })));
+}
+
+#[test]
+fn unit_grid_quantize_conserves_waits() {
+    let doc = looping_doc(1234, 5678);
+    let (quantized, _) = quantize(&doc, &QuantizeOptions::new(1));
+    assert!(verify_wait_conservation(&doc, &quantized).is_ok());
+}
+
+#[test]
+fn command_sequences_ignore_commands_after_end_of_data() {
+    let original = vec![VgmCommand::from(Wait735Samples)];
+    let split = vec![
+        VgmCommand::from(WaitSamples(700)),
+        VgmCommand::from(WaitSamples(35)),
+        VgmCommand::EndOfData(soundlog::vgm::command::EndOfData),
+        VgmCommand::from(WaitSamples(35)),
+    ];
+    assert_eq!(
+        verify_command_wait_conservation(&original, &split).map(|t| t.total),
+        Ok(735)
+    );
+}
+
+fn block(data_type: u8, byte: u8) -> DataBlock {
+    DataBlock {
+        marker: 0x66,
+        chip_instance: 0,
+        data_type,
+        size: 2,
+        data: vec![byte, byte],
+    }
+}
+
+fn build_with_blocks() -> Vec<u8> {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+    builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x90 });
+    builder.add_vgm_command(block(0x00, 1));
+    builder.add_vgm_command(WaitSamples(100));
+    builder.add_vgm_command(block(0x7F, 2));
+    builder.add_vgm_command(block(0x00, 3));
+    builder.add_vgm_command(Wait735Samples);
+    builder.set_loop_offset(1);
+    builder.set_gd3(Gd3 {
+        track_name_en: Some("Track".to_string()),
+        ..Gd3::default()
+    });
+    let doc = builder.finalize();
+    verify_deterministic(&doc).unwrap()
+}
+
+#[test]
+fn builder_output_is_reproducible() {
+    let bytes = build_with_blocks();
+    assert_eq!(bytes, build_with_blocks());
+
+    // The decompression table comes first, the other blocks keep their order.
+    let doc = soundlog::VgmDocument::try_from(&bytes[..]).unwrap();
+    let types: Vec<(u8, u8)> = doc
+        .commands
+        .iter()
+        .filter_map(|command| match command {
+            VgmCommand::DataBlock(block) => Some((block.data_type, block.data[0])),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(types, vec![(0x7F, 2), (0x00, 1), (0x00, 3)]);
+    assert!(matches!(doc.commands[3], VgmCommand::Sn76489Write(..)));
+    assert_eq!(
+        doc.loop_command_index()
+            .map(|index| doc.commands[index].clone()),
+        Some(VgmCommand::WaitSamples(WaitSamples(100)))
+    );
+}
+
+#[test]
+fn verify_deterministic_reports_documents_that_change_when_rebuilt() {
+    // Commands after the end of data are dropped by the parser, so the
+    // serialized file does not rebuild to itself.
+    let mut doc = looping_doc(100, 735);
+    doc.commands.push(VgmCommand::WaitSamples(WaitSamples(1)));
+    let err = verify_deterministic(&doc).unwrap_err();
+    assert!(matches!(err, DeterminismError::NotFixedPoint { .. }));
+    assert!(err.to_string().contains("rebuilding"));
+}
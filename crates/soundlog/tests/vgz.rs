@@ -0,0 +1,37 @@
+#![cfg(feature = "vgz")]
+
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+use soundlog::chip::{Chip, PsgSpec};
+use soundlog::meta::Gd3;
+use soundlog::vgm::command::{Instance, WaitSamples};
+use soundlog::{VgmBuilder, VgmDocument};
+
+#[test]
+fn vgz_bytes_decompress_to_the_vgm_bytes() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Sn76489, Instance::Primary, 3_579_545);
+    for _ in 0..100 {
+        builder.add_chip_write(Instance::Primary, PsgSpec { value: 0x90 });
+        builder.add_vgm_command(WaitSamples(735));
+    }
+    builder.set_gd3(Gd3 {
+        track_name_en: Some("Title".to_string()),
+        ..Gd3::default()
+    });
+    let doc = builder.finalize();
+
+    let vgm: Vec<u8> = (&doc).into();
+    let vgz = doc.to_vgz_bytes();
+    assert!(vgz.len() < vgm.len());
+    assert_eq!(vgz, doc.to_vgz_bytes());
+
+    let mut decompressed = Vec::new();
+    GzDecoder::new(&vgz[..])
+        .read_to_end(&mut decompressed)
+        .unwrap();
+    assert_eq!(decompressed, vgm);
+    let parsed = VgmDocument::try_from(&decompressed[..]).unwrap();
+    assert_eq!(parsed.commands, doc.commands);
+}
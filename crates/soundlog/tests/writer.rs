@@ -0,0 +1,117 @@
+use std::io::Cursor;
+
+use soundlog::chip::{Chip, Ym2612Spec};
+use soundlog::meta::Gd3;
+use soundlog::vgm::command::{EndOfData, Instance, VgmCommand, WaitSamples};
+use soundlog::{VgmBuilder, VgmDocument, VgmHeader, VgmStreamWriter};
+
+fn commands() -> Vec<VgmCommand> {
+    let mut commands = Vec::new();
+    for value in 0..8u8 {
+        commands.push(
+            (
+                Instance::Primary,
+                Ym2612Spec {
+                    port: 0,
+                    register: 0x28,
+                    value,
+                },
+            )
+                .into(),
+        );
+        commands.push(WaitSamples(735).into());
+    }
+    commands
+}
+
+fn header() -> VgmHeader {
+    VgmHeader {
+        ym2612_clock: 7_670_454,
+        ..Default::default()
+    }
+}
+
+fn gd3() -> Gd3 {
+    Gd3 {
+        track_name_en: Some("Stream".to_string()),
+        ..Default::default()
+    }
+}
+
+fn write_stream(loop_index: Option<usize>) -> Vec<u8> {
+    let mut writer = VgmStreamWriter::new(Cursor::new(Vec::new()), header()).unwrap();
+    for (index, command) in commands().into_iter().enumerate() {
+        if loop_index == Some(index) {
+            writer.mark_loop();
+        }
+        writer.write_command(command).unwrap();
+    }
+    writer.set_gd3(gd3());
+    writer.finish().unwrap().into_inner()
+}
+
+#[test]
+fn stream_writer_matches_builder_output() {
+    let mut builder = VgmBuilder::new();
+    builder.register_chip(Chip::Ym2612, Instance::Primary, 7_670_454);
+    builder.add_vgm_commands(commands());
+    builder.set_loop_index(4);
+    builder.set_gd3(gd3());
+    let expected = Vec::<u8>::from(&builder.finalize());
+
+    assert_eq!(write_stream(Some(4)), expected);
+}
+
+#[test]
+fn stream_writer_back_patches_header_fields() {
+    let bytes = write_stream(Some(2));
+    let doc = VgmDocument::try_from(bytes.as_slice()).unwrap();
+
+    assert_eq!(doc.header.eof_offset as usize, bytes.len() - 4);
+    assert_eq!(doc.header.total_samples, 8 * 735);
+    assert_eq!(doc.header.loop_samples, 7 * 735);
+    assert_eq!(doc.loop_command_index(), Some(2));
+    assert_eq!(doc.gd3, Some(gd3()));
+    assert_eq!(doc.commands.len(), 17);
+    assert!(matches!(
+        doc.commands.last(),
+        Some(VgmCommand::EndOfData(_))
+    ));
+}
+
+#[test]
+fn stream_writer_does_not_repeat_end_of_data() {
+    let mut writer = VgmStreamWriter::new(Cursor::new(Vec::new()), header()).unwrap();
+    writer.write_command(WaitSamples(100)).unwrap();
+    writer.write_command(EndOfData).unwrap();
+    let bytes = writer.finish().unwrap().into_inner();
+    let doc = VgmDocument::try_from(bytes.as_slice()).unwrap();
+
+    assert_eq!(doc.header.gd3_offset, 0);
+    assert_eq!(doc.header.loop_offset, 0);
+    assert_eq!(doc.header.total_samples, 100);
+    assert_eq!(doc.commands.len(), 2);
+    // Header, one 0x61 wait and the 0x66 end marker.
+    let header_size = VgmHeader::total_header_size(doc.header.version, doc.header.data_offset);
+    assert_eq!(bytes.len(), header_size + 3 + 1);
+}
+
+#[test]
+fn stream_writer_starts_at_the_target_position() {
+    let mut target = Cursor::new(b"prefix".to_vec());
+    target.set_position(6);
+    let mut writer = VgmStreamWriter::new(target, header()).unwrap();
+    for (index, command) in commands().into_iter().enumerate() {
+        if index == 4 {
+            writer.mark_loop();
+        }
+        writer.write_command(command).unwrap();
+    }
+    writer.set_gd3(gd3());
+    let target = writer.finish().unwrap();
+    assert_eq!(target.position(), target.get_ref().len() as u64);
+
+    let bytes = target.into_inner();
+    assert_eq!(&bytes[..6], b"prefix");
+    assert_eq!(bytes[6..], write_stream(Some(4)));
+}